@@ -0,0 +1,73 @@
+//! Hosts that `url`, `zip-url` and `tgz-url` sources may be downloaded from
+//!
+//! In locked-down environments `allowed_source_hosts` lists the approved
+//! hosts, either exactly (`repo.example.org`) or with all their subdomains
+//! (`*.example.org`). A source URL on any other host is refused before a
+//! request is sent. Redirects of an approved URL are followed as usual. An
+//! empty list allows every host.
+
+use crate::config::Config;
+use crate::error::{MirrorError, Result};
+
+/// Whether `host` matches one of `patterns`
+pub fn host_allowed(patterns: &[String], host: &str) -> bool {
+    let host = host.to_ascii_lowercase();
+    patterns.iter().any(|pattern| {
+        let pattern = pattern.to_ascii_lowercase();
+        match pattern.strip_prefix("*.") {
+            Some(domain) => host
+                .strip_suffix(domain)
+                .is_some_and(|subdomain| subdomain.ends_with('.')),
+            None => host == pattern,
+        }
+    })
+}
+
+/// Refuse a remote source whose host is not in `allowed_source_hosts`
+pub fn check_source(source: &str, is_local_file: bool, config: &Config) -> Result<()> {
+    if is_local_file || config.allowed_source_hosts.is_empty() {
+        return Ok(());
+    }
+    let url = url::Url::parse(source)?;
+    let host = url.host_str().unwrap_or_default();
+    if host_allowed(&config.allowed_source_hosts, host) {
+        Ok(())
+    } else {
+        Err(MirrorError::SourceNotAllowed {
+            source_url: source.to_string(),
+            host: host.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_source() {
+        let config = Config {
+            allowed_source_hosts: vec![
+                "artifacts.example.org".to_string(),
+                "*.prefix.dev".to_string(),
+            ],
+            ..Default::default()
+        };
+        let allowed = |source: &str| check_source(source, false, &config).is_ok();
+
+        assert!(allowed("https://artifacts.example.org/builds/latest.zip"));
+        assert!(allowed("https://Artifacts.Example.org:8443/a-1.0-0.conda"));
+        assert!(allowed(
+            "https://repo.prefix.dev/channel/noarch/a-1.0-0.conda"
+        ));
+        assert!(!allowed("https://prefix.dev/a-1.0-0.conda"));
+        assert!(!allowed("https://evilprefix.dev/a-1.0-0.conda"));
+        assert!(!allowed("https://example.org/builds/latest.zip"));
+        assert!(matches!(
+            check_source("https://example.org/a.zip", false, &config),
+            Err(MirrorError::SourceNotAllowed { host, .. }) if host == "example.org"
+        ));
+        assert!(check_source("./builds/latest.zip", true, &config).is_ok());
+        assert!(check_source("https://example.org/a.zip", false, &Config::default()).is_ok());
+    }
+}
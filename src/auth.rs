@@ -0,0 +1,294 @@
+//! Credentials for private upstream channels
+//!
+//! Private prefix.dev, Quetz and anaconda.org channels can serve as sources.
+//! Credentials are read from a JSON file in the format rattler and pixi store
+//! them in (`pixi auth login` writes it), mapping a host, or `*.domain` for all
+//! its subdomains, to one of:
+//!
+//! ```json
+//! {
+//!   "repo.prefix.dev": { "BearerToken": "pfx_..." },
+//!   "quetz.example.org": { "BasicHTTP": { "username": "mirror", "password": "..." } },
+//!   "*.anaconda.org": { "CondaToken": "xy-12345678-..." }
+//! }
+//! ```
+//!
+//! Package and archive downloads and channel `repodata.json` reads pick up the
+//! credentials of their host. A bearer token overrides the GitHub token the
+//! download client otherwise sends.
+
+use reqwest::{Client, Method, RequestBuilder};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tracing::{debug, warn};
+
+use crate::config::Config;
+use crate::error::{MirrorError, Result};
+
+/// How requests to one host authenticate, as rattler stores it
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub enum Authentication {
+    /// Sent as `Authorization: Bearer <token>`
+    BearerToken(String),
+    /// Sent as HTTP basic auth
+    BasicHTTP { username: String, password: String },
+    /// Inserted into the URL as `/t/<token>/...`, as anaconda.org expects
+    CondaToken(String),
+    /// Credentials for S3 channels, which sources do not read; ignored
+    S3Credentials {
+        access_key_id: String,
+        secret_access_key: String,
+        session_token: Option<String>,
+    },
+}
+
+/// Credentials by host
+#[derive(Debug, Clone, Default)]
+pub struct Credentials {
+    hosts: BTreeMap<String, Authentication>,
+}
+
+impl Credentials {
+    /// Read a credentials file; a missing file holds no credentials
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e.into()),
+        };
+        let hosts = serde_json::from_str(&content).map_err(|e| {
+            MirrorError::InvalidInput(format!(
+                "Invalid credentials file '{}': {}",
+                path.display(),
+                e
+            ))
+        })?;
+        Ok(Self { hosts })
+    }
+
+    /// Credentials for the host of `url`, trying `*.domain` entries from the closest domain outwards
+    pub fn for_url(&self, url: &str) -> Option<&Authentication> {
+        let url = url::Url::parse(url).ok()?;
+        let host = url.host_str()?;
+        if let Some(authentication) = self.hosts.get(host) {
+            return Some(authentication);
+        }
+        let mut domain = url.domain()?;
+        loop {
+            if let Some(authentication) = self.hosts.get(&format!("*.{}", domain)) {
+                return Some(authentication);
+            }
+            domain = domain.split_once('.')?.1;
+        }
+    }
+
+    /// A request for `url` carrying the credentials of its host
+    pub fn request(&self, client: &Client, method: Method, url: &str) -> RequestBuilder {
+        match self.for_url(url) {
+            Some(Authentication::BearerToken(token)) => {
+                client.request(method, url).bearer_auth(token)
+            }
+            Some(Authentication::BasicHTTP { username, password }) => client
+                .request(method, url)
+                .basic_auth(username, Some(password)),
+            Some(Authentication::CondaToken(token)) => {
+                client.request(method, with_conda_token(url, token))
+            }
+            Some(Authentication::S3Credentials { .. }) | None => client.request(method, url),
+        }
+    }
+}
+
+/// `url` with `/t/<token>` in front of its path, unless it already has a token
+fn with_conda_token(url: &str, token: &str) -> String {
+    let Ok(mut parsed) = url::Url::parse(url) else {
+        return url.to_string();
+    };
+    if parsed.path().starts_with("/t/") {
+        return url.to_string();
+    }
+    let path = format!("/t/{}{}", token, parsed.path());
+    parsed.set_path(&path);
+    parsed.to_string()
+}
+
+/// The credentials file of a configuration
+///
+/// `auth_file` when set, otherwise rattler's default `~/.rattler/credentials.json`.
+fn credentials_path(config: &Config) -> Option<PathBuf> {
+    match &config.auth_file {
+        Some(path) => Some(PathBuf::from(path)),
+        None => std::env::var_os("HOME").map(|home| {
+            PathBuf::from(home)
+                .join(".rattler")
+                .join("credentials.json")
+        }),
+    }
+}
+
+static CREDENTIALS: OnceLock<Credentials> = OnceLock::new();
+
+/// Credentials shared by every request made in this process
+///
+/// Like the host throttle, they are read for the first configuration that asks
+/// for them. An unreadable file is reported and leaves requests unauthenticated.
+pub fn shared(config: &Config) -> &'static Credentials {
+    CREDENTIALS.get_or_init(|| {
+        let Some(path) = credentials_path(config) else {
+            return Credentials::default();
+        };
+        match Credentials::from_path(&path) {
+            Ok(credentials) => {
+                if !credentials.hosts.is_empty() {
+                    debug!(
+                        "Read credentials for {} hosts from {}",
+                        credentials.hosts.len(),
+                        path.display()
+                    );
+                }
+                credentials
+            }
+            Err(e) => {
+                warn!("Ignoring credentials: {}", e);
+                Credentials::default()
+            }
+        }
+    })
+}
+
+/// A request for `url` with the shared credentials, or without if none were set up
+fn request(client: &Client, method: Method, url: &str) -> RequestBuilder {
+    match CREDENTIALS.get() {
+        Some(credentials) => credentials.request(client, method, url),
+        None => client.request(method, url),
+    }
+}
+
+/// A GET request for `url` with the shared credentials
+pub fn get(client: &Client, url: &str) -> RequestBuilder {
+    request(client, Method::GET, url)
+}
+
+/// A HEAD request for `url` with the shared credentials
+pub fn head(client: &Client, url: &str) -> RequestBuilder {
+    request(client, Method::HEAD, url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{MockResponse, MockServer};
+    use tempfile::TempDir;
+
+    fn credentials(json: &str) -> Credentials {
+        Credentials {
+            hosts: serde_json::from_str(json).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_for_url_matches_host_then_wildcards() {
+        let credentials = credentials(
+            r#"{"repo.prefix.dev": {"BearerToken": "pfx"},
+                "*.example.org": {"BasicHTTP": {"username": "mirror", "password": "secret"}},
+                "*.anaconda.org": {"CondaToken": "tk"}}"#,
+        );
+        assert_eq!(
+            credentials.for_url("https://repo.prefix.dev/private/noarch/repodata.json"),
+            Some(&Authentication::BearerToken("pfx".to_string()))
+        );
+        assert!(matches!(
+            credentials.for_url("https://quetz.channels.example.org/get/internal"),
+            Some(Authentication::BasicHTTP { .. })
+        ));
+        assert_eq!(
+            credentials.for_url("https://conda.anaconda.org/org/noarch/a-1-0.conda"),
+            Some(&Authentication::CondaToken("tk".to_string()))
+        );
+        assert_eq!(credentials.for_url("https://prefix.dev/channels"), None);
+        assert_eq!(credentials.for_url("./local/channel"), None);
+    }
+
+    #[test]
+    fn test_with_conda_token() {
+        assert_eq!(
+            with_conda_token("https://conda.anaconda.org/org/noarch/repodata.json", "tk"),
+            "https://conda.anaconda.org/t/tk/org/noarch/repodata.json"
+        );
+        assert_eq!(
+            with_conda_token(
+                "https://conda.anaconda.org/t/other/org/noarch/repodata.json",
+                "tk"
+            ),
+            "https://conda.anaconda.org/t/other/org/noarch/repodata.json"
+        );
+    }
+
+    #[test]
+    fn test_from_path() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("credentials.json");
+        assert!(Credentials::from_path(&path).unwrap().hosts.is_empty());
+
+        std::fs::write(
+            &path,
+            r#"{"s3://bucket": {"S3Credentials": {"access_key_id": "a", "secret_access_key": "b", "session_token": null}},
+                "127.0.0.1": {"BearerToken": "pfx"}}"#,
+        )
+        .unwrap();
+        assert_eq!(Credentials::from_path(&path).unwrap().hosts.len(), 2);
+
+        std::fs::write(&path, r#"{"127.0.0.1": {"Password": "x"}}"#).unwrap();
+        assert!(Credentials::from_path(&path).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_get_sends_credentials() {
+        let server = MockServer::start().await.unwrap();
+        server.mock(
+            "GET",
+            "/noarch/repodata.json",
+            MockResponse::json(200, "{}"),
+        );
+        server.mock(
+            "GET",
+            "/t/tk/noarch/repodata.json",
+            MockResponse::json(200, "{}"),
+        );
+        let url = format!("{}/noarch/repodata.json", server.url());
+        let client = Client::new();
+
+        let bearer = credentials(r#"{"127.0.0.1": {"BearerToken": "pfx"}}"#);
+        bearer
+            .request(&client, Method::GET, &url)
+            .send()
+            .await
+            .unwrap();
+        let basic = credentials(
+            r#"{"127.0.0.1": {"BasicHTTP": {"username": "mirror", "password": "secret"}}}"#,
+        );
+        basic
+            .request(&client, Method::GET, &url)
+            .send()
+            .await
+            .unwrap();
+        let conda = credentials(r#"{"127.0.0.1": {"CondaToken": "tk"}}"#);
+        let response = conda
+            .request(&client, Method::GET, &url)
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+
+        let requests = server.requests();
+        assert_eq!(requests[0].headers["authorization"], "Bearer pfx");
+        assert_eq!(
+            requests[1].headers["authorization"],
+            "Basic bWlycm9yOnNlY3JldA=="
+        );
+        assert_eq!(requests[2].path, "/t/tk/noarch/repodata.json");
+        assert!(!requests[2].headers.contains_key("authorization"));
+    }
+}
@@ -1,13 +1,46 @@
-use anyhow::{anyhow, Result};
 use comfy_table::presets::NOTHING;
 use comfy_table::{Attribute, Cell, ContentArrangement, Table};
 
+use rattler_conda_types::Platform;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 
 use tracing::{info, warn};
 
 use crate::config::Config;
+use crate::download::verify_download_size;
+use crate::error::{MirrorError, Result};
+use crate::listing;
+use crate::listing_cache::ListingCache;
+use crate::politeness;
+use crate::suggest;
+
+/// Headers of the artifact table, whose keys `--columns` and `--sort-by` take
+const ARTIFACT_COLUMNS: &[&str] = &[
+    "ID",
+    "Name",
+    "Type",
+    "Size",
+    "Platform",
+    "Source",
+    "Download Available",
+];
+
+/// Name prefixes of the artifacts conda-forge feedstocks upload from their builds
+const CONDA_FORGE_ARTIFACT_PREFIXES: &[&str] = &["conda_artifacts_", "conda_pkgs_"];
+
+/// Headers of the build table, whose keys `--columns` and `--sort-by` take
+const BUILD_COLUMNS: &[&str] = &[
+    "Build ID",
+    "Build Number",
+    "Status",
+    "Result",
+    "Definition",
+    "Source Branch",
+    "Finish Time",
+    "Mirror Source",
+];
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AzureDevOpsArtifact {
@@ -84,19 +117,35 @@ pub struct AzureDevOpsBuildsResponse {
 pub struct AzureDevOpsClient {
     client: Client,
     token: Option<String>,
+    base_url: String,
+    listing_cache: Option<ListingCache>,
 }
 
 impl AzureDevOpsClient {
     pub fn new(config: &Config) -> Result<Self> {
         let client = Client::builder()
             .timeout(std::time::Duration::from_secs(config.timeout_seconds))
-            .user_agent("meso-forge-mirror/0.1.0")
+            .user_agent(politeness::user_agent(config))
             .build()?;
 
-        Ok(Self {
+        Ok(Self::with_client(client, config))
+    }
+
+    /// Use a caller-supplied client, e.g. one with proxies, custom TLS roots or tracing
+    pub fn with_client(client: Client, config: &Config) -> Self {
+        Self {
             client,
             token: config.azure_devops_token.clone(),
-        })
+            base_url: config.azure_devops_url.trim_end_matches('/').to_string(),
+            listing_cache: ListingCache::from_config(config),
+        }
+    }
+
+    /// Send API requests to another base URL, e.g. Azure DevOps Server or a mock server
+    #[allow(dead_code)]
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
     }
 
     /// List artifacts for a specific build
@@ -107,9 +156,24 @@ impl AzureDevOpsClient {
         build_id: u64,
     ) -> Result<Vec<AzureDevOpsArtifact>> {
         let url = format!(
-            "https://dev.azure.com/{}/{}/_apis/build/builds/{}/artifacts?api-version=6.0",
-            organization, project, build_id
+            "{}/{}/{}/_apis/build/builds/{}/artifacts?api-version=6.0",
+            self.base_url, organization, project, build_id
         );
+        let cache_key = ListingCache::key(&url, self.token.is_some());
+        if let Some(artifacts) = self
+            .listing_cache
+            .as_ref()
+            .and_then(|cache| cache.get::<Vec<AzureDevOpsArtifact>>(&cache_key))
+        {
+            info!(
+                "Using {} cached artifacts for build {} in {}/{}",
+                artifacts.len(),
+                build_id,
+                organization,
+                project
+            );
+            return Ok(artifacts);
+        }
 
         let mut request = self.client.get(&url);
 
@@ -123,10 +187,10 @@ impl AzureDevOpsClient {
         if !response.status().is_success() {
             let status = response.status();
             let error_text = response.text().await.unwrap_or_default();
-            return Err(anyhow!(
-                "Failed to list Azure DevOps artifacts: {} - {}",
+            return Err(MirrorError::from_status(
                 status,
-                error_text
+                "Failed to list Azure DevOps artifacts",
+                &error_text,
             ));
         }
 
@@ -159,12 +223,15 @@ impl AzureDevOpsClient {
                     "\n\nExpected JSON response from Azure DevOps API."
                 };
 
-                return Err(anyhow!(
+                let message = format!(
                     "Failed to parse Azure DevOps artifacts response as JSON: {}\nResponse preview:\n{}\n{}",
-                    e,
-                    preview,
-                    guidance
-                ));
+                    e, preview, guidance
+                );
+                return Err(if is_auth_redirect(&response_text) {
+                    MirrorError::AuthError(message)
+                } else {
+                    MirrorError::InvalidResponse(message)
+                });
             }
         };
 
@@ -172,6 +239,9 @@ impl AzureDevOpsClient {
             "Found {} artifacts for build {} in {}/{}",
             artifacts_response.count, build_id, organization, project
         );
+        if let Some(cache) = &self.listing_cache {
+            cache.put(&cache_key, &artifacts_response.value);
+        }
 
         Ok(artifacts_response.value)
     }
@@ -184,13 +254,27 @@ impl AzureDevOpsClient {
         definition_id: Option<u64>,
     ) -> Result<Vec<AzureDevOpsBuild>> {
         let mut url = format!(
-            "https://dev.azure.com/{}/{}/_apis/build/builds?api-version=6.0&$top=50&statusFilter=completed",
-            organization, project
+            "{}/{}/{}/_apis/build/builds?api-version=6.0&$top=50&statusFilter=completed",
+            self.base_url, organization, project
         );
 
         if let Some(def_id) = definition_id {
             url.push_str(&format!("&definitions={}", def_id));
         }
+        let cache_key = ListingCache::key(&url, self.token.is_some());
+        if let Some(builds) = self
+            .listing_cache
+            .as_ref()
+            .and_then(|cache| cache.get::<Vec<AzureDevOpsBuild>>(&cache_key))
+        {
+            info!(
+                "Using {} cached builds in {}/{}",
+                builds.len(),
+                organization,
+                project
+            );
+            return Ok(builds);
+        }
 
         let mut request = self.client.get(&url);
 
@@ -203,10 +287,10 @@ impl AzureDevOpsClient {
         if !response.status().is_success() {
             let status = response.status();
             let error_text = response.text().await.unwrap_or_default();
-            return Err(anyhow!(
-                "Failed to list Azure DevOps builds: {} - {}",
+            return Err(MirrorError::from_status(
                 status,
-                error_text
+                "Failed to list Azure DevOps builds",
+                &error_text,
             ));
         }
 
@@ -238,12 +322,15 @@ impl AzureDevOpsClient {
                     "\n\nExpected JSON response from Azure DevOps API."
                 };
 
-                return Err(anyhow!(
+                let message = format!(
                     "Failed to parse Azure DevOps builds response as JSON: {}\nResponse preview:\n{}\n{}",
-                    e,
-                    preview,
-                    guidance
-                ));
+                    e, preview, guidance
+                );
+                return Err(if is_auth_redirect(&response_text) {
+                    MirrorError::AuthError(message)
+                } else {
+                    MirrorError::InvalidResponse(message)
+                });
             }
         };
 
@@ -251,6 +338,9 @@ impl AzureDevOpsClient {
             "Found {} builds in {}/{}",
             builds_response.count, organization, project
         );
+        if let Some(cache) = &self.listing_cache {
+            cache.put(&cache_key, &builds_response.value);
+        }
 
         Ok(builds_response.value)
     }
@@ -264,8 +354,8 @@ impl AzureDevOpsClient {
         artifact_name: &str,
     ) -> Result<bytes::Bytes> {
         let url = format!(
-            "https://dev.azure.com/{}/{}/_apis/build/builds/{}/artifacts?artifactName={}&$format=zip&api-version=6.0",
-            organization, project, build_id, artifact_name
+            "{}/{}/{}/_apis/build/builds/{}/artifacts?artifactName={}&$format=zip&api-version=6.0",
+            self.base_url, organization, project, build_id, artifact_name
         );
 
         let mut request = self.client.get(&url);
@@ -277,15 +367,18 @@ impl AzureDevOpsClient {
         let response = request.send().await?;
 
         if !response.status().is_success() {
-            return Err(anyhow!(
-                "Failed to download Azure DevOps artifact {}: {} - {}",
-                artifact_name,
-                response.status(),
-                response.text().await.unwrap_or_default()
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(MirrorError::from_status(
+                status,
+                &format!("Failed to download Azure DevOps artifact {}", artifact_name),
+                &error_text,
             ));
         }
 
+        let expected_size = response.content_length();
         let content = response.bytes().await?;
+        verify_download_size(&url, expected_size, content.len())?;
 
         info!(
             "Downloaded artifact {} ({} bytes) from build {} in {}/{}",
@@ -393,11 +486,56 @@ impl AzureDevOpsClient {
         Ok(filtered)
     }
 
+    /// Sort artifacts by the table column `sort_by` names, e.g. `size` or `name`
+    pub fn sort_artifacts(
+        &self,
+        artifacts: &mut [AzureDevOpsArtifact],
+        sort_by: &str,
+    ) -> Result<()> {
+        listing::sort_by_column(artifacts, ARTIFACT_COLUMNS, sort_by, |key, a, b| {
+            Some(match key {
+                "id" => a.id.cmp(&b.id),
+                "name" => a.name.cmp(&b.name),
+                "type" => a.resource.artifact_type.cmp(&b.resource.artifact_type),
+                "size" => artifact_size(a).cmp(&artifact_size(b)),
+                "platform" => artifact_platform(&a.name)
+                    .map(Platform::as_str)
+                    .cmp(&artifact_platform(&b.name).map(Platform::as_str)),
+                "source" => a.source.cmp(&b.source),
+                "download-available" => a
+                    .resource
+                    .download_url
+                    .is_some()
+                    .cmp(&b.resource.download_url.is_some()),
+                _ => return None,
+            })
+        })
+    }
+
+    /// Sort builds by the table column `sort_by` names, e.g. `finish-time`
+    pub fn sort_builds(&self, builds: &mut [AzureDevOpsBuild], sort_by: &str) -> Result<()> {
+        listing::sort_by_column(builds, BUILD_COLUMNS, sort_by, |key, a, b| {
+            Some(match key {
+                "build-id" | "mirror-source" => a.id.cmp(&b.id),
+                "build-number" => a.build_number.cmp(&b.build_number),
+                "status" => a.status.cmp(&b.status),
+                "result" => a.result.cmp(&b.result),
+                "definition" => a.definition.name.cmp(&b.definition.name),
+                "source-branch" => a.source_branch.cmp(&b.source_branch),
+                "finish-time" => a.finish_time.cmp(&b.finish_time),
+                _ => return None,
+            })
+        })
+    }
+
     /// Print artifact information in a formatted way
+    ///
+    /// `columns` selects and orders the table columns by key; all are shown when empty.
     pub fn print_artifacts_info(
         &self,
         artifacts: &[AzureDevOpsArtifact],
         format: &str,
+        columns: &[String],
     ) -> Result<()> {
         match format.to_lowercase().as_str() {
             "yaml" => {
@@ -416,37 +554,39 @@ impl AzureDevOpsClient {
                 println!("{}", json_output);
             }
             "table" => {
-                self.print_artifacts_info_table(artifacts);
+                self.print_artifacts_info_table(artifacts, columns)?;
             }
             _ => {
-                return Err(anyhow!(
+                return Err(MirrorError::InvalidInput(format!(
                     "Unsupported output format: {}. Supported formats: yaml, json, table",
                     format
-                ));
+                )));
             }
         }
         Ok(())
     }
 
     /// Print artifact information in table format using comfy-table
-    fn print_artifacts_info_table(&self, artifacts: &[AzureDevOpsArtifact]) {
+    fn print_artifacts_info_table(
+        &self,
+        artifacts: &[AzureDevOpsArtifact],
+        columns: &[String],
+    ) -> Result<()> {
+        let selected = listing::selected_columns(ARTIFACT_COLUMNS, columns)?;
         if artifacts.is_empty() {
             println!("No artifacts found.");
-            return;
+            return Ok(());
         }
 
         let mut table = Table::new();
         table
             .load_preset(NOTHING)
             .set_content_arrangement(ContentArrangement::Dynamic)
-            .set_header(vec![
-                Cell::new("ID").add_attribute(Attribute::Bold),
-                Cell::new("Name").add_attribute(Attribute::Bold),
-                Cell::new("Type").add_attribute(Attribute::Bold),
-                Cell::new("Size").add_attribute(Attribute::Bold),
-                Cell::new("Source").add_attribute(Attribute::Bold),
-                Cell::new("Download Available").add_attribute(Attribute::Bold),
-            ]);
+            .set_header(
+                selected
+                    .iter()
+                    .map(|&i| Cell::new(ARTIFACT_COLUMNS[i]).add_attribute(Attribute::Bold)),
+            );
 
         for artifact in artifacts {
             let size_display = if let Some(ref props) = artifact.resource.properties {
@@ -475,27 +615,35 @@ impl AzureDevOpsClient {
                 "No"
             };
 
-            table.add_row(vec![
-                Cell::new(artifact.id.to_string()),
-                Cell::new(&artifact.name),
-                Cell::new(&artifact.resource.artifact_type),
-                Cell::new(&size_display),
-                Cell::new(&artifact.source),
-                Cell::new(download_available),
-            ]);
+            let row = [
+                artifact.id.to_string(),
+                artifact.name.clone(),
+                artifact.resource.artifact_type.clone(),
+                size_display,
+                artifact_platform(&artifact.name)
+                    .map_or("-", Platform::as_str)
+                    .to_string(),
+                artifact.source.clone(),
+                download_available.to_string(),
+            ];
+            table.add_row(selected.iter().map(|&i| Cell::new(&row[i])));
         }
 
         println!("\nFound {} artifacts:", artifacts.len());
         println!("{}", table);
+        Ok(())
     }
 
     /// Print builds information in a formatted way with mirror command examples
+    ///
+    /// `columns` selects and orders the table columns by key; all are shown when empty.
     pub fn print_builds_info(
         &self,
         builds: &[AzureDevOpsBuild],
         organization: &str,
         project: &str,
         format: &str,
+        columns: &[String],
     ) -> Result<()> {
         match format.to_lowercase().as_str() {
             "yaml" => {
@@ -514,13 +662,13 @@ impl AzureDevOpsClient {
                 println!("{}", json_output);
             }
             "table" => {
-                self.print_builds_info_table(builds, organization, project);
+                self.print_builds_info_table(builds, organization, project, columns)?;
             }
             _ => {
-                return Err(anyhow!(
+                return Err(MirrorError::InvalidInput(format!(
                     "Unsupported output format: {}. Supported formats: yaml, json, table",
                     format
-                ));
+                )));
             }
         }
         Ok(())
@@ -532,26 +680,23 @@ impl AzureDevOpsClient {
         builds: &[AzureDevOpsBuild],
         organization: &str,
         project: &str,
-    ) {
+        columns: &[String],
+    ) -> Result<()> {
+        let selected = listing::selected_columns(BUILD_COLUMNS, columns)?;
         if builds.is_empty() {
             println!("No builds found.");
-            return;
+            return Ok(());
         }
 
         let mut table = Table::new();
         table
             .load_preset(NOTHING)
             .set_content_arrangement(ContentArrangement::Dynamic)
-            .set_header(vec![
-                Cell::new("Build ID").add_attribute(Attribute::Bold),
-                Cell::new("Build Number").add_attribute(Attribute::Bold),
-                Cell::new("Status").add_attribute(Attribute::Bold),
-                Cell::new("Result").add_attribute(Attribute::Bold),
-                Cell::new("Definition").add_attribute(Attribute::Bold),
-                Cell::new("Source Branch").add_attribute(Attribute::Bold),
-                Cell::new("Finish Time").add_attribute(Attribute::Bold),
-                Cell::new("Mirror Source").add_attribute(Attribute::Bold),
-            ]);
+            .set_header(
+                selected
+                    .iter()
+                    .map(|&i| Cell::new(BUILD_COLUMNS[i]).add_attribute(Attribute::Bold)),
+            );
 
         for build in builds {
             let finish_time = build
@@ -566,16 +711,17 @@ impl AzureDevOpsClient {
             let source_branch_display = build.source_branch.as_deref().unwrap_or("N/A");
             let build_number_display = build.build_number.as_deref().unwrap_or("N/A");
 
-            table.add_row(vec![
-                Cell::new(build.id.to_string()),
-                Cell::new(build_number_display),
-                Cell::new(&build.status),
-                Cell::new(result_display),
-                Cell::new(&build.definition.name),
-                Cell::new(source_branch_display),
-                Cell::new(&finish_time),
-                Cell::new(&src_value),
-            ]);
+            let row = [
+                build.id.to_string(),
+                build_number_display.to_string(),
+                build.status.clone(),
+                result_display.to_string(),
+                build.definition.name.clone(),
+                source_branch_display.to_string(),
+                finish_time,
+                src_value,
+            ];
+            table.add_row(selected.iter().map(|&i| Cell::new(&row[i])));
         }
 
         println!(
@@ -616,9 +762,75 @@ impl AzureDevOpsClient {
                 println!();
             }
         }
+        Ok(())
     }
 }
 
+/// Size in bytes an artifact's properties report, 0 when unknown
+fn artifact_size(artifact: &AzureDevOpsArtifact) -> u64 {
+    artifact
+        .resource
+        .properties
+        .as_ref()
+        .and_then(|props| props.artifactsize.as_deref())
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Build platform of a conda-forge artifact, read from its name
+///
+/// conda-forge feedstocks name their artifacts after the build configuration,
+/// as in `conda_artifacts_20240101.1_linux_64_python3.12.____cpython` or
+/// `conda_pkgs_osx_arm64_`, so the packages of such an artifact belong in the
+/// subdir of that platform, together with any `noarch` packages the build
+/// made. `None` for artifacts named any other way.
+pub fn artifact_platform(name: &str) -> Option<Platform> {
+    let config = CONDA_FORGE_ARTIFACT_PREFIXES
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))?;
+    let parts: Vec<&str> = config.split('_').collect();
+    parts.windows(2).find_map(|pair| {
+        Platform::from_str(&format!("{}-{}", pair[0], pair[1]))
+            .ok()
+            .filter(|platform| *platform != Platform::NoArch)
+    })
+}
+
+/// The platforms given to `--platforms`, each a known subdir
+pub fn parse_platforms(platforms: &[String]) -> Result<Vec<Platform>> {
+    platforms
+        .iter()
+        .map(|platform| {
+            Platform::from_str(platform.trim()).map_err(|_| {
+                let mut message = format!("Unknown platform '{}'", platform);
+                let suggestions = suggest::similar(platform, Platform::all().map(Platform::as_str));
+                if let Some(hint) = suggest::did_you_mean(&suggestions) {
+                    message.push_str(&format!("; {}", hint));
+                }
+                MirrorError::InvalidInput(message)
+            })
+        })
+        .collect()
+}
+
+/// Platform an artifact was built for when it is not one of `platforms`
+///
+/// Artifacts whose name gives no platform are always kept, and so is every
+/// artifact when `platforms` is empty or includes `noarch`, since a feedstock
+/// builds its noarch packages in whichever configuration it chooses.
+pub fn unselected_platform(name: &str, platforms: &[Platform]) -> Option<Platform> {
+    if platforms.is_empty() || platforms.contains(&Platform::NoArch) {
+        return None;
+    }
+    artifact_platform(name).filter(|platform| !platforms.contains(platform))
+}
+
+/// Whether an HTML response body is Azure DevOps redirecting to its sign-in page
+fn is_auth_redirect(response_text: &str) -> bool {
+    (response_text.contains("<html") || response_text.contains("<!DOCTYPE html"))
+        && (response_text.contains("_signin") || response_text.contains("login"))
+}
+
 /// Parse Azure DevOps organization/project from various formats
 pub fn parse_azure_devops_url(input: &str) -> Result<(String, String)> {
     // Handle Azure DevOps URLs
@@ -641,16 +853,17 @@ pub fn parse_azure_devops_url(input: &str) -> Result<(String, String)> {
         }
     }
 
-    Err(anyhow!(
+    Err(MirrorError::InvalidInput(
         "Invalid Azure DevOps format. Expected 'organization/project' or 'https://dev.azure.com/organization/project'"
+            .to_string(),
     ))
 }
 
 /// Parse build ID from string
 pub fn parse_build_id(input: &str) -> Result<u64> {
-    input
-        .parse::<u64>()
-        .map_err(|_| anyhow!("Invalid build ID: '{}'. Must be a number.", input))
+    input.parse::<u64>().map_err(|_| {
+        MirrorError::InvalidInput(format!("Invalid build ID: '{}'. Must be a number.", input))
+    })
 }
 
 /// Parse Azure DevOps source string with optional build ID
@@ -676,6 +889,7 @@ pub fn parse_azure_source(input: &str) -> Result<(String, String, Option<u64>)>
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::AZURE_DEVOPS_URL;
 
     #[test]
     fn test_parse_azure_devops_url() {
@@ -708,6 +922,54 @@ mod tests {
         assert!(parse_build_id("").is_err());
     }
 
+    #[test]
+    fn test_artifact_platform() {
+        assert_eq!(
+            artifact_platform("conda_artifacts_20240101.1_linux_64_python3.12.____cpython"),
+            Some(Platform::Linux64)
+        );
+        assert_eq!(
+            artifact_platform("conda_artifacts_1146.1_osx_arm64_"),
+            Some(Platform::OsxArm64)
+        );
+        assert_eq!(
+            artifact_platform("conda_pkgs_linux_aarch64_numpy2"),
+            Some(Platform::LinuxAarch64)
+        );
+        assert_eq!(
+            artifact_platform("conda_pkgs_win_64"),
+            Some(Platform::Win64)
+        );
+        assert_eq!(artifact_platform("conda_artifacts_1146.1_noarch_"), None);
+        assert_eq!(artifact_platform("build_artifacts_linux_64"), None);
+        assert_eq!(artifact_platform("conda_envs_linux_64"), None);
+    }
+
+    #[test]
+    fn test_platform_selection() {
+        let platforms =
+            parse_platforms(&["linux-64".to_string(), "osx-arm64".to_string()]).unwrap();
+        assert_eq!(
+            unselected_platform("conda_artifacts_1_win_64_", &platforms),
+            Some(Platform::Win64)
+        );
+        assert_eq!(
+            unselected_platform("conda_artifacts_1_osx_arm64_", &platforms),
+            None
+        );
+        assert_eq!(unselected_platform("packages", &platforms), None);
+        assert_eq!(unselected_platform("conda_artifacts_1_win_64_", &[]), None);
+        assert_eq!(
+            unselected_platform("conda_artifacts_1_win_64_", &[Platform::NoArch]),
+            None
+        );
+
+        let err = parse_platforms(&["linux-6".to_string()])
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("did you mean linux-64?"));
+    }
+
     #[test]
     fn test_parse_azure_source() {
         // Test without build ID
@@ -739,6 +1001,8 @@ mod tests {
         let client = AzureDevOpsClient {
             client: reqwest::Client::new(),
             token: None,
+            base_url: AZURE_DEVOPS_URL.to_string(),
+            listing_cache: None,
         };
 
         // Create mock builds with different statuses
@@ -814,12 +1078,12 @@ mod tests {
         // This test mainly verifies that the function doesn't panic and handles optional fields correctly
         // In a real scenario, this would print to stdout, but in tests we just verify it executes
         client
-            .print_builds_info(&builds, "conda-forge", "feedstock-builds", "table")
+            .print_builds_info(&builds, "conda-forge", "feedstock-builds", "table", &[])
             .unwrap();
 
         // Test with empty builds list
         client
-            .print_builds_info(&[], "conda-forge", "feedstock-builds", "table")
+            .print_builds_info(&[], "conda-forge", "feedstock-builds", "table", &[])
             .unwrap();
     }
 
@@ -828,6 +1092,8 @@ mod tests {
         let client = AzureDevOpsClient {
             client: reqwest::Client::new(),
             token: None,
+            base_url: AZURE_DEVOPS_URL.to_string(),
+            listing_cache: None,
         };
 
         // Create builds with different definition names
@@ -936,6 +1202,8 @@ mod tests {
         let client = AzureDevOpsClient {
             client: reqwest::Client::new(),
             token: None,
+            base_url: AZURE_DEVOPS_URL.to_string(),
+            listing_cache: None,
         };
 
         // Create a build with missing queue_time field to test the fix
@@ -974,6 +1242,7 @@ mod tests {
                 "conda-forge",
                 "feedstock-builds",
                 "table",
+                &[],
             )
             .unwrap();
     }
@@ -983,6 +1252,8 @@ mod tests {
         let client = AzureDevOpsClient {
             client: reqwest::Client::new(),
             token: None,
+            base_url: AZURE_DEVOPS_URL.to_string(),
+            listing_cache: None,
         };
 
         // Create test artifacts for name filtering
@@ -1099,6 +1370,8 @@ mod tests {
         let client = AzureDevOpsClient {
             client: reqwest::Client::new(),
             token: None,
+            base_url: AZURE_DEVOPS_URL.to_string(),
+            listing_cache: None,
         };
 
         // Create test data
@@ -1145,29 +1418,62 @@ mod tests {
 
         // Test table format (should not panic)
         client
-            .print_builds_info(&builds, "conda-forge", "feedstock-builds", "table")
+            .print_builds_info(&builds, "conda-forge", "feedstock-builds", "table", &[])
+            .unwrap();
+        client
+            .print_artifacts_info(&artifacts, "table", &[])
             .unwrap();
-        client.print_artifacts_info(&artifacts, "table").unwrap();
 
         // Test YAML format (should not panic)
         client
-            .print_builds_info(&builds, "conda-forge", "feedstock-builds", "yaml")
+            .print_builds_info(&builds, "conda-forge", "feedstock-builds", "yaml", &[])
+            .unwrap();
+        client
+            .print_artifacts_info(&artifacts, "yaml", &[])
             .unwrap();
-        client.print_artifacts_info(&artifacts, "yaml").unwrap();
 
         // Test JSON format (should not panic)
         client
-            .print_builds_info(&builds, "conda-forge", "feedstock-builds", "json")
+            .print_builds_info(&builds, "conda-forge", "feedstock-builds", "json", &[])
+            .unwrap();
+        client
+            .print_artifacts_info(&artifacts, "json", &[])
             .unwrap();
-        client.print_artifacts_info(&artifacts, "json").unwrap();
 
         // Test invalid format (should return error)
         let result =
-            client.print_builds_info(&builds, "conda-forge", "feedstock-builds", "invalid");
+            client.print_builds_info(&builds, "conda-forge", "feedstock-builds", "invalid", &[]);
         assert!(result.is_err());
 
-        let result = client.print_artifacts_info(&artifacts, "invalid");
+        let result = client.print_artifacts_info(&artifacts, "invalid", &[]);
         assert!(result.is_err());
+
+        // Column selection and sorting
+        let columns = vec!["finish-time".to_string(), "build-id".to_string()];
+        client
+            .print_builds_info(
+                &builds,
+                "conda-forge",
+                "feedstock-builds",
+                "table",
+                &columns,
+            )
+            .unwrap();
+        let mut sorted = vec![
+            AzureDevOpsBuild {
+                id: 1002,
+                finish_time: Some("2024-10-24T10:30:00Z".to_string()),
+                ..builds[0].clone()
+            },
+            builds[0].clone(),
+        ];
+        client.sort_builds(&mut sorted, "finish-time").unwrap();
+        assert_eq!(sorted[0].id, 1001);
+        let mut artifacts = artifacts;
+        client.sort_artifacts(&mut artifacts, "size").unwrap();
+        assert!(client
+            .print_artifacts_info(&artifacts, "table", &["owner".to_string()])
+            .is_err());
     }
 
     #[test]
@@ -1175,6 +1481,8 @@ mod tests {
         let client = AzureDevOpsClient {
             client: reqwest::Client::new(),
             token: None,
+            base_url: AZURE_DEVOPS_URL.to_string(),
+            listing_cache: None,
         };
 
         // Test data with various field states to verify table formatting
@@ -1267,18 +1575,22 @@ mod tests {
         // Test table format with various field states - should not panic
         println!("Testing comfy-table integration for builds...");
         client
-            .print_builds_info(&builds, "test-org", "test-project", "table")
+            .print_builds_info(&builds, "test-org", "test-project", "table", &[])
             .unwrap();
 
         println!("Testing comfy-table integration for artifacts...");
-        client.print_artifacts_info(&artifacts, "table").unwrap();
+        client
+            .print_artifacts_info(&artifacts, "table", &[])
+            .unwrap();
 
         // Test YAML format with metadata comments
         println!("Testing YAML output with metadata...");
         client
-            .print_builds_info(&builds, "test-org", "test-project", "yaml")
+            .print_builds_info(&builds, "test-org", "test-project", "yaml", &[])
+            .unwrap();
+        client
+            .print_artifacts_info(&artifacts, "yaml", &[])
             .unwrap();
-        client.print_artifacts_info(&artifacts, "yaml").unwrap();
 
         // Verify the structures serialize to valid JSON (all fields included)
         let builds_json = serde_json::to_string_pretty(&builds).unwrap();
@@ -1291,4 +1603,50 @@ mod tests {
         assert!(artifacts_json.contains("downloadUrl"));
         assert!(artifacts_json.contains("properties"));
     }
+
+    #[tokio::test]
+    async fn test_api_requests_against_mock_server() {
+        use crate::test_util::{MockResponse, MockServer};
+
+        let server = MockServer::start().await.unwrap();
+        server.mock(
+            "GET",
+            "/org/project/_apis/build/builds/42/artifacts",
+            MockResponse::json(
+                200,
+                r#"{"count": 1, "value": [{"id": 1, "name": "conda_pkgs_linux", "source": "s",
+                    "resource": {"type": "Container", "data": "/1/conda", "url": "u"}}]}"#,
+            ),
+        );
+        server.mock(
+            "GET",
+            "/org/private/_apis/build/builds/42/artifacts",
+            MockResponse::new(
+                200,
+                "<!DOCTYPE html><html><form action=\"/_signin\"></form></html>",
+            ),
+        );
+
+        let config = Config {
+            azure_devops_token: Some("pat".to_string()),
+            ..Default::default()
+        };
+        let client = AzureDevOpsClient::new(&config)
+            .unwrap()
+            .with_base_url(server.url());
+
+        let artifacts = client.list_artifacts("org", "project", 42).await.unwrap();
+        assert_eq!(artifacts.len(), 1);
+        assert_eq!(artifacts[0].name, "conda_pkgs_linux");
+
+        let result = client.list_artifacts("org", "private", 42).await;
+        assert!(matches!(result, Err(MirrorError::AuthError(_))));
+
+        let requests = server.requests();
+        assert!(requests[0].path.ends_with("?api-version=6.0"));
+        assert!(requests[0]
+            .headers
+            .get("authorization")
+            .is_some_and(|value| value.starts_with("Basic ")));
+    }
 }
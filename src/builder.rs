@@ -0,0 +1,415 @@
+//! Fluent library API for mirroring
+//!
+//! [`Mirror::builder`] describes a run with typed [`Source`] and [`Target`]
+//! values instead of the `src_type`/`tgt_type` strings used by the CLI:
+//!
+//! ```rust,no_run
+//! use meso_forge_mirror::{Mirror, Source, Target};
+//!
+//! # async fn run() -> meso_forge_mirror::error::Result<()> {
+//! let report = Mirror::builder()
+//!     .source(Source::GithubArtifacts {
+//!         repository: "conda-forge/staged-recipes".to_string(),
+//!         artifact_id: None,
+//!     })
+//!     .target(Target::S3 {
+//!         bucket: "conda-mirror".to_string(),
+//!         prefix: Some("staging".to_string()),
+//!     })
+//!     .filter("conda-.*")
+//!     .concurrency(8)
+//!     .run()
+//!     .await?;
+//! println!("{} packages mirrored", report.mirrored_count());
+//! # Ok(())
+//! # }
+//! ```
+
+use rattler_cache::default_cache_dir;
+
+use crate::config::Config;
+use crate::error::{MirrorError, Result};
+use crate::mirror::{configured_repository, mirror_into, oci_backend, upload_batching};
+use crate::report::MirrorReport;
+use crate::repository::{PrefixDevBackend, Repository, RepositoryType};
+
+/// Where packages are mirrored from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// A single conda package on the local filesystem
+    LocalPackage { path: String },
+    /// A single conda package at a remote URL
+    PackageUrl { url: String },
+    /// A local ZIP file; packages are taken from the first member matching `member_pattern`
+    ZipFile {
+        path: String,
+        member_pattern: String,
+    },
+    /// A remote ZIP file; packages are taken from the first member matching `member_pattern`
+    ZipUrl { url: String, member_pattern: String },
+    /// A local tar.gz archive of conda packages
+    TarballFile { path: String },
+    /// A remote tar.gz archive of conda packages
+    TarballUrl { url: String },
+    /// Workflow artifacts of a GitHub repository (`owner/repo` or a GitHub URL)
+    GithubArtifacts {
+        repository: String,
+        artifact_id: Option<u64>,
+    },
+    /// Build artifacts of an Azure DevOps project (`org/project` or an Azure DevOps URL)
+    ///
+    /// Running with this source requires the `azure` feature.
+    AzureArtifacts {
+        project: String,
+        build_id: Option<u64>,
+    },
+    /// Every package listed in the repodata of a conda channel (a URL or a local directory)
+    ///
+    /// Empty `subdirs` mirror every subdir with a `repodata.json`; the filter
+    /// selects packages by filename.
+    Channel { url: String, subdirs: Vec<String> },
+    /// Job artifacts of a GitLab CI pipeline (`group/project` or a GitLab URL)
+    ///
+    /// Without a pipeline the latest successful one is mirrored.
+    GitlabArtifacts {
+        project: String,
+        pipeline_id: Option<u64>,
+    },
+}
+
+/// Where packages are mirrored to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// A conda channel on the local filesystem
+    Local { path: String },
+    /// A conda channel in an S3 bucket, optionally below a key prefix
+    ///
+    /// Running with this target requires the `s3` feature.
+    S3 {
+        bucket: String,
+        prefix: Option<String>,
+    },
+    /// A prefix.dev channel
+    PrefixDev { channel_url: String },
+    /// A rattler package cache; `None` uses the default cache directory
+    Cache { path: Option<String> },
+    /// A channel in an OCI registry, e.g. `oci://ghcr.io/org/channel`
+    Oci { reference: String },
+}
+
+/// Entry point of the fluent API
+pub struct Mirror;
+
+impl Mirror {
+    pub fn builder() -> MirrorBuilder {
+        MirrorBuilder::default()
+    }
+}
+
+/// Collects the settings of one mirroring run
+#[derive(Debug, Clone, Default)]
+pub struct MirrorBuilder {
+    source: Option<Source>,
+    target: Option<Target>,
+    filter: Option<String>,
+    http_client: Option<reqwest::Client>,
+    config: Config,
+}
+
+/// Arguments for [`mirror_packages`](crate::mirror_packages) derived from a builder
+#[derive(Debug, PartialEq)]
+struct MirrorArgs {
+    source: String,
+    src_path: Option<String>,
+    source_type: &'static str,
+    is_local_file: bool,
+    target_type: RepositoryType,
+    target_path: String,
+}
+
+impl MirrorBuilder {
+    pub fn source(mut self, source: Source) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    pub fn target(mut self, target: Target) -> Self {
+        self.target = Some(target);
+        self
+    }
+
+    /// Regular expression selecting GitHub, Azure DevOps or GitLab artifacts by name, or channel packages by filename
+    pub fn filter(mut self, pattern: impl Into<String>) -> Self {
+        self.filter = Some(pattern.into());
+        self
+    }
+
+    /// Match spec such as `numpy >=1.21,<2` a package must satisfy; with several, a package must satisfy one
+    pub fn spec(mut self, spec: impl Into<String>) -> Self {
+        self.config.specs.push(spec.into());
+        self
+    }
+
+    /// Maximum number of concurrent downloads
+    pub fn concurrency(mut self, max_concurrent_downloads: usize) -> Self {
+        self.config.max_concurrent_downloads = max_concurrent_downloads.max(1);
+        self
+    }
+
+    /// HTTP client for package downloads, the GitHub and Azure DevOps APIs and prefix.dev targets
+    ///
+    /// Lets callers configure proxies, TLS roots or tracing once. GitHub rejects
+    /// requests without a `User-Agent`, so the client should set one.
+    pub fn http_client(mut self, client: reqwest::Client) -> Self {
+        self.http_client = Some(client);
+        self
+    }
+
+    /// Base configuration, replacing any settings such as [`concurrency`](Self::concurrency) made before
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Mirror the packages of the source into the target
+    pub async fn run(self) -> Result<MirrorReport> {
+        let args = self.args()?;
+        let repository = match (&self.http_client, &args.target_type) {
+            (Some(client), RepositoryType::PrefixDev) => Repository::from_backend(
+                RepositoryType::PrefixDev,
+                args.target_path.clone(),
+                std::sync::Arc::new(
+                    PrefixDevBackend::with_client(args.target_path.clone(), client.clone())
+                        .with_batching(upload_batching(&self.config)),
+                ),
+            ),
+            (client, RepositoryType::Oci) => Repository::from_backend(
+                RepositoryType::Oci,
+                args.target_path.clone(),
+                std::sync::Arc::new(oci_backend(
+                    &args.target_path,
+                    &self.config,
+                    client.clone(),
+                )?),
+            ),
+            _ => Repository::new(args.target_type.clone(), args.target_path.clone()),
+        };
+        let mut config = self.config;
+        if let Some(Source::Channel { subdirs, .. }) = &self.source {
+            if !subdirs.is_empty() {
+                config.src_subdirs = subdirs.clone();
+            }
+        }
+        let mut repository = configured_repository(repository, &config)?;
+
+        mirror_into(
+            &args.source,
+            args.src_path.as_deref(),
+            args.source_type,
+            args.is_local_file,
+            &mut repository,
+            &config,
+            self.http_client,
+        )
+        .await
+    }
+
+    fn args(&self) -> Result<MirrorArgs> {
+        let source = self
+            .source
+            .clone()
+            .ok_or_else(|| MirrorError::InvalidInput("No mirror source set".to_string()))?;
+        let target = self
+            .target
+            .clone()
+            .ok_or_else(|| MirrorError::InvalidInput("No mirror target set".to_string()))?;
+
+        if let Some(pattern) = &self.filter {
+            regex::Regex::new(pattern)?;
+            if !matches!(
+                source,
+                Source::GithubArtifacts { .. }
+                    | Source::AzureArtifacts { .. }
+                    | Source::GitlabArtifacts { .. }
+                    | Source::Channel { .. }
+            ) {
+                return Err(MirrorError::InvalidInput(
+                    "A name filter only applies to GitHub, Azure DevOps or GitLab artifact sources and channels"
+                        .to_string(),
+                ));
+            }
+        }
+
+        let (source, src_path, source_type, is_local_file) = match source {
+            Source::LocalPackage { path } => (path, None, "local", true),
+            Source::PackageUrl { url } => (url, None, "url", false),
+            Source::ZipFile {
+                path,
+                member_pattern,
+            } => {
+                regex::Regex::new(&member_pattern)?;
+                (path, Some(member_pattern), "zip", true)
+            }
+            Source::ZipUrl {
+                url,
+                member_pattern,
+            } => {
+                regex::Regex::new(&member_pattern)?;
+                (url, Some(member_pattern), "zip-url", false)
+            }
+            Source::TarballFile { path } => (path, None, "tgz", true),
+            Source::TarballUrl { url } => (url, None, "tgz-url", false),
+            Source::GithubArtifacts {
+                repository,
+                artifact_id,
+            } => {
+                crate::github::parse_github_repository(&repository)?;
+                let source = match artifact_id {
+                    Some(id) => format!("{}#{}", repository, id),
+                    None => repository,
+                };
+                (source, self.filter.clone(), "github", false)
+            }
+            Source::AzureArtifacts { project, build_id } => {
+                let source = match build_id {
+                    Some(id) => format!("{}#{}", project, id),
+                    None => project,
+                };
+                crate::azure::parse_azure_source(&source)?;
+                (source, self.filter.clone(), "azure", false)
+            }
+            Source::GitlabArtifacts {
+                project,
+                pipeline_id,
+            } => {
+                let source = match pipeline_id {
+                    Some(id) => format!("{}#{}", project, id),
+                    None => project,
+                };
+                crate::gitlab::parse_gitlab_source(&source)?;
+                (source, self.filter.clone(), "gitlab", false)
+            }
+            Source::Channel { url, .. } => (url, self.filter.clone(), "channel", false),
+        };
+
+        let (target_type, target_path) = match target {
+            Target::Local { path } => (RepositoryType::Local, path),
+            Target::S3 { bucket, prefix } => {
+                let path = match prefix.as_deref().map(|p| p.trim_matches('/')) {
+                    Some(prefix) if !prefix.is_empty() => format!("s3://{}/{}", bucket, prefix),
+                    _ => format!("s3://{}", bucket),
+                };
+                (RepositoryType::S3, path)
+            }
+            Target::PrefixDev { channel_url } => (RepositoryType::PrefixDev, channel_url),
+            Target::Oci { reference } => (RepositoryType::Oci, reference),
+            Target::Cache { path } => {
+                let path = match path {
+                    Some(path) => path,
+                    None => default_cache_dir()
+                        .map_err(|e| {
+                            MirrorError::InvalidInput(format!(
+                                "Failed to get default cache directory: {}",
+                                e
+                            ))
+                        })?
+                        .to_string_lossy()
+                        .to_string(),
+                };
+                (RepositoryType::Cache, path)
+            }
+        };
+
+        Ok(MirrorArgs {
+            source,
+            src_path,
+            source_type,
+            is_local_file,
+            target_type,
+            target_path,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder_github_to_s3() {
+        let builder = Mirror::builder()
+            .source(Source::GithubArtifacts {
+                repository: "owner/repo".to_string(),
+                artifact_id: Some(42),
+            })
+            .target(Target::S3 {
+                bucket: "bucket".to_string(),
+                prefix: Some("/channel/".to_string()),
+            })
+            .filter("conda-.*")
+            .concurrency(8);
+
+        assert_eq!(builder.config.max_concurrent_downloads, 8);
+        assert_eq!(
+            builder.args().unwrap(),
+            MirrorArgs {
+                source: "owner/repo#42".to_string(),
+                src_path: Some("conda-.*".to_string()),
+                source_type: "github",
+                is_local_file: false,
+                target_type: RepositoryType::S3,
+                target_path: "s3://bucket/channel".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_builder_zip_uses_member_pattern() {
+        let args = Mirror::builder()
+            .source(Source::ZipFile {
+                path: "artifacts.zip".to_string(),
+                member_pattern: ".*/packages/".to_string(),
+            })
+            .target(Target::Local {
+                path: "./repo".to_string(),
+            })
+            .args()
+            .unwrap();
+
+        assert_eq!(args.source_type, "zip");
+        assert!(args.is_local_file);
+        assert_eq!(args.src_path.as_deref(), Some(".*/packages/"));
+        assert_eq!(args.target_type, RepositoryType::Local);
+    }
+
+    #[test]
+    fn test_builder_rejects_incomplete_or_invalid_settings() {
+        assert!(matches!(
+            Mirror::builder().args(),
+            Err(MirrorError::InvalidInput(_))
+        ));
+
+        let result = Mirror::builder()
+            .source(Source::PackageUrl {
+                url: "https://example.com/pkg-1.0-0.conda".to_string(),
+            })
+            .target(Target::Local {
+                path: "./repo".to_string(),
+            })
+            .filter("conda-.*")
+            .args();
+        assert!(matches!(result, Err(MirrorError::InvalidInput(_))));
+
+        let result = Mirror::builder()
+            .source(Source::GithubArtifacts {
+                repository: "owner/repo".to_string(),
+                artifact_id: None,
+            })
+            .target(Target::Local {
+                path: "./repo".to_string(),
+            })
+            .filter("(unclosed")
+            .args();
+        assert!(matches!(result, Err(MirrorError::InvalidInput(_))));
+    }
+}
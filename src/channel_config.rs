@@ -0,0 +1,126 @@
+//! Channel configuration pointing clients at a mirror
+//!
+//! After a successful run into a local, S3, prefix.dev or OCI target, `mirror`
+//! prints `pixi.toml` and `.condarc` snippets that add the mirror as a channel,
+//! ready to paste into a project or user configuration.
+
+use url::Url;
+
+use crate::config::Config;
+use crate::error::{MirrorError, Result};
+use crate::oci::OciChannel;
+use crate::repository::{s3_bucket_and_prefix, RepositoryType};
+
+/// URL clients reach a mirrored channel at
+///
+/// `public_url` from the configuration wins, e.g. when the mirror is served
+/// over HTTPS or through a CDN. Otherwise local targets become `file://` URLs,
+/// S3 targets the HTTPS URL of their bucket and OCI targets their `oci://`
+/// URL. Package caches are not channels and have no URL.
+pub fn channel_url(
+    repo_type: &RepositoryType,
+    target: &str,
+    config: &Config,
+) -> Result<Option<String>> {
+    let url = match (repo_type, &config.public_url) {
+        (RepositoryType::Cache, _) => return Ok(None),
+        (_, Some(url)) => url.trim_end_matches('/').to_string(),
+        (RepositoryType::PrefixDev, None) => target.trim_end_matches('/').to_string(),
+        (RepositoryType::Oci, None) => OciChannel::parse(target)?.url(),
+        (RepositoryType::Local, None) => {
+            let path = std::fs::canonicalize(target)?;
+            Url::from_directory_path(&path)
+                .map_err(|_| {
+                    MirrorError::InvalidInput(format!("Invalid channel path {}", path.display()))
+                })?
+                .as_str()
+                .trim_end_matches('/')
+                .to_string()
+        }
+        (RepositoryType::S3, None) => {
+            let (bucket, prefix) = s3_bucket_and_prefix(target)?;
+            let base = match &config.s3_endpoint {
+                Some(endpoint) => format!("{}/{}", endpoint.trim_end_matches('/'), bucket),
+                None => format!(
+                    "https://{}.s3.{}.amazonaws.com",
+                    bucket,
+                    config.s3_region.as_deref().unwrap_or("us-east-1")
+                ),
+            };
+            if prefix.is_empty() {
+                base
+            } else {
+                format!("{}/{}", base, prefix)
+            }
+        }
+    };
+    Ok(Some(url))
+}
+
+/// `pixi.toml` and `.condarc` snippets listing the mirror as the first channel
+///
+/// Strict channel priority makes clients take every package the mirror has
+/// from it, even when channels listed after it carry newer versions.
+pub fn snippets(url: &str) -> String {
+    format!(
+        r#"# pixi.toml
+[workspace]
+channels = [
+    "{url}",  # first, so strict priority prefers the mirror over later channels
+]
+channel-priority = "strict"
+
+# .condarc
+channels:
+  - {url}  # first, so strict priority prefers the mirror over later channels
+channel_priority: strict
+"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel_url() {
+        let mut config = Config::default();
+        assert_eq!(
+            channel_url(&RepositoryType::S3, "s3://bucket/conda/", &config).unwrap(),
+            Some("https://bucket.s3.us-east-1.amazonaws.com/conda".to_string())
+        );
+        config.s3_endpoint = Some("https://minio.example.com/".to_string());
+        assert_eq!(
+            channel_url(&RepositoryType::S3, "s3://bucket", &config).unwrap(),
+            Some("https://minio.example.com/bucket".to_string())
+        );
+        assert_eq!(
+            channel_url(&RepositoryType::Cache, "/cache", &config).unwrap(),
+            None
+        );
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let url = channel_url(
+            &RepositoryType::Local,
+            &temp_dir.path().to_string_lossy(),
+            &config,
+        )
+        .unwrap()
+        .unwrap();
+        assert!(url.starts_with("file:///"));
+        assert!(!url.ends_with('/'));
+
+        config.public_url = Some("https://conda.example.com/mirror/".to_string());
+        assert_eq!(
+            channel_url(&RepositoryType::Local, "/srv/mirror", &config).unwrap(),
+            Some("https://conda.example.com/mirror".to_string())
+        );
+        assert_eq!(
+            channel_url(&RepositoryType::Cache, "/cache", &config).unwrap(),
+            None
+        );
+        let snippets = snippets("https://conda.example.com/mirror");
+        assert!(snippets.contains("\"https://conda.example.com/mirror\","));
+        assert!(snippets.contains("  - https://conda.example.com/mirror"));
+    }
+}
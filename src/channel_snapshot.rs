@@ -0,0 +1,332 @@
+//! Dated, immutable copies of a local channel
+//!
+//! `snapshot` freezes what a local channel serves today under
+//! `snapshots/<name>/` at its root, named after the date by default, so that
+//! environments can be solved again later against "the channel as of
+//! 2025-06-01" with `--channel ./my-conda-repo/snapshots/2025-06-01`. Each
+//! package the repodata of a subdir lists is hard-linked into the snapshot,
+//! taking no extra space, and copied only where the snapshot is on another
+//! file system. The `repodata.json` of every subdir and the signatures are
+//! copied as they were. Package files are only ever replaced by renaming a new
+//! file into place, so later runs, prunes and rollbacks leave the linked copies
+//! of a snapshot untouched.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use crate::error::{MirrorError, Result};
+use crate::signing::signature_filename;
+use crate::snapshot::SNAPSHOT_DIR;
+
+/// Directory at the channel root holding the channel snapshots
+pub const CHANNEL_SNAPSHOT_DIR: &str = "snapshots";
+
+/// Description written to `snapshot.json` at the root of each snapshot
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelSnapshot {
+    pub name: String,
+    pub taken_at: DateTime<Utc>,
+    /// The channel the snapshot was taken of
+    pub channel: String,
+    /// Subdirs that had a `repodata.json`
+    pub subdirs: Vec<String>,
+    pub packages: usize,
+    /// Packages copied because they could not be hard-linked
+    pub copied: usize,
+}
+
+/// The name a snapshot taken now gets by default, e.g. `2025-06-01`
+pub fn default_name() -> String {
+    Utc::now().format("%Y-%m-%d").to_string()
+}
+
+/// Where the snapshots of `channel` are kept unless another directory is given
+pub fn snapshot_root(channel: &Path) -> PathBuf {
+    channel.join(CHANNEL_SNAPSHOT_DIR)
+}
+
+/// Snapshot `channel` to `<root>/<name>`, `root` being `snapshots/` of the channel by default
+///
+/// An existing snapshot is never changed, so taking one under a name already
+/// in use fails.
+pub fn take(channel: &Path, name: &str, root: Option<&Path>) -> Result<ChannelSnapshot> {
+    if name.is_empty()
+        || name.starts_with('.')
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+    {
+        return Err(MirrorError::InvalidInput(format!(
+            "Invalid snapshot name '{}': use letters, digits, '.', '_' and '-', e.g. 2025-06-01",
+            name
+        )));
+    }
+    let root = root.map_or_else(|| snapshot_root(channel), Path::to_path_buf);
+    let destination = root.join(name);
+    if destination.exists() {
+        return Err(MirrorError::InvalidInput(format!(
+            "Snapshot {} already exists; snapshots are never changed, pick another name",
+            destination.display()
+        )));
+    }
+
+    // Built aside and renamed into place, so a snapshot is either complete or absent
+    let partial = root.join(format!(".{}.partial", name));
+    if partial.exists() {
+        std::fs::remove_dir_all(&partial).map_err(|e| MirrorError::target_io(&partial, e))?;
+    }
+    let mut snapshot = ChannelSnapshot {
+        name: name.to_string(),
+        taken_at: Utc::now(),
+        channel: channel.to_string_lossy().to_string(),
+        subdirs: Vec::new(),
+        packages: 0,
+        copied: 0,
+    };
+    let result = freeze(channel, &partial, &mut snapshot);
+    if let Err(e) = result {
+        let _ = std::fs::remove_dir_all(&partial);
+        return Err(e);
+    }
+    if snapshot.subdirs.is_empty() {
+        let _ = std::fs::remove_dir_all(&partial);
+        return Err(MirrorError::NotFound(format!(
+            "No repodata.json in any subdir of {}",
+            channel.display()
+        )));
+    }
+    write(
+        &partial.join("snapshot.json"),
+        &serde_json::to_vec_pretty(&snapshot)?,
+    )?;
+    std::fs::rename(&partial, &destination).map_err(|e| MirrorError::target_io(&destination, e))?;
+
+    info!(
+        "Snapshot {} of {}: {} packages in {} subdirs ({} copied)",
+        name,
+        channel.display(),
+        snapshot.packages,
+        snapshot.subdirs.len(),
+        snapshot.copied
+    );
+    Ok(snapshot)
+}
+
+/// Link the packages and repodata of every subdir of `channel` into `destination`
+fn freeze(channel: &Path, destination: &Path, snapshot: &mut ChannelSnapshot) -> Result<()> {
+    let mut subdirs: Vec<String> = std::fs::read_dir(channel)
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => {
+                MirrorError::NotFound(format!("Channel directory {}", channel.display()))
+            }
+            _ => MirrorError::target_io(channel, e),
+        })?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().join("repodata.json").is_file())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| name != CHANNEL_SNAPSHOT_DIR && name != SNAPSHOT_DIR)
+        .collect();
+    subdirs.sort();
+
+    for subdir in subdirs {
+        let source_dir = channel.join(&subdir);
+        let target_dir = destination.join(&subdir);
+        std::fs::create_dir_all(&target_dir).map_err(|e| MirrorError::target_io(&target_dir, e))?;
+
+        let repodata_path = source_dir.join("repodata.json");
+        let content =
+            std::fs::read(&repodata_path).map_err(|e| MirrorError::target_io(&repodata_path, e))?;
+        let mut repodata: serde_json::Value = serde_json::from_slice(&content)?;
+
+        let mut missing = Vec::new();
+        for section in ["packages", "packages.conda"] {
+            let Some(records) = repodata.get(section).and_then(|p| p.as_object()) else {
+                continue;
+            };
+            for filename in records.keys() {
+                let source = source_dir.join(filename);
+                if !source.is_file() {
+                    warn!(
+                        "Leaving {}/{} out of snapshot {}: its file is missing",
+                        subdir, filename, snapshot.name
+                    );
+                    missing.push((section, filename.clone()));
+                    continue;
+                }
+                if !link(&source, &target_dir.join(filename))? {
+                    snapshot.copied += 1;
+                }
+                snapshot.packages += 1;
+                copy_signature(&source_dir, &target_dir, filename)?;
+            }
+        }
+
+        if missing.is_empty() {
+            write(&target_dir.join("repodata.json"), &content)?;
+            copy_signature(&source_dir, &target_dir, "repodata.json")?;
+        } else {
+            // The signature no longer matches the trimmed repodata, so it is left out
+            for (section, filename) in missing {
+                if let Some(records) = repodata.get_mut(section).and_then(|p| p.as_object_mut()) {
+                    records.remove(&filename);
+                }
+            }
+            write(
+                &target_dir.join("repodata.json"),
+                &serde_json::to_vec_pretty(&repodata)?,
+            )?;
+        }
+        snapshot.subdirs.push(subdir);
+    }
+
+    // Clients read noarch of every channel, even one without noarch packages
+    if !snapshot.subdirs.is_empty() && !snapshot.subdirs.iter().any(|s| s == "noarch") {
+        let noarch = destination.join("noarch");
+        std::fs::create_dir_all(&noarch).map_err(|e| MirrorError::target_io(&noarch, e))?;
+        let empty = serde_json::json!({
+            "info": { "subdir": "noarch" },
+            "packages": {},
+            "packages.conda": {},
+        });
+        write(
+            &noarch.join("repodata.json"),
+            &serde_json::to_vec_pretty(&empty)?,
+        )?;
+    }
+    Ok(())
+}
+
+/// Hard-link `source` to `destination`, copying it where linking is not possible
+///
+/// Returns whether the file was linked.
+fn link(source: &Path, destination: &Path) -> Result<bool> {
+    match std::fs::hard_link(source, destination) {
+        Ok(()) => Ok(true),
+        Err(e) => {
+            info!("Copying {} instead of linking it: {}", source.display(), e);
+            std::fs::copy(source, destination)
+                .map_err(|e| MirrorError::target_io(destination, e))?;
+            Ok(false)
+        }
+    }
+}
+
+/// Copy the signature of `filename`, if it has one
+///
+/// Signatures are rewritten in place when a key is rotated, so they are not linked.
+fn copy_signature(source_dir: &Path, target_dir: &Path, filename: &str) -> Result<()> {
+    let signature = source_dir.join(signature_filename(filename));
+    if signature.is_file() {
+        let destination = target_dir.join(signature_filename(filename));
+        std::fs::copy(&signature, &destination)
+            .map_err(|e| MirrorError::target_io(&destination, e))?;
+    }
+    Ok(())
+}
+
+fn write(path: &Path, content: &[u8]) -> Result<()> {
+    std::fs::write(path, content).map_err(|e| MirrorError::target_io(path, e))
+}
+
+/// Snapshots kept under `root`, oldest name first
+pub fn list(root: &Path) -> Result<Vec<ChannelSnapshot>> {
+    let entries = match std::fs::read_dir(root) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(MirrorError::target_io(root, e)),
+    };
+    let mut snapshots = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(|e| MirrorError::target_io(root, e))?.path();
+        let Ok(content) = std::fs::read(path.join("snapshot.json")) else {
+            continue;
+        };
+        snapshots.push(serde_json::from_slice::<ChannelSnapshot>(&content)?);
+    }
+    snapshots.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(snapshots)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::{Repository, RepositoryType};
+    use crate::test_support::PackageFixture;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_snapshot_links_packages_and_freezes_repodata() {
+        let temp_dir = TempDir::new().unwrap();
+        let channel = temp_dir.path().join("channel");
+        let mut repository =
+            Repository::new(RepositoryType::Local, channel.to_string_lossy().to_string());
+        let numpy = PackageFixture::new("numpy", "1.26.4").subdir("linux-64");
+        repository
+            .upload_package(&numpy.conda_filename(), numpy.to_conda())
+            .await
+            .unwrap();
+        repository.finalize_repository().await.unwrap();
+        let repodata = std::fs::read(channel.join("linux-64/repodata.json")).unwrap();
+
+        let snapshot = take(&channel, "2025-06-01", None).unwrap();
+        assert_eq!(snapshot.packages, 1);
+        assert_eq!(snapshot.copied, 0);
+        let frozen = channel.join("snapshots/2025-06-01");
+        assert_eq!(
+            std::fs::read(frozen.join("linux-64/repodata.json")).unwrap(),
+            repodata
+        );
+        assert!(frozen.join("noarch/repodata.json").is_file());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let path = format!("linux-64/{}", numpy.conda_filename());
+            let original = std::fs::metadata(channel.join(&path)).unwrap();
+            let linked = std::fs::metadata(frozen.join(&path)).unwrap();
+            assert_eq!(original.ino(), linked.ino());
+        }
+
+        // Later changes to the channel leave the snapshot alone
+        let scipy = PackageFixture::new("scipy", "1.12.0").subdir("linux-64");
+        repository
+            .upload_package(&scipy.conda_filename(), scipy.to_conda())
+            .await
+            .unwrap();
+        repository.finalize_repository().await.unwrap();
+        std::fs::remove_file(channel.join("linux-64").join(numpy.conda_filename())).unwrap();
+        assert_eq!(
+            std::fs::read(frozen.join("linux-64/repodata.json")).unwrap(),
+            repodata
+        );
+        assert!(frozen
+            .join("linux-64")
+            .join(numpy.conda_filename())
+            .is_file());
+
+        // The missing numpy file is left out of the next snapshot
+        let next = take(&channel, "2025-06-02", None).unwrap();
+        assert_eq!(next.packages, 1);
+        let trimmed =
+            std::fs::read_to_string(channel.join("snapshots/2025-06-02/linux-64/repodata.json"))
+                .unwrap();
+        assert!(trimmed.contains("scipy-1.12.0-0.conda"));
+        assert!(!trimmed.contains("numpy-1.26.4-0.conda"));
+
+        assert!(matches!(
+            take(&channel, "2025-06-01", None),
+            Err(MirrorError::InvalidInput(_))
+        ));
+        assert!(matches!(
+            take(&channel, "../escape", None),
+            Err(MirrorError::InvalidInput(_))
+        ));
+        let names: Vec<String> = list(&snapshot_root(&channel))
+            .unwrap()
+            .into_iter()
+            .map(|snapshot| snapshot.name)
+            .collect();
+        assert_eq!(names, ["2025-06-01", "2025-06-02"]);
+    }
+}
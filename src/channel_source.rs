@@ -0,0 +1,272 @@
+//! Every package of a conda channel as a source
+//!
+//! `--src-type channel` reads the `repodata.json` of the selected subdirs of a
+//! channel, such as `https://conda.anaconda.org/conda-forge` or a local
+//! channel directory, and offers each package it lists, optionally only those
+//! whose filename matches `--src-path`. Up to `max_concurrent_downloads`
+//! packages are downloaded ahead of the upload, and each download is checked
+//! against the size and sha256 (or md5, for records without one) of its
+//! repodata record before it is mirrored. Packages the target's repodata
+//! already lists with the same sha256 are skipped without being downloaded,
+//! unless `--force` is given.
+
+use async_trait::async_trait;
+use futures::stream::{self, StreamExt};
+use md5::Md5;
+use regex::Regex;
+use reqwest::Client;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use tracing::info;
+
+use crate::config::Config;
+use crate::download::verify_download_size;
+use crate::error::{MirrorError, Result};
+use crate::mirror::{
+    download_package, exclude_regex, excluded, prefetched, push_exclude_hint, skipped_stream,
+};
+use crate::report::SkipCode;
+use crate::sbom::{load_channel, ChannelPackage};
+use crate::source::{PackageEntry, PackageStream, SourceProvider};
+
+/// Whether `channel` is the URL of a remote channel rather than a local directory
+pub fn is_remote(channel: &str) -> bool {
+    channel.starts_with("http://") || channel.starts_with("https://")
+}
+
+/// Where the package of a repodata record is downloaded from
+pub fn package_url(channel: &str, package: &ChannelPackage) -> String {
+    format!(
+        "{}/{}/{}",
+        channel.trim_end_matches('/'),
+        package.subdir,
+        package.filename
+    )
+}
+
+/// Check downloaded content against the size and checksum of its repodata record
+///
+/// The sha256 is compared when the record has one, the md5 otherwise.
+pub fn verify_record(package: &ChannelPackage, source_url: &str, content: &[u8]) -> Result<()> {
+    verify_download_size(source_url, package.size, content.len())?;
+    let (algorithm, expected, actual) = match (&package.sha256, &package.md5) {
+        (Some(expected), _) => ("sha256", expected, format!("{:x}", Sha256::digest(content))),
+        (None, Some(expected)) => ("md5", expected, format!("{:x}", Md5::digest(content))),
+        (None, None) => return Ok(()),
+    };
+    if !actual.eq_ignore_ascii_case(expected) {
+        return Err(MirrorError::Corrupt(format!(
+            "{} of {} is {}, repodata lists {}",
+            algorithm, source_url, actual, expected
+        )));
+    }
+    Ok(())
+}
+
+/// The packages listed in the repodata of a conda channel
+pub(crate) struct ChannelProvider {
+    pub channel: String,
+    /// Regular expression selecting packages by filename
+    pub package_filter: Option<String>,
+    pub client: Client,
+    pub config: Config,
+}
+
+#[async_trait]
+impl SourceProvider for ChannelProvider {
+    fn name(&self) -> &str {
+        &self.channel
+    }
+
+    async fn entries(&self) -> Result<PackageStream> {
+        let config = &self.config;
+        let filter = self.package_filter.as_deref().map(Regex::new).transpose()?;
+        let exclude = exclude_regex(config)?;
+        info!("Reading the repodata of channel {}", self.channel);
+        let packages = load_channel(&self.client, &self.channel, &config.src_subdirs).await?;
+        let listed = packages.len();
+
+        let mut skipped = Vec::new();
+        let selected: Vec<ChannelPackage> = packages
+            .into_iter()
+            .filter(|package| {
+                filter
+                    .as_ref()
+                    .is_none_or(|filter| filter.is_match(&package.filename))
+            })
+            .filter(|package| {
+                excluded(exclude.as_ref(), "package", &package.filename)
+                    .map(|item| skipped.push(item))
+                    .is_none()
+            })
+            .collect();
+        if selected.is_empty() {
+            let mut message = format!("No packages of channel {}", self.channel);
+            if let Some(pattern) = &self.package_filter {
+                message.push_str(&format!(" match '{}'", pattern));
+            }
+            push_exclude_hint(&mut message, config);
+            return Err(MirrorError::NotFound(message));
+        }
+
+        info!(
+            "Mirroring {} of the {} packages listed by {}",
+            selected.len(),
+            listed,
+            self.channel
+        );
+
+        let entries: Vec<Result<PackageEntry>> = selected
+            .into_iter()
+            .map(|package| {
+                let url = package_url(&self.channel, &package);
+                let filename = package.filename.clone();
+                let size = package.size;
+                let client = self.client.clone();
+                let config = self.config.clone();
+                let origin = url.clone();
+                let present = is_present(&package, self.config.target_packages.as_deref());
+                let fetch = async move {
+                    let content = download_package(&client, &url, &config).await?;
+                    verify_record(&package, &url, &content)?;
+                    Ok(content)
+                };
+                let entry = PackageEntry::new(filename, size, fetch).with_origin(origin);
+                Ok(if present {
+                    // Packages the target already has are not downloaded again
+                    entry.skipped(
+                        SkipCode::AlreadyPresent,
+                        "identical copy already listed by the target",
+                    )
+                } else {
+                    entry
+                })
+            })
+            .collect();
+        let budget = config.max_concurrent_downloads.max(1);
+        Ok(skipped_stream(skipped)
+            .chain(prefetched(stream::iter(entries).boxed(), budget))
+            .boxed())
+    }
+}
+
+/// Whether the target lists the package with the sha256 of its repodata record
+fn is_present(package: &ChannelPackage, target_packages: Option<&HashMap<String, String>>) -> bool {
+    let stored = target_packages.and_then(|packages| packages.get(&package.filename));
+    match (stored, &package.sha256) {
+        (Some(stored), Some(sha256)) => stored.eq_ignore_ascii_case(sha256),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::PackageFixture;
+    use crate::test_util::{MockResponse, MockServer};
+
+    #[tokio::test]
+    async fn test_channel_packages_are_verified_against_repodata() {
+        let server = MockServer::start().await.unwrap();
+        let numpy = PackageFixture::new("numpy", "1.26.0")
+            .subdir("linux-64")
+            .to_conda();
+        let scipy = PackageFixture::new("scipy", "1.11.0")
+            .subdir("linux-64")
+            .to_conda();
+        let record = |name: &str, content: &[u8], sha256: String| {
+            serde_json::json!({
+                "name": name, "version": "1.0", "build": "0", "subdir": "linux-64",
+                "size": content.len(), "sha256": sha256
+            })
+        };
+        let repodata = serde_json::json!({
+            "info": { "subdir": "linux-64" },
+            "packages": {},
+            "packages.conda": {
+                "numpy-1.26.0-0.conda":
+                    record("numpy", &numpy, format!("{:x}", Sha256::digest(&numpy))),
+                "scipy-1.11.0-0.conda": record("scipy", &scipy, "00".repeat(32)),
+                "pandas-2.1.0-0.conda": record("pandas", b"", String::new()),
+            }
+        });
+        server.mock(
+            "GET",
+            "/conda-forge/linux-64/repodata.json",
+            MockResponse::json(200, repodata.to_string()),
+        );
+        server.mock(
+            "GET",
+            "/conda-forge/linux-64/numpy-1.26.0-0.conda",
+            MockResponse::new(200, numpy.to_vec()),
+        );
+        server.mock(
+            "GET",
+            "/conda-forge/linux-64/scipy-1.11.0-0.conda",
+            MockResponse::new(200, scipy.to_vec()),
+        );
+
+        let provider = ChannelProvider {
+            channel: format!("{}/conda-forge/", server.url()),
+            package_filter: Some("^(numpy|scipy)-".to_string()),
+            client: Client::new(),
+            config: Config {
+                src_subdirs: vec!["linux-64".to_string()],
+                retry_attempts: 1,
+                ..Default::default()
+            },
+        };
+        let entries: Vec<PackageEntry> = provider
+            .entries()
+            .await
+            .unwrap()
+            .map(|entry| entry.unwrap())
+            .collect()
+            .await;
+        assert_eq!(entries.len(), 2);
+        let mut entries = entries.into_iter();
+
+        let numpy_entry = entries.next().unwrap();
+        assert_eq!(numpy_entry.name, "numpy-1.26.0-0.conda");
+        assert_eq!(numpy_entry.size, Some(numpy.len() as u64));
+        assert_eq!(
+            numpy_entry.origin.as_deref(),
+            Some(format!("{}/conda-forge/linux-64/numpy-1.26.0-0.conda", server.url()).as_str())
+        );
+        assert_eq!(numpy_entry.fetch.await.unwrap(), numpy);
+
+        let scipy_entry = entries.next().unwrap();
+        let err = scipy_entry.fetch.await.unwrap_err();
+        assert!(
+            matches!(err, MirrorError::Corrupt(ref message) if message.contains("repodata lists 0000"))
+        );
+
+        let provider = ChannelProvider {
+            package_filter: Some("^matplotlib-".to_string()),
+            ..provider
+        };
+        assert!(matches!(
+            provider.entries().await,
+            Err(MirrorError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_verify_record_falls_back_to_md5() {
+        let mut package: ChannelPackage = serde_json::from_value(serde_json::json!({
+            "name": "pkg", "version": "1.0", "build": "0",
+            "md5": format!("{:X}", Md5::digest(b"content"))
+        }))
+        .unwrap();
+        assert!(verify_record(&package, "pkg", b"content").is_ok());
+        assert!(matches!(
+            verify_record(&package, "pkg", b"altered"),
+            Err(MirrorError::Corrupt(_))
+        ));
+        package.size = Some(3);
+        assert!(matches!(
+            verify_record(&package, "pkg", b"content"),
+            Err(MirrorError::Truncated { .. })
+        ));
+    }
+}
@@ -0,0 +1,251 @@
+//! `channeldata.json` with mirror extensions
+//!
+//! Whenever `mirror-manifest.json` changes, `channeldata.json` at the channel
+//! root is rewritten from the repodata of every subdir: each package name gets
+//! the subdirs it is built for, its latest version and its license, as
+//! conda-index writes them. Under the `meso_forge_mirror` key, each package
+//! also lists the files of it that were mirrored, with the source and time from
+//! the manifest and whether the file still has the sha256 it was mirrored with,
+//! and counts the files uploaded natively; a channel-wide `meso_forge_mirror`
+//! entry sums them up. Fields other tools wrote to an existing
+//! `channeldata.json` are kept.
+
+use chrono::{DateTime, Utc};
+use rattler_conda_types::Version;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::str::FromStr;
+
+use crate::error::{MirrorError, Result};
+use crate::manifest::MirrorManifest;
+
+/// Name of the channel data at the channel root
+pub const CHANNELDATA_FILENAME: &str = "channeldata.json";
+
+/// Key of the mirror extension, per package and for the whole channel
+pub const EXTENSION_KEY: &str = "meso_forge_mirror";
+
+/// What the repodata tells about one package name
+#[derive(Default)]
+struct PackageSummary {
+    subdirs: BTreeSet<String>,
+    version: Option<String>,
+    license: Option<String>,
+    /// Mirrored files by `subdir/filename`, with their manifest details
+    mirrored: Map<String, Value>,
+    native: usize,
+    modified: usize,
+}
+
+impl PackageSummary {
+    /// Keep the version and license of the newest record
+    fn add_version(&mut self, version: &str, license: Option<&str>) {
+        let newer = match &self.version {
+            None => true,
+            Some(latest) => match (Version::from_str(version), Version::from_str(latest)) {
+                (Ok(version), Ok(latest)) => version > latest,
+                _ => version > latest.as_str(),
+            },
+        };
+        if newer {
+            self.version = Some(version.to_string());
+            self.license = license.map(str::to_string);
+        }
+    }
+}
+
+/// `channeldata.json` for the repodata of every subdir, merged into `existing`
+pub fn build(
+    existing: Option<&[u8]>,
+    repodata: &BTreeMap<String, Vec<u8>>,
+    manifest: &MirrorManifest,
+    generated_at: DateTime<Utc>,
+) -> Result<Value> {
+    let mut summaries: BTreeMap<String, PackageSummary> = BTreeMap::new();
+    for (subdir, content) in repodata {
+        let repodata: Value = serde_json::from_slice(content).map_err(|e| {
+            MirrorError::Corrupt(format!("Invalid repodata.json of {}: {}", subdir, e))
+        })?;
+        for key in ["packages", "packages.conda"] {
+            let Some(records) = repodata[key].as_object() else {
+                continue;
+            };
+            for (filename, record) in records {
+                let Some(name) = record["name"].as_str() else {
+                    continue;
+                };
+                let summary = summaries.entry(name.to_string()).or_default();
+                summary.subdirs.insert(subdir.clone());
+                if let Some(version) = record["version"].as_str() {
+                    summary.add_version(version, record["license"].as_str());
+                }
+
+                let path = format!("{}/{}", subdir, filename);
+                match manifest.packages.get(&path) {
+                    Some(entry) => {
+                        let verified = record["sha256"].as_str() == Some(entry.sha256.as_str());
+                        if !verified {
+                            summary.modified += 1;
+                        }
+                        summary.mirrored.insert(
+                            path,
+                            json!({
+                                "source": entry.source,
+                                "url": entry.url,
+                                "mirrored_at": entry.mirrored_at,
+                                "verified": verified,
+                            }),
+                        );
+                    }
+                    None => summary.native += 1,
+                }
+            }
+        }
+    }
+
+    let mut channeldata = match existing.map(serde_json::from_slice::<Value>) {
+        Some(Ok(value)) if value.is_object() => value,
+        _ => json!({}),
+    };
+    channeldata["channeldata_version"] = json!(1);
+    channeldata["subdirs"] = json!(repodata.keys().collect::<Vec<_>>());
+    let previous = channeldata["packages"]
+        .as_object()
+        .cloned()
+        .unwrap_or_default();
+
+    let (mut mirrored, mut native, mut modified) = (0, 0, 0);
+    let mut packages = Map::new();
+    for (name, summary) in summaries {
+        mirrored += summary.mirrored.len();
+        native += summary.native;
+        modified += summary.modified;
+
+        let mut package = match previous.get(&name) {
+            Some(Value::Object(package)) => Value::Object(package.clone()),
+            _ => json!({}),
+        };
+        package["subdirs"] = json!(summary.subdirs);
+        package["version"] = json!(summary.version);
+        if let Some(license) = summary.license {
+            package["license"] = json!(license);
+        }
+        package[EXTENSION_KEY] = json!({
+            "mirrored": summary.mirrored.len(),
+            "native": summary.native,
+            "modified": summary.modified,
+            "files": summary.mirrored,
+        });
+        packages.insert(name, package);
+    }
+    channeldata["packages"] = Value::Object(packages);
+    channeldata[EXTENSION_KEY] = json!({
+        "generated_at": generated_at,
+        "mirrored": mirrored,
+        "native": native,
+        "modified": modified,
+    });
+    Ok(channeldata)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::ManifestEntry;
+
+    fn record(name: &str, version: &str, sha256: &str) -> Value {
+        json!({
+            "name": name,
+            "version": version,
+            "build": "0",
+            "license": format!("{}-license", version),
+            "sha256": sha256,
+        })
+    }
+
+    #[test]
+    fn test_build_channeldata() {
+        let repodata: BTreeMap<String, Vec<u8>> = [
+            (
+                "linux-64".to_string(),
+                json!({
+                    "packages.conda": {
+                        "app-2.0-0.conda": record("app", "2.0", "aa"),
+                        "app-10.0-0.conda": record("app", "10.0", "bb"),
+                    }
+                }),
+            ),
+            (
+                "noarch".to_string(),
+                json!({ "packages": { "data-1.0-0.tar.bz2": record("data", "1.0", "cc") } }),
+            ),
+        ]
+        .into_iter()
+        .map(|(subdir, repodata)| (subdir, serde_json::to_vec(&repodata).unwrap()))
+        .collect();
+
+        let mirrored_at = Utc::now();
+        let entry = |sha256: &str| ManifestEntry {
+            source: "owner/repo".to_string(),
+            url: None,
+            artifact: None,
+            run_id: None,
+            build_id: None,
+            pipeline_id: None,
+            job_id: None,
+            mirrored_at,
+            sha256: sha256.to_string(),
+            metadata_source: None,
+        };
+        let mut manifest = MirrorManifest::default();
+        manifest
+            .packages
+            .insert("linux-64/app-2.0-0.conda".to_string(), entry("aa"));
+        // Replaced at the target since it was mirrored
+        manifest
+            .packages
+            .insert("linux-64/app-10.0-0.conda".to_string(), entry("00"));
+
+        let existing = json!({
+            "packages": {
+                "app": { "description": "An application", "version": "1.0" },
+                "gone": { "version": "1.0" },
+            }
+        });
+        let channeldata = build(
+            Some(&serde_json::to_vec(&existing).unwrap()),
+            &repodata,
+            &manifest,
+            mirrored_at,
+        )
+        .unwrap();
+
+        assert_eq!(channeldata["subdirs"], json!(["linux-64", "noarch"]));
+        let app = &channeldata["packages"]["app"];
+        assert_eq!(app["description"], "An application");
+        assert_eq!(app["version"], "10.0");
+        assert_eq!(app["license"], "10.0-license");
+        assert_eq!(app["subdirs"], json!(["linux-64"]));
+        let extension = &app[EXTENSION_KEY];
+        assert_eq!(extension["mirrored"], 2);
+        assert_eq!(extension["modified"], 1);
+        assert_eq!(
+            extension["files"]["linux-64/app-2.0-0.conda"]["source"],
+            "owner/repo"
+        );
+        assert_eq!(
+            extension["files"]["linux-64/app-2.0-0.conda"]["verified"],
+            true
+        );
+        assert_eq!(
+            extension["files"]["linux-64/app-10.0-0.conda"]["verified"],
+            false
+        );
+
+        assert_eq!(channeldata["packages"]["data"][EXTENSION_KEY]["native"], 1);
+        assert!(channeldata["packages"].get("gone").is_none());
+        assert_eq!(channeldata[EXTENSION_KEY]["mirrored"], 2);
+        assert_eq!(channeldata[EXTENSION_KEY]["native"], 1);
+        assert_eq!(channeldata[EXTENSION_KEY]["modified"], 1);
+    }
+}
@@ -0,0 +1,211 @@
+//! Per-host circuit breaker for upstream requests
+//!
+//! Consecutive failures are counted per host. Once a host reaches the configured
+//! threshold its circuit opens and further requests to it fail immediately with
+//! [`MirrorError::HostUnavailable`] until the cooldown has passed, so one dead
+//! upstream does not stall a run on retries.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::error::{MirrorError, Result};
+
+#[derive(Debug, Default)]
+struct HostState {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+}
+
+/// Tracks request failures per host and short-circuits hosts that keep failing
+#[derive(Debug)]
+pub struct HostCircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    hosts: Mutex<HashMap<String, HostState>>,
+}
+
+impl HostCircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            cooldown,
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Fail fast if the circuit for the URL's host is open
+    pub fn check(&self, url: &str) -> Result<()> {
+        let host = host_of(url);
+        let mut hosts = self.hosts.lock().unwrap();
+        let Some(state) = hosts.get_mut(&host) else {
+            return Ok(());
+        };
+
+        match state.open_until {
+            Some(open_until) if Instant::now() < open_until => Err(MirrorError::HostUnavailable {
+                host,
+                failures: state.consecutive_failures,
+                retry_in: open_until - Instant::now(),
+            }),
+            Some(_) => {
+                // Cooldown elapsed: let one request through to probe the host
+                info!("Circuit for {} half-open, retrying host", host);
+                state.open_until = None;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    pub fn record_success(&self, url: &str) {
+        let mut hosts = self.hosts.lock().unwrap();
+        hosts.remove(&host_of(url));
+    }
+
+    pub fn record_failure(&self, url: &str) {
+        let host = host_of(url);
+        let mut hosts = self.hosts.lock().unwrap();
+        let state = hosts.entry(host.clone()).or_default();
+        state.consecutive_failures += 1;
+
+        if state.consecutive_failures >= self.threshold && state.open_until.is_none() {
+            warn!(
+                "Circuit opened for {} after {} consecutive failures; skipping requests for {}s",
+                host,
+                state.consecutive_failures,
+                self.cooldown.as_secs()
+            );
+            state.open_until = Some(Instant::now() + self.cooldown);
+        }
+    }
+
+    /// Run a request against `url`, recording its outcome for the host
+    ///
+    /// Failures that may be transient (network errors, server errors, truncation,
+    /// throttling) count against the host; any other answer shows the host is alive.
+    pub async fn guard<F, Fut, T>(&self, url: &str, operation: F) -> Result<T>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        self.check(url)?;
+
+        let result = operation().await;
+        match &result {
+            Err(e) if e.is_retryable() => self.record_failure(url),
+            _ => self.record_success(url),
+        }
+        result
+    }
+
+    /// Hosts whose circuit is currently open, with their consecutive failure counts
+    pub fn open_hosts(&self) -> Vec<(String, u32)> {
+        let now = Instant::now();
+        let hosts = self.hosts.lock().unwrap();
+        let mut open: Vec<_> = hosts
+            .iter()
+            .filter(|(_, state)| state.open_until.is_some_and(|until| now < until))
+            .map(|(host, state)| (host.clone(), state.consecutive_failures))
+            .collect();
+        open.sort();
+        open
+    }
+}
+
+/// Breaker shared by every request made in this process
+///
+/// It is created from the first configuration that asks for it, so that hosts
+/// failing for one source are also skipped for the sources that follow.
+pub fn shared(config: &Config) -> &'static HostCircuitBreaker {
+    static BREAKER: OnceLock<HostCircuitBreaker> = OnceLock::new();
+    BREAKER.get_or_init(|| {
+        HostCircuitBreaker::new(
+            config.circuit_breaker_threshold,
+            Duration::from_secs(config.circuit_breaker_cooldown_seconds),
+        )
+    })
+}
+
+fn host_of(url: &str) -> String {
+    url::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_else(|| url.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_circuit_opens_after_threshold() {
+        let breaker = HostCircuitBreaker::new(2, Duration::from_secs(60));
+        let url = "https://dead.example.com/pkg.conda";
+
+        breaker.record_failure(url);
+        assert!(breaker.check(url).is_ok());
+
+        breaker.record_failure("https://dead.example.com/other.conda");
+        assert!(matches!(
+            breaker.check(url),
+            Err(MirrorError::HostUnavailable { failures: 2, .. })
+        ));
+        assert_eq!(
+            breaker.open_hosts(),
+            vec![("dead.example.com".to_string(), 2)]
+        );
+
+        // Other hosts are unaffected
+        assert!(breaker.check("https://alive.example.com/pkg.conda").is_ok());
+    }
+
+    #[test]
+    fn test_success_resets_failures() {
+        let breaker = HostCircuitBreaker::new(2, Duration::from_secs(60));
+        let url = "https://flaky.example.com/pkg.conda";
+
+        breaker.record_failure(url);
+        breaker.record_success(url);
+        breaker.record_failure(url);
+        assert!(breaker.check(url).is_ok());
+    }
+
+    #[test]
+    fn test_circuit_half_opens_after_cooldown() {
+        let breaker = HostCircuitBreaker::new(1, Duration::ZERO);
+        let url = "https://slow.example.com/pkg.conda";
+
+        breaker.record_failure(url);
+        assert!(breaker.check(url).is_ok());
+        assert!(breaker.open_hosts().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_guard_only_counts_retryable_failures() {
+        let breaker = HostCircuitBreaker::new(1, Duration::from_secs(60));
+        let url = "https://example.com/missing.conda";
+
+        let result: Result<()> = breaker
+            .guard(url, || async {
+                Err(MirrorError::NotFound("missing.conda".to_string()))
+            })
+            .await;
+        assert!(result.is_err());
+        assert!(breaker.check(url).is_ok());
+
+        let result: Result<()> = breaker
+            .guard(url, || async {
+                Err(MirrorError::Http {
+                    status: 503,
+                    message: "unavailable".to_string(),
+                })
+            })
+            .await;
+        assert!(result.is_err());
+        assert!(breaker.check(url).is_err());
+    }
+}
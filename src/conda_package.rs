@@ -1,13 +1,23 @@
-use anyhow::{anyhow, Result};
 use bytes::Bytes;
 use rattler_conda_types::Platform;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::io::{Cursor, Read};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use tracing::{debug, info, warn};
 
+use crate::error::{MirrorError, Result};
+
+/// Partial `repodata.json` files written so far, numbering the next one
+static PARTIAL_WRITES: AtomicUsize = AtomicUsize::new(0);
+
 /// Represents a processed conda package with metadata
-#[derive(Debug, Clone)]
+///
+/// The serialized form records the processing result only; `content` is not
+/// written and is empty after deserializing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProcessedPackage {
+    #[serde(skip)]
     pub content: Bytes,
     pub metadata: SimpleIndexJson,
     pub filename: String,
@@ -15,20 +25,73 @@ pub struct ProcessedPackage {
     pub size: u64,
     pub md5: String,
     pub sha256: String,
+    /// Where the metadata and platform came from
+    #[serde(default)]
+    pub metadata_source: MetadataSource,
+}
+
+/// Where the metadata of a processed package came from
+///
+/// Anything but `rattler` means `info/index.json` could not be read, so the
+/// metadata published for the package is incomplete.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum MetadataSource {
+    /// Read from the package's `info/index.json`
+    #[default]
+    Rattler,
+    /// Parsed from the filename, including the platform
+    Filename,
+    /// Parsed from the filename, with the platform guessed from the package name or defaulted
+    Heuristic,
+}
+
+impl MetadataSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MetadataSource::Rattler => "rattler",
+            MetadataSource::Filename => "filename",
+            MetadataSource::Heuristic => "heuristic",
+        }
+    }
+}
+
+impl std::fmt::Display for MetadataSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 /// Simplified conda package metadata structure
-#[derive(Debug, Clone)]
+///
+/// Field names follow `info/index.json`; optional fields are omitted when unset.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SimpleIndexJson {
     pub name: String,
     pub version: String,
     pub build: String,
+    #[serde(default)]
     pub build_number: u64,
+    #[serde(default)]
     pub depends: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub license: Option<String>,
+    /// Broad license category such as `BSD` or `GPL3`, as set by the recipe
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license_family: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub platform: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub subdir: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub arch: Option<String>,
+    /// `python` or `generic` for packages that install on every platform
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub noarch: Option<String>,
+    /// When the package was built, as recorded in `info/index.json`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub timestamp: Option<chrono::DateTime<chrono::Utc>>,
 }
 
@@ -41,14 +104,66 @@ impl Default for SimpleIndexJson {
             build_number: 0,
             depends: Vec::new(),
             license: None,
+            license_family: None,
             platform: None,
             subdir: None,
             arch: None,
+            noarch: None,
             timestamp: Some(chrono::Utc::now()),
         }
     }
 }
 
+/// One rule tried while assigning a package its platform
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlatformStep {
+    /// The rule, e.g. [`PlatformStep::SUBDIR`]
+    pub rule: &'static str,
+    /// What the rule found in the metadata
+    pub evidence: String,
+    /// The platform the rule settled on; `None` when it did not apply
+    pub platform: Option<Platform>,
+}
+
+impl PlatformStep {
+    pub const SUBDIR: &'static str = "metadata subdir";
+    pub const NOARCH: &'static str = "metadata noarch";
+    pub const PLATFORM_ARCH: &'static str = "platform and arch";
+    pub const PLATFORM: &'static str = "platform";
+    pub const PACKAGE_NAME: &'static str = "package name";
+    pub const DEFAULT: &'static str = "default";
+}
+
+/// How a package's platform was decided, see [`CondaPackageHandler::explain_platform`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlatformExplanation {
+    /// The rules tried, in order; the last one applied
+    pub steps: Vec<PlatformStep>,
+    pub platform: Platform,
+}
+
+impl std::fmt::Display for PlatformExplanation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, step) in self.steps.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            let outcome = step
+                .platform
+                .map_or("no decision".to_string(), |platform| platform.to_string());
+            write!(
+                f,
+                "{}. {}: {} -> {}",
+                i + 1,
+                step.rule,
+                step.evidence,
+                outcome
+            )?;
+        }
+        Ok(())
+    }
+}
+
 /// Statistics about processed packages
 #[derive(Debug, Default)]
 pub struct PackageStats {
@@ -86,16 +201,26 @@ impl CondaPackageHandler {
 
         // Validate that this is a conda package by checking the filename extension
         if !Self::is_conda_package(filename) {
-            return Err(anyhow!("File {} is not a conda package", filename));
+            return Err(MirrorError::InvalidInput(format!(
+                "File {} is not a conda package",
+                filename
+            )));
         }
 
         // Use rattler_package_streaming to extract metadata
-        let metadata = self
+        let (metadata, read_from_package) = self
             .extract_metadata_with_rattler(&content, filename)
             .await?;
 
         // Determine platform from the extracted metadata
         let platform = Self::determine_platform_from_metadata(&metadata)?;
+        let metadata_source = Self::metadata_source(&metadata, read_from_package);
+        if metadata_source != MetadataSource::Rattler {
+            warn!(
+                "Metadata of {} is incomplete (metadata_source: {}, platform: {})",
+                filename, metadata_source, platform
+            );
+        }
 
         // Calculate checksums
         use md5::Md5;
@@ -111,6 +236,7 @@ impl CondaPackageHandler {
             size: content.len() as u64,
             md5,
             sha256,
+            metadata_source,
         };
 
         // Cache the processed package
@@ -124,19 +250,22 @@ impl CondaPackageHandler {
         Ok(processed)
     }
 
-    /// Extract metadata from conda package using manual parsing
+    /// Extract metadata from the `info/index.json` of a conda package
+    ///
+    /// Only when the package cannot be read is the metadata guessed from the
+    /// filename, which the returned flag, false, tells apart.
     async fn extract_metadata_with_rattler(
         &self,
         content: &Bytes,
         filename: &str,
-    ) -> Result<SimpleIndexJson> {
+    ) -> Result<(SimpleIndexJson, bool)> {
         debug!("Extracting metadata from conda package: {}", filename);
 
         // Try to extract metadata from the conda package
         if filename.ends_with(".conda") {
             // New .conda format (ZIP with inner tarballs)
             match self.extract_from_conda_format(content) {
-                Ok(metadata) => return Ok(metadata),
+                Ok(metadata) => return Ok((metadata, true)),
                 Err(e) => {
                     warn!("Failed to extract from .conda format: {}, falling back to filename parsing", e);
                 }
@@ -144,7 +273,7 @@ impl CondaPackageHandler {
         } else if filename.ends_with(".tar.bz2") {
             // Legacy .tar.bz2 format
             match self.extract_from_legacy_format(content) {
-                Ok(metadata) => return Ok(metadata),
+                Ok(metadata) => return Ok((metadata, true)),
                 Err(e) => {
                     warn!("Failed to extract from .tar.bz2 format: {}, falling back to filename parsing", e);
                 }
@@ -155,13 +284,31 @@ impl CondaPackageHandler {
             "Could not extract metadata from {}, falling back to filename parsing",
             filename
         );
-        self.extract_metadata_from_filename_fallback(filename)
+        Ok((
+            self.extract_metadata_from_filename_fallback(filename)?,
+            false,
+        ))
+    }
+
+    /// Where the metadata of a package came from, given whether it was read from the package
+    pub fn metadata_source(metadata: &SimpleIndexJson, read_from_package: bool) -> MetadataSource {
+        if read_from_package {
+            return MetadataSource::Rattler;
+        }
+        match Self::explain_platform(metadata)
+            .steps
+            .last()
+            .map(|step| step.rule)
+        {
+            Some(PlatformStep::PACKAGE_NAME | PlatformStep::DEFAULT) => MetadataSource::Heuristic,
+            _ => MetadataSource::Filename,
+        }
     }
 
     /// Extract metadata from .conda format (ZIP with inner tarballs)
-    /// Extract metadata from .conda format (ZIP with inner tarballs) - legacy fallback
-    /// This method is kept for future enhanced ZIP extraction if needed
-    #[allow(dead_code)]
+    ///
+    /// The metadata lives in `info/index.json` inside the zstd-compressed
+    /// `info-*.tar.zst` member.
     fn extract_from_conda_format(&self, content: &Bytes) -> Result<SimpleIndexJson> {
         use zip::ZipArchive;
 
@@ -172,43 +319,46 @@ impl CondaPackageHandler {
         let info_file_name = archive
             .file_names()
             .find(|name| name.starts_with("info-") && name.ends_with(".tar.zst"))
-            .ok_or_else(|| anyhow!("No info tarball found in conda package"))?
+            .ok_or_else(|| {
+                MirrorError::Corrupt("No info tarball found in conda package".to_string())
+            })?
             .to_string();
 
-        let mut info_file = archive.by_name(&info_file_name)?;
-        let mut info_data = Vec::new();
-        info_file.read_to_end(&mut info_data)?;
-
-        // For now, we'll extract what we can from the filename since zstd decompression
-        // would require additional dependencies. In production, you'd decompress the
-        // zstd tarball and extract info/index.json
-        warn!(
-            "Full conda package metadata extraction not yet implemented, using filename fallback"
-        );
-        Err(anyhow!("zstd decompression not implemented"))
+        let info_file = archive.by_name(&info_file_name)?;
+        let decoder = zstd::stream::read::Decoder::new(info_file)
+            .map_err(|e| MirrorError::Corrupt(format!("Invalid {}: {}", info_file_name, e)))?;
+        self.index_json_from_tar(decoder, &info_file_name)
     }
 
     /// Extract metadata from legacy .tar.bz2 format
     fn extract_from_legacy_format(&self, content: &Bytes) -> Result<SimpleIndexJson> {
         use bzip2::read::BzDecoder;
-        use tar::Archive;
 
         let cursor = Cursor::new(content.as_ref());
-        let decoder = BzDecoder::new(cursor);
-        let mut archive = Archive::new(decoder);
+        self.index_json_from_tar(BzDecoder::new(cursor), "legacy conda package")
+    }
 
+    /// Read `info/index.json` from an uncompressed tar stream
+    fn index_json_from_tar(&self, reader: impl Read, description: &str) -> Result<SimpleIndexJson> {
+        use tar::Archive;
+
+        let mut archive = Archive::new(reader);
         for entry in archive.entries()? {
             let mut entry = entry?;
             let path = entry.path()?;
             if path.to_str() == Some("info/index.json") {
                 let mut contents = String::new();
                 entry.read_to_string(&mut contents)?;
-                let metadata: serde_json::Value = serde_json::from_str(&contents)?;
+                let metadata: serde_json::Value = serde_json::from_str(&contents)
+                    .map_err(|e| MirrorError::Corrupt(format!("Invalid info/index.json: {}", e)))?;
                 return self.parse_conda_index_json(&metadata);
             }
         }
 
-        Err(anyhow!("No info/index.json found in legacy conda package"))
+        Err(MirrorError::Corrupt(format!(
+            "No info/index.json found in {}",
+            description
+        )))
     }
 
     /// Parse conda index.json metadata into our simplified structure
@@ -270,6 +420,23 @@ impl CondaPackageHandler {
             .and_then(|v| v.as_str())
             .map(|s| s.to_string());
 
+        let license_family = index_json
+            .get("license_family")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string());
+
+        // Older packages mark noarch packages with `true` rather than a kind
+        let noarch = match index_json.get("noarch") {
+            Some(serde_json::Value::String(kind)) => Some(kind.clone()),
+            Some(serde_json::Value::Bool(true)) => Some("generic".to_string()),
+            _ => None,
+        };
+
+        let timestamp = index_json
+            .get("timestamp")
+            .and_then(|v| v.as_i64())
+            .and_then(Self::parse_index_timestamp);
+
         Ok(SimpleIndexJson {
             name,
             version,
@@ -277,13 +444,27 @@ impl CondaPackageHandler {
             build_number,
             depends,
             license,
+            license_family,
             platform,
             subdir,
             arch,
-            timestamp: Some(chrono::Utc::now()),
+            noarch,
+            timestamp,
         })
     }
 
+    /// The build time of an `index.json` timestamp
+    ///
+    /// conda-build writes milliseconds since the epoch, older builds seconds;
+    /// values that would lie past the year 9999 as seconds are milliseconds.
+    fn parse_index_timestamp(timestamp: i64) -> Option<chrono::DateTime<chrono::Utc>> {
+        if timestamp > 253_402_300_799 {
+            chrono::DateTime::from_timestamp_millis(timestamp)
+        } else {
+            chrono::DateTime::from_timestamp(timestamp, 0)
+        }
+    }
+
     /// Fallback metadata extraction from filename when rattler extraction fails
     pub fn extract_metadata_from_filename_fallback(
         &self,
@@ -294,7 +475,9 @@ impl CondaPackageHandler {
         let name_without_ext = filename
             .strip_suffix(".conda")
             .or_else(|| filename.strip_suffix(".tar.bz2"))
-            .ok_or_else(|| anyhow!("Invalid conda package extension"))?;
+            .ok_or_else(|| {
+                MirrorError::InvalidInput("Invalid conda package extension".to_string())
+            })?;
 
         let parts: Vec<&str> = name_without_ext.split('-').collect();
         if parts.len() < 2 {
@@ -349,41 +532,82 @@ impl CondaPackageHandler {
             build_number,
             depends: Vec::new(),
             license: None,
+            license_family: None,
             platform: Self::extract_platform_from_filename(filename),
             subdir: None, // Cannot determine subdir from filename alone
             arch: None,
+            noarch: None,
             timestamp: Some(chrono::Utc::now()),
         })
     }
 
     /// Determine the platform from metadata using subdir field (most accurate)
     pub fn determine_platform_from_metadata(metadata: &SimpleIndexJson) -> Result<Platform> {
-        // Priority 1: Use subdir field (most accurate for repository organization)
         if let Some(subdir) = &metadata.subdir {
-            match subdir.as_str() {
-                "linux-64" => return Ok(Platform::Linux64),
-                "linux-32" => return Ok(Platform::Linux32),
-                "linux-aarch64" => return Ok(Platform::LinuxAarch64),
-                "linux-armv6l" => return Ok(Platform::LinuxArmV6l),
-                "linux-armv7l" => return Ok(Platform::LinuxArmV7l),
-                "linux-ppc64le" => return Ok(Platform::LinuxPpc64le),
-                "linux-s390x" => return Ok(Platform::LinuxS390X),
-                "osx-64" => return Ok(Platform::Osx64),
-                "osx-arm64" => return Ok(Platform::OsxArm64),
-                "win-32" => return Ok(Platform::Win32),
-                "win-64" => return Ok(Platform::Win64),
-                "noarch" => return Ok(Platform::NoArch),
-                _ => {
-                    warn!("Unknown subdir '{}', trying platform field", subdir);
-                }
+            if Self::known_subdir(subdir).is_none() {
+                warn!("Unknown subdir '{}', trying platform field", subdir);
             }
         }
 
+        let explanation = Self::explain_platform(metadata);
+        match explanation.steps.last().map(|step| step.rule) {
+            Some(PlatformStep::PACKAGE_NAME) => info!(
+                "Determined platform {} for {} based on package name analysis",
+                explanation.platform, metadata.name
+            ),
+            Some(PlatformStep::DEFAULT) => {
+                warn!("Could not determine platform from metadata, defaulting to NoArch")
+            }
+            _ => {}
+        }
+        Ok(explanation.platform)
+    }
+
+    /// The chain of rules that assigns a package its platform, up to the one that applied
+    ///
+    /// The rules are tried in order: the metadata `subdir`, its `noarch` kind,
+    /// its `platform` and `arch` together, the `platform` alone (taken from the
+    /// filename when the package could not be read), known package names, and
+    /// finally `noarch`.
+    pub fn explain_platform(metadata: &SimpleIndexJson) -> PlatformExplanation {
+        let mut steps = Vec::new();
+        let mut decide = |rule, evidence: String, platform: Option<Platform>| {
+            steps.push(PlatformStep {
+                rule,
+                evidence,
+                platform,
+            });
+            platform
+        };
+
+        // Priority 1: Use subdir field (most accurate for repository organization)
+        let decided = match &metadata.subdir {
+            Some(subdir) => match Self::known_subdir(subdir) {
+                Some(platform) => decide(
+                    PlatformStep::SUBDIR,
+                    format!("subdir \"{}\"", subdir),
+                    Some(platform),
+                ),
+                None => decide(
+                    PlatformStep::SUBDIR,
+                    format!("unknown subdir \"{}\"", subdir),
+                    None,
+                ),
+            },
+            None => decide(PlatformStep::SUBDIR, "no subdir".to_string(), None),
+        }
+        .or_else(|| match &metadata.noarch {
+            Some(kind) => decide(
+                PlatformStep::NOARCH,
+                format!("noarch \"{}\"", kind),
+                Some(Platform::NoArch),
+            ),
+            None => decide(PlatformStep::NOARCH, "no noarch".to_string(), None),
+        })
         // Priority 2: Try to combine platform and arch fields
-        if let Some(platform_str) = &metadata.platform {
-            if let Some(arch_str) = &metadata.arch {
-                // Try to construct subdir-like string from platform + arch
-                let constructed_subdir = match (platform_str.as_str(), arch_str.as_str()) {
+        .or_else(|| match (&metadata.platform, &metadata.arch) {
+            (Some(platform), Some(arch)) => {
+                let constructed_subdir = match (platform.as_str(), arch.as_str()) {
                     ("linux", "x86_64") => "linux-64",
                     ("linux", "aarch64") => "linux-aarch64",
                     ("osx", "x86_64") => "osx-64",
@@ -392,34 +616,107 @@ impl CondaPackageHandler {
                     ("win", "x86") => "win-32",
                     _ => "",
                 };
-
-                if !constructed_subdir.is_empty() {
-                    if let Ok(platform) = constructed_subdir.parse() {
-                        return Ok(platform);
-                    }
+                let evidence = format!("platform \"{}\", arch \"{}\"", platform, arch);
+                match constructed_subdir.parse() {
+                    Ok(platform) => decide(PlatformStep::PLATFORM_ARCH, evidence, Some(platform)),
+                    Err(_) => decide(
+                        PlatformStep::PLATFORM_ARCH,
+                        format!("{} make no known subdir", evidence),
+                        None,
+                    ),
                 }
             }
-
-            // Fall back to just platform field
-            if let Ok(platform) = platform_str.parse() {
-                return Ok(platform);
+            (platform, arch) => decide(
+                PlatformStep::PLATFORM_ARCH,
+                format!(
+                    "{}, {}",
+                    platform
+                        .as_ref()
+                        .map_or("no platform".to_string(), |p| format!("platform \"{}\"", p)),
+                    arch.as_ref()
+                        .map_or("no arch".to_string(), |a| format!("arch \"{}\"", a))
+                ),
+                None,
+            ),
+        })
+        // Fall back to just platform field
+        .or_else(|| {
+            let platform = metadata.platform.as_ref()?;
+            match platform.parse() {
+                Ok(parsed) => decide(
+                    PlatformStep::PLATFORM,
+                    format!("platform \"{}\"", platform),
+                    Some(parsed),
+                ),
+                Err(_) => decide(
+                    PlatformStep::PLATFORM,
+                    format!("platform \"{}\" is not a subdir", platform),
+                    None,
+                ),
             }
-        }
-
+        })
         // Priority 3: Intelligent guessing based on known package names
         // This addresses the specific issue where binary packages like coreos-installer
         // and okd-install should be platform-specific but metadata extraction failed
-        let platform = Self::guess_platform_from_package_name(&metadata.name);
-        if platform != Platform::NoArch {
-            info!(
-                "Determined platform {} for {} based on package name analysis",
-                platform, metadata.name
+        .or_else(|| {
+            let platform = Self::guess_platform_from_package_name(&metadata.name);
+            if platform != Platform::NoArch {
+                decide(
+                    PlatformStep::PACKAGE_NAME,
+                    format!("\"{}\" is a known {} package", metadata.name, platform),
+                    Some(platform),
+                )
+            } else {
+                decide(
+                    PlatformStep::PACKAGE_NAME,
+                    format!(
+                        "\"{}\" is not a known platform-specific package",
+                        metadata.name
+                    ),
+                    None,
+                )
+            }
+        });
+
+        let platform = decided.unwrap_or_else(|| {
+            decide(
+                PlatformStep::DEFAULT,
+                "no rule applied".to_string(),
+                Some(Platform::NoArch),
             );
-            return Ok(platform);
+            Platform::NoArch
+        });
+        PlatformExplanation { steps, platform }
+    }
+
+    /// The platform of a subdir named in package metadata
+    fn known_subdir(subdir: &str) -> Option<Platform> {
+        match subdir {
+            "linux-64" => Some(Platform::Linux64),
+            "linux-32" => Some(Platform::Linux32),
+            "linux-aarch64" => Some(Platform::LinuxAarch64),
+            "linux-armv6l" => Some(Platform::LinuxArmV6l),
+            "linux-armv7l" => Some(Platform::LinuxArmV7l),
+            "linux-ppc64le" => Some(Platform::LinuxPpc64le),
+            "linux-s390x" => Some(Platform::LinuxS390X),
+            "osx-64" => Some(Platform::Osx64),
+            "osx-arm64" => Some(Platform::OsxArm64),
+            "win-32" => Some(Platform::Win32),
+            "win-64" => Some(Platform::Win64),
+            "noarch" => Some(Platform::NoArch),
+            _ => None,
         }
+    }
 
-        warn!("Could not determine platform from metadata, defaulting to NoArch");
-        Ok(Platform::NoArch)
+    /// Whether the platform comes from the package's own `subdir` metadata
+    ///
+    /// Metadata built from the filename fallback has no `subdir`, so any platform
+    /// determined for it was guessed.
+    pub fn platform_from_metadata(metadata: &SimpleIndexJson) -> bool {
+        metadata
+            .subdir
+            .as_deref()
+            .is_some_and(|subdir| subdir.parse::<Platform>().is_ok())
     }
 
     /// Guess platform based on package name patterns (fallback for known packages)
@@ -573,19 +870,27 @@ impl CondaPackageHandler {
     /// Validate a processed package
     pub fn validate_package(&self, package: &ProcessedPackage) -> Result<()> {
         if package.filename.is_empty() {
-            return Err(anyhow!("Package filename cannot be empty"));
+            return Err(MirrorError::InvalidInput(
+                "Package filename cannot be empty".to_string(),
+            ));
         }
 
         if package.metadata.name.is_empty() {
-            return Err(anyhow!("Package name cannot be empty"));
+            return Err(MirrorError::Corrupt(
+                "Package name cannot be empty".to_string(),
+            ));
         }
 
         if package.metadata.version.is_empty() {
-            return Err(anyhow!("Package version cannot be empty"));
+            return Err(MirrorError::Corrupt(
+                "Package version cannot be empty".to_string(),
+            ));
         }
 
         if package.size == 0 {
-            return Err(anyhow!("Package size cannot be zero"));
+            return Err(MirrorError::Corrupt(
+                "Package size cannot be zero".to_string(),
+            ));
         }
 
         Ok(())
@@ -615,11 +920,20 @@ impl CondaPackageHandler {
     }
 
     /// Get a cached package by filename
-    #[allow(dead_code)]
     pub fn get_package(&self, filename: &str) -> Option<&ProcessedPackage> {
         self.cache.get(filename)
     }
 
+    /// Drop a package from the cache so it is left out of the generated repodata
+    pub fn remove_package(&mut self, filename: &str) -> Option<ProcessedPackage> {
+        self.cache.remove(filename)
+    }
+
+    /// Replace the cached entry for a package, e.g. after its platform was resolved
+    pub fn record_package(&mut self, package: ProcessedPackage) {
+        self.cache.insert(package.filename.clone(), package);
+    }
+
     /// Get all cached packages
     #[allow(dead_code)]
     pub fn get_all_packages(&self) -> Vec<&ProcessedPackage> {
@@ -627,53 +941,118 @@ impl CondaPackageHandler {
     }
 
     /// Create or update repodata.json for a platform
+    /// The record of a package in the `repodata.json` of `platform`
+    pub fn repodata_record(package: &ProcessedPackage, platform: &Platform) -> serde_json::Value {
+        let mut package_record = serde_json::json!({
+            "build": package.metadata.build,
+            "build_number": package.metadata.build_number,
+            "depends": package.metadata.depends,
+            "license": package.metadata.license.clone().unwrap_or_default(),
+            "md5": package.md5,
+            "sha256": package.sha256,
+            "size": package.size,
+            "subdir": platform.to_string(),
+            "name": package.metadata.name,
+            "version": package.metadata.version,
+            "timestamp": package.metadata.timestamp.map(|t| t.timestamp_millis()),
+        });
+        if let Some(license_family) = &package.metadata.license_family {
+            package_record["license_family"] = serde_json::Value::from(license_family.as_str());
+        }
+        if let Some(noarch) = &package.metadata.noarch {
+            package_record["noarch"] = serde_json::Value::from(noarch.as_str());
+        }
+        package_record
+    }
+
+    /// Add the records of `packages` to the `repodata.json` content of `platform`
+    ///
+    /// Records of other packages are kept, as are `info` and any other keys of
+    /// `existing`; a package already listed, in either section, is replaced by
+    /// a record in the section of its extension.
+    pub fn merged_repodata(
+        existing: Option<&[u8]>,
+        platform: &Platform,
+        packages: &[ProcessedPackage],
+    ) -> Result<serde_json::Value> {
+        let mut repodata = match existing {
+            Some(content) => serde_json::from_slice(content).map_err(|e| {
+                MirrorError::Corrupt(format!("Invalid repodata.json of {}: {}", platform, e))
+            })?,
+            None => serde_json::json!({}),
+        };
+        if !repodata.is_object() {
+            return Err(MirrorError::Corrupt(format!(
+                "Invalid repodata.json of {}: not an object",
+                platform
+            )));
+        }
+        if !repodata["info"].is_object() {
+            repodata["info"] = serde_json::json!({});
+        }
+        repodata["info"]["subdir"] = serde_json::Value::from(platform.to_string());
+        for section in ["packages", "packages.conda"] {
+            if !repodata[section].is_object() {
+                repodata[section] = serde_json::json!({});
+            }
+        }
+
+        for package in packages {
+            let (section, other) = Self::repodata_sections(&package.filename);
+            if let Some(stale) = repodata[other].as_object_mut() {
+                stale.remove(&package.filename);
+            }
+            repodata[section][&package.filename] = Self::repodata_record(package, platform);
+        }
+        Ok(repodata)
+    }
+
+    /// The `repodata.json` section listing `filename`, and the other section
+    ///
+    /// `.conda` packages are listed under `packages.conda` and `.tar.bz2`
+    /// packages under `packages`, as conda and rattler expect.
+    pub fn repodata_sections(filename: &str) -> (&'static str, &'static str) {
+        if filename.ends_with(".conda") {
+            ("packages.conda", "packages")
+        } else {
+            ("packages", "packages.conda")
+        }
+    }
+
+    /// Add `packages` to the `repodata.json` of `platform` below `base_path`
+    ///
+    /// The file is written next to the old one and renamed over it, so readers
+    /// never see a partial index.
     pub async fn create_repodata(
         &self,
         platform: &Platform,
         packages: &[ProcessedPackage],
         base_path: &std::path::Path,
     ) -> Result<()> {
-        use std::collections::HashMap;
-
         info!("Creating repodata for platform: {}", platform);
 
         let platform_dir = base_path.join(platform.to_string());
-        std::fs::create_dir_all(&platform_dir)?;
+        std::fs::create_dir_all(&platform_dir)
+            .map_err(|e| MirrorError::target_io(&platform_dir, e))?;
 
         let repodata_path = platform_dir.join("repodata.json");
-
-        // Create a simple repodata structure
-        let mut repodata_packages = HashMap::new();
-
-        // Add packages to repodata
-        for package in packages {
-            let package_record = serde_json::json!({
-                "build": package.metadata.build,
-                "build_number": package.metadata.build_number,
-                "depends": package.metadata.depends,
-                "license": package.metadata.license.clone().unwrap_or_default(),
-                "md5": package.md5,
-                "sha256": package.sha256,
-                "size": package.size,
-                "subdir": platform.to_string(),
-                "name": package.metadata.name,
-                "version": package.metadata.version,
-                "timestamp": package.metadata.timestamp,
-            });
-
-            repodata_packages.insert(package.filename.clone(), package_record);
-        }
-
-        let repodata = serde_json::json!({
-            "info": {
-                "subdir": platform.to_string()
-            },
-            "packages": repodata_packages
-        });
-
-        // Write repodata
-        let repodata_json = serde_json::to_string_pretty(&repodata)?;
-        std::fs::write(&repodata_path, repodata_json)?;
+        let existing = match std::fs::read(&repodata_path) {
+            Ok(content) => Some(content),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(MirrorError::target_io(&repodata_path, e)),
+        };
+        let repodata = Self::merged_repodata(existing.as_deref(), platform, packages)?;
+
+        // Each write has its own partial file, so concurrent writers never mix
+        let partial = platform_dir.join(format!(
+            ".repodata.json.{}-{}.partial",
+            std::process::id(),
+            PARTIAL_WRITES.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::write(&partial, serde_json::to_vec_pretty(&repodata)?)
+            .map_err(|e| MirrorError::target_io(&partial, e))?;
+        std::fs::rename(&partial, &repodata_path)
+            .map_err(|e| MirrorError::target_io(&repodata_path, e))?;
 
         info!("Updated repodata.json with {} packages", packages.len());
         Ok(())
@@ -728,6 +1107,53 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_processed_package_serde_round_trip() {
+        let content = Bytes::from_static(b"content");
+        let package = ProcessedPackage {
+            content: content.clone(),
+            metadata: SimpleIndexJson {
+                name: "numpy".to_string(),
+                version: "1.21.0".to_string(),
+                build: "py39_0".to_string(),
+                build_number: 0,
+                depends: vec!["python >=3.9".to_string()],
+                subdir: Some("linux-64".to_string()),
+                timestamp: None,
+                ..Default::default()
+            },
+            filename: "numpy-1.21.0-py39_0.conda".to_string(),
+            platform: Platform::Linux64,
+            size: content.len() as u64,
+            md5: "md5".to_string(),
+            sha256: "sha256".to_string(),
+            metadata_source: MetadataSource::Rattler,
+        };
+
+        let json = serde_json::to_value(&package).unwrap();
+        assert_eq!(json["platform"], "linux-64");
+        assert_eq!(json["metadata"]["subdir"], "linux-64");
+        assert!(json.get("content").is_none());
+        assert!(json["metadata"].get("license").is_none());
+
+        let restored: ProcessedPackage = serde_json::from_value(json).unwrap();
+        assert_eq!(restored.platform, Platform::Linux64);
+        assert_eq!(restored.metadata.depends, package.metadata.depends);
+        assert_eq!(restored.sha256, package.sha256);
+        assert!(restored.content.is_empty());
+    }
+
+    #[test]
+    fn test_simple_index_json_from_index_json() {
+        let metadata: SimpleIndexJson = serde_json::from_str(
+            r#"{"name": "tzdata", "version": "2024a", "build": "h0c530f3_0", "subdir": "noarch"}"#,
+        )
+        .unwrap();
+        assert_eq!(metadata.build_number, 0);
+        assert!(metadata.depends.is_empty());
+        assert_eq!(metadata.subdir.as_deref(), Some("noarch"));
+    }
+
     #[test]
     fn test_is_platform_string() {
         assert!(CondaPackageHandler::is_platform_string("linux-64"));
@@ -756,6 +1182,100 @@ mod tests {
         assert_eq!(platform, Platform::NoArch);
     }
 
+    #[test]
+    fn test_explain_platform() {
+        let metadata = SimpleIndexJson {
+            name: "kubectl".to_string(),
+            subdir: Some("linux-sparc".to_string()),
+            platform: Some("linux".to_string()),
+            arch: Some("sparc".to_string()),
+            ..Default::default()
+        };
+        let explanation = CondaPackageHandler::explain_platform(&metadata);
+        assert_eq!(explanation.platform, Platform::Linux64);
+        let rules: Vec<&str> = explanation.steps.iter().map(|step| step.rule).collect();
+        assert_eq!(
+            rules,
+            [
+                PlatformStep::SUBDIR,
+                PlatformStep::NOARCH,
+                PlatformStep::PLATFORM_ARCH,
+                PlatformStep::PLATFORM,
+                PlatformStep::PACKAGE_NAME
+            ]
+        );
+        assert_eq!(
+            explanation.to_string().lines().next(),
+            Some("1. metadata subdir: unknown subdir \"linux-sparc\" -> no decision")
+        );
+        assert!(explanation
+            .to_string()
+            .ends_with("5. package name: \"kubectl\" is a known linux-64 package -> linux-64"));
+
+        let metadata = SimpleIndexJson {
+            subdir: Some("osx-arm64".to_string()),
+            ..Default::default()
+        };
+        let explanation = CondaPackageHandler::explain_platform(&metadata);
+        assert_eq!(explanation.steps.len(), 1);
+        assert_eq!(explanation.platform, Platform::OsxArm64);
+
+        let explanation = CondaPackageHandler::explain_platform(&SimpleIndexJson::default());
+        assert_eq!(explanation.platform, Platform::NoArch);
+        assert_eq!(
+            explanation.steps.last().map(|step| step.rule),
+            Some(PlatformStep::DEFAULT)
+        );
+    }
+
+    #[test]
+    fn test_platform_from_metadata() {
+        let metadata = SimpleIndexJson {
+            subdir: Some("linux-64".to_string()),
+            ..Default::default()
+        };
+        assert!(CondaPackageHandler::platform_from_metadata(&metadata));
+
+        let handler = CondaPackageHandler::new();
+        let guessed = handler
+            .extract_metadata_from_filename_fallback("numpy-1.21.0-py39_0-linux-64.conda")
+            .unwrap();
+        assert!(!CondaPackageHandler::platform_from_metadata(&guessed));
+    }
+
+    #[test]
+    fn test_metadata_source() {
+        let handler = CondaPackageHandler::new();
+        let read = SimpleIndexJson {
+            subdir: Some("linux-64".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            CondaPackageHandler::metadata_source(&read, true),
+            MetadataSource::Rattler
+        );
+
+        let from_filename = handler
+            .extract_metadata_from_filename_fallback("rb-asciidoctor-2.0.20-h1234_0-noarch.conda")
+            .unwrap();
+        assert_eq!(
+            CondaPackageHandler::metadata_source(&from_filename, false),
+            MetadataSource::Filename
+        );
+
+        let guessed = handler
+            .extract_metadata_from_filename_fallback("coreos-installer-0.17.0-h1234_0.conda")
+            .unwrap();
+        assert_eq!(
+            CondaPackageHandler::metadata_source(&guessed, false),
+            MetadataSource::Heuristic
+        );
+        assert_eq!(
+            serde_json::to_value(MetadataSource::Heuristic).unwrap(),
+            "heuristic"
+        );
+    }
+
     #[test]
     fn test_simple_index_json_default() {
         let metadata = SimpleIndexJson::default();
@@ -856,6 +1376,104 @@ mod tests {
         assert_eq!(metadata.depends, vec!["python >=3.7"]);
     }
 
+    #[tokio::test]
+    async fn test_process_package_reads_index_json() {
+        let fixture = crate::test_support::PackageFixture::new("pyyaml", "6.0.1")
+            .build("py312h_0")
+            .subdir("linux-aarch64")
+            .depends(["python >=3.12", "yaml >=0.2.5"]);
+        for (content, filename) in [
+            (fixture.to_conda(), fixture.conda_filename()),
+            (fixture.to_tar_bz2(), fixture.tar_bz2_filename()),
+        ] {
+            let mut handler = CondaPackageHandler::new();
+            let processed = handler.process_package(content, &filename).await.unwrap();
+            assert_eq!(processed.platform, Platform::LinuxAarch64);
+            assert_eq!(processed.metadata.build, "py312h_0");
+            assert_eq!(
+                processed.metadata.depends,
+                vec!["python >=3.12", "yaml >=0.2.5"]
+            );
+            assert_eq!(
+                processed.metadata.timestamp.map(|t| t.timestamp_millis()),
+                Some(1_700_000_000_000)
+            );
+            assert!(processed.metadata.noarch.is_none());
+        }
+
+        let generic = crate::test_support::PackageFixture::new("tzdata", "2024a");
+        let processed = CondaPackageHandler::new()
+            .process_package(generic.to_conda(), &generic.conda_filename())
+            .await
+            .unwrap();
+        assert_eq!(processed.platform, Platform::NoArch);
+        assert_eq!(processed.metadata.noarch.as_deref(), Some("generic"));
+        let record = CondaPackageHandler::repodata_record(&processed, &Platform::NoArch);
+        assert_eq!(record["noarch"], "generic");
+        assert_eq!(record["timestamp"], 1_700_000_000_000i64);
+    }
+
+    #[tokio::test]
+    async fn test_merged_repodata_lists_packages_by_extension() {
+        let fixture = crate::test_support::PackageFixture::new("pkg", "1.0");
+        let mut handler = CondaPackageHandler::new();
+        let conda = handler
+            .process_package(fixture.to_conda(), &fixture.conda_filename())
+            .await
+            .unwrap();
+        let tar_bz2 = handler
+            .process_package(fixture.to_tar_bz2(), &fixture.tar_bz2_filename())
+            .await
+            .unwrap();
+
+        // Stale records of both packages sit in the other section
+        let existing = serde_json::json!({
+            "packages": {
+                "pkg-1.0-0.conda": { "name": "stale" },
+                "kept-1.0-0.tar.bz2": { "name": "kept" }
+            },
+            "packages.conda": { "pkg-1.0-0.tar.bz2": { "name": "stale" } }
+        })
+        .to_string();
+        let repodata = CondaPackageHandler::merged_repodata(
+            Some(existing.as_bytes()),
+            &Platform::NoArch,
+            &[conda, tar_bz2],
+        )
+        .unwrap();
+
+        let listed = |section: &str| -> Vec<String> {
+            let mut listed: Vec<String> = repodata[section]
+                .as_object()
+                .unwrap()
+                .keys()
+                .cloned()
+                .collect();
+            listed.sort();
+            listed
+        };
+        assert_eq!(listed("packages.conda"), ["pkg-1.0-0.conda"]);
+        assert_eq!(
+            listed("packages"),
+            ["kept-1.0-0.tar.bz2", "pkg-1.0-0.tar.bz2"]
+        );
+        assert_eq!(repodata["packages.conda"]["pkg-1.0-0.conda"]["name"], "pkg");
+        assert_eq!(repodata["packages"]["pkg-1.0-0.tar.bz2"]["name"], "pkg");
+        assert_eq!(repodata["info"]["subdir"], "noarch");
+
+        // A new repodata.json has both sections
+        let repodata = CondaPackageHandler::merged_repodata(None, &Platform::NoArch, &[]).unwrap();
+        assert!(repodata["packages"].as_object().unwrap().is_empty());
+        assert!(repodata["packages.conda"].as_object().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_index_timestamp() {
+        let millis = CondaPackageHandler::parse_index_timestamp(1_700_000_000_000).unwrap();
+        let seconds = CondaPackageHandler::parse_index_timestamp(1_700_000_000).unwrap();
+        assert_eq!(millis, seconds);
+    }
+
     #[test]
     fn test_platform_detection_fallback_chain() {
         // This test demonstrates the fallback logic chain:
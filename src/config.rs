@@ -1,6 +1,19 @@
-use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
+use std::sync::Arc;
+
+#[cfg(feature = "schedule")]
+use crate::daemon::JobConfig;
+use crate::email::EmailConfig;
+use crate::error::{MirrorError, Result};
+use crate::naming::FilenamePolicy;
+use crate::notify::WebhookConfig;
+use crate::osv::VulnerabilityConfig;
+use crate::policy::PolicyConfig;
+use crate::repository::{DuplicatePlatformPolicy, PlatformMapping};
+use crate::retention::RetentionConfig;
+use crate::signing::SigningConfig;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
@@ -10,7 +23,292 @@ pub struct Config {
     pub s3_region: Option<String>,
     pub s3_endpoint: Option<String>,
     pub github_token: Option<String>,
+    /// GitHub REST API `github` and `github-release` sources are read from
+    #[serde(default = "default_github_api_url")]
+    pub github_api_url: String,
     pub azure_devops_token: Option<String>,
+    /// Azure DevOps instance `azure` sources are read from
+    #[serde(default = "default_azure_devops_url")]
+    pub azure_devops_url: String,
+    /// GitLab token sent as `PRIVATE-TOKEN` to the GitLab API; `GITLAB_TOKEN` when unset
+    #[serde(default = "default_gitlab_token")]
+    pub gitlab_token: Option<String>,
+    /// GitLab instance `gitlab` sources given as a project path are read from
+    #[serde(default = "default_gitlab_url")]
+    pub gitlab_url: String,
+    /// rattler credentials file for private upstream channels; `~/.rattler/credentials.json` when unset
+    #[serde(default = "default_auth_file")]
+    pub auth_file: Option<String>,
+    /// Hosts `url`, `zip-url` and `tgz-url` sources may be downloaded from, `*.domain` for all subdomains; any host when empty
+    #[serde(default)]
+    pub allowed_source_hosts: Vec<String>,
+    /// Directory that receives archives which were still corrupt after a re-download
+    #[serde(default = "default_quarantine_dir")]
+    pub quarantine_dir: String,
+    /// Overwrite packages at the target even when their sha256 differs
+    #[serde(default)]
+    pub force_replace: bool,
+    /// Download and upload packages even when the target already has an identical copy
+    #[serde(default)]
+    pub force: bool,
+    /// sha256 of the packages the target's repodata lists, by filename, read at the start of the run
+    #[serde(skip)]
+    pub target_packages: Option<Arc<HashMap<String, String>>>,
+    /// Consecutive failures after which requests to a host are skipped
+    #[serde(default = "default_circuit_breaker_threshold")]
+    pub circuit_breaker_threshold: u32,
+    /// How long a failing host is skipped before it is tried again
+    #[serde(default = "default_circuit_breaker_cooldown_seconds")]
+    pub circuit_breaker_cooldown_seconds: u64,
+    /// File recording completed and pending packages when a run is interrupted
+    #[serde(default = "default_resume_state_file")]
+    pub resume_state_file: String,
+    /// How to handle a filename processed twice in one run with different platforms
+    #[serde(default)]
+    pub duplicate_platform_policy: DuplicatePlatformPolicy,
+    /// Refuse packages whose platform had to be guessed from their name
+    #[serde(default)]
+    pub strict_platform: bool,
+    /// Print, for every package, the rules that decided its platform and their evidence
+    #[serde(default)]
+    pub explain_platform: bool,
+    /// Whether a package whose filename does not match its metadata is renamed or refused
+    #[serde(default)]
+    pub filename_policy: FilenamePolicy,
+    /// Webhooks notified with a summary when a mirror run finishes
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+    /// SMTP alerts sent when runs of the same source and target keep failing
+    #[serde(default)]
+    pub email: Option<EmailConfig>,
+    /// SQLite database recording every mirrored package; disabled when unset
+    #[serde(default)]
+    pub state_db: Option<String>,
+    /// Skip packages the state database records as already delivered to the target
+    #[serde(default)]
+    pub since_last_run: bool,
+    /// File recording the ETag and Last-Modified of sources, skipping runs whose source is unchanged; disabled when unset
+    #[serde(default)]
+    pub upstream_state_file: Option<String>,
+    /// Write an in-toto SLSA provenance statement for every mirrored package
+    #[serde(default)]
+    pub provenance: bool,
+    /// Mirror runs repeated on cron schedules by the `daemon` command
+    #[cfg(feature = "schedule")]
+    #[serde(default)]
+    pub jobs: Vec<JobConfig>,
+    /// Address the daemon serves `/healthz`, `/readyz` and `/status` on, e.g. `0.0.0.0:8080`
+    #[serde(default)]
+    pub health_listen: Option<String>,
+    /// Admission rules packages must pass before they are uploaded; disabled when unset
+    #[serde(default)]
+    pub policy: Option<PolicyConfig>,
+    /// OSV.dev lookups annotating, and optionally blocking, vulnerable packages
+    #[serde(default)]
+    pub vulnerabilities: Option<VulnerabilityConfig>,
+    /// Channel the `drift` command compares the mirror with, a URL or local path
+    #[serde(default)]
+    pub upstream_channel: Option<String>,
+    /// URL clients reach the mirrored channel at, used in the channel configuration printed after a run
+    #[serde(default)]
+    pub public_url: Option<String>,
+    /// File the `pixi.toml`/`.condarc` channel configuration is also written to after a run
+    #[serde(default)]
+    pub channel_config_file: Option<String>,
+    /// Subdirs whose packages are published under another subdir, instead or as well
+    #[serde(default)]
+    pub platform_mappings: Vec<PlatformMapping>,
+    /// Channel name written into repodata and its package records, republishing
+    /// mirrored packages under a channel identity of their own
+    #[serde(default)]
+    pub channel_name: Option<String>,
+    /// Contact URL or address added to the User-Agent, so upstream operators can reach the mirror's owner
+    #[serde(default)]
+    pub user_agent_contact: Option<String>,
+    /// Minimum pause between requests to the same upstream host, in milliseconds
+    #[serde(default)]
+    pub crawl_delay_ms: u64,
+    /// Requests run at once against one upstream host; 0 leaves them unlimited
+    #[serde(default)]
+    pub max_connections_per_host: usize,
+    /// Average rate packages are fetched at in a run, in bytes per second; 0 leaves it unlimited
+    #[serde(default)]
+    pub max_bytes_per_second: u64,
+    /// Requests run at once by all runs of the process together, e.g. the daemon's jobs; 0 leaves them unlimited
+    #[serde(default)]
+    pub global_max_connections: usize,
+    /// Rate packages are fetched at by all runs of the process together, in bytes per second; 0 leaves it unlimited
+    #[serde(default)]
+    pub global_max_bytes_per_second: u64,
+    /// Package content all runs of the process may hold fetched ahead of its upload, in bytes; 0 leaves it unlimited
+    #[serde(default)]
+    pub global_max_buffered_bytes: u64,
+    /// Priority of the run among the daemon's jobs, taken from the job
+    #[serde(skip)]
+    pub priority: i32,
+    /// Close of the daemon job's time window; the run stops between packages once it passes
+    #[serde(skip)]
+    pub run_until: Option<chrono::DateTime<chrono::Utc>>,
+    /// Platform subdirs a source must provide packages for, or the run fails
+    #[serde(default)]
+    pub require_platforms: Vec<String>,
+    /// Mirror every archive member matching `--src-path`, not only the first
+    #[serde(default)]
+    pub all_matches: bool,
+    /// Also unpack packages written to a cache target, as `PackageCache` does on a fetch
+    #[serde(default)]
+    pub cache_extract: bool,
+    /// Fully extract every package and check it against its `info/paths.json` before it is uploaded
+    #[serde(default)]
+    pub verify_extraction: bool,
+    /// Directory for temporary files such as extraction checks; the system temporary directory when unset
+    #[serde(default)]
+    pub scratch_dir: Option<String>,
+    /// Bytes to keep free on the scratch and local target file systems; checked before each package is fetched
+    #[serde(default)]
+    pub min_free_space: u64,
+    /// Remote ZIP sources at least this large are read with HTTP range requests where the host allows
+    #[serde(default = "default_range_read_min_bytes")]
+    pub range_read_min_bytes: u64,
+    /// Regex of archive members and CI artifact names to skip, complementing `--src-path`
+    #[serde(default)]
+    pub src_exclude: Option<String>,
+    /// Named artifact and package path filters, such as `conda-forge`, used when `--src-path` is not given
+    #[serde(default)]
+    pub filter_preset: Option<String>,
+    /// Subdirs of a `channel` source to mirror; empty mirrors every subdir with a `repodata.json`
+    #[serde(default)]
+    pub src_subdirs: Vec<String>,
+    /// Build platforms of conda-forge Azure DevOps artifacts to mirror, read from artifact names; empty mirrors every artifact
+    #[serde(default)]
+    pub platforms: Vec<String>,
+    /// Match specs such as `numpy >=1.21,<2`; when any are given, only packages matching one are mirrored
+    #[serde(default)]
+    pub specs: Vec<String>,
+    /// Channel whose repodata supplies the license and dependencies a package's own metadata lacks
+    #[serde(default)]
+    pub enrich_from: Option<String>,
+    /// Directory caching GitHub and Azure DevOps listings between runs; disabled when unset
+    #[serde(default)]
+    pub listing_cache_dir: Option<String>,
+    /// How long a cached listing is used before the API is queried again
+    #[serde(default = "default_listing_cache_ttl_seconds")]
+    pub listing_cache_ttl_seconds: u64,
+    /// User name sent to OCI registries; `OCI_USERNAME` when unset
+    #[serde(default)]
+    pub oci_username: Option<String>,
+    /// Password or token sent to OCI registries; `OCI_PASSWORD`, or `GITHUB_TOKEN` for ghcr.io, when unset
+    #[serde(default)]
+    pub oci_password: Option<String>,
+    /// User name clients of `serve` must authenticate with; `SERVE_USERNAME` when unset
+    #[serde(default)]
+    pub serve_username: Option<String>,
+    /// Password clients of `serve` must authenticate with; `SERVE_PASSWORD` when unset
+    #[serde(default)]
+    pub serve_password: Option<String>,
+    /// Packages uploaded to prefix.dev together in one batch
+    #[serde(default = "default_upload_batch_size")]
+    pub upload_batch_size: usize,
+    /// Queued bytes that also start a prefix.dev batch; 0 leaves the batch size unlimited
+    #[serde(default)]
+    pub upload_batch_bytes: u64,
+    /// Uploads of a prefix.dev batch that run at once
+    #[serde(default = "default_upload_parallelism")]
+    pub upload_parallelism: usize,
+    /// Packages of at least this many bytes go to S3 in parts that can be resumed; 0 never splits them
+    #[serde(default = "default_s3_multipart_threshold")]
+    pub s3_multipart_threshold: u64,
+    /// Size of the parts of an S3 multipart upload, at least 5 MiB
+    #[serde(default = "default_s3_multipart_part_size")]
+    pub s3_multipart_part_size: u64,
+    /// File recording the S3 multipart uploads in progress, so an interrupted run can resume them
+    #[serde(default = "default_s3_multipart_state_file")]
+    pub s3_multipart_state_file: String,
+    /// GPG key signing uploaded packages and repodata; disabled when unset
+    #[serde(default)]
+    pub signing: Option<SigningConfig>,
+    /// Newest versions or builds kept at the target after each run; disabled when unset
+    #[serde(default)]
+    pub retention: Option<RetentionConfig>,
+    /// Snapshots of the target's repodata kept for `rollback`; 0 takes none
+    #[serde(default = "default_repodata_snapshots")]
+    pub repodata_snapshots: usize,
+    /// Also write CEP-16 sharded repodata next to each `repodata.json` of local and S3 targets
+    #[serde(default)]
+    pub sharded_repodata: bool,
+}
+
+fn default_gitlab_token() -> Option<String> {
+    std::env::var("GITLAB_TOKEN").ok()
+}
+
+fn default_github_api_url() -> String {
+    crate::github::GITHUB_API_URL.to_string()
+}
+
+/// Base URL of the Azure DevOps Services REST API, the default `azure_devops_url`
+///
+/// Kept here rather than in `azure`, which is only built with the `azure` feature.
+pub const AZURE_DEVOPS_URL: &str = "https://dev.azure.com";
+
+fn default_azure_devops_url() -> String {
+    AZURE_DEVOPS_URL.to_string()
+}
+
+fn default_gitlab_url() -> String {
+    "https://gitlab.com".to_string()
+}
+
+fn default_auth_file() -> Option<String> {
+    std::env::var("RATTLER_AUTH_FILE").ok()
+}
+
+fn default_quarantine_dir() -> String {
+    "quarantine".to_string()
+}
+
+fn default_resume_state_file() -> String {
+    ".meso-forge-mirror-resume.json".to_string()
+}
+
+fn default_range_read_min_bytes() -> u64 {
+    64 * 1024 * 1024
+}
+
+fn default_circuit_breaker_threshold() -> u32 {
+    5
+}
+
+fn default_circuit_breaker_cooldown_seconds() -> u64 {
+    300
+}
+
+fn default_listing_cache_ttl_seconds() -> u64 {
+    300
+}
+
+fn default_upload_batch_size() -> usize {
+    1
+}
+
+fn default_upload_parallelism() -> usize {
+    4
+}
+
+fn default_s3_multipart_threshold() -> u64 {
+    64 * 1024 * 1024
+}
+
+fn default_s3_multipart_part_size() -> u64 {
+    16 * 1024 * 1024
+}
+
+fn default_s3_multipart_state_file() -> String {
+    ".meso-forge-mirror-multipart.json".to_string()
+}
+
+fn default_repodata_snapshots() -> usize {
+    10
 }
 
 impl Default for Config {
@@ -22,7 +320,78 @@ impl Default for Config {
             s3_region: None,
             s3_endpoint: None,
             github_token: std::env::var("GITHUB_TOKEN").ok(),
+            github_api_url: default_github_api_url(),
             azure_devops_token: std::env::var("AZURE_DEVOPS_TOKEN").ok(),
+            azure_devops_url: default_azure_devops_url(),
+            gitlab_token: default_gitlab_token(),
+            gitlab_url: default_gitlab_url(),
+            auth_file: default_auth_file(),
+            allowed_source_hosts: Vec::new(),
+            quarantine_dir: default_quarantine_dir(),
+            force_replace: false,
+            force: false,
+            target_packages: None,
+            circuit_breaker_threshold: default_circuit_breaker_threshold(),
+            circuit_breaker_cooldown_seconds: default_circuit_breaker_cooldown_seconds(),
+            resume_state_file: default_resume_state_file(),
+            duplicate_platform_policy: DuplicatePlatformPolicy::default(),
+            strict_platform: false,
+            explain_platform: false,
+            filename_policy: FilenamePolicy::default(),
+            webhooks: Vec::new(),
+            email: None,
+            state_db: None,
+            since_last_run: false,
+            upstream_state_file: None,
+            provenance: false,
+            #[cfg(feature = "schedule")]
+            jobs: Vec::new(),
+            health_listen: None,
+            policy: None,
+            vulnerabilities: None,
+            upstream_channel: None,
+            public_url: None,
+            channel_config_file: None,
+            channel_name: None,
+            platform_mappings: Vec::new(),
+            user_agent_contact: None,
+            crawl_delay_ms: 0,
+            max_connections_per_host: 0,
+            max_bytes_per_second: 0,
+            global_max_connections: 0,
+            global_max_bytes_per_second: 0,
+            global_max_buffered_bytes: 0,
+            priority: 0,
+            run_until: None,
+            require_platforms: Vec::new(),
+            all_matches: false,
+            cache_extract: false,
+            verify_extraction: false,
+            scratch_dir: None,
+            min_free_space: 0,
+            range_read_min_bytes: default_range_read_min_bytes(),
+            src_exclude: None,
+            filter_preset: None,
+            src_subdirs: Vec::new(),
+            platforms: Vec::new(),
+            specs: Vec::new(),
+            enrich_from: None,
+            listing_cache_dir: None,
+            listing_cache_ttl_seconds: default_listing_cache_ttl_seconds(),
+            oci_username: None,
+            oci_password: None,
+            serve_username: None,
+            serve_password: None,
+            upload_batch_size: default_upload_batch_size(),
+            upload_batch_bytes: 0,
+            upload_parallelism: default_upload_parallelism(),
+            s3_multipart_threshold: default_s3_multipart_threshold(),
+            s3_multipart_part_size: default_s3_multipart_part_size(),
+            s3_multipart_state_file: default_s3_multipart_state_file(),
+            signing: None,
+            retention: None,
+            repodata_snapshots: default_repodata_snapshots(),
+            sharded_repodata: false,
         }
     }
 }
@@ -30,7 +399,9 @@ impl Default for Config {
 impl Config {
     pub fn load_from_file(path: &str) -> Result<Self> {
         let content = fs::read_to_string(path)?;
-        let config: Config = serde_json::from_str(&content)?;
+        let config: Config = serde_json::from_str(&content).map_err(|e| {
+            MirrorError::InvalidInput(format!("Invalid configuration file '{}': {}", path, e))
+        })?;
         Ok(config)
     }
 
@@ -69,4 +440,105 @@ mod tests {
         );
         assert_eq!(loaded_config.retry_attempts, config.retry_attempts);
     }
+
+    #[test]
+    fn test_config_missing_quarantine_dir_uses_default() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("old_config.json");
+        std::fs::write(
+            &config_path,
+            r#"{"max_concurrent_downloads": 2, "retry_attempts": 1, "timeout_seconds": 60}"#,
+        )
+        .unwrap();
+
+        let config = Config::load_from_file(config_path.to_str().unwrap()).unwrap();
+        assert_eq!(config.quarantine_dir, "quarantine");
+        assert!(!config.force_replace);
+        assert!(!config.force);
+        assert_eq!(config.circuit_breaker_threshold, 5);
+        assert_eq!(config.circuit_breaker_cooldown_seconds, 300);
+        assert_eq!(
+            config.duplicate_platform_policy,
+            DuplicatePlatformPolicy::Error
+        );
+        assert_eq!(config.filename_policy, FilenamePolicy::Rename);
+        assert!(config.webhooks.is_empty());
+        assert!(config.state_db.is_none());
+        assert!(!config.since_last_run);
+        assert!(config.upstream_state_file.is_none());
+        assert!(config.allowed_source_hosts.is_empty());
+        assert_eq!(config.gitlab_url, "https://gitlab.com");
+        assert_eq!(config.github_api_url, "https://api.github.com");
+        assert_eq!(config.azure_devops_url, "https://dev.azure.com");
+        assert!(!config.provenance);
+        #[cfg(feature = "schedule")]
+        assert!(config.jobs.is_empty());
+        assert!(config.health_listen.is_none());
+        assert!(config.policy.is_none());
+        assert!(config.vulnerabilities.is_none());
+        assert!(config.upstream_channel.is_none());
+        assert!(config.public_url.is_none());
+        assert!(config.channel_config_file.is_none());
+        assert!(config.signing.is_none());
+        assert!(config.retention.is_none());
+        assert!(config.channel_name.is_none());
+        assert!(config.platform_mappings.is_empty());
+        assert!(config.user_agent_contact.is_none());
+        assert_eq!(config.crawl_delay_ms, 0);
+        assert_eq!(config.max_connections_per_host, 0);
+        assert_eq!(config.global_max_connections, 0);
+        assert_eq!(config.global_max_bytes_per_second, 0);
+        assert_eq!(config.global_max_buffered_bytes, 0);
+        assert!(config.require_platforms.is_empty());
+        assert!(!config.all_matches);
+        assert!(!config.cache_extract);
+        assert!(!config.verify_extraction);
+        assert!(config.scratch_dir.is_none());
+        assert_eq!(config.min_free_space, 0);
+        assert_eq!(config.range_read_min_bytes, 64 * 1024 * 1024);
+        assert!(config.src_exclude.is_none());
+        assert!(config.filter_preset.is_none());
+        assert!(config.oci_username.is_none());
+        assert!(config.oci_password.is_none());
+        assert!(config.serve_username.is_none());
+        assert!(config.serve_password.is_none());
+        assert!(config.src_subdirs.is_empty());
+        assert!(config.platforms.is_empty());
+        assert!(config.specs.is_empty());
+        assert!(config.enrich_from.is_none());
+        assert!(config.listing_cache_dir.is_none());
+        assert_eq!(config.listing_cache_ttl_seconds, 300);
+        assert_eq!(config.upload_batch_size, 1);
+        assert_eq!(config.upload_batch_bytes, 0);
+        assert_eq!(config.upload_parallelism, 4);
+        assert_eq!(config.s3_multipart_threshold, 64 * 1024 * 1024);
+        assert_eq!(config.s3_multipart_part_size, 16 * 1024 * 1024);
+        assert_eq!(
+            config.s3_multipart_state_file,
+            ".meso-forge-mirror-multipart.json"
+        );
+        assert_eq!(config.repodata_snapshots, 10);
+        assert!(!config.sharded_repodata);
+    }
+
+    #[test]
+    fn test_config_webhooks() {
+        let config: Config = serde_json::from_str(
+            r#"{"max_concurrent_downloads": 2, "retry_attempts": 1, "timeout_seconds": 60,
+                "webhooks": [{"url": "https://hooks.slack.com/services/x", "format": "slack", "on": "failure"},
+                             {"url": "https://example.com/hook"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(config.webhooks.len(), 2);
+        assert_eq!(
+            config.webhooks[0].format,
+            crate::notify::WebhookFormat::Slack
+        );
+        assert_eq!(config.webhooks[0].on, crate::notify::WebhookEvents::Failure);
+        assert_eq!(
+            config.webhooks[1].format,
+            crate::notify::WebhookFormat::Generic
+        );
+        assert_eq!(config.webhooks[1].on, crate::notify::WebhookEvents::Always);
+    }
 }
@@ -0,0 +1,626 @@
+//! Long-running scheduler for mirror jobs
+//!
+//! The `daemon` command runs every [`JobConfig`] of the configuration on its own
+//! cron schedule from a single process, so different channels can sync at
+//! different cadences. Schedules are evaluated in the job's time zone: `0 2 * * *`
+//! in `Europe/Berlin` fires at 02:00 local time on both sides of a daylight
+//! saving change. A job never overlaps itself; ticks that pass while it is still
+//! running are skipped. Jobs writing to the same target take turns, so two of
+//! them never update its repodata at once.
+//!
+//! A job can override the download concurrency and bandwidth of the
+//! configuration, and jobs of a higher `priority` preempt running jobs of a
+//! lower one between packages, see [`crate::priority`]. The `global_*` limits
+//! of the daemon's configuration apply to all jobs together, see
+//! [`crate::limits`].
+//!
+//! Jobs with `windows` only transfer inside them, see [`crate::window`]; a run
+//! cut short by the close of its window resumes when the next one opens.
+
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::task::JoinSet;
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::error::{MirrorError, Result};
+use crate::health::{self, DaemonStatus, LastRun};
+use crate::limits;
+use crate::mirror::mirror_packages;
+use crate::notify::{self, Notification};
+use crate::priority;
+use crate::report::MirrorReport;
+use crate::repository::RepositoryType;
+use crate::shutdown;
+use crate::window::Windows;
+
+/// A mirror run the daemon repeats on a schedule
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JobConfig {
+    /// Name used in logs and to keep the resume state of each job apart
+    pub name: String,
+    /// Cron expression: five fields (`min hour day month weekday`), or six or
+    /// seven with leading seconds and trailing year
+    pub schedule: String,
+    /// IANA time zone the schedule is evaluated in, e.g. `Europe/Berlin`; UTC when unset
+    #[serde(default)]
+    pub timezone: Option<String>,
+    /// Source type, as for `mirror --src-type`
+    #[serde(default = "default_src_type")]
+    pub src_type: String,
+    pub src: String,
+    #[serde(default)]
+    pub src_path: Option<String>,
+    /// Target type, as for `mirror --tgt-type`
+    #[serde(default = "default_tgt_type")]
+    pub tgt_type: String,
+    /// Target path or URL; must be unset for the cache
+    #[serde(default)]
+    pub tgt: Option<String>,
+    /// Packages fetched ahead at once, instead of `max_concurrent_downloads`
+    #[serde(default)]
+    pub max_concurrent_downloads: Option<usize>,
+    /// Average download rate in bytes per second, instead of `max_bytes_per_second`
+    #[serde(default)]
+    pub max_bytes_per_second: Option<u64>,
+    /// Jobs of lower priority pause between packages while this one runs
+    #[serde(default)]
+    pub priority: i32,
+    /// Local times the job may transfer in, e.g. `01:00-05:00`; any time when empty
+    #[serde(default)]
+    pub windows: Vec<String>,
+}
+
+fn default_src_type() -> String {
+    "local".to_string()
+}
+
+fn default_tgt_type() -> String {
+    "cache".to_string()
+}
+
+impl JobConfig {
+    pub fn cron_schedule(&self) -> Result<CronSchedule> {
+        CronSchedule::parse(&self.schedule, self.timezone.as_deref())
+            .map_err(|e| MirrorError::InvalidInput(format!("Job '{}': {}", self.name, e)))
+    }
+
+    pub fn time_windows(&self) -> Result<Windows> {
+        let timezone = self.cron_schedule()?.timezone();
+        Windows::parse(&self.windows, timezone)
+            .map_err(|e| MirrorError::InvalidInput(format!("Job '{}': {}", self.name, e)))
+    }
+
+    /// Resolve the target path, which for the cache is the rattler cache directory
+    pub fn target_path(&self) -> Result<String> {
+        match (RepositoryType::from_string(&self.tgt_type)?, &self.tgt) {
+            (RepositoryType::Cache, None) => Ok(rattler_cache::default_cache_dir()
+                .map_err(|e| {
+                    MirrorError::InvalidInput(format!(
+                        "Failed to get default cache directory: {}",
+                        e
+                    ))
+                })?
+                .to_string_lossy()
+                .to_string()),
+            (RepositoryType::Cache, Some(_)) => Err(MirrorError::InvalidInput(format!(
+                "Job '{}': tgt cannot be set when tgt_type is 'cache'",
+                self.name
+            ))),
+            (_, Some(tgt)) => Ok(tgt.clone()),
+            (_, None) => Err(MirrorError::InvalidInput(format!(
+                "Job '{}': tgt is required for repository types (local, s3, prefix-dev)",
+                self.name
+            ))),
+        }
+    }
+
+    fn is_local_file(&self) -> bool {
+        matches!(self.src_type.as_str(), "zip" | "local" | "tgz")
+    }
+
+    /// The configuration for runs of this job, with a resume state file and limits of its own
+    fn run_config(&self, config: &Config) -> Config {
+        let path = Path::new(&config.resume_state_file);
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let filename = match path.extension() {
+            Some(extension) => format!("{}-{}.{}", stem, self.name, extension.to_string_lossy()),
+            None => format!("{}-{}", stem, self.name),
+        };
+
+        let mut config = config.clone();
+        config.resume_state_file = path.with_file_name(filename).to_string_lossy().to_string();
+        if let Some(max_concurrent_downloads) = self.max_concurrent_downloads {
+            config.max_concurrent_downloads = max_concurrent_downloads.max(1);
+        }
+        if let Some(max_bytes_per_second) = self.max_bytes_per_second {
+            config.max_bytes_per_second = max_bytes_per_second;
+        }
+        config.priority = self.priority;
+        config
+    }
+}
+
+/// A cron expression evaluated in a time zone
+#[derive(Debug, Clone)]
+pub struct CronSchedule {
+    schedule: cron::Schedule,
+    timezone: Tz,
+}
+
+impl CronSchedule {
+    /// Parse a cron expression and an optional IANA time zone (UTC when `None`)
+    ///
+    /// Five-field expressions follow standard cron, where weekdays run from 0
+    /// (Sunday) to 6, with 7 also meaning Sunday.
+    pub fn parse(expression: &str, timezone: Option<&str>) -> Result<Self> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let normalized = match fields.as_slice() {
+            [minute, hour, day, month, weekday] => format!(
+                "0 {} {} {} {} {}",
+                minute,
+                hour,
+                day,
+                month,
+                standard_weekdays(weekday)
+                    .map_err(|e| invalid_schedule(expression, e.to_string()))?
+            ),
+            _ => fields.join(" "),
+        };
+        let schedule = cron::Schedule::from_str(&normalized)
+            .map_err(|e| invalid_schedule(expression, e.to_string()))?;
+
+        let timezone = match timezone {
+            Some(name) => name
+                .parse::<Tz>()
+                .map_err(|_| MirrorError::InvalidInput(format!("Unknown time zone '{}'", name)))?,
+            None => Tz::UTC,
+        };
+
+        Ok(Self { schedule, timezone })
+    }
+
+    pub fn timezone(&self) -> Tz {
+        self.timezone
+    }
+
+    /// The first time the schedule fires after `after`
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.schedule
+            .after(&after.with_timezone(&self.timezone))
+            .next()
+            .map(|time| time.with_timezone(&Utc))
+    }
+
+    /// How many times the schedule fired in `(from, until]`
+    fn fired_between(&self, from: DateTime<Utc>, until: DateTime<Utc>) -> usize {
+        self.schedule
+            .after(&from.with_timezone(&self.timezone))
+            .take_while(|time| time.with_timezone(&Utc) <= until)
+            .count()
+    }
+}
+
+fn invalid_schedule(expression: &str, reason: String) -> MirrorError {
+    MirrorError::InvalidInput(format!(
+        "Invalid cron schedule '{}': {}",
+        expression, reason
+    ))
+}
+
+/// Translate a standard cron weekday field (Sunday = 0 or 7) to the cron
+/// crate's numbering (Sunday = 1); weekday names are passed through
+fn standard_weekdays(field: &str) -> Result<String> {
+    let invalid = || MirrorError::InvalidInput(format!("invalid day of week '{}'", field));
+    let day = |value: &str| -> Result<u32> {
+        value
+            .parse::<u32>()
+            .ok()
+            .filter(|day| *day <= 7)
+            .ok_or_else(invalid)
+    };
+
+    if field == "*" || field == "?" {
+        return Ok(field.to_string());
+    }
+
+    let mut days: Vec<String> = Vec::new();
+    for item in field.split(',') {
+        if item.chars().any(|c| c.is_ascii_alphabetic()) {
+            days.push(item.to_string());
+            continue;
+        }
+
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (
+                range,
+                Some(
+                    step.parse::<usize>()
+                        .ok()
+                        .filter(|step| *step > 0)
+                        .ok_or_else(invalid)?,
+                ),
+            ),
+            None => (item, None),
+        };
+        let (first, last) = match range.split_once('-') {
+            _ if range == "*" => (0, 6),
+            Some((first, last)) => (day(first)?, day(last)?),
+            None if step.is_some() => (day(range)?, 6),
+            None => (day(range)?, day(range)?),
+        };
+        if first > last {
+            return Err(invalid());
+        }
+
+        for weekday in (first..=last).step_by(step.unwrap_or(1)) {
+            let translated = (weekday % 7 + 1).to_string();
+            if !days.contains(&translated) {
+                days.push(translated);
+            }
+        }
+    }
+    Ok(days.join(","))
+}
+
+/// Run the configured jobs on their schedules until a shutdown is requested
+///
+/// With `health_listen` set, the endpoints of [`crate::health`] are served
+/// while the daemon runs.
+pub async fn run(config: &Config) -> Result<()> {
+    if config.jobs.is_empty() {
+        return Err(MirrorError::InvalidInput(
+            "No jobs configured; add them to 'jobs' in the configuration file".to_string(),
+        ));
+    }
+
+    // Jobs running at once share the global limits of the daemon's configuration
+    limits::shared(config);
+    let status = DaemonStatus::new();
+    let mut names = HashSet::new();
+    let mut target_locks: HashMap<String, Arc<Mutex<()>>> = HashMap::new();
+    let mut scheduled = Vec::new();
+    for job in &config.jobs {
+        if !names.insert(job.name.as_str()) {
+            return Err(MirrorError::InvalidInput(format!(
+                "Job name '{}' is used more than once",
+                job.name
+            )));
+        }
+        let schedule = job.cron_schedule()?;
+        let windows = job.time_windows()?;
+        let target = job.target_path()?;
+        status.add_job(&job.name, &job.schedule, schedule.timezone().name());
+        scheduled.push(ScheduledJob {
+            target_lock: target_locks.entry(target.clone()).or_default().clone(),
+            config: job.run_config(config),
+            job: job.clone(),
+            schedule,
+            windows,
+            target,
+            status: status.clone(),
+        });
+    }
+
+    let server = match &config.health_listen {
+        Some(address) => Some(tokio::spawn(health::serve(
+            health::bind(address).await?,
+            status.clone(),
+        ))),
+        None => None,
+    };
+
+    info!("Starting daemon with {} jobs", scheduled.len());
+    let mut tasks = JoinSet::new();
+    for job in scheduled {
+        tasks.spawn(job.run());
+    }
+    status.set_ready(true);
+    while let Some(result) = tasks.join_next().await {
+        result.map_err(|e| MirrorError::InvalidInput(format!("Job task failed: {}", e)))?;
+    }
+
+    if let Some(server) = server {
+        server.abort();
+    }
+    info!("Daemon stopped");
+    Ok(())
+}
+
+/// A job with everything its scheduling loop needs
+struct ScheduledJob {
+    job: JobConfig,
+    schedule: CronSchedule,
+    windows: Windows,
+    target: String,
+    config: Config,
+    /// Held while the job runs, shared by all jobs with the same target
+    target_lock: Arc<Mutex<()>>,
+    status: DaemonStatus,
+}
+
+impl ScheduledJob {
+    /// Run the job each time its schedule fires
+    async fn run(self) {
+        let name = &self.job.name;
+        // Set when the close of a window interrupted the last run
+        let mut resume = false;
+        loop {
+            let now = Utc::now();
+            let next = if resume {
+                Some(self.windows.next_open(now))
+            } else {
+                self.schedule
+                    .next_after(now)
+                    .map(|tick| self.windows.next_open(tick))
+            };
+            self.status.scheduled(name, next);
+            let Some(next) = next else {
+                info!("Job '{}' has no further scheduled runs", name);
+                return;
+            };
+            info!(
+                "Job '{}' {} at {}",
+                name,
+                if resume { "resumes" } else { "next runs" },
+                next.with_timezone(&self.schedule.timezone())
+            );
+
+            let delay = (next - now).to_std().unwrap_or_default();
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = shutdown::requested() => return,
+            }
+
+            let _turn = self.target_lock.lock().await;
+            if shutdown::is_requested() {
+                return;
+            }
+            let _running = priority::start(self.job.priority);
+            let started_at = Utc::now();
+            let mut config = self.config.clone();
+            config.run_until = self.windows.closes_at(started_at);
+            self.status.started(name);
+            let result = run_job(&self.job, &self.target, &config).await;
+            self.status
+                .finished(name, LastRun::new(started_at, &result));
+
+            resume =
+                matches!(result, Err(MirrorError::Interrupted { .. })) && !shutdown::is_requested();
+            let skipped = self.schedule.fired_between(next, Utc::now());
+            if skipped > 0 && !resume {
+                warn!(
+                    "Job '{}' ran past {} scheduled runs, which were skipped",
+                    name, skipped
+                );
+            }
+        }
+    }
+}
+
+/// Run a job once and send the notifications configured for it
+pub async fn run_job(job: &JobConfig, target: &str, config: &Config) -> Result<MirrorReport> {
+    info!("Running job '{}'", job.name);
+    let result = mirror_packages(
+        &job.src,
+        job.src_path.as_deref(),
+        &job.src_type,
+        job.is_local_file(),
+        RepositoryType::from_string(&job.tgt_type)?,
+        target,
+        config,
+    )
+    .await;
+
+    let notification = match &result {
+        Ok(report) => Notification::from_report(report),
+        Err(e) => Notification::from_error(&job.src, target, e).with_report(e.partial_report()),
+    };
+    match &result {
+        Ok(report) if report.is_success() => {
+            info!("Job '{}' finished: {}", job.name, notification.summary())
+        }
+        _ => warn!("Job '{}' failed: {}", job.name, notification.summary()),
+    }
+    notify::announce(
+        config,
+        &notification,
+        matches!(result, Err(MirrorError::Interrupted { .. })),
+    )
+    .await;
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn utc(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_five_field_schedule() {
+        let schedule = CronSchedule::parse("30 2 * * *", None).unwrap();
+        assert_eq!(
+            schedule.next_after(utc(2024, 5, 1, 12, 0)),
+            Some(utc(2024, 5, 2, 2, 30))
+        );
+
+        let schedule = CronSchedule::parse("*/15 * * * *", None).unwrap();
+        assert_eq!(
+            schedule.next_after(utc(2024, 5, 1, 12, 1)),
+            Some(utc(2024, 5, 1, 12, 15))
+        );
+
+        // Seconds and year may be given explicitly
+        let schedule = CronSchedule::parse("0 0 6 1 1 * 2030", None).unwrap();
+        assert_eq!(
+            schedule.next_after(utc(2024, 5, 1, 12, 0)),
+            Some(utc(2030, 1, 1, 6, 0))
+        );
+
+        assert!(CronSchedule::parse("* * *", None).is_err());
+        assert!(CronSchedule::parse("61 * * * *", None).is_err());
+        assert!(CronSchedule::parse("0 2 * * 8", None).is_err());
+    }
+
+    #[test]
+    fn test_standard_weekdays() {
+        assert_eq!(standard_weekdays("0").unwrap(), "1");
+        assert_eq!(standard_weekdays("7").unwrap(), "1");
+        assert_eq!(standard_weekdays("1-5").unwrap(), "2,3,4,5,6");
+        assert_eq!(standard_weekdays("5-7").unwrap(), "6,7,1");
+        assert_eq!(standard_weekdays("*/2").unwrap(), "1,3,5,7");
+        assert_eq!(standard_weekdays("MON-FRI").unwrap(), "MON-FRI");
+        assert!(standard_weekdays("5-1").is_err());
+
+        // 2024-05-04 is a Saturday; weekdays-only skips to Monday
+        let schedule = CronSchedule::parse("0 9 * * 1-5", None).unwrap();
+        assert_eq!(
+            schedule.next_after(utc(2024, 5, 4, 0, 0)),
+            Some(utc(2024, 5, 6, 9, 0))
+        );
+        let schedule = CronSchedule::parse("0 9 * * 0", None).unwrap();
+        assert_eq!(
+            schedule.next_after(utc(2024, 5, 4, 0, 0)),
+            Some(utc(2024, 5, 5, 9, 0))
+        );
+    }
+
+    #[test]
+    fn test_schedule_timezone() {
+        let schedule = CronSchedule::parse("0 2 * * *", Some("Europe/Berlin")).unwrap();
+        // CEST (UTC+2) in summer, CET (UTC+1) in winter
+        assert_eq!(
+            schedule.next_after(utc(2024, 7, 1, 12, 0)),
+            Some(utc(2024, 7, 2, 0, 0))
+        );
+        assert_eq!(
+            schedule.next_after(utc(2024, 12, 1, 12, 0)),
+            Some(utc(2024, 12, 2, 1, 0))
+        );
+        assert_eq!(
+            schedule.fired_between(utc(2024, 7, 1, 12, 0), utc(2024, 7, 4, 12, 0)),
+            3
+        );
+
+        assert!(CronSchedule::parse("0 2 * * *", Some("Mars/Olympus")).is_err());
+    }
+
+    #[test]
+    fn test_job_config() {
+        let job: JobConfig = serde_json::from_str(
+            r#"{"name": "nightly", "schedule": "0 2 * * *", "src": "owner/repo",
+                "src_type": "github", "tgt_type": "local", "tgt": "./repo"}"#,
+        )
+        .unwrap();
+        assert_eq!(job.timezone, None);
+        assert_eq!(job.target_path().unwrap(), "./repo");
+        assert!(!job.is_local_file());
+
+        let config = Config {
+            resume_state_file: "state/resume.json".to_string(),
+            ..Config::default()
+        };
+        assert_eq!(
+            job.run_config(&config).resume_state_file,
+            "state/resume-nightly.json"
+        );
+        assert_eq!(job.run_config(&config).max_concurrent_downloads, 5);
+        assert_eq!(job.run_config(&config).priority, 0);
+
+        let urgent: JobConfig = serde_json::from_str(
+            r#"{"name": "security", "schedule": "*/10 * * * *", "src": "owner/repo",
+                "src_type": "github", "tgt_type": "local", "tgt": "./repo",
+                "max_concurrent_downloads": 16, "max_bytes_per_second": 1000000, "priority": 10}"#,
+        )
+        .unwrap();
+        let run_config = urgent.run_config(&config);
+        assert_eq!(run_config.max_concurrent_downloads, 16);
+        assert_eq!(run_config.max_bytes_per_second, 1_000_000);
+        assert_eq!(run_config.priority, 10);
+
+        let job = JobConfig { tgt: None, ..job };
+        assert!(job.target_path().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_run_requires_jobs() {
+        assert!(run(&Config::default()).await.is_err());
+
+        let job = JobConfig {
+            name: "a".to_string(),
+            schedule: "0 2 * * *".to_string(),
+            timezone: None,
+            src_type: "local".to_string(),
+            src: "pkg.conda".to_string(),
+            src_path: None,
+            tgt_type: "local".to_string(),
+            tgt: Some("./repo".to_string()),
+            max_concurrent_downloads: None,
+            max_bytes_per_second: None,
+            priority: 0,
+            windows: Vec::new(),
+        };
+        let config = Config {
+            jobs: vec![job.clone(), job],
+            ..Config::default()
+        };
+        let error = run(&config).await.unwrap_err().to_string();
+        assert!(error.contains("used more than once"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_run_job() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let fixture = crate::test_support::PackageFixture::new("pkg", "1.0").subdir("linux-64");
+        let package = temp_dir.path().join(fixture.conda_filename());
+        std::fs::write(&package, fixture.to_conda()).unwrap();
+        let target = temp_dir.path().join("repo");
+
+        let job = JobConfig {
+            name: "local".to_string(),
+            schedule: "0 2 * * *".to_string(),
+            timezone: None,
+            src_type: "local".to_string(),
+            src: package.to_string_lossy().to_string(),
+            src_path: None,
+            tgt_type: "local".to_string(),
+            tgt: Some(target.to_string_lossy().to_string()),
+            max_concurrent_downloads: None,
+            max_bytes_per_second: None,
+            priority: 0,
+            windows: Vec::new(),
+        };
+        let config = job.run_config(&Config {
+            resume_state_file: temp_dir
+                .path()
+                .join("resume.json")
+                .to_string_lossy()
+                .to_string(),
+            ..Config::default()
+        });
+        let report = run_job(&job, &job.target_path().unwrap(), &config)
+            .await
+            .unwrap();
+
+        assert!(report.is_success());
+        assert!(target
+            .join("linux-64")
+            .join(fixture.conda_filename())
+            .exists());
+    }
+}
@@ -0,0 +1,109 @@
+use bytes::Bytes;
+use std::future::Future;
+use tracing::warn;
+
+use crate::error::{MirrorError, Result};
+
+/// Check that a downloaded payload has the size announced by the server or listing.
+///
+/// `expected` is `None` when the size is unknown (e.g. chunked transfer encoding),
+/// in which case the check always passes.
+pub fn verify_download_size(source: &str, expected: Option<u64>, received: usize) -> Result<()> {
+    match expected {
+        Some(expected) if expected != received as u64 => Err(MirrorError::Truncated {
+            source_url: source.to_string(),
+            expected,
+            received: received as u64,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Run a download operation, retrying with exponential backoff on retryable failures.
+///
+/// Size mismatches reported by [`verify_download_size`] are treated like any other
+/// transient failure so a truncated transfer is fetched again rather than processed.
+/// Errors that cannot succeed on retry (authentication, not found) are returned at once.
+pub async fn download_with_retries<F, Fut>(
+    description: &str,
+    max_attempts: u32,
+    mut operation: F,
+) -> Result<Bytes>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<Bytes>>,
+{
+    let max_attempts = max_attempts.max(1);
+    let mut attempts = 0;
+
+    loop {
+        attempts += 1;
+
+        match operation().await {
+            Ok(content) => return Ok(content),
+            Err(e) => {
+                if attempts >= max_attempts || !e.is_retryable() {
+                    return Err(e);
+                }
+                warn!(
+                    "Download of {} failed (attempt {}/{}): {}, retrying...",
+                    description, attempts, max_attempts, e
+                );
+            }
+        }
+
+        tokio::time::sleep(std::time::Duration::from_secs(2_u64.pow(attempts - 1))).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[test]
+    fn test_verify_download_size() {
+        assert!(verify_download_size("test", Some(10), 10).is_ok());
+        assert!(verify_download_size("test", None, 10).is_ok());
+
+        let err = verify_download_size("test", Some(10), 4).unwrap_err();
+        assert!(err.to_string().contains("expected 10 bytes, received 4"));
+    }
+
+    #[tokio::test]
+    async fn test_download_with_retries_recovers_from_truncation() {
+        let calls = AtomicU32::new(0);
+
+        let content = download_with_retries("test", 3, || {
+            let attempt = calls.fetch_add(1, Ordering::SeqCst);
+            async move {
+                let body = if attempt == 0 {
+                    Bytes::from_static(b"trunc")
+                } else {
+                    Bytes::from_static(b"complete!")
+                };
+                verify_download_size("test", Some(9), body.len())?;
+                Ok(body)
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(content.as_ref(), b"complete!");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_download_with_retries_gives_up() {
+        let calls = AtomicU32::new(0);
+
+        let result = download_with_retries("test", 1, || {
+            calls.fetch_add(1, Ordering::SeqCst);
+            async { verify_download_size("test", Some(9), 5).map(|_| Bytes::new()) }
+        })
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
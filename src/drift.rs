@@ -0,0 +1,283 @@
+//! Comparison of a mirror with its upstream channel
+//!
+//! The `drift` command reads the repodata of both channels and reports
+//! packages upstream has that the mirror lacks, packages the mirror still
+//! serves after upstream removed them, and packages present in both whose
+//! hashes differ. It exits with an error when anything drifted, so it can run
+//! as a nightly CI check.
+
+use comfy_table::presets::NOTHING;
+use comfy_table::{Attribute, Cell, ContentArrangement, Table};
+use regex::Regex;
+use reqwest::Client;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::error::{MirrorError, Result};
+use crate::sbom::{load_channel, ChannelPackage};
+
+/// A package present in both channels with different content
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HashMismatch {
+    /// `subdir/filename`
+    pub path: String,
+    /// `sha256` when both channels list it, otherwise `md5`
+    pub algorithm: String,
+    pub mirror: String,
+    pub upstream: String,
+}
+
+/// How a mirror differs from its upstream channel
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ChannelDrift {
+    pub mirror: String,
+    pub upstream: String,
+    /// Packages upstream has that the mirror lacks, as `subdir/filename`
+    pub missing: Vec<String>,
+    /// Packages the mirror serves that upstream no longer has
+    pub removed_upstream: Vec<String>,
+    pub hash_mismatches: Vec<HashMismatch>,
+}
+
+impl ChannelDrift {
+    pub fn is_empty(&self) -> bool {
+        self.missing.is_empty()
+            && self.removed_upstream.is_empty()
+            && self.hash_mismatches.is_empty()
+    }
+}
+
+fn by_path(packages: Vec<ChannelPackage>) -> BTreeMap<String, ChannelPackage> {
+    packages
+        .into_iter()
+        .map(|package| (format!("{}/{}", package.subdir, package.filename), package))
+        .collect()
+}
+
+/// Compare the packages of a mirror with those of its upstream channel
+pub fn compare(
+    mirror: &str,
+    mirror_packages: Vec<ChannelPackage>,
+    upstream: &str,
+    upstream_packages: Vec<ChannelPackage>,
+) -> ChannelDrift {
+    let mirror_packages = by_path(mirror_packages);
+    let upstream_packages = by_path(upstream_packages);
+
+    let mut drift = ChannelDrift {
+        mirror: mirror.to_string(),
+        upstream: upstream.to_string(),
+        ..ChannelDrift::default()
+    };
+    for (path, upstream_package) in &upstream_packages {
+        let Some(mirror_package) = mirror_packages.get(path) else {
+            drift.missing.push(path.clone());
+            continue;
+        };
+        let hashes = match (
+            (&mirror_package.sha256, &upstream_package.sha256),
+            (&mirror_package.md5, &upstream_package.md5),
+        ) {
+            ((Some(mirror), Some(upstream)), _) => Some(("sha256", mirror, upstream)),
+            (_, (Some(mirror), Some(upstream))) => Some(("md5", mirror, upstream)),
+            _ => None,
+        };
+        if let Some((algorithm, mirror, upstream)) = hashes {
+            if !mirror.eq_ignore_ascii_case(upstream) {
+                drift.hash_mismatches.push(HashMismatch {
+                    path: path.clone(),
+                    algorithm: algorithm.to_string(),
+                    mirror: mirror.clone(),
+                    upstream: upstream.clone(),
+                });
+            }
+        }
+    }
+    drift.removed_upstream = mirror_packages
+        .keys()
+        .filter(|path| !upstream_packages.contains_key(*path))
+        .cloned()
+        .collect();
+    drift
+}
+
+/// Read both channels and compare them
+///
+/// Without `subdirs`, the subdirs the mirror has are compared. `package`
+/// restricts the comparison to package names matching the regular expression.
+pub async fn channel_drift(
+    client: &Client,
+    mirror: &str,
+    upstream: &str,
+    subdirs: &[String],
+    package: Option<&str>,
+) -> Result<ChannelDrift> {
+    let filter = package
+        .map(|pattern| {
+            Regex::new(&format!("^(?:{})$", pattern)).map_err(|e| {
+                MirrorError::InvalidInput(format!("Invalid package pattern '{}': {}", pattern, e))
+            })
+        })
+        .transpose()?;
+    let selected = |packages: Vec<ChannelPackage>| -> Vec<ChannelPackage> {
+        packages
+            .into_iter()
+            .filter(|p| {
+                filter
+                    .as_ref()
+                    .is_none_or(|filter| filter.is_match(&p.name))
+            })
+            .collect()
+    };
+
+    let mirror_packages = load_channel(client, mirror, subdirs).await?;
+    let subdirs = if subdirs.is_empty() {
+        let mut subdirs: Vec<String> = mirror_packages.iter().map(|p| p.subdir.clone()).collect();
+        subdirs.dedup();
+        subdirs
+    } else {
+        subdirs.to_vec()
+    };
+    let upstream_packages = load_channel(client, upstream, &subdirs).await?;
+
+    Ok(compare(
+        mirror,
+        selected(mirror_packages),
+        upstream,
+        selected(upstream_packages),
+    ))
+}
+
+/// Print a drift report as yaml, json or a table
+pub fn print_drift(drift: &ChannelDrift, format: &str) -> Result<()> {
+    match format.to_lowercase().as_str() {
+        "yaml" => println!("{}", serde_yaml::to_string(drift)?),
+        "json" => println!("{}", serde_json::to_string_pretty(drift)?),
+        "table" => {
+            if drift.is_empty() {
+                println!("No drift: {} matches {}.", drift.mirror, drift.upstream);
+                return Ok(());
+            }
+            let mut table = Table::new();
+            table
+                .load_preset(NOTHING)
+                .set_content_arrangement(ContentArrangement::Dynamic)
+                .set_header(
+                    ["Package", "Drift", "Detail"]
+                        .iter()
+                        .map(|title| Cell::new(title).add_attribute(Attribute::Bold)),
+                );
+            for path in &drift.missing {
+                table.add_row(vec![Cell::new(path), Cell::new("missing"), Cell::new("")]);
+            }
+            for path in &drift.removed_upstream {
+                table.add_row(vec![
+                    Cell::new(path),
+                    Cell::new("removed upstream"),
+                    Cell::new(""),
+                ]);
+            }
+            for mismatch in &drift.hash_mismatches {
+                table.add_row(vec![
+                    Cell::new(&mismatch.path),
+                    Cell::new("hash mismatch"),
+                    Cell::new(format!(
+                        "{} mirror {} upstream {}",
+                        mismatch.algorithm, mismatch.mirror, mismatch.upstream
+                    )),
+                ]);
+            }
+            println!("{}", table);
+        }
+        _ => {
+            return Err(MirrorError::InvalidInput(format!(
+                "Unsupported output format: {}. Supported formats: yaml, json, table",
+                format
+            )))
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{MockResponse, MockServer};
+
+    fn repodata(packages: &[(&str, &str, &str)]) -> String {
+        let packages: serde_json::Map<String, serde_json::Value> = packages
+            .iter()
+            .map(|(filename, name, sha256)| {
+                (
+                    filename.to_string(),
+                    serde_json::json!({
+                        "name": name, "version": "1.0", "build": "0", "subdir": "noarch",
+                        "sha256": sha256,
+                    }),
+                )
+            })
+            .collect();
+        serde_json::json!({ "packages.conda": packages }).to_string()
+    }
+
+    #[tokio::test]
+    async fn test_channel_drift() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir_all(temp_dir.path().join("noarch")).unwrap();
+        std::fs::write(
+            temp_dir.path().join("noarch/repodata.json"),
+            repodata(&[
+                ("same-1.0-0.conda", "same", "aa"),
+                ("changed-1.0-0.conda", "changed", "bb"),
+                ("gone-1.0-0.conda", "gone", "cc"),
+            ]),
+        )
+        .unwrap();
+
+        let server = MockServer::start().await.unwrap();
+        server.mock(
+            "GET",
+            "/upstream/noarch/repodata.json",
+            MockResponse::json(
+                200,
+                repodata(&[
+                    ("same-1.0-0.conda", "same", "AA"),
+                    ("changed-1.0-0.conda", "changed", "dd"),
+                    ("new-1.0-0.conda", "new", "ee"),
+                ]),
+            ),
+        );
+
+        let mirror = temp_dir.path().to_string_lossy().to_string();
+        let upstream = format!("{}/upstream", server.url());
+        let drift = channel_drift(&Client::new(), &mirror, &upstream, &[], None)
+            .await
+            .unwrap();
+        assert_eq!(drift.missing, vec!["noarch/new-1.0-0.conda"]);
+        assert_eq!(drift.removed_upstream, vec!["noarch/gone-1.0-0.conda"]);
+        assert_eq!(
+            drift.hash_mismatches,
+            vec![HashMismatch {
+                path: "noarch/changed-1.0-0.conda".to_string(),
+                algorithm: "sha256".to_string(),
+                mirror: "bb".to_string(),
+                upstream: "dd".to_string(),
+            }]
+        );
+        // Only the subdirs the mirror has are fetched upstream
+        assert_eq!(server.requests().len(), 1);
+
+        let drift = channel_drift(&Client::new(), &mirror, &upstream, &[], Some("same|new"))
+            .await
+            .unwrap();
+        assert_eq!(drift.missing, vec!["noarch/new-1.0-0.conda"]);
+        assert!(drift.removed_upstream.is_empty());
+        assert!(drift.hash_mismatches.is_empty());
+
+        assert!(
+            channel_drift(&Client::new(), &mirror, &upstream, &[], Some("("))
+                .await
+                .is_err()
+        );
+    }
+}
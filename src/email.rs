@@ -0,0 +1,339 @@
+//! Email alerts for repeatedly failing mirror runs
+//!
+//! Consecutive failures are counted per source and target in a small state
+//! file. Once the count reaches [`EmailConfig::failure_threshold`] a summary of
+//! the last failure is mailed to the configured recipients, and again every
+//! `failure_threshold` failures after that; a successful run resets the count.
+//! Sending requires the `email` feature.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+use tracing::warn;
+
+use crate::error::{MirrorError, Result};
+use crate::notify::{Notification, RunStatus};
+
+/// How the SMTP connection is secured
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SmtpTls {
+    /// Plain connection upgraded with STARTTLS (usually port 587)
+    #[default]
+    Starttls,
+    /// TLS from the start (usually port 465)
+    Tls,
+    /// No encryption; only for local relays
+    None,
+}
+
+/// SMTP settings and recipients for failure alerts
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmailConfig {
+    pub smtp_server: String,
+    #[serde(default = "default_smtp_port")]
+    pub smtp_port: u16,
+    #[serde(default)]
+    pub tls: SmtpTls,
+    #[serde(default)]
+    pub username: Option<String>,
+    /// Falls back to the `SMTP_PASSWORD` environment variable
+    #[serde(default = "smtp_password_from_env", skip_serializing)]
+    pub password: Option<String>,
+    pub from: String,
+    pub recipients: Vec<String>,
+    /// Consecutive failed runs of the same source and target before an email is sent
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+    /// File counting consecutive failures between runs
+    #[serde(default = "default_failure_state_file")]
+    pub failure_state_file: String,
+}
+
+fn default_smtp_port() -> u16 {
+    587
+}
+
+fn smtp_password_from_env() -> Option<String> {
+    std::env::var("SMTP_PASSWORD").ok()
+}
+
+fn default_failure_threshold() -> u32 {
+    1
+}
+
+fn default_failure_state_file() -> String {
+    ".meso-forge-mirror-failures.json".to_string()
+}
+
+/// Serializes updates of the failure state file by concurrent runs of the daemon
+static STATE_FILE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Consecutive failures per `source -> target` pair
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct FailureStreaks {
+    #[serde(flatten)]
+    streaks: HashMap<String, u32>,
+}
+
+impl FailureStreaks {
+    fn load(path: &str) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).map_err(|e| {
+                MirrorError::InvalidInput(format!("Invalid failure state file '{}': {}", path, e))
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn save(&self, path: &str) -> Result<()> {
+        if self.streaks.is_empty() {
+            if Path::new(path).exists() {
+                std::fs::remove_file(path)?;
+            }
+            return Ok(());
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// Record the outcome of a run and say whether it calls for an alert
+///
+/// Returns the number of consecutive failures when an email is due.
+pub fn record_run(config: &EmailConfig, notification: &Notification) -> Result<Option<u32>> {
+    let key = format!("{} -> {}", notification.source, notification.target);
+    let _guard = STATE_FILE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut state = FailureStreaks::load(&config.failure_state_file)?;
+
+    let due = match notification.status {
+        RunStatus::Success => {
+            state.streaks.remove(&key);
+            None
+        }
+        RunStatus::Failure => {
+            let count = state.streaks.entry(key).or_insert(0);
+            *count += 1;
+            let threshold = config.failure_threshold.max(1);
+            (*count % threshold == 0).then_some(*count)
+        }
+    };
+
+    state.save(&config.failure_state_file)?;
+    Ok(due)
+}
+
+/// Subject and plain-text body of a failure alert
+#[cfg_attr(not(feature = "email"), allow(dead_code))]
+pub fn render(notification: &Notification, consecutive_failures: u32) -> (String, String) {
+    let subject = format!(
+        "[meso-forge-mirror] {} -> {} failed {} time{} in a row",
+        notification.source,
+        notification.target,
+        consecutive_failures,
+        if consecutive_failures == 1 { "" } else { "s" }
+    );
+    let body = format!(
+        "{}\n\nConsecutive failed runs: {}\n",
+        notification.summary(),
+        consecutive_failures
+    );
+    (subject, body)
+}
+
+/// Record the run and mail the recipients if the failure streak calls for it
+///
+/// Returns whether an email was sent.
+#[cfg(feature = "email")]
+pub async fn notify_failures(config: &EmailConfig, notification: &Notification) -> Result<bool> {
+    let Some(consecutive_failures) = record_run(config, notification)? else {
+        return Ok(false);
+    };
+    let (subject, body) = render(notification, consecutive_failures);
+    send(config, subject, body).await?;
+    Ok(true)
+}
+
+/// Mail the configured recipients if this run extends a failure streak far enough
+///
+/// Problems are logged rather than returned, so alerts never fail the run.
+#[cfg(feature = "email")]
+pub async fn alert_failures(config: &EmailConfig, notification: &Notification) {
+    match notify_failures(config, notification).await {
+        Ok(true) => tracing::info!("Sent failure alert email"),
+        Ok(false) => {}
+        Err(e) => warn!("Failed to send failure alert email: {}", e),
+    }
+}
+
+#[cfg(not(feature = "email"))]
+pub async fn alert_failures(config: &EmailConfig, notification: &Notification) {
+    if let Err(e) = record_run(config, notification) {
+        warn!("Failed to record run outcome: {}", e);
+    }
+    warn!("Email alerts are configured, but this build was compiled without the 'email' feature");
+}
+
+#[cfg(feature = "email")]
+async fn send(config: &EmailConfig, subject: String, body: String) -> Result<()> {
+    use lettre::message::header::ContentType;
+    use lettre::transport::smtp::authentication::Credentials;
+    use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+    let address = |value: &str| {
+        value.parse().map_err(|e| {
+            MirrorError::InvalidInput(format!("Invalid email address '{}': {}", value, e))
+        })
+    };
+
+    let mut message = Message::builder()
+        .from(address(&config.from)?)
+        .subject(subject)
+        .header(ContentType::TEXT_PLAIN);
+    for recipient in &config.recipients {
+        message = message.to(address(recipient)?);
+    }
+    let message = message
+        .body(body)
+        .map_err(|e| MirrorError::InvalidInput(format!("Invalid alert email: {}", e)))?;
+
+    let smtp_error = |e: lettre::transport::smtp::Error| {
+        MirrorError::Other(anyhow::anyhow!(
+            "Failed to send alert email via {}: {}",
+            config.smtp_server,
+            e
+        ))
+    };
+    let mut transport = match config.tls {
+        SmtpTls::Starttls => {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.smtp_server)
+                .map_err(smtp_error)?
+        }
+        SmtpTls::Tls => {
+            AsyncSmtpTransport::<Tokio1Executor>::relay(&config.smtp_server).map_err(smtp_error)?
+        }
+        SmtpTls::None => {
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.smtp_server)
+        }
+    }
+    .port(config.smtp_port);
+    if let (Some(username), Some(password)) = (&config.username, &config.password) {
+        transport = transport.credentials(Credentials::new(username.clone(), password.clone()));
+    }
+
+    transport.build().send(message).await.map_err(smtp_error)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn config(temp_dir: &TempDir, failure_threshold: u32) -> EmailConfig {
+        EmailConfig {
+            smtp_server: "127.0.0.1".to_string(),
+            smtp_port: 25,
+            tls: SmtpTls::None,
+            username: None,
+            password: None,
+            from: "mirror@example.com".to_string(),
+            recipients: vec!["ops@example.com".to_string()],
+            failure_threshold,
+            failure_state_file: temp_dir
+                .path()
+                .join("failures.json")
+                .to_string_lossy()
+                .to_string(),
+        }
+    }
+
+    #[test]
+    fn test_record_run_alerts_every_threshold_failures() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = config(&temp_dir, 2);
+        let failure = Notification::from_error("owner/repo", "./repo", &"boom");
+        let other = Notification::from_error("owner/other", "./repo", &"boom");
+
+        assert_eq!(record_run(&config, &failure).unwrap(), None);
+        assert_eq!(record_run(&config, &other).unwrap(), None);
+        assert_eq!(record_run(&config, &failure).unwrap(), Some(2));
+        assert_eq!(record_run(&config, &failure).unwrap(), None);
+        assert_eq!(record_run(&config, &failure).unwrap(), Some(4));
+
+        let mut success = failure.clone();
+        success.status = RunStatus::Success;
+        assert_eq!(record_run(&config, &success).unwrap(), None);
+        assert_eq!(record_run(&config, &failure).unwrap(), None);
+        assert_eq!(record_run(&config, &other).unwrap(), Some(2));
+    }
+
+    #[test]
+    fn test_render() {
+        let notification = Notification::from_error("owner/repo", "s3://bucket", &"denied");
+        let (subject, body) = render(&notification, 3);
+        assert_eq!(
+            subject,
+            "[meso-forge-mirror] owner/repo -> s3://bucket failed 3 times in a row"
+        );
+        assert!(body.contains("Error: denied"));
+        assert!(body.contains("Consecutive failed runs: 3"));
+    }
+
+    #[cfg(feature = "email")]
+    #[tokio::test]
+    async fn test_notify_failures_sends_over_smtp() {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // Minimal SMTP server capturing the message data
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            writer.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+            let mut data = String::new();
+            let mut in_data = false;
+            while let Some(line) = lines.next_line().await.unwrap() {
+                if in_data {
+                    if line == "." {
+                        in_data = false;
+                        writer.write_all(b"250 OK\r\n").await.unwrap();
+                    } else {
+                        data.push_str(&line);
+                        data.push('\n');
+                    }
+                    continue;
+                }
+                let reply: &[u8] = match line.split_whitespace().next().unwrap_or("") {
+                    "EHLO" | "HELO" => b"250 localhost\r\n",
+                    "DATA" => {
+                        in_data = true;
+                        b"354 End data with <CR><LF>.<CR><LF>\r\n"
+                    }
+                    "QUIT" => {
+                        writer.write_all(b"221 Bye\r\n").await.unwrap();
+                        break;
+                    }
+                    _ => b"250 OK\r\n",
+                };
+                writer.write_all(reply).await.unwrap();
+            }
+            data
+        });
+
+        let temp_dir = TempDir::new().unwrap();
+        let mut config = config(&temp_dir, 1);
+        config.smtp_port = port;
+        let notification = Notification::from_error("owner/repo", "./repo", &"boom");
+
+        assert!(notify_failures(&config, &notification).await.unwrap());
+        let data = server.await.unwrap();
+        assert!(data.contains("Subject: [meso-forge-mirror] owner/repo -> ./repo failed 1 time"));
+        assert!(data.contains("To: ops@example.com"));
+        assert!(data.contains("Error: boom"));
+    }
+}
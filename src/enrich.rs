@@ -0,0 +1,179 @@
+//! Metadata enrichment from an upstream channel
+//!
+//! Packages built by hand or by older tools may carry an `info/index.json`
+//! without a `license` or `depends`, and a package whose metadata could not be
+//! read at all has neither. With `enrich_from` (or `--enrich-from`) naming an
+//! upstream channel, such a package is looked up by filename in the repodata of
+//! its subdir there, and when the upstream record lists the same sha256, the
+//! fields the package lacks are taken from it before the package is admitted
+//! and recorded. The repodata of each subdir is read once per run, when the
+//! first package of that subdir needs it.
+
+use reqwest::Client;
+use std::collections::HashMap;
+use tracing::{debug, info, warn};
+
+use crate::conda_package::ProcessedPackage;
+use crate::error::MirrorError;
+use crate::sbom::{load_channel, ChannelPackage};
+
+/// Upstream repodata read so far, by subdir and filename
+#[derive(Debug, Clone)]
+pub struct MetadataUpstream {
+    channel: String,
+    client: Client,
+    subdirs: HashMap<String, HashMap<String, ChannelPackage>>,
+}
+
+impl MetadataUpstream {
+    pub fn new(client: Client, channel: impl Into<String>) -> Self {
+        Self {
+            channel: channel.into(),
+            client,
+            subdirs: HashMap::new(),
+        }
+    }
+
+    /// Fill in the fields `package` lacks from its upstream record, returning their names
+    pub async fn enrich(&mut self, package: &mut ProcessedPackage) -> Vec<&'static str> {
+        if !lacks_metadata(package) {
+            return Vec::new();
+        }
+        let subdir = package.platform.to_string();
+        let record = self.records(&subdir).await.get(&package.filename).cloned();
+        let Some(record) = record else {
+            debug!("{} is not in {}/{}", package.filename, self.channel, subdir);
+            return Vec::new();
+        };
+        if record.sha256.as_deref() != Some(package.sha256.as_str()) {
+            debug!(
+                "{} in {} has a different sha256; leaving its metadata as it is",
+                package.filename, self.channel
+            );
+            return Vec::new();
+        }
+
+        let filled = merge(package, &record);
+        if !filled.is_empty() {
+            info!(
+                "Took {} of {} from {}",
+                filled.join(" and "),
+                package.filename,
+                self.channel
+            );
+        }
+        filled
+    }
+
+    /// The upstream records of `subdir`, read on first use
+    async fn records(&mut self, subdir: &str) -> &HashMap<String, ChannelPackage> {
+        if !self.subdirs.contains_key(subdir) {
+            let records =
+                match load_channel(&self.client, &self.channel, &[subdir.to_string()]).await {
+                    Ok(packages) => packages
+                        .into_iter()
+                        .map(|package| (package.filename.clone(), package))
+                        .collect(),
+                    Err(MirrorError::NotFound(_)) => HashMap::new(),
+                    Err(e) => {
+                        warn!(
+                            "Cannot read {}/{} to enrich package metadata: {}",
+                            self.channel, subdir, e
+                        );
+                        HashMap::new()
+                    }
+                };
+            self.subdirs.insert(subdir.to_string(), records);
+        }
+        &self.subdirs[subdir]
+    }
+}
+
+/// Whether the package has no license or no dependencies recorded
+fn lacks_metadata(package: &ProcessedPackage) -> bool {
+    let metadata = &package.metadata;
+    metadata
+        .license
+        .as_deref()
+        .is_none_or(|license| license.trim().is_empty())
+        || metadata.depends.is_empty()
+}
+
+/// Copy the fields the package lacks from `record`, returning their names
+fn merge(package: &mut ProcessedPackage, record: &ChannelPackage) -> Vec<&'static str> {
+    let metadata = &mut package.metadata;
+    let mut filled = Vec::new();
+    let license = record
+        .license
+        .as_deref()
+        .map(str::trim)
+        .filter(|license| !license.is_empty());
+    if let (true, Some(license)) = (
+        metadata
+            .license
+            .as_deref()
+            .is_none_or(|license| license.trim().is_empty()),
+        license,
+    ) {
+        metadata.license = Some(license.to_string());
+        filled.push("license");
+    }
+    if metadata.depends.is_empty() && !record.depends.is_empty() {
+        metadata.depends = record.depends.clone();
+        filled.push("depends");
+    }
+    filled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conda_package::CondaPackageHandler;
+    use crate::test_support::PackageFixture;
+
+    #[tokio::test]
+    async fn test_enrich_from_local_channel() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let fixture = PackageFixture::new("rb-asciidoctor", "2.0.20").subdir("noarch");
+        let content = fixture.to_conda();
+        let mut handler = CondaPackageHandler::new();
+        let mut package = handler
+            .process_package(content.clone(), &fixture.conda_filename())
+            .await
+            .unwrap();
+        package.metadata.license = None;
+        package.metadata.depends.clear();
+
+        let subdir = temp_dir.path().join("noarch");
+        std::fs::create_dir_all(&subdir).unwrap();
+        let repodata = serde_json::json!({
+            "packages.conda": {
+                fixture.conda_filename(): {
+                    "name": "rb-asciidoctor",
+                    "version": "2.0.20",
+                    "build": "0",
+                    "subdir": "noarch",
+                    "license": "MIT",
+                    "depends": ["ruby >=3.2"],
+                    "sha256": package.sha256,
+                }
+            }
+        });
+        std::fs::write(subdir.join("repodata.json"), repodata.to_string()).unwrap();
+
+        let mut upstream = MetadataUpstream::new(Client::new(), temp_dir.path().to_string_lossy());
+        assert_eq!(
+            upstream.enrich(&mut package).await,
+            vec!["license", "depends"]
+        );
+        assert_eq!(package.metadata.license.as_deref(), Some("MIT"));
+        assert_eq!(package.metadata.depends, vec!["ruby >=3.2".to_string()]);
+
+        // Complete metadata is left alone, and so is a package with other content
+        assert!(upstream.enrich(&mut package).await.is_empty());
+        package.metadata.license = None;
+        package.sha256 = "0".repeat(64);
+        assert!(upstream.enrich(&mut package).await.is_empty());
+        assert_eq!(package.metadata.license, None);
+    }
+}
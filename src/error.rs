@@ -0,0 +1,366 @@
+//! Error types for the mirroring library
+//!
+//! Library functions return [`MirrorError`] so that callers can match on the
+//! class of failure (authentication, missing resources, rate limiting, corrupt
+//! data, target I/O) and decide how to retry or report it themselves.
+
+use reqwest::StatusCode;
+use std::path::PathBuf;
+use std::time::Duration;
+use thiserror::Error;
+
+use crate::report::{MirrorReport, SkippedItem};
+
+/// Result type used throughout the library
+pub type Result<T, E = MirrorError> = std::result::Result<T, E>;
+
+/// Classified failure raised while mirroring packages
+#[derive(Debug, Error)]
+pub enum MirrorError {
+    /// Credentials were missing, invalid, or lacked the required permissions
+    #[error("Authentication failed: {0}")]
+    AuthError(String),
+
+    /// A requested resource (artifact, build, file, package) does not exist
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    /// The remote service throttled the request
+    #[error("Rate limited: {message}")]
+    RateLimited {
+        message: String,
+        retry_after: Option<Duration>,
+    },
+
+    /// Requests to a host are skipped because it kept failing
+    #[error(
+        "Skipping {host}: {failures} consecutive failures, retrying the host in {}s",
+        retry_in.as_secs()
+    )]
+    HostUnavailable {
+        host: String,
+        failures: u32,
+        retry_in: Duration,
+    },
+
+    /// A download ended with a different size than announced
+    #[error(
+        "Truncated download from {source_url}: expected {expected} bytes, received {received}"
+    )]
+    Truncated {
+        source_url: String,
+        expected: u64,
+        received: u64,
+    },
+
+    /// Package or archive content could not be parsed
+    #[error("Corrupt data: {0}")]
+    Corrupt(String),
+
+    /// An archive stayed corrupt after a re-download and was moved aside
+    #[error("Corrupt archive quarantined at {path}: {reason}")]
+    Quarantined { path: PathBuf, reason: String },
+
+    /// A part of a source, such as an expired artifact, was skipped
+    #[error("Skipped {} {}: {}", .0.kind, .0.name, .0.reason)]
+    Skipped(SkippedItem),
+
+    /// Writing to the target repository failed
+    #[error("Target I/O error at {path}: {source}")]
+    TargetIo {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// A file system lacks the free space a package needs, checked before it is fetched
+    #[error(
+        "Not enough disk space for {purpose} at {path}: {needed} bytes needed, {available} available (see scratch_dir and min_free_space)"
+    )]
+    InsufficientSpace {
+        path: PathBuf,
+        purpose: String,
+        needed: u64,
+        available: u64,
+    },
+
+    /// The target already holds a different build under the same filename
+    #[error(
+        "Checksum conflict for {filename} at {location}: existing sha256 {existing_sha256}, new sha256 {new_sha256} (use --force-replace to overwrite)"
+    )]
+    ChecksumConflict {
+        filename: String,
+        location: String,
+        existing_sha256: String,
+        new_sha256: String,
+    },
+
+    /// Strict platform mode refused a package whose platform could only be guessed
+    #[error(
+        "Refusing {filename}: no subdir in package metadata, platform {guessed} was guessed (--strict-platform)"
+    )]
+    GuessedPlatform { filename: String, guessed: String },
+
+    /// The package failed the admission policy of the target
+    #[error("{filename} rejected by policy: {reasons}")]
+    PolicyRejected {
+        filename: String,
+        /// Platform subdir the package was detected for
+        platform: String,
+        reasons: String,
+        /// The license rules ask for the package to be kept for review
+        quarantine: bool,
+        /// Its size is the only reason the package was rejected
+        oversized: bool,
+    },
+
+    /// A package matched none of the match specs given with `--spec`
+    #[error("{filename} matches none of the specs {specs}")]
+    SpecMismatch { filename: String, specs: String },
+
+    /// A package failed the extraction smoke test of `verify_extraction`
+    #[error("{filename} failed the extraction check: {reason}")]
+    ExtractionFailed { filename: String, reason: String },
+
+    /// A source URL is on a host outside `allowed_source_hosts`
+    #[error("Refusing to download {source_url}: host {host} is not in allowed_source_hosts")]
+    SourceNotAllowed { source_url: String, host: String },
+
+    /// The same filename was seen twice in one run with different detected platforms
+    #[error(
+        "Platform conflict for {filename}: first processed as {first}, now detected as {second} (see --duplicate-platform-policy)"
+    )]
+    PlatformConflict {
+        filename: String,
+        first: String,
+        second: String,
+    },
+
+    /// A package filename does not match the name, version and build in its metadata
+    #[error("Invalid package filename {filename}: {reason}")]
+    InvalidFilename { filename: String, reason: String },
+
+    /// A source had no packages for platforms the run requires
+    #[error(
+        "No packages for required platforms {} in {source_name} (found: {})",
+        missing.join(", "),
+        if found.is_empty() { "none".to_string() } else { found.join(", ") }
+    )]
+    MissingPlatforms {
+        source_name: String,
+        missing: Vec<String>,
+        found: Vec<String>,
+    },
+
+    /// An environment cannot be solved with the packages of a channel
+    #[cfg_attr(not(feature = "solve"), allow(dead_code))]
+    #[error("Cannot solve the environment for {platform}: {message}")]
+    Unsolvable { platform: String, message: String },
+
+    /// Installing a solved environment into a prefix failed
+    #[cfg_attr(not(feature = "solve"), allow(dead_code))]
+    #[error("Install failed: {0}")]
+    InstallFailed(String),
+
+    /// The run was stopped by an interrupt after finishing in-flight work
+    #[error(
+        "Interrupted: {completed} packages mirrored, {pending} pending (resume state saved to {state_file})"
+    )]
+    Interrupted {
+        completed: usize,
+        pending: usize,
+        state_file: String,
+        /// Results of the packages handled before the interrupt
+        report: Box<MirrorReport>,
+    },
+
+    /// Producing a detached signature failed
+    #[error("Signing failed: {0}")]
+    Signing(String),
+
+    /// Uploading to a remote target repository failed
+    #[error("Target upload failed: {0}")]
+    TargetUpload(String),
+
+    /// A remote API answered with an unexpected status code
+    #[error("HTTP {status}: {message}")]
+    Http { status: u16, message: String },
+
+    /// A remote API answered with a body that could not be understood
+    #[error("Invalid response: {0}")]
+    InvalidResponse(String),
+
+    /// Arguments or configuration supplied by the caller are invalid
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+
+    /// Network-level failure (connection, TLS, timeout)
+    #[error("Network error: {0}")]
+    Network(#[from] reqwest::Error),
+
+    /// Local I/O failure outside of the target repository
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// Reading or writing the state database failed
+    #[cfg_attr(not(feature = "state-db"), allow(dead_code))]
+    #[error("State database error: {0}")]
+    StateDb(String),
+
+    /// Any other failure
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl MirrorError {
+    /// Classify a non-success HTTP response
+    pub fn from_status(status: StatusCode, context: &str, body: &str) -> Self {
+        let message = if body.is_empty() {
+            context.to_string()
+        } else {
+            format!("{} - {}", context, body)
+        };
+
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                MirrorError::AuthError(format!("{}: {}", status, message))
+            }
+            StatusCode::NOT_FOUND | StatusCode::GONE => {
+                MirrorError::NotFound(format!("{}: {}", status, message))
+            }
+            StatusCode::TOO_MANY_REQUESTS => MirrorError::RateLimited {
+                message,
+                retry_after: None,
+            },
+            _ => MirrorError::Http {
+                status: status.as_u16(),
+                message,
+            },
+        }
+    }
+
+    /// Build an error for a failed write to the target repository
+    pub fn target_io(path: impl Into<PathBuf>, source: std::io::Error) -> Self {
+        MirrorError::TargetIo {
+            path: path.into(),
+            source,
+        }
+    }
+
+    /// Whether retrying the same operation may succeed
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            MirrorError::RateLimited { .. }
+            | MirrorError::Truncated { .. }
+            | MirrorError::Network(_) => true,
+            MirrorError::Http { status, .. } => *status >= 500,
+            _ => false,
+        }
+    }
+
+    /// Results of the packages an interrupted run handled before it stopped
+    pub fn partial_report(&self) -> Option<&MirrorReport> {
+        match self {
+            MirrorError::Interrupted { report, .. } => Some(report),
+            _ => None,
+        }
+    }
+}
+
+impl From<anyhow::Error> for MirrorError {
+    fn from(err: anyhow::Error) -> Self {
+        match err.downcast::<MirrorError>() {
+            Ok(mirror_error) => mirror_error,
+            Err(err) => MirrorError::Other(err),
+        }
+    }
+}
+
+#[cfg(feature = "state-db")]
+impl From<rusqlite::Error> for MirrorError {
+    fn from(err: rusqlite::Error) -> Self {
+        MirrorError::StateDb(err.to_string())
+    }
+}
+
+impl From<zip::result::ZipError> for MirrorError {
+    fn from(err: zip::result::ZipError) -> Self {
+        MirrorError::Corrupt(format!("Invalid ZIP archive: {}", err))
+    }
+}
+
+impl From<serde_json::Error> for MirrorError {
+    fn from(err: serde_json::Error) -> Self {
+        MirrorError::InvalidResponse(err.to_string())
+    }
+}
+
+impl From<serde_yaml::Error> for MirrorError {
+    fn from(err: serde_yaml::Error) -> Self {
+        MirrorError::Other(err.into())
+    }
+}
+
+impl From<regex::Error> for MirrorError {
+    fn from(err: regex::Error) -> Self {
+        MirrorError::InvalidInput(format!("Invalid regular expression: {}", err))
+    }
+}
+
+impl From<url::ParseError> for MirrorError {
+    fn from(err: url::ParseError) -> Self {
+        MirrorError::InvalidInput(format!("Invalid URL: {}", err))
+    }
+}
+
+impl From<reqwest::header::InvalidHeaderValue> for MirrorError {
+    fn from(err: reqwest::header::InvalidHeaderValue) -> Self {
+        MirrorError::InvalidInput(format!("Invalid header value: {}", err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_status_classification() {
+        assert!(matches!(
+            MirrorError::from_status(StatusCode::UNAUTHORIZED, "list", ""),
+            MirrorError::AuthError(_)
+        ));
+        assert!(matches!(
+            MirrorError::from_status(StatusCode::FORBIDDEN, "list", ""),
+            MirrorError::AuthError(_)
+        ));
+        assert!(matches!(
+            MirrorError::from_status(StatusCode::NOT_FOUND, "list", ""),
+            MirrorError::NotFound(_)
+        ));
+        assert!(matches!(
+            MirrorError::from_status(StatusCode::TOO_MANY_REQUESTS, "list", ""),
+            MirrorError::RateLimited { .. }
+        ));
+        assert!(matches!(
+            MirrorError::from_status(StatusCode::BAD_GATEWAY, "list", "upstream"),
+            MirrorError::Http { status: 502, .. }
+        ));
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        assert!(MirrorError::from_status(StatusCode::SERVICE_UNAVAILABLE, "x", "").is_retryable());
+        assert!(MirrorError::from_status(StatusCode::TOO_MANY_REQUESTS, "x", "").is_retryable());
+        assert!(!MirrorError::from_status(StatusCode::UNAUTHORIZED, "x", "").is_retryable());
+        assert!(!MirrorError::Corrupt("bad".to_string()).is_retryable());
+    }
+
+    #[test]
+    fn test_anyhow_round_trip_preserves_class() {
+        let err: anyhow::Error = MirrorError::NotFound("artifact 1".to_string()).into();
+        assert!(matches!(MirrorError::from(err), MirrorError::NotFound(_)));
+
+        let err = anyhow::anyhow!("something else");
+        assert!(matches!(MirrorError::from(err), MirrorError::Other(_)));
+    }
+}
@@ -0,0 +1,309 @@
+//! Smoke test of packages before they are published
+//!
+//! Reading `info/index.json` only touches the info part of a package, so a
+//! truncated or corrupt CI artifact can still make it into the channel. With
+//! `verify_extraction` set, every package is fully extracted to a temporary
+//! directory under `scratch_dir` before it is uploaded, and each file its
+//! `info/paths.json` lists must be there with the recorded size and sha256.
+//!
+//! The extracted files are also checked for relocation problems, which only
+//! show once the package is installed: prefix placeholders that are missing
+//! from their file, binary placeholders shorter than the install prefixes
+//! conda tools use, and build prefixes in files without a prefix record.
+//! These are reported as warnings, not failures.
+
+use rattler_conda_types::package::{FileMode, PathType, PathsEntry, PathsJson};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+use crate::scratch;
+
+/// Length of the binary prefix placeholder of conda-build and rattler-build
+///
+/// Binary files can only be patched with prefixes up to the length of their
+/// placeholder, so packages built with a shorter one break in longer prefixes.
+pub const BINARY_PLACEHOLDER_LENGTH: usize = 255;
+
+/// Parts of the build prefixes of conda-build and rattler-build
+const BUILD_PREFIX_MARKERS: &[&str] = &["_placehold_placehold", "anaconda1anaconda2anaconda3"];
+
+/// What the extraction check found in an intact package
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verification {
+    /// Number of paths checked
+    pub paths: usize,
+    /// Why the package may not work where it is installed
+    pub relocation_warnings: Vec<String>,
+}
+
+/// Extract a package and check its files against `info/paths.json`
+///
+/// Returns what was checked, or why the package is broken.
+pub fn verify(
+    content: &[u8],
+    filename: &str,
+    scratch_dir: Option<&Path>,
+) -> std::result::Result<Verification, String> {
+    let dir =
+        scratch::tempdir(scratch_dir).map_err(|e| format!("no temporary directory: {}", e))?;
+    let extracted = if filename.ends_with(".conda") {
+        rattler_package_streaming::read::extract_conda_via_streaming(content, dir.path())
+    } else if filename.ends_with(".tar.bz2") {
+        rattler_package_streaming::read::extract_tar_bz2(content, dir.path())
+    } else {
+        return Err("not a conda package".to_string());
+    };
+    extracted.map_err(|e| format!("extraction failed: {}", e))?;
+
+    if !dir.path().join("info/index.json").is_file() {
+        return Err("no info/index.json".to_string());
+    }
+    let paths = PathsJson::from_package_directory_with_deprecated_fallback(dir.path())
+        .map_err(|e| format!("unreadable info/paths.json: {}", e))?;
+    for entry in &paths.paths {
+        check_path(dir.path(), entry).map_err(|problem| {
+            format!(
+                "{} {} (info/paths.json)",
+                entry.relative_path.display(),
+                problem
+            )
+        })?;
+    }
+    Ok(Verification {
+        paths: paths.paths.len(),
+        relocation_warnings: relocation_warnings(dir.path(), &paths),
+    })
+}
+
+/// Relocation problems of the files of an extracted package
+pub fn relocation_warnings(root: &Path, paths: &PathsJson) -> Vec<String> {
+    let placeholders: Vec<&str> = paths
+        .paths
+        .iter()
+        .filter_map(|entry| entry.prefix_placeholder.as_ref())
+        .map(|prefix| prefix.placeholder.as_str())
+        .collect();
+
+    let mut warnings = Vec::new();
+    for entry in &paths.paths {
+        if entry.path_type != PathType::HardLink {
+            continue;
+        }
+        let Ok(content) = std::fs::read(root.join(&entry.relative_path)) else {
+            continue;
+        };
+        let path = entry.relative_path.display();
+        match &entry.prefix_placeholder {
+            Some(prefix) => {
+                if !contains(&content, &prefix.placeholder) {
+                    warnings.push(format!(
+                        "{} does not contain its prefix placeholder {}",
+                        path, prefix.placeholder
+                    ));
+                } else if prefix.file_mode == FileMode::Binary
+                    && prefix.placeholder.len() < BINARY_PLACEHOLDER_LENGTH
+                {
+                    warnings.push(format!(
+                        "{} has a binary prefix placeholder of {} characters, not {}, so it breaks in longer install prefixes",
+                        path,
+                        prefix.placeholder.len(),
+                        BINARY_PLACEHOLDER_LENGTH
+                    ));
+                }
+            }
+            None => {
+                let build_prefix = placeholders
+                    .iter()
+                    .copied()
+                    .chain(BUILD_PREFIX_MARKERS.iter().copied())
+                    .any(|marker| contains(&content, marker));
+                if build_prefix {
+                    warnings.push(format!(
+                        "{} contains the build prefix but has no prefix record, so it is not relocated",
+                        path
+                    ));
+                }
+            }
+        }
+    }
+    warnings
+}
+
+fn contains(content: &[u8], needle: &str) -> bool {
+    let needle = needle.as_bytes();
+    !needle.is_empty() && content.windows(needle.len()).any(|window| window == needle)
+}
+
+/// Whether an extracted path matches its `info/paths.json` entry
+fn check_path(root: &Path, entry: &PathsEntry) -> std::result::Result<(), String> {
+    let path = root.join(&entry.relative_path);
+    let metadata = std::fs::symlink_metadata(&path).map_err(|_| "is missing".to_string())?;
+    match entry.path_type {
+        PathType::Directory if !metadata.is_dir() => return Err("is not a directory".to_string()),
+        PathType::SoftLink if !metadata.is_symlink() => return Err("is not a symlink".to_string()),
+        PathType::HardLink if !metadata.is_file() => return Err("is not a file".to_string()),
+        PathType::HardLink => {}
+        _ => return Ok(()),
+    }
+
+    if let Some(expected) = entry.size_in_bytes {
+        if metadata.len() != expected {
+            return Err(format!(
+                "has {} bytes, {} expected",
+                metadata.len(),
+                expected
+            ));
+        }
+    }
+    if let Some(expected) = &entry.sha256 {
+        let content = std::fs::read(&path).map_err(|e| format!("is unreadable: {}", e))?;
+        let actual = format!("{:x}", Sha256::digest(&content));
+        let expected = format!("{:x}", expected);
+        if actual != expected {
+            return Err(format!("has sha256 {}, {} expected", actual, expected));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::PackageFixture;
+    use std::io::Write;
+
+    /// A `.tar.bz2` package whose `info/paths.json` lists `listed`
+    fn package_listing(listed: serde_json::Value) -> Vec<u8> {
+        package_with(
+            &[listed],
+            vec![("share/broken/README", b"broken 1.0\n".to_vec())],
+        )
+    }
+
+    /// A `.tar.bz2` package holding `payload`, whose `info/paths.json` lists `listed`
+    fn package_with(listed: &[serde_json::Value], payload: Vec<(&str, Vec<u8>)>) -> Vec<u8> {
+        let mut files = vec![
+            (
+                "info/index.json",
+                br#"{"name": "broken", "version": "1.0", "build": "0", "subdir": "noarch"}"#
+                    .to_vec(),
+            ),
+            (
+                "info/paths.json",
+                serde_json::json!({ "paths": listed, "paths_version": 1 })
+                    .to_string()
+                    .into_bytes(),
+            ),
+        ];
+        files.extend(payload);
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, path, &content[..])
+                .unwrap();
+        }
+        let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+        encoder.write_all(&builder.into_inner().unwrap()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_verify() {
+        let fixture = PackageFixture::new("intact", "1.0");
+        assert_eq!(
+            verify(&fixture.to_conda(), &fixture.conda_filename(), None).map(|v| v.paths),
+            Ok(1)
+        );
+        assert_eq!(
+            verify(&fixture.to_tar_bz2(), &fixture.tar_bz2_filename(), None).map(|v| v.paths),
+            Ok(1)
+        );
+
+        let conda = fixture.to_conda();
+        let truncated = verify(&conda[..conda.len() / 2], &fixture.conda_filename(), None);
+        assert!(truncated.unwrap_err().starts_with("extraction failed"));
+
+        let readme = "share/broken/README";
+        let intact = package_listing(serde_json::json!({
+            "_path": readme, "path_type": "hardlink", "size_in_bytes": 11
+        }));
+        assert_eq!(
+            verify(&intact, "broken-1.0-0.tar.bz2", None),
+            Ok(Verification {
+                paths: 1,
+                relocation_warnings: Vec::new()
+            })
+        );
+
+        let missing = package_listing(serde_json::json!({
+            "_path": "share/broken/LICENSE", "path_type": "hardlink"
+        }));
+        assert_eq!(
+            verify(&missing, "broken-1.0-0.tar.bz2", None),
+            Err("share/broken/LICENSE is missing (info/paths.json)".to_string())
+        );
+        let resized = package_listing(serde_json::json!({
+            "_path": readme, "path_type": "hardlink", "size_in_bytes": 12
+        }));
+        assert_eq!(
+            verify(&resized, "broken-1.0-0.tar.bz2", None),
+            Err("share/broken/README has 11 bytes, 12 expected (info/paths.json)".to_string())
+        );
+        let altered = package_listing(serde_json::json!({
+            "_path": readme, "path_type": "hardlink", "sha256": "00".repeat(32)
+        }));
+        assert!(verify(&altered, "broken-1.0-0.tar.bz2", None)
+            .unwrap_err()
+            .contains("has sha256"));
+    }
+
+    #[test]
+    fn test_relocation_warnings() {
+        let short = format!("/opt/build_env_placehold{}", "_".repeat(56));
+        let long = format!("/opt/host_env{}", "_placehold".repeat(25))[..255].to_string();
+        let binary = |placeholder: &str| {
+            let mut content = b"\x7fELF".to_vec();
+            content.extend_from_slice(placeholder.as_bytes());
+            content.push(0);
+            content
+        };
+        let listed = [
+            serde_json::json!({
+                "_path": "lib/libshort.so", "path_type": "hardlink",
+                "file_mode": "binary", "prefix_placeholder": short
+            }),
+            serde_json::json!({
+                "_path": "lib/liblong.so", "path_type": "hardlink",
+                "file_mode": "binary", "prefix_placeholder": long
+            }),
+            serde_json::json!({
+                "_path": "bin/tool", "path_type": "hardlink",
+                "file_mode": "text", "prefix_placeholder": long
+            }),
+            serde_json::json!({ "_path": "lib/libunrecorded.so", "path_type": "hardlink" }),
+            serde_json::json!({ "_path": "share/broken/README", "path_type": "hardlink" }),
+        ];
+        let package = package_with(
+            &listed,
+            vec![
+                ("lib/libshort.so", binary(&short)),
+                ("lib/liblong.so", binary(&long)),
+                ("bin/tool", b"#!/bin/sh\nexec python\n".to_vec()),
+                ("lib/libunrecorded.so", binary(&long)),
+                ("share/broken/README", b"broken 1.0\n".to_vec()),
+            ],
+        );
+
+        let verification = verify(&package, "broken-1.0-0.tar.bz2", None).unwrap();
+        assert_eq!(verification.paths, 5);
+        let warnings = verification.relocation_warnings;
+        assert_eq!(warnings.len(), 3, "{:?}", warnings);
+        assert!(warnings[0].starts_with("lib/libshort.so has a binary prefix placeholder of 80"));
+        assert!(warnings[1].starts_with("bin/tool does not contain its prefix placeholder"));
+        assert!(warnings[2].starts_with("lib/libunrecorded.so contains the build prefix"));
+    }
+}
@@ -1,5 +1,3 @@
-use anyhow::{anyhow, Result};
-
 use comfy_table::presets::NOTHING;
 use comfy_table::{Attribute, Cell, ContentArrangement, Table};
 use reqwest::Client;
@@ -8,6 +6,17 @@ use serde::{Deserialize, Serialize};
 use tracing::{info, warn};
 
 use crate::config::Config;
+use crate::download::verify_download_size;
+use crate::error::{MirrorError, Result};
+use crate::listing;
+use crate::listing_cache::ListingCache;
+use crate::politeness;
+
+/// Base URL of the public GitHub REST API, the default `github_api_url`
+pub const GITHUB_API_URL: &str = "https://api.github.com";
+
+/// Upper bound on the alternatives suggested for expired artifacts
+const MAX_EXPIRED_ALTERNATIVES: usize = 10;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct GitHubArtifact {
@@ -38,30 +47,107 @@ pub struct GitHubArtifactsResponse {
     pub artifacts: Vec<GitHubArtifact>,
 }
 
+/// Workflow runs of one commit, as `/actions/runs` lists them
+#[derive(Debug, Deserialize)]
+struct WorkflowRunsResponse {
+    workflow_runs: Vec<WorkflowRunSummary>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WorkflowRunSummary {
+    id: u64,
+}
+
+/// A pull request, of which only the commit it currently points at matters
+#[derive(Debug, Deserialize)]
+struct PullRequest {
+    head: PullRequestHead,
+}
+
+#[derive(Debug, Deserialize)]
+struct PullRequestHead {
+    sha: String,
+}
+
+/// A published release and the files attached to it
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GitHubRelease {
+    pub id: u64,
+    pub tag_name: String,
+    pub html_url: String,
+    pub assets: Vec<GitHubReleaseAsset>,
+}
+
+/// A file attached to a release
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GitHubReleaseAsset {
+    pub id: u64,
+    pub name: String,
+    pub size: u64,
+    /// API address of the asset, which serves its content with `Accept: application/octet-stream`
+    pub url: String,
+    pub browser_download_url: String,
+}
+
+/// Headers of the artifact table, whose keys `--columns` and `--sort-by` take
+const ARTIFACT_COLUMNS: &[&str] = &["ID", "Name", "Size", "Created", "Expires", "Expired"];
+
 pub struct GitHubClient {
     client: Client,
     token: Option<String>,
+    api_base: String,
+    listing_cache: Option<ListingCache>,
 }
 
 impl GitHubClient {
     pub fn new(config: &Config) -> Result<Self> {
         let client = Client::builder()
             .timeout(std::time::Duration::from_secs(config.timeout_seconds))
-            .user_agent("meso-forge-mirror/0.1.0")
+            .user_agent(politeness::user_agent(config))
             .build()?;
 
-        Ok(Self {
+        Ok(Self::with_client(client, config))
+    }
+
+    /// Use a caller-supplied client, e.g. one with proxies, custom TLS roots or tracing
+    ///
+    /// GitHub rejects API requests without a `User-Agent`, so the client should set one.
+    pub fn with_client(client: Client, config: &Config) -> Self {
+        Self {
             client,
             token: config.github_token.clone(),
-        })
+            api_base: config.github_api_url.trim_end_matches('/').to_string(),
+            listing_cache: ListingCache::from_config(config),
+        }
+    }
+
+    /// Send API requests to another base URL, e.g. GitHub Enterprise or a mock server
+    #[allow(dead_code)]
+    pub fn with_api_base(mut self, api_base: impl Into<String>) -> Self {
+        self.api_base = api_base.into().trim_end_matches('/').to_string();
+        self
     }
 
     /// List all artifacts for a repository
     pub async fn list_artifacts(&self, owner: &str, repo: &str) -> Result<Vec<GitHubArtifact>> {
         let url = format!(
-            "https://api.github.com/repos/{}/{}/actions/artifacts",
-            owner, repo
+            "{}/repos/{}/{}/actions/artifacts",
+            self.api_base, owner, repo
         );
+        let cache_key = ListingCache::key(&url, self.token.is_some());
+        if let Some(artifacts) = self
+            .listing_cache
+            .as_ref()
+            .and_then(|cache| cache.get::<Vec<GitHubArtifact>>(&cache_key))
+        {
+            info!(
+                "Using {} cached artifacts for {}/{}",
+                artifacts.len(),
+                owner,
+                repo
+            );
+            return Ok(artifacts);
+        }
 
         let mut request = self.client.get(&url);
 
@@ -75,11 +161,7 @@ impl GitHubClient {
         let response = request.send().await?;
 
         if !response.status().is_success() {
-            return Err(anyhow!(
-                "Failed to list GitHub artifacts: {} - {}",
-                response.status(),
-                response.text().await.unwrap_or_default()
-            ));
+            return Err(github_status_error(response, "Failed to list GitHub artifacts").await);
         }
 
         let artifacts_response: GitHubArtifactsResponse = response.json().await?;
@@ -88,6 +170,9 @@ impl GitHubClient {
             "Found {} artifacts for {}/{}",
             artifacts_response.total_count, owner, repo
         );
+        if let Some(cache) = &self.listing_cache {
+            cache.put(&cache_key, &artifacts_response.artifacts);
+        }
 
         Ok(artifacts_response.artifacts)
     }
@@ -100,8 +185,8 @@ impl GitHubClient {
         artifact_id: u64,
     ) -> Result<GitHubArtifact> {
         let url = format!(
-            "https://api.github.com/repos/{}/{}/actions/artifacts/{}",
-            owner, repo, artifact_id
+            "{}/repos/{}/{}/actions/artifacts/{}",
+            self.api_base, owner, repo, artifact_id
         );
 
         let mut request = self.client.get(&url);
@@ -116,18 +201,124 @@ impl GitHubClient {
         let response = request.send().await?;
 
         if !response.status().is_success() {
-            return Err(anyhow!(
-                "Failed to get GitHub artifact {}: {} - {}",
-                artifact_id,
-                response.status(),
-                response.text().await.unwrap_or_default()
-            ));
+            return Err(github_status_error(
+                response,
+                &format!("Failed to get GitHub artifact {}", artifact_id),
+            )
+            .await);
         }
 
         let artifact: GitHubArtifact = response.json().await?;
         Ok(artifact)
     }
 
+    /// Get the commit a pull request currently points at
+    pub async fn get_pull_request_head(
+        &self,
+        owner: &str,
+        repo: &str,
+        number: u64,
+    ) -> Result<String> {
+        let url = format!(
+            "{}/repos/{}/{}/pulls/{}",
+            self.api_base, owner, repo, number
+        );
+
+        let mut request = self.client.get(&url);
+
+        if let Some(token) = &self.token {
+            request = request.header("Authorization", format!("Bearer {}", token));
+        }
+
+        request = request.header("Accept", "application/vnd.github+json");
+        request = request.header("X-GitHub-Api-Version", "2022-11-28");
+
+        let response = request.send().await?;
+
+        if !response.status().is_success() {
+            return Err(github_status_error(
+                response,
+                &format!("Failed to get pull request #{}", number),
+            )
+            .await);
+        }
+
+        let pull_request: PullRequest = response.json().await?;
+        Ok(pull_request.head.sha)
+    }
+
+    /// List the artifacts of every workflow run of a commit
+    pub async fn list_commit_artifacts(
+        &self,
+        owner: &str,
+        repo: &str,
+        head_sha: &str,
+    ) -> Result<Vec<GitHubArtifact>> {
+        let url = format!(
+            "{}/repos/{}/{}/actions/runs?head_sha={}",
+            self.api_base, owner, repo, head_sha
+        );
+
+        let mut request = self.client.get(&url);
+
+        if let Some(token) = &self.token {
+            request = request.header("Authorization", format!("Bearer {}", token));
+        }
+
+        request = request.header("Accept", "application/vnd.github+json");
+        request = request.header("X-GitHub-Api-Version", "2022-11-28");
+
+        let response = request.send().await?;
+
+        if !response.status().is_success() {
+            return Err(github_status_error(
+                response,
+                &format!("Failed to list workflow runs of {}", head_sha),
+            )
+            .await);
+        }
+
+        let runs: WorkflowRunsResponse = response.json().await?;
+        let mut artifacts = Vec::new();
+        for run in runs.workflow_runs {
+            let url = format!(
+                "{}/repos/{}/{}/actions/runs/{}/artifacts",
+                self.api_base, owner, repo, run.id
+            );
+
+            let mut request = self.client.get(&url);
+
+            if let Some(token) = &self.token {
+                request = request.header("Authorization", format!("Bearer {}", token));
+            }
+
+            request = request.header("Accept", "application/vnd.github+json");
+            request = request.header("X-GitHub-Api-Version", "2022-11-28");
+
+            let response = request.send().await?;
+
+            if !response.status().is_success() {
+                return Err(github_status_error(
+                    response,
+                    &format!("Failed to list artifacts of workflow run {}", run.id),
+                )
+                .await);
+            }
+
+            let run_artifacts: GitHubArtifactsResponse = response.json().await?;
+            artifacts.extend(run_artifacts.artifacts);
+        }
+
+        info!(
+            "Found {} artifacts of {} for {}/{}",
+            artifacts.len(),
+            head_sha,
+            owner,
+            repo
+        );
+        Ok(artifacts)
+    }
+
     /// Download an artifact by ID
     pub async fn download_artifact(
         &self,
@@ -136,8 +327,8 @@ impl GitHubClient {
         artifact_id: u64,
     ) -> Result<bytes::Bytes> {
         let url = format!(
-            "https://api.github.com/repos/{}/{}/actions/artifacts/{}/zip",
-            owner, repo, artifact_id
+            "{}/repos/{}/{}/actions/artifacts/{}/zip",
+            self.api_base, owner, repo, artifact_id
         );
 
         let mut request = self.client.get(&url);
@@ -152,15 +343,16 @@ impl GitHubClient {
         let response = request.send().await?;
 
         if !response.status().is_success() {
-            return Err(anyhow!(
-                "Failed to download GitHub artifact {}: {} - {}",
-                artifact_id,
-                response.status(),
-                response.text().await.unwrap_or_default()
-            ));
+            return Err(github_status_error(
+                response,
+                &format!("Failed to download GitHub artifact {}", artifact_id),
+            )
+            .await);
         }
 
+        let expected_size = response.content_length();
         let content = response.bytes().await?;
+        verify_download_size(&url, expected_size, content.len())?;
 
         info!(
             "Downloaded artifact {} ({} bytes) from {}/{}",
@@ -173,6 +365,91 @@ impl GitHubClient {
         Ok(content)
     }
 
+    /// Get the release with the given tag, or the latest release when no tag is given
+    pub async fn get_release(
+        &self,
+        owner: &str,
+        repo: &str,
+        tag: Option<&str>,
+    ) -> Result<GitHubRelease> {
+        let url = match tag {
+            Some(tag) => format!(
+                "{}/repos/{}/{}/releases/tags/{}",
+                self.api_base, owner, repo, tag
+            ),
+            None => format!("{}/repos/{}/{}/releases/latest", self.api_base, owner, repo),
+        };
+
+        let mut request = self.client.get(&url);
+
+        if let Some(token) = &self.token {
+            request = request.header("Authorization", format!("Bearer {}", token));
+        }
+
+        request = request.header("Accept", "application/vnd.github+json");
+        request = request.header("X-GitHub-Api-Version", "2022-11-28");
+
+        let response = request.send().await?;
+
+        if !response.status().is_success() {
+            let context = match tag {
+                Some(tag) => format!(
+                    "Failed to get GitHub release '{}' of {}/{}",
+                    tag, owner, repo
+                ),
+                None => format!(
+                    "Failed to get the latest GitHub release of {}/{}",
+                    owner, repo
+                ),
+            };
+            return Err(github_status_error(response, &context).await);
+        }
+
+        let release: GitHubRelease = response.json().await?;
+        info!(
+            "Found {} assets in release {} of {}/{}",
+            release.assets.len(),
+            release.tag_name,
+            owner,
+            repo
+        );
+        Ok(release)
+    }
+
+    /// Download a release asset through the API, which also serves assets of private repositories
+    pub async fn download_release_asset(&self, asset: &GitHubReleaseAsset) -> Result<bytes::Bytes> {
+        let mut request = self.client.get(&asset.url);
+
+        if let Some(token) = &self.token {
+            request = request.header("Authorization", format!("Bearer {}", token));
+        }
+
+        request = request.header("Accept", "application/octet-stream");
+        request = request.header("X-GitHub-Api-Version", "2022-11-28");
+
+        let response = request.send().await?;
+
+        if !response.status().is_success() {
+            return Err(github_status_error(
+                response,
+                &format!("Failed to download GitHub release asset '{}'", asset.name),
+            )
+            .await);
+        }
+
+        let expected_size = response.content_length();
+        let content = response.bytes().await?;
+        verify_download_size(&asset.url, expected_size, content.len())?;
+
+        info!(
+            "Downloaded release asset '{}' ({} bytes)",
+            asset.name,
+            content.len()
+        );
+
+        Ok(content)
+    }
+
     /// Filter artifacts by name pattern
     pub fn filter_artifacts_by_name(
         &self,
@@ -231,8 +508,62 @@ impl GitHubClient {
         non_expired
     }
 
+    /// Find non-expired artifacts that can stand in for expired ones
+    ///
+    /// Artifacts sharing a name with an expired one were produced by the same workflow
+    /// in another run or on another branch, so they are preferred; otherwise any
+    /// non-expired artifact is offered. The most recent artifacts come first.
+    pub fn find_alternatives_for_expired(
+        &self,
+        expired: &[GitHubArtifact],
+        all: &[GitHubArtifact],
+    ) -> Vec<GitHubArtifact> {
+        let available: Vec<_> = all
+            .iter()
+            .filter(|artifact| !artifact.expired)
+            .cloned()
+            .collect();
+
+        let same_workflow: Vec<_> = available
+            .iter()
+            .filter(|artifact| expired.iter().any(|e| e.name == artifact.name))
+            .cloned()
+            .collect();
+
+        let mut alternatives = if same_workflow.is_empty() {
+            available
+        } else {
+            same_workflow
+        };
+        alternatives.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        alternatives.truncate(MAX_EXPIRED_ALTERNATIVES);
+        alternatives
+    }
+
+    /// Sort artifacts by the table column `sort_by` names, e.g. `size` or `created`
+    pub fn sort_artifacts(&self, artifacts: &mut [GitHubArtifact], sort_by: &str) -> Result<()> {
+        listing::sort_by_column(artifacts, ARTIFACT_COLUMNS, sort_by, |key, a, b| {
+            Some(match key {
+                "id" => a.id.cmp(&b.id),
+                "name" => a.name.cmp(&b.name),
+                "size" => a.size_in_bytes.cmp(&b.size_in_bytes),
+                "created" => a.created_at.cmp(&b.created_at),
+                "expires" => a.expires_at.cmp(&b.expires_at),
+                "expired" => a.expired.cmp(&b.expired),
+                _ => return None,
+            })
+        })
+    }
+
     /// Print artifact information in a formatted way
-    pub fn print_artifacts_info(&self, artifacts: &[GitHubArtifact], format: &str) -> Result<()> {
+    ///
+    /// `columns` selects and orders the table columns by key; all are shown when empty.
+    pub fn print_artifacts_info(
+        &self,
+        artifacts: &[GitHubArtifact],
+        format: &str,
+        columns: &[String],
+    ) -> Result<()> {
         match format.to_lowercase().as_str() {
             "yaml" => {
                 // Add metadata header for better documentation
@@ -250,37 +581,39 @@ impl GitHubClient {
                 println!("{}", json_output);
             }
             "table" => {
-                self.print_artifacts_info_table(artifacts);
+                self.print_artifacts_info_table(artifacts, columns)?;
             }
             _ => {
-                return Err(anyhow!(
+                return Err(MirrorError::InvalidInput(format!(
                     "Unsupported output format: {}. Supported formats: yaml, json, table",
                     format
-                ));
+                )));
             }
         }
         Ok(())
     }
 
     /// Print artifact information in table format using comfy-table
-    fn print_artifacts_info_table(&self, artifacts: &[GitHubArtifact]) {
+    fn print_artifacts_info_table(
+        &self,
+        artifacts: &[GitHubArtifact],
+        columns: &[String],
+    ) -> Result<()> {
+        let selected = listing::selected_columns(ARTIFACT_COLUMNS, columns)?;
         if artifacts.is_empty() {
             println!("No artifacts found.");
-            return;
+            return Ok(());
         }
 
         let mut table = Table::new();
         table
             .load_preset(NOTHING)
             .set_content_arrangement(ContentArrangement::Dynamic)
-            .set_header(vec![
-                Cell::new("ID").add_attribute(Attribute::Bold),
-                Cell::new("Name").add_attribute(Attribute::Bold),
-                Cell::new("Size").add_attribute(Attribute::Bold),
-                Cell::new("Created").add_attribute(Attribute::Bold),
-                Cell::new("Expires").add_attribute(Attribute::Bold),
-                Cell::new("Expired").add_attribute(Attribute::Bold),
-            ]);
+            .set_header(
+                selected
+                    .iter()
+                    .map(|&i| Cell::new(ARTIFACT_COLUMNS[i]).add_attribute(Attribute::Bold)),
+            );
 
         for artifact in artifacts {
             let size_display = if artifact.size_in_bytes > 1_000_000 {
@@ -303,19 +636,58 @@ impl GitHubClient {
                 Err(_) => artifact.expires_at.clone(),
             };
 
-            table.add_row(vec![
-                Cell::new(artifact.id.to_string()),
-                Cell::new(&artifact.name),
-                Cell::new(&size_display),
-                Cell::new(&created_display),
-                Cell::new(&expires_display),
-                Cell::new(if artifact.expired { "Yes" } else { "No" }),
-            ]);
+            let row = [
+                artifact.id.to_string(),
+                artifact.name.clone(),
+                size_display,
+                created_display,
+                expires_display,
+                if artifact.expired { "Yes" } else { "No" }.to_string(),
+            ];
+            table.add_row(selected.iter().map(|&i| Cell::new(&row[i])));
         }
 
         println!("\nFound {} artifacts:", artifacts.len());
         println!("{}", table);
+        Ok(())
+    }
+}
+
+/// Convert an unsuccessful GitHub API response into a classified error.
+///
+/// GitHub signals exhausted rate limits with 403 (or 429) plus
+/// `x-ratelimit-remaining: 0`, which would otherwise look like an auth failure.
+async fn github_status_error(response: reqwest::Response, context: &str) -> MirrorError {
+    let status = response.status();
+    let headers = response.headers();
+
+    let rate_limited = headers
+        .get("x-ratelimit-remaining")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v == "0");
+
+    if rate_limited || status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        let retry_after = headers
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok())
+            .or_else(|| {
+                headers
+                    .get("x-ratelimit-reset")
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse::<i64>().ok())
+                    .map(|reset| (reset - chrono::Utc::now().timestamp()).max(0) as u64)
+            })
+            .map(std::time::Duration::from_secs);
+
+        return MirrorError::RateLimited {
+            message: format!("{}: GitHub API rate limit exceeded", context),
+            retry_after,
+        };
     }
+
+    let body = response.text().await.unwrap_or_default();
+    MirrorError::from_status(status, context, &body)
 }
 
 /// Parse GitHub repository from URL or owner/repo format
@@ -343,16 +715,55 @@ pub fn parse_github_repository(input: &str) -> Result<(String, String)> {
         }
     }
 
-    Err(anyhow!(
+    Err(MirrorError::InvalidInput(
         "Invalid GitHub repository format. Expected 'owner/repo' or 'https://github.com/owner/repo'"
+            .to_string(),
     ))
 }
 
+/// Parse a release source, `owner/repo@tag`, into the repository and the tag
+///
+/// Without `@tag` the latest release is meant.
+pub fn parse_release_source(input: &str) -> Result<(String, String, Option<String>)> {
+    let (repository, tag) = match input.rsplit_once('@') {
+        Some((repository, tag)) => {
+            let tag = tag.trim();
+            if tag.is_empty() {
+                return Err(MirrorError::InvalidInput(format!(
+                    "Missing release tag after '@' in '{}'",
+                    input
+                )));
+            }
+            (repository, Some(tag.to_string()))
+        }
+        None => (input, None),
+    };
+    let (owner, repo) = parse_github_repository(repository)?;
+    Ok((owner, repo, tag))
+}
+
+/// The number of a pull request URL, `https://github.com/owner/repo/pull/123`
+///
+/// Anything after the number, such as `/checks` or `/files`, is ignored.
+pub fn parse_pull_request(input: &str) -> Option<u64> {
+    let path = input
+        .strip_prefix("https://github.com/")
+        .or_else(|| input.strip_prefix("http://github.com/"))?;
+    let parts: Vec<&str> = path.split(['/', '#', '?']).collect();
+    match parts.as_slice() {
+        [_, _, "pull", number, ..] => number.parse().ok(),
+        _ => None,
+    }
+}
+
 /// Parse artifact ID from string
 pub fn parse_artifact_id(input: &str) -> Result<u64> {
-    input
-        .parse::<u64>()
-        .map_err(|_| anyhow!("Invalid artifact ID: '{}'. Must be a number.", input))
+    input.parse::<u64>().map_err(|_| {
+        MirrorError::InvalidInput(format!(
+            "Invalid artifact ID: '{}'. Must be a number.",
+            input
+        ))
+    })
 }
 
 #[cfg(test)]
@@ -383,10 +794,330 @@ mod tests {
         assert!(parse_github_repository("/").is_err());
     }
 
+    #[test]
+    fn test_parse_pull_request() {
+        assert_eq!(
+            parse_pull_request("https://github.com/conda-forge/staged-recipes/pull/28123"),
+            Some(28123)
+        );
+        assert_eq!(
+            parse_pull_request("https://github.com/owner/repo/pull/7/checks"),
+            Some(7)
+        );
+        assert_eq!(parse_pull_request("https://github.com/owner/repo"), None);
+        assert_eq!(parse_pull_request("owner/repo"), None);
+        assert_eq!(
+            parse_pull_request("https://github.com/owner/repo/pull/next"),
+            None
+        );
+    }
+
     #[test]
     fn test_parse_artifact_id() {
         assert_eq!(parse_artifact_id("123456").unwrap(), 123456);
         assert!(parse_artifact_id("invalid").is_err());
         assert!(parse_artifact_id("").is_err());
     }
+
+    fn artifact(id: u64, name: &str, expired: bool, created_at: &str) -> GitHubArtifact {
+        GitHubArtifact {
+            id,
+            name: name.to_string(),
+            size_in_bytes: 100,
+            url: String::new(),
+            archive_download_url: String::new(),
+            expired,
+            created_at: created_at.to_string(),
+            updated_at: created_at.to_string(),
+            expires_at: created_at.to_string(),
+            workflow_run: None,
+        }
+    }
+
+    #[test]
+    fn test_find_alternatives_for_expired() {
+        let client = GitHubClient::new(&Config::default()).unwrap();
+        let all = vec![
+            artifact(1, "conda-linux", true, "2024-01-01T00:00:00Z"),
+            artifact(2, "conda-linux", false, "2024-02-01T00:00:00Z"),
+            artifact(3, "conda-linux", false, "2024-03-01T00:00:00Z"),
+            artifact(4, "docs", false, "2024-04-01T00:00:00Z"),
+        ];
+
+        // Same-named artifacts from other runs are preferred, newest first
+        let alternatives = client.find_alternatives_for_expired(&all[..1], &all);
+        let ids: Vec<_> = alternatives.iter().map(|a| a.id).collect();
+        assert_eq!(ids, vec![3, 2]);
+
+        // Without a same-named artifact, any non-expired artifact is offered
+        let expired = vec![artifact(5, "conda-osx", true, "2024-01-01T00:00:00Z")];
+        let alternatives = client.find_alternatives_for_expired(&expired, &all);
+        let ids: Vec<_> = alternatives.iter().map(|a| a.id).collect();
+        assert_eq!(ids, vec![4, 3, 2]);
+    }
+
+    #[test]
+    fn test_sort_and_select_columns() {
+        let client = GitHubClient::new(&Config::default()).unwrap();
+        let mut artifacts = vec![
+            artifact(1, "docs", false, "2024-03-01T00:00:00Z"),
+            artifact(2, "conda-linux", false, "2024-01-01T00:00:00Z"),
+            artifact(3, "conda-osx", false, "2024-02-01T00:00:00Z"),
+        ];
+        artifacts[0].size_in_bytes = 50;
+        artifacts[2].size_in_bytes = 500;
+
+        client.sort_artifacts(&mut artifacts, "size").unwrap();
+        let ids: Vec<_> = artifacts.iter().map(|a| a.id).collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+        client.sort_artifacts(&mut artifacts, "created").unwrap();
+        let ids: Vec<_> = artifacts.iter().map(|a| a.id).collect();
+        assert_eq!(ids, vec![2, 3, 1]);
+        assert!(client.sort_artifacts(&mut artifacts, "owner").is_err());
+
+        let columns = vec!["name".to_string(), "size".to_string()];
+        client
+            .print_artifacts_info(&artifacts, "table", &columns)
+            .unwrap();
+        assert!(client
+            .print_artifacts_info(&artifacts, "table", &["owner".to_string()])
+            .is_err());
+    }
+
+    #[test]
+    fn test_with_client_keeps_configured_token() {
+        let config = Config {
+            github_token: Some("secret".to_string()),
+            ..Default::default()
+        };
+        let client = GitHubClient::with_client(Client::new(), &config);
+        assert_eq!(client.token.as_deref(), Some("secret"));
+    }
+
+    #[tokio::test]
+    async fn test_api_requests_against_mock_server() {
+        use crate::test_util::{MockResponse, MockServer};
+
+        let server = MockServer::start().await.unwrap();
+        server.mock(
+            "GET",
+            "/repos/owner/repo/actions/artifacts",
+            MockResponse::json(
+                200,
+                serde_json::to_vec(&GitHubArtifactsResponse {
+                    total_count: 1,
+                    artifacts: vec![artifact(7, "conda-linux", false, "2024-01-01T00:00:00Z")],
+                })
+                .unwrap(),
+            ),
+        );
+        server.mock(
+            "GET",
+            "/repos/owner/repo/actions/artifacts/8",
+            MockResponse::json(403, r#"{"message":"API rate limit exceeded"}"#)
+                .with_header("x-ratelimit-remaining", "0")
+                .with_header("retry-after", "30"),
+        );
+
+        let config = Config {
+            github_token: Some("secret".to_string()),
+            ..Default::default()
+        };
+        let client = GitHubClient::new(&config)
+            .unwrap()
+            .with_api_base(server.url());
+
+        let artifacts = client.list_artifacts("owner", "repo").await.unwrap();
+        assert_eq!(artifacts.len(), 1);
+        assert_eq!(artifacts[0].id, 7);
+
+        let result = client.get_artifact("owner", "repo", 8).await;
+        assert!(matches!(
+            result,
+            Err(MirrorError::RateLimited {
+                retry_after: Some(d),
+                ..
+            }) if d.as_secs() == 30
+        ));
+
+        let result = client.download_artifact("owner", "repo", 9).await;
+        assert!(matches!(result, Err(MirrorError::NotFound(_))));
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(
+            requests[0].headers.get("authorization").map(String::as_str),
+            Some("Bearer secret")
+        );
+        assert_eq!(
+            requests[2].path,
+            "/repos/owner/repo/actions/artifacts/9/zip"
+        );
+    }
+    #[tokio::test]
+    async fn test_list_pull_request_artifacts() {
+        use crate::test_util::{MockResponse, MockServer};
+
+        let server = MockServer::start().await.unwrap();
+        server.mock(
+            "GET",
+            "/repos/owner/repo/pulls/12",
+            MockResponse::json(200, r#"{"number":12,"head":{"sha":"abc123"}}"#),
+        );
+        server.mock(
+            "GET",
+            "/repos/owner/repo/actions/runs?head_sha=abc123",
+            MockResponse::json(
+                200,
+                r#"{"total_count":2,"workflow_runs":[{"id":1},{"id":2}]}"#,
+            ),
+        );
+        for (run, id) in [(1, 7), (2, 8)] {
+            server.mock(
+                "GET",
+                &format!("/repos/owner/repo/actions/runs/{}/artifacts", run),
+                MockResponse::json(
+                    200,
+                    serde_json::to_vec(&GitHubArtifactsResponse {
+                        total_count: 1,
+                        artifacts: vec![artifact(id, "conda", false, "2024-01-01T00:00:00Z")],
+                    })
+                    .unwrap(),
+                ),
+            );
+        }
+
+        let client = GitHubClient::new(&Config::default())
+            .unwrap()
+            .with_api_base(server.url());
+        let head = client
+            .get_pull_request_head("owner", "repo", 12)
+            .await
+            .unwrap();
+        assert_eq!(head, "abc123");
+        let artifacts = client
+            .list_commit_artifacts("owner", "repo", &head)
+            .await
+            .unwrap();
+        let ids: Vec<_> = artifacts.iter().map(|a| a.id).collect();
+        assert_eq!(ids, vec![7, 8]);
+
+        let result = client.get_pull_request_head("owner", "repo", 13).await;
+        assert!(matches!(result, Err(MirrorError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_list_artifacts_uses_listing_cache() {
+        use crate::test_util::{MockResponse, MockServer};
+
+        let server = MockServer::start().await.unwrap();
+        server.mock(
+            "GET",
+            "/repos/owner/repo/actions/artifacts",
+            MockResponse::json(
+                200,
+                serde_json::to_vec(&GitHubArtifactsResponse {
+                    total_count: 1,
+                    artifacts: vec![artifact(7, "conda-linux", false, "2024-01-01T00:00:00Z")],
+                })
+                .unwrap(),
+            ),
+        );
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let config = Config {
+            github_token: None,
+            listing_cache_dir: Some(temp_dir.path().to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        for _ in 0..2 {
+            let client = GitHubClient::new(&config)
+                .unwrap()
+                .with_api_base(server.url());
+            let artifacts = client.list_artifacts("owner", "repo").await.unwrap();
+            assert_eq!(artifacts[0].id, 7);
+        }
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn test_parse_release_source() {
+        assert_eq!(
+            parse_release_source("owner/repo@v1.2.3").unwrap(),
+            (
+                "owner".to_string(),
+                "repo".to_string(),
+                Some("v1.2.3".to_string())
+            )
+        );
+        assert_eq!(
+            parse_release_source("https://github.com/owner/repo").unwrap(),
+            ("owner".to_string(), "repo".to_string(), None)
+        );
+        assert!(parse_release_source("owner/repo@").is_err());
+        assert!(parse_release_source("repo@v1").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_release_requests_against_mock_server() {
+        use crate::test_util::{MockResponse, MockServer};
+
+        let server = MockServer::start().await.unwrap();
+        let asset = GitHubReleaseAsset {
+            id: 5,
+            name: "pkg-1.0-0.conda".to_string(),
+            size: 4,
+            url: format!("{}/repos/owner/repo/releases/assets/5", server.url()),
+            browser_download_url:
+                "https://github.com/owner/repo/releases/download/v1/pkg-1.0-0.conda".to_string(),
+        };
+        server.mock(
+            "GET",
+            "/repos/owner/repo/releases/tags/v1",
+            MockResponse::json(
+                200,
+                serde_json::to_vec(&GitHubRelease {
+                    id: 1,
+                    tag_name: "v1".to_string(),
+                    html_url: "https://github.com/owner/repo/releases/tag/v1".to_string(),
+                    assets: vec![asset.clone()],
+                })
+                .unwrap(),
+            ),
+        );
+        server.mock(
+            "GET",
+            "/repos/owner/repo/releases/assets/5",
+            MockResponse::new(200, "data"),
+        );
+
+        let config = Config {
+            github_token: Some("secret".to_string()),
+            ..Default::default()
+        };
+        let client = GitHubClient::new(&config)
+            .unwrap()
+            .with_api_base(server.url());
+
+        let release = client
+            .get_release("owner", "repo", Some("v1"))
+            .await
+            .unwrap();
+        assert_eq!(release.assets.len(), 1);
+        let content = client
+            .download_release_asset(&release.assets[0])
+            .await
+            .unwrap();
+        assert_eq!(&content[..], b"data");
+
+        let result = client.get_release("owner", "repo", None).await;
+        assert!(matches!(result, Err(MirrorError::NotFound(_))));
+
+        let requests = server.requests();
+        assert_eq!(
+            requests[1].headers.get("accept").map(String::as_str),
+            Some("application/octet-stream")
+        );
+        assert_eq!(requests[2].path, "/repos/owner/repo/releases/latest");
+    }
 }
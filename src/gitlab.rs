@@ -0,0 +1,681 @@
+use comfy_table::presets::NOTHING;
+use comfy_table::{Attribute, Cell, ContentArrangement, Table};
+
+use reqwest::{Client, RequestBuilder};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::download::verify_download_size;
+use crate::error::{MirrorError, Result};
+use crate::listing;
+use crate::listing_cache::ListingCache;
+use crate::politeness;
+
+/// Headers of the job table, whose keys `--columns` and `--sort-by` take
+const JOB_COLUMNS: &[&str] = &["Job ID", "Name", "Stage", "Status", "Artifacts", "Finished"];
+
+/// Headers of the pipeline table, whose keys `--columns` and `--sort-by` take
+const PIPELINE_COLUMNS: &[&str] = &[
+    "Pipeline ID",
+    "Status",
+    "Ref",
+    "Commit",
+    "Created",
+    "Mirror Source",
+];
+
+/// Jobs and pipelines requested per page; GitLab caps `per_page` at 100
+const PER_PAGE: usize = 100;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GitLabPipeline {
+    pub id: u64,
+    pub status: String,
+    #[serde(rename = "ref")]
+    pub git_ref: String,
+    pub sha: String,
+    pub web_url: String,
+    pub created_at: String,
+    pub updated_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GitLabJob {
+    pub id: u64,
+    pub name: String,
+    pub stage: String,
+    pub status: String,
+    pub web_url: String,
+    pub created_at: String,
+    pub finished_at: Option<String>,
+    /// The artifacts archive, absent for jobs without artifacts or whose artifacts expired
+    pub artifacts_file: Option<ArtifactsFile>,
+    pub artifacts_expire_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ArtifactsFile {
+    pub filename: String,
+    pub size: u64,
+}
+
+/// A GitLab project and optionally one of its pipelines, as given to `--src`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitLabSource {
+    /// Instance the source names with a URL, e.g. `https://gitlab.example.org`
+    pub instance: Option<String>,
+    /// Full project path, e.g. `group/subgroup/project`
+    pub project: String,
+    pub pipeline_id: Option<u64>,
+}
+
+pub struct GitLabClient {
+    client: Client,
+    token: Option<String>,
+    base_url: String,
+    listing_cache: Option<ListingCache>,
+}
+
+impl GitLabClient {
+    pub fn new(config: &Config) -> Result<Self> {
+        let client = Client::builder()
+            .timeout(std::time::Duration::from_secs(config.timeout_seconds))
+            .user_agent(politeness::user_agent(config))
+            .build()?;
+
+        Ok(Self::with_client(client, config))
+    }
+
+    /// Use a caller-supplied client, e.g. one with proxies, custom TLS roots or tracing
+    pub fn with_client(client: Client, config: &Config) -> Self {
+        Self {
+            client,
+            token: config.gitlab_token.clone(),
+            base_url: config.gitlab_url.trim_end_matches('/').to_string(),
+            listing_cache: ListingCache::from_config(config),
+        }
+    }
+
+    /// Send API requests to another instance, e.g. a self-managed GitLab or a mock server
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Base URL of the project's API resources
+    fn project_url(&self, project: &str) -> String {
+        let encoded: String = url::form_urlencoded::byte_serialize(project.as_bytes()).collect();
+        format!("{}/api/v4/projects/{}", self.base_url, encoded)
+    }
+
+    fn get(&self, url: &str) -> RequestBuilder {
+        let request = self.client.get(url);
+        match &self.token {
+            Some(token) => request.header("PRIVATE-TOKEN", token),
+            None => request,
+        }
+    }
+
+    /// Fetch a JSON listing, going through the listing cache
+    async fn list<T: Serialize + DeserializeOwned>(&self, url: &str, context: &str) -> Result<T> {
+        let cache_key = ListingCache::key(url, self.token.is_some());
+        if let Some(listing) = self
+            .listing_cache
+            .as_ref()
+            .and_then(|cache| cache.get::<T>(&cache_key))
+        {
+            return Ok(listing);
+        }
+
+        let response = self.get(url).send().await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(MirrorError::from_status(status, context, &error_text));
+        }
+        let listing: T = response.json().await.map_err(|e| {
+            MirrorError::InvalidResponse(format!("{}: unexpected response: {}", context, e))
+        })?;
+        if let Some(cache) = &self.listing_cache {
+            cache.put(&cache_key, &listing);
+        }
+        Ok(listing)
+    }
+
+    /// List the most recent finished pipelines of a project, newest first
+    pub async fn list_pipelines(&self, project: &str) -> Result<Vec<GitLabPipeline>> {
+        let url = format!(
+            "{}/pipelines?scope=finished&per_page={}",
+            self.project_url(project),
+            PER_PAGE
+        );
+        let pipelines: Vec<GitLabPipeline> =
+            self.list(&url, "Failed to list GitLab pipelines").await?;
+        info!("Found {} pipelines in {}", pipelines.len(), project);
+        Ok(pipelines)
+    }
+
+    /// List the jobs of a pipeline
+    pub async fn list_jobs(&self, project: &str, pipeline_id: u64) -> Result<Vec<GitLabJob>> {
+        let url = format!(
+            "{}/pipelines/{}/jobs?per_page={}",
+            self.project_url(project),
+            pipeline_id,
+            PER_PAGE
+        );
+        let jobs: Vec<GitLabJob> = self.list(&url, "Failed to list GitLab jobs").await?;
+        info!(
+            "Found {} jobs in pipeline {} of {}",
+            jobs.len(),
+            pipeline_id,
+            project
+        );
+        Ok(jobs)
+    }
+
+    /// Download the artifacts archive of a job
+    pub async fn download_artifacts(&self, project: &str, job_id: u64) -> Result<bytes::Bytes> {
+        let url = format!("{}/jobs/{}/artifacts", self.project_url(project), job_id);
+        let response = self.get(&url).send().await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(MirrorError::from_status(
+                status,
+                &format!("Failed to download artifacts of GitLab job {}", job_id),
+                &error_text,
+            ));
+        }
+
+        let expected_size = response.content_length();
+        let content = response.bytes().await?;
+        verify_download_size(&url, expected_size, content.len())?;
+
+        info!(
+            "Downloaded artifacts of job {} ({} bytes) from {}",
+            job_id,
+            content.len(),
+            project
+        );
+
+        Ok(content)
+    }
+
+    /// Web page of a project on this instance
+    pub fn project_web_url(&self, project: &str) -> String {
+        format!("{}/{}", self.base_url, project)
+    }
+
+    /// Filter jobs by name pattern
+    pub fn filter_jobs_by_name(&self, jobs: &[GitLabJob], pattern: Option<&str>) -> Vec<GitLabJob> {
+        let Some(pattern) = pattern else {
+            return jobs.to_vec();
+        };
+        match regex::Regex::new(pattern) {
+            Ok(regex) => {
+                let filtered: Vec<_> = jobs
+                    .iter()
+                    .filter(|job| regex.is_match(&job.name))
+                    .cloned()
+                    .collect();
+                info!(
+                    "Filtered {} jobs to {} matching pattern '{}'",
+                    jobs.len(),
+                    filtered.len(),
+                    pattern
+                );
+                filtered
+            }
+            Err(e) => {
+                warn!("Invalid regex pattern '{}': {}", pattern, e);
+                jobs.to_vec()
+            }
+        }
+    }
+
+    /// Filter pipelines by the branch or tag they ran for
+    pub fn filter_pipelines_by_ref(
+        &self,
+        pipelines: &[GitLabPipeline],
+        pattern: &str,
+    ) -> Result<Vec<GitLabPipeline>> {
+        let regex = regex::Regex::new(pattern)?;
+
+        let filtered: Vec<GitLabPipeline> = pipelines
+            .iter()
+            .filter(|pipeline| regex.is_match(&pipeline.git_ref))
+            .cloned()
+            .collect();
+
+        info!(
+            "Filtered {} pipelines to {} with a ref matching '{}'",
+            pipelines.len(),
+            filtered.len(),
+            pattern
+        );
+
+        Ok(filtered)
+    }
+
+    /// Sort jobs by the table column `sort_by` names, e.g. `artifacts` or `finished`
+    pub fn sort_jobs(&self, jobs: &mut [GitLabJob], sort_by: &str) -> Result<()> {
+        listing::sort_by_column(jobs, JOB_COLUMNS, sort_by, |key, a, b| {
+            Some(match key {
+                "job-id" => a.id.cmp(&b.id),
+                "name" => a.name.cmp(&b.name),
+                "stage" => a.stage.cmp(&b.stage),
+                "status" => a.status.cmp(&b.status),
+                "artifacts" => artifacts_size(a).cmp(&artifacts_size(b)),
+                "finished" => a.finished_at.cmp(&b.finished_at),
+                _ => return None,
+            })
+        })
+    }
+
+    /// Sort pipelines by the table column `sort_by` names, e.g. `created`
+    pub fn sort_pipelines(&self, pipelines: &mut [GitLabPipeline], sort_by: &str) -> Result<()> {
+        listing::sort_by_column(pipelines, PIPELINE_COLUMNS, sort_by, |key, a, b| {
+            Some(match key {
+                "pipeline-id" | "mirror-source" => a.id.cmp(&b.id),
+                "status" => a.status.cmp(&b.status),
+                "ref" => a.git_ref.cmp(&b.git_ref),
+                "commit" => a.sha.cmp(&b.sha),
+                "created" => a.created_at.cmp(&b.created_at),
+                _ => return None,
+            })
+        })
+    }
+
+    /// Print job information in a formatted way
+    ///
+    /// `columns` selects and orders the table columns by key; all are shown when empty.
+    pub fn print_jobs_info(
+        &self,
+        jobs: &[GitLabJob],
+        format: &str,
+        columns: &[String],
+    ) -> Result<()> {
+        match format.to_lowercase().as_str() {
+            "yaml" => {
+                println!("# GitLab CI Jobs");
+                println!("# Total jobs found: {}", jobs.len());
+                println!("# Use --name-filter to filter jobs by name pattern");
+                println!("# Jobs without artifacts_file have no artifacts to mirror");
+                println!();
+
+                let yaml_output = serde_yaml::to_string(&jobs)?;
+                println!("{}", yaml_output);
+            }
+            "json" => {
+                let json_output = serde_json::to_string_pretty(&jobs)?;
+                println!("{}", json_output);
+            }
+            "table" => {
+                self.print_jobs_info_table(jobs, columns)?;
+            }
+            _ => {
+                return Err(MirrorError::InvalidInput(format!(
+                    "Unsupported output format: {}. Supported formats: yaml, json, table",
+                    format
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Print job information in table format using comfy-table
+    fn print_jobs_info_table(&self, jobs: &[GitLabJob], columns: &[String]) -> Result<()> {
+        let selected = listing::selected_columns(JOB_COLUMNS, columns)?;
+        if jobs.is_empty() {
+            println!("No jobs found.");
+            return Ok(());
+        }
+
+        let mut table = Table::new();
+        table
+            .load_preset(NOTHING)
+            .set_content_arrangement(ContentArrangement::Dynamic)
+            .set_header(
+                selected
+                    .iter()
+                    .map(|&i| Cell::new(JOB_COLUMNS[i]).add_attribute(Attribute::Bold)),
+            );
+
+        for job in jobs {
+            let artifacts_display = match &job.artifacts_file {
+                Some(file) if file.size > 1_000_000 => {
+                    format!("{:.1}M", file.size as f64 / 1_000_000.0)
+                }
+                Some(file) if file.size > 1_000 => format!("{:.1}K", file.size as f64 / 1_000.0),
+                Some(file) => file.size.to_string(),
+                None => "None".to_string(),
+            };
+
+            let row = [
+                job.id.to_string(),
+                job.name.clone(),
+                job.stage.clone(),
+                job.status.clone(),
+                artifacts_display,
+                format_time(job.finished_at.as_deref(), "In Progress"),
+            ];
+            table.add_row(selected.iter().map(|&i| Cell::new(&row[i])));
+        }
+
+        println!("\nFound {} jobs:", jobs.len());
+        println!("{}", table);
+        Ok(())
+    }
+
+    /// Print pipeline information in a formatted way with mirror command examples
+    ///
+    /// `columns` selects and orders the table columns by key; all are shown when empty.
+    pub fn print_pipelines_info(
+        &self,
+        pipelines: &[GitLabPipeline],
+        project: &str,
+        format: &str,
+        columns: &[String],
+    ) -> Result<()> {
+        match format.to_lowercase().as_str() {
+            "yaml" => {
+                println!("# GitLab CI Pipelines for {}", project);
+                println!("# Total pipelines found: {}", pipelines.len());
+                println!("# Use 'meso-forge-mirror mirror --src-type gitlab --src {}#<pipeline_id>' to mirror job artifacts", project);
+                println!("# Filter with --description-filter to narrow results by ref");
+                println!();
+
+                let yaml_output = serde_yaml::to_string(&pipelines)?;
+                println!("{}", yaml_output);
+            }
+            "json" => {
+                let json_output = serde_json::to_string_pretty(&pipelines)?;
+                println!("{}", json_output);
+            }
+            "table" => {
+                self.print_pipelines_info_table(pipelines, project, columns)?;
+            }
+            _ => {
+                return Err(MirrorError::InvalidInput(format!(
+                    "Unsupported output format: {}. Supported formats: yaml, json, table",
+                    format
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Print pipeline information in table format using comfy-table with mirror command examples
+    fn print_pipelines_info_table(
+        &self,
+        pipelines: &[GitLabPipeline],
+        project: &str,
+        columns: &[String],
+    ) -> Result<()> {
+        let selected = listing::selected_columns(PIPELINE_COLUMNS, columns)?;
+        if pipelines.is_empty() {
+            println!("No pipelines found.");
+            return Ok(());
+        }
+
+        let mut table = Table::new();
+        table
+            .load_preset(NOTHING)
+            .set_content_arrangement(ContentArrangement::Dynamic)
+            .set_header(
+                selected
+                    .iter()
+                    .map(|&i| Cell::new(PIPELINE_COLUMNS[i]).add_attribute(Attribute::Bold)),
+            );
+
+        for pipeline in pipelines {
+            let row = [
+                pipeline.id.to_string(),
+                pipeline.status.clone(),
+                pipeline.git_ref.clone(),
+                pipeline.sha.chars().take(8).collect(),
+                format_time(Some(&pipeline.created_at), "N/A"),
+                format!("{}#{}", project, pipeline.id),
+            ];
+            table.add_row(selected.iter().map(|&i| Cell::new(&row[i])));
+        }
+
+        println!("\nFound {} pipelines for {}:", pipelines.len(), project);
+        println!("{}", table);
+
+        let successful: Vec<_> = pipelines
+            .iter()
+            .filter(|p| p.status == "success")
+            .take(3)
+            .collect();
+        if !successful.is_empty() {
+            println!("\nExample mirror commands for recent successful pipelines:");
+            println!();
+
+            for (i, pipeline) in successful.iter().enumerate() {
+                println!(
+                    "{}. Pipeline {} ({}):",
+                    i + 1,
+                    pipeline.id,
+                    pipeline.git_ref
+                );
+                println!("   # Mirror the packages of all jobs:");
+                println!(
+                    "   meso-forge-mirror mirror --src-type gitlab --src {}#{}",
+                    project, pipeline.id
+                );
+                println!("   # Mirror the packages of matching jobs only:");
+                println!("   meso-forge-mirror mirror --src-type gitlab --src {}#{} --src-path '.*linux-64.*'", project, pipeline.id);
+                println!();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Size in bytes of a job's artifacts archive, 0 when it has none
+fn artifacts_size(job: &GitLabJob) -> u64 {
+    job.artifacts_file.as_ref().map_or(0, |file| file.size)
+}
+
+/// An API timestamp as shown in tables
+fn format_time(time: Option<&str>, missing: &str) -> String {
+    match time {
+        Some(time) => chrono::DateTime::parse_from_rfc3339(time)
+            .map(|dt| dt.format("%Y-%m-%d %H:%M UTC").to_string())
+            .unwrap_or_else(|_| time.to_string()),
+        None => missing.to_string(),
+    }
+}
+
+/// Parse pipeline ID from string
+pub fn parse_pipeline_id(input: &str) -> Result<u64> {
+    input.parse::<u64>().map_err(|_| {
+        MirrorError::InvalidInput(format!(
+            "Invalid pipeline ID: '{}'. Must be a number.",
+            input
+        ))
+    })
+}
+
+/// Parse a GitLab source string with optional pipeline ID
+/// Formats supported:
+/// - group/project, group/subgroup/project
+/// - group/project#pipeline_id
+/// - https://gitlab.example.org/group/project
+/// - https://gitlab.example.org/group/project/-/pipelines/pipeline_id
+/// - https://gitlab.example.org/group/project#pipeline_id
+pub fn parse_gitlab_source(input: &str) -> Result<GitLabSource> {
+    let (path_part, pipeline_id) = match input.split_once('#') {
+        Some((path_part, id)) => (path_part, Some(parse_pipeline_id(id)?)),
+        None => (input, None),
+    };
+
+    let (instance, path) = if path_part.starts_with("https://") || path_part.starts_with("http://")
+    {
+        let url = url::Url::parse(path_part)?;
+        let instance = url.origin().ascii_serialization();
+        (Some(instance), url.path().to_string())
+    } else {
+        (None, path_part.to_string())
+    };
+
+    // Web URLs of pipelines and other pages put them after `/-/`
+    let (project, page) = match path.split_once("/-/") {
+        Some((project, page)) => (project, Some(page)),
+        None => (path.as_str(), None),
+    };
+    let pipeline_id = match (pipeline_id, page.and_then(|p| p.strip_prefix("pipelines/"))) {
+        (None, Some(id)) => Some(parse_pipeline_id(id.trim_end_matches('/'))?),
+        (pipeline_id, _) => pipeline_id,
+    };
+
+    let project = project.trim().trim_matches('/').to_string();
+    if !project.contains('/') || project.split('/').any(str::is_empty) {
+        return Err(MirrorError::InvalidInput(
+            "Invalid GitLab format. Expected 'group/project' or 'https://gitlab.com/group/project'"
+                .to_string(),
+        ));
+    }
+
+    Ok(GitLabSource {
+        instance,
+        project,
+        pipeline_id,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{MockResponse, MockServer};
+
+    fn job(id: u64, name: &str, artifacts: Option<u64>) -> GitLabJob {
+        GitLabJob {
+            id,
+            name: name.to_string(),
+            stage: "build".to_string(),
+            status: "success".to_string(),
+            web_url: String::new(),
+            created_at: "2024-05-01T12:00:00Z".to_string(),
+            finished_at: Some(format!("2024-05-01T12:{:02}:00Z", id)),
+            artifacts_file: artifacts.map(|size| ArtifactsFile {
+                filename: "artifacts.zip".to_string(),
+                size,
+            }),
+            artifacts_expire_at: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_gitlab_source() {
+        let source = parse_gitlab_source("group/project").unwrap();
+        assert_eq!(source.instance, None);
+        assert_eq!(source.project, "group/project");
+        assert_eq!(source.pipeline_id, None);
+
+        let source = parse_gitlab_source("group/subgroup/project#1234").unwrap();
+        assert_eq!(source.project, "group/subgroup/project");
+        assert_eq!(source.pipeline_id, Some(1234));
+
+        let source = parse_gitlab_source("https://gitlab.example.org/group/project/").unwrap();
+        assert_eq!(
+            source.instance.as_deref(),
+            Some("https://gitlab.example.org")
+        );
+        assert_eq!(source.project, "group/project");
+
+        let source =
+            parse_gitlab_source("https://gitlab.com/group/project/-/pipelines/987").unwrap();
+        assert_eq!(source.instance.as_deref(), Some("https://gitlab.com"));
+        assert_eq!(source.project, "group/project");
+        assert_eq!(source.pipeline_id, Some(987));
+
+        assert!(parse_gitlab_source("project").is_err());
+        assert!(parse_gitlab_source("group//project").is_err());
+        assert!(parse_gitlab_source("group/project#latest").is_err());
+    }
+
+    #[test]
+    fn test_filter_and_sort_jobs() {
+        let client = GitLabClient::new(&Config::default()).unwrap();
+        let mut jobs = vec![
+            job(3, "build-osx-64", Some(5_000)),
+            job(1, "build-linux-64", Some(20_000)),
+            job(2, "test", None),
+        ];
+
+        let filtered = client.filter_jobs_by_name(&jobs, Some("^build-"));
+        assert_eq!(filtered.len(), 2);
+
+        client.sort_jobs(&mut jobs, "artifacts").unwrap();
+        let ids: Vec<_> = jobs.iter().map(|j| j.id).collect();
+        assert_eq!(ids, vec![2, 3, 1]);
+        client.sort_jobs(&mut jobs, "finished").unwrap();
+        let ids: Vec<_> = jobs.iter().map(|j| j.id).collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+        assert!(client.sort_jobs(&mut jobs, "runner").is_err());
+
+        client
+            .print_jobs_info(
+                &jobs,
+                "table",
+                &["name".to_string(), "artifacts".to_string()],
+            )
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_api_requests_against_mock_server() {
+        let server = MockServer::start().await.unwrap();
+        server.mock(
+            "GET",
+            "/api/v4/projects/group%2Fproject/pipelines?scope=finished&per_page=100",
+            MockResponse::json(
+                200,
+                r#"[{"id": 42, "status": "success", "ref": "main", "sha": "0123456789abcdef",
+                     "web_url": "https://gitlab.com/group/project/-/pipelines/42",
+                     "created_at": "2024-05-01T12:00:00Z", "updated_at": null}]"#,
+            ),
+        );
+        server.mock(
+            "GET",
+            "/api/v4/projects/group%2Fproject/pipelines/42/jobs?per_page=100",
+            MockResponse::json(
+                200,
+                serde_json::to_vec(&[job(7, "build", Some(3))]).unwrap(),
+            ),
+        );
+        server.mock(
+            "GET",
+            "/api/v4/projects/group%2Fproject/jobs/7/artifacts",
+            MockResponse::new(200, "zip"),
+        );
+
+        let config = Config {
+            gitlab_token: Some("glpat-secret".to_string()),
+            ..Default::default()
+        };
+        let client = GitLabClient::new(&config)
+            .unwrap()
+            .with_base_url(server.url());
+
+        let pipelines = client.list_pipelines("group/project").await.unwrap();
+        assert_eq!(pipelines[0].git_ref, "main");
+        let jobs = client.list_jobs("group/project", 42).await.unwrap();
+        assert_eq!(jobs[0].artifacts_file.as_ref().unwrap().size, 3);
+        let content = client.download_artifacts("group/project", 7).await.unwrap();
+        assert_eq!(&content[..], b"zip");
+        let missing = client.download_artifacts("group/project", 8).await;
+        assert!(matches!(missing, Err(MirrorError::NotFound(_))));
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 4);
+        assert!(requests
+            .iter()
+            .all(|r| r.headers.get("private-token").map(String::as_str) == Some("glpat-secret")));
+    }
+}
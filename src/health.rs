@@ -0,0 +1,349 @@
+//! Health, readiness and status endpoints of the daemon
+//!
+//! With `health_listen` set, the daemon answers plain HTTP on that address so
+//! it can run under Kubernetes probes:
+//!
+//! - `GET /healthz` returns 200 while the process is serving requests
+//! - `GET /readyz` returns 200 once every job is scheduled, and 503 before
+//!   that and while the daemon is shutting down
+//! - `GET /status` returns JSON with the schedule, next run and last result of
+//!   every job
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, info};
+
+use crate::error::Result;
+use crate::notify::{Notification, RunStatus};
+use crate::report::MirrorReport;
+use crate::shutdown;
+
+/// Largest request head read before the connection is answered or dropped
+const MAX_REQUEST_HEAD: usize = 8 * 1024;
+/// How long a client may take to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// State of the daemon shared between the jobs and the status endpoints
+#[derive(Debug, Clone)]
+pub struct DaemonStatus {
+    inner: Arc<Mutex<StatusSnapshot>>,
+}
+
+/// What `/status` reports
+#[derive(Debug, Clone, Serialize)]
+pub struct StatusSnapshot {
+    pub ready: bool,
+    pub started_at: DateTime<Utc>,
+    pub jobs: BTreeMap<String, JobStatus>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JobStatus {
+    pub schedule: String,
+    pub timezone: String,
+    pub running: bool,
+    pub next_run: Option<DateTime<Utc>>,
+    pub last_run: Option<LastRun>,
+}
+
+/// Outcome of the most recent run of a job
+#[derive(Debug, Clone, Serialize)]
+pub struct LastRun {
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub status: RunStatus,
+    pub mirrored: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub quarantined: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl LastRun {
+    pub fn new(
+        started_at: DateTime<Utc>,
+        result: &std::result::Result<MirrorReport, impl std::fmt::Display>,
+    ) -> Self {
+        let (notification, counts) = match result {
+            Ok(report) => (
+                Notification::from_report(report),
+                [
+                    report.mirrored_count(),
+                    report.skipped_count(),
+                    report.failed_count(),
+                    report.quarantined.len(),
+                ],
+            ),
+            Err(e) => (Notification::from_error("", "", e), [0; 4]),
+        };
+        let [mirrored, skipped, failed, quarantined] = counts;
+        Self {
+            started_at,
+            finished_at: Utc::now(),
+            status: notification.status,
+            mirrored,
+            skipped,
+            failed,
+            quarantined,
+            error: notification.error,
+        }
+    }
+}
+
+impl Default for DaemonStatus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DaemonStatus {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(StatusSnapshot {
+                ready: false,
+                started_at: Utc::now(),
+                jobs: BTreeMap::new(),
+            })),
+        }
+    }
+
+    fn update<T>(&self, f: impl FnOnce(&mut StatusSnapshot) -> T) -> T {
+        f(&mut self.inner.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    fn update_job(&self, name: &str, f: impl FnOnce(&mut JobStatus)) {
+        self.update(|status| {
+            if let Some(job) = status.jobs.get_mut(name) {
+                f(job);
+            }
+        })
+    }
+
+    pub fn add_job(&self, name: &str, schedule: &str, timezone: &str) {
+        self.update(|status| {
+            status.jobs.insert(
+                name.to_string(),
+                JobStatus {
+                    schedule: schedule.to_string(),
+                    timezone: timezone.to_string(),
+                    running: false,
+                    next_run: None,
+                    last_run: None,
+                },
+            );
+        })
+    }
+
+    /// Mark the daemon ready once all jobs are scheduled
+    pub fn set_ready(&self, ready: bool) {
+        self.update(|status| status.ready = ready)
+    }
+
+    /// Whether the daemon is ready and not shutting down
+    pub fn is_ready(&self) -> bool {
+        self.update(|status| status.ready) && !shutdown::is_requested()
+    }
+
+    pub fn scheduled(&self, name: &str, next_run: Option<DateTime<Utc>>) {
+        self.update_job(name, |job| job.next_run = next_run)
+    }
+
+    pub fn started(&self, name: &str) {
+        self.update_job(name, |job| {
+            job.running = true;
+            job.next_run = None;
+        })
+    }
+
+    pub fn finished(&self, name: &str, last_run: LastRun) {
+        self.update_job(name, |job| {
+            job.running = false;
+            job.last_run = Some(last_run);
+        })
+    }
+
+    pub fn snapshot(&self) -> StatusSnapshot {
+        let mut snapshot = self.update(|status| status.clone());
+        snapshot.ready = self.is_ready();
+        snapshot
+    }
+}
+
+/// Serve the endpoints on `listener` until the task is aborted
+pub async fn serve(listener: TcpListener, status: DaemonStatus) {
+    if let Ok(address) = listener.local_addr() {
+        info!("Serving health and status endpoints on http://{}", address);
+    }
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                debug!("Failed to accept health connection: {}", e);
+                continue;
+            }
+        };
+        let status = status.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle(stream, &status).await {
+                debug!("Health connection failed: {}", e);
+            }
+        });
+    }
+}
+
+/// Bind the endpoint address, e.g. `0.0.0.0:8080`
+pub async fn bind(address: &str) -> Result<TcpListener> {
+    Ok(TcpListener::bind(address).await?)
+}
+
+/// Read the request line and headers; `None` when the client is too slow to send them
+pub(crate) async fn read_request_head(stream: &mut TcpStream) -> std::io::Result<Option<String>> {
+    let mut head = Vec::new();
+    let mut buffer = [0u8; 1024];
+    let read = async {
+        while !head.windows(4).any(|w| w == b"\r\n\r\n") && head.len() < MAX_REQUEST_HEAD {
+            let n = stream.read(&mut buffer).await?;
+            if n == 0 {
+                break;
+            }
+            head.extend_from_slice(&buffer[..n]);
+        }
+        Ok::<_, std::io::Error>(())
+    };
+    let Ok(result) = tokio::time::timeout(REQUEST_TIMEOUT, read).await else {
+        return Ok(None);
+    };
+    result?;
+    Ok(Some(String::from_utf8_lossy(&head).into_owned()))
+}
+
+async fn handle(mut stream: TcpStream, status: &DaemonStatus) -> std::io::Result<()> {
+    let Some(head) = read_request_head(&mut stream).await? else {
+        return Ok(());
+    };
+    let mut request_line = head.lines().next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default();
+    let target = request_line.next().unwrap_or_default();
+    let (code, body) = respond(method, target, status);
+
+    let reason = match code {
+        200 => "OK",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Service Unavailable",
+    };
+    let mut response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        code,
+        reason,
+        if body.starts_with('{') {
+            "application/json"
+        } else {
+            "text/plain; charset=utf-8"
+        },
+        body.len()
+    );
+    if method != "HEAD" {
+        response.push_str(&body);
+    }
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Status code and body for a request
+fn respond(method: &str, target: &str, status: &DaemonStatus) -> (u16, String) {
+    if method != "GET" && method != "HEAD" {
+        return (405, "method not allowed\n".to_string());
+    }
+    let path = target.split('?').next().unwrap_or_default();
+    match path {
+        "/healthz" => (200, "ok\n".to_string()),
+        "/readyz" if status.is_ready() => (200, "ready\n".to_string()),
+        "/readyz" => (503, "not ready\n".to_string()),
+        "/status" => (
+            200,
+            serde_json::to_string_pretty(&status.snapshot()).unwrap_or_default(),
+        ),
+        _ => (404, "not found\n".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::MirrorError;
+
+    #[test]
+    fn test_respond() {
+        let status = DaemonStatus::new();
+        status.add_job("nightly", "0 2 * * *", "UTC");
+
+        assert_eq!(respond("GET", "/healthz", &status).0, 200);
+        assert_eq!(respond("GET", "/readyz", &status).0, 503);
+        status.set_ready(true);
+        assert_eq!(respond("GET", "/readyz?verbose", &status).0, 200);
+        assert_eq!(respond("POST", "/readyz", &status).0, 405);
+        assert_eq!(respond("GET", "/metrics", &status).0, 404);
+
+        status.started("nightly");
+        status.finished(
+            "nightly",
+            LastRun::new(
+                Utc::now(),
+                &Err::<MirrorReport, _>(MirrorError::InvalidInput("bad source".to_string())),
+            ),
+        );
+        let (code, body) = respond("GET", "/status", &status);
+        assert_eq!(code, 200);
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["ready"], true);
+        let job = &json["jobs"]["nightly"];
+        assert_eq!(job["schedule"], "0 2 * * *");
+        assert_eq!(job["running"], false);
+        assert_eq!(job["last_run"]["status"], "failure");
+        assert_eq!(job["last_run"]["error"], "Invalid input: bad source");
+    }
+
+    #[tokio::test]
+    async fn test_serve() {
+        let listener = bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let status = DaemonStatus::new();
+        status.add_job("nightly", "0 2 * * *", "Europe/Berlin");
+        let server = tokio::spawn(serve(listener, status.clone()));
+
+        let client = reqwest::Client::new();
+        let response = client
+            .get(format!("http://{}/healthz", address))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.text().await.unwrap(), "ok\n");
+
+        let response = client
+            .get(format!("http://{}/readyz", address))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 503);
+
+        let status: serde_json::Value = client
+            .get(format!("http://{}/status", address))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(status["jobs"]["nightly"]["timezone"], "Europe/Berlin");
+
+        server.abort();
+    }
+}
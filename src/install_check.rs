@@ -0,0 +1,287 @@
+//! End-to-end install check of a mirror
+//!
+//! The `check-install` command solves specs against a mirror alone, like
+//! `lock`, then installs the solution into a temporary prefix with rattler's
+//! installer, as pixi does: every package is downloaded from the URL of its
+//! repodata record, checked against the record's size and sha256 (or md5) and
+//! extracted into a package cache, and the installer links the packages into
+//! the prefix in dependency order. It replaces prefix placeholders, resolves
+//! files clobbered by several packages, places the files of noarch python
+//! packages in the site-packages of the solved Python with their entry points,
+//! and writes a `conda-meta` record for each package. A successful check proves
+//! that the repodata and the packages it points to are consumable by real
+//! clients, not just that they exist.
+//!
+//! Pre- and post-link scripts are run when the check installs for the platform
+//! it runs on; packages for other platforms cannot run theirs.
+
+use md5::Md5;
+use rattler::install::Installer;
+use rattler::package_cache::PackageCache;
+use rattler_conda_types::{PackageRecord, Platform, PrefixRecord, RepoDataRecord};
+use reqwest::Client;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::download::verify_download_size;
+use crate::error::{MirrorError, Result};
+use crate::lockfile;
+use crate::mirror::download_package;
+use crate::scratch;
+
+/// What a successful install check installed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstallCheck {
+    pub platform: Platform,
+    /// Filenames of the installed packages, in install order
+    pub packages: Vec<String>,
+    /// Number of files, links and directories created in the prefix
+    pub files: usize,
+}
+
+/// Solve `specs` for `platform` against `channel` and install the solution into a prefix
+///
+/// Without `prefix`, a temporary prefix is created under `scratch_dir` and
+/// removed afterwards; a given prefix is kept and must not hold files yet.
+pub async fn check_install(
+    client: &Client,
+    channel: &str,
+    specs: &[String],
+    platform: Platform,
+    prefix: Option<&Path>,
+    config: &Config,
+) -> Result<InstallCheck> {
+    let match_specs = lockfile::match_specs(specs)?;
+    let records = lockfile::solve_platform(client, channel, &match_specs, platform).await?;
+
+    let dir = scratch::tempdir(config.scratch_dir.as_deref().map(Path::new))?;
+    let prefix = match prefix {
+        Some(prefix) => {
+            if std::fs::read_dir(prefix).is_ok_and(|mut entries| entries.next().is_some()) {
+                return Err(MirrorError::InvalidInput(format!(
+                    "Prefix {} is not empty",
+                    prefix.display()
+                )));
+            }
+            prefix.to_path_buf()
+        }
+        None => dir.path().join("prefix"),
+    };
+
+    // Packages are downloaded and verified here, through the mirror's own
+    // client, so that the installer finds every one of them in its cache
+    let package_cache = PackageCache::new(dir.path().join("pkgs"));
+    for record in &records {
+        let content = fetch(client, record, config).await?;
+        verify(record, &content)?;
+        let filename = record.file_name.clone();
+        package_cache
+            .get_or_fetch(
+                &record.package_record,
+                move |destination| {
+                    let (content, filename) = (content.clone(), filename.clone());
+                    async move { extract(&content, &filename, &destination) }
+                },
+                None,
+            )
+            .await
+            .map_err(|e| extraction_failed(&record.file_name, e.to_string()))?;
+    }
+
+    let installed = Installer::new()
+        .with_package_cache(package_cache)
+        .with_target_platform(platform)
+        .with_execute_link_scripts(platform == Platform::current())
+        .install(&prefix, records.clone())
+        .await
+        .map_err(|e| MirrorError::InstallFailed(format!("{:#}", anyhow::Error::from(e))))?;
+    for (kind, result) in [
+        ("pre-link", installed.pre_link_script_result.map(Ok)),
+        ("post-link", installed.post_link_script_result),
+    ] {
+        let failed = match result {
+            Some(Ok(result)) => result.failed_packages,
+            Some(Err(e)) => return Err(MirrorError::InstallFailed(e.to_string())),
+            None => continue,
+        };
+        if !failed.is_empty() {
+            let names: Vec<&str> = failed.iter().map(|name| name.as_normalized()).collect();
+            return Err(MirrorError::InstallFailed(format!(
+                "{} scripts of {} failed",
+                kind,
+                names.join(", ")
+            )));
+        }
+    }
+
+    let files = PrefixRecord::collect_from_prefix::<PrefixRecord>(&prefix)?
+        .iter()
+        .map(|record| record.paths_data.paths.len())
+        .sum();
+    let check = InstallCheck {
+        platform,
+        packages: PackageRecord::sort_topologically(records)
+            .into_iter()
+            .map(|record| record.file_name)
+            .collect(),
+        files,
+    };
+    info!(
+        "Installed {} packages ({} files) for {} from {}",
+        check.packages.len(),
+        check.files,
+        platform,
+        channel
+    );
+    Ok(check)
+}
+
+/// Download a package from the URL of its repodata record, or read it for a local channel
+async fn fetch(client: &Client, record: &RepoDataRecord, config: &Config) -> Result<bytes::Bytes> {
+    if record.url.scheme() == "file" {
+        let path = record.url.to_file_path().map_err(|_| {
+            MirrorError::InvalidInput(format!("Invalid package location {}", record.url))
+        })?;
+        return match tokio::fs::read(&path).await {
+            Ok(content) => Ok(content.into()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(MirrorError::NotFound(
+                format!("{} is listed in the repodata but missing", path.display()),
+            )),
+            Err(e) => Err(e.into()),
+        };
+    }
+    download_package(client, record.url.as_str(), config).await
+}
+
+/// Check a package against the size and checksum of its repodata record
+fn verify(record: &RepoDataRecord, content: &[u8]) -> Result<()> {
+    let url = record.url.as_str();
+    verify_download_size(url, record.package_record.size, content.len())?;
+    let (algorithm, expected, actual) =
+        match (&record.package_record.sha256, &record.package_record.md5) {
+            (Some(expected), _) => (
+                "sha256",
+                format!("{:x}", expected),
+                format!("{:x}", Sha256::digest(content)),
+            ),
+            (None, Some(expected)) => (
+                "md5",
+                format!("{:x}", expected),
+                format!("{:x}", Md5::digest(content)),
+            ),
+            (None, None) => {
+                warn!("{} has no checksum in the repodata", record.file_name);
+                return Ok(());
+            }
+        };
+    if actual != expected {
+        return Err(MirrorError::Corrupt(format!(
+            "{} of {} is {}, repodata lists {}",
+            algorithm, url, actual, expected
+        )));
+    }
+    Ok(())
+}
+
+fn extract(content: &[u8], filename: &str, destination: &Path) -> Result<()> {
+    let extracted = if filename.ends_with(".conda") {
+        rattler_package_streaming::read::extract_conda_via_streaming(content, destination)
+    } else {
+        rattler_package_streaming::read::extract_tar_bz2(content, destination)
+    };
+    extracted.map_err(|e| extraction_failed(filename, format!("extraction failed: {}", e)))?;
+    Ok(())
+}
+
+fn extraction_failed(filename: &str, reason: String) -> MirrorError {
+    MirrorError::ExtractionFailed {
+        filename: filename.to_string(),
+        reason,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conda_package::CondaPackageHandler;
+    use crate::test_support::PackageFixture;
+
+    #[tokio::test]
+    async fn test_check_install_from_local_channel() {
+        let channel_dir = tempfile::TempDir::new().unwrap();
+        let subdir = channel_dir.path().join("linux-64");
+        std::fs::create_dir_all(&subdir).unwrap();
+        let fixtures = [
+            PackageFixture::new("app", "2.0")
+                .subdir("linux-64")
+                .depends(["lib >=1.5"]),
+            PackageFixture::new("lib", "1.6").subdir("linux-64"),
+        ];
+        let mut handler = CondaPackageHandler::new();
+        let mut packages = Vec::new();
+        for fixture in &fixtures {
+            let content = fixture.to_conda();
+            std::fs::write(subdir.join(fixture.conda_filename()), &content).unwrap();
+            packages.push(
+                handler
+                    .process_package(content, &fixture.conda_filename())
+                    .await
+                    .unwrap(),
+            );
+        }
+        handler
+            .create_repodata(&Platform::Linux64, &packages, channel_dir.path())
+            .await
+            .unwrap();
+        let channel = channel_dir.path().to_string_lossy().to_string();
+        let config = Config::default();
+
+        let prefix_dir = tempfile::TempDir::new().unwrap();
+        let check = check_install(
+            &Client::new(),
+            &channel,
+            &["app".to_string()],
+            Platform::Linux64,
+            Some(prefix_dir.path()),
+            &config,
+        )
+        .await
+        .unwrap();
+        // Dependencies are installed before the packages that need them
+        assert_eq!(check.packages, vec!["lib-1.6-0.conda", "app-2.0-0.conda"]);
+        assert_eq!(check.files, 2);
+        let prefix = prefix_dir.path();
+        assert!(prefix.join("share/app/README").is_file());
+        assert!(prefix.join("conda-meta/lib-1.6-0.json").is_file());
+
+        // The prefix given must be empty
+        let result = check_install(
+            &Client::new(),
+            &channel,
+            &["app".to_string()],
+            Platform::Linux64,
+            Some(prefix),
+            &config,
+        )
+        .await;
+        assert!(matches!(result, Err(MirrorError::InvalidInput(_))));
+
+        // A package that does not match its repodata record fails the check
+        std::fs::write(subdir.join("lib-1.6-0.conda"), fixtures[0].to_conda()).unwrap();
+        let result = check_install(
+            &Client::new(),
+            &channel,
+            &["app".to_string()],
+            Platform::Linux64,
+            None,
+            &config,
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(MirrorError::Truncated { .. } | MirrorError::Corrupt(_))
+        ));
+    }
+}
@@ -4,17 +4,97 @@
 //! This library provides enhanced functionality through integration with the rattler ecosystem
 //! for proper conda package handling, validation, and repository structure management.
 
+pub mod allowlist;
+pub mod auth;
 pub mod azure;
+pub mod builder;
+pub mod channel_config;
+pub mod channel_snapshot;
+pub mod channel_source;
+pub mod channeldata;
+pub mod circuit_breaker;
 pub mod conda_package;
 pub mod config;
+#[cfg(feature = "schedule")]
+pub mod daemon;
+pub mod download;
+pub mod drift;
+pub mod email;
+pub mod enrich;
+pub mod error;
+pub mod extraction;
 pub mod github;
+pub mod gitlab;
+pub mod health;
+#[cfg(feature = "solve")]
+pub mod install_check;
+pub mod limits;
+pub mod listing;
+pub mod listing_cache;
+#[cfg(feature = "solve")]
+pub mod lockfile;
+pub mod manifest;
 pub mod mirror;
+#[cfg(feature = "s3")]
+pub mod multipart;
+pub mod naming;
+pub mod notify;
+pub mod oci;
+pub mod osv;
+pub mod policy;
+pub mod politeness;
+pub mod presets;
+pub mod priority;
+pub mod promote;
+pub mod provenance;
+pub mod quarantine;
+pub mod remote_zip;
+pub mod report;
 pub mod repository;
-
-pub use conda_package::{CondaPackageHandler, PackageStats, ProcessedPackage, SimpleIndexJson};
+pub mod resume;
+pub mod retention;
+pub mod review;
+pub mod sbom;
+pub mod scratch;
+pub mod serve;
+#[cfg(feature = "sharded-repodata")]
+pub mod shards;
+pub mod share;
+pub mod shortcuts;
+pub mod shutdown;
+pub mod signing;
+pub mod snapshot;
+pub mod source;
+pub mod specs;
+#[cfg(feature = "state-db")]
+pub mod state;
+pub mod suggest;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_support;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+pub mod upstream;
+#[cfg(feature = "schedule")]
+pub mod window;
+
+pub use async_trait::async_trait;
+pub use builder::{Mirror, MirrorBuilder, Source, Target};
+pub use conda_package::{
+    CondaPackageHandler, MetadataSource, PackageStats, ProcessedPackage, SimpleIndexJson,
+};
 pub use config::Config;
-pub use mirror::mirror_packages;
-pub use repository::{Repository, RepositoryType};
+pub use error::MirrorError;
+pub use mirror::{mirror_from_provider, mirror_packages, mirror_sources};
+pub use report::{MirrorReport, PackageOutcome, PackageReport, QuarantinedArchive};
+#[cfg(feature = "s3")]
+pub use repository::S3Backend;
+pub use repository::{
+    CacheBackend, FailedUpload, LocalBackend, PrefixDevBackend, Repository, RepositoryBackend,
+    RepositoryType, UploadBatching, UploadStatus,
+};
+pub use source::{PackageEntry, PackageStream, SourceProvider};
+#[cfg(feature = "state-db")]
+pub use state::StateDb;
 
 #[cfg(test)]
 mod tests {
@@ -123,9 +203,11 @@ mod tests {
             build_number: 0,
             depends: vec!["python >=3.7".to_string()],
             license: Some("MIT".to_string()),
+            license_family: Some("MIT".to_string()),
             platform: Some("linux".to_string()),
             subdir: Some("linux-64".to_string()),
             arch: Some("x86_64".to_string()),
+            noarch: None,
             timestamp: Some(chrono::Utc::now()),
         };
 
@@ -138,6 +220,7 @@ mod tests {
             size: mock_content.len() as u64,
             md5: format!("{:x}", md5::Md5::digest(&mock_content)),
             sha256: format!("{:x}", sha2::Sha256::digest(&mock_content)),
+            metadata_source: MetadataSource::Rattler,
         };
 
         assert!(!processed.filename.is_empty());
@@ -215,21 +298,24 @@ pub mod examples {
     //!
     //! #[tokio::main]
     //! async fn main() -> anyhow::Result<()> {
-    //!     let sources = vec![
-    //!         "https://example.com/package1.conda".to_string(),
-    //!         "https://example.com/package2.conda".to_string(),
-    //!     ];
-    //!
     //!     let config = Config::default();
     //!
-    //!     // Mirror to Rattler cache
-    //!     mirror_packages(
-    //!         &sources,
+    //!     // Mirror a single remote package into a local conda repository
+    //!     let report = mirror_packages(
+    //!         "https://example.com/package1.conda",
+    //!         None,
+    //!         "url",
+    //!         false,
     //!         RepositoryType::Local,
-    //!         "~/.cache/rattler/cache/pkgs/",
-    //!         &config
+    //!         "./conda-repo",
+    //!         &config,
     //!     ).await?;
     //!
+    //!     report.print_summary();
+    //!     for (filename, error) in report.failures() {
+    //!         eprintln!("{} failed: {}", filename, error);
+    //!     }
+    //!
     //!     Ok(())
     //! }
     //! ```
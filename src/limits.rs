@@ -0,0 +1,157 @@
+//! Limits shared by every run in the process
+//!
+//! `max_connections_per_host`, `max_bytes_per_second` and
+//! `max_concurrent_downloads` hold back one run. When the daemon runs several
+//! jobs at once their sum can still swamp an upstream or the machine, so three
+//! limits apply to all runs of the process together:
+//! `global_max_connections` requests in flight, `global_max_bytes_per_second`
+//! of package downloads, and `global_max_buffered_bytes` of packages fetched
+//! ahead of their upload. Like the host throttle, the limits are set up from
+//! the first configuration that asks for them; the daemon passes its own.
+
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::Instant;
+
+use crate::config::Config;
+
+/// Buffered bytes are counted in units of this size
+const BUFFER_UNIT: u64 = 1024;
+
+/// Process-wide caps on requests, download rate and buffered package content
+#[derive(Debug)]
+pub struct GlobalLimits {
+    requests: Option<Arc<Semaphore>>,
+    bytes_per_second: u64,
+    /// When the bytes accounted for so far have been transferred at the allowed rate
+    transfers_due: Mutex<Option<Instant>>,
+    buffer: Option<Arc<Semaphore>>,
+    buffer_units: u32,
+}
+
+impl GlobalLimits {
+    /// A limit of 0 leaves that resource unlimited
+    pub fn new(max_connections: usize, bytes_per_second: u64, max_buffered_bytes: u64) -> Self {
+        let buffer_units = (max_buffered_bytes.div_ceil(BUFFER_UNIT)).min(u32::MAX as u64) as u32;
+        Self {
+            requests: (max_connections > 0).then(|| Arc::new(Semaphore::new(max_connections))),
+            bytes_per_second,
+            transfers_due: Mutex::new(None),
+            buffer: (buffer_units > 0).then(|| Arc::new(Semaphore::new(buffer_units as usize))),
+            buffer_units,
+        }
+    }
+
+    /// Wait for a request slot, held until the returned permit is dropped
+    pub async fn request(&self) -> Option<OwnedSemaphorePermit> {
+        let requests = Arc::clone(self.requests.as_ref()?);
+        Some(requests.acquire_owned().await.expect("never closed"))
+    }
+
+    /// Account for `bytes` downloaded by any run, waiting until they fit the global rate
+    ///
+    /// Transfers are queued one after the other at the allowed rate, so idle
+    /// time does not build up credit for a later burst.
+    pub async fn transferred(&self, bytes: u64) {
+        if self.bytes_per_second == 0 {
+            return;
+        }
+        let duration = Duration::from_secs_f64(bytes as f64 / self.bytes_per_second as f64);
+        let due = {
+            let mut transfers_due = self.transfers_due.lock().unwrap();
+            let start = transfers_due.map_or(Instant::now(), |due| due.max(Instant::now()));
+            *transfers_due = Some(start + duration);
+            start + duration
+        };
+        tokio::time::sleep_until(due).await;
+    }
+
+    /// Reserve room for `bytes` of package content kept in memory, released with the permit
+    ///
+    /// A package larger than the whole budget waits until nothing else is buffered.
+    pub async fn reserve(&self, bytes: u64) -> Option<OwnedSemaphorePermit> {
+        let buffer = Arc::clone(self.buffer.as_ref()?);
+        let units = (bytes.div_ceil(BUFFER_UNIT)).clamp(1, self.buffer_units as u64) as u32;
+        Some(
+            buffer
+                .acquire_many_owned(units)
+                .await
+                .expect("never closed"),
+        )
+    }
+}
+
+static LIMITS: OnceLock<GlobalLimits> = OnceLock::new();
+
+/// Limits shared by every run in this process, created from the first configuration
+pub fn shared(config: &Config) -> &'static GlobalLimits {
+    LIMITS.get_or_init(|| {
+        GlobalLimits::new(
+            config.global_max_connections,
+            config.global_max_bytes_per_second,
+            config.global_max_buffered_bytes,
+        )
+    })
+}
+
+/// The shared limits, if any run has set them up yet
+pub fn current() -> Option<&'static GlobalLimits> {
+    LIMITS.get()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_requests_are_capped() {
+        let limits = GlobalLimits::new(2, 0, 0);
+        let running = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        let request = || async {
+            let _permit = limits.request().await;
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            peak.fetch_max(now, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            running.fetch_sub(1, Ordering::SeqCst);
+        };
+        futures::future::join_all((0..6).map(|_| request())).await;
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+
+        assert!(GlobalLimits::new(0, 0, 0).request().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_transfers_share_the_rate() {
+        let limits = GlobalLimits::new(0, 10_000, 0);
+        let started = std::time::Instant::now();
+        futures::future::join(limits.transferred(500), limits.transferred(500)).await;
+        assert!(started.elapsed() >= Duration::from_millis(100));
+
+        // Idle time builds no credit
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let started = std::time::Instant::now();
+        limits.transferred(500).await;
+        assert!(started.elapsed() >= Duration::from_millis(40));
+    }
+
+    #[tokio::test]
+    async fn test_buffered_bytes_are_bounded() {
+        let limits = GlobalLimits::new(0, 0, 4 * BUFFER_UNIT);
+        let first = limits.reserve(3 * BUFFER_UNIT).await.unwrap();
+        let waiting = limits.reserve(2 * BUFFER_UNIT);
+        tokio::pin!(waiting);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(20), &mut waiting)
+                .await
+                .is_err()
+        );
+        drop(first);
+        assert!(waiting.await.is_some());
+
+        // Oversized packages still get through, one at a time
+        assert!(limits.reserve(100 * BUFFER_UNIT).await.is_some());
+    }
+}
@@ -0,0 +1,101 @@
+//! Column selection and sorting for the `info` listings
+//!
+//! Table listings name their columns by key: the header in lowercase with
+//! dashes, e.g. `finish-time` for "Finish Time". `--columns` picks and orders
+//! columns by key, and `--sort-by` orders rows by the column with that key.
+
+use std::cmp::Ordering;
+
+use crate::error::{MirrorError, Result};
+
+/// Key naming a column in `--columns` and `--sort-by`
+pub fn column_key(header: &str) -> String {
+    header.to_lowercase().replace(' ', "-")
+}
+
+/// Indices of the columns to show, in the requested order; all when `columns` is empty
+pub fn selected_columns(headers: &[&str], columns: &[String]) -> Result<Vec<usize>> {
+    if columns.is_empty() {
+        return Ok((0..headers.len()).collect());
+    }
+    columns
+        .iter()
+        .map(|column| {
+            let key = column_key(column.trim());
+            headers
+                .iter()
+                .position(|header| column_key(header) == key)
+                .ok_or_else(|| unknown_column(column, headers))
+        })
+        .collect()
+}
+
+/// Sort `items` by the column `sort_by` names, using `compare` for that column's key
+///
+/// `compare` returns `None` for keys of columns that cannot be sorted by.
+pub fn sort_by_column<T>(
+    items: &mut [T],
+    headers: &[&str],
+    sort_by: &str,
+    compare: impl Fn(&str, &T, &T) -> Option<Ordering>,
+) -> Result<()> {
+    let key = column_key(sort_by.trim());
+    if !headers.iter().any(|header| column_key(header) == key) {
+        return Err(unknown_column(sort_by, headers));
+    }
+    if let [first, second, ..] = items {
+        if compare(&key, first, second).is_none() {
+            return Err(MirrorError::InvalidInput(format!(
+                "Cannot sort by column '{}'",
+                sort_by
+            )));
+        }
+    }
+    items.sort_by(|a, b| compare(&key, a, b).unwrap_or(Ordering::Equal));
+    Ok(())
+}
+
+fn unknown_column(column: &str, headers: &[&str]) -> MirrorError {
+    MirrorError::InvalidInput(format!(
+        "Unknown column '{}'. Available columns: {}",
+        column,
+        headers
+            .iter()
+            .map(|header| column_key(header))
+            .collect::<Vec<_>>()
+            .join(", ")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADERS: &[&str] = &["ID", "Name", "Finish Time"];
+
+    #[test]
+    fn test_selected_columns() {
+        assert_eq!(selected_columns(HEADERS, &[]).unwrap(), vec![0, 1, 2]);
+        assert_eq!(
+            selected_columns(HEADERS, &["finish-time".to_string(), "ID".to_string()]).unwrap(),
+            vec![2, 0]
+        );
+        assert!(selected_columns(HEADERS, &["size".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_sort_by_column() {
+        let compare = |key: &str, a: &(u64, &str), b: &(u64, &str)| match key {
+            "id" => Some(a.0.cmp(&b.0)),
+            "name" => Some(a.1.cmp(b.1)),
+            _ => None,
+        };
+        let mut items = vec![(2, "a"), (1, "b")];
+        sort_by_column(&mut items, HEADERS, "id", compare).unwrap();
+        assert_eq!(items, vec![(1, "b"), (2, "a")]);
+        sort_by_column(&mut items, HEADERS, "Name", compare).unwrap();
+        assert_eq!(items, vec![(2, "a"), (1, "b")]);
+        assert!(sort_by_column(&mut items, HEADERS, "finish-time", compare).is_err());
+        assert!(sort_by_column(&mut items, HEADERS, "size", compare).is_err());
+    }
+}
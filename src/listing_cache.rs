@@ -0,0 +1,116 @@
+//! Short-lived cache of GitHub and Azure DevOps listings
+//!
+//! With `listing_cache_dir` set, artifact and build listings are kept there
+//! for `listing_cache_ttl_seconds`, so an `info` followed by a `mirror` of the
+//! same source a few minutes later reads the listing from disk instead of
+//! querying the API, and spending rate limit, again. Entries are keyed by the
+//! request URL and whether it was authenticated; unreadable and expired entries
+//! are fetched anew.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use tracing::{debug, warn};
+
+use crate::config::Config;
+
+/// Listings stored on disk for a limited time
+#[derive(Debug, Clone)]
+pub struct ListingCache {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry<T> {
+    key: String,
+    fetched_at: DateTime<Utc>,
+    value: T,
+}
+
+impl ListingCache {
+    pub fn new(dir: impl Into<PathBuf>, ttl_seconds: u64) -> Self {
+        Self {
+            dir: dir.into(),
+            ttl: Duration::seconds(ttl_seconds.try_into().unwrap_or(i64::MAX)),
+        }
+    }
+
+    /// The cache configured by `listing_cache_dir`, `None` when it is unset or the TTL is 0
+    pub fn from_config(config: &Config) -> Option<Self> {
+        match (&config.listing_cache_dir, config.listing_cache_ttl_seconds) {
+            (Some(dir), ttl_seconds) if ttl_seconds > 0 => Some(Self::new(dir, ttl_seconds)),
+            _ => None,
+        }
+    }
+
+    /// Key of a listing request; authenticated requests may see more than anonymous ones
+    pub fn key(url: &str, authenticated: bool) -> String {
+        let access = if authenticated { "token" } else { "anonymous" };
+        format!("{} {}", access, url)
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir
+            .join(format!("{:x}.json", Sha256::digest(key.as_bytes())))
+    }
+
+    /// The listing stored under `key`, unless it is missing, unreadable or expired
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let content = std::fs::read(self.path(key)).ok()?;
+        let entry: Entry<T> = serde_json::from_slice(&content).ok()?;
+        if entry.key != key || Utc::now() - entry.fetched_at >= self.ttl {
+            return None;
+        }
+        debug!("Using cached listing for {}", key);
+        Some(entry.value)
+    }
+
+    /// Store a listing under `key`; failures only cost a refetch, so they are logged
+    pub fn put<T: Serialize>(&self, key: &str, value: &T) {
+        let entry = Entry {
+            key: key.to_string(),
+            fetched_at: Utc::now(),
+            value,
+        };
+        let path = self.path(key);
+        let partial = path.with_extension("json.partial");
+        let written = std::fs::create_dir_all(&self.dir)
+            .and_then(|_| std::fs::write(&partial, serde_json::to_vec(&entry)?))
+            .and_then(|_| std::fs::rename(&partial, &path));
+        if let Err(e) = written {
+            warn!("Failed to cache listing in {}: {}", self.dir.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_and_put() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let cache = ListingCache::new(temp_dir.path().join("listings"), 300);
+        let key = ListingCache::key("https://api.github.com/repos/o/r/actions/artifacts", true);
+        assert_eq!(cache.get::<Vec<u64>>(&key), None);
+
+        cache.put(&key, &vec![1u64, 2]);
+        assert_eq!(cache.get::<Vec<u64>>(&key), Some(vec![1, 2]));
+        let anonymous =
+            ListingCache::key("https://api.github.com/repos/o/r/actions/artifacts", false);
+        assert_eq!(cache.get::<Vec<u64>>(&anonymous), None);
+
+        let expired = ListingCache::new(temp_dir.path().join("listings"), 0);
+        assert_eq!(expired.get::<Vec<u64>>(&key), None);
+
+        let config = Config {
+            listing_cache_dir: Some(temp_dir.path().to_string_lossy().to_string()),
+            ..Config::default()
+        };
+        assert!(ListingCache::from_config(&config).is_some());
+        assert!(ListingCache::from_config(&Config::default()).is_none());
+    }
+}
@@ -0,0 +1,292 @@
+//! Lockfiles solved against a mirror
+//!
+//! The `lock` command solves an environment using only the packages of a
+//! mirror and writes the solution as a `pixi.lock` (the rattler lock-file
+//! format). A successful solve proves the mirror is self-sufficient for that
+//! environment: every package the lockfile names is served by the mirror.
+
+use rattler_conda_types::{
+    Channel, GenericVirtualPackage, MatchSpec, ParseStrictness, Platform, RepoData, RepoDataRecord,
+};
+use rattler_lock::{LockFile, DEFAULT_ENVIRONMENT_NAME};
+use rattler_solve::{resolvo, SolverImpl, SolverTask};
+use rattler_virtual_packages::{VirtualPackageOverrides, VirtualPackages};
+use reqwest::Client;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::Path;
+use tracing::{info, warn};
+use url::Url;
+
+use crate::error::{MirrorError, Result};
+use crate::sbom::read_repodata;
+
+/// The parts of a conda `environment.yml` the solve uses
+///
+/// The `channels` of the file are ignored: the environment is always solved
+/// against the mirror alone.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct EnvironmentSpec {
+    #[serde(default)]
+    pub dependencies: Vec<serde_yaml::Value>,
+}
+
+impl EnvironmentSpec {
+    pub fn from_path(path: &str) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        serde_yaml::from_str(&content).map_err(|e| {
+            MirrorError::InvalidInput(format!("Invalid environment file {}: {}", path, e))
+        })
+    }
+
+    /// Conda match specs of the environment; `pip:` sections are skipped
+    pub fn specs(&self) -> Vec<String> {
+        self.dependencies
+            .iter()
+            .filter_map(|dependency| match dependency {
+                serde_yaml::Value::String(spec) => Some(spec.clone()),
+                other => {
+                    warn!("Skipping non-conda dependency {:?}", other);
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+/// URL of a channel given as a URL or a local path
+fn channel_url(channel: &str) -> Result<Url> {
+    if channel.starts_with("http://") || channel.starts_with("https://") {
+        let channel = format!("{}/", channel.trim_end_matches('/'));
+        return Url::parse(&channel).map_err(|e| {
+            MirrorError::InvalidInput(format!("Invalid channel URL {}: {}", channel, e))
+        });
+    }
+    let path = std::fs::canonicalize(Path::new(channel))?;
+    Url::from_directory_path(&path)
+        .map_err(|_| MirrorError::InvalidInput(format!("Invalid channel path {}", path.display())))
+}
+
+/// Records of the `platform` and `noarch` subdirs of a channel
+async fn load_records(
+    client: &Client,
+    channel: &str,
+    platform: Platform,
+) -> Result<Vec<RepoDataRecord>> {
+    let rattler_channel = Channel::from_url(channel_url(channel)?);
+    let mut records = Vec::new();
+    for subdir in [platform, Platform::NoArch] {
+        let Some(content) = read_repodata(client, channel, subdir.as_str()).await? else {
+            continue;
+        };
+        let repodata: RepoData = serde_json::from_str(&content).map_err(|e| {
+            MirrorError::InvalidResponse(format!("Invalid repodata.json for {}: {}", subdir, e))
+        })?;
+        records.extend(repodata.into_repo_data_records(&rattler_channel));
+    }
+    Ok(records)
+}
+
+fn virtual_packages(platform: Platform) -> Result<Vec<GenericVirtualPackage>> {
+    let detected =
+        VirtualPackages::detect_for_platform(platform, &VirtualPackageOverrides::from_env())
+            .map_err(|e| {
+                MirrorError::InvalidInput(format!(
+                    "Cannot determine virtual packages for {}: {}",
+                    platform, e
+                ))
+            })?;
+    Ok(detected.into_generic_virtual_packages().collect())
+}
+
+/// Filenames of the conda packages a lockfile references, in any environment and platform
+pub fn referenced_packages(path: &Path) -> Result<HashSet<String>> {
+    let lock = LockFile::from_path(path).map_err(|e| {
+        MirrorError::InvalidInput(format!("Failed to read lockfile {}: {}", path.display(), e))
+    })?;
+    Ok(lock
+        .environments()
+        .flat_map(|(_, environment)| {
+            environment
+                .conda_packages_by_platform()
+                .flat_map(|(_, packages)| packages)
+                .filter_map(|package| package.location().file_name())
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        })
+        .collect())
+}
+
+/// Parse conda match specs, leniently as conda does
+pub(crate) fn match_specs(specs: &[String]) -> Result<Vec<MatchSpec>> {
+    if specs.is_empty() {
+        return Err(MirrorError::InvalidInput(
+            "The environment has no dependencies to solve".to_string(),
+        ));
+    }
+    specs
+        .iter()
+        .map(|spec| {
+            MatchSpec::from_str(spec, ParseStrictness::Lenient).map_err(|e| {
+                MirrorError::InvalidInput(format!("Invalid match spec '{}': {}", spec, e))
+            })
+        })
+        .collect()
+}
+
+/// Solve `specs` for one platform using only the packages of `channel`
+pub(crate) async fn solve_platform(
+    client: &Client,
+    channel: &str,
+    specs: &[MatchSpec],
+    platform: Platform,
+) -> Result<Vec<RepoDataRecord>> {
+    let records = load_records(client, channel, platform).await?;
+    let virtual_packages = virtual_packages(platform)?;
+    let specs = specs.to_vec();
+    let solution = tokio::task::spawn_blocking(move || {
+        let task = SolverTask {
+            virtual_packages,
+            specs,
+            ..SolverTask::from_iter([&records])
+        };
+        resolvo::Solver.solve(task)
+    })
+    .await
+    .map_err(|e| MirrorError::Other(e.into()))?
+    .map_err(|e| MirrorError::Unsolvable {
+        platform: platform.to_string(),
+        message: e.to_string(),
+    })?;
+
+    info!(
+        "Solved {} packages for {} from {}",
+        solution.records.len(),
+        platform,
+        channel
+    );
+    Ok(solution.records)
+}
+
+/// Solve `specs` for each platform using only the packages of `channel`
+///
+/// Virtual packages are those of the current machine for its own platform and
+/// minimal defaults for other platforms; `CONDA_OVERRIDE_*` variables
+/// override them.
+pub async fn solve(
+    client: &Client,
+    channel: &str,
+    specs: &[String],
+    platforms: &[Platform],
+) -> Result<LockFile> {
+    let match_specs = match_specs(specs)?;
+
+    let mut builder = LockFile::builder();
+    builder.set_channels(
+        DEFAULT_ENVIRONMENT_NAME,
+        [channel_url(channel)?.to_string()],
+    );
+    for &platform in platforms {
+        for record in solve_platform(client, channel, &match_specs, platform).await? {
+            builder.add_conda_package(DEFAULT_ENVIRONMENT_NAME, platform, record.into());
+        }
+    }
+    Ok(builder.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conda_package::CondaPackageHandler;
+    use crate::test_support::PackageFixture;
+
+    async fn channel() -> tempfile::TempDir {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let fixtures = [
+            PackageFixture::new("app", "2.0")
+                .subdir("linux-64")
+                .depends(["lib >=1.5"]),
+            PackageFixture::new("lib", "1.0").subdir("linux-64"),
+            PackageFixture::new("lib", "1.6").subdir("linux-64"),
+            PackageFixture::new("orphan", "1.0")
+                .subdir("linux-64")
+                .depends(["absent"]),
+        ];
+        let mut handler = CondaPackageHandler::new();
+        let mut packages = Vec::new();
+        for fixture in &fixtures {
+            packages.push(
+                handler
+                    .process_package(fixture.to_conda(), &fixture.conda_filename())
+                    .await
+                    .unwrap(),
+            );
+        }
+        handler
+            .create_repodata(&Platform::Linux64, &packages, temp_dir.path())
+            .await
+            .unwrap();
+        temp_dir
+    }
+
+    #[tokio::test]
+    async fn test_solve_against_mirror() {
+        let temp_dir = channel().await;
+        let channel = temp_dir.path().to_string_lossy().to_string();
+
+        let lock = solve(
+            &Client::new(),
+            &channel,
+            &["app".to_string()],
+            &[Platform::Linux64],
+        )
+        .await
+        .unwrap();
+        let environment = lock.default_environment().unwrap();
+        let mut solved: Vec<String> = environment
+            .conda_repodata_records(Platform::Linux64)
+            .unwrap()
+            .unwrap()
+            .into_iter()
+            .map(|record| {
+                format!(
+                    "{}={}",
+                    record.package_record.name.as_normalized(),
+                    record.package_record.version
+                )
+            })
+            .collect();
+        solved.sort();
+        assert_eq!(solved, vec!["app=2.0", "lib=1.6"]);
+        assert!(lock.render_to_string().unwrap().contains("lib-1.6-0.conda"));
+
+        let lock_path = temp_dir.path().join("pixi.lock");
+        lock.to_path(&lock_path).unwrap();
+        let mut referenced: Vec<String> = referenced_packages(&lock_path)
+            .unwrap()
+            .into_iter()
+            .collect();
+        referenced.sort();
+        assert_eq!(referenced, vec!["app-2.0-0.conda", "lib-1.6-0.conda"]);
+        assert!(referenced_packages(&temp_dir.path().join("missing.lock")).is_err());
+
+        let error = solve(
+            &Client::new(),
+            &channel,
+            &["orphan".to_string()],
+            &[Platform::Linux64],
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(error, MirrorError::Unsolvable { .. }));
+    }
+
+    #[test]
+    fn test_environment_spec() {
+        let spec: EnvironmentSpec = serde_yaml::from_str(
+            "name: analysis\nchannels: [conda-forge]\ndependencies:\n  - python 3.12.*\n  - numpy\n  - pip:\n      - requests\n",
+        )
+        .unwrap();
+        assert_eq!(spec.specs(), vec!["python 3.12.*", "numpy"]);
+    }
+}
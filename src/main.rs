@@ -3,53 +3,262 @@ use clap::{Parser, Subcommand};
 use rattler_cache::default_cache_dir;
 use tracing::{info, warn};
 
+mod allowlist;
+mod auth;
 mod azure;
+mod channel_config;
+mod channel_snapshot;
+mod channel_source;
+mod channeldata;
+mod circuit_breaker;
 mod conda_package;
 mod config;
+#[cfg(feature = "schedule")]
+mod daemon;
+mod download;
+mod drift;
+mod email;
+mod enrich;
+mod error;
+mod extraction;
 mod github;
+mod gitlab;
+#[cfg_attr(not(feature = "schedule"), allow(dead_code))]
+mod health;
+#[cfg(feature = "solve")]
+mod install_check;
+mod limits;
+mod listing;
+mod listing_cache;
+#[cfg(feature = "solve")]
+mod lockfile;
+mod manifest;
 mod mirror;
+#[cfg(feature = "s3")]
+mod multipart;
+mod naming;
+mod notify;
+mod oci;
+mod osv;
+mod policy;
+mod politeness;
+mod presets;
+mod priority;
+mod promote;
+mod provenance;
+mod quarantine;
+mod remote_zip;
+mod report;
 mod repository;
+mod resume;
+mod retention;
+mod review;
+mod sbom;
+mod scratch;
+mod serve;
+#[cfg(feature = "sharded-repodata")]
+mod shards;
+mod share;
+mod shortcuts;
+mod shutdown;
+mod signing;
+mod snapshot;
+mod source;
+mod specs;
+#[cfg(feature = "state-db")]
+mod state;
+mod suggest;
+#[cfg(test)]
+mod test_support;
+#[cfg(test)]
+mod test_util;
+mod upstream;
+#[cfg(feature = "schedule")]
+mod window;
 
 use config::Config;
-use mirror::mirror_packages;
+use mirror::mirror_sources;
 use repository::RepositoryType;
 
 #[derive(Parser)]
 #[command(name = "meso-forge-mirror")]
 #[command(version)]
 #[command(about = "Mirror conda packages from staging PRs to target repositories", long_about = None)]
+#[command(after_help = shortcuts::HELP)]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
 }
 
+// Parsed once per process, so the size of the mirror variant does not matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 enum Commands {
     /// Mirror packages from source to target repository
     Mirror {
-        /// Source type: zip (local zip), zip-url (remote zip), local (local conda), url (remote conda), tgz (local tarball), tgz-url (remote tarball), github (GitHub artifacts), azure (Azure DevOps artifacts)
+        /// Source type: zip (local zip), zip-url (remote zip), local (local conda), url (remote conda), tgz (local tarball), tgz-url (remote tarball), github (GitHub artifacts), github-release (assets of a GitHub release, given as owner/repo@tag), azure (Azure DevOps artifacts), gitlab (GitLab CI job artifacts), channel (every package of a conda channel)
         #[arg(long, default_value = "local")]
         src_type: String,
 
-        /// Source path or URL (local file path or remote URL)
-        #[arg(long)]
-        src: String,
+        /// Source path or URL (local file path or remote URL); repeat to mirror several sources of the same type concurrently in one run
+        #[arg(short, long, required = true)]
+        src: Vec<String>,
 
-        /// Regular expression to match file paths within ZIP file where conda packages are located (only first match processed; required when src-type is 'zip' or 'zip-url')
+        /// Regular expression to match file paths within ZIP files and tarballs where conda packages are located (only first match processed unless --all-matches; required when src-type is 'zip' or 'zip-url'), or the package filenames to mirror from a channel
         #[arg(long)]
         src_path: Option<String>,
 
-        /// Target type: 'cache' stores individual packages for reuse, 'local'/'s3'/'prefix-dev' create conda repositories with repodata
+        /// Mirror every package matching --src-path instead of only the first (overrides all_matches in the config)
+        #[arg(long, visible_alias = "match-all")]
+        all_matches: bool,
+
+        /// Fail unless the source has packages for each of these platforms, e.g. linux-64,osx-arm64 (overrides require_platforms in the config)
+        #[arg(long, value_delimiter = ',')]
+        require_platforms: Vec<String>,
+
+        /// Regular expression of ZIP/tarball member paths and artifact names to skip, e.g. debug symbol bundles (overrides src_exclude in the config)
+        #[arg(long)]
+        src_exclude: Option<String>,
+
+        /// Named artifact and package path filters used when --src-path is not given: conda-forge, rattler-build (overrides filter_preset in the config)
+        #[arg(long)]
+        filter_preset: Option<String>,
+
+        /// Subdirs of a channel source to mirror, e.g. linux-64,noarch (overrides src_subdirs in the config; default: every subdir with a repodata.json)
+        #[arg(long, value_delimiter = ',')]
+        src_subdirs: Vec<String>,
+
+        /// Mirror only the conda-forge Azure DevOps artifacts built for these platforms, e.g. linux-64,osx-arm64, read from the artifact names before download (overrides platforms in the config)
+        #[arg(long, value_delimiter = ',')]
+        platforms: Vec<String>,
+
+        /// Mirror only packages matching this match spec, e.g. "numpy >=1.21,<2"; repeat to mirror packages matching any of several (overrides specs in the config)
+        #[arg(long = "spec")]
+        specs: Vec<String>,
+
+        /// Channel whose repodata supplies the license and dependencies missing from a package's own metadata, matched by filename and sha256 (overrides enrich_from in the config)
+        #[arg(long)]
+        enrich_from: Option<String>,
+
+        /// Also write sharded repodata (CEP-16) next to each repodata.json of local and S3 targets (overrides sharded_repodata in the config)
+        #[arg(long)]
+        sharded_repodata: bool,
+
+        /// Target type: 'cache' stores individual packages for reuse, 'local'/'s3'/'prefix-dev'/'oci' create conda repositories with repodata
         #[arg(long, default_value = "cache")]
         tgt_type: String,
 
         /// Target path or URL (automatically determined for 'cache', required for repository types)
-        #[arg(long)]
+        #[arg(short, long)]
         tgt: Option<String>,
 
+        /// Also unpack packages mirrored to a cache target, ready for pixi and rattler to use (overrides cache_extract in the config)
+        #[arg(long)]
+        cache_extract: bool,
+
+        /// Fully extract every package and check it against its info/paths.json before uploading it (overrides verify_extraction in the config)
+        #[arg(long)]
+        verify_extraction: bool,
+
+        /// Directory for temporary files such as extracted packages (overrides scratch_dir in the config)
+        #[arg(long)]
+        scratch_dir: Option<String>,
+
         /// Configuration file (optional)
         #[arg(short, long)]
         config: Option<String>,
+
+        /// Replace packages already at the target even if their sha256 differs
+        #[arg(long)]
+        force_replace: bool,
+
+        /// Download and upload packages even when the target already has an identical copy
+        #[arg(long)]
+        force: bool,
+
+        /// When a filename is seen twice with different platforms: error, keep-first, prefer-metadata
+        #[arg(long, value_parser = ["error", "keep-first", "prefer-metadata"])]
+        duplicate_platform_policy: Option<String>,
+
+        /// Refuse packages whose platform could not be read from their metadata
+        #[arg(long)]
+        strict_platform: bool,
+
+        /// Print the rules that decided each package's platform, with the evidence for each
+        #[arg(long)]
+        explain: bool,
+
+        /// When a package filename does not match its name, version and build: rename, reject
+        #[arg(long, value_parser = ["rename", "reject"])]
+        filename_policy: Option<String>,
+
+        /// Publish packages of one subdir under another instead, as FROM=TO, e.g. osx-64=osx-arm64 (can be repeated; added to platform_mappings in the config)
+        #[arg(long)]
+        platform_map: Vec<String>,
+
+        /// Publish packages of one subdir under another as well, as FROM=TO (can be repeated; added to platform_mappings in the config)
+        #[arg(long)]
+        platform_alias: Vec<String>,
+
+        /// SQLite database recording mirrored packages (overrides state_db in the config)
+        #[arg(long)]
+        state_db: Option<String>,
+
+        /// Skip packages the state database records as already delivered to the target
+        #[arg(long)]
+        since_last_run: bool,
+
+        /// File recording the ETag/Last-Modified of sources; runs whose source is unchanged are skipped (overrides upstream_state_file in the config)
+        #[arg(long)]
+        upstream_state: Option<String>,
+
+        /// Write an in-toto SLSA provenance statement for every mirrored package
+        #[arg(long)]
+        provenance: bool,
+
+        /// URL clients reach the mirror at, for the printed channel configuration (overrides public_url in the config)
+        #[arg(long)]
+        public_url: Option<String>,
+
+        /// Also write the pixi.toml/.condarc channel configuration to this file (overrides channel_config_file in the config)
+        #[arg(long)]
+        channel_config: Option<String>,
+
+        /// Channel name written into repodata and its package records (overrides channel_name in the config)
+        #[arg(long)]
+        channel_name: Option<String>,
+
+        /// Directory caching GitHub/Azure DevOps listings for a few minutes (overrides listing_cache_dir in the config)
+        #[arg(long)]
+        listing_cache: Option<String>,
+
+        /// Minimum pause between requests to the same upstream host in milliseconds (overrides crawl_delay_ms in the config)
+        #[arg(long)]
+        crawl_delay_ms: Option<u64>,
+
+        /// Requests run at once against one upstream host (overrides max_connections_per_host in the config)
+        #[arg(long)]
+        max_connections_per_host: Option<usize>,
+
+        /// Average rate packages are fetched at in bytes per second (overrides max_bytes_per_second in the config)
+        #[arg(long)]
+        max_bytes_per_second: Option<u64>,
+
+        /// Contact URL or address added to the User-Agent (overrides user_agent_contact in the config)
+        #[arg(long)]
+        user_agent_contact: Option<String>,
+
+        /// Packages uploaded to prefix.dev together in one batch (overrides upload_batch_size in the config)
+        #[arg(long)]
+        upload_batch_size: Option<usize>,
+
+        /// Queued bytes that also start a prefix.dev upload batch (overrides upload_batch_bytes in the config)
+        #[arg(long)]
+        upload_batch_bytes: Option<u64>,
+
+        /// Uploads of a prefix.dev batch that run at once (overrides upload_parallelism in the config)
+        #[arg(long)]
+        upload_parallelism: Option<usize>,
     },
     /// Get information about repository artifacts
     Info {
@@ -61,7 +270,11 @@ enum Commands {
         #[arg(long)]
         azure: Option<String>,
 
-        /// Azure DevOps build ID (optional, if not specified lists recent builds)
+        /// GitLab project in format 'group/project' or GitLab URL
+        #[arg(long)]
+        gitlab: Option<String>,
+
+        /// Azure DevOps build or GitLab pipeline ID (optional, if not specified lists recent builds or pipelines)
         #[arg(long)]
         build_id: Option<u64>,
 
@@ -69,7 +282,11 @@ enum Commands {
         #[arg(long)]
         name_filter: Option<String>,
 
-        /// Filter builds by description pattern (regex) - Azure only
+        /// Filter artifacts with the artifact pattern of a named preset when --name-filter is not given, e.g. conda-forge
+        #[arg(long)]
+        filter_preset: Option<String>,
+
+        /// Filter builds by description pattern (regex) - Azure definition name, GitLab pipeline ref
         #[arg(long)]
         description_filter: Option<String>,
 
@@ -81,245 +298,1797 @@ enum Commands {
         #[arg(long, default_value = "true")]
         exclude_expired: bool,
 
+        /// Order the listing by a table column, e.g. size, created or finish-time
+        #[arg(long)]
+        sort_by: Option<String>,
+
+        /// Reverse the order, e.g. largest or newest first with --sort-by
+        #[arg(long)]
+        reverse: bool,
+
+        /// Table columns to show, in order, e.g. name,size,created (table output only)
+        #[arg(long, value_delimiter = ',')]
+        columns: Vec<String>,
+
+        /// Show only conda-forge artifacts built for these platforms, e.g. linux-64,osx-arm64 (Azure DevOps only; overrides platforms in the config)
+        #[arg(long, value_delimiter = ',')]
+        platforms: Vec<String>,
+
+        /// Directory caching GitHub/Azure DevOps listings for a few minutes (overrides listing_cache_dir in the config)
+        #[arg(long)]
+        listing_cache: Option<String>,
+
         /// Configuration file (optional)
         #[arg(short, long)]
         config: Option<String>,
     },
-    /// Initialize configuration file
-    Init {
-        /// Output path for config file
-        #[arg(short, long, default_value = "meso-forge-mirror.json")]
-        output: String,
+    /// Show packages recorded in the state database, or drift from what a target holds
+    History {
+        /// SQLite database to read (overrides state_db in the config)
+        #[arg(long)]
+        state_db: Option<String>,
+
+        /// Only packages mirrored from this source
+        #[arg(long)]
+        src: Option<String>,
+
+        /// Target type, used with --drift to list what the target holds
+        #[arg(long, default_value = "cache")]
+        tgt_type: String,
+
+        /// Only packages mirrored to this target; with --drift, the target to inspect
+        #[arg(long)]
+        tgt: Option<String>,
+
+        /// Only packages whose filename contains this text
+        #[arg(long)]
+        package: Option<String>,
+
+        /// Maximum number of records to show
+        #[arg(long, default_value = "50")]
+        limit: usize,
+
+        /// Compare the records of the target with the packages it actually holds
+        #[arg(long)]
+        drift: bool,
+
+        /// Show promotions instead, --src and --tgt selecting the staging and production targets
+        #[arg(long, conflicts_with = "drift")]
+        promotions: bool,
+
+        /// Output format (yaml, json, table)
+        #[arg(long, default_value = "table", value_parser = ["yaml", "json", "table"])]
+        encode: String,
+
+        /// Configuration file (optional)
+        #[arg(short, long)]
+        config: Option<String>,
     },
-}
+    /// Show package counts and sizes per platform a target held after mirror runs
+    Stats {
+        /// Target to show, as recorded by mirror runs, e.g. the --tgt of the mirror command
+        #[arg(long)]
+        tgt: String,
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    // Initialize tracing
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::from_default_env()
-                .add_directive(tracing::Level::INFO.into()),
-        )
-        .init();
+        /// Show every recorded run, oldest first, instead of only the latest
+        #[arg(long)]
+        history: bool,
 
-    let cli = Cli::parse();
+        /// SQLite database to read (overrides state_db in the config)
+        #[arg(long)]
+        state_db: Option<String>,
 
-    match cli.command {
-        Commands::Mirror {
-            src_type,
-            src,
-            src_path,
-            tgt_type,
-            tgt,
-            config,
-        } => {
-            info!("Starting package mirroring");
+        /// Output format (yaml, json, csv, table)
+        #[arg(long, default_value = "table", value_parser = ["yaml", "json", "csv", "table"])]
+        encode: String,
 
-            // Validate source type
-            match src_type.as_str() {
-                "zip" | "zip-url" | "local" | "url" | "tgz" | "tgz-url" | "github" | "azure" => {}
-                _ => {
-                    return Err(anyhow::anyhow!(
-                    "Invalid src-type '{}'. Must be one of: zip, zip-url, local, url, tgz, tgz-url, github, azure",
-                    src_type
-                ))
+        /// Configuration file (optional)
+        #[arg(short, long)]
+        config: Option<String>,
+    },
+    /// Generate a software bill of materials for a conda channel
+    Sbom {
+        /// Channel to describe: a local repository path or the URL of a channel
+        #[arg(long)]
+        channel: String,
+
+        /// Subdirs to include (repeatable; default: every subdir with a repodata.json)
+        #[arg(long)]
+        subdir: Vec<String>,
+
+        /// Document format
+        #[arg(long, default_value = "cyclonedx", value_parser = ["cyclonedx", "spdx"])]
+        format: String,
+
+        /// Write the document to this file instead of standard output
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// Configuration file (optional)
+        #[arg(short, long)]
+        config: Option<String>,
+    },
+    /// Look up the packages of a conda channel in the OSV.dev vulnerability database
+    Scan {
+        /// Channel to scan: a local repository path or the URL of a channel
+        #[arg(long)]
+        channel: String,
+
+        /// Subdirs to include (repeatable; default: every subdir with a repodata.json)
+        #[arg(long)]
+        subdir: Vec<String>,
+
+        /// Output format (yaml, json, table)
+        #[arg(long, default_value = "table", value_parser = ["yaml", "json", "table"])]
+        encode: String,
+
+        /// Configuration file with OSV settings (optional)
+        #[arg(short, long)]
+        config: Option<String>,
+    },
+    /// Compare a mirror with its upstream channel, failing when they drifted apart
+    Drift {
+        /// Mirror to check: a local repository path or the URL of a channel
+        #[arg(long)]
+        mirror: String,
+
+        /// Upstream channel (default: upstream_channel from the configuration file)
+        #[arg(long)]
+        upstream: Option<String>,
+
+        /// Subdirs to compare (repeatable; default: every subdir of the mirror)
+        #[arg(long)]
+        subdir: Vec<String>,
+
+        /// Only compare packages whose name matches this regular expression
+        #[arg(long)]
+        package: Option<String>,
+
+        /// Output format (yaml, json, table)
+        #[arg(long, default_value = "table", value_parser = ["yaml", "json", "table"])]
+        encode: String,
+
+        /// Configuration file (optional)
+        #[arg(short, long)]
+        config: Option<String>,
+    },
+    /// Solve an environment against a mirror alone and write the solution as a pixi.lock
+    Lock {
+        /// Mirror to solve against: a local repository path or the URL of a channel
+        #[arg(long)]
+        channel: String,
+
+        /// Conda environment.yml listing the dependencies (its channels are ignored)
+        #[arg(short, long)]
+        file: Option<String>,
+
+        /// Match spec to add to the environment (repeatable)
+        #[arg(long)]
+        spec: Vec<String>,
+
+        /// Platform to solve for (repeatable; default: the current platform)
+        #[arg(long)]
+        platform: Vec<String>,
+
+        /// Write the lockfile to this file instead of standard output
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// Configuration file (optional)
+        #[arg(short, long)]
+        config: Option<String>,
+    },
+    /// Install specs from a mirror into a temporary prefix, proving its packages are consumable
+    CheckInstall {
+        /// Mirror to install from: a local repository path or the URL of a channel
+        #[arg(long)]
+        channel: String,
+
+        /// Conda environment.yml listing the dependencies (its channels are ignored)
+        #[arg(short, long)]
+        file: Option<String>,
+
+        /// Match spec to install (repeatable)
+        #[arg(long)]
+        spec: Vec<String>,
+
+        /// Platform to install for (default: the current platform)
+        #[arg(long)]
+        platform: Option<String>,
+
+        /// Install into this empty directory and keep it, instead of a temporary prefix
+        #[arg(long)]
+        prefix: Option<String>,
+
+        /// Directory for the temporary prefix and extracted packages (overrides scratch_dir in the config)
+        #[arg(long)]
+        scratch_dir: Option<String>,
+
+        /// Configuration file (optional)
+        #[arg(short, long)]
+        config: Option<String>,
+    },
+    /// Remove all but the newest versions or builds of each package from a target
+    Prune {
+        /// Target type
+        #[arg(long, default_value = "cache")]
+        tgt_type: String,
+
+        /// Target path or URL (automatically determined for 'cache')
+        #[arg(long)]
+        tgt: Option<String>,
+
+        /// Number of versions or builds to keep (overrides retention.keep in the config)
+        #[arg(long)]
+        keep: Option<usize>,
+
+        /// What to count: versions of each package name, or builds of each version (overrides retention.scope in the config)
+        #[arg(long, value_parser = ["versions", "builds"])]
+        scope: Option<String>,
+
+        /// Remove packages stored longer ago than this, e.g. 180d (overrides retention.older_than in the config)
+        #[arg(long)]
+        older_than: Option<String>,
+
+        /// Match spec of packages never to remove, e.g. "python >=3.10" (can be repeated; added to retention.protect in the config)
+        #[arg(long)]
+        protect: Vec<String>,
+
+        /// Lockfile (pixi.lock) whose packages are kept; others are removed (can be repeated; added to retention.lockfiles in the config)
+        #[arg(long)]
+        lockfile: Vec<String>,
+
+        /// List the packages that would be removed without removing them
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Configuration file (optional)
+        #[arg(short, long)]
+        config: Option<String>,
+    },
+    /// Index or delete package files at a target that no repodata lists
+    Gc {
+        /// Target type
+        #[arg(long, default_value = "local", value_parser = ["local", "s3"])]
+        tgt_type: String,
+
+        /// Target path or URL
+        #[arg(long)]
+        tgt: String,
+
+        /// What to do with orphaned package files: add them to the repodata, or delete them
+        #[arg(long, default_value = "index", value_parser = ["index", "delete"])]
+        action: String,
+
+        /// List the orphaned package files without changing anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Configuration file (optional)
+        #[arg(short, long)]
+        config: Option<String>,
+    },
+    /// Abort the multipart uploads an S3 target holds that were never completed
+    AbortUploads {
+        /// Target type
+        #[arg(long, default_value = "s3", value_parser = ["s3"])]
+        tgt_type: String,
+
+        /// Target URL, e.g. s3://bucket/prefix
+        #[arg(long)]
+        tgt: String,
+
+        /// List the incomplete uploads without aborting them
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Configuration file (optional)
+        #[arg(short, long)]
+        config: Option<String>,
+    },
+    /// Restore the repodata of a target from a snapshot taken before an earlier run or prune
+    Rollback {
+        /// Target type
+        #[arg(long, default_value = "local", value_parser = ["local", "s3"])]
+        tgt_type: String,
+
+        /// Target path or URL
+        #[arg(long)]
+        tgt: String,
+
+        /// Snapshot to restore (default: the latest one)
+        #[arg(long)]
+        snapshot: Option<String>,
+
+        /// List the snapshots of the target instead of restoring one
+        #[arg(long)]
+        list: bool,
+
+        /// Configuration file (optional)
+        #[arg(short, long)]
+        config: Option<String>,
+    },
+    /// Freeze a local channel as it is today into a dated snapshot of hard-linked packages
+    Snapshot {
+        /// Local channel directory
+        #[arg(long)]
+        tgt: String,
+
+        /// Name of the snapshot (default: today's date, e.g. 2025-06-01)
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Directory the snapshots are kept in (default: snapshots/ in the channel)
+        #[arg(long)]
+        output: Option<String>,
+
+        /// List the snapshots instead of taking one
+        #[arg(long)]
+        list: bool,
+    },
+    /// Build a channel from the packages in the rattler cache, to share them with others
+    Share {
+        /// Package cache directory (default: the rattler package cache, e.g. ~/.cache/rattler/cache/pkgs)
+        #[arg(long)]
+        cache: Option<String>,
+
+        /// Directory the channel is written to
+        #[arg(long)]
+        tgt: String,
+
+        /// Regular expression; only packages whose name matches are shared
+        #[arg(long)]
+        package: Option<String>,
+
+        /// Hard-link package archives into the channel instead of copying them, where the filesystem allows
+        #[arg(long)]
+        hardlink: bool,
+    },
+    /// Serve a local channel over HTTP, so pixi and conda can install from it directly
+    Serve {
+        /// Local channel directory
+        #[arg(long)]
+        path: String,
+
+        /// Port to listen on
+        #[arg(long, default_value_t = 8080)]
+        port: u16,
+
+        /// Address to listen on
+        #[arg(long, default_value = "0.0.0.0")]
+        bind: String,
+
+        /// Require HTTP basic authentication with this user name and serve_password, or SERVE_PASSWORD (overrides serve_username in the config)
+        #[arg(long)]
+        username: Option<String>,
+
+        /// Configuration file (optional)
+        #[arg(short, long)]
+        config: Option<String>,
+    },
+    /// Copy the packages of a staging target to a production target once every one passes validation
+    Promote {
+        /// Staging target type
+        #[arg(long, default_value = "local")]
+        src_type: String,
+
+        /// Staging target path or URL
+        #[arg(long)]
+        src: String,
+
+        /// Production target type
+        #[arg(long, default_value = "local")]
+        tgt_type: String,
+
+        /// Production target path or URL
+        #[arg(long)]
+        tgt: String,
+
+        /// Regular expression; only packages whose name matches are promoted
+        #[arg(long)]
+        package: Option<String>,
+
+        /// Label recorded with the promotion in the state database, e.g. stable
+        #[arg(long)]
+        label: Option<String>,
+
+        /// Validate the staged packages and list them without promoting them
+        #[arg(long)]
+        dry_run: bool,
+
+        /// SQLite database recording the promotion (overrides state_db in the config)
+        #[arg(long)]
+        state_db: Option<String>,
+
+        /// Configuration file (optional)
+        #[arg(short, long)]
+        config: Option<String>,
+    },
+    /// Run the jobs of the configuration file on their cron schedules until interrupted
+    Daemon {
+        /// Configuration file listing the jobs
+        #[arg(short, long)]
+        config: String,
+
+        /// Serve /healthz, /readyz and /status on this address (overrides health_listen in the config)
+        #[arg(long)]
+        listen: Option<String>,
+    },
+    /// Evaluate the admission policy of the configuration file
+    Policy {
+        #[command(subcommand)]
+        command: PolicyCommands,
+    },
+    /// List, approve or reject packages held in the quarantine directory for review
+    Review {
+        #[command(subcommand)]
+        command: ReviewCommands,
+    },
+    /// Manage the keys signing a target's packages and repodata
+    Keys {
+        #[command(subcommand)]
+        command: KeysCommands,
+    },
+    /// Initialize configuration file
+    Init {
+        /// Output path for config file
+        #[arg(short, long, default_value = "meso-forge-mirror.json")]
+        output: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum PolicyCommands {
+    /// Show which packages of a source the policy would reject and why, without uploading
+    Check {
+        /// Source type, as for the mirror command
+        #[arg(long, default_value = "local")]
+        src_type: String,
+
+        /// Source path or URL
+        #[arg(long)]
+        src: String,
+
+        /// Regular expression selecting packages within ZIP files, or the artifact name filter
+        #[arg(long)]
+        src_path: Option<String>,
+
+        /// Configuration file with the policy
+        #[arg(short, long)]
+        config: String,
+
+        /// Output format (yaml, json, table)
+        #[arg(long, default_value = "table", value_parser = ["yaml", "json", "table"])]
+        encode: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum ReviewCommands {
+    /// Show the packages waiting for review
+    List {
+        /// Also show packages that were already approved or rejected
+        #[arg(long)]
+        all: bool,
+
+        /// Configuration file naming the quarantine directory (optional)
+        #[arg(short, long)]
+        config: Option<String>,
+
+        /// Output format (yaml, json, table)
+        #[arg(long, default_value = "table", value_parser = ["yaml", "json", "table"])]
+        encode: String,
+    },
+    /// Mirror held packages into the target they were meant for and re-index it
+    Approve {
+        /// IDs or filenames of the packages to approve
+        packages: Vec<String>,
+
+        /// Approve every pending package
+        #[arg(long, conflicts_with = "packages")]
+        all: bool,
+
+        /// Configuration file with the target settings (optional)
+        #[arg(short, long)]
+        config: Option<String>,
+    },
+    /// Delete held packages, recording that they were rejected
+    Reject {
+        /// IDs or filenames of the packages to reject
+        packages: Vec<String>,
+
+        /// Reject every pending package
+        #[arg(long, conflicts_with = "packages")]
+        all: bool,
+
+        /// Configuration file naming the quarantine directory (optional)
+        #[arg(short, long)]
+        config: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum KeysCommands {
+    /// Re-sign the repodata of a target with a new key and publish the key change in keys.json
+    Rotate {
+        /// Target type
+        #[arg(long, default_value = "local", value_parser = ["local", "s3"])]
+        tgt_type: String,
+
+        /// Target path or URL
+        #[arg(long)]
+        tgt: String,
+
+        /// Key id, fingerprint or user id of the new key
+        #[arg(long)]
+        new_key: String,
+
+        /// Only move these subdirs to the new key (repeatable; default: the default key is replaced)
+        #[arg(long)]
+        subdir: Vec<String>,
+
+        /// Configuration file with the signing section; it is updated to the new key
+        #[arg(short, long)]
+        config: String,
+    },
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    // Initialize tracing
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::from_default_env()
+                .add_directive(tracing::Level::INFO.into()),
+        )
+        .init();
+
+    let cli = Cli::parse_from(shortcuts::expand(std::env::args_os().collect()));
+
+    match cli.command {
+        Commands::Mirror {
+            src_type,
+            src,
+            src_path,
+            all_matches,
+            require_platforms,
+            src_exclude,
+            filter_preset,
+            src_subdirs,
+            platforms,
+            specs,
+            enrich_from,
+            sharded_repodata,
+            tgt_type,
+            tgt,
+            cache_extract,
+            verify_extraction,
+            scratch_dir,
+            config,
+            force_replace,
+            force,
+            duplicate_platform_policy,
+            strict_platform,
+            explain,
+            filename_policy,
+            platform_map,
+            platform_alias,
+            state_db,
+            since_last_run,
+            upstream_state,
+            provenance,
+            public_url,
+            channel_config,
+            channel_name,
+            listing_cache,
+            crawl_delay_ms,
+            max_connections_per_host,
+            max_bytes_per_second,
+            user_agent_contact,
+            upload_batch_size,
+            upload_batch_bytes,
+            upload_parallelism,
+        } => {
+            info!("Starting package mirroring");
+
+            // Validate source type
+            match src_type.as_str() {
+                "zip" | "zip-url" | "local" | "url" | "tgz" | "tgz-url" | "github"
+                | "github-release" | "azure" | "gitlab" | "channel" => {}
+                _ => {
+                    return Err(anyhow::anyhow!(
+                    "Invalid src-type '{}'. Must be one of: zip, zip-url, local, url, tgz, tgz-url, github, github-release, azure, gitlab, channel",
+                    src_type
+                ))
+                }
+            }
+
+            // Validate target type
+            match tgt_type.as_str() {
+                "prefix-dev" | "prefix" | "s3" | "minio" | "local" | "file" | "cache" | "oci" => {}
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "Invalid tgt-type '{}'. Must be one of: cache (individual package storage), prefix-dev, s3, local, oci (conda repositories)",
+                        tgt_type
+                    ));
+                }
+            }
+
+            // Validate GitHub source format
+            if src_type == "github" {
+                for src in &src {
+                    if let Err(e) = github::parse_github_repository(src) {
+                        return Err(anyhow::anyhow!("Invalid GitHub repository format: {}", e));
+                    }
+                }
+            }
+
+            // Validate GitHub release source format
+            if src_type == "github-release" {
+                for src in &src {
+                    if let Err(e) = github::parse_release_source(src) {
+                        return Err(anyhow::anyhow!("Invalid GitHub release format: {}", e));
+                    }
+                }
+            }
+
+            // Validate Azure DevOps source format
+            if src_type == "azure" {
+                for src in &src {
+                    if let Err(e) = azure::parse_azure_source(src) {
+                        return Err(anyhow::anyhow!("Invalid Azure DevOps format: {}", e));
+                    }
+                }
+                azure::parse_platforms(&platforms)?;
+            }
+
+            // Validate GitLab source format
+            if src_type == "gitlab" {
+                for src in &src {
+                    if let Err(e) = gitlab::parse_gitlab_source(src) {
+                        return Err(anyhow::anyhow!("Invalid GitLab format: {}", e));
+                    }
+                }
+            }
+
+            // Validate regex pattern if provided
+            if let Some(ref pattern) = src_path {
+                if let Err(e) = regex::Regex::new(pattern) {
+                    return Err(anyhow::anyhow!(
+                        "Invalid regular expression in --src-path: {}",
+                        e
+                    ));
+                }
+            }
+            if let Some(ref pattern) = src_exclude {
+                if let Err(e) = regex::Regex::new(pattern) {
+                    return Err(anyhow::anyhow!(
+                        "Invalid regular expression in --src-exclude: {}",
+                        e
+                    ));
+                }
+            }
+
+            let mut config = if let Some(config_path) = config {
+                Config::load_from_file(&config_path)?
+            } else {
+                Config::default()
+            };
+            config.force_replace |= force_replace;
+            config.force |= force;
+            config.strict_platform |= strict_platform;
+            config.explain_platform |= explain;
+            config.since_last_run |= since_last_run;
+            config.provenance |= provenance;
+            if state_db.is_some() {
+                config.state_db = state_db;
+            }
+            if upstream_state.is_some() {
+                config.upstream_state_file = upstream_state;
+            }
+            if public_url.is_some() {
+                config.public_url = public_url;
+            }
+            if channel_config.is_some() {
+                config.channel_config_file = channel_config;
+            }
+            if channel_name.is_some() {
+                config.channel_name = channel_name;
+            }
+            if listing_cache.is_some() {
+                config.listing_cache_dir = listing_cache;
+            }
+            if src_exclude.is_some() {
+                config.src_exclude = src_exclude;
+            }
+            if filter_preset.is_some() {
+                config.filter_preset = filter_preset;
+            }
+            if let Some(ref preset) = config.filter_preset {
+                presets::find(preset)?;
+            }
+
+            // Validate that src_path, or a preset standing in for it, is provided for zip files
+            if (src_type == "zip" || src_type == "zip-url")
+                && src_path.is_none()
+                && config.filter_preset.is_none()
+            {
+                return Err(anyhow::anyhow!(
+                    "--src-path or --filter-preset is required when src-type is 'zip' or 'zip-url'"
+                ));
+            }
+            if all_matches {
+                config.all_matches = true;
+            }
+            if !require_platforms.is_empty() {
+                config.require_platforms = require_platforms;
+            }
+            if !src_subdirs.is_empty() {
+                config.src_subdirs = src_subdirs;
+            }
+            if !platforms.is_empty() {
+                config.platforms = platforms;
+            }
+            if !specs.is_empty() {
+                specs::SpecFilter::new(&specs)?;
+                config.specs = specs;
+            }
+            if enrich_from.is_some() {
+                config.enrich_from = enrich_from;
+            }
+            if sharded_repodata {
+                config.sharded_repodata = true;
+            }
+            if cache_extract {
+                config.cache_extract = true;
+            }
+            if verify_extraction {
+                config.verify_extraction = true;
+            }
+            if scratch_dir.is_some() {
+                config.scratch_dir = scratch_dir;
+            }
+            if let Some(delay) = crawl_delay_ms {
+                config.crawl_delay_ms = delay;
+            }
+            if let Some(connections) = max_connections_per_host {
+                config.max_connections_per_host = connections;
+            }
+            if let Some(rate) = max_bytes_per_second {
+                config.max_bytes_per_second = rate;
+            }
+            if let Some(batch_size) = upload_batch_size {
+                config.upload_batch_size = batch_size;
+            }
+            if let Some(batch_bytes) = upload_batch_bytes {
+                config.upload_batch_bytes = batch_bytes;
+            }
+            if let Some(parallelism) = upload_parallelism {
+                config.upload_parallelism = parallelism;
+            }
+            if user_agent_contact.is_some() {
+                config.user_agent_contact = user_agent_contact;
+            }
+            for mapping in &platform_map {
+                config
+                    .platform_mappings
+                    .push(repository::PlatformMapping::from_arg(mapping, false)?);
+            }
+            for alias in &platform_alias {
+                config
+                    .platform_mappings
+                    .push(repository::PlatformMapping::from_arg(alias, true)?);
+            }
+            if let Some(policy) = duplicate_platform_policy {
+                config.duplicate_platform_policy =
+                    repository::DuplicatePlatformPolicy::from_string(&policy)?;
+            }
+            if let Some(policy) = filename_policy {
+                config.filename_policy = naming::FilenamePolicy::from_string(&policy)?;
+            }
+
+            let repo_type = RepositoryType::from_string(&tgt_type)?;
+
+            let target_path = target_path(&repo_type, tgt)?;
+
+            let is_local_file = matches!(src_type.as_str(), "zip" | "local" | "tgz");
+            shutdown::install_handler();
+            let result = mirror_sources(
+                &src,
+                src_path.as_deref(),
+                &src_type,
+                is_local_file,
+                repo_type.clone(),
+                &target_path,
+                &config,
+            )
+            .await;
+
+            let notification = match &result {
+                Ok(report) => notify::Notification::from_report(report),
+                Err(e) => notify::Notification::from_error(&src.join(", "), &target_path, e)
+                    .with_report(e.partial_report()),
+            };
+            notify::announce(
+                &config,
+                &notification,
+                matches!(result, Err(error::MirrorError::Interrupted { .. })),
+            )
+            .await;
+
+            if let Err(e @ error::MirrorError::Interrupted { .. }) = result {
+                if let Some(report) = e.partial_report() {
+                    print_platform_explanations(report);
+                    report.print_summary();
+                }
+                warn!("{}", e);
+                std::process::exit(shutdown::INTERRUPTED_EXIT_CODE);
+            }
+            let report = result?;
+            print_platform_explanations(&report);
+            report.print_summary();
+
+            if report.failed_count() > 0 {
+                return Err(anyhow::anyhow!(
+                    "{} packages failed to mirror",
+                    report.failed_count()
+                ));
+            }
+            if !report.is_success() {
+                return Err(anyhow::anyhow!(
+                    "All {} artifacts were corrupt and have been quarantined in {}",
+                    report.quarantined.len(),
+                    config.quarantine_dir
+                ));
+            }
+
+            if let Some(url) = channel_config::channel_url(&repo_type, &target_path, &config)? {
+                let snippets = channel_config::snippets(&url);
+                println!(
+                    "\nAdd the mirror to a project or conda configuration:\n\n{}",
+                    snippets
+                );
+                if let Some(path) = &config.channel_config_file {
+                    std::fs::write(path, &snippets)?;
+                    info!("Wrote channel configuration to {}", path);
+                }
+            }
+
+            info!("Mirroring completed successfully");
+        }
+        Commands::Info {
+            github,
+            azure,
+            gitlab,
+            build_id,
+            name_filter,
+            filter_preset,
+            description_filter,
+            encode,
+            exclude_expired,
+            sort_by,
+            reverse,
+            columns,
+            platforms,
+            listing_cache,
+            config,
+        } => {
+            let mut config = if let Some(config_path) = config {
+                Config::load_from_file(&config_path)?
+            } else {
+                Config::default()
+            };
+            if listing_cache.is_some() {
+                config.listing_cache_dir = listing_cache;
+            }
+            if !platforms.is_empty() {
+                config.platforms = platforms;
+            }
+            let name_filter = match (name_filter, filter_preset) {
+                (None, Some(preset)) => presets::find(&preset)?.artifacts.map(str::to_string),
+                (name_filter, _) => name_filter,
+            };
+
+            match (github, azure, gitlab) {
+                (Some(repo), None, None) => {
+                    // GitHub info
+                    info!(
+                        "Getting GitHub artifact information for repository: {}",
+                        repo
+                    );
+                    let github_client = github::GitHubClient::new(&config)?;
+                    let (owner, repo_name) = github::parse_github_repository(&repo)?;
+
+                    let mut artifacts = github_client.list_artifacts(&owner, &repo_name).await?;
+                    let artifact_names: Vec<String> =
+                        artifacts.iter().map(|a| a.name.clone()).collect();
+
+                    // Filter by name if specified
+                    if let Some(ref pattern) = name_filter {
+                        artifacts =
+                            github_client.filter_artifacts_by_name(&artifacts, Some(pattern));
+                    }
+
+                    // Filter expired artifacts if requested
+                    if exclude_expired {
+                        artifacts = github_client.filter_non_expired_artifacts(&artifacts);
+                    }
+
+                    if let Some(ref sort_by) = sort_by {
+                        github_client.sort_artifacts(&mut artifacts, sort_by)?;
+                    }
+                    if reverse {
+                        artifacts.reverse();
+                    }
+
+                    // Print the results
+                    github_client.print_artifacts_info(&artifacts, &encode, &columns)?;
+                    if let (true, Some(pattern)) = (artifacts.is_empty(), &name_filter) {
+                        suggest_artifact_names(pattern, &artifact_names);
+                    }
+                }
+                (None, Some(azure_spec), None) => {
+                    // Azure DevOps info
+                    let azure_client = azure::AzureDevOpsClient::new(&config)?;
+                    let (organization, project, specified_build_id) =
+                        azure::parse_azure_source(&azure_spec)?;
+
+                    let target_build_id = build_id.or(specified_build_id);
+
+                    // Case 1: Show artifacts for specific build (with optional name filtering)
+                    if let Some(build_id) = target_build_id {
+                        info!(
+                            "Getting Azure DevOps artifacts for build {} in {}/{}",
+                            build_id, organization, project
+                        );
+                        let mut artifacts = azure_client
+                            .list_artifacts(&organization, &project, build_id)
+                            .await?;
+                        let artifact_names: Vec<String> =
+                            artifacts.iter().map(|a| a.name.clone()).collect();
+
+                        // Apply name filter if specified (works independently)
+                        if let Some(ref pattern) = name_filter {
+                            artifacts =
+                                azure_client.filter_artifacts_by_name(&artifacts, Some(pattern));
+                        }
+                        let platforms = azure::parse_platforms(&config.platforms)?;
+                        artifacts.retain(|artifact| {
+                            azure::unselected_platform(&artifact.name, &platforms).is_none()
+                        });
+
+                        if let Some(ref sort_by) = sort_by {
+                            azure_client.sort_artifacts(&mut artifacts, sort_by)?;
+                        }
+                        if reverse {
+                            artifacts.reverse();
+                        }
+
+                        azure_client.print_artifacts_info(&artifacts, &encode, &columns)?;
+                        if let (true, Some(pattern)) = (artifacts.is_empty(), &name_filter) {
+                            suggest_artifact_names(pattern, &artifact_names);
+                        }
+                    }
+                    // Case 2: Show builds list (with optional description filtering)
+                    else {
+                        info!(
+                            "Getting Azure DevOps builds for {}/{}",
+                            organization, project
+                        );
+                        let mut builds = azure_client
+                            .list_builds(&organization, &project, None)
+                            .await?;
+
+                        // Apply description filter if specified (works independently)
+                        if let Some(ref pattern) = description_filter {
+                            builds = azure_client.filter_builds_by_description(&builds, pattern)?;
+                        }
+
+                        // Warn if name_filter specified but ignored
+                        if name_filter.is_some() {
+                            warn!("--name-filter is ignored when listing builds (no --build-id specified). Use --description-filter to filter builds.");
+                        }
+
+                        if let Some(ref sort_by) = sort_by {
+                            azure_client.sort_builds(&mut builds, sort_by)?;
+                        }
+                        if reverse {
+                            builds.reverse();
+                        }
+
+                        azure_client.print_builds_info(
+                            &builds,
+                            &organization,
+                            &project,
+                            &encode,
+                            &columns,
+                        )?;
+                    }
+                }
+                (None, None, Some(gitlab_spec)) => {
+                    // GitLab CI info
+                    let source = gitlab::parse_gitlab_source(&gitlab_spec)?;
+                    let mut gitlab_client = gitlab::GitLabClient::new(&config)?;
+                    if let Some(instance) = source.instance {
+                        gitlab_client = gitlab_client.with_base_url(instance);
+                    }
+                    let project = source.project;
+
+                    // Case 1: Show the jobs of a specific pipeline (with optional name filtering)
+                    if let Some(pipeline_id) = build_id.or(source.pipeline_id) {
+                        info!(
+                            "Getting GitLab jobs of pipeline {} in {}",
+                            pipeline_id, project
+                        );
+                        let mut jobs = gitlab_client.list_jobs(&project, pipeline_id).await?;
+                        let job_names: Vec<String> = jobs.iter().map(|j| j.name.clone()).collect();
+
+                        if let Some(ref pattern) = name_filter {
+                            jobs = gitlab_client.filter_jobs_by_name(&jobs, Some(pattern));
+                        }
+
+                        if let Some(ref sort_by) = sort_by {
+                            gitlab_client.sort_jobs(&mut jobs, sort_by)?;
+                        }
+                        if reverse {
+                            jobs.reverse();
+                        }
+
+                        gitlab_client.print_jobs_info(&jobs, &encode, &columns)?;
+                        if let (true, Some(pattern)) = (jobs.is_empty(), &name_filter) {
+                            suggest_artifact_names(pattern, &job_names);
+                        }
+                    }
+                    // Case 2: Show pipelines list (with optional ref filtering)
+                    else {
+                        info!("Getting GitLab pipelines for {}", project);
+                        let mut pipelines = gitlab_client.list_pipelines(&project).await?;
+
+                        if let Some(ref pattern) = description_filter {
+                            pipelines =
+                                gitlab_client.filter_pipelines_by_ref(&pipelines, pattern)?;
+                        }
+
+                        if name_filter.is_some() {
+                            warn!("--name-filter is ignored when listing pipelines (no --build-id specified). Use --description-filter to filter pipelines by ref.");
+                        }
+
+                        if let Some(ref sort_by) = sort_by {
+                            gitlab_client.sort_pipelines(&mut pipelines, sort_by)?;
+                        }
+                        if reverse {
+                            pipelines.reverse();
+                        }
+
+                        gitlab_client
+                            .print_pipelines_info(&pipelines, &project, &encode, &columns)?;
+                    }
+                }
+                (None, None, None) => {
+                    return Err(anyhow::anyhow!(
+                        "Must specify one of --github (for GitHub), --azure (for Azure DevOps) or --gitlab (for GitLab CI)."
+                    ));
+                }
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "Specify only one of --github, --azure and --gitlab."
+                    ));
+                }
+            }
+        }
+        Commands::History {
+            state_db,
+            src,
+            tgt_type,
+            tgt,
+            package,
+            limit,
+            drift,
+            promotions,
+            encode,
+            config,
+        } => {
+            let config = if let Some(config_path) = config {
+                Config::load_from_file(&config_path)?
+            } else {
+                Config::default()
+            };
+            let Some(state_db) = state_db.or(config.state_db) else {
+                return Err(anyhow::anyhow!(
+                    "No state database: pass --state-db or set state_db in the configuration"
+                ));
+            };
+            if promotions {
+                show_promotions(&state_db, src, tgt, package, limit, &encode)?;
+            } else {
+                show_history(
+                    &state_db, src, &tgt_type, tgt, package, limit, drift, &encode,
+                )
+                .await?;
+            }
+        }
+        Commands::Stats {
+            tgt,
+            history,
+            state_db,
+            encode,
+            config,
+        } => {
+            let config = if let Some(config_path) = config {
+                Config::load_from_file(&config_path)?
+            } else {
+                Config::default()
+            };
+            let Some(state_db) = state_db.or(config.state_db) else {
+                return Err(anyhow::anyhow!(
+                    "No state database: pass --state-db or set state_db in the configuration"
+                ));
+            };
+            show_stats(&state_db, &tgt, history, &encode)?;
+        }
+        Commands::Sbom {
+            channel,
+            subdir,
+            format,
+            output,
+            config,
+        } => {
+            let config = if let Some(config_path) = config {
+                Config::load_from_file(&config_path)?
+            } else {
+                Config::default()
+            };
+            let format = sbom::SbomFormat::from_string(&format)?;
+            let client = politeness::client(&config)?;
+
+            let packages = sbom::load_channel(&client, &channel, &subdir).await?;
+            let document = sbom::generate(format, &channel, &packages, chrono::Utc::now());
+            let document = serde_json::to_string_pretty(&document)?;
+            match output {
+                Some(path) => {
+                    std::fs::write(&path, document)?;
+                    info!("Wrote SBOM of {} packages to {}", packages.len(), path);
+                }
+                None => println!("{}", document),
+            }
+        }
+        Commands::Scan {
+            channel,
+            subdir,
+            encode,
+            config,
+        } => {
+            let config = if let Some(config_path) = config {
+                Config::load_from_file(&config_path)?
+            } else {
+                Config::default()
+            };
+            let vulnerabilities = config.vulnerabilities.clone().unwrap_or_default();
+            let client = politeness::client(&config)?;
+
+            let packages = sbom::load_channel(&client, &channel, &subdir).await?;
+            let osv_client = osv::OsvClient::new(&vulnerabilities, config.timeout_seconds)?;
+            let results =
+                osv::scan(&osv_client, &packages, config.max_concurrent_downloads).await?;
+            osv::print_scan(&results, &encode)?;
+
+            let blocked = results
+                .iter()
+                .filter(|result| osv_client.blocking(&result.advisories).is_some())
+                .count();
+            if blocked > 0 {
+                return Err(anyhow::anyhow!(
+                    "{} packages have advisories at or above the blocking severity",
+                    blocked
+                ));
+            }
+        }
+        Commands::Drift {
+            mirror,
+            upstream,
+            subdir,
+            package,
+            encode,
+            config,
+        } => {
+            let config = if let Some(config_path) = config {
+                Config::load_from_file(&config_path)?
+            } else {
+                Config::default()
+            };
+            let upstream = upstream
+                .or(config.upstream_channel.clone())
+                .ok_or_else(|| {
+                    anyhow::anyhow!("No upstream channel: pass --upstream or set upstream_channel")
+                })?;
+            let client = politeness::client(&config)?;
+
+            let drift =
+                drift::channel_drift(&client, &mirror, &upstream, &subdir, package.as_deref())
+                    .await?;
+            drift::print_drift(&drift, &encode)?;
+            if !drift.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "{} drifted from {}: {} missing, {} removed upstream, {} hash mismatches",
+                    mirror,
+                    upstream,
+                    drift.missing.len(),
+                    drift.removed_upstream.len(),
+                    drift.hash_mismatches.len()
+                ));
+            }
+        }
+        #[cfg(feature = "solve")]
+        Commands::Lock {
+            channel,
+            file,
+            spec,
+            platform,
+            output,
+            config,
+        } => {
+            let config = if let Some(config_path) = config {
+                Config::load_from_file(&config_path)?
+            } else {
+                Config::default()
+            };
+            let mut specs = match file {
+                Some(path) => lockfile::EnvironmentSpec::from_path(&path)?.specs(),
+                None => Vec::new(),
+            };
+            specs.extend(spec);
+            let platforms = if platform.is_empty() {
+                vec![rattler_conda_types::Platform::current()]
+            } else {
+                platform
+                    .iter()
+                    .map(|p| p.parse::<rattler_conda_types::Platform>())
+                    .collect::<std::result::Result<Vec<_>, _>>()?
+            };
+            let client = politeness::client(&config)?;
+
+            let lock = lockfile::solve(&client, &channel, &specs, &platforms).await?;
+            match output {
+                Some(path) => {
+                    lock.to_path(std::path::Path::new(&path))?;
+                    info!(
+                        "Wrote lockfile for {} platforms to {}",
+                        platforms.len(),
+                        path
+                    );
+                }
+                None => print!("{}", lock.render_to_string()?),
+            }
+        }
+        #[cfg(feature = "solve")]
+        Commands::CheckInstall {
+            channel,
+            file,
+            spec,
+            platform,
+            prefix,
+            scratch_dir,
+            config,
+        } => {
+            let mut config = if let Some(config_path) = config {
+                Config::load_from_file(&config_path)?
+            } else {
+                Config::default()
+            };
+            if scratch_dir.is_some() {
+                config.scratch_dir = scratch_dir;
+            }
+            let mut specs = match file {
+                Some(path) => lockfile::EnvironmentSpec::from_path(&path)?.specs(),
+                None => Vec::new(),
+            };
+            specs.extend(spec);
+            let platform = match platform {
+                Some(platform) => platform.parse::<rattler_conda_types::Platform>()?,
+                None => rattler_conda_types::Platform::current(),
+            };
+            let client = politeness::client(&config)?;
+
+            let check = install_check::check_install(
+                &client,
+                &channel,
+                &specs,
+                platform,
+                prefix.as_deref().map(std::path::Path::new),
+                &config,
+            )
+            .await?;
+            println!(
+                "Installed {} packages ({} files) for {} from {}:",
+                check.packages.len(),
+                check.files,
+                check.platform,
+                channel
+            );
+            for package in &check.packages {
+                println!("  {}", package);
+            }
+        }
+        #[cfg(not(feature = "solve"))]
+        Commands::Lock { .. } | Commands::CheckInstall { .. } => {
+            return Err(anyhow::anyhow!(
+                "The lock and check-install commands require the 'solve' feature, which this build was compiled without"
+            ));
+        }
+        Commands::Prune {
+            tgt_type,
+            tgt,
+            keep,
+            scope,
+            older_than,
+            protect,
+            lockfile,
+            dry_run,
+            config,
+        } => {
+            let config = if let Some(config_path) = config {
+                Config::load_from_file(&config_path)?
+            } else {
+                Config::default()
+            };
+            let mut retention = config.retention.clone().unwrap_or_default();
+            if keep.is_some() {
+                retention.keep = keep;
+            }
+            if let Some(scope) = scope {
+                retention.scope = retention::RetentionScope::from_string(&scope)?;
+            }
+            if older_than.is_some() {
+                retention.older_than = older_than;
+            }
+            retention.protect.extend(protect);
+            retention.lockfiles.extend(lockfile);
+            if retention.keep.is_none()
+                && retention.older_than.is_none()
+                && retention.lockfiles.is_empty()
+            {
+                return Err(anyhow::anyhow!(
+                    "No retention rule: pass --keep, --older-than, --lockfile or set retention in the config"
+                ));
+            }
+            let rule = retention::RetentionRule::new(&retention)?;
+
+            let repo_type = RepositoryType::from_string(&tgt_type)?;
+            let target = target_path(&repo_type, tgt)?;
+            let repository = mirror::configured_repository(
+                repository::Repository::new(repo_type, target.clone()),
+                &config,
+            )?;
+            let expired = repository.prune(&rule, dry_run).await?;
+            let verb = if dry_run { "Would remove" } else { "Removed" };
+            for path in &expired {
+                println!("{} {}", verb, path);
+            }
+            println!("{} {} packages from {}", verb, expired.len(), target);
+        }
+        Commands::Gc {
+            tgt_type,
+            tgt,
+            action,
+            dry_run,
+            config,
+        } => {
+            let config = if let Some(config_path) = config {
+                Config::load_from_file(&config_path)?
+            } else {
+                Config::default()
+            };
+            let action = repository::OrphanAction::from_string(&action)?;
+            let repository = mirror::configured_repository(
+                repository::Repository::new(RepositoryType::from_string(&tgt_type)?, tgt.clone()),
+                &config,
+            )?;
+            let orphans = repository.collect_orphans(action, dry_run).await?;
+            let verb = match (dry_run, action) {
+                (true, _) => "Found orphaned",
+                (false, repository::OrphanAction::Index) => "Indexed",
+                (false, repository::OrphanAction::Delete) => "Removed",
+            };
+            for path in &orphans {
+                println!("{} {}", verb, path);
+            }
+            println!("{} {} packages in {}", verb, orphans.len(), tgt);
+        }
+        Commands::AbortUploads {
+            tgt_type,
+            tgt,
+            dry_run,
+            config,
+        } => {
+            let config = if let Some(config_path) = config {
+                Config::load_from_file(&config_path)?
+            } else {
+                Config::default()
+            };
+            let repository = mirror::configured_repository(
+                repository::Repository::new(RepositoryType::from_string(&tgt_type)?, tgt.clone()),
+                &config,
+            )?;
+            let uploads = repository.abort_incomplete_uploads(dry_run).await?;
+            let verb = if dry_run {
+                "Found incomplete"
+            } else {
+                "Aborted"
+            };
+            for upload in &uploads {
+                println!("{} upload of {}", verb, upload);
+            }
+            println!("{} {} uploads in {}", verb, uploads.len(), tgt);
+        }
+        Commands::Rollback {
+            tgt_type,
+            tgt,
+            snapshot,
+            list,
+            config,
+        } => {
+            let config = if let Some(config_path) = config {
+                Config::load_from_file(&config_path)?
+            } else {
+                Config::default()
+            };
+            let repository = mirror::configured_repository(
+                repository::Repository::new(RepositoryType::from_string(&tgt_type)?, tgt.clone()),
+                &config,
+            )?;
+            if list {
+                let snapshots = repository.repodata_snapshots().await?;
+                for snapshot in &snapshots {
+                    println!(
+                        "{}  {}  before {}  ({})",
+                        snapshot.id,
+                        snapshot.taken_at.to_rfc3339(),
+                        snapshot.reason,
+                        snapshot.subdirs.join(", ")
+                    );
                 }
+                println!("{} repodata snapshots of {}", snapshots.len(), tgt);
+            } else {
+                let rollback = repository.rollback(snapshot.as_deref()).await?;
+                for path in &rollback.missing {
+                    println!("Left out {}: its file is no longer at the target", path);
+                }
+                println!(
+                    "Restored the repodata of {} from snapshot {} taken {}",
+                    tgt,
+                    rollback.snapshot.id,
+                    rollback.snapshot.taken_at.to_rfc3339()
+                );
             }
-
-            // Validate target type
-            match tgt_type.as_str() {
-                "prefix-dev" | "prefix" | "s3" | "minio" | "local" | "file" | "cache" => {}
-                _ => {
-                    return Err(anyhow::anyhow!(
-                        "Invalid tgt-type '{}'. Must be one of: cache (individual package storage), prefix-dev, s3, local (conda repositories)",
-                        tgt_type
-                    ));
+        }
+        Commands::Snapshot {
+            tgt,
+            name,
+            output,
+            list,
+        } => {
+            let channel = std::path::Path::new(&tgt);
+            let root = output.map_or_else(
+                || channel_snapshot::snapshot_root(channel),
+                std::path::PathBuf::from,
+            );
+            if list {
+                let snapshots = channel_snapshot::list(&root)?;
+                for snapshot in &snapshots {
+                    println!(
+                        "{}  {}  {} packages  ({})",
+                        snapshot.name,
+                        snapshot.taken_at.to_rfc3339(),
+                        snapshot.packages,
+                        snapshot.subdirs.join(", ")
+                    );
                 }
+                println!("{} snapshots of {}", snapshots.len(), tgt);
+            } else {
+                let name = name.unwrap_or_else(channel_snapshot::default_name);
+                let snapshot = channel_snapshot::take(channel, &name, Some(&root))?;
+                println!(
+                    "Snapshot {} of {} at {}: {} packages in {} subdirs, {} copied instead of linked",
+                    snapshot.name,
+                    tgt,
+                    root.join(&snapshot.name).display(),
+                    snapshot.packages,
+                    snapshot.subdirs.len(),
+                    snapshot.copied
+                );
             }
-
-            // Validate that src_path is provided for zip files
-            if (src_type == "zip" || src_type == "zip-url") && src_path.is_none() {
+        }
+        Commands::Share {
+            cache,
+            tgt,
+            package,
+            hardlink,
+        } => {
+            let cache_dir = match cache {
+                Some(cache) => std::path::PathBuf::from(cache),
+                None => default_cache_dir()
+                    .map_err(|e| anyhow::anyhow!("Failed to get default cache directory: {}", e))?
+                    .join(rattler_cache::PACKAGE_CACHE_DIR),
+            };
+            let package = package
+                .map(|pattern| {
+                    regex::Regex::new(&pattern)
+                        .map_err(|e| anyhow::anyhow!("Invalid --package pattern: {}", e))
+                })
+                .transpose()?;
+            let report = share::share_cache(
+                &cache_dir,
+                std::path::Path::new(&tgt),
+                package.as_ref(),
+                hardlink,
+            )
+            .await?;
+            report.print_summary();
+            if report.failed_count() > 0 {
                 return Err(anyhow::anyhow!(
-                    "--src-path is required when src-type is 'zip' or 'zip-url'"
+                    "{} cached packages could not be shared",
+                    report.failed_count()
                 ));
             }
-
-            // Validate GitHub source format
-            if src_type == "github" {
-                if let Err(e) = github::parse_github_repository(&src) {
-                    return Err(anyhow::anyhow!("Invalid GitHub repository format: {}", e));
-                }
-            }
-
-            // Validate Azure DevOps source format
-            if src_type == "azure" {
-                if let Err(e) = azure::parse_azure_source(&src) {
-                    return Err(anyhow::anyhow!("Invalid Azure DevOps format: {}", e));
-                }
-            }
-
-            // Validate regex pattern if provided
-            if let Some(ref pattern) = src_path {
-                if let Err(e) = regex::Regex::new(pattern) {
-                    return Err(anyhow::anyhow!(
-                        "Invalid regular expression in --src-path: {}",
-                        e
-                    ));
-                }
-            }
-
+        }
+        Commands::Serve {
+            path,
+            port,
+            bind,
+            username,
+            config,
+        } => {
             let config = if let Some(config_path) = config {
                 Config::load_from_file(&config_path)?
             } else {
                 Config::default()
             };
-
-            let repo_type = RepositoryType::from_string(&tgt_type)?;
-
-            // Handle target path based on repository type
-            let target_path = match &repo_type {
-                repository::RepositoryType::Cache => {
-                    if tgt.is_some() {
-                        return Err(anyhow::anyhow!(
-                            "--tgt cannot be set when --tgt-type is 'cache'. Cache stores individual packages in the rattler cache directory automatically."
-                        ));
-                    }
-                    default_cache_dir()
-                        .map_err(|e| {
-                            anyhow::anyhow!("Failed to get default cache directory: {}", e)
-                        })?
-                        .to_string_lossy()
-                        .to_string()
+            let username = username
+                .or(config.serve_username)
+                .or_else(|| std::env::var("SERVE_USERNAME").ok());
+            let password = config
+                .serve_password
+                .or_else(|| std::env::var("SERVE_PASSWORD").ok());
+            let credentials = match (&username, &password) {
+                (Some(username), Some(password)) => Some((username.as_str(), password.as_str())),
+                (None, None) => None,
+                (Some(_), None) => {
+                    return Err(anyhow::anyhow!(
+                        "A user name is set but no password; set serve_password or SERVE_PASSWORD"
+                    ))
+                }
+                (None, Some(_)) => {
+                    return Err(anyhow::anyhow!(
+                        "A password is set but no user name; set --username, serve_username or SERVE_USERNAME"
+                    ))
                 }
-                _ => tgt.ok_or_else(|| {
-                    anyhow::anyhow!(
-                        "--tgt is required for repository types (local, s3, prefix-dev)"
-                    )
-                })?,
             };
-
-            let is_local_file = matches!(src_type.as_str(), "zip" | "local" | "tgz");
-            mirror_packages(
-                &src,
-                src_path.as_deref(),
-                &src_type,
-                is_local_file,
-                repo_type,
-                &target_path,
-                &config,
-            )
-            .await?;
-
-            info!("Mirroring completed successfully");
+            let server = serve::ChannelServer::new(&path, credentials)?;
+            let listener = serve::bind(&format!("{}:{}", bind, port)).await?;
+            shutdown::install_handler();
+            serve::serve(listener, server).await;
         }
-        Commands::Info {
-            github,
-            azure,
-            build_id,
-            name_filter,
-            description_filter,
-            encode,
-            exclude_expired,
+        Commands::Promote {
+            src_type,
+            src,
+            tgt_type,
+            tgt,
+            package,
+            label,
+            dry_run,
+            state_db,
             config,
         } => {
-            let config = if let Some(config_path) = config {
+            let mut config = if let Some(config_path) = config {
                 Config::load_from_file(&config_path)?
             } else {
                 Config::default()
             };
+            if state_db.is_some() {
+                config.state_db = state_db;
+            }
+            let package = package
+                .map(|pattern| {
+                    regex::Regex::new(&pattern)
+                        .map_err(|e| anyhow::anyhow!("Invalid --package pattern: {}", e))
+                })
+                .transpose()?;
+            let staging =
+                mirror::target_repository(RepositoryType::from_string(&src_type)?, &src, &config)?;
+            let mut production =
+                mirror::target_repository(RepositoryType::from_string(&tgt_type)?, &tgt, &config)?;
+
+            let source = promote::StagingSource::new(&staging, package);
+            let verdicts = promote::validate(&source, &config).await?;
+            let rejected = verdicts.iter().filter(|v| !v.admitted).count();
+            if rejected > 0 || dry_run {
+                policy::print_verdicts(&verdicts, "table")?;
+            }
+            if rejected > 0 {
+                return Err(anyhow::anyhow!(
+                    "{} of {} staged packages failed validation; nothing was promoted",
+                    rejected,
+                    verdicts.len()
+                ));
+            }
+            if dry_run {
+                println!(
+                    "{} packages of {} would be promoted to {}",
+                    verdicts.len(),
+                    src,
+                    tgt
+                );
+                return Ok(());
+            }
 
-            match (github, azure) {
-                (Some(repo), None) => {
-                    // GitHub info
-                    info!(
-                        "Getting GitHub artifact information for repository: {}",
-                        repo
-                    );
-                    let github_client = github::GitHubClient::new(&config)?;
-                    let (owner, repo_name) = github::parse_github_repository(&repo)?;
-
-                    let mut artifacts = github_client.list_artifacts(&owner, &repo_name).await?;
-
-                    // Filter by name if specified
-                    if let Some(ref pattern) = name_filter {
-                        artifacts =
-                            github_client.filter_artifacts_by_name(&artifacts, Some(pattern));
-                    }
+            let report =
+                promote::promote(&source, &mut production, &config, label.as_deref()).await?;
+            report.print_summary();
+            if report.failed_count() > 0 {
+                return Err(anyhow::anyhow!(
+                    "{} staged packages could not be promoted",
+                    report.failed_count()
+                ));
+            }
+        }
+        #[cfg(feature = "schedule")]
+        Commands::Daemon { config, listen } => {
+            let mut config = Config::load_from_file(&config)?;
+            if listen.is_some() {
+                config.health_listen = listen;
+            }
+            shutdown::install_handler();
+            daemon::run(&config).await?;
+        }
+        #[cfg(not(feature = "schedule"))]
+        Commands::Daemon { .. } => {
+            return Err(anyhow::anyhow!(
+                "The daemon command requires the 'schedule' feature, which this build was compiled without"
+            ));
+        }
+        Commands::Policy {
+            command:
+                PolicyCommands::Check {
+                    src_type,
+                    src,
+                    src_path,
+                    config,
+                    encode,
+                },
+        } => {
+            let config = Config::load_from_file(&config)?;
+            let policy_config = config
+                .policy
+                .as_ref()
+                .ok_or_else(|| anyhow::anyhow!("The configuration file has no 'policy' section"))?;
+            let policy = policy::Policy::new(policy_config)?;
+            if (src_type == "zip" || src_type == "zip-url")
+                && src_path.is_none()
+                && config.filter_preset.is_none()
+            {
+                return Err(anyhow::anyhow!(
+                    "--src-path is required when src-type is 'zip' or 'zip-url' and the configuration has no filter_preset"
+                ));
+            }
 
-                    // Filter expired artifacts if requested
-                    if exclude_expired {
-                        artifacts = github_client.filter_non_expired_artifacts(&artifacts);
-                    }
+            let is_local_file = matches!(src_type.as_str(), "zip" | "local" | "tgz");
+            let provider = mirror::source_provider(
+                &src,
+                src_path.as_deref(),
+                &src_type,
+                is_local_file,
+                &config,
+                None,
+            )?;
+            let verdicts = policy::check(provider.as_ref(), &policy).await?;
+            policy::print_verdicts(&verdicts, &encode)?;
 
-                    // Print the results
-                    github_client.print_artifacts_info(&artifacts, &encode)?;
+            let rejected = verdicts.iter().filter(|v| !v.admitted).count();
+            if rejected > 0 {
+                return Err(anyhow::anyhow!(
+                    "{} of {} packages would be rejected by the policy",
+                    rejected,
+                    verdicts.len()
+                ));
+            }
+        }
+        Commands::Review { command } => match command {
+            ReviewCommands::List {
+                all,
+                config,
+                encode,
+            } => {
+                let config = if let Some(config_path) = config {
+                    Config::load_from_file(&config_path)?
+                } else {
+                    Config::default()
+                };
+                let manifest =
+                    review::ReviewManifest::load(std::path::Path::new(&config.quarantine_dir))?;
+                review::print_entries(&manifest, all, &encode)?;
+            }
+            ReviewCommands::Approve {
+                packages,
+                all,
+                config,
+            } => {
+                if packages.is_empty() && !all {
+                    return Err(anyhow::anyhow!(
+                        "Name the packages to approve, or pass --all to approve every pending package"
+                    ));
                 }
-                (None, Some(azure_spec)) => {
-                    // Azure DevOps info
-                    let azure_client = azure::AzureDevOpsClient::new(&config)?;
-                    let (organization, project, specified_build_id) =
-                        azure::parse_azure_source(&azure_spec)?;
-
-                    let target_build_id = build_id.or(specified_build_id);
-
-                    // Case 1: Show artifacts for specific build (with optional name filtering)
-                    if let Some(build_id) = target_build_id {
-                        info!(
-                            "Getting Azure DevOps artifacts for build {} in {}/{}",
-                            build_id, organization, project
-                        );
-                        let mut artifacts = azure_client
-                            .list_artifacts(&organization, &project, build_id)
-                            .await?;
-
-                        // Apply name filter if specified (works independently)
-                        if let Some(ref pattern) = name_filter {
-                            artifacts =
-                                azure_client.filter_artifacts_by_name(&artifacts, Some(pattern));
-                        }
-
-                        azure_client.print_artifacts_info(&artifacts, &encode)?;
-                    }
-                    // Case 2: Show builds list (with optional description filtering)
-                    else {
-                        info!(
-                            "Getting Azure DevOps builds for {}/{}",
-                            organization, project
-                        );
-                        let mut builds = azure_client
-                            .list_builds(&organization, &project, None)
-                            .await?;
-
-                        // Apply description filter if specified (works independently)
-                        if let Some(ref pattern) = description_filter {
-                            builds = azure_client.filter_builds_by_description(&builds, pattern)?;
-                        }
-
-                        // Warn if name_filter specified but ignored
-                        if name_filter.is_some() {
-                            warn!("--name-filter is ignored when listing builds (no --build-id specified). Use --description-filter to filter builds.");
-                        }
-
-                        azure_client.print_builds_info(
-                            &builds,
-                            &organization,
-                            &project,
-                            &encode,
-                        )?;
-                    }
+                let config = if let Some(config_path) = config {
+                    Config::load_from_file(&config_path)?
+                } else {
+                    Config::default()
+                };
+                let reports = review::approve(
+                    std::path::Path::new(&config.quarantine_dir),
+                    &packages,
+                    &config,
+                )
+                .await?;
+                let mut failed = 0;
+                for report in &reports {
+                    report.print_summary();
+                    failed += report.failed_count();
                 }
-                (Some(_), Some(_)) => {
+                if failed > 0 {
                     return Err(anyhow::anyhow!(
-                        "Cannot specify both --github and --azure. Choose one."
+                        "{} approved packages could not be mirrored and stay pending",
+                        failed
                     ));
                 }
-                (None, None) => {
+            }
+            ReviewCommands::Reject {
+                packages,
+                all,
+                config,
+            } => {
+                if packages.is_empty() && !all {
                     return Err(anyhow::anyhow!(
-                        "Must specify either --github (for GitHub) or --azure (for Azure DevOps)."
+                        "Name the packages to reject, or pass --all to reject every pending package"
                     ));
                 }
+                let config = if let Some(config_path) = config {
+                    Config::load_from_file(&config_path)?
+                } else {
+                    Config::default()
+                };
+                let rejected =
+                    review::reject(std::path::Path::new(&config.quarantine_dir), &packages)?;
+                println!("Rejected {} packages", rejected.len());
+            }
+        },
+        Commands::Keys {
+            command:
+                KeysCommands::Rotate {
+                    tgt_type,
+                    tgt,
+                    new_key,
+                    subdir,
+                    config: config_path,
+                },
+        } => {
+            let mut config = Config::load_from_file(&config_path)?;
+            let repository = mirror::configured_repository(
+                repository::Repository::new(RepositoryType::from_string(&tgt_type)?, tgt.clone()),
+                &config,
+            )?;
+            let (signing, rotation) = repository.rotate_signing_key(&new_key, &subdir).await?;
+            for subdir in &rotation.subdirs {
+                println!("Re-signed {}/repodata.json", subdir);
             }
+            println!(
+                "Rotated {} from {} to {}; the change is published in {}",
+                tgt,
+                rotation.from.join(", "),
+                rotation.to,
+                signing::KEYS_FILE
+            );
+            config.signing = Some(signing);
+            config.save_to_file(&config_path)?;
+            println!("Updated the signing section of {}", config_path);
         }
         Commands::Init { output } => {
             info!("Initializing configuration file at: {}", output);
@@ -332,6 +2101,154 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+/// Resolve the target path, which for the cache is always the rattler cache directory
+fn target_path(repo_type: &RepositoryType, tgt: Option<String>) -> Result<String> {
+    match repo_type {
+        RepositoryType::Cache => {
+            if tgt.is_some() {
+                return Err(anyhow::anyhow!(
+                    "--tgt cannot be set when --tgt-type is 'cache'. Cache stores individual packages in the rattler cache directory automatically."
+                ));
+            }
+            Ok(default_cache_dir()
+                .map_err(|e| anyhow::anyhow!("Failed to get default cache directory: {}", e))?
+                .to_string_lossy()
+                .to_string())
+        }
+        _ => tgt.ok_or_else(|| {
+            anyhow::anyhow!("--tgt is required for repository types (local, s3, prefix-dev)")
+        }),
+    }
+}
+
+/// Print how the platform of each package of a run was decided, for `--explain`
+fn print_platform_explanations(report: &report::MirrorReport) {
+    for explanation in report
+        .packages
+        .iter()
+        .filter_map(|package| package.platform_explanation.as_deref())
+    {
+        println!("{}", explanation);
+    }
+}
+
+/// Print the records of the state database, or the drift of one target from them
+#[cfg(feature = "state-db")]
+#[allow(clippy::too_many_arguments)]
+async fn show_history(
+    state_db: &str,
+    src: Option<String>,
+    tgt_type: &str,
+    tgt: Option<String>,
+    package: Option<String>,
+    limit: usize,
+    drift: bool,
+    encode: &str,
+) -> Result<()> {
+    let db = state::StateDb::open(state_db)?;
+
+    if drift {
+        let repo_type = RepositoryType::from_string(tgt_type)?;
+        let target = target_path(&repo_type, tgt)?;
+        let stored = repository::Repository::new(repo_type, target.clone())
+            .stored_packages()
+            .await?;
+        state::print_drift(&db.drift(&target, &stored)?, encode)?;
+        return Ok(());
+    }
+
+    let packages = db.history(&state::HistoryQuery {
+        source: src,
+        target: tgt.clone(),
+        package: package.clone(),
+        limit: Some(limit),
+    })?;
+    state::print_history(&packages, encode)?;
+    if let (true, Some(package)) = (packages.is_empty(), &package) {
+        let names = db.package_names(tgt.as_deref())?;
+        let suggestions = suggest::similar(package, names.iter().map(String::as_str));
+        if let Some(hint) = suggest::did_you_mean(&suggestions) {
+            eprintln!("No package matches '{}'; {}", package, hint);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "state-db")]
+fn show_promotions(
+    state_db: &str,
+    staging: Option<String>,
+    production: Option<String>,
+    package: Option<String>,
+    limit: usize,
+    encode: &str,
+) -> Result<()> {
+    let promotions = state::StateDb::open(state_db)?.promotions(&state::HistoryQuery {
+        source: staging,
+        target: production,
+        package,
+        limit: Some(limit),
+    })?;
+    state::print_promotions(&promotions, encode)?;
+    Ok(())
+}
+
+#[cfg(not(feature = "state-db"))]
+fn show_promotions(
+    _state_db: &str,
+    _staging: Option<String>,
+    _production: Option<String>,
+    _package: Option<String>,
+    _limit: usize,
+    _encode: &str,
+) -> Result<()> {
+    Err(anyhow::anyhow!(
+        "The history command requires the 'state-db' feature, which this build was compiled without"
+    ))
+}
+
+#[cfg(feature = "state-db")]
+fn show_stats(state_db: &str, target: &str, history: bool, encode: &str) -> Result<()> {
+    let mut stats = state::StateDb::open(state_db)?.stats_history(target)?;
+    if !history {
+        stats = stats.split_off(stats.len().saturating_sub(1));
+    }
+    state::print_stats(&stats, encode)?;
+    Ok(())
+}
+
+#[cfg(not(feature = "state-db"))]
+fn show_stats(_state_db: &str, _target: &str, _history: bool, _encode: &str) -> Result<()> {
+    Err(anyhow::anyhow!(
+        "The stats command requires the 'state-db' feature, which this build was compiled without"
+    ))
+}
+
+#[cfg(not(feature = "state-db"))]
+#[allow(clippy::too_many_arguments)]
+async fn show_history(
+    _state_db: &str,
+    _src: Option<String>,
+    _tgt_type: &str,
+    _tgt: Option<String>,
+    _package: Option<String>,
+    _limit: usize,
+    _drift: bool,
+    _encode: &str,
+) -> Result<()> {
+    Err(anyhow::anyhow!(
+        "The history command requires the 'state-db' feature, which this build was compiled without"
+    ))
+}
+
+/// Suggest artifact names close to a `--name-filter` that matched nothing
+fn suggest_artifact_names(pattern: &str, names: &[String]) {
+    let suggestions = suggest::similar(pattern, names.iter().map(String::as_str));
+    if let Some(hint) = suggest::did_you_mean(&suggestions) {
+        eprintln!("No artifact matches '{}'; {}", pattern, hint);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{Cli, Commands};
@@ -351,6 +2268,82 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_repeated_src() {
+        let args = vec![
+            "meso-forge-mirror",
+            "mirror",
+            "--src-type",
+            "github",
+            "--src",
+            "owner/first",
+            "--src",
+            "owner/second",
+        ];
+        let cli = Cli::try_parse_from(args).unwrap();
+
+        match cli.command {
+            Commands::Mirror { src, .. } => {
+                assert_eq!(src, vec!["owner/first", "owner/second"]);
+            }
+            _ => panic!("Expected Mirror command"),
+        }
+        assert!(Cli::try_parse_from(vec!["meso-forge-mirror", "mirror"]).is_err());
+    }
+
+    #[test]
+    fn test_match_all_alias() {
+        let args = vec![
+            "meso-forge-mirror",
+            "mirror",
+            "--src-type",
+            "zip",
+            "--src",
+            "artifacts.zip",
+            "--src-path",
+            r".*\.conda$",
+            "--match-all",
+        ];
+        let cli = Cli::try_parse_from(args).unwrap();
+
+        match cli.command {
+            Commands::Mirror { all_matches, .. } => assert!(all_matches),
+            _ => panic!("Expected Mirror command"),
+        }
+    }
+
+    #[test]
+    fn test_shortcut_parses_as_mirror() {
+        let args = [
+            "meso-forge-mirror",
+            "pr",
+            "https://github.com/owner/repo/pull/12",
+        ]
+        .into_iter()
+        .chain(["-t", "s3://bucket/conda", "--all-matches"])
+        .map(std::ffi::OsString::from)
+        .collect();
+        let cli = Cli::try_parse_from(crate::shortcuts::expand(args)).unwrap();
+
+        match cli.command {
+            Commands::Mirror {
+                src_type,
+                src,
+                tgt_type,
+                tgt,
+                all_matches,
+                ..
+            } => {
+                assert_eq!(src_type, "github");
+                assert_eq!(src, vec!["https://github.com/owner/repo/pull/12"]);
+                assert_eq!(tgt_type, "s3");
+                assert_eq!(tgt.as_deref(), Some("s3://bucket/conda"));
+                assert!(all_matches);
+            }
+            _ => panic!("Expected Mirror command"),
+        }
+    }
+
     #[test]
     fn test_cache_tgt_type_validation() {
         // Test that tgt is optional when tgt_type is cache
@@ -400,7 +2393,12 @@ mod tests {
     #[test]
     fn test_help_shows_cache_option() {
         // This test ensures the help text includes cache as an option
-        let help_output = Cli::command().render_help().to_string();
+        let mut command = Cli::command();
+        let help_output = command
+            .find_subcommand_mut("mirror")
+            .expect("mirror subcommand")
+            .render_help()
+            .to_string();
         assert!(help_output.contains("cache"));
         assert!(help_output.contains("stores individual packages for reuse"));
         assert!(help_output.contains("automatically determined for 'cache'"));
@@ -494,17 +2492,12 @@ mod tests {
     }
 
     fn create_test_conda_package_content() -> Vec<u8> {
-        // Create a minimal but valid conda package structure
-        // This is a simplified representation - in reality, conda packages are more complex
-        let mut content = Vec::new();
-
-        // Add some mock conda package data (ZIP format with metadata)
-        // For testing purposes, we'll create a simple structure that represents a conda package
-        content.extend_from_slice(b"PK\x03\x04"); // ZIP file signature
-        content.extend_from_slice(b"mock_conda_package_content_for_testing");
-        content.extend_from_slice(&[0u8; 100]); // Padding to make it look more realistic
-
-        content
+        crate::test_support::PackageFixture::from_filename(
+            "rb-asciidoctor-revealjs-5.2.0-h1d6dcf3_0.conda",
+        )
+        .unwrap()
+        .to_conda()
+        .to_vec()
     }
 
     #[test]
@@ -0,0 +1,165 @@
+//! Provenance manifest of a mirrored channel
+//!
+//! Every run that mirrors something into a local or S3 channel records, in
+//! `mirror-manifest.json` at the channel root, where each package it wrote came
+//! from: the source of the run, the URL and CI artifact the package was taken
+//! from, when it was mirrored, its sha256 and where its metadata came from.
+//! Entries written by earlier runs are kept, so the manifest traces every
+//! package in the channel; packages removed by retention are dropped from it.
+//! The manifest is replaced as a whole, so readers never see a partial update.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::conda_package::MetadataSource;
+use crate::error::Result;
+use crate::report::{MirrorReport, PackageOutcome};
+
+/// Name of the manifest at the channel root
+pub const MANIFEST_FILENAME: &str = "mirror-manifest.json";
+
+/// Where one package came from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// The source of the run that mirrored the package, e.g. `owner/repo`
+    pub source: String,
+    /// URL or path the package was taken from, e.g. a workflow run artifact
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Name of the CI artifact the package was extracted from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artifact: Option<String>,
+    /// GitHub Actions workflow run that produced the artifact
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<u64>,
+    /// Azure DevOps build that produced the artifact
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build_id: Option<u64>,
+    /// GitLab CI pipeline that produced the artifact
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pipeline_id: Option<u64>,
+    /// GitLab CI job whose artifacts the package was taken from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job_id: Option<u64>,
+    pub mirrored_at: DateTime<Utc>,
+    pub sha256: String,
+    /// Where the metadata of the package came from; unset in entries of older versions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata_source: Option<MetadataSource>,
+}
+
+/// Sources of the packages in a channel, keyed by `subdir/filename`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MirrorManifest {
+    #[serde(default)]
+    pub packages: BTreeMap<String, ManifestEntry>,
+}
+
+impl MirrorManifest {
+    pub fn from_slice(content: &[u8]) -> Result<Self> {
+        Ok(serde_json::from_slice(content)?)
+    }
+
+    pub fn to_vec(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec_pretty(self)?)
+    }
+
+    /// Record the packages a run mirrored, replacing earlier entries for them
+    ///
+    /// Returns the number of packages recorded.
+    pub fn record(&mut self, report: &MirrorReport, mirrored_at: DateTime<Utc>) -> usize {
+        let mut recorded = 0;
+        for package in &report.packages {
+            let (PackageOutcome::Mirrored, Some(platform), Some(sha256)) =
+                (&package.outcome, &package.platform, &package.sha256)
+            else {
+                continue;
+            };
+            let artifact = package.artifact.as_ref();
+            self.packages.insert(
+                format!("{}/{}", platform, package.filename),
+                ManifestEntry {
+                    source: report.source.clone(),
+                    url: package.origin.clone(),
+                    artifact: artifact.map(|artifact| artifact.name.clone()),
+                    run_id: artifact.and_then(|artifact| artifact.run_id),
+                    build_id: artifact.and_then(|artifact| artifact.build_id),
+                    pipeline_id: artifact.and_then(|artifact| artifact.pipeline_id),
+                    job_id: artifact.and_then(|artifact| artifact.job_id),
+                    mirrored_at,
+                    sha256: sha256.clone(),
+                    metadata_source: package.metadata_source,
+                },
+            );
+            recorded += 1;
+        }
+        recorded
+    }
+
+    /// Drop the entries of removed packages, given as `subdir/filename` paths
+    pub fn remove(&mut self, paths: &[String]) {
+        for path in paths {
+            self.packages.remove(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::SkipCode;
+    use crate::source::ArtifactSource;
+    use std::time::Duration;
+
+    #[test]
+    fn test_record_and_remove() {
+        let mut report = MirrorReport::new("owner/repo", "/srv/channel");
+        let package = report.record(
+            "pkg-1.0-0.conda",
+            PackageOutcome::Mirrored,
+            10,
+            Duration::ZERO,
+        );
+        package.platform = Some("noarch".to_string());
+        package.sha256 = Some("abc".to_string());
+        package.origin =
+            Some("https://github.com/owner/repo/actions/runs/42/artifacts/7".to_string());
+        package.artifact = Some(ArtifactSource {
+            name: "conda-packages".to_string(),
+            run_id: Some(42),
+            build_id: None,
+            pipeline_id: None,
+            job_id: None,
+        });
+        report.record(
+            "other-1.0-0.conda",
+            PackageOutcome::Skipped {
+                code: SkipCode::AlreadyPresent,
+                reason: "identical copy already present at the target".to_string(),
+            },
+            10,
+            Duration::ZERO,
+        );
+
+        let mirrored_at = Utc::now();
+        let mut manifest = MirrorManifest::default();
+        assert_eq!(manifest.record(&report, mirrored_at), 1);
+        let entry = &manifest.packages["noarch/pkg-1.0-0.conda"];
+        assert_eq!(entry.source, "owner/repo");
+        assert_eq!(entry.artifact.as_deref(), Some("conda-packages"));
+        assert_eq!(entry.run_id, Some(42));
+        assert_eq!(entry.build_id, None);
+        assert_eq!(entry.sha256, "abc");
+
+        let content = manifest.to_vec().unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&content).unwrap();
+        assert!(json["packages"]["noarch/pkg-1.0-0.conda"]
+            .get("build_id")
+            .is_none());
+        assert_eq!(MirrorManifest::from_slice(&content).unwrap(), manifest);
+
+        manifest.remove(&["noarch/pkg-1.0-0.conda".to_string()]);
+        assert!(manifest.packages.is_empty());
+    }
+}
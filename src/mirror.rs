@@ -1,18 +1,56 @@
-use anyhow::{anyhow, Result};
+use async_trait::async_trait;
 use bytes::Bytes;
 use flate2::read::GzDecoder;
+use futures::{future, stream, FutureExt, StreamExt};
 use regex::Regex;
 use reqwest::Client;
+use std::future::Future;
 use std::io::Read;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tar::Archive;
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn};
 use url::Url;
 
+use crate::allowlist;
+use crate::auth;
 use crate::azure;
+use crate::channel_source::{self, ChannelProvider};
+use crate::circuit_breaker;
 use crate::config::Config;
+use crate::download::{download_with_retries, verify_download_size};
+use crate::enrich::MetadataUpstream;
+use crate::error::{MirrorError, Result};
 use crate::github;
-use crate::repository::{Repository, RepositoryType};
+use crate::gitlab;
+use crate::limits;
+use crate::osv::OsvClient;
+use crate::policy::Policy;
+use crate::politeness;
+use crate::presets;
+use crate::priority;
+use crate::provenance::{self, ProvenanceContext};
+use crate::quarantine;
+use crate::remote_zip;
+use crate::report::{
+    MirrorReport, PackageOutcome, PackageReport, QuarantinedArchive, SkipCode, SkippedItem,
+};
+use crate::repository::{
+    listed_packages, FailedUpload, OciBackend, PrefixDevBackend, Repository, RepositoryType,
+    UploadBatching, UploadStatus,
+};
+use crate::resume::ResumeState;
+use crate::retention::RetentionRule;
+use crate::review;
+use crate::shutdown;
+use crate::signing::Signer;
+use crate::source::{ArtifactSource, PackageEntry, PackageStream, SourceProvider};
+use crate::specs::SpecFilter;
+#[cfg(feature = "state-db")]
+use crate::state::StateDb;
+use crate::suggest;
+use crate::upstream::{self, UpstreamState, Validators};
 
 pub async fn mirror_packages(
     source: &str,
@@ -22,74 +60,617 @@ pub async fn mirror_packages(
     target_type: RepositoryType,
     target_path: &str,
     config: &Config,
+) -> Result<MirrorReport> {
+    let mut repository = target_repository(target_type, target_path, config)?;
+    mirror_into(
+        source,
+        zip_path,
+        source_type,
+        is_local_file,
+        &mut repository,
+        config,
+        None,
+    )
+    .await
+}
+
+/// Repository for a target, with the settings of `config`
+pub(crate) fn target_repository(
+    target_type: RepositoryType,
+    target_path: &str,
+    config: &Config,
+) -> Result<Repository> {
+    let repository = match target_type {
+        RepositoryType::PrefixDev => Repository::from_backend(
+            RepositoryType::PrefixDev,
+            target_path,
+            Arc::new(PrefixDevBackend::new(target_path).with_batching(upload_batching(config))),
+        ),
+        RepositoryType::Oci => Repository::from_backend(
+            RepositoryType::Oci,
+            target_path,
+            Arc::new(oci_backend(target_path, config, None)?),
+        ),
+        target_type => Repository::new(target_type, target_path.to_string()),
+    };
+    configured_repository(repository, config)
+}
+
+/// Backend of an OCI target, with the credentials of `config`
+///
+/// `http_client`, when given, is used instead of a client built from `config`.
+pub(crate) fn oci_backend(
+    target_path: &str,
+    config: &Config,
+    http_client: Option<Client>,
+) -> Result<OciBackend> {
+    let client = match http_client {
+        Some(client) => client,
+        None => build_client(config)?,
+    };
+    let credentials = config.oci_password.clone().map(|password| {
+        let username = config
+            .oci_username
+            .clone()
+            .unwrap_or_else(|| "oauth2".to_string());
+        (username, password)
+    });
+    Ok(OciBackend::with_client(target_path, client, credentials))
+}
+
+/// How `config` asks for packages to be uploaded to prefix.dev
+pub(crate) fn upload_batching(config: &Config) -> UploadBatching {
+    UploadBatching {
+        max_packages: config.upload_batch_size.max(1),
+        max_bytes: config.upload_batch_bytes,
+        parallelism: config.upload_parallelism.max(1),
+        attempts: config.retry_attempts,
+    }
+}
+
+/// Apply the repository settings of `config`
+pub(crate) fn configured_repository(repository: Repository, config: &Config) -> Result<Repository> {
+    Ok(repository
+        .with_force_replace(config.force_replace)
+        .with_force(config.force)
+        .with_duplicate_platform_policy(config.duplicate_platform_policy)
+        .with_strict_platform(config.strict_platform)
+        .with_explain_platform(config.explain_platform)
+        .with_filename_policy(config.filename_policy)
+        .with_cache_extract(config.cache_extract)
+        .with_s3_multipart(
+            config.s3_multipart_threshold,
+            config.s3_multipart_part_size,
+            &config.s3_multipart_state_file,
+        )
+        .with_verify_extraction(config.verify_extraction)
+        .with_scratch_space(
+            config.scratch_dir.as_ref().map(PathBuf::from),
+            config.min_free_space,
+        )
+        .with_policy(config.policy.as_ref().map(Policy::new).transpose()?)
+        .with_specs(SpecFilter::new(&config.specs)?)
+        .with_metadata_upstream(
+            config
+                .enrich_from
+                .as_ref()
+                .map(|channel| {
+                    Ok::<_, MirrorError>(MetadataUpstream::new(
+                        politeness::client(config)?,
+                        channel,
+                    ))
+                })
+                .transpose()?,
+        )
+        .with_signer(config.signing.as_ref().map(Signer::new))
+        .with_channel_name(config.channel_name.clone())
+        .with_platform_mappings(config.platform_mappings.clone())
+        .with_repodata_snapshots(config.repodata_snapshots)
+        .with_sharded_repodata(config.sharded_repodata))
+}
+
+/// Mirror a source into an already constructed repository
+///
+/// `http_client`, when given, is used for package downloads and the GitHub and
+/// Azure DevOps APIs instead of clients built from `config`.
+pub(crate) async fn mirror_into(
+    source: &str,
+    zip_path: Option<&str>,
+    source_type: &str,
+    is_local_file: bool,
+    repository: &mut Repository,
+    config: &Config,
+    http_client: Option<Client>,
+) -> Result<MirrorReport> {
+    let sources = [source.to_string()];
+    check_allowed_sources(&sources, source_type, is_local_file, config)?;
+    let upstream = check_upstream(
+        &sources,
+        source_type,
+        is_local_file,
+        &repository.path,
+        config,
+    )
+    .await?;
+    if !upstream.unchanged.is_empty() {
+        return Ok(upstream.unchanged_report(source, &repository.path));
+    }
+
+    // Surface credential and permission problems before spending time on downloads
+    repository.preflight().await?;
+    repository.snapshot_repodata("mirror").await?;
+    let config = &with_target_packages(source_type, repository, config).await?;
+
+    let provider = source_provider(
+        source,
+        zip_path,
+        source_type,
+        is_local_file,
+        config,
+        http_client,
+    )?;
+
+    let result = mirror_from_provider(provider.as_ref(), repository, config).await;
+    warn_open_hosts(config);
+    if let Ok(report) = &result {
+        upstream.record(report, config)?;
+    }
+    result
+}
+
+/// The configuration with the packages the target lists, unless `force` is set
+///
+/// Only channel sources know the sha256 of their packages before downloading
+/// them, and leave out those the target already has an identical copy of.
+async fn with_target_packages(
+    source_type: &str,
+    repository: &Repository,
+    config: &Config,
+) -> Result<Config> {
+    let mut config = config.clone();
+    if source_type == "channel" && !config.force {
+        let listed = listed_packages(repository.backend().as_ref()).await?;
+        config.target_packages = Some(Arc::new(
+            listed
+                .into_iter()
+                .filter_map(|package| Some((package.filename, package.sha256?)))
+                .collect(),
+        ));
+    }
+    Ok(config)
+}
+
+/// Refuse remote sources outside `allowed_source_hosts` before anything is requested from them
+fn check_allowed_sources(
+    sources: &[String],
+    source_type: &str,
+    is_local_file: bool,
+    config: &Config,
 ) -> Result<()> {
-    let mut repository = Repository::new(target_type, target_path.to_string());
+    if matches!(
+        source_type,
+        "github" | "github-release" | "azure" | "gitlab"
+    ) {
+        return Ok(());
+    }
+    sources.iter().try_for_each(|source| {
+        let is_local = match source_type {
+            "channel" => !channel_source::is_remote(source),
+            _ => is_local_file,
+        };
+        allowlist::check_source(source, is_local, config)
+    })
+}
+
+/// Sources of a run checked for upstream changes against `upstream_state_file`
+#[derive(Debug, Default)]
+struct UpstreamCheck {
+    state: UpstreamState,
+    target: String,
+    /// Current validators of the sources that changed
+    changed: Vec<(String, Validators)>,
+    unchanged: Vec<SkippedItem>,
+}
+
+/// Compare the validators of `sources` with those of the last run into `target`
+///
+/// A channel is checked through the `repodata.json` of each of its subdirs, and
+/// is unchanged only when none of them changed. CI artifact and release sources
+/// are not checked, as their listings change with every workflow run, build or
+/// release anyway.
+async fn check_upstream(
+    sources: &[String],
+    source_type: &str,
+    is_local_file: bool,
+    target: &str,
+    config: &Config,
+) -> Result<UpstreamCheck> {
+    let Some(path) = &config.upstream_state_file else {
+        return Ok(UpstreamCheck::default());
+    };
+    if matches!(
+        source_type,
+        "github" | "github-release" | "azure" | "gitlab"
+    ) {
+        return Ok(UpstreamCheck::default());
+    }
+
+    let mut check = UpstreamCheck {
+        state: UpstreamState::load(path)?,
+        target: target.to_string(),
+        ..Default::default()
+    };
     let client = build_client(config)?;
+    for source in sources {
+        if source_type == "channel" {
+            check
+                .check_channel(&client, source, &config.src_subdirs)
+                .await;
+            continue;
+        }
+        let Some(validators) = upstream::probe(&client, source, is_local_file).await else {
+            continue;
+        };
+        if check.state.is_unchanged(source, target, &validators) {
+            info!(
+                "{} is unchanged since it was last mirrored ({})",
+                source,
+                validators.describe()
+            );
+            check.unchanged.push(SkippedItem::new(
+                "source",
+                source.as_str(),
+                SkipCode::Unchanged,
+                format!("unchanged upstream ({})", validators.describe()),
+            ));
+        } else {
+            check.changed.push((source.clone(), validators));
+        }
+    }
+    Ok(check)
+}
+
+impl UpstreamCheck {
+    /// Compare the `repodata.json` of each subdir of `channel` with the last run
+    async fn check_channel(&mut self, client: &Client, channel: &str, subdirs: &[String]) {
+        let Some(repodata) = upstream::probe_channel(client, channel, subdirs).await else {
+            return;
+        };
+        let unchanged = !repodata.is_empty()
+            && repodata.iter().all(|(source, validators)| {
+                self.state.is_unchanged(source, &self.target, validators)
+            });
+        if unchanged {
+            info!(
+                "No repodata.json of {} changed since it was last mirrored",
+                channel
+            );
+            self.unchanged.push(SkippedItem::new(
+                "source",
+                channel,
+                SkipCode::Unchanged,
+                format!("unchanged upstream ({} repodata.json)", repodata.len()),
+            ));
+        } else {
+            self.changed.extend(repodata);
+        }
+    }
+
+    fn is_unchanged(&self, source: &str) -> bool {
+        self.unchanged.iter().any(|item| item.name == source)
+    }
+
+    /// Report of a run that ended because its sources were all unchanged
+    fn unchanged_report(&self, source: &str, target: &str) -> MirrorReport {
+        let mut report = MirrorReport::new(source, target);
+        report.skipped_items = self.unchanged.clone();
+        report
+    }
+
+    /// Remember the validators of the changed sources once a run mirrored them without failures
+    fn record(&self, report: &MirrorReport, config: &Config) -> Result<()> {
+        let Some(path) = &config.upstream_state_file else {
+            return Ok(());
+        };
+        if self.changed.is_empty() || !report.is_success() || shutdown::is_requested() {
+            return Ok(());
+        }
+        let mut state = self.state.clone();
+        for (source, validators) in &self.changed {
+            state.record(source, &self.target, validators.clone(), chrono::Utc::now());
+        }
+        state.save(path)
+    }
+}
+
+/// Mirror several sources of the same type into one target in a single run
+///
+/// The sources are listed and their packages fetched concurrently, each with up
+/// to `max_concurrent_downloads` packages fetched ahead, while packages are
+/// uploaded one at a time as they arrive. The target is finalized once, after
+/// the packages of every source are in, instead of once per source.
+pub async fn mirror_sources(
+    sources: &[String],
+    zip_path: Option<&str>,
+    source_type: &str,
+    is_local_file: bool,
+    target_type: RepositoryType,
+    target_path: &str,
+    config: &Config,
+) -> Result<MirrorReport> {
+    if let [source] = sources {
+        return mirror_packages(
+            source,
+            zip_path,
+            source_type,
+            is_local_file,
+            target_type,
+            target_path,
+            config,
+        )
+        .await;
+    }
+
+    check_allowed_sources(sources, source_type, is_local_file, config)?;
+    let upstream = check_upstream(sources, source_type, is_local_file, target_path, config).await?;
+    let changed: Vec<&String> = sources
+        .iter()
+        .filter(|source| !upstream.is_unchanged(source))
+        .collect();
+    if changed.is_empty() {
+        return Ok(upstream.unchanged_report(&sources.join(", "), target_path));
+    }
+
+    let mut repository = target_repository(target_type, target_path, config)?;
+    repository.preflight().await?;
+    repository.snapshot_repodata("mirror").await?;
+    let config = &with_target_packages(source_type, &repository, config).await?;
+
+    let providers = changed
+        .into_iter()
+        .map(|source| source_provider(source, zip_path, source_type, is_local_file, config, None))
+        .collect::<Result<Vec<_>>>()?;
+    let provider = MultiSourceProvider::new(providers, config.max_concurrent_downloads);
+
+    let result = mirror_from_provider(&provider, &mut repository, config).await;
+    warn_open_hosts(config);
+    let mut report = result?;
+    upstream.record(&report, config)?;
+    report.skipped_items.extend(upstream.unchanged);
+    Ok(report)
+}
+
+/// Warn about the hosts the circuit breaker stopped sending requests to
+fn warn_open_hosts(config: &Config) {
+    for (host, failures) in circuit_breaker::shared(config).open_hosts() {
+        warn!(
+            "Host {} was skipped after {} consecutive failures",
+            host, failures
+        );
+    }
+}
+
+/// Several sources mirrored in one run, whose packages are fetched concurrently
+struct MultiSourceProvider {
+    name: String,
+    providers: Vec<Box<dyn SourceProvider>>,
+    per_source: usize,
+}
+
+impl MultiSourceProvider {
+    fn new(providers: Vec<Box<dyn SourceProvider>>, per_source: usize) -> Self {
+        let name = providers
+            .iter()
+            .map(|provider| provider.name())
+            .collect::<Vec<_>>()
+            .join(", ");
+        Self {
+            name,
+            providers,
+            per_source: per_source.max(1),
+        }
+    }
+}
+
+#[async_trait]
+impl SourceProvider for MultiSourceProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Packages of all sources, in the order they are fetched
+    ///
+    /// Every source has to list its packages before any is mirrored, so a
+    /// misspelled source fails the run before the others touch the target.
+    async fn entries(&self) -> Result<PackageStream> {
+        let streams =
+            future::try_join_all(self.providers.iter().map(|provider| provider.entries())).await?;
+        let per_source = self.per_source;
+        Ok(stream::select_all(
+            streams
+                .into_iter()
+                .map(|entries| prefetched(entries, per_source)),
+        )
+        .boxed())
+    }
+}
+
+/// Fetch up to `budget` packages of a stream ahead of the consumer
+///
+/// Fetch errors are kept in the entry, so they are reported for that package.
+/// Packages of known size hold room under `global_max_buffered_bytes` until
+/// the consumer is done with them. The room is taken in stream order, before
+/// the fetch starts, so the package the consumer waits for always has it;
+/// entries already holding room, fetched ahead by an inner stream, keep theirs.
+pub(crate) fn prefetched(entries: PackageStream, budget: usize) -> PackageStream {
+    entries
+        .then(|entry| async move {
+            let mut entry = entry?;
+            if let (None, None, Some(limits), Some(size)) = (
+                &entry.reservation,
+                &entry.skip,
+                limits::current(),
+                entry.size,
+            ) {
+                entry.reservation = limits.reserve(size).await;
+            }
+            Ok(entry)
+        })
+        .map(|entry: Result<PackageEntry>| async move {
+            let mut entry = entry?;
+            if entry.skip.is_some() {
+                return Ok(entry);
+            }
+            let content = (&mut entry.fetch).await;
+            entry.fetch = future::ready(content).boxed();
+            Ok(entry)
+        })
+        .buffered(budget)
+        .boxed()
+}
+
+/// The provider reading packages from a source of the given type
+///
+/// `http_client`, when given, is used instead of clients built from `config`.
+pub(crate) fn source_provider(
+    source: &str,
+    zip_path: Option<&str>,
+    source_type: &str,
+    is_local_file: bool,
+    config: &Config,
+    http_client: Option<Client>,
+) -> Result<Box<dyn SourceProvider>> {
+    check_allowed_sources(&[source.to_string()], source_type, is_local_file, config)?;
+    let client = match &http_client {
+        Some(client) => client.clone(),
+        None => build_client(config)?,
+    };
+
+    // A filter preset stands in for a missing --src-path, selecting every package it matches
+    let preset = config
+        .filter_preset
+        .as_deref()
+        .map(presets::find)
+        .transpose()?;
+    let mut config = config.clone();
+    let (artifact_filter, member_pattern) = match (zip_path, preset) {
+        (None, Some(preset)) => {
+            info!(
+                "Selecting artifacts and packages with the {} preset",
+                preset.name
+            );
+            config.all_matches = true;
+            (
+                preset.artifacts.map(str::to_string),
+                Some(preset.paths.to_string()),
+            )
+        }
+        _ => (zip_path.map(str::to_string), zip_path.map(str::to_string)),
+    };
+    let config = &config;
 
-    // Handle different source types
-    match source_type {
+    let provider: Box<dyn SourceProvider> = match source_type {
         "zip" | "zip-url" => {
             info!(
                 "Processing ZIP file source: {} (type: {})",
                 source, source_type
             );
-            let zip_path_str = zip_path.unwrap_or("");
-            return mirror_from_zip(
-                &client,
-                source,
-                zip_path_str,
+            Box::new(ZipProvider {
+                source: source.to_string(),
                 is_local_file,
-                &mut repository,
-                config,
-            )
-            .await;
+                member_pattern: member_pattern.clone().unwrap_or_default(),
+                client,
+                config: config.clone(),
+            })
         }
         "tgz" | "tgz-url" => {
             info!(
                 "Processing tarball source: {} (type: {})",
                 source, source_type
             );
-            return mirror_from_tarball(&client, source, is_local_file, &mut repository, config)
-                .await;
+            Box::new(TarballProvider {
+                source: source.to_string(),
+                is_local_file,
+                member_pattern: member_pattern.clone().unwrap_or_default(),
+                client,
+                config: config.clone(),
+            })
         }
         "github" => {
             info!("Processing GitHub artifact source: {} (type: {})", source, source_type);
-            return mirror_from_github(&client, source, zip_path, &mut repository, config).await;
+            Box::new(GithubArtifactsProvider {
+                source: source.to_string(),
+                name_filter: artifact_filter.clone(),
+                member_pattern: member_pattern.clone(),
+                http_client: http_client.clone(),
+                config: config.clone(),
+            })
+        }
+        "github-release" => {
+            info!("Processing GitHub release source: {} (type: {})", source, source_type);
+            Box::new(GithubReleaseProvider {
+                source: source.to_string(),
+                member_pattern: member_pattern.clone(),
+                http_client: http_client.clone(),
+                config: config.clone(),
+            })
         }
         "azure" => {
             info!("Processing Azure DevOps artifact source: {} (type: {})", source, source_type);
-            return mirror_from_azure(&client, source, zip_path, &mut repository, config).await;
+            Box::new(AzureArtifactsProvider {
+                source: source.to_string(),
+                name_filter: artifact_filter.clone(),
+                member_pattern: member_pattern.clone(),
+                http_client: http_client.clone(),
+                config: config.clone(),
+            })
         }
         "local" | "url" => {
             info!(
                 "Starting mirroring of single package: {} (type: {})",
                 source, source_type
             );
-            match mirror_single_package(&client, source, is_local_file, &mut repository, config)
-                .await
-            {
-                Ok(_) => {
-                    info!("Finalizing repository structure and generating metadata");
-                    repository.finalize_repository().await?;
-                    info!("Mirroring completed successfully");
-                    Ok(())
-                }
-                Err(e) => {
-                    error!("Error mirroring package: {}", e);
-                    Err(e)
-                }
-            }
+            Box::new(PackageProvider {
+                source: source.to_string(),
+                is_local_file,
+                client,
+                config: config.clone(),
+            })
         }
-        _ => Err(anyhow::anyhow!(
-            "Unsupported source type: {}. Must be one of: zip, zip-url, local, url, tgz, tgz-url, github, azure",
-            source_type
-        )),
-    }
+        "channel" => {
+            info!("Processing conda channel source: {} (type: {})", source, source_type);
+            Box::new(ChannelProvider {
+                channel: source.to_string(),
+                package_filter: zip_path.map(str::to_string),
+                client,
+                config: config.clone(),
+            })
+        }
+        "gitlab" => {
+            info!("Processing GitLab CI artifact source: {} (type: {})", source, source_type);
+            Box::new(GitLabArtifactsProvider {
+                source: source.to_string(),
+                name_filter: artifact_filter.clone(),
+                member_pattern: member_pattern.clone(),
+                http_client: http_client.clone(),
+                config: config.clone(),
+            })
+        }
+        _ => {
+            return Err(MirrorError::InvalidInput(format!(
+                "Unsupported source type: {}. Must be one of: zip, zip-url, local, url, tgz, tgz-url, github, github-release, azure, gitlab, channel",
+                source_type
+            )))
+        }
+    };
+    Ok(provider)
 }
 
 fn build_client(config: &Config) -> Result<Client> {
-    let mut builder =
-        Client::builder().timeout(std::time::Duration::from_secs(config.timeout_seconds));
+    auth::shared(config);
+    let mut builder = Client::builder()
+        .timeout(std::time::Duration::from_secs(config.timeout_seconds))
+        .user_agent(politeness::user_agent(config));
 
     if let Some(token) = &config.github_token {
         let mut headers = reqwest::header::HeaderMap::new();
@@ -104,45 +685,14 @@ fn build_client(config: &Config) -> Result<Client> {
     Ok(builder.build()?)
 }
 
-async fn mirror_single_package(
-    client: &Client,
-    source: &str,
-    is_local_file: bool,
-    repository: &mut Repository,
-    config: &Config,
-) -> Result<()> {
-    info!("Mirroring package from: {}", source);
-
-    // Get package content (either from URL or local file)
-    let content = if is_local_file {
-        info!("Reading local file: {}", source);
-        let file_bytes = std::fs::read(source)
-            .map_err(|e| anyhow!("Failed to read local file '{}': {}", source, e))?;
-        info!(
-            "Successfully read {} bytes from local file",
-            file_bytes.len()
-        );
-        Bytes::from(file_bytes)
-    } else {
-        download_package(client, source, config).await?
-    };
-
-    // Extract package name from URL
-    let package_name = extract_package_name(source)?;
-
-    // Upload to target repository
-    repository.upload_package(&package_name, content).await?;
-
-    info!("Successfully mirrored: {}", package_name);
-    Ok(())
-}
-
-async fn download_package(client: &Client, url: &str, config: &Config) -> Result<Bytes> {
+pub(crate) async fn download_package(client: &Client, url: &str, config: &Config) -> Result<Bytes> {
     // Check if it's a local file path or file:// URL
     if url.starts_with("file://") || (!url.starts_with("http://") && !url.starts_with("https://")) {
         return download_local_file(url).await;
     }
 
+    let breaker = circuit_breaker::shared(config);
+    let throttle = politeness::shared(config);
     let mut attempts = 0;
     let max_attempts = config.retry_attempts;
 
@@ -153,25 +703,18 @@ async fn download_package(client: &Client, url: &str, config: &Config) -> Result
             url, attempts, max_attempts
         );
 
-        match client.get(url).send().await {
-            Ok(response) => {
-                if response.status().is_success() {
-                    let content = response.bytes().await?;
-                    info!("Successfully downloaded {} bytes", content.len());
-                    return Ok(content);
-                } else {
-                    let status = response.status();
-                    if attempts >= max_attempts {
-                        return Err(anyhow!("Failed to download: HTTP {}", status));
-                    }
-                    warn!("Download failed with status {}, retrying...", status);
-                }
+        let fetch = || throttle.guard(url, || fetch_url(client, url));
+        match breaker.guard(url, fetch).await {
+            Ok(content) => {
+                info!("Successfully downloaded {} bytes", content.len());
+                return Ok(content);
             }
+            Err(e @ MirrorError::HostUnavailable { .. }) => return Err(e),
             Err(e) => {
                 if attempts >= max_attempts {
-                    return Err(anyhow!("Failed to download: {}", e));
+                    return Err(e);
                 }
-                warn!("Download error: {}, retrying...", e);
+                warn!("Download failed: {}, retrying...", e);
             }
         }
 
@@ -180,6 +723,24 @@ async fn download_package(client: &Client, url: &str, config: &Config) -> Result
     }
 }
 
+/// Issue a single GET request, treating a body shorter than announced as a failure
+async fn fetch_url(client: &Client, url: &str) -> Result<Bytes> {
+    let response = auth::get(client, url).send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(MirrorError::from_status(
+            status,
+            &format!("Failed to download {}", url),
+            "",
+        ));
+    }
+
+    let expected_size = response.content_length();
+    let content = response.bytes().await?;
+    verify_download_size(url, expected_size, content.len())?;
+    Ok(content)
+}
+
 async fn download_local_file(url: &str) -> Result<Bytes> {
     let file_path = if url.starts_with("file://") {
         url.strip_prefix("file://").unwrap()
@@ -191,7 +752,10 @@ async fn download_local_file(url: &str) -> Result<Bytes> {
 
     let path = Path::new(file_path);
     if !path.exists() {
-        return Err(anyhow!("Local file does not exist: {}", file_path));
+        return Err(MirrorError::NotFound(format!(
+            "Local file does not exist: {}",
+            file_path
+        )));
     }
 
     let content = tokio::fs::read(path).await?;
@@ -201,120 +765,1201 @@ async fn download_local_file(url: &str) -> Result<Bytes> {
     Ok(bytes)
 }
 
-async fn mirror_from_zip(
+/// Conda packages pulled out of an archive, plus every path seen for diagnostics
+#[derive(Default)]
+struct ExtractedPackages {
+    packages: Vec<(String, Bytes)>,
+    all_file_paths: Vec<String>,
+    /// Conda packages in the archive that were left out
+    skipped: Vec<SkippedItem>,
+}
+
+/// Read a source archive from disk or download it
+async fn fetch_source(
     client: &Client,
     source: &str,
-    zip_path: &str,
     is_local_file: bool,
-    repository: &mut Repository,
     config: &Config,
-) -> Result<()> {
-    // Get ZIP file content (either from URL or local file)
-    let zip_content = if is_local_file {
+) -> Result<Bytes> {
+    if is_local_file {
         info!("Reading local file: {}", source);
-        std::fs::read(source)
-            .map_err(|e| anyhow!("Failed to read local file '{}': {}", source, e))?
-            .into()
+        Ok(std::fs::read(source)
+            .map_err(|e| local_read_error(source, e))?
+            .into())
     } else {
-        info!("Downloading ZIP file from: {}", source);
-        download_package(client, source, config).await?
-    };
+        info!("Downloading archive from: {}", source);
+        download_package(client, source, config).await
+    }
+}
 
-    info!("Extracting conda packages from ZIP file");
+/// Fetch an archive and extract it, fetching it a second time if extraction finds
+/// it corrupt. An archive that is corrupt twice is quarantined instead of mirrored.
+async fn fetch_and_extract<F, Fut, T>(
+    name: &str,
+    config: &Config,
+    mut fetch: F,
+    extract: impl Fn(&Bytes) -> Result<T>,
+) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<Bytes>>,
+{
+    let content = fetch().await?;
+    match extract(&content) {
+        Err(MirrorError::Corrupt(reason)) => {
+            warn!(
+                "Archive {} is corrupt ({}), downloading it again",
+                name, reason
+            );
+        }
+        result => return result,
+    }
+
+    let content = fetch().await?;
+    match extract(&content) {
+        Err(MirrorError::Corrupt(reason)) => {
+            let path = quarantine::quarantine_file(
+                Path::new(&config.quarantine_dir),
+                name,
+                &content,
+                &reason,
+            )?;
+            Err(MirrorError::Quarantined { path, reason })
+        }
+        result => result,
+    }
+}
 
-    // Create a cursor from the downloaded bytes
-    let cursor = std::io::Cursor::new(zip_content);
-    let mut archive = zip::ZipArchive::new(cursor)?;
+fn corrupt_archive(kind: &str, err: impl std::fmt::Display) -> MirrorError {
+    MirrorError::Corrupt(format!("Failed to read {} archive: {}", kind, err))
+}
 
-    let mut success_count = 0;
-    let mut error_count = 0;
-    let mut all_file_paths = Vec::new();
+/// Derive the package filename from the path of an archive member
+///
+/// Members that would escape the extraction directory (absolute paths, `..`
+/// components, drive prefixes) and names that are not valid UTF-8 or contain
+/// control characters are rejected instead of being converted lossily.
+fn package_name_from_member(path: &Path) -> std::result::Result<String, String> {
+    // Archives created on Windows may use backslashes as separators
+    if let Some(normalized) = path.to_str().filter(|p| p.contains('\\')) {
+        return package_name_from_member(Path::new(&normalized.replace('\\', "/")));
+    }
 
-    // Compile regex pattern if provided
-    let path_regex = if zip_path.is_empty() {
-        None
-    } else {
-        Some(Regex::new(zip_path)?)
-    };
+    for component in path.components() {
+        match component {
+            std::path::Component::Normal(_) | std::path::Component::CurDir => {}
+            _ => return Err("path escapes the archive".to_string()),
+        }
+    }
+
+    let name = path
+        .file_name()
+        .ok_or_else(|| "path has no file name".to_string())?;
+    let name = name
+        .to_str()
+        .ok_or_else(|| "file name is not valid UTF-8".to_string())?;
+
+    if name.chars().any(char::is_control) {
+        return Err("file name contains control characters".to_string());
+    }
+
+    Ok(name.to_string())
+}
+
+/// Which conda packages inside an archive are mirrored
+#[derive(Debug, Default)]
+struct MemberFilter {
+    /// `--src-path`: only members whose path matches
+    path: Option<Regex>,
+    /// `src_exclude`: members whose path matches are skipped
+    exclude: Option<Regex>,
+    /// Mirror every member matching `path` instead of only the first
+    all_matches: bool,
+}
+
+impl MemberFilter {
+    /// The filter for a `--src-path` pattern, empty for every member, and the configuration
+    fn new(path_pattern: &str, config: &Config) -> Result<Self> {
+        Ok(Self {
+            path: (!path_pattern.is_empty())
+                .then(|| Regex::new(path_pattern))
+                .transpose()?,
+            exclude: exclude_regex(config)?,
+            all_matches: config.all_matches,
+        })
+    }
+
+    /// Whether the member at `path` is a conda package to mirror
+    ///
+    /// Conda packages left out are returned as skipped; other files are not.
+    fn select(&self, kind: &str, path: &str) -> std::result::Result<bool, SkippedItem> {
+        if !(path.ends_with(".conda") || path.ends_with(".tar.bz2")) {
+            return Ok(false);
+        }
+        if let Some(regex) = self.path.as_ref().filter(|regex| !regex.is_match(path)) {
+            return Err(SkippedItem::new(
+                kind,
+                path,
+                SkipCode::Unmatched,
+                format!("does not match --src-path '{}'", regex),
+            ));
+        }
+        match excluded(self.exclude.as_ref(), kind, path) {
+            Some(skipped) => Err(skipped),
+            None => Ok(true),
+        }
+    }
+
+    /// Whether only the first selected member is mirrored
+    fn first_only(&self) -> bool {
+        self.path.is_some() && !self.all_matches
+    }
+}
+
+/// Extract the conda packages from a ZIP archive, reading every matching entry
+/// before anything is uploaded so a damaged archive is rejected as a whole
+///
+/// Some pipelines compress each package again before uploading it: members
+/// such as `numpy-1.26.4-0.conda.zst` are decompressed, and the conda packages
+/// inside `.tar.zst` members are extracted as from a tarball.
+fn extract_zip_packages(content: &Bytes, filter: &MemberFilter) -> Result<ExtractedPackages> {
+    let cursor = std::io::Cursor::new(content.clone());
+    extract_zip_archive(&mut zip::ZipArchive::new(cursor)?, filter)
+}
 
-    let mut first_match_processed = false;
+/// Extract the conda packages from an opened ZIP archive
+///
+/// Only the members that are read are accessed, so over a
+/// [`remote_zip::RangeReader`] only they are downloaded.
+fn extract_zip_archive<R: Read + std::io::Seek>(
+    archive: &mut zip::ZipArchive<R>,
+    filter: &MemberFilter,
+) -> Result<ExtractedPackages> {
+    let mut extracted = ExtractedPackages::default();
 
     // Iterate through files in the ZIP
     for i in 0..archive.len() {
-        let mut file = archive.by_index(i)?;
-        let file_name = file.name().to_string();
+        let file_name = archive.name_for_index(i).unwrap_or_default().to_string();
 
         // Collect all file paths for potential debugging
-        all_file_paths.push(file_name.clone());
+        extracted.all_file_paths.push(file_name.clone());
 
-        // Check if this file matches the regex pattern (if any) and is a conda package
-        let is_in_path = if let Some(ref regex) = path_regex {
-            regex.is_match(&file_name)
-        } else {
-            true
-        };
+        if file_name.ends_with(".tar.zst") {
+            let mut content = Vec::new();
+            archive
+                .by_index(i)?
+                .read_to_end(&mut content)
+                .map_err(|e| corrupt_archive("ZIP", e))?;
+            let decoder = zstd::stream::read::Decoder::new(&content[..])
+                .map_err(|e| corrupt_archive("zstd", e))?;
+            let prefix = format!("{}/", file_name);
+            if extract_tar_members(decoder, "zstd tarball", &prefix, filter, &mut extracted)? {
+                break;
+            }
+            continue;
+        }
 
-        let is_conda_package = file_name.ends_with(".conda") || file_name.ends_with(".tar.bz2");
+        // A package compressed again is selected by the name it has once decompressed
+        let compressed = file_name.ends_with(".zst");
+        let member_name = file_name.strip_suffix(".zst").unwrap_or(&file_name);
 
-        // If using regex pattern, only process the first match
-        let should_process = if path_regex.is_some() {
-            is_in_path && is_conda_package && !first_match_processed
-        } else {
-            is_in_path && is_conda_package
-        };
+        // Check if this file matches the regex pattern (if any) and is a conda package
+        let selected = filter
+            .select("ZIP member", member_name)
+            .unwrap_or_else(|item| {
+                extracted.skipped.push(item);
+                false
+            });
+        if selected {
+            // Take the package name from the member path only if it stays inside the archive
+            let package_name = match package_name_from_member(Path::new(member_name)) {
+                Ok(package_name) => package_name,
+                Err(reason) => {
+                    warn!("Skipping ZIP member {:?}: {}", file_name, reason);
+                    extracted.skipped.push(SkippedItem::new(
+                        "ZIP member",
+                        file_name,
+                        SkipCode::UnsafePath,
+                        reason,
+                    ));
+                    continue;
+                }
+            };
 
-        if should_process {
             info!("Found conda package in ZIP: {}", file_name);
 
-            // If using regex, mark that we've processed the first match
-            if path_regex.is_some() {
-                first_match_processed = true;
-            }
-
             // Read the file content
             let mut content = Vec::new();
-            file.read_to_end(&mut content)?;
-            let content_bytes = Bytes::from(content);
-
-            // Extract just the filename for the package name
-            let package_name = std::path::Path::new(&file_name)
-                .file_name()
-                .and_then(|name| name.to_str())
-                .ok_or_else(|| anyhow!("Could not extract package name from: {}", file_name))?;
-
-            // Upload to repository
-            match repository.upload_package(package_name, content_bytes).await {
-                Ok(_) => {
-                    success_count += 1;
-                    info!("Successfully extracted and mirrored: {}", package_name);
-                }
-                Err(e) => {
-                    error_count += 1;
-                    error!("Error mirroring package {}: {}", package_name, e);
-                }
+            archive
+                .by_index(i)?
+                .read_to_end(&mut content)
+                .map_err(|e| corrupt_archive("ZIP", e))?;
+            if compressed {
+                content = zstd::stream::decode_all(&content[..])
+                    .map_err(|e| corrupt_archive("zstd", e))?;
             }
+            extracted
+                .packages
+                .push((package_name, Bytes::from(content)));
 
-            // If using regex, stop after processing the first match
-            if path_regex.is_some() {
+            // If using regex, only process the first match unless all are asked for
+            if filter.first_only() {
                 break;
             }
         }
     }
 
-    info!(
-        "ZIP processing completed: {} succeeded, {} failed",
-        success_count, error_count
-    );
+    Ok(extracted)
+}
 
-    // Finalize repository structure
-    if success_count > 0 {
-        info!("Finalizing repository structure and generating metadata");
+/// Extract the conda packages from a gzipped tarball
+fn extract_tarball_packages(content: &Bytes, filter: &MemberFilter) -> Result<ExtractedPackages> {
+    let cursor = std::io::Cursor::new(content.clone());
+    let mut extracted = ExtractedPackages::default();
+    extract_tar_members(
+        GzDecoder::new(cursor),
+        "tarball",
+        "",
+        filter,
+        &mut extracted,
+    )?;
+    Ok(extracted)
+}
+
+/// Add the conda packages among the members of a tar stream to `extracted`
+///
+/// Member paths are reported with `prefix` in front. Returns whether the first
+/// selected member was taken and nothing further is to be extracted.
+fn extract_tar_members(
+    tar: impl Read,
+    kind: &str,
+    prefix: &str,
+    filter: &MemberFilter,
+    extracted: &mut ExtractedPackages,
+) -> Result<bool> {
+    let mut archive = Archive::new(tar);
+    let member_kind = format!("{} member", kind);
+
+    // Iterate through files in the tarball
+    for entry in archive.entries().map_err(|e| corrupt_archive(kind, e))? {
+        let mut entry = entry.map_err(|e| corrupt_archive(kind, e))?;
+        let path = entry
+            .path()
+            .map_err(|e| corrupt_archive(kind, e))?
+            .into_owned();
+        let file_name = format!("{}{}", prefix, path.to_string_lossy());
+
+        // Collect all file paths for potential debugging
+        extracted.all_file_paths.push(file_name.clone());
+
+        // Check if this file matches the regex pattern (if any) and is a conda package
+        let selected = filter
+            .select(&member_kind, &file_name)
+            .unwrap_or_else(|item| {
+                extracted.skipped.push(item);
+                false
+            });
+        if selected {
+            // Use the raw member path so non-UTF-8 names are rejected rather than mangled
+            let package_name = match package_name_from_member(&path) {
+                Ok(package_name) => package_name,
+                Err(reason) => {
+                    warn!("Skipping {} {:?}: {}", member_kind, file_name, reason);
+                    extracted.skipped.push(SkippedItem::new(
+                        member_kind.as_str(),
+                        file_name,
+                        SkipCode::UnsafePath,
+                        reason,
+                    ));
+                    continue;
+                }
+            };
+
+            info!("Found conda package in {}: {}", kind, file_name);
+
+            // Read the file content
+            let mut content = Vec::new();
+            entry
+                .read_to_end(&mut content)
+                .map_err(|e| corrupt_archive(kind, e))?;
+            extracted
+                .packages
+                .push((package_name, Bytes::from(content)));
+
+            // As for ZIP files, only the first match unless all are asked for
+            if filter.first_only() {
+                return Ok(true);
+            }
+        }
+    }
+
+    Ok(false)
+}
+
+/// The `src_exclude` regular expression, if one is configured
+pub(crate) fn exclude_regex(config: &Config) -> Result<Option<Regex>> {
+    Ok(config.src_exclude.as_deref().map(Regex::new).transpose()?)
+}
+
+/// The skipped item for `name` if it matches the `src_exclude` expression, logging what is skipped
+pub(crate) fn excluded(exclude: Option<&Regex>, kind: &str, name: &str) -> Option<SkippedItem> {
+    let regex = exclude.filter(|regex| regex.is_match(name))?;
+    info!("Skipping {} excluded by --src-exclude: {}", kind, name);
+    Some(SkippedItem::new(
+        kind,
+        name,
+        SkipCode::Excluded,
+        format!("matches --src-exclude '{}'", regex),
+    ))
+}
+
+/// Append a hint about `src_exclude` to a message reporting that nothing was found
+pub(crate) fn push_exclude_hint(message: &mut String, config: &Config) {
+    if let Some(exclude) = &config.src_exclude {
+        message.push_str(&format!(
+            "\nNames matching the exclude pattern '{}' were skipped",
+            exclude
+        ));
+    }
+}
+
+/// Mark packages whose batch upload failed after they were accepted as failed
+///
+/// They were reported as mirrored when queued, and are dropped from the
+/// completed packages so an interrupted run tries them again.
+fn fail_batched_uploads(
+    report: &mut MirrorReport,
+    completed: &mut Vec<String>,
+    failed: Vec<FailedUpload>,
+) {
+    for failed in failed {
+        let Some(package) = report.packages.iter_mut().find(|package| {
+            package.outcome == PackageOutcome::Mirrored
+                && package.stored_as.as_deref().unwrap_or(&package.filename) == failed.filename
+        }) else {
+            continue;
+        };
+        error!(
+            "Error mirroring package {}: {}",
+            package.filename, failed.error
+        );
+        package.outcome = PackageOutcome::Failed {
+            error: failed.error.to_string(),
+        };
+        completed.retain(|name| *name != package.filename);
+    }
+}
+
+/// Fail unless a source has packages for every platform of `require_platforms`
+///
+/// Every entry is fetched and its platform detected before anything is
+/// uploaded, so a source lacking a required platform leaves the target
+/// untouched. The fetched packages are held in memory, outside the
+/// `global_max_buffered_bytes` budget, and handed back in the same order.
+/// Entries after an error for the source as a whole are not fetched, and
+/// the platforms are not checked, as the run fails anyway.
+async fn require_platforms(
+    mut entries: PackageStream,
+    repository: &Repository,
+    source: &str,
+    config: &Config,
+) -> Result<PackageStream> {
+    let mut held = Vec::new();
+    let mut found = std::collections::BTreeSet::new();
+    let mut complete = true;
+    while let Some(entry) = entries.next().await {
+        let mut entry = match entry {
+            Ok(entry) if entry.skip.is_none() => entry,
+            Ok(entry) => {
+                held.push(Ok(entry));
+                continue;
+            }
+            Err(e @ (MirrorError::Quarantined { .. } | MirrorError::Skipped(_))) => {
+                held.push(Err(e));
+                continue;
+            }
+            Err(e) => {
+                held.push(Err(e));
+                complete = false;
+                break;
+            }
+        };
+        // Holding room for every package would leave none for the fetches still to come
+        entry.reservation = None;
+        let content = (&mut entry.fetch).await;
+        if let Ok(content) = &content {
+            match repository
+                .published_platform(&entry.name, content.clone())
+                .await
+            {
+                Ok(platform) => {
+                    found.insert(platform.to_string());
+                }
+                Err(e) => debug!("No platform detected for {}: {}", entry.name, e),
+            }
+        }
+        entry.fetch = future::ready(content).boxed();
+        held.push(Ok(entry));
+    }
+
+    let missing: Vec<String> = config
+        .require_platforms
+        .iter()
+        .filter(|platform| !found.contains(*platform))
+        .cloned()
+        .collect();
+    if complete && !missing.is_empty() {
+        return Err(MirrorError::MissingPlatforms {
+            source_name: source.to_string(),
+            missing,
+            found: found.into_iter().collect(),
+        });
+    }
+    Ok(stream::iter(held).chain(entries).boxed())
+}
+
+/// Mirror every package a source provides into the repository
+///
+/// Packages recorded as completed by an interrupted run of the same source and target
+/// are skipped without being fetched. When a shutdown is requested, the upload in flight
+/// is finished, the remaining packages are written to the resume state file and the run
+/// stops. Archives that stayed corrupt after a re-download are skipped; they only fail
+/// the run when nothing else could be mirrored.
+pub async fn mirror_from_provider(
+    provider: &dyn SourceProvider,
+    repository: &mut Repository,
+    config: &Config,
+) -> Result<MirrorReport> {
+    let source = provider.name();
+    let mut report = MirrorReport::new(source, repository.path.clone());
+    let run_started = Instant::now();
+    let previous = ResumeState::load_for(&config.resume_state_file, source, &repository.path)?;
+    let already_completed = previous
+        .as_ref()
+        .map(|state| state.completed.clone())
+        .unwrap_or_default();
+    if previous.is_some() {
+        info!(
+            "Resuming interrupted run of {}: {} packages already mirrored",
+            source,
+            already_completed.len()
+        );
+    }
+
+    if config.since_last_run && config.state_db.is_none() {
+        return Err(MirrorError::InvalidInput(
+            "--since-last-run requires a state database (state_db in the configuration or --state-db)"
+                .to_string(),
+        ));
+    }
+    for platform in &config.require_platforms {
+        platform
+            .parse::<rattler_conda_types::Platform>()
+            .map_err(|e| {
+                MirrorError::InvalidInput(format!("Invalid required platform {}: {}", platform, e))
+            })?;
+    }
+    let retention = config
+        .retention
+        .as_ref()
+        .map(RetentionRule::new)
+        .transpose()?;
+    #[cfg(feature = "state-db")]
+    let mut state_db = config.state_db.as_deref().map(StateDb::open).transpose()?;
+    #[cfg(feature = "state-db")]
+    if let (true, Some(db)) = (config.since_last_run, &state_db) {
+        match db.last_run(source, &repository.path)? {
+            Some(finished_at) => info!(
+                "Skipping packages already delivered to {}; last run of {} finished at {}",
+                repository.path, source, finished_at
+            ),
+            None => info!(
+                "No earlier run of {} into {} is recorded",
+                source, repository.path
+            ),
+        }
+    }
+    #[cfg(not(feature = "state-db"))]
+    if config.state_db.is_some() {
+        return Err(MirrorError::InvalidInput(
+            "The state database requires the 'state-db' feature, which this build was compiled without"
+                .to_string(),
+        ));
+    }
+
+    let specs = SpecFilter::new(&config.specs)?;
+    let osv = config
+        .vulnerabilities
+        .as_ref()
+        .map(|vulnerabilities| OsvClient::new(vulnerabilities, config.timeout_seconds))
+        .transpose()?;
+
+    let global_limits = limits::shared(config);
+    let mut entries = provider.entries().await?;
+    if !config.require_platforms.is_empty() {
+        entries = require_platforms(entries, repository, source, config).await?;
+    }
+    let mut bandwidth = politeness::BandwidthLimit::new(config.max_bytes_per_second);
+
+    let mut completed = Vec::new();
+    let mut pending = Vec::new();
+    let mut source_error = None;
+
+    while let Some(entry) = entries.next().await {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(MirrorError::Quarantined { path, reason }) => {
+                warn!(
+                    "Skipping archive: corrupt after re-download ({}), quarantined at {:?}",
+                    reason, path
+                );
+                report.quarantined.push(QuarantinedArchive { path, reason });
+                continue;
+            }
+            Err(MirrorError::Skipped(item)) => {
+                report.skipped_items.push(item);
+                continue;
+            }
+            Err(e) => {
+                source_error = Some(e);
+                break;
+            }
+        };
+
+        priority::yield_to_higher(config.priority).await;
+        let window_closed = config
+            .run_until
+            .is_some_and(|until| chrono::Utc::now() >= until);
+        if window_closed {
+            info!(
+                "The time window of the run has closed; stopping before {}",
+                entry.name
+            );
+        }
+        if shutdown::is_requested() || window_closed {
+            pending.push(entry.name);
+            // Record the entries already at hand without fetching further archives
+            while let Some(Some(Ok(entry))) = entries.next().now_or_never() {
+                pending.push(entry.name);
+            }
+            break;
+        }
+
+        if already_completed.contains(&entry.name) {
+            info!(
+                "Skipping {}: mirrored before the previous run was interrupted",
+                entry.name
+            );
+            report.record(
+                entry.name.clone(),
+                PackageOutcome::Skipped {
+                    code: SkipCode::Resumed,
+                    reason: "mirrored before the previous run was interrupted".to_string(),
+                },
+                entry.size.unwrap_or(0),
+                Duration::ZERO,
+            );
+            completed.push(entry.name);
+            continue;
+        }
+
+        if let Some((code, reason)) = entry.skip {
+            info!("Skipping {}: {}", entry.name, reason);
+            let package = report.record(
+                entry.name.clone(),
+                PackageOutcome::Skipped { code, reason },
+                entry.size.unwrap_or(0),
+                Duration::ZERO,
+            );
+            package.origin = entry.origin;
+            package.artifact = entry.artifact;
+            completed.push(entry.name);
+            continue;
+        }
+
+        #[cfg(feature = "state-db")]
+        if let (true, Some(db)) = (config.since_last_run, &state_db) {
+            if db.is_mirrored(&repository.path, &entry.name)? {
+                info!("Skipping {}: delivered by an earlier run", entry.name);
+                let package = report.record(
+                    entry.name.clone(),
+                    PackageOutcome::Skipped {
+                        code: SkipCode::DeliveredEarlier,
+                        reason: "delivered to the target by an earlier run".to_string(),
+                    },
+                    entry.size.unwrap_or(0),
+                    Duration::ZERO,
+                );
+                package.origin = entry.origin;
+                package.artifact = entry.artifact;
+                completed.push(entry.name);
+                continue;
+            }
+        }
+
+        if specs
+            .as_ref()
+            .is_some_and(|specs| specs.rules_out(&entry.name))
+        {
+            info!(
+                "Skipping {}: matches none of the --spec match specs",
+                entry.name
+            );
+            let package = report.record(
+                entry.name.clone(),
+                PackageOutcome::Skipped {
+                    code: SkipCode::SpecMismatch,
+                    reason: format!(
+                        "matches none of the specs {}",
+                        specs.as_ref().map(SpecFilter::describe).unwrap_or_default()
+                    ),
+                },
+                entry.size.unwrap_or(0),
+                Duration::ZERO,
+            );
+            package.origin = entry.origin;
+            package.artifact = entry.artifact;
+            completed.push(entry.name);
+            continue;
+        }
+
+        let advisories = match &osv {
+            Some(osv) => osv.advisories_for_file(&entry.name).await,
+            None => Vec::new(),
+        };
+        if !advisories.is_empty() {
+            warn!(
+                "{} has {} known vulnerabilities: {}",
+                entry.name,
+                advisories.len(),
+                advisories
+                    .iter()
+                    .map(|advisory| advisory.id.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        if let Some(advisory) = osv.as_ref().and_then(|osv| osv.blocking(&advisories)) {
+            let reason = format!(
+                "blocked by {} advisory {}",
+                advisory
+                    .severity
+                    .map_or("unknown".to_string(), |s| s.to_string()),
+                advisory.id
+            );
+            warn!("Skipping {}: {}", entry.name, reason);
+            let mut reason = reason;
+            if osv.as_ref().is_some_and(OsvClient::quarantines) {
+                let held = match entry.fetch.await {
+                    Ok(content) => review::hold(
+                        Path::new(&config.quarantine_dir),
+                        &entry.name,
+                        &content,
+                        &reason,
+                        source,
+                        &repository.repo_type,
+                        &repository.path,
+                    ),
+                    Err(e) => Err(e),
+                };
+                match held {
+                    Ok(path) => reason.push_str(&format!("; quarantined at {}", path.display())),
+                    Err(e) => warn!("Failed to quarantine {}: {}", entry.name, e),
+                }
+            }
+            let package = report.record(
+                entry.name.clone(),
+                PackageOutcome::Skipped {
+                    code: SkipCode::Vulnerable,
+                    reason,
+                },
+                entry.size.unwrap_or(0),
+                Duration::ZERO,
+            );
+            package.origin = entry.origin;
+            package.artifact = entry.artifact;
+            package.advisories = advisories;
+            completed.push(entry.name);
+            continue;
+        }
+
+        // A full disk fails every package after it, so stop before fetching this one
+        if let Err(e) = repository.ensure_space(entry.size.unwrap_or(0)) {
+            error!("Stopping before {}: {}", entry.name, e);
+            source_error = Some(e);
+            break;
+        }
+
+        let package_name = entry.name;
+        let started = Instant::now();
+        let started_on = chrono::Utc::now();
+        let mut bytes = 0;
+        let mut fetched = None;
+        let result = match entry.fetch.await {
+            Ok(content) => {
+                bytes = content.len() as u64;
+                bandwidth.consume(bytes).await;
+                global_limits.transferred(bytes).await;
+                fetched = Some(content.clone());
+                repository.upload_package(&package_name, content).await
+            }
+            Err(e) => Err(e),
+        };
+
+        let mut rejected_platform = None;
+        let outcome = match result {
+            Ok(UploadStatus::Uploaded) => {
+                info!("Successfully mirrored: {}", package_name);
+                completed.push(package_name.clone());
+                PackageOutcome::Mirrored
+            }
+            Ok(UploadStatus::AlreadyPresent) => {
+                completed.push(package_name.clone());
+                PackageOutcome::Skipped {
+                    code: SkipCode::AlreadyPresent,
+                    reason: "identical copy already present at the target".to_string(),
+                }
+            }
+            Err(
+                e @ MirrorError::PolicyRejected {
+                    quarantine,
+                    oversized,
+                    ..
+                },
+            ) => {
+                warn!("Skipping {}", e);
+                completed.push(package_name.clone());
+                if let MirrorError::PolicyRejected { platform, .. } = &e {
+                    rejected_platform = Some(platform.clone());
+                }
+                let mut reason = e.to_string();
+                if let (true, Some(content)) = (quarantine, &fetched) {
+                    match review::hold(
+                        Path::new(&config.quarantine_dir),
+                        &package_name,
+                        content,
+                        &reason,
+                        source,
+                        &repository.repo_type,
+                        &repository.path,
+                    ) {
+                        Ok(path) => {
+                            reason.push_str(&format!("; quarantined at {}", path.display()))
+                        }
+                        Err(e) => warn!("Failed to quarantine {}: {}", package_name, e),
+                    }
+                }
+                PackageOutcome::Skipped {
+                    code: if oversized {
+                        SkipCode::Oversized
+                    } else {
+                        SkipCode::PolicyRejected
+                    },
+                    reason,
+                }
+            }
+            Err(e @ MirrorError::SpecMismatch { .. }) => {
+                info!("Skipping {}", e);
+                completed.push(package_name.clone());
+                PackageOutcome::Skipped {
+                    code: SkipCode::SpecMismatch,
+                    reason: e.to_string(),
+                }
+            }
+            Err(e @ MirrorError::ExtractionFailed { .. }) => {
+                error!("{}", e);
+                let mut error = e.to_string();
+                if let Some(content) = &fetched {
+                    match quarantine::quarantine_file(
+                        Path::new(&config.quarantine_dir),
+                        &package_name,
+                        content,
+                        &error,
+                    ) {
+                        Ok(path) => error.push_str(&format!("; quarantined at {}", path.display())),
+                        Err(e) => warn!("Failed to quarantine {}: {}", package_name, e),
+                    }
+                }
+                PackageOutcome::Failed { error }
+            }
+            Err(e) => {
+                error!("Error mirroring package {}: {}", package_name, e);
+                PackageOutcome::Failed {
+                    error: e.to_string(),
+                }
+            }
+        };
+        let package = report.record(package_name, outcome, bytes, started.elapsed());
+        package.origin = entry.origin;
+        package.artifact = entry.artifact;
+        package.advisories = advisories;
+        package.platform = rejected_platform;
+        package.platform_explanation = repository.take_platform_explanation(&package.filename);
+        if !matches!(package.outcome, PackageOutcome::Failed { .. }) {
+            if let Some(processed) = repository.processed_package(&package.filename) {
+                package.platform = Some(processed.platform.to_string());
+                package.stored_as =
+                    (processed.filename != package.filename).then(|| processed.filename.clone());
+                package.sha256 = Some(processed.sha256.clone());
+                package.license = processed.metadata.license.clone();
+                package.metadata_source = Some(processed.metadata_source);
+
+                if config.provenance && package.outcome == PackageOutcome::Mirrored {
+                    let context = ProvenanceContext {
+                        source,
+                        origin: package.origin.as_deref(),
+                        target: &repository.path,
+                        started_on,
+                        finished_on: chrono::Utc::now(),
+                    };
+                    let statement = provenance::statement(processed, &context);
+                    store_attestation(repository, &processed.platform, package, &statement).await;
+                    package.provenance = Some(statement);
+                }
+            }
+        }
+    }
+
+    // Finalize repository structure, including after an interrupt so that the
+    // repodata covers every package that was uploaded
+    if report.mirrored_count() + report.skipped_count() > 0 {
+        info!("Finalizing repository structure and generating metadata");
         repository.finalize_repository().await?;
     }
+    fail_batched_uploads(
+        &mut report,
+        &mut completed,
+        repository.take_failed_uploads(),
+    );
+    if report.mirrored_count() > 0 {
+        if let Err(e) = repository.update_manifest(&report).await {
+            warn!(
+                "Failed to update the mirror manifest of {}: {}",
+                repository.path, e
+            );
+        }
+    }
+    if let (Some(rule), true) = (&retention, report.mirrored_count() > 0) {
+        match repository.prune(rule, false).await {
+            Ok(expired) if !expired.is_empty() => {
+                info!("Retention removed {} expired packages", expired.len())
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Failed to apply retention to {}: {}", repository.path, e),
+        }
+    }
+    report.duration = run_started.elapsed();
+
+    #[cfg(feature = "state-db")]
+    if let (Some(db), false) = (&mut state_db, report.packages.is_empty()) {
+        let run_id = db.record_report(&report)?;
+        match repository.stored_packages().await {
+            Ok(stored) => db.record_stats(run_id, &repository.path, &stored)?,
+            Err(e) => info!(
+                "No run statistics recorded: cannot list the packages of {}: {}",
+                repository.path, e
+            ),
+        }
+    }
+
+    if !pending.is_empty() {
+        let state = ResumeState {
+            source: source.to_string(),
+            target: repository.path.clone(),
+            completed,
+            pending,
+            interrupted_at: chrono::Utc::now(),
+        };
+        state.save(&config.resume_state_file)?;
+
+        warn!(
+            "Mirroring of {} interrupted: {} mirrored, {} failed, {} pending",
+            source,
+            report.mirrored_count() + report.skipped_count(),
+            report.failed_count(),
+            state.pending.len()
+        );
+        for package_name in &state.pending {
+            warn!("  pending: {}", package_name);
+        }
+
+        return Err(MirrorError::Interrupted {
+            completed: state.completed.len(),
+            pending: state.pending.len(),
+            state_file: config.resume_state_file.clone(),
+            report: Box::new(report),
+        });
+    }
+
+    if let Some(e) = source_error {
+        return Err(e);
+    }
+
+    if previous.is_some() {
+        ResumeState::clear(&config.resume_state_file)?;
+    }
+
+    info!(
+        "Mirroring of {} completed: {} mirrored, {} skipped, {} failed",
+        source,
+        report.mirrored_count(),
+        report.skipped_count(),
+        report.failed_count()
+    );
+
+    if report.packages.is_empty() && report.quarantined.is_empty() {
+        return Err(MirrorError::NotFound(format!(
+            "No conda packages found in {}",
+            source
+        )));
+    }
+
+    Ok(report)
+}
+
+/// Store a provenance statement next to a mirrored package, warning if that fails
+///
+/// The statement is kept in the run report, and so in the state database, either way.
+async fn store_attestation(
+    repository: &Repository,
+    platform: &rattler_conda_types::Platform,
+    package: &PackageReport,
+    statement: &serde_json::Value,
+) {
+    let stored = repository
+        .store_attestation(
+            platform,
+            &package.filename,
+            statement.to_string().as_bytes(),
+        )
+        .await;
+    match stored {
+        Ok(true) => {}
+        Ok(false) => info!(
+            "Target {} cannot hold attestations; provenance of {} is kept in the run report",
+            repository.path, package.filename
+        ),
+        Err(e) => warn!("Failed to store provenance of {}: {}", package.filename, e),
+    }
+}
+
+/// Stream the packages extracted from an archive
+fn entries_stream(entries: Vec<PackageEntry>) -> PackageStream {
+    stream::iter(entries.into_iter().map(Ok)).boxed()
+}
+
+/// The packages read from one archive, and the members of it that were skipped
+struct ArchiveEntries {
+    packages: Vec<PackageEntry>,
+    skipped: Vec<SkippedItem>,
+}
+
+impl ArchiveEntries {
+    /// Record the CI artifact, and its address, the packages were extracted from
+    fn with_artifact(mut self, origin: &str, artifact: &ArtifactSource) -> Self {
+        self.packages = self
+            .packages
+            .into_iter()
+            .map(|entry| entry.with_origin(origin).with_artifact(artifact.clone()))
+            .collect();
+        self
+    }
+
+    /// Record where the archive the packages were extracted from can be downloaded
+    fn with_origin(mut self, origin: &str) -> Self {
+        self.packages = self
+            .packages
+            .into_iter()
+            .map(|entry| entry.with_origin(origin))
+            .collect();
+        self
+    }
+
+    /// Stream the skipped members, to be recorded in the report, then the packages
+    fn into_stream(self) -> PackageStream {
+        skipped_stream(self.skipped)
+            .chain(entries_stream(self.packages))
+            .boxed()
+    }
+}
+
+/// Stream items of a source that were skipped, for the report to record
+pub(crate) fn skipped_stream(skipped: Vec<SkippedItem>) -> PackageStream {
+    stream::iter(
+        skipped
+            .into_iter()
+            .map(|item| Err(MirrorError::Skipped(item))),
+    )
+    .boxed()
+}
+
+/// Stream the packages of one archive among several, passing on its error instead
+fn archive_entries_stream(entries: Result<ArchiveEntries>) -> PackageStream {
+    match entries {
+        Ok(entries) => entries.into_stream(),
+        Err(e) => stream::once(future::ready(Err(e))).boxed(),
+    }
+}
+
+/// A single local or remote conda package
+struct PackageProvider {
+    source: String,
+    is_local_file: bool,
+    client: Client,
+    config: Config,
+}
+
+#[async_trait]
+impl SourceProvider for PackageProvider {
+    fn name(&self) -> &str {
+        &self.source
+    }
+
+    async fn entries(&self) -> Result<PackageStream> {
+        info!("Mirroring package from: {}", self.source);
+
+        // Extract package name from URL
+        let package_name = extract_package_name(&self.source)?;
+
+        let source = self.source.clone();
+        let is_local_file = self.is_local_file;
+        let client = self.client.clone();
+        let config = self.config.clone();
+        let fetch = async move {
+            // Get package content (either from URL or local file)
+            if is_local_file {
+                info!("Reading local file: {}", source);
+                let file_bytes =
+                    std::fs::read(&source).map_err(|e| local_read_error(&source, e))?;
+                info!(
+                    "Successfully read {} bytes from local file",
+                    file_bytes.len()
+                );
+                Ok(Bytes::from(file_bytes))
+            } else {
+                download_package(&client, &source, &config).await
+            }
+        };
+
+        Ok(stream::once(future::ready(Ok(PackageEntry::new(
+            package_name,
+            None,
+            fetch,
+        )
+        .with_origin(self.source.clone()))))
+        .boxed())
+    }
+}
+
+/// Conda packages in a local or remote ZIP file
+struct ZipProvider {
+    source: String,
+    is_local_file: bool,
+    member_pattern: String,
+    client: Client,
+    config: Config,
+}
+
+#[async_trait]
+impl SourceProvider for ZipProvider {
+    fn name(&self) -> &str {
+        &self.source
+    }
+
+    async fn entries(&self) -> Result<PackageStream> {
+        if !self.is_local_file {
+            if let Some(entries) = self.range_entries().await? {
+                return Ok(entries.into_stream());
+            }
+        }
+        let entries = zip_archive_entries(
+            &self.source,
+            || fetch_source(&self.client, &self.source, self.is_local_file, &self.config),
+            &self.member_pattern,
+            &self.config,
+        )
+        .await?;
+        Ok(entries.into_stream())
+    }
+}
+
+impl ZipProvider {
+    /// Read only the central directory and the selected members of a large remote archive
+    ///
+    /// Returns `None` when the archive is to be downloaded whole instead: it is
+    /// small, its host does not serve byte ranges, or reading ranges failed.
+    async fn range_entries(&self) -> Result<Option<ArchiveEntries>> {
+        let Some(length) = remote_zip::range_length(&self.client, &self.source).await else {
+            return Ok(None);
+        };
+        if length < self.config.range_read_min_bytes {
+            return Ok(None);
+        }
+        info!(
+            "Reading the selected members of {} ({} bytes) with range requests",
+            self.source, length
+        );
+
+        let filter = MemberFilter::new(&self.member_pattern, &self.config)?;
+        let reader = remote_zip::RangeReader::new(
+            tokio::runtime::Handle::current(),
+            self.client.clone(),
+            self.source.as_str(),
+            length,
+        );
+        let extracted = tokio::task::spawn_blocking(move || {
+            let mut archive = zip::ZipArchive::new(reader)?;
+            let extracted = extract_zip_archive(&mut archive, &filter)?;
+            Ok::<_, MirrorError>((extracted, archive.into_inner().fetched()))
+        })
+        .await
+        .map_err(|e| MirrorError::Other(e.into()))?;
+        match extracted {
+            Ok((extracted, fetched)) => {
+                info!("Fetched {} of {} bytes of {}", fetched, length, self.source);
+                zip_entries(extracted, &self.member_pattern, &self.config).map(Some)
+            }
+            Err(e) => {
+                warn!(
+                    "Range requests for {} failed, downloading it whole: {}",
+                    self.source, e
+                );
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// Read the conda packages contained in a ZIP archive obtained from `fetch`
+async fn zip_archive_entries<F, Fut>(
+    name: &str,
+    fetch: F,
+    zip_path: &str,
+    config: &Config,
+) -> Result<ArchiveEntries>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<Bytes>>,
+{
+    // Compile regex pattern if provided
+    let filter = MemberFilter::new(zip_path, config)?;
+
+    info!("Extracting conda packages from ZIP file");
+
+    let extracted = fetch_and_extract(name, config, fetch, |content| {
+        extract_zip_packages(content, &filter)
+    })
+    .await?;
+    zip_entries(extracted, zip_path, config)
+}
 
-    if error_count > 0 {
-        Err(anyhow!("{} packages failed to mirror", error_count))
-    } else if success_count == 0 {
+/// The packages extracted from a ZIP archive, or why there are none
+fn zip_entries(
+    extracted: ExtractedPackages,
+    zip_path: &str,
+    config: &Config,
+) -> Result<ArchiveEntries> {
+    if extracted.packages.is_empty() {
         let mut error_msg = format!(
             "No conda packages found in ZIP file matching pattern: '{}'",
             if zip_path.is_empty() {
@@ -325,446 +1970,2761 @@ async fn mirror_from_zip(
         );
 
         error_msg.push_str("\n\nAll files in ZIP:");
-        for (i, path) in all_file_paths.iter().enumerate() {
+        for (i, path) in extracted.all_file_paths.iter().enumerate() {
             error_msg.push_str(&format!("\n  {}: {}", i + 1, path));
         }
 
         if !zip_path.is_empty() {
             error_msg.push_str(&format!(
-                "\n\nHint: File paths must match regex pattern '{}' and have .conda or .tar.bz2 extensions",
-                zip_path
-            ));
+            "\n\nHint: File paths must match regex pattern '{}' and have .conda or .tar.bz2 extensions",
+            zip_path
+        ));
         } else {
             error_msg.push_str("\n\nHint: Files must have .conda or .tar.bz2 extensions");
         }
+        push_exclude_hint(&mut error_msg, config);
+
+        return Err(MirrorError::NotFound(error_msg));
+    }
+
+    Ok(ArchiveEntries {
+        packages: extracted
+            .packages
+            .into_iter()
+            .map(|(package_name, content)| PackageEntry::ready(package_name, content))
+            .collect(),
+        skipped: extracted.skipped,
+    })
+}
+
+/// Conda packages in a local or remote gzipped tarball
+struct TarballProvider {
+    source: String,
+    is_local_file: bool,
+    member_pattern: String,
+    client: Client,
+    config: Config,
+}
+
+#[async_trait]
+impl SourceProvider for TarballProvider {
+    fn name(&self) -> &str {
+        &self.source
+    }
+
+    async fn entries(&self) -> Result<PackageStream> {
+        let entries = tarball_archive_entries(
+            &self.source,
+            || fetch_source(&self.client, &self.source, self.is_local_file, &self.config),
+            &self.member_pattern,
+            &self.config,
+        )
+        .await?;
+        Ok(entries.into_stream())
+    }
+}
+
+/// Read the conda packages contained in a gzipped tarball obtained from `fetch`
+async fn tarball_archive_entries<F, Fut>(
+    name: &str,
+    fetch: F,
+    member_pattern: &str,
+    config: &Config,
+) -> Result<ArchiveEntries>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<Bytes>>,
+{
+    info!("Extracting conda packages from tarball");
+
+    let filter = MemberFilter::new(member_pattern, config)?;
+    let extracted = fetch_and_extract(name, config, fetch, |content| {
+        extract_tarball_packages(content, &filter)
+    })
+    .await?;
+
+    if extracted.packages.is_empty() {
+        let mut error_msg = "No conda packages found in tarball".to_string();
+        if !member_pattern.is_empty() {
+            error_msg.push_str(&format!(" matching pattern: '{}'", member_pattern));
+        }
+
+        error_msg.push_str("\n\nAll files in tarball:");
+        for (i, path) in extracted.all_file_paths.iter().enumerate() {
+            error_msg.push_str(&format!("\n  {}: {}", i + 1, path));
+        }
+
+        if member_pattern.is_empty() {
+            error_msg.push_str("\n\nHint: Files must have .conda or .tar.bz2 extensions");
+        } else {
+            error_msg.push_str(&format!(
+                "\n\nHint: File paths must match regex pattern '{}' and have .conda or .tar.bz2 extensions",
+                member_pattern
+            ));
+        }
+        push_exclude_hint(&mut error_msg, config);
+
+        return Err(MirrorError::NotFound(error_msg));
+    }
+
+    Ok(ArchiveEntries {
+        packages: extracted
+            .packages
+            .into_iter()
+            .map(|(package_name, content)| PackageEntry::ready(package_name, content))
+            .collect(),
+        skipped: extracted.skipped,
+    })
+}
+
+/// Describe a failure to read a local source file, keeping missing files distinguishable
+fn local_read_error(path: &str, err: std::io::Error) -> MirrorError {
+    if err.kind() == std::io::ErrorKind::NotFound {
+        MirrorError::NotFound(format!("Local file does not exist: {}", path))
+    } else {
+        MirrorError::Io(std::io::Error::new(
+            err.kind(),
+            format!("Failed to read local file '{}': {}", path, err),
+        ))
+    }
+}
+
+fn extract_package_name(source: &str) -> Result<String> {
+    // Handle local file paths
+    if !source.starts_with("http://")
+        && !source.starts_with("https://")
+        && !source.starts_with("file://")
+    {
+        let path = Path::new(source);
+        let package_name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| {
+                MirrorError::InvalidInput(
+                    "Could not extract package name from file path".to_string(),
+                )
+            })?;
+        return Ok(package_name.to_string());
+    }
+
+    // Handle URLs
+    let parsed_url = Url::parse(source)?;
+    let path = parsed_url.path();
+
+    // Get the last segment of the path
+    let package_name = path.split('/').next_back().ok_or_else(|| {
+        MirrorError::InvalidInput("Could not extract package name from URL".to_string())
+    })?;
+
+    if package_name.is_empty() {
+        return Err(MirrorError::InvalidInput(
+            "Package name is empty".to_string(),
+        ));
+    }
+
+    Ok(package_name.to_string())
+}
+
+/// Regular expression selecting the packages inside an artifact when no name filter is given
+const DEFAULT_ARTIFACT_PATTERN: &str = r".*\.conda$|.*\.tar\.bz2$";
+
+/// Workflow artifacts of a GitHub repository, each a ZIP file of conda packages
+struct GithubArtifactsProvider {
+    source: String,
+    name_filter: Option<String>,
+    /// Paths of the packages inside each artifact; any package when unset
+    member_pattern: Option<String>,
+    http_client: Option<Client>,
+    config: Config,
+}
+
+#[async_trait]
+impl SourceProvider for GithubArtifactsProvider {
+    fn name(&self) -> &str {
+        &self.source
+    }
+
+    async fn entries(&self) -> Result<PackageStream> {
+        let source = self.source.as_str();
+        let name_filter = self.name_filter.as_deref();
+        let config = &self.config;
+        info!("Starting GitHub artifact mirroring from: {}", source);
+
+        // Parse GitHub repository
+        let (owner, repo) = github::parse_github_repository(source)?;
+        info!("Parsed GitHub repository: {}/{}", owner, repo);
+
+        // Create GitHub client
+        let github_client = Arc::new(match &self.http_client {
+            Some(client) => github::GitHubClient::with_client(client.clone(), config),
+            None => github::GitHubClient::new(config)?,
+        });
+
+        // Handle specific artifact ID or list artifacts
+        let mut skipped = Vec::new();
+        let artifacts = if let Some(artifact_id_str) = source.split('#').nth(1) {
+            // Handle specific artifact by ID (format: owner/repo#artifact_id)
+            let artifact_id = github::parse_artifact_id(artifact_id_str)?;
+            info!("Downloading specific artifact ID: {}", artifact_id);
+
+            let artifact = github_client
+                .get_artifact(&owner, &repo, artifact_id)
+                .await?;
+
+            if artifact.expired {
+                let all_artifacts = github_client.list_artifacts(&owner, &repo).await?;
+                let alternatives = github_client
+                    .find_alternatives_for_expired(std::slice::from_ref(&artifact), &all_artifacts);
+                return Err(expired_artifacts_error(
+                    &owner,
+                    &repo,
+                    &[artifact],
+                    &alternatives,
+                ));
+            }
+
+            vec![artifact]
+        } else {
+            // List all artifacts, or those built for a pull request, and optionally filter
+            let all_artifacts = match github::parse_pull_request(source) {
+                Some(number) => {
+                    let head_sha = github_client
+                        .get_pull_request_head(&owner, &repo, number)
+                        .await?;
+                    info!("Pull request #{} is at {}", number, head_sha);
+                    let artifacts = github_client
+                        .list_commit_artifacts(&owner, &repo, &head_sha)
+                        .await?;
+                    if artifacts.is_empty() {
+                        return Err(MirrorError::NotFound(format!(
+                            "No artifacts were built for {} of pull request #{} yet",
+                            head_sha, number
+                        )));
+                    }
+                    artifacts
+                }
+                None => github_client.list_artifacts(&owner, &repo).await?,
+            };
+            let mut artifacts = all_artifacts.clone();
+
+            // Filter by name if specified
+            if let Some(pattern) = name_filter {
+                artifacts = github_client.filter_artifacts_by_name(&artifacts, Some(pattern));
+            }
+            let exclude = exclude_regex(config)?;
+            artifacts.retain(|artifact| {
+                excluded(exclude.as_ref(), "artifact", &artifact.name)
+                    .map(|item| skipped.push(item))
+                    .is_none()
+            });
+
+            // Filter out expired artifacts, suggesting replacements if nothing is left
+            let matching = artifacts;
+            artifacts = github_client.filter_non_expired_artifacts(&matching);
+            skipped.extend(
+                matching
+                    .iter()
+                    .filter(|artifact| artifact.expired)
+                    .map(|artifact| expired_artifact(&artifact.name)),
+            );
+
+            if artifacts.is_empty() && !matching.is_empty() {
+                let alternatives =
+                    github_client.find_alternatives_for_expired(&matching, &all_artifacts);
+                return Err(expired_artifacts_error(
+                    &owner,
+                    &repo,
+                    &matching,
+                    &alternatives,
+                ));
+            }
+
+            if artifacts.is_empty() {
+                let mut message = "No artifacts found matching the criteria".to_string();
+                let suggestions = name_filter.map_or_else(Vec::new, |pattern| {
+                    suggest::similar(pattern, all_artifacts.iter().map(|a| a.name.as_str()))
+                });
+                if let Some(hint) = suggest::did_you_mean(&suggestions) {
+                    message.push_str(&format!("; {}", hint));
+                }
+                return Err(MirrorError::NotFound(message));
+            }
+
+            // For mirroring, we might want to process all or ask user to specify
+            // For now, let's process the first one or all if there's a name filter
+            if name_filter.is_none() && artifacts.len() > 1 {
+                warn!(
+                    "Multiple artifacts found ({}) but no name filter specified. Processing the most recent one.",
+                    artifacts.len()
+                );
+                // Sort by creation date and take the most recent
+                artifacts.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+                vec![artifacts.into_iter().next().unwrap()]
+            } else {
+                artifacts
+            }
+        };
+
+        let artifacts: Vec<_> = artifacts
+            .into_iter()
+            .filter(|artifact| {
+                if artifact.expired {
+                    skipped.push(expired_artifact(&artifact.name));
+                }
+                !artifact.expired
+            })
+            .collect();
+
+        // Download and extract the artifacts one at a time as the stream is consumed
+        let zip_path_pattern = self
+            .member_pattern
+            .as_deref()
+            .unwrap_or(DEFAULT_ARTIFACT_PATTERN)
+            .to_string();
+        let config = config.clone();
+        let artifact_entries = stream::iter(artifacts)
+            .then(move |artifact| {
+                let github_client = Arc::clone(&github_client);
+                let (owner, repo) = (owner.clone(), repo.clone());
+                let zip_path_pattern = zip_path_pattern.clone();
+                let config = config.clone();
+                async move {
+                    github_artifact_entries(
+                        &github_client,
+                        &owner,
+                        &repo,
+                        &artifact,
+                        &zip_path_pattern,
+                        &config,
+                    )
+                    .await
+                }
+            })
+            .flat_map(archive_entries_stream);
+        Ok(skipped_stream(skipped).chain(artifact_entries).boxed())
+    }
+}
+
+/// Download one GitHub artifact and read the conda packages it contains
+async fn github_artifact_entries(
+    github_client: &github::GitHubClient,
+    owner: &str,
+    repo: &str,
+    artifact: &github::GitHubArtifact,
+    zip_path_pattern: &str,
+    config: &Config,
+) -> Result<ArchiveEntries> {
+    info!(
+        "Processing artifact '{}' (ID: {}, Size: {} bytes)",
+        artifact.name, artifact.id, artifact.size_in_bytes
+    );
+
+    // Download the artifact (it comes as a ZIP file). The listing's
+    // size_in_bytes need not match the generated ZIP, so only the
+    // Content-Length check inside download_artifact applies here.
+    let breaker = circuit_breaker::shared(config);
+    let description = format!("GitHub artifact '{}'", artifact.name);
+    let fetch = || {
+        download_with_retries(&description, config.retry_attempts, || {
+            breaker.guard(&artifact.archive_download_url, || {
+                github_client.download_artifact(owner, repo, artifact.id)
+            })
+        })
+    };
+
+    let archive_name = format!("{}.zip", artifact.name);
+    let origin = github_artifact_origin(owner, repo, artifact);
+    let source = ArtifactSource {
+        name: artifact.name.clone(),
+        run_id: artifact.workflow_run.as_ref().map(|run| run.id),
+        build_id: None,
+        pipeline_id: None,
+        job_id: None,
+    };
+    let entries = zip_archive_entries(&archive_name, fetch, zip_path_pattern, config).await?;
+    Ok(entries.with_artifact(&origin, &source))
+}
+
+/// The skipped item for a CI artifact that expired before it could be downloaded
+fn expired_artifact(name: &str) -> SkippedItem {
+    warn!("Artifact '{}' has expired, skipping", name);
+    SkippedItem::new("artifact", name, SkipCode::Expired, "artifact has expired")
+}
+
+/// Web address of a GitHub artifact, naming the workflow run that produced it when known
+fn github_artifact_origin(owner: &str, repo: &str, artifact: &github::GitHubArtifact) -> String {
+    match &artifact.workflow_run {
+        Some(run) => format!(
+            "https://github.com/{}/{}/actions/runs/{}/artifacts/{}",
+            owner, repo, run.id, artifact.id
+        ),
+        None => artifact.url.clone(),
+    }
+}
+
+/// Explain that the requested artifacts have expired and list what could be used instead
+fn expired_artifacts_error(
+    owner: &str,
+    repo: &str,
+    expired: &[github::GitHubArtifact],
+    alternatives: &[github::GitHubArtifact],
+) -> MirrorError {
+    let names: Vec<_> = expired
+        .iter()
+        .map(|artifact| format!("'{}' (ID: {})", artifact.name, artifact.id))
+        .collect();
+    let mut error_msg = format!(
+        "All {} matching artifact(s) in {}/{} have expired: {}",
+        expired.len(),
+        owner,
+        repo,
+        names.join(", ")
+    );
+
+    if alternatives.is_empty() {
+        error_msg.push_str(
+            "\n\nNo non-expired artifacts are available; re-run the workflow to produce new ones.",
+        );
+    } else {
+        error_msg.push_str("\n\nNon-expired alternatives from other runs:");
+        for artifact in alternatives {
+            let (branch, run_id) = artifact
+                .workflow_run
+                .as_ref()
+                .map(|run| (run.head_branch.as_str(), run.id.to_string()))
+                .unwrap_or(("unknown", "unknown".to_string()));
+            error_msg.push_str(&format!(
+                "\n  - {} (ID: {}, branch: {}, run: {}, created: {})",
+                artifact.name, artifact.id, branch, run_id, artifact.created_at
+            ));
+        }
+        error_msg.push_str(&format!(
+            "\n\nHint: mirror one of them with --src {}/{}#<ID>",
+            owner, repo
+        ));
+    }
+
+    MirrorError::NotFound(error_msg)
+}
+
+/// Conda packages attached to a GitHub release, directly or inside ZIP files and tarballs
+struct GithubReleaseProvider {
+    source: String,
+    /// Paths of the packages to mirror, among the assets and inside archives; any package when unset
+    member_pattern: Option<String>,
+    http_client: Option<Client>,
+    config: Config,
+}
+
+/// How a release asset is read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReleaseAsset {
+    Package,
+    Zip,
+    Tarball,
+}
+
+impl ReleaseAsset {
+    /// The kind of asset named `name`, if it may hold conda packages
+    fn of(name: &str) -> Option<Self> {
+        if name.ends_with(".conda") || name.ends_with(".tar.bz2") {
+            Some(Self::Package)
+        } else if name.ends_with(".zip") {
+            Some(Self::Zip)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Self::Tarball)
+        } else {
+            None
+        }
+    }
+}
+
+#[async_trait]
+impl SourceProvider for GithubReleaseProvider {
+    fn name(&self) -> &str {
+        &self.source
+    }
+
+    async fn entries(&self) -> Result<PackageStream> {
+        let config = &self.config;
+        let (owner, repo, tag) = github::parse_release_source(&self.source)?;
+        info!(
+            "Reading release {} of {}/{}",
+            tag.as_deref().unwrap_or("latest"),
+            owner,
+            repo
+        );
+
+        let github_client = Arc::new(match &self.http_client {
+            Some(client) => github::GitHubClient::with_client(client.clone(), config),
+            None => github::GitHubClient::new(config)?,
+        });
+        let release = github_client
+            .get_release(&owner, &repo, tag.as_deref())
+            .await?;
+
+        // Packages attached directly are selected like the members of an archive
+        let member_pattern = self.member_pattern.as_deref().unwrap_or_default();
+        let filter = MemberFilter::new(member_pattern, config)?;
+        let exclude = exclude_regex(config)?;
+        let mut skipped = Vec::new();
+        let mut packages = Vec::new();
+        let mut archives = Vec::new();
+        for asset in &release.assets {
+            match ReleaseAsset::of(&asset.name) {
+                Some(ReleaseAsset::Package) => match filter.select("asset", &asset.name) {
+                    Ok(_) if filter.first_only() && !packages.is_empty() => {}
+                    Ok(_) => packages.push(asset.clone()),
+                    Err(item) => skipped.push(item),
+                },
+                Some(kind) => match excluded(exclude.as_ref(), "asset", &asset.name) {
+                    Some(item) => skipped.push(item),
+                    None => archives.push((kind, asset.clone())),
+                },
+                None => debug!("Ignoring release asset {}", asset.name),
+            }
+        }
+
+        if packages.is_empty() && archives.is_empty() {
+            let mut message = format!(
+                "No conda packages or archives found in release {} of {}/{}",
+                release.tag_name, owner, repo
+            );
+            message.push_str("\n\nAll assets of the release:");
+            for (i, asset) in release.assets.iter().enumerate() {
+                message.push_str(&format!("\n  {}: {}", i + 1, asset.name));
+            }
+            push_exclude_hint(&mut message, config);
+            return Err(MirrorError::NotFound(message));
+        }
+        info!(
+            "Mirroring {} packages and {} archives attached to release {}",
+            packages.len(),
+            archives.len(),
+            release.tag_name
+        );
+
+        let retry_attempts = config.retry_attempts;
+        let package_entries: Vec<_> = packages
+            .into_iter()
+            .map(|asset| {
+                let github_client = Arc::clone(&github_client);
+                let breaker = circuit_breaker::shared(config);
+                let name = asset.name.clone();
+                let origin = asset.browser_download_url.clone();
+                let size = Some(asset.size).filter(|size| *size > 0);
+                let fetch = async move {
+                    let description = format!("GitHub release asset '{}'", asset.name);
+                    download_with_retries(&description, retry_attempts, || {
+                        breaker.guard(&asset.url, || github_client.download_release_asset(&asset))
+                    })
+                    .await
+                };
+                PackageEntry::new(name, size, fetch).with_origin(origin)
+            })
+            .collect();
+
+        // Download and extract the archives one at a time as the stream is consumed
+        let archive_pattern = self
+            .member_pattern
+            .as_deref()
+            .unwrap_or(DEFAULT_ARTIFACT_PATTERN)
+            .to_string();
+        let config = config.clone();
+        let archive_entries = stream::iter(archives)
+            .then(move |(kind, asset)| {
+                let github_client = Arc::clone(&github_client);
+                let archive_pattern = archive_pattern.clone();
+                let config = config.clone();
+                async move {
+                    let breaker = circuit_breaker::shared(&config);
+                    let description = format!("GitHub release asset '{}'", asset.name);
+                    let fetch = || {
+                        download_with_retries(&description, config.retry_attempts, || {
+                            breaker
+                                .guard(&asset.url, || github_client.download_release_asset(&asset))
+                        })
+                    };
+                    let entries = match kind {
+                        ReleaseAsset::Tarball => {
+                            tarball_archive_entries(&asset.name, fetch, &archive_pattern, &config)
+                                .await
+                        }
+                        _ => {
+                            zip_archive_entries(&asset.name, fetch, &archive_pattern, &config).await
+                        }
+                    }?;
+                    Ok(entries.with_origin(&asset.browser_download_url))
+                }
+            })
+            .flat_map(archive_entries_stream);
+
+        Ok(skipped_stream(skipped)
+            .chain(entries_stream(package_entries))
+            .chain(archive_entries)
+            .boxed())
+    }
+}
+
+/// Build artifacts of an Azure DevOps project, each a ZIP file of conda packages
+struct AzureArtifactsProvider {
+    source: String,
+    name_filter: Option<String>,
+    /// Paths of the packages inside each artifact; any package when unset
+    member_pattern: Option<String>,
+    http_client: Option<Client>,
+    config: Config,
+}
+
+#[async_trait]
+impl SourceProvider for AzureArtifactsProvider {
+    fn name(&self) -> &str {
+        &self.source
+    }
+
+    async fn entries(&self) -> Result<PackageStream> {
+        let source = self.source.as_str();
+        let name_filter = self.name_filter.as_deref();
+        let config = &self.config;
+        info!("Starting Azure DevOps artifact mirroring from: {}", source);
+
+        // Parse Azure DevOps organization/project/build_id
+        let (organization, project, build_id) = azure::parse_azure_source(source)?;
+        info!("Parsed Azure DevOps: {}/{}", organization, project);
+
+        // Create Azure DevOps client
+        let azure_client = Arc::new(match &self.http_client {
+            Some(client) => azure::AzureDevOpsClient::with_client(client.clone(), config),
+            None => azure::AzureDevOpsClient::new(config)?,
+        });
+
+        // Handle specific build ID or list recent builds
+        let builds_and_artifacts = if let Some(build_id) = build_id {
+            info!("Processing specific build ID: {}", build_id);
+            let artifacts = azure_client
+                .list_artifacts(&organization, &project, build_id)
+                .await?;
+            vec![(build_id, artifacts)]
+        } else {
+            // List recent builds and get their artifacts
+            let builds = azure_client
+                .list_builds(&organization, &project, None)
+                .await?;
+
+            if builds.is_empty() {
+                return Err(MirrorError::NotFound(format!(
+                    "No builds found for {}/{}",
+                    organization, project
+                )));
+            }
+
+            // For mirroring, we might want to process all recent successful builds
+            // or just the most recent one if no name filter is specified
+            let builds_to_process = if name_filter.is_none() && builds.len() > 1 {
+                warn!(
+                    "Multiple builds found ({}) but no name filter specified. Processing the most recent successful build.",
+                    builds.len()
+                );
+                // Filter for successful builds and take the most recent
+                let mut successful_builds: Vec<_> = builds
+                    .into_iter()
+                    .filter(|b| b.result.as_deref() == Some("succeeded"))
+                    .collect();
+                successful_builds.sort_by_key(|b| std::cmp::Reverse(b.id));
+                successful_builds.into_iter().take(1).collect()
+            } else {
+                builds
+            };
+
+            let mut builds_and_artifacts = Vec::new();
+            for build in builds_to_process {
+                info!("Getting artifacts for build {}", build.id);
+                let artifacts = azure_client
+                    .list_artifacts(&organization, &project, build.id)
+                    .await?;
+                builds_and_artifacts.push((build.id, artifacts));
+            }
+            builds_and_artifacts
+        };
+
+        // Select the artifacts of each build that can be downloaded
+        let exclude = exclude_regex(config)?;
+        let platforms = azure::parse_platforms(&config.platforms)?;
+        let mut selected = Vec::new();
+        let mut skipped = Vec::new();
+        for (build_id, artifacts) in builds_and_artifacts {
+            let mut filtered_artifacts = artifacts;
+
+            // Filter by name if specified
+            if let Some(pattern) = name_filter {
+                filtered_artifacts =
+                    azure_client.filter_artifacts_by_name(&filtered_artifacts, Some(pattern));
+            }
+            filtered_artifacts.retain(|artifact| {
+                excluded(exclude.as_ref(), "artifact", &artifact.name)
+                    .map(|item| skipped.push(item))
+                    .is_none()
+            });
+            // conda-forge artifact names give the platform they were built for
+            filtered_artifacts.retain(|artifact| {
+                let Some(platform) = azure::unselected_platform(&artifact.name, &platforms) else {
+                    return true;
+                };
+                info!(
+                    "Skipping artifact '{}' built for {}, which --platforms does not select",
+                    artifact.name, platform
+                );
+                skipped.push(SkippedItem::new(
+                    "artifact",
+                    &artifact.name,
+                    SkipCode::OtherPlatform,
+                    format!("built for {}, not one of --platforms", platform),
+                ));
+                false
+            });
+
+            // Filter for downloadable artifacts (those with download URLs or specific types)
+            let downloadable_artifacts: Vec<_> = filtered_artifacts
+                .into_iter()
+                .filter(|artifact| {
+                    // Prefer artifacts that can be downloaded as files
+                    artifact
+                        .resource
+                        .artifact_type
+                        .eq_ignore_ascii_case("Container")
+                        || artifact
+                            .resource
+                            .artifact_type
+                            .eq_ignore_ascii_case("FilePath")
+                        || artifact.resource.download_url.is_some()
+                })
+                .collect();
+
+            if downloadable_artifacts.is_empty() {
+                warn!("No downloadable artifacts found for build {}", build_id);
+                continue;
+            }
+
+            selected.extend(
+                downloadable_artifacts
+                    .into_iter()
+                    .map(|artifact| (build_id, artifact)),
+            );
+        }
+
+        // Download and extract the artifacts one at a time as the stream is consumed
+        let zip_path_pattern = self
+            .member_pattern
+            .as_deref()
+            .unwrap_or(DEFAULT_ARTIFACT_PATTERN)
+            .to_string();
+        let config = config.clone();
+        let artifact_entries = stream::iter(selected)
+            .then(move |(build_id, artifact)| {
+                let azure_client = Arc::clone(&azure_client);
+                let (organization, project) = (organization.clone(), project.clone());
+                let zip_path_pattern = zip_path_pattern.clone();
+                let config = config.clone();
+                async move {
+                    azure_artifact_entries(
+                        &azure_client,
+                        &organization,
+                        &project,
+                        build_id,
+                        &artifact,
+                        &zip_path_pattern,
+                        &config,
+                    )
+                    .await
+                }
+            })
+            .flat_map(archive_entries_stream);
+        Ok(skipped_stream(skipped).chain(artifact_entries).boxed())
+    }
+}
+
+/// Download one Azure DevOps artifact and read the conda packages it contains
+async fn azure_artifact_entries(
+    azure_client: &azure::AzureDevOpsClient,
+    organization: &str,
+    project: &str,
+    build_id: u64,
+    artifact: &azure::AzureDevOpsArtifact,
+    zip_path_pattern: &str,
+    config: &Config,
+) -> Result<ArchiveEntries> {
+    info!(
+        "Processing artifact '{}' (ID: {}, Type: {}) from build {}",
+        artifact.name, artifact.id, artifact.resource.artifact_type, build_id
+    );
+    if let Some(platform) = azure::artifact_platform(&artifact.name) {
+        info!(
+            "Artifact '{}' holds packages for {} and noarch",
+            artifact.name, platform
+        );
+    }
+
+    // Download the artifact. The listing's artifactsize describes the
+    // uncompressed content rather than the generated ZIP, so only the
+    // Content-Length check inside download_artifact applies here.
+    let breaker = circuit_breaker::shared(config);
+    let azure_host = format!("https://dev.azure.com/{}", organization);
+    let description = format!("Azure DevOps artifact '{}'", artifact.name);
+    let fetch = || {
+        download_with_retries(&description, config.retry_attempts, || {
+            breaker.guard(&azure_host, || {
+                azure_client.download_artifact(organization, project, build_id, &artifact.name)
+            })
+        })
+    };
+
+    let archive_name = format!("{}.zip", artifact.name);
+    // The download URL names both the build and the artifact
+    let origin = artifact.resource.download_url.clone().unwrap_or_else(|| {
+        format!(
+            "https://dev.azure.com/{}/{}/_build/results?buildId={}&view=artifacts",
+            organization, project, build_id
+        )
+    });
+    let source = ArtifactSource {
+        name: artifact.name.clone(),
+        run_id: None,
+        build_id: Some(build_id),
+        pipeline_id: None,
+        job_id: None,
+    };
+    let entries = zip_archive_entries(&archive_name, fetch, zip_path_pattern, config).await?;
+    Ok(entries.with_artifact(&origin, &source))
+}
+
+/// Job artifacts of a GitLab CI pipeline, each a ZIP file of conda packages
+struct GitLabArtifactsProvider {
+    source: String,
+    name_filter: Option<String>,
+    /// Paths of the packages inside each artifact; any package when unset
+    member_pattern: Option<String>,
+    http_client: Option<Client>,
+    config: Config,
+}
+
+#[async_trait]
+impl SourceProvider for GitLabArtifactsProvider {
+    fn name(&self) -> &str {
+        &self.source
+    }
+
+    async fn entries(&self) -> Result<PackageStream> {
+        let source = self.source.as_str();
+        let name_filter = self.name_filter.as_deref();
+        let config = &self.config;
+        info!("Starting GitLab CI artifact mirroring from: {}", source);
+
+        let gitlab::GitLabSource {
+            instance,
+            project,
+            pipeline_id,
+        } = gitlab::parse_gitlab_source(source)?;
+        let mut gitlab_client = match &self.http_client {
+            Some(client) => gitlab::GitLabClient::with_client(client.clone(), config),
+            None => gitlab::GitLabClient::new(config)?,
+        };
+        if let Some(instance) = instance {
+            gitlab_client = gitlab_client.with_base_url(instance);
+        }
+        let gitlab_client = Arc::new(gitlab_client);
+
+        // Without a pipeline ID, the latest successful pipeline is mirrored
+        let pipeline_id = match pipeline_id {
+            Some(pipeline_id) => pipeline_id,
+            None => {
+                let pipelines = gitlab_client.list_pipelines(&project).await?;
+                let latest = pipelines
+                    .iter()
+                    .filter(|pipeline| pipeline.status == "success")
+                    .max_by_key(|pipeline| pipeline.id)
+                    .ok_or_else(|| {
+                        MirrorError::NotFound(format!(
+                            "No successful pipelines found for {}",
+                            project
+                        ))
+                    })?;
+                info!(
+                    "Processing the latest successful pipeline {} ({})",
+                    latest.id, latest.git_ref
+                );
+                latest.id
+            }
+        };
+
+        let jobs = gitlab_client.list_jobs(&project, pipeline_id).await?;
+        let job_names: Vec<String> = jobs.iter().map(|job| job.name.clone()).collect();
+        let mut jobs = gitlab_client.filter_jobs_by_name(&jobs, name_filter);
+        let exclude = exclude_regex(config)?;
+        let mut skipped = Vec::new();
+        jobs.retain(|job| {
+            excluded(exclude.as_ref(), "artifact", &job.name)
+                .map(|item| skipped.push(item))
+                .is_none()
+        });
+        jobs.retain(|job| {
+            if job.artifacts_file.is_none() {
+                debug!(
+                    "Job {} of pipeline {} has no artifacts",
+                    job.name, pipeline_id
+                );
+            }
+            job.artifacts_file.is_some()
+        });
+
+        if jobs.is_empty() {
+            let mut message = format!("No jobs with artifacts found in pipeline {}", pipeline_id);
+            let suggestions = name_filter.map_or_else(Vec::new, |pattern| {
+                suggest::similar(pattern, job_names.iter().map(String::as_str))
+            });
+            if let Some(hint) = suggest::did_you_mean(&suggestions) {
+                message.push_str(&format!("; {}", hint));
+            }
+            return Err(MirrorError::NotFound(message));
+        }
+
+        // Download and extract the artifacts one at a time as the stream is consumed
+        let zip_path_pattern = self
+            .member_pattern
+            .as_deref()
+            .unwrap_or(DEFAULT_ARTIFACT_PATTERN)
+            .to_string();
+        let config = config.clone();
+        let artifact_entries = stream::iter(jobs)
+            .then(move |job| {
+                let gitlab_client = Arc::clone(&gitlab_client);
+                let project = project.clone();
+                let zip_path_pattern = zip_path_pattern.clone();
+                let config = config.clone();
+                async move {
+                    gitlab_job_entries(
+                        &gitlab_client,
+                        &project,
+                        pipeline_id,
+                        &job,
+                        &zip_path_pattern,
+                        &config,
+                    )
+                    .await
+                }
+            })
+            .flat_map(archive_entries_stream);
+        Ok(skipped_stream(skipped).chain(artifact_entries).boxed())
+    }
+}
+
+/// Download the artifacts of one GitLab CI job and read the conda packages they contain
+async fn gitlab_job_entries(
+    gitlab_client: &gitlab::GitLabClient,
+    project: &str,
+    pipeline_id: u64,
+    job: &gitlab::GitLabJob,
+    zip_path_pattern: &str,
+    config: &Config,
+) -> Result<ArchiveEntries> {
+    info!(
+        "Processing artifacts of job '{}' (ID: {}) from pipeline {}",
+        job.name, job.id, pipeline_id
+    );
+
+    let breaker = circuit_breaker::shared(config);
+    let project_url = gitlab_client.project_web_url(project);
+    let description = format!("GitLab job artifacts '{}'", job.name);
+    let origin = format!("{}/-/jobs/{}/artifacts/download", project_url, job.id);
+    let expected_size = job.artifacts_file.as_ref().map(|file| file.size);
+    let fetch = || {
+        download_with_retries(&description, config.retry_attempts, || {
+            breaker.guard(&project_url, || async {
+                let content = gitlab_client.download_artifacts(project, job.id).await?;
+                verify_download_size(&origin, expected_size, content.len())?;
+                Ok(content)
+            })
+        })
+    };
+
+    let archive_name = format!("{}.zip", job.name);
+    let source = ArtifactSource {
+        name: job.name.clone(),
+        run_id: None,
+        build_id: None,
+        pipeline_id: Some(pipeline_id),
+        job_id: Some(job.id),
+    };
+    let entries = zip_archive_entries(&archive_name, fetch, zip_path_pattern, config).await?;
+    Ok(entries.with_artifact(&origin, &source))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_package_name() {
+        let url = "https://example.com/packages/my-package-1.0.0.tar.bz2";
+        let name = extract_package_name(url).unwrap();
+        assert_eq!(name, "my-package-1.0.0.tar.bz2");
+    }
+
+    #[test]
+    fn test_extract_package_name_with_query() {
+        let url = "https://example.com/packages/my-package.tar.bz2?token=abc";
+        let name = extract_package_name(url).unwrap();
+        assert_eq!(name, "my-package.tar.bz2");
+    }
+
+    #[test]
+    fn test_extract_package_name_empty() {
+        let url = "https://example.com/";
+        assert!(extract_package_name(url).is_err());
+    }
+
+    fn zip_with_package() -> Bytes {
+        use std::io::Write;
+
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        writer
+            .start_file(
+                "pkgs/numpy-1.21.0-py39_0.tar.bz2",
+                zip::write::SimpleFileOptions::default(),
+            )
+            .unwrap();
+        writer.write_all(b"package content").unwrap();
+        Bytes::from(writer.finish().unwrap().into_inner())
+    }
+
+    #[tokio::test]
+    async fn test_fetch_and_extract_refetches_corrupt_archive() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let config = Config {
+            quarantine_dir: temp_dir.path().to_string_lossy().to_string(),
+            ..Default::default()
+        };
+        let calls = std::sync::atomic::AtomicU32::new(0);
+
+        let extracted = fetch_and_extract(
+            "artifact.zip",
+            &config,
+            || async {
+                if calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst) == 0 {
+                    Ok(Bytes::from_static(b"PK\x03\x04 truncated"))
+                } else {
+                    Ok(zip_with_package())
+                }
+            },
+            |content| extract_zip_packages(content, &MemberFilter::default()),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+        assert_eq!(extracted.packages.len(), 1);
+        assert_eq!(extracted.packages[0].0, "numpy-1.21.0-py39_0.tar.bz2");
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_and_extract_quarantines_persistent_corruption() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let config = Config {
+            quarantine_dir: temp_dir.path().to_string_lossy().to_string(),
+            ..Default::default()
+        };
+
+        let result = fetch_and_extract(
+            "artifact.zip",
+            &config,
+            || async { Ok(Bytes::from_static(b"not a zip archive")) },
+            |content| extract_zip_packages(content, &MemberFilter::default()),
+        )
+        .await;
+
+        match result {
+            Err(MirrorError::Quarantined { path, .. }) => {
+                assert!(path.starts_with(temp_dir.path()));
+                assert_eq!(std::fs::read(path).unwrap(), b"not a zip archive");
+            }
+            Err(e) => panic!("expected quarantine, got {}", e),
+            Ok(_) => panic!("expected quarantine, got success"),
+        }
+    }
+
+    #[test]
+    fn test_expired_artifacts_error_lists_alternatives() {
+        let artifact = |id: u64, expired: bool| github::GitHubArtifact {
+            id,
+            name: "conda-packages".to_string(),
+            size_in_bytes: 100,
+            url: String::new(),
+            archive_download_url: String::new(),
+            expired,
+            created_at: "2024-01-01T00:00:00Z".to_string(),
+            updated_at: "2024-01-01T00:00:00Z".to_string(),
+            expires_at: "2024-04-01T00:00:00Z".to_string(),
+            workflow_run: Some(github::WorkflowRun {
+                id: id * 10,
+                repository_id: 1,
+                head_repository_id: None,
+                head_branch: "feature".to_string(),
+                head_sha: "abc".to_string(),
+            }),
+        };
+
+        let message =
+            expired_artifacts_error("owner", "repo", &[artifact(1, true)], &[artifact(2, false)])
+                .to_string();
+        assert!(message.contains("have expired: 'conda-packages' (ID: 1)"));
+        assert!(message.contains("ID: 2, branch: feature, run: 20"));
+        assert!(message.contains("--src owner/repo#<ID>"));
+
+        let message =
+            expired_artifacts_error("owner", "repo", &[artifact(1, true)], &[]).to_string();
+        assert!(message.contains("re-run the workflow"));
+    }
+
+    #[test]
+    fn test_github_artifact_origin() {
+        let mut artifact = github::GitHubArtifact {
+            id: 42,
+            name: "conda-packages".to_string(),
+            size_in_bytes: 100,
+            url: "https://api.github.com/repos/owner/repo/actions/artifacts/42".to_string(),
+            archive_download_url: String::new(),
+            expired: false,
+            created_at: "2024-01-01T00:00:00Z".to_string(),
+            updated_at: "2024-01-01T00:00:00Z".to_string(),
+            expires_at: "2024-04-01T00:00:00Z".to_string(),
+            workflow_run: Some(github::WorkflowRun {
+                id: 7,
+                repository_id: 1,
+                head_repository_id: None,
+                head_branch: "main".to_string(),
+                head_sha: "abc".to_string(),
+            }),
+        };
+        assert_eq!(
+            github_artifact_origin("owner", "repo", &artifact),
+            "https://github.com/owner/repo/actions/runs/7/artifacts/42"
+        );
+        artifact.workflow_run = None;
+        assert_eq!(
+            github_artifact_origin("owner", "repo", &artifact),
+            "https://api.github.com/repos/owner/repo/actions/artifacts/42"
+        );
+    }
+
+    /// Provider serving packages that are already in memory
+    struct StaticProvider {
+        name: String,
+        packages: Vec<(String, Bytes)>,
+    }
+
+    #[async_trait]
+    impl SourceProvider for StaticProvider {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        async fn entries(&self) -> Result<PackageStream> {
+            Ok(entries_stream(
+                self.packages
+                    .iter()
+                    .map(|(name, content)| PackageEntry::ready(name.clone(), content.clone()))
+                    .collect(),
+            ))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mirror_from_provider_fails_packages_of_failed_batches() {
+        use crate::test_support::PackageFixture;
+        use crate::test_util::{MockResponse, MockServer};
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let server = MockServer::start().await.unwrap();
+        server.mock(
+            "PUT",
+            "/channel/noarch/a-1.0-0.conda",
+            MockResponse::new(403, "forbidden"),
+        );
+        for name in ["b", "c"] {
+            server.mock(
+                "PUT",
+                &format!("/channel/noarch/{}-1.0-0.conda", name),
+                MockResponse::new(200, ""),
+            );
+        }
+        let config = Config {
+            resume_state_file: temp_dir
+                .path()
+                .join("resume.json")
+                .to_string_lossy()
+                .to_string(),
+            ..Default::default()
+        };
+        let provider = StaticProvider {
+            name: "batched".to_string(),
+            packages: ["a", "b", "c"]
+                .into_iter()
+                .map(|name| {
+                    let fixture = PackageFixture::new(name, "1.0");
+                    (fixture.conda_filename(), fixture.to_conda())
+                })
+                .collect(),
+        };
+        let backend = PrefixDevBackend::new(format!("{}/channel", server.url())).with_batching(
+            UploadBatching {
+                max_packages: 2,
+                max_bytes: 0,
+                parallelism: 2,
+                attempts: 1,
+            },
+        );
+        let mut repository =
+            Repository::from_backend(RepositoryType::PrefixDev, server.url(), Arc::new(backend));
+
+        let report = mirror_from_provider(&provider, &mut repository, &config)
+            .await
+            .unwrap();
+        let outcomes: Vec<_> = report
+            .packages
+            .iter()
+            .map(|package| {
+                (
+                    package.filename.as_str(),
+                    package.outcome == PackageOutcome::Mirrored,
+                )
+            })
+            .collect();
+        assert_eq!(
+            outcomes,
+            [
+                ("a-1.0-0.conda", false),
+                ("b-1.0-0.conda", true),
+                ("c-1.0-0.conda", true)
+            ]
+        );
+        assert!(matches!(
+            &report.packages[0].outcome,
+            PackageOutcome::Failed { error } if error.contains("forbidden")
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_mirror_records_skipped_archive_members() {
+        use crate::test_support::PackageFixture;
+        use std::io::Write;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let config = Config {
+            resume_state_file: temp_dir
+                .path()
+                .join("resume.json")
+                .to_string_lossy()
+                .to_string(),
+            all_matches: true,
+            ..Default::default()
+        };
+        let linux = PackageFixture::new("linux", "1.0").subdir("linux-64");
+        let osx = PackageFixture::new("osx", "1.0").subdir("osx-64");
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default();
+        for (dir, package) in [("linux-64", &linux), ("osx-64", &osx)] {
+            writer
+                .start_file(format!("{}/{}", dir, package.conda_filename()), options)
+                .unwrap();
+            writer.write_all(&package.to_conda()).unwrap();
+        }
+        let archive = temp_dir.path().join("packages.zip");
+        std::fs::write(&archive, writer.finish().unwrap().into_inner()).unwrap();
+
+        let provider = source_provider(
+            &archive.to_string_lossy(),
+            Some("^linux-64/"),
+            "zip",
+            true,
+            &config,
+            None,
+        )
+        .unwrap();
+        let mut repository = Repository::new(
+            RepositoryType::Local,
+            temp_dir.path().join("repo").to_string_lossy().to_string(),
+        );
+        let report = mirror_from_provider(provider.as_ref(), &mut repository, &config)
+            .await
+            .unwrap();
+
+        assert_eq!(report.mirrored_count(), 1);
+        assert_eq!(report.skipped_items.len(), 1);
+        assert_eq!(
+            report.skipped_items[0].name,
+            format!("osx-64/{}", osx.conda_filename())
+        );
+        assert_eq!(
+            report.skip_summary().into_iter().collect::<Vec<_>>(),
+            vec![(SkipCode::Unmatched, 1)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mirror_from_multiple_sources_finalizes_once() {
+        use crate::test_support::PackageFixture;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let repo_path = temp_dir.path().join("repo");
+        let config = Config {
+            resume_state_file: temp_dir
+                .path()
+                .join("resume.json")
+                .to_string_lossy()
+                .to_string(),
+            ..Default::default()
+        };
+        let first = PackageFixture::new("first", "1.0").subdir("linux-64");
+        let second = PackageFixture::new("second", "1.0");
+        let providers: Vec<Box<dyn SourceProvider>> = vec![
+            Box::new(StaticProvider {
+                name: "owner/first".to_string(),
+                packages: vec![(first.conda_filename(), first.to_conda())],
+            }),
+            Box::new(StaticProvider {
+                name: "owner/second".to_string(),
+                packages: vec![(second.conda_filename(), second.to_conda())],
+            }),
+        ];
+        let provider = MultiSourceProvider::new(providers, 2);
+        assert_eq!(provider.name(), "owner/first, owner/second");
+
+        let mut repository = Repository::new(
+            RepositoryType::Local,
+            repo_path.to_string_lossy().to_string(),
+        );
+        let report = mirror_from_provider(&provider, &mut repository, &config)
+            .await
+            .unwrap();
+        assert_eq!(report.mirrored_count(), 2);
+        assert!(repo_path.join("linux-64/repodata.json").exists());
+        assert!(repo_path.join("noarch/repodata.json").exists());
+    }
+
+    #[tokio::test]
+    async fn test_mirror_from_provider_skips_completed_from_resume_state() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let repo_path = temp_dir.path().join("repo").to_string_lossy().to_string();
+        let state_file = temp_dir.path().join("resume.json");
+        let config = Config {
+            resume_state_file: state_file.to_string_lossy().to_string(),
+            ..Default::default()
+        };
+
+        ResumeState {
+            source: "artifacts.zip".to_string(),
+            target: repo_path.clone(),
+            completed: vec!["done-1.0-0.tar.bz2".to_string()],
+            pending: vec!["todo-1.0-0.tar.bz2".to_string()],
+            interrupted_at: chrono::Utc::now(),
+        }
+        .save(&config.resume_state_file)
+        .unwrap();
+
+        let mut repository = Repository::new(RepositoryType::Local, repo_path.clone());
+        let provider = StaticProvider {
+            name: "artifacts.zip".to_string(),
+            packages: vec![
+                (
+                    "done-1.0-0.tar.bz2".to_string(),
+                    Bytes::from_static(b"done"),
+                ),
+                (
+                    "todo-1.0-0.tar.bz2".to_string(),
+                    Bytes::from_static(b"todo"),
+                ),
+            ],
+        };
+
+        let report = mirror_from_provider(&provider, &mut repository, &config)
+            .await
+            .unwrap();
+        assert_eq!(report.mirrored_count(), 1);
+        assert_eq!(report.skipped_count(), 1);
+
+        let noarch = Path::new(&repo_path).join("noarch");
+        assert!(!noarch.join("done-1.0-0.tar.bz2").exists());
+        assert!(noarch.join("todo-1.0-0.tar.bz2").exists());
+        assert!(!state_file.exists());
+    }
+
+    #[tokio::test]
+    async fn test_channel_source_skips_packages_already_at_the_target() {
+        use crate::test_support::PackageFixture;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let channel = temp_dir
+            .path()
+            .join("channel")
+            .to_string_lossy()
+            .to_string();
+        let target = temp_dir.path().join("target").to_string_lossy().to_string();
+        let mut upstream = Repository::new(RepositoryType::Local, channel.clone());
+        for fixture in [
+            PackageFixture::new("numpy", "1.26.4").subdir("linux-64"),
+            PackageFixture::new("scipy", "1.12.0"),
+        ] {
+            upstream
+                .upload_package(&fixture.conda_filename(), fixture.to_conda())
+                .await
+                .unwrap();
+        }
+        upstream.finalize_repository().await.unwrap();
+
+        let mut config = Config {
+            resume_state_file: temp_dir
+                .path()
+                .join("resume.json")
+                .to_string_lossy()
+                .to_string(),
+            src_subdirs: vec!["linux-64".to_string(), "noarch".to_string()],
+            ..Default::default()
+        };
+        let mirror = |config: Config| {
+            let (channel, target) = (channel.clone(), target.clone());
+            async move {
+                mirror_packages(
+                    &channel,
+                    None,
+                    "channel",
+                    false,
+                    RepositoryType::Local,
+                    &target,
+                    &config,
+                )
+                .await
+                .unwrap()
+            }
+        };
+
+        assert_eq!(mirror(config.clone()).await.mirrored_count(), 2);
+
+        // Neither package is downloaded again
+        let report = mirror(config.clone()).await;
+        assert_eq!(report.mirrored_count(), 0);
+        assert_eq!(
+            report.skip_summary().into_iter().collect::<Vec<_>>(),
+            vec![(SkipCode::AlreadyPresent, 2)]
+        );
+
+        config.force = true;
+        assert_eq!(mirror(config).await.mirrored_count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_mirror_from_provider_stops_when_window_closes() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let repo_path = temp_dir.path().join("repo").to_string_lossy().to_string();
+        let config = Config {
+            resume_state_file: temp_dir
+                .path()
+                .join("resume.json")
+                .to_string_lossy()
+                .to_string(),
+            run_until: Some(chrono::Utc::now()),
+            ..Default::default()
+        };
+
+        let mut repository = Repository::new(RepositoryType::Local, repo_path.clone());
+        let provider = StaticProvider {
+            name: "artifacts.zip".to_string(),
+            packages: vec![(
+                "todo-1.0-0.tar.bz2".to_string(),
+                Bytes::from_static(b"todo"),
+            )],
+        };
+
+        let result = mirror_from_provider(&provider, &mut repository, &config).await;
+        let Err(MirrorError::Interrupted {
+            pending: 1, report, ..
+        }) = result
+        else {
+            panic!("expected an interrupted run, got {:?}", result);
+        };
+        assert_eq!(report.source, "artifacts.zip");
+        assert_eq!(report.target, repo_path);
+        let state = ResumeState::load_for(&config.resume_state_file, "artifacts.zip", &repo_path)
+            .unwrap()
+            .unwrap();
+        assert_eq!(state.pending, vec!["todo-1.0-0.tar.bz2".to_string()]);
+    }
+
+    #[cfg(feature = "state-db")]
+    #[tokio::test]
+    async fn test_mirror_from_provider_since_last_run() {
+        use crate::state::{HistoryQuery, StateDb};
+        use crate::test_support::PackageFixture;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let repo_path = temp_dir.path().join("repo").to_string_lossy().to_string();
+        let state_db = temp_dir.path().join("state.db");
+        let mut config = Config {
+            resume_state_file: temp_dir
+                .path()
+                .join("resume.json")
+                .to_string_lossy()
+                .to_string(),
+            state_db: Some(state_db.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let first = PackageFixture::new("first", "1.0").subdir("linux-64");
+        let second = PackageFixture::new("second", "1.0");
+
+        let mut repository = Repository::new(RepositoryType::Local, repo_path.clone());
+        let provider = StaticProvider {
+            name: "owner/repo".to_string(),
+            packages: vec![(first.conda_filename(), first.to_conda())],
+        };
+        let report = mirror_from_provider(&provider, &mut repository, &config)
+            .await
+            .unwrap();
+        assert_eq!(report.mirrored_count(), 1);
+        assert_eq!(report.packages[0].platform.as_deref(), Some("linux-64"));
+
+        // The recorded package is skipped without looking at its content
+        config.since_last_run = true;
+        let mut repository = Repository::new(RepositoryType::Local, repo_path.clone());
+        let provider = StaticProvider {
+            name: "owner/repo".to_string(),
+            packages: vec![
+                (first.conda_filename(), Bytes::from_static(b"not fetched")),
+                (second.conda_filename(), second.to_conda()),
+            ],
+        };
+        let report = mirror_from_provider(&provider, &mut repository, &config)
+            .await
+            .unwrap();
+        assert_eq!(report.mirrored_count(), 1);
+        assert_eq!(report.skipped_count(), 1);
+        assert_eq!(
+            report.packages[0].outcome,
+            PackageOutcome::Skipped {
+                code: SkipCode::DeliveredEarlier,
+                reason: "delivered to the target by an earlier run".to_string()
+            }
+        );
+
+        let history = StateDb::open(&state_db)
+            .unwrap()
+            .history(&HistoryQuery::default())
+            .unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].path(), "noarch/second-1.0-0.conda");
+        assert_eq!(history[1].path(), "linux-64/first-1.0-0.conda");
+        assert_eq!(history[1].target, repo_path);
+
+        config.state_db = None;
+        let result = mirror_from_provider(&provider, &mut repository, &config).await;
+        assert!(matches!(result, Err(MirrorError::InvalidInput(_))));
+    }
+
+    #[tokio::test]
+    async fn test_mirror_from_provider_writes_provenance() {
+        use crate::test_support::PackageFixture;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let repo_path = temp_dir.path().join("repo").to_string_lossy().to_string();
+        let config = Config {
+            resume_state_file: temp_dir
+                .path()
+                .join("resume.json")
+                .to_string_lossy()
+                .to_string(),
+            provenance: true,
+            ..Default::default()
+        };
+        let fixture = PackageFixture::new("attested", "1.0").subdir("linux-64");
+
+        let mut repository = Repository::new(RepositoryType::Local, repo_path.clone());
+        let provider = StaticProvider {
+            name: "owner/repo".to_string(),
+            packages: vec![(fixture.conda_filename(), fixture.to_conda())],
+        };
+        let report = mirror_from_provider(&provider, &mut repository, &config)
+            .await
+            .unwrap();
+        let statement = report.packages[0].provenance.clone().unwrap();
+        assert_eq!(
+            statement["subject"][0]["digest"]["sha256"].as_str(),
+            report.packages[0].sha256.as_deref()
+        );
+
+        let stored = Path::new(&repo_path)
+            .join("linux-64")
+            .join("attested-1.0-0.conda.intoto.json");
+        let stored: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(stored).unwrap()).unwrap();
+        assert_eq!(stored, statement);
+    }
+
+    #[tokio::test]
+    async fn test_mirror_from_provider_renames_nonconforming_filenames() {
+        use crate::naming::FilenamePolicy;
+        use crate::test_support::PackageFixture;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let repo_path = temp_dir.path().join("repo");
+        let mut config = Config {
+            resume_state_file: temp_dir
+                .path()
+                .join("resume.json")
+                .to_string_lossy()
+                .to_string(),
+            ..Default::default()
+        };
+        let package = PackageFixture::new("renamed", "1.0").subdir("linux-64");
+        let provider = StaticProvider {
+            name: "owner/repo".to_string(),
+            packages: vec![("renamed-linux-64.conda".to_string(), package.to_conda())],
+        };
+
+        let mut repository = Repository::new(
+            RepositoryType::Local,
+            repo_path.to_string_lossy().to_string(),
+        );
+        let report = mirror_from_provider(&provider, &mut repository, &config)
+            .await
+            .unwrap();
+        assert_eq!(report.mirrored_count(), 1);
+        assert_eq!(
+            report.packages[0].stored_as.as_deref(),
+            Some(package.conda_filename().as_str())
+        );
+        assert!(repo_path
+            .join("linux-64")
+            .join(package.conda_filename())
+            .exists());
+        assert!(!repo_path
+            .join("linux-64")
+            .join("renamed-linux-64.conda")
+            .exists());
+
+        config.filename_policy = FilenamePolicy::Reject;
+        let mut repository = configured_repository(
+            Repository::new(
+                RepositoryType::Local,
+                temp_dir.path().join("strict").to_string_lossy().to_string(),
+            ),
+            &config,
+        )
+        .unwrap();
+        let report = mirror_from_provider(&provider, &mut repository, &config)
+            .await
+            .unwrap();
+        assert_eq!(report.failed_count(), 1);
+        assert!(report
+            .failures()
+            .next()
+            .unwrap()
+            .1
+            .contains(&package.conda_filename()));
+    }
+
+    #[tokio::test]
+    async fn test_mirror_from_provider_reports_failures() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let config = Config {
+            resume_state_file: temp_dir
+                .path()
+                .join("resume.json")
+                .to_string_lossy()
+                .to_string(),
+            ..Default::default()
+        };
+        let mut repository = Repository::new(
+            RepositoryType::Local,
+            temp_dir.path().join("repo").to_string_lossy().to_string(),
+        );
 
-        Err(anyhow!(error_msg))
-    } else {
-        Ok(())
+        let provider = StaticProvider {
+            name: "bad-names".to_string(),
+            packages: vec![("NUL.conda".to_string(), Bytes::from_static(b"x"))],
+        };
+        let report = mirror_from_provider(&provider, &mut repository, &config)
+            .await
+            .unwrap();
+        assert_eq!(report.failed_count(), 1);
+        assert!(!report.is_success());
+        assert_eq!(report.failures().next().unwrap().0, "NUL.conda");
+
+        let provider = StaticProvider {
+            name: "empty".to_string(),
+            packages: Vec::new(),
+        };
+        let result = mirror_from_provider(&provider, &mut repository, &config).await;
+        assert!(matches!(result, Err(MirrorError::NotFound(_))));
     }
-}
 
-async fn mirror_from_tarball(
-    client: &Client,
-    source: &str,
-    is_local_file: bool,
-    repository: &mut Repository,
-    config: &Config,
-) -> Result<()> {
-    // Get tarball content (either from URL or local file)
-    let tarball_content = if is_local_file {
-        info!("Reading local tarball: {}", source);
-        std::fs::read(source)
-            .map_err(|e| anyhow!("Failed to read local file '{}': {}", source, e))?
-            .into()
-    } else {
-        info!("Downloading tarball from: {}", source);
-        download_package(client, source, config).await?
-    };
+    #[tokio::test]
+    async fn test_mirror_from_provider_applies_policy() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let config = Config {
+            resume_state_file: temp_dir
+                .path()
+                .join("resume.json")
+                .to_string_lossy()
+                .to_string(),
+            policy: Some(crate::policy::PolicyConfig {
+                deny: vec!["pytest".to_string()],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let repo = temp_dir.path().join("repo");
+        let mut repository = configured_repository(
+            Repository::new(RepositoryType::Local, repo.to_string_lossy().to_string()),
+            &config,
+        )
+        .unwrap();
 
-    info!("Extracting conda packages from tarball");
+        use crate::test_support::PackageFixture;
 
-    // Create a cursor from the downloaded bytes
-    let cursor = std::io::Cursor::new(tarball_content);
-    let tar = GzDecoder::new(cursor);
-    let mut archive = Archive::new(tar);
+        let admitted = PackageFixture::new("numpy", "1.26.4").subdir("linux-64");
+        let denied = PackageFixture::new("pytest", "8.0").subdir("linux-64");
+        let provider = StaticProvider {
+            name: "policy".to_string(),
+            packages: vec![
+                (admitted.conda_filename(), admitted.to_conda()),
+                (denied.conda_filename(), denied.to_conda()),
+            ],
+        };
+        let report = mirror_from_provider(&provider, &mut repository, &config)
+            .await
+            .unwrap();
 
-    let mut success_count = 0;
-    let mut error_count = 0;
-    let mut all_file_paths = Vec::new();
+        assert!(report.is_success());
+        assert_eq!(report.mirrored_count(), 1);
+        assert_eq!(
+            report.packages[1].outcome,
+            PackageOutcome::Skipped {
+                code: SkipCode::PolicyRejected,
+                reason: "pytest-8.0-0.conda rejected by policy: pytest matches denylist pattern 'pytest'"
+                    .to_string()
+            }
+        );
+        assert!(!repo.join("linux-64").join(denied.conda_filename()).exists());
+        let repodata = std::fs::read_to_string(repo.join("linux-64/repodata.json")).unwrap();
+        assert!(repodata.contains("numpy-1.26.4-0.conda"));
+        assert!(!repodata.contains("pytest"));
+    }
 
-    // Iterate through files in the tarball
-    for entry in archive.entries()? {
-        let mut entry = entry?;
-        let path = entry.path()?;
-        let file_name = path.to_string_lossy().to_string();
+    #[tokio::test]
+    async fn test_mirror_from_provider_selects_packages_by_spec() {
+        use crate::test_support::PackageFixture;
 
-        // Collect all file paths for potential debugging
-        all_file_paths.push(file_name.clone());
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let config = Config {
+            resume_state_file: temp_dir
+                .path()
+                .join("resume.json")
+                .to_string_lossy()
+                .to_string(),
+            specs: vec!["numpy >=1.21,<2".to_string()],
+            ..Default::default()
+        };
+        let repo = temp_dir.path().join("repo");
+        let mut repository = configured_repository(
+            Repository::new(RepositoryType::Local, repo.to_string_lossy().to_string()),
+            &config,
+        )
+        .unwrap();
 
-        // Check if this file is a conda package
-        let is_conda_package = file_name.ends_with(".conda") || file_name.ends_with(".tar.bz2");
+        let selected = PackageFixture::new("numpy", "1.26.4").subdir("linux-64");
+        let too_new = PackageFixture::new("numpy", "2.0.0").subdir("linux-64");
+        let renamed = PackageFixture::new("numpy", "2.1.0").subdir("linux-64");
+        let provider = StaticProvider {
+            name: "specs".to_string(),
+            packages: vec![
+                (selected.conda_filename(), selected.to_conda()),
+                (too_new.conda_filename(), too_new.to_conda()),
+                ("numpy-linux-64.conda".to_string(), renamed.to_conda()),
+            ],
+        };
+        let report = mirror_from_provider(&provider, &mut repository, &config)
+            .await
+            .unwrap();
 
-        if is_conda_package {
-            info!("Found conda package in tarball: {}", file_name);
+        assert!(report.is_success());
+        assert_eq!(report.mirrored_count(), 1);
+        // Ruled out by its filename, without being fetched
+        assert_eq!(
+            report.packages[1].outcome,
+            PackageOutcome::Skipped {
+                code: SkipCode::SpecMismatch,
+                reason: "matches none of the specs 'numpy >=1.21,<2'".to_string()
+            }
+        );
+        // Only its metadata tells the version of a renamed package
+        assert_eq!(
+            report.packages[2].outcome,
+            PackageOutcome::Skipped {
+                code: SkipCode::SpecMismatch,
+                reason: "numpy-linux-64.conda matches none of the specs 'numpy >=1.21,<2'"
+                    .to_string()
+            }
+        );
+        let repodata = std::fs::read_to_string(repo.join("linux-64/repodata.json")).unwrap();
+        assert!(repodata.contains("numpy-1.26.4-0.conda"));
+        assert!(!repodata.contains("numpy-2."));
+    }
 
-            // Read the file content
-            let mut content = Vec::new();
-            entry.read_to_end(&mut content)?;
-            let content_bytes = Bytes::from(content);
+    #[tokio::test]
+    async fn test_mirror_from_provider_reports_and_requires_platforms() {
+        use crate::test_support::PackageFixture;
 
-            // Extract just the filename for the package name
-            let package_name = std::path::Path::new(&file_name)
-                .file_name()
-                .unwrap()
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config {
+            resume_state_file: temp_dir
+                .path()
+                .join("resume.json")
                 .to_string_lossy()
-                .to_string();
+                .to_string(),
+            policy: Some(crate::policy::PolicyConfig {
+                platforms: vec!["linux-64".to_string(), "noarch".to_string()],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let fixtures = [
+            PackageFixture::new("numpy", "1.26.4").subdir("linux-64"),
+            PackageFixture::new("scipy", "1.12.0").subdir("linux-64"),
+            PackageFixture::new("numpy", "1.26.4").subdir("osx-arm64"),
+            PackageFixture::new("pytest", "8.0"),
+        ];
+        let provider = StaticProvider {
+            name: "multi-platform".to_string(),
+            packages: fixtures
+                .iter()
+                .enumerate()
+                .map(|(i, fixture)| {
+                    (
+                        format!("{}-{}", i, fixture.conda_filename()),
+                        fixture.to_conda(),
+                    )
+                })
+                .collect(),
+        };
+        let repo = temp_dir.path().join("repo");
+        let repository = |config: &Config| {
+            configured_repository(
+                Repository::new(RepositoryType::Local, repo.to_string_lossy().to_string()),
+                config,
+            )
+            .unwrap()
+        };
 
-            // Upload the package
-            match repository
-                .upload_package(&package_name, content_bytes)
-                .await
-            {
-                Ok(_) => {
-                    info!("Successfully uploaded: {}", package_name);
-                    success_count += 1;
-                }
-                Err(e) => {
-                    error!("Failed to upload {}: {}", package_name, e);
-                    error_count += 1;
-                }
-            }
-        }
-    }
+        let report = mirror_from_provider(&provider, &mut repository(&config), &config)
+            .await
+            .unwrap();
+        let summary: Vec<_> = report
+            .platform_summary()
+            .into_iter()
+            .map(|s| (s.platform, s.found, s.mirrored, s.skipped))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("linux-64".to_string(), 2, 2, 0),
+                ("noarch".to_string(), 1, 1, 0),
+                ("osx-arm64".to_string(), 1, 0, 1),
+            ]
+        );
 
-    info!(
-        "Tarball processing completed: {} succeeded, {} failed",
-        success_count, error_count
-    );
+        // Nothing reaches a target the source lacks a required platform for
+        config.require_platforms = vec!["linux-64".to_string(), "win-64".to_string()];
+        let untouched = temp_dir.path().join("untouched");
+        let mut target = configured_repository(
+            Repository::new(
+                RepositoryType::Local,
+                untouched.to_string_lossy().to_string(),
+            ),
+            &config,
+        )
+        .unwrap();
+        let error = mirror_from_provider(&provider, &mut target, &config)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            &error,
+            MirrorError::MissingPlatforms { missing, .. } if missing == &["win-64".to_string()]
+        ));
+        assert!(error
+            .to_string()
+            .contains("found: linux-64, noarch, osx-arm64"));
+        assert!(target.stored_packages().await.unwrap().is_empty());
+        for subdir in ["linux-64", "noarch", "osx-arm64"] {
+            assert!(!untouched.join(subdir).join("repodata.json").exists());
+        }
 
-    // Finalize repository structure
-    if success_count > 0 {
-        repository.finalize_repository().await?;
+        config.require_platforms = vec!["linux-65".to_string()];
+        assert!(matches!(
+            mirror_from_provider(&provider, &mut repository(&config), &config).await,
+            Err(MirrorError::InvalidInput(_))
+        ));
     }
 
-    if success_count == 0 {
-        let mut error_msg = "No conda packages found in tarball".to_string();
+    #[tokio::test]
+    async fn test_mirror_from_provider_quarantines_license_violations() {
+        use crate::policy::{LicensePolicy, PolicyConfig, ViolationAction};
+        use crate::test_support::PackageFixture;
 
-        error_msg.push_str("\n\nAll files in tarball:");
-        for (i, path) in all_file_paths.iter().enumerate() {
-            error_msg.push_str(&format!("\n  {}: {}", i + 1, path));
-        }
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let quarantine_dir = temp_dir.path().join("quarantine");
+        let config = Config {
+            resume_state_file: temp_dir
+                .path()
+                .join("resume.json")
+                .to_string_lossy()
+                .to_string(),
+            quarantine_dir: quarantine_dir.to_string_lossy().to_string(),
+            policy: Some(PolicyConfig {
+                licenses: Some(LicensePolicy {
+                    deny: vec!["GPL3".to_string()],
+                    action: ViolationAction::Quarantine,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let repo = temp_dir.path().join("repo");
+        let mut repository = configured_repository(
+            Repository::new(RepositoryType::Local, repo.to_string_lossy().to_string()),
+            &config,
+        )
+        .unwrap();
 
-        error_msg.push_str("\n\nHint: Files must have .conda or .tar.bz2 extensions");
+        let permissive = PackageFixture::new("permissive", "1.0").license("MIT");
+        let copyleft = PackageFixture::new("copyleft", "1.0").license("GPL-3.0-only");
+        let provider = StaticProvider {
+            name: "licenses".to_string(),
+            packages: vec![
+                (permissive.conda_filename(), permissive.to_conda()),
+                (copyleft.conda_filename(), copyleft.to_conda()),
+            ],
+        };
+        let report = mirror_from_provider(&provider, &mut repository, &config)
+            .await
+            .unwrap();
 
-        Err(anyhow!(error_msg))
-    } else {
-        Ok(())
-    }
-}
+        assert_eq!(report.mirrored_count(), 1);
+        assert_eq!(report.packages[0].license.as_deref(), Some("MIT"));
+        let PackageOutcome::Skipped { code, reason } = &report.packages[1].outcome else {
+            panic!("copyleft package was not skipped");
+        };
+        assert_eq!(*code, SkipCode::PolicyRejected);
+        assert!(
+            reason.contains("license GPL-3.0-only (family GPL3) is not allowed; quarantined at"),
+            "{}",
+            reason
+        );
+        assert!(!repo.join("noarch").join(copyleft.conda_filename()).exists());
+        let quarantined: Vec<_> = std::fs::read_dir(&quarantine_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(quarantined.len(), 3);
+        assert!(quarantined
+            .iter()
+            .any(|name| name.ends_with("copyleft-1.0-0.conda")));
 
-fn extract_package_name(source: &str) -> Result<String> {
-    // Handle local file paths
-    if !source.starts_with("http://")
-        && !source.starts_with("https://")
-        && !source.starts_with("file://")
-    {
-        let path = Path::new(source);
-        let package_name = path
-            .file_name()
-            .and_then(|name| name.to_str())
-            .ok_or_else(|| anyhow!("Could not extract package name from file path"))?;
-        return Ok(package_name.to_string());
+        // The package waits for review, for the target it was meant for
+        let manifest = review::ReviewManifest::load(&quarantine_dir).unwrap();
+        let entry = manifest.packages.values().next().unwrap();
+        assert_eq!(entry.filename, copyleft.conda_filename());
+        assert_eq!(entry.status, review::ReviewStatus::Pending);
+        assert_eq!(entry.target_type, "local");
     }
 
-    // Handle URLs
-    let parsed_url = Url::parse(source)?;
-    let path = parsed_url.path();
+    #[tokio::test]
+    async fn test_unchanged_upstream_skips_the_run() {
+        use crate::test_support::PackageFixture;
+        use crate::test_util::{MockResponse, MockServer};
 
-    // Get the last segment of the path
-    let package_name = path
-        .split('/')
-        .next_back()
-        .ok_or_else(|| anyhow!("Could not extract package name from URL"))?;
+        let server = MockServer::start().await.unwrap();
+        let fixture = PackageFixture::new("nightly", "1.0");
+        let path = format!("/{}", fixture.conda_filename());
+        let package = fixture.to_conda();
+        let serve = |etag: &str| {
+            for method in ["HEAD", "GET"] {
+                server.mock(
+                    method,
+                    &path,
+                    MockResponse::new(200, package.to_vec()).with_header("ETag", etag),
+                );
+            }
+        };
+        serve("\"v1\"");
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let config = Config {
+            resume_state_file: temp_dir
+                .path()
+                .join("resume.json")
+                .to_string_lossy()
+                .to_string(),
+            upstream_state_file: Some(
+                temp_dir
+                    .path()
+                    .join("upstream.json")
+                    .to_string_lossy()
+                    .to_string(),
+            ),
+            ..Default::default()
+        };
+        let source = format!("{}{}", server.url(), path);
+        let target = temp_dir.path().join("repo").to_string_lossy().to_string();
+        let mirror = || {
+            mirror_packages(
+                &source,
+                None,
+                "url",
+                false,
+                RepositoryType::Local,
+                &target,
+                &config,
+            )
+        };
+        let downloads = || {
+            server
+                .requests()
+                .iter()
+                .filter(|request| request.method == "GET")
+                .count()
+        };
 
-    if package_name.is_empty() {
-        return Err(anyhow!("Package name is empty"));
+        assert_eq!(mirror().await.unwrap().mirrored_count(), 1);
+        assert_eq!(downloads(), 1);
+
+        let report = mirror().await.unwrap();
+        assert!(report.packages.is_empty());
+        assert_eq!(report.skipped_items[0].code, SkipCode::Unchanged);
+        assert_eq!(downloads(), 1);
+
+        serve("\"v2\"");
+        let report = mirror().await.unwrap();
+        assert_eq!(report.skipped_count(), 1);
+        assert_eq!(downloads(), 2);
     }
 
-    Ok(package_name.to_string())
-}
+    #[tokio::test]
+    async fn test_unchanged_channel_skips_the_run() {
+        use crate::test_support::PackageFixture;
 
-// Helper function to resolve GitHub artifact URLs from PRs
-#[allow(dead_code)]
-pub async fn resolve_github_pr_artifacts(pr_url: &str, config: &Config) -> Result<Vec<String>> {
-    info!("Resolving artifacts from PR: {}", pr_url);
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let channel = temp_dir
+            .path()
+            .join("channel")
+            .to_string_lossy()
+            .to_string();
+        let target = temp_dir.path().join("target").to_string_lossy().to_string();
+        let mut upstream = Repository::new(RepositoryType::Local, channel.clone());
+        for fixture in [
+            PackageFixture::new("numpy", "1.26.4").subdir("linux-64"),
+            PackageFixture::new("scipy", "1.12.0"),
+        ] {
+            upstream
+                .upload_package(&fixture.conda_filename(), fixture.to_conda())
+                .await
+                .unwrap();
+        }
+        upstream.finalize_repository().await.unwrap();
 
-    // Parse PR URL to extract owner, repo, and PR number
-    let parsed_url = Url::parse(pr_url)?;
-    let path_segments: Vec<&str> = parsed_url
-        .path()
-        .trim_start_matches('/')
-        .split('/')
-        .collect();
+        let config = Config {
+            resume_state_file: temp_dir
+                .path()
+                .join("resume.json")
+                .to_string_lossy()
+                .to_string(),
+            upstream_state_file: Some(
+                temp_dir
+                    .path()
+                    .join("upstream.json")
+                    .to_string_lossy()
+                    .to_string(),
+            ),
+            src_subdirs: vec!["linux-64".to_string(), "noarch".to_string()],
+            ..Default::default()
+        };
+        let mirror = || {
+            mirror_packages(
+                &channel,
+                None,
+                "channel",
+                false,
+                RepositoryType::Local,
+                &target,
+                &config,
+            )
+        };
 
-    if path_segments.len() < 4 || path_segments[2] != "pull" {
-        return Err(anyhow!("Invalid GitHub PR URL format"));
-    }
+        assert_eq!(mirror().await.unwrap().mirrored_count(), 2);
 
-    let owner = path_segments[0];
-    let repo = path_segments[1];
-    let pr_number = path_segments[3].trim_end_matches('/');
+        let report = mirror().await.unwrap();
+        assert!(report.packages.is_empty());
+        assert_eq!(report.skipped_items[0].code, SkipCode::Unchanged);
 
-    // Use GitHub API to get artifacts
-    let _client = build_client(config)?;
-    let _api_url = format!(
-        "https://api.github.com/repos/{}/{}/pulls/{}/checks",
-        owner, repo, pr_number
-    );
+        // A new package in one subdir is enough for the channel to be mirrored
+        let pandas = PackageFixture::new("pandas", "2.2.0");
+        upstream
+            .upload_package(&pandas.conda_filename(), pandas.to_conda())
+            .await
+            .unwrap();
+        upstream.finalize_repository().await.unwrap();
+        let report = mirror().await.unwrap();
+        assert_eq!(report.mirrored_count(), 1);
+        assert_eq!(report.skipped_count(), 2);
+    }
 
-    info!("Fetching PR artifacts from GitHub API");
+    #[tokio::test]
+    async fn test_gitlab_source_mirrors_latest_successful_pipeline() {
+        use crate::test_support::PackageFixture;
+        use crate::test_util::{MockResponse, MockServer};
+        use std::io::Write;
 
-    // Note: This is a simplified version. In practice, you'd need to:
-    // 1. Get the PR details
-    // 2. Find associated CI runs
-    // 3. Download artifacts from those runs
-    // For now, return empty list as placeholder
-    warn!("GitHub artifact resolution is not fully implemented yet");
-    Ok(vec![])
-}
+        let server = MockServer::start().await.unwrap();
+        let fixture = PackageFixture::new("gitlab-built", "1.0");
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        writer
+            .start_file(
+                format!("pkgs/{}", fixture.conda_filename()),
+                zip::write::SimpleFileOptions::default(),
+            )
+            .unwrap();
+        writer.write_all(&fixture.to_conda()).unwrap();
+        let archive = writer.finish().unwrap().into_inner();
 
-async fn mirror_from_github(
-    client: &Client,
-    source: &str,
-    name_filter: Option<&str>,
-    repository: &mut Repository,
-    config: &Config,
-) -> Result<()> {
-    info!("Starting GitHub artifact mirroring from: {}", source);
+        let api = "/api/v4/projects/group%2Fproject";
+        server.mock(
+            "GET",
+            &format!("{}/pipelines", api),
+            MockResponse::json(
+                200,
+                r#"[{"id": 43, "status": "failed", "ref": "main", "sha": "b", "web_url": "",
+                     "created_at": "2024-05-02T12:00:00Z", "updated_at": null},
+                    {"id": 42, "status": "success", "ref": "main", "sha": "a", "web_url": "",
+                     "created_at": "2024-05-01T12:00:00Z", "updated_at": null}]"#,
+            ),
+        );
+        server.mock(
+            "GET",
+            &format!("{}/pipelines/42/jobs", api),
+            MockResponse::json(
+                200,
+                format!(
+                    r#"[{{"id": 7, "name": "build-linux", "stage": "build", "status": "success",
+                          "web_url": "", "created_at": "2024-05-01T12:00:00Z", "finished_at": null,
+                          "artifacts_file": {{"filename": "artifacts.zip", "size": {}}},
+                          "artifacts_expire_at": null}},
+                         {{"id": 8, "name": "lint", "stage": "test", "status": "success",
+                          "web_url": "", "created_at": "2024-05-01T12:00:00Z", "finished_at": null,
+                          "artifacts_expire_at": null}}]"#,
+                    archive.len()
+                ),
+            ),
+        );
+        server.mock(
+            "GET",
+            &format!("{}/jobs/7/artifacts", api),
+            MockResponse::new(200, archive),
+        );
 
-    // Parse GitHub repository
-    let (owner, repo) = github::parse_github_repository(source)?;
-    info!("Parsed GitHub repository: {}/{}", owner, repo);
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let config = Config {
+            resume_state_file: temp_dir
+                .path()
+                .join("resume.json")
+                .to_string_lossy()
+                .to_string(),
+            ..Default::default()
+        };
+        let target = temp_dir.path().join("repo").to_string_lossy().to_string();
+        let report = mirror_packages(
+            &format!("{}/group/project", server.url()),
+            None,
+            "gitlab",
+            false,
+            RepositoryType::Local,
+            &target,
+            &config,
+        )
+        .await
+        .unwrap();
 
-    // Create GitHub client
-    let github_client = github::GitHubClient::new(config)?;
+        assert_eq!(report.mirrored_count(), 1);
+        let artifact = report.packages[0].artifact.as_ref().unwrap();
+        assert_eq!(artifact.name, "build-linux");
+        assert_eq!(artifact.pipeline_id, Some(42));
+        assert_eq!(artifact.job_id, Some(7));
+        assert!(server
+            .requests()
+            .iter()
+            .all(|request| !request.path.contains("/jobs/8/")));
+    }
 
-    // Handle specific artifact ID or list artifacts
-    let artifacts = if let Some(artifact_id_str) = source.split('#').nth(1) {
-        // Handle specific artifact by ID (format: owner/repo#artifact_id)
-        let artifact_id = github::parse_artifact_id(artifact_id_str)?;
-        info!("Downloading specific artifact ID: {}", artifact_id);
+    #[tokio::test]
+    async fn test_truncated_package_fails_extraction_check() {
+        use crate::test_support::PackageFixture;
 
-        let artifact = github_client
-            .get_artifact(&owner, &repo, artifact_id)
-            .await?;
-        vec![artifact]
-    } else {
-        // List all artifacts and optionally filter
-        let mut artifacts = github_client.list_artifacts(&owner, &repo).await?;
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let fixture = PackageFixture::new("truncated", "1.0");
+        let package = fixture.to_conda();
+        let source = temp_dir.path().join(fixture.conda_filename());
+        std::fs::write(&source, &package[..package.len() / 2]).unwrap();
+        let target = temp_dir.path().join("repo");
+        let config = Config {
+            quarantine_dir: temp_dir
+                .path()
+                .join("quarantine")
+                .to_string_lossy()
+                .to_string(),
+            verify_extraction: true,
+            ..Default::default()
+        };
 
-        // Filter by name if specified
-        if let Some(pattern) = name_filter {
-            artifacts = github_client.filter_artifacts_by_name(&artifacts, Some(pattern));
-        }
+        let report = mirror_packages(
+            source.to_str().unwrap(),
+            None,
+            "local",
+            true,
+            RepositoryType::Local,
+            target.to_str().unwrap(),
+            &config,
+        )
+        .await
+        .unwrap();
+        assert_eq!(report.failed_count(), 1);
+        let PackageOutcome::Failed { error } = &report.packages[0].outcome else {
+            panic!("expected a failure");
+        };
+        assert!(error.contains("failed the extraction check"));
+        assert!(error.contains("quarantined at"));
+        assert!(!target
+            .join("noarch")
+            .join(fixture.conda_filename())
+            .exists());
+    }
 
-        // Filter out expired artifacts
-        artifacts = github_client.filter_non_expired_artifacts(&artifacts);
+    #[tokio::test]
+    async fn test_full_disk_stops_the_run_before_fetching() {
+        use crate::test_support::PackageFixture;
 
-        if artifacts.is_empty() {
-            return Err(anyhow!("No artifacts found matching the criteria"));
-        }
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let fixture = PackageFixture::new("large", "1.0");
+        let source = temp_dir.path().join(fixture.conda_filename());
+        std::fs::write(&source, fixture.to_conda()).unwrap();
+        let target = temp_dir.path().join("repo");
+        let scratch = temp_dir.path().join("scratch");
+        let config = Config {
+            verify_extraction: true,
+            scratch_dir: Some(scratch.to_string_lossy().to_string()),
+            min_free_space: u64::MAX / 2,
+            ..Default::default()
+        };
 
-        // For mirroring, we might want to process all or ask user to specify
-        // For now, let's process the first one or all if there's a name filter
-        if name_filter.is_none() && artifacts.len() > 1 {
-            warn!(
-                "Multiple artifacts found ({}) but no name filter specified. Processing the most recent one.",
-                artifacts.len()
-            );
-            // Sort by creation date and take the most recent
-            artifacts.sort_by(|a, b| b.created_at.cmp(&a.created_at));
-            vec![artifacts.into_iter().next().unwrap()]
-        } else {
-            artifacts
-        }
-    };
+        let result = mirror_packages(
+            source.to_str().unwrap(),
+            None,
+            "local",
+            true,
+            RepositoryType::Local,
+            target.to_str().unwrap(),
+            &config,
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(MirrorError::InsufficientSpace { ref path, ref purpose, .. })
+                if *path == scratch && purpose == "extracting packages"
+        ));
+        assert!(!target.join("noarch").exists());
 
-    // Process each selected artifact
-    for artifact in artifacts {
-        info!(
-            "Processing artifact '{}' (ID: {}, Size: {} bytes)",
-            artifact.name, artifact.id, artifact.size_in_bytes
-        );
+        // With the room it needs, the package is extracted in the scratch directory
+        let config = Config {
+            min_free_space: 0,
+            ..config
+        };
+        let report = mirror_packages(
+            source.to_str().unwrap(),
+            None,
+            "local",
+            true,
+            RepositoryType::Local,
+            target.to_str().unwrap(),
+            &config,
+        )
+        .await
+        .unwrap();
+        assert_eq!(report.mirrored_count(), 1);
+        assert!(scratch.is_dir());
+    }
 
-        if artifact.expired {
-            warn!("Artifact '{}' has expired, skipping", artifact.name);
-            continue;
+    #[tokio::test]
+    async fn test_filter_preset_selects_the_build_output() {
+        use crate::test_support::PackageFixture;
+        use std::io::Write;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let numpy = PackageFixture::new("numpy", "1.26.0").subdir("linux-64");
+        let tzdata = PackageFixture::new("tzdata", "2024a").subdir("noarch");
+        let python = PackageFixture::new("python", "3.12.0").subdir("linux-64");
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default();
+        for (path, content) in [
+            (
+                format!("build_artifacts/linux-64/{}", numpy.conda_filename()),
+                numpy.to_conda(),
+            ),
+            (
+                format!("build_artifacts/noarch/{}", tzdata.conda_filename()),
+                tzdata.to_conda(),
+            ),
+            (
+                format!("pkgs/{}", python.conda_filename()),
+                python.to_conda(),
+            ),
+        ] {
+            writer.start_file(path, options).unwrap();
+            writer.write_all(&content).unwrap();
         }
+        let source = temp_dir.path().join("conda_artifacts_1_linux_64.zip");
+        std::fs::write(&source, writer.finish().unwrap().into_inner()).unwrap();
+        let target = temp_dir.path().join("repo");
+        let config = Config {
+            filter_preset: Some("conda-forge".to_string()),
+            ..Default::default()
+        };
 
-        // Download the artifact (it comes as a ZIP file)
-        let artifact_content = github_client
-            .download_artifact(&owner, &repo, artifact.id)
-            .await?;
+        let report = mirror_packages(
+            source.to_str().unwrap(),
+            None,
+            "zip",
+            true,
+            RepositoryType::Local,
+            target.to_str().unwrap(),
+            &config,
+        )
+        .await
+        .unwrap();
+        assert_eq!(report.mirrored_count(), 2);
+        assert!(target
+            .join("linux-64")
+            .join(numpy.conda_filename())
+            .exists());
+        assert!(target.join("noarch").join(tzdata.conda_filename()).exists());
+        assert!(!target
+            .join("linux-64")
+            .join(python.conda_filename())
+            .exists());
+        assert_eq!(report.skipped_items.len(), 1);
+        assert_eq!(report.skipped_items[0].code, SkipCode::Unmatched);
 
-        // Save to temporary file and process as ZIP
-        let temp_dir = tempfile::TempDir::new()?;
-        let temp_zip_path = temp_dir.path().join(format!("{}.zip", artifact.name));
-        std::fs::write(&temp_zip_path, artifact_content)?;
+        let config = Config {
+            filter_preset: Some("conda-froge".to_string()),
+            ..config
+        };
+        assert!(matches!(
+            mirror_packages(
+                source.to_str().unwrap(),
+                None,
+                "zip",
+                true,
+                RepositoryType::Local,
+                target.to_str().unwrap(),
+                &config,
+            )
+            .await,
+            Err(MirrorError::InvalidInput(_))
+        ));
+    }
 
-        info!("Downloaded artifact to temporary file: {:?}", temp_zip_path);
+    #[tokio::test]
+    async fn test_source_outside_allowed_hosts_is_refused() {
+        use crate::test_util::MockServer;
 
-        // Process the ZIP file - look for conda packages
-        let zip_path_pattern = name_filter.unwrap_or(r".*\.conda$|.*\.tar\.bz2$");
+        let server = MockServer::start().await.unwrap();
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let config = Config {
+            allowed_source_hosts: vec!["*.example.org".to_string()],
+            ..Default::default()
+        };
+        let target = temp_dir.path().join("repo").to_string_lossy().to_string();
 
-        mirror_from_zip(
-            client,
-            temp_zip_path.to_str().unwrap(),
-            zip_path_pattern,
-            true, // is_local_file = true since we downloaded it locally
-            repository,
-            config,
+        let result = mirror_packages(
+            &format!("{}/builds.zip", server.url()),
+            Some(".*"),
+            "zip-url",
+            false,
+            RepositoryType::Local,
+            &target,
+            &config,
         )
-        .await?;
+        .await;
+        assert!(matches!(result, Err(MirrorError::SourceNotAllowed { .. })));
+        assert!(server.requests().is_empty());
     }
 
-    info!("GitHub artifact mirroring completed");
-    Ok(())
-}
+    #[tokio::test]
+    async fn test_mirror_from_provider_blocks_vulnerable_packages() {
+        use crate::osv::{Severity, VulnerabilityConfig};
+        use crate::test_support::PackageFixture;
+        use crate::test_util::{MockResponse, MockServer};
 
-async fn mirror_from_azure(
-    client: &Client,
-    source: &str,
-    name_filter: Option<&str>,
-    repository: &mut Repository,
-    config: &Config,
-) -> Result<()> {
-    info!("Starting Azure DevOps artifact mirroring from: {}", source);
+        let server = MockServer::start().await.unwrap();
+        server.mock(
+            "POST",
+            "/v1/query",
+            MockResponse::json(
+                200,
+                r#"{"vulns": [{"id": "GHSA-aaaa-bbbb-cccc", "database_specific": {"severity": "HIGH"}}]}"#,
+            ),
+        );
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config {
+            resume_state_file: temp_dir
+                .path()
+                .join("resume.json")
+                .to_string_lossy()
+                .to_string(),
+            vulnerabilities: Some(VulnerabilityConfig {
+                api_url: server.url().to_string(),
+                block_severity: Some(Severity::Critical),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let mut repository = Repository::new(
+            RepositoryType::Local,
+            temp_dir.path().join("repo").to_string_lossy().to_string(),
+        );
+        let fixture = PackageFixture::new("vulnerable", "1.0");
+        let provider = StaticProvider {
+            name: "osv".to_string(),
+            packages: vec![(fixture.conda_filename(), fixture.to_conda())],
+        };
 
-    // Parse Azure DevOps organization/project/build_id
-    let (organization, project, build_id) = azure::parse_azure_source(source)?;
-    info!("Parsed Azure DevOps: {}/{}", organization, project);
+        // Below the threshold the advisory is only reported
+        let report = mirror_from_provider(&provider, &mut repository, &config)
+            .await
+            .unwrap();
+        assert_eq!(report.mirrored_count(), 1);
+        assert_eq!(report.packages[0].advisories[0].id, "GHSA-aaaa-bbbb-cccc");
 
-    // Create Azure DevOps client
-    let azure_client = azure::AzureDevOpsClient::new(config)?;
+        config.vulnerabilities.as_mut().unwrap().block_severity = Some(Severity::High);
+        let mut repository = Repository::new(
+            RepositoryType::Local,
+            temp_dir.path().join("other").to_string_lossy().to_string(),
+        );
+        let report = mirror_from_provider(&provider, &mut repository, &config)
+            .await
+            .unwrap();
+        assert_eq!(report.mirrored_count(), 0);
+        assert_eq!(
+            report.packages[0].outcome,
+            PackageOutcome::Skipped {
+                code: SkipCode::Vulnerable,
+                reason: "blocked by high advisory GHSA-aaaa-bbbb-cccc".to_string()
+            }
+        );
+        assert!(!temp_dir
+            .path()
+            .join("other/noarch")
+            .join(fixture.conda_filename())
+            .exists());
 
-    // Handle specific build ID or list recent builds
-    let builds_and_artifacts = if let Some(build_id) = build_id {
-        info!("Processing specific build ID: {}", build_id);
-        let artifacts = azure_client
-            .list_artifacts(&organization, &project, build_id)
-            .await?;
-        vec![(build_id, artifacts)]
-    } else {
-        // List recent builds and get their artifacts
-        let builds = azure_client
-            .list_builds(&organization, &project, None)
-            .await?;
+        // With the quarantine action the package is held for review instead
+        config.vulnerabilities.as_mut().unwrap().action =
+            crate::policy::ViolationAction::Quarantine;
+        config.quarantine_dir = temp_dir
+            .path()
+            .join("quarantine")
+            .to_string_lossy()
+            .to_string();
+        let mut repository = Repository::new(
+            RepositoryType::Local,
+            temp_dir.path().join("held").to_string_lossy().to_string(),
+        );
+        let report = mirror_from_provider(&provider, &mut repository, &config)
+            .await
+            .unwrap();
+        assert_eq!(report.mirrored_count(), 0);
+        let manifest = review::ReviewManifest::load(Path::new(&config.quarantine_dir)).unwrap();
+        let entry = manifest.packages.values().next().unwrap();
+        assert_eq!(entry.filename, fixture.conda_filename());
+        assert_eq!(entry.reason, "blocked by high advisory GHSA-aaaa-bbbb-cccc");
+    }
 
-        if builds.is_empty() {
-            return Err(anyhow!("No builds found for {}/{}", organization, project));
-        }
+    #[test]
+    fn test_package_name_from_member() {
+        assert_eq!(
+            package_name_from_member(Path::new("pkgs/linux-64/numpy-1.0-0.conda")).unwrap(),
+            "numpy-1.0-0.conda"
+        );
+        assert_eq!(
+            package_name_from_member(Path::new("./numpy-1.0-0.conda")).unwrap(),
+            "numpy-1.0-0.conda"
+        );
+        assert_eq!(
+            package_name_from_member(Path::new("build\\numpy-1.0-0.conda")).unwrap(),
+            "numpy-1.0-0.conda"
+        );
 
-        // For mirroring, we might want to process all recent successful builds
-        // or just the most recent one if no name filter is specified
-        let builds_to_process = if name_filter.is_none() && builds.len() > 1 {
-            warn!(
-                "Multiple builds found ({}) but no name filter specified. Processing the most recent successful build.",
-                builds.len()
+        for hostile in [
+            "../numpy-1.0-0.conda",
+            "pkgs/../../numpy-1.0-0.conda",
+            "/etc/numpy-1.0-0.conda",
+            "pkgs\\..\\..\\numpy-1.0-0.conda",
+            "pkgs/numpy\u{1b}[31m-1.0-0.conda",
+        ] {
+            assert!(
+                package_name_from_member(Path::new(hostile)).is_err(),
+                "{:?} should be rejected",
+                hostile
             );
-            // Filter for successful builds and take the most recent
-            let mut successful_builds: Vec<_> = builds
-                .into_iter()
-                .filter(|b| b.result.as_deref() == Some("succeeded"))
-                .collect();
-            successful_builds.sort_by(|a, b| b.id.cmp(&a.id));
-            successful_builds.into_iter().take(1).collect()
-        } else {
-            builds
-        };
-
-        let mut builds_and_artifacts = Vec::new();
-        for build in builds_to_process {
-            info!("Getting artifacts for build {}", build.id);
-            let artifacts = azure_client
-                .list_artifacts(&organization, &project, build.id)
-                .await?;
-            builds_and_artifacts.push((build.id, artifacts));
         }
-        builds_and_artifacts
-    };
+    }
 
-    // Process each build's artifacts
-    for (build_id, artifacts) in builds_and_artifacts {
-        let mut filtered_artifacts = artifacts;
+    #[cfg(unix)]
+    #[test]
+    fn test_package_name_from_member_rejects_non_utf8() {
+        use std::os::unix::ffi::OsStrExt;
 
-        // Filter by name if specified
-        if let Some(pattern) = name_filter {
-            filtered_artifacts =
-                azure_client.filter_artifacts_by_name(&filtered_artifacts, Some(pattern));
-        }
+        let path = Path::new(std::ffi::OsStr::from_bytes(b"pkgs/num\xffpy-1.0-0.conda"));
+        assert!(package_name_from_member(path).is_err());
+    }
 
-        // Filter for downloadable artifacts (those with download URLs or specific types)
-        let downloadable_artifacts: Vec<_> = filtered_artifacts
-            .into_iter()
-            .filter(|artifact| {
-                // Prefer artifacts that can be downloaded as files
-                artifact
-                    .resource
-                    .artifact_type
-                    .eq_ignore_ascii_case("Container")
-                    || artifact
-                        .resource
-                        .artifact_type
-                        .eq_ignore_ascii_case("FilePath")
-                    || artifact.resource.download_url.is_some()
-            })
-            .collect();
+    #[test]
+    fn test_extract_zip_packages_skips_traversal_entries() {
+        use std::io::Write;
 
-        if downloadable_artifacts.is_empty() {
-            warn!("No downloadable artifacts found for build {}", build_id);
-            continue;
-        }
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default();
+        writer
+            .start_file("../../evil-1.0-0.conda", options)
+            .unwrap();
+        writer.write_all(b"evil").unwrap();
+        writer.start_file("pkgs/good-1.0-0.conda", options).unwrap();
+        writer.write_all(b"good").unwrap();
+        let content = Bytes::from(writer.finish().unwrap().into_inner());
 
-        // Process each downloadable artifact
-        for artifact in downloadable_artifacts {
-            info!(
-                "Processing artifact '{}' (ID: {}, Type: {}) from build {}",
-                artifact.name, artifact.id, artifact.resource.artifact_type, build_id
-            );
+        let extracted = extract_zip_packages(&content, &MemberFilter::default()).unwrap();
+        let names: Vec<_> = extracted
+            .packages
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        assert_eq!(names, vec!["good-1.0-0.conda"]);
+        assert_eq!(extracted.all_file_paths.len(), 2);
+        assert_eq!(extracted.skipped.len(), 1);
+        assert_eq!(extracted.skipped[0].code, SkipCode::UnsafePath);
+    }
 
-            // Download the artifact
-            let artifact_content = azure_client
-                .download_artifact(&organization, &project, build_id, &artifact.name)
-                .await?;
+    #[test]
+    fn test_extract_zip_packages_skips_excluded_members() {
+        use std::io::Write;
 
-            // Save to temporary file and process as ZIP
-            let temp_dir = tempfile::TempDir::new()?;
-            let temp_zip_path = temp_dir.path().join(format!("{}.zip", artifact.name));
-            std::fs::write(&temp_zip_path, artifact_content)?;
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default();
+        writer.start_file("pkgs/good-1.0-0.conda", options).unwrap();
+        writer.write_all(b"good").unwrap();
+        writer
+            .start_file("debug/good-dbg-1.0-0.conda", options)
+            .unwrap();
+        writer.write_all(b"symbols").unwrap();
+        let content = Bytes::from(writer.finish().unwrap().into_inner());
 
-            info!("Downloaded artifact to temporary file: {:?}", temp_zip_path);
+        let config = Config {
+            src_exclude: Some("^debug/".to_string()),
+            ..Default::default()
+        };
+        let filter = MemberFilter {
+            exclude: exclude_regex(&config).unwrap(),
+            ..Default::default()
+        };
+        let extracted = extract_zip_packages(&content, &filter).unwrap();
+        let names: Vec<_> = extracted
+            .packages
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        assert_eq!(names, vec!["good-1.0-0.conda"]);
+        assert_eq!(
+            extracted.skipped,
+            vec![SkippedItem::new(
+                "ZIP member",
+                "debug/good-dbg-1.0-0.conda",
+                SkipCode::Excluded,
+                "matches --src-exclude '^debug/'",
+            )]
+        );
 
-            // Process the ZIP file - look for conda packages
-            let zip_path_pattern = name_filter.unwrap_or(r".*\.conda$|.*\.tar\.bz2$");
+        let config = Config {
+            src_exclude: Some("(".to_string()),
+            ..Default::default()
+        };
+        assert!(exclude_regex(&config).is_err());
+    }
 
-            mirror_from_zip(
-                client,
-                temp_zip_path.to_str().unwrap(),
-                zip_path_pattern,
-                true, // is_local_file = true since we downloaded it locally
-                repository,
-                config,
-            )
-            .await?;
-        }
+    #[test]
+    fn test_release_asset_kinds() {
+        assert_eq!(
+            ReleaseAsset::of("numpy-1.26.4-py312_0.conda"),
+            Some(ReleaseAsset::Package)
+        );
+        assert_eq!(
+            ReleaseAsset::of("numpy-1.26.4-py312_0.tar.bz2"),
+            Some(ReleaseAsset::Package)
+        );
+        assert_eq!(
+            ReleaseAsset::of("conda-packages.zip"),
+            Some(ReleaseAsset::Zip)
+        );
+        assert_eq!(
+            ReleaseAsset::of("packages.tgz"),
+            Some(ReleaseAsset::Tarball)
+        );
+        assert_eq!(
+            ReleaseAsset::of("source.tar.gz"),
+            Some(ReleaseAsset::Tarball)
+        );
+        assert_eq!(ReleaseAsset::of("checksums.txt"), None);
     }
 
-    info!("Azure DevOps artifact mirroring completed");
-    Ok(())
-}
+    #[test]
+    fn test_extract_zip_packages_decompresses_zstd_members() {
+        use std::io::Write;
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        let mut tarball = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        header.set_mode(0o644);
+        header.set_cksum();
+        tarball
+            .append_data(&mut header, "out/inner-1.0-0.tar.bz2", &b"inner"[..])
+            .unwrap();
+        let tarball = tarball.into_inner().unwrap();
 
-    #[test]
-    fn test_extract_package_name() {
-        let url = "https://example.com/packages/my-package-1.0.0.tar.bz2";
-        let name = extract_package_name(url).unwrap();
-        assert_eq!(name, "my-package-1.0.0.tar.bz2");
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default();
+        writer
+            .start_file("single-1.0-0.conda.zst", options)
+            .unwrap();
+        writer
+            .write_all(&zstd::stream::encode_all(&b"single"[..], 0).unwrap())
+            .unwrap();
+        writer.start_file("packages.tar.zst", options).unwrap();
+        writer
+            .write_all(&zstd::stream::encode_all(&tarball[..], 0).unwrap())
+            .unwrap();
+        writer.start_file("build.log.zst", options).unwrap();
+        writer
+            .write_all(&zstd::stream::encode_all(&b"log"[..], 0).unwrap())
+            .unwrap();
+        let content = Bytes::from(writer.finish().unwrap().into_inner());
+
+        let extracted = extract_zip_packages(&content, &MemberFilter::default()).unwrap();
+        assert_eq!(
+            extracted.packages,
+            vec![
+                (
+                    "single-1.0-0.conda".to_string(),
+                    Bytes::from_static(b"single")
+                ),
+                (
+                    "inner-1.0-0.tar.bz2".to_string(),
+                    Bytes::from_static(b"inner")
+                ),
+            ]
+        );
+        assert!(extracted
+            .all_file_paths
+            .contains(&"packages.tar.zst/out/inner-1.0-0.tar.bz2".to_string()));
+
+        // Only the member matching --src-path, also inside compressed tarballs
+        let filter = MemberFilter {
+            path: Some(Regex::new("inner").unwrap()),
+            ..Default::default()
+        };
+        let extracted = extract_zip_packages(&content, &filter).unwrap();
+        assert_eq!(extracted.packages.len(), 1);
+        assert_eq!(extracted.packages[0].0, "inner-1.0-0.tar.bz2");
+
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        writer
+            .start_file("broken-1.0-0.conda.zst", options)
+            .unwrap();
+        writer.write_all(b"not zstd").unwrap();
+        let content = Bytes::from(writer.finish().unwrap().into_inner());
+        assert!(matches!(
+            extract_zip_packages(&content, &MemberFilter::default()),
+            Err(MirrorError::Corrupt(_))
+        ));
     }
 
     #[test]
-    fn test_extract_package_name_with_query() {
-        let url = "https://example.com/packages/my-package.tar.bz2?token=abc";
-        let name = extract_package_name(url).unwrap();
-        assert_eq!(name, "my-package.tar.bz2");
+    fn test_extract_tarball_packages_filters_by_path() {
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+            Vec::new(),
+            flate2::Compression::default(),
+        ));
+        for path in [
+            "linux-64/a-1.0-0.conda",
+            "osx-64/b-1.0-0.conda",
+            "linux-64/c-1.0-0.conda",
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(7);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, path, &b"content"[..])
+                .unwrap();
+        }
+        let content = Bytes::from(builder.into_inner().unwrap().finish().unwrap());
+
+        let names = |filter: &MemberFilter| -> Vec<String> {
+            extract_tarball_packages(&content, filter)
+                .unwrap()
+                .packages
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect()
+        };
+        let mut config = Config::default();
+        assert_eq!(
+            names(&MemberFilter::new("", &config).unwrap()),
+            vec!["a-1.0-0.conda", "b-1.0-0.conda", "c-1.0-0.conda"]
+        );
+        assert_eq!(
+            names(&MemberFilter::new("^linux-64/", &config).unwrap()),
+            vec!["a-1.0-0.conda"]
+        );
+        config.all_matches = true;
+        assert_eq!(
+            names(&MemberFilter::new("^linux-64/", &config).unwrap()),
+            vec!["a-1.0-0.conda", "c-1.0-0.conda"]
+        );
+
+        let filter = MemberFilter::new("^linux-64/", &config).unwrap();
+        let skipped = extract_tarball_packages(&content, &filter).unwrap().skipped;
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].name, "osx-64/b-1.0-0.conda");
+        assert_eq!(skipped[0].code, SkipCode::Unmatched);
     }
 
     #[test]
-    fn test_extract_package_name_empty() {
-        let url = "https://example.com/";
-        assert!(extract_package_name(url).is_err());
+    fn test_extract_tarball_packages_rejects_garbage() {
+        let result =
+            extract_tarball_packages(&Bytes::from_static(b"not gzip"), &MemberFilter::default());
+        assert!(matches!(result, Err(MirrorError::Corrupt(_))));
     }
 }
@@ -0,0 +1,151 @@
+//! Resumable multipart uploads to S3
+//!
+//! Packages of at least `s3_multipart_threshold` bytes go to an S3 target in
+//! parts of `s3_multipart_part_size`. The upload ID and the ETag of each part
+//! are written to `s3_multipart_state_file` as soon as S3 acknowledges them.
+//! When a run is interrupted, the next run uploading the same package to the
+//! same key continues the upload with the parts S3 still holds, instead of
+//! starting over and leaving the old parts stored, and billed, until the
+//! bucket's lifecycle rules or `abort-uploads` remove them.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::error::{MirrorError, Result};
+
+/// S3 refuses parts smaller than this, except for the last one
+pub const MIN_PART_SIZE: u64 = 5 * 1024 * 1024;
+
+/// S3 refuses uploads with more parts than this
+pub const MAX_PARTS: u64 = 10_000;
+
+/// The multipart uploads a target has in progress
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MultipartState {
+    /// Uploads by `bucket/key`
+    #[serde(default)]
+    pub uploads: BTreeMap<String, PendingUpload>,
+}
+
+/// A multipart upload that was started but not completed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingUpload {
+    pub upload_id: String,
+    /// sha256 of the package being uploaded; another package under the key starts over
+    pub sha256: String,
+    pub part_size: u64,
+    /// ETags of the parts S3 acknowledged, by part number
+    #[serde(default)]
+    pub parts: BTreeMap<i32, String>,
+    pub started_at: DateTime<Utc>,
+}
+
+impl MultipartState {
+    /// Load the state file; a missing file means no uploads are in progress
+    pub fn load(path: &str) -> Result<Self> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e.into()),
+        };
+        serde_json::from_str(&content).map_err(|e| {
+            MirrorError::InvalidInput(format!("Invalid multipart state file '{}': {}", path, e))
+        })
+    }
+
+    /// Write the state file, or remove it once no upload is in progress
+    pub fn save(&self, path: &str) -> Result<()> {
+        if self.uploads.is_empty() {
+            return match std::fs::remove_file(path) {
+                Ok(()) => Ok(()),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+                Err(e) => Err(e.into()),
+            };
+        }
+        if let Some(parent) = Path::new(path).parent() {
+            if !parent.as_os_str().is_empty() {
+                std::fs::create_dir_all(parent)?;
+            }
+        }
+        // Write next to the file and rename over it, so a crash never leaves half a state
+        let partial = format!("{}.partial", path);
+        std::fs::write(&partial, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(&partial, path)?;
+        Ok(())
+    }
+
+    /// The upload in progress for `object` that can carry on with this content
+    pub fn resumable(&self, object: &str, sha256: &str, part_size: u64) -> Option<&PendingUpload> {
+        self.uploads
+            .get(object)
+            .filter(|upload| upload.sha256 == sha256 && upload.part_size == part_size)
+    }
+}
+
+/// The part size used for `len` bytes, raised to what S3 accepts
+pub fn part_size(len: u64, configured: u64) -> u64 {
+    configured.max(MIN_PART_SIZE).max(len.div_ceil(MAX_PARTS))
+}
+
+/// Part numbers, counted from 1, and the byte ranges of content split into parts
+pub fn parts(len: usize, part_size: u64) -> Vec<(i32, Range<usize>)> {
+    let part_size = part_size.max(1) as usize;
+    (0..len.div_ceil(part_size))
+        .map(|i| {
+            let start = i * part_size;
+            (i as i32 + 1, start..(start + part_size).min(len))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_state_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("multipart.json");
+        let path = path.to_str().unwrap();
+        assert!(MultipartState::load(path).unwrap().uploads.is_empty());
+
+        let mut state = MultipartState::default();
+        state.uploads.insert(
+            "bucket/linux-64/big-1.0-0.conda".to_string(),
+            PendingUpload {
+                upload_id: "upload-1".to_string(),
+                sha256: "abc".to_string(),
+                part_size: MIN_PART_SIZE,
+                parts: BTreeMap::from([(1, "\"etag-1\"".to_string())]),
+                started_at: Utc::now(),
+            },
+        );
+        state.save(path).unwrap();
+
+        let loaded = MultipartState::load(path).unwrap();
+        let object = "bucket/linux-64/big-1.0-0.conda";
+        let upload = loaded.resumable(object, "abc", MIN_PART_SIZE).unwrap();
+        assert_eq!(upload.parts[&1], "\"etag-1\"");
+        assert!(loaded.resumable(object, "def", MIN_PART_SIZE).is_none());
+        assert!(loaded.resumable(object, "abc", 2 * MIN_PART_SIZE).is_none());
+
+        MultipartState::default().save(path).unwrap();
+        assert!(!Path::new(path).exists());
+    }
+
+    #[test]
+    fn test_parts() {
+        assert_eq!(part_size(1, 0), MIN_PART_SIZE);
+        assert_eq!(
+            part_size(MIN_PART_SIZE * MAX_PARTS * 2, 0),
+            MIN_PART_SIZE * 2
+        );
+        assert_eq!(parts(10, 4), [(1, 0..4), (2, 4..8), (3, 8..10)]);
+        assert_eq!(parts(8, 4), [(1, 0..4), (2, 4..8)]);
+        assert!(parts(0, 4).is_empty());
+    }
+}
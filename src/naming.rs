@@ -0,0 +1,157 @@
+//! Conda package filename validation
+//!
+//! Solvers match repodata entries by the name, version and build recorded in
+//! `info/index.json`, and clients download them by filename. A package stored
+//! as `NumPy-1.26.4-py312_0.conda`, or renamed by a CI upload step to
+//! `numpy-linux-64.conda`, ends up in repodata under a key no client expects.
+//! Every package is therefore checked on ingest against the canonical
+//! `<name>-<version>-<build><ext>` built from its metadata, with the name in
+//! its normalized lower-case form. Underscores and dashes are left as they
+//! are: `typing_extensions` and `typing-extensions` are different packages.
+
+use rattler_conda_types::{PackageName, Version};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use crate::conda_package::SimpleIndexJson;
+use crate::error::{MirrorError, Result};
+
+/// What to do with a package whose filename does not match its metadata
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FilenamePolicy {
+    /// Store the package under its canonical filename
+    #[default]
+    Rename,
+    /// Refuse the package
+    Reject,
+}
+
+impl FilenamePolicy {
+    pub fn from_string(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "rename" => Ok(FilenamePolicy::Rename),
+            "reject" | "error" => Ok(FilenamePolicy::Reject),
+            _ => Err(MirrorError::InvalidInput(format!(
+                "Unknown filename policy: {}. Must be one of: rename, reject",
+                s
+            ))),
+        }
+    }
+}
+
+/// Archive extension of a conda package filename, `None` for anything else
+fn extension(filename: &str) -> Option<&'static str> {
+    [".conda", ".tar.bz2"]
+        .into_iter()
+        .find(|ext| filename.ends_with(ext))
+}
+
+/// Filename a package with `metadata` must be stored under
+///
+/// Fails when the metadata itself cannot make a valid filename, since no
+/// rename would let a solver match such a package.
+pub fn canonical_filename(metadata: &SimpleIndexJson, filename: &str) -> Result<String> {
+    let invalid = |reason: String| MirrorError::InvalidFilename {
+        filename: filename.to_string(),
+        reason,
+    };
+
+    let ext = extension(filename)
+        .ok_or_else(|| invalid("not a .conda or .tar.bz2 package".to_string()))?;
+    let name = PackageName::from_str(&metadata.name)
+        .map_err(|e| invalid(format!("package name '{}': {}", metadata.name, e)))?;
+    Version::from_str(&metadata.version)
+        .map_err(|e| invalid(format!("version '{}': {}", metadata.version, e)))?;
+    if metadata.build.is_empty() || metadata.build.contains('-') {
+        return Err(invalid(format!(
+            "build string '{}' must be non-empty and contain no '-'",
+            metadata.build
+        )));
+    }
+
+    Ok(format!(
+        "{}-{}-{}{}",
+        name.as_normalized(),
+        metadata.version,
+        metadata.build,
+        ext
+    ))
+}
+
+/// The canonical filename of a package, `None` when `filename` already is
+pub fn nonconforming(metadata: &SimpleIndexJson, filename: &str) -> Result<Option<String>> {
+    let canonical = canonical_filename(metadata, filename)?;
+    Ok((canonical != filename).then_some(canonical))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(name: &str, version: &str, build: &str) -> SimpleIndexJson {
+        SimpleIndexJson {
+            name: name.to_string(),
+            version: version.to_string(),
+            build: build.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_canonical_filename() {
+        let numpy = metadata("numpy", "1.26.4", "py312_0");
+        assert_eq!(
+            nonconforming(&numpy, "numpy-1.26.4-py312_0.conda").unwrap(),
+            None
+        );
+        assert_eq!(
+            nonconforming(&numpy, "numpy-linux-64.tar.bz2").unwrap(),
+            Some("numpy-1.26.4-py312_0.tar.bz2".to_string())
+        );
+        assert_eq!(
+            nonconforming(
+                &metadata("NumPy", "1.26.4", "py312_0"),
+                "NumPy-1.26.4-py312_0.conda"
+            )
+            .unwrap(),
+            Some("numpy-1.26.4-py312_0.conda".to_string())
+        );
+
+        // Underscores are part of the name, not a spelling of '-'
+        let typing = metadata("typing_extensions", "4.9.0", "pyha770c72_0");
+        assert_eq!(
+            nonconforming(&typing, "typing_extensions-4.9.0-pyha770c72_0.conda").unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_invalid_metadata_is_refused() {
+        for (name, version, build) in [
+            ("num py", "1.0", "0"),
+            ("numpy", "1..0", "0"),
+            ("numpy", "1.0", ""),
+            ("numpy", "1.0", "py-0"),
+        ] {
+            let error = canonical_filename(&metadata(name, version, build), "x.conda").unwrap_err();
+            assert!(
+                matches!(error, MirrorError::InvalidFilename { .. }),
+                "{name} {version} {build}: {error}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_filename_policy_from_string() {
+        assert_eq!(
+            FilenamePolicy::from_string("reject").unwrap(),
+            FilenamePolicy::Reject
+        );
+        assert_eq!(
+            FilenamePolicy::from_string("Rename").unwrap(),
+            FilenamePolicy::Rename
+        );
+        assert!(FilenamePolicy::from_string("keep").is_err());
+    }
+}
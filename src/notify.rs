@@ -0,0 +1,302 @@
+//! Notifications sent when a mirroring run finishes
+//!
+//! Each configured [`WebhookConfig`] receives a JSON POST summarizing the run,
+//! either as a generic payload or shaped for Slack or Discord incoming webhooks.
+//! Delivery failures are logged and never fail the run itself.
+
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::email;
+use crate::report::MirrorReport;
+
+/// Payload shape expected by the receiving service
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WebhookFormat {
+    /// The [`Notification`] as JSON
+    #[default]
+    Generic,
+    /// A Slack incoming webhook message (`{"text": ...}`)
+    Slack,
+    /// A Discord webhook message (`{"content": ...}`)
+    Discord,
+}
+
+/// Which run outcomes trigger a webhook
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WebhookEvents {
+    #[default]
+    Always,
+    Success,
+    Failure,
+}
+
+/// A webhook notified when a run finishes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    #[serde(default)]
+    pub format: WebhookFormat,
+    #[serde(default)]
+    pub on: WebhookEvents,
+}
+
+/// Whether a run succeeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RunStatus {
+    Success,
+    Failure,
+}
+
+/// Summary of a finished run, sent as the generic webhook payload
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Notification {
+    pub status: RunStatus,
+    pub source: String,
+    pub target: String,
+    /// Error that ended or failed the run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Per-package results, absent when the run stopped before producing them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub report: Option<MirrorReport>,
+}
+
+impl Notification {
+    /// Summarize a run that produced a report
+    pub fn from_report(report: &MirrorReport) -> Self {
+        let (status, error) = if report.is_success() {
+            (RunStatus::Success, None)
+        } else if report.failed_count() > 0 {
+            (
+                RunStatus::Failure,
+                Some(format!(
+                    "{} packages failed to mirror",
+                    report.failed_count()
+                )),
+            )
+        } else {
+            (
+                RunStatus::Failure,
+                Some(format!(
+                    "All {} artifacts were corrupt and have been quarantined",
+                    report.quarantined.len()
+                )),
+            )
+        };
+
+        Self {
+            status,
+            source: report.source.clone(),
+            target: report.target.clone(),
+            error,
+            report: Some(report.clone()),
+        }
+    }
+
+    /// Summarize a run that ended with an error before producing a report
+    pub fn from_error(source: &str, target: &str, error: &dyn std::fmt::Display) -> Self {
+        Self {
+            status: RunStatus::Failure,
+            source: source.to_string(),
+            target: target.to_string(),
+            error: Some(error.to_string()),
+            report: None,
+        }
+    }
+
+    /// Attach the results a run produced before it ended with an error
+    pub fn with_report(mut self, report: Option<&MirrorReport>) -> Self {
+        self.report = report.cloned();
+        self
+    }
+
+    /// One-paragraph human-readable summary used by the chat formats
+    pub fn summary(&self) -> String {
+        let headline = match self.status {
+            RunStatus::Success => "Mirror succeeded",
+            RunStatus::Failure => "Mirror failed",
+        };
+        let mut text = format!("{}: {} -> {}", headline, self.source, self.target);
+
+        if let Some(report) = &self.report {
+            text.push_str(&format!(
+                "\n{} mirrored, {} skipped, {} failed, {} quarantined ({} bytes in {:.1}s)",
+                report.mirrored_count(),
+                report.skipped_count(),
+                report.failed_count(),
+                report.quarantined.len(),
+                report.bytes_transferred(),
+                report.duration.as_secs_f64()
+            ));
+            for (filename, error) in report.failures() {
+                text.push_str(&format!("\n- {}: {}", filename, error));
+            }
+        }
+        if let Some(error) = &self.error {
+            text.push_str(&format!("\nError: {}", error));
+        }
+        text
+    }
+
+    /// Request body for a webhook of the given format
+    pub fn payload(&self, format: WebhookFormat) -> serde_json::Value {
+        match format {
+            WebhookFormat::Generic => serde_json::to_value(self).unwrap_or_default(),
+            WebhookFormat::Slack => serde_json::json!({ "text": self.summary() }),
+            WebhookFormat::Discord => serde_json::json!({ "content": self.summary() }),
+        }
+    }
+}
+
+impl WebhookConfig {
+    fn wants(&self, status: RunStatus) -> bool {
+        match self.on {
+            WebhookEvents::Always => true,
+            WebhookEvents::Success => status == RunStatus::Success,
+            WebhookEvents::Failure => status == RunStatus::Failure,
+        }
+    }
+}
+
+/// POST the notification to every webhook interested in its outcome
+///
+/// Returns the number of webhooks that accepted the notification.
+pub async fn send_webhooks(
+    client: &Client,
+    webhooks: &[WebhookConfig],
+    notification: &Notification,
+) -> usize {
+    let mut delivered = 0;
+    for webhook in webhooks.iter().filter(|w| w.wants(notification.status)) {
+        let result = client
+            .post(&webhook.url)
+            .json(&notification.payload(webhook.format))
+            .send()
+            .await;
+        match result {
+            Ok(response) if response.status().is_success() => {
+                info!("Sent run notification to {}", webhook.url);
+                delivered += 1;
+            }
+            Ok(response) => warn!(
+                "Webhook {} rejected the run notification: HTTP {}",
+                webhook.url,
+                response.status()
+            ),
+            Err(e) => warn!("Failed to send run notification to {}: {}", webhook.url, e),
+        }
+    }
+    delivered
+}
+
+/// Send the webhooks and email alerts configured for a finished run
+///
+/// An interrupted run is neither a success nor a failure of the source, so it
+/// does not count towards email alerts.
+pub async fn announce(config: &Config, notification: &Notification, interrupted: bool) {
+    if !config.webhooks.is_empty() {
+        match Client::builder()
+            .timeout(Duration::from_secs(config.timeout_seconds))
+            .build()
+        {
+            Ok(client) => {
+                send_webhooks(&client, &config.webhooks, notification).await;
+            }
+            Err(e) => warn!("Failed to create webhook client: {}", e),
+        }
+    }
+    if let (Some(email_config), false) = (&config.email, interrupted) {
+        email::alert_failures(email_config, notification).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::PackageOutcome;
+    use crate::test_util::{MockResponse, MockServer};
+    use std::time::Duration;
+
+    fn failed_report() -> MirrorReport {
+        let mut report = MirrorReport::new("owner/repo", "./repo");
+        report.record(
+            "a-1.0-0.conda",
+            PackageOutcome::Mirrored,
+            10,
+            Duration::ZERO,
+        );
+        report.record(
+            "b-1.0-0.conda",
+            PackageOutcome::Failed {
+                error: "checksum conflict".to_string(),
+            },
+            0,
+            Duration::ZERO,
+        );
+        report
+    }
+
+    #[test]
+    fn test_payload_formats() {
+        let notification = Notification::from_report(&failed_report());
+        assert_eq!(notification.status, RunStatus::Failure);
+
+        let generic = notification.payload(WebhookFormat::Generic);
+        assert_eq!(generic["status"], "failure");
+        assert_eq!(generic["report"]["packages"][1]["status"], "failed");
+
+        let slack = notification.payload(WebhookFormat::Slack);
+        let text = slack["text"].as_str().unwrap();
+        assert!(text.starts_with("Mirror failed: owner/repo -> ./repo"));
+        assert!(text.contains("1 mirrored, 0 skipped, 1 failed"));
+        assert!(text.contains("- b-1.0-0.conda: checksum conflict"));
+
+        let discord = Notification::from_error("src", "tgt", &"No conda packages found")
+            .payload(WebhookFormat::Discord);
+        assert!(discord["content"]
+            .as_str()
+            .unwrap()
+            .ends_with("Error: No conda packages found"));
+    }
+
+    #[tokio::test]
+    async fn test_send_webhooks_filters_by_event() {
+        let server = MockServer::start().await.unwrap();
+        server.mock("POST", "/hook", MockResponse::new(200, ""));
+        server.mock("POST", "/broken", MockResponse::new(500, ""));
+
+        let webhooks = vec![
+            WebhookConfig {
+                url: format!("{}/hook", server.url()),
+                format: WebhookFormat::Slack,
+                on: WebhookEvents::Failure,
+            },
+            WebhookConfig {
+                url: format!("{}/hook", server.url()),
+                format: WebhookFormat::Generic,
+                on: WebhookEvents::Success,
+            },
+            WebhookConfig {
+                url: format!("{}/broken", server.url()),
+                format: WebhookFormat::Generic,
+                on: WebhookEvents::Always,
+            },
+        ];
+
+        let notification = Notification::from_report(&failed_report());
+        let delivered = send_webhooks(&Client::new(), &webhooks, &notification).await;
+        assert_eq!(delivered, 1);
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].path, "/hook");
+        assert_eq!(requests[1].path, "/broken");
+    }
+}
@@ -0,0 +1,640 @@
+//! OCI registries as conda channels
+//!
+//! `--tgt-type oci` pushes packages to a registry such as ghcr.io in the layout
+//! rattler reads `oci://` channels from, the one the conda-forge mirror at
+//! `ghcr.io/channel-mirrors/conda-forge` uses. For the channel
+//! `oci://ghcr.io/org/channel`:
+//!
+//! - a package is the manifest `org/channel/<subdir>/<name>:<version>-<build>`,
+//!   with `+`, `!` and `=` in the tag spelled `__p__`, `__e__` and `__eq__`
+//!   and a `zzz` prefix on names starting with `_`
+//! - its content is a layer of media type `application/vnd.conda.package.v2`
+//!   (`v1` for `.tar.bz2`), stored under its sha256, next to a layer holding
+//!   its repodata record
+//! - the `repodata.json` of a subdir is the layer of the manifest
+//!   `org/channel/<subdir>/repodata.json:latest`
+//!
+//! Registries asking for credentials get `oci_username` and `oci_password`,
+//! or `OCI_USERNAME` and `OCI_PASSWORD`; for ghcr.io `GITHUB_TOKEN` is used
+//! when neither is set.
+
+use bytes::Bytes;
+use regex::Regex;
+use reqwest::header::{ACCEPT, CONTENT_TYPE, LOCATION, WWW_AUTHENTICATE};
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use url::Url;
+
+use crate::error::{MirrorError, Result};
+
+/// Media type of `.conda` package layers
+pub const PACKAGE_V2_MEDIA_TYPE: &str = "application/vnd.conda.package.v2";
+/// Media type of `.tar.bz2` package layers
+pub const PACKAGE_V1_MEDIA_TYPE: &str = "application/vnd.conda.package.v1";
+/// Media type of the layer holding the repodata record of a package
+pub const INDEX_MEDIA_TYPE: &str = "application/vnd.conda.info.index.v1+json";
+/// Media type of `repodata.json` layers
+pub const REPODATA_MEDIA_TYPE: &str = "application/vnd.conda.repodata.v1+json";
+/// Media type of the manifests pushed
+pub const MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+/// The empty config of artifacts that are not container images
+const EMPTY_CONFIG_MEDIA_TYPE: &str = "application/vnd.oci.empty.v1+json";
+/// Layer annotation naming the file a layer holds
+const TITLE_ANNOTATION: &str = "org.opencontainers.image.title";
+/// Tag of the `repodata.json` manifests
+pub const REPODATA_TAG: &str = "latest";
+
+/// A conda channel in an OCI registry, addressed as `oci://registry/path`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OciChannel {
+    /// Host, and port if given, of the registry
+    pub registry: String,
+    /// Repository path of the channel within the registry
+    pub path: String,
+}
+
+impl OciChannel {
+    /// Parse a target such as `oci://ghcr.io/org/channel`; the scheme may be left out
+    pub fn parse(target: &str) -> Result<Self> {
+        let reference = target.strip_prefix("oci://").unwrap_or(target);
+        let (registry, path) = reference
+            .trim_end_matches('/')
+            .split_once('/')
+            .filter(|(registry, path)| !registry.is_empty() && !path.is_empty())
+            .ok_or_else(|| {
+                MirrorError::InvalidInput(format!(
+                    "Invalid OCI target '{}': expected oci://<registry>/<path>, e.g. oci://ghcr.io/org/channel",
+                    target
+                ))
+            })?;
+        let valid = Regex::new(r"^[a-z0-9]+([._-][a-z0-9]+)*(/[a-z0-9]+([._-][a-z0-9]+)*)*$")?;
+        if !valid.is_match(path) {
+            return Err(MirrorError::InvalidInput(format!(
+                "Invalid OCI target '{}': repository paths are lowercase letters, digits and separators",
+                target
+            )));
+        }
+        Ok(Self {
+            registry: registry.to_string(),
+            path: path.to_string(),
+        })
+    }
+
+    /// The `oci://` URL clients add as a channel
+    pub fn url(&self) -> String {
+        format!("oci://{}/{}", self.registry, self.path)
+    }
+
+    /// Base URL of the registry API; registries on the local host are spoken to over plain HTTP
+    pub fn base_url(&self) -> String {
+        let host = self
+            .registry
+            .rsplit_once(':')
+            .map_or(self.registry.as_str(), |(host, _)| host);
+        let scheme = if matches!(host, "localhost" | "127.0.0.1" | "[::1]") {
+            "http"
+        } else {
+            "https"
+        };
+        format!("{}://{}", scheme, self.registry)
+    }
+
+    /// Repository and tag of a package, and the media type of its layer
+    pub fn package(&self, subdir: &str, filename: &str) -> Result<PackageReference> {
+        let (stem, media_type) = if let Some(stem) = filename.strip_suffix(".conda") {
+            (stem, PACKAGE_V2_MEDIA_TYPE)
+        } else if let Some(stem) = filename.strip_suffix(".tar.bz2") {
+            (stem, PACKAGE_V1_MEDIA_TYPE)
+        } else {
+            return Err(MirrorError::InvalidInput(format!(
+                "{} is not a conda package",
+                filename
+            )));
+        };
+        let mut parts = stem.rsplitn(3, '-');
+        let (Some(build), Some(version), Some(name)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(MirrorError::InvalidInput(format!(
+                "{} is not named <name>-<version>-<build>",
+                filename
+            )));
+        };
+        Ok(PackageReference {
+            repository: format!("{}/{}/{}", self.path, subdir, image_name(name)),
+            tag: version_build_tag(&format!("{}-{}", version, build)),
+            media_type,
+        })
+    }
+
+    /// Repository holding the `repodata.json` of a subdir under [`REPODATA_TAG`]
+    pub fn repodata_repository(&self, subdir: &str) -> String {
+        format!("{}/{}/repodata.json", self.path, subdir)
+    }
+}
+
+/// Where a package is kept in a registry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageReference {
+    pub repository: String,
+    pub tag: String,
+    /// Media type of the layer holding the package
+    pub media_type: &'static str,
+}
+
+/// OCI tags cannot hold `+`, `!` or `=`, spelled as rattler expects
+pub fn version_build_tag(tag: &str) -> String {
+    tag.replace('+', "__p__")
+        .replace('!', "__e__")
+        .replace('=', "__eq__")
+}
+
+/// OCI repository names cannot start with `_`
+fn image_name(name: &str) -> String {
+    if name.starts_with('_') {
+        format!("zzz{}", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Content addressed by a manifest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Descriptor {
+    pub media_type: String,
+    pub digest: String,
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
+}
+
+impl Descriptor {
+    /// Describe `content` of a media type, naming it `title` if given
+    pub fn new(media_type: &str, content: &[u8], title: Option<&str>) -> Self {
+        Self {
+            media_type: media_type.to_string(),
+            digest: digest(content),
+            size: content.len() as u64,
+            annotations: title
+                .map(|title| BTreeMap::from([(TITLE_ANNOTATION.to_string(), title.to_string())]))
+                .unwrap_or_default(),
+        }
+    }
+
+    /// The sha256 in the digest, if it is one
+    pub fn sha256(&self) -> Option<&str> {
+        self.digest.strip_prefix("sha256:")
+    }
+}
+
+/// An OCI image manifest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    pub schema_version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+    pub config: Descriptor,
+    pub layers: Vec<Descriptor>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
+}
+
+impl Manifest {
+    /// A manifest of the given layers with the empty config
+    pub fn new(layers: Vec<Descriptor>) -> Self {
+        Self {
+            schema_version: 2,
+            media_type: Some(MANIFEST_MEDIA_TYPE.to_string()),
+            config: Descriptor::new(EMPTY_CONFIG_MEDIA_TYPE, EMPTY_CONFIG, None),
+            layers,
+            annotations: BTreeMap::new(),
+        }
+    }
+
+    /// The first layer of a media type
+    pub fn layer(&self, media_type: &str) -> Option<&Descriptor> {
+        self.layers
+            .iter()
+            .find(|layer| layer.media_type == media_type)
+    }
+}
+
+/// Content of the empty config
+pub const EMPTY_CONFIG: &[u8] = b"{}";
+
+/// The `sha256:` digest of content
+pub fn digest(content: &[u8]) -> String {
+    format!("sha256:{:x}", Sha256::digest(content))
+}
+
+/// Credentials from the environment: `OCI_USERNAME` and `OCI_PASSWORD`, or `GITHUB_TOKEN` for ghcr.io
+pub fn env_credentials(registry: &str) -> Option<(String, String)> {
+    if let Ok(password) = std::env::var("OCI_PASSWORD") {
+        let username = std::env::var("OCI_USERNAME").unwrap_or_else(|_| "oauth2".to_string());
+        return Some((username, password));
+    }
+    if registry == "ghcr.io" {
+        if let Ok(token) = std::env::var("GITHUB_TOKEN") {
+            let username = std::env::var("GITHUB_ACTOR").unwrap_or_else(|_| "token".to_string());
+            return Some((username, token));
+        }
+    }
+    None
+}
+
+/// How requests to a repository are authorized after the registry asked
+#[derive(Debug, Clone)]
+enum Authorization {
+    Bearer(String),
+    Basic,
+}
+
+/// Client of the OCI distribution API of one registry
+pub struct OciClient {
+    client: Client,
+    base_url: String,
+    credentials: Option<(String, String)>,
+    /// Authorization obtained for each repository
+    authorizations: Mutex<HashMap<String, Authorization>>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    #[serde(default)]
+    token: Option<String>,
+    #[serde(default)]
+    access_token: Option<String>,
+}
+
+impl OciClient {
+    pub fn new(client: Client, base_url: String, credentials: Option<(String, String)>) -> Self {
+        Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            credentials,
+            authorizations: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn url(&self, repository: &str, path: &str) -> String {
+        format!("{}/v2/{}/{}", self.base_url, repository, path)
+    }
+
+    /// Send a request, answering the registry's authentication challenge once
+    async fn send<F>(&self, repository: &str, request: F) -> Result<Response>
+    where
+        F: Fn(&Client) -> RequestBuilder,
+    {
+        let cached = self.authorizations.lock().unwrap().get(repository).cloned();
+        let response = self
+            .authorized(request(&self.client), cached.as_ref())
+            .send()
+            .await?;
+        if response.status() != StatusCode::UNAUTHORIZED {
+            return Ok(response);
+        }
+
+        let challenge = response
+            .headers()
+            .get(WWW_AUTHENTICATE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        let authorization = self.authorize(repository, &challenge).await?;
+        let response = self
+            .authorized(request(&self.client), Some(&authorization))
+            .send()
+            .await?;
+        self.authorizations
+            .lock()
+            .unwrap()
+            .insert(repository.to_string(), authorization);
+        Ok(response)
+    }
+
+    fn authorized(
+        &self,
+        request: RequestBuilder,
+        authorization: Option<&Authorization>,
+    ) -> RequestBuilder {
+        match (authorization, &self.credentials) {
+            (Some(Authorization::Bearer(token)), _) => request.bearer_auth(token),
+            (Some(Authorization::Basic), Some((username, password))) => {
+                request.basic_auth(username, Some(password))
+            }
+            _ => request,
+        }
+    }
+
+    /// Obtain the authorization a `WWW-Authenticate` challenge asks for
+    async fn authorize(&self, repository: &str, challenge: &str) -> Result<Authorization> {
+        let (scheme, params) = challenge.split_once(' ').unwrap_or((challenge, ""));
+        if scheme.eq_ignore_ascii_case("basic") {
+            return match self.credentials {
+                Some(_) => Ok(Authorization::Basic),
+                None => Err(MirrorError::AuthError(format!(
+                    "{} asks for credentials; set oci_username and oci_password",
+                    self.base_url
+                ))),
+            };
+        }
+        if !scheme.eq_ignore_ascii_case("bearer") {
+            return Err(MirrorError::AuthError(format!(
+                "{} refused the request for {}",
+                self.base_url, repository
+            )));
+        }
+
+        let params: HashMap<String, String> = Regex::new(r#"(\w+)="([^"]*)""#)?
+            .captures_iter(params)
+            .map(|captures| (captures[1].to_lowercase(), captures[2].to_string()))
+            .collect();
+        let realm = params.get("realm").ok_or_else(|| {
+            MirrorError::AuthError(format!(
+                "{} sent a challenge without a realm",
+                self.base_url
+            ))
+        })?;
+        let actions = if self.credentials.is_some() {
+            "pull,push"
+        } else {
+            "pull"
+        };
+        let mut url = Url::parse(realm)?;
+        {
+            let mut query = url.query_pairs_mut();
+            if let Some(service) = params.get("service") {
+                query.append_pair("service", service);
+            }
+            query.append_pair("scope", &format!("repository:{}:{}", repository, actions));
+        }
+        let mut request = self.client.get(url);
+        if let Some((username, password)) = &self.credentials {
+            request = request.basic_auth(username, Some(password));
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(MirrorError::from_status(
+                status,
+                &format!("Token request for {} at {}", repository, realm),
+                &body,
+            ));
+        }
+        let token: TokenResponse = response.json().await?;
+        token
+            .token
+            .or(token.access_token)
+            .map(Authorization::Bearer)
+            .ok_or_else(|| MirrorError::InvalidResponse(format!("{} returned no token", realm)))
+    }
+
+    /// Fail with the registry's answer unless the request succeeded
+    async fn check(response: Response, context: &str) -> Result<Response> {
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let body = response.text().await.unwrap_or_default();
+        Err(MirrorError::from_status(status, context, &body))
+    }
+
+    /// The manifest of a tag and its raw content, `None` if the tag does not exist
+    pub async fn manifest(&self, repository: &str, tag: &str) -> Result<Option<(Manifest, Bytes)>> {
+        let url = self.url(repository, &format!("manifests/{}", tag));
+        let response = self
+            .send(repository, |client| {
+                client.get(&url).header(ACCEPT, MANIFEST_MEDIA_TYPE)
+            })
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let content = Self::check(
+            response,
+            &format!("Reading manifest {}:{}", repository, tag),
+        )
+        .await?
+        .bytes()
+        .await?;
+        let manifest = serde_json::from_slice(&content).map_err(|e| {
+            MirrorError::InvalidResponse(format!("Invalid manifest {}:{}: {}", repository, tag, e))
+        })?;
+        Ok(Some((manifest, content)))
+    }
+
+    /// Content of a blob
+    pub async fn blob(&self, repository: &str, digest: &str) -> Result<Bytes> {
+        let url = self.url(repository, &format!("blobs/{}", digest));
+        let response = self.send(repository, |client| client.get(&url)).await?;
+        let content = Self::check(response, &format!("Reading blob {}@{}", repository, digest))
+            .await?
+            .bytes()
+            .await?;
+        if self::digest(&content) != digest {
+            return Err(MirrorError::Corrupt(format!(
+                "Blob {}@{} does not match its digest",
+                repository, digest
+            )));
+        }
+        Ok(content)
+    }
+
+    /// Upload a blob unless the repository already holds it
+    pub async fn push_blob(
+        &self,
+        repository: &str,
+        descriptor: &Descriptor,
+        content: Bytes,
+    ) -> Result<()> {
+        let url = self.url(repository, &format!("blobs/{}", descriptor.digest));
+        let response = self.send(repository, |client| client.head(&url)).await?;
+        if response.status().is_success() {
+            return Ok(());
+        }
+
+        let context = format!("Uploading blob {}@{}", repository, descriptor.digest);
+        let uploads = self.url(repository, "blobs/uploads/");
+        let response = self
+            .send(repository, |client| client.post(&uploads))
+            .await?;
+        let response = Self::check(response, &context).await?;
+        let location = response
+            .headers()
+            .get(LOCATION)
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(|| {
+                MirrorError::InvalidResponse(format!("{}: no upload location", context))
+            })?;
+        let mut upload = Url::parse(&self.base_url)?.join(location)?;
+        upload
+            .query_pairs_mut()
+            .append_pair("digest", &descriptor.digest);
+        let response = self
+            .send(repository, |client| {
+                client
+                    .put(upload.clone())
+                    .header(CONTENT_TYPE, "application/octet-stream")
+                    .body(content.clone())
+            })
+            .await?;
+        Self::check(response, &context).await?;
+        Ok(())
+    }
+
+    /// Tag a manifest whose blobs were pushed
+    pub async fn push_manifest(
+        &self,
+        repository: &str,
+        tag: &str,
+        manifest: &Manifest,
+    ) -> Result<()> {
+        let url = self.url(repository, &format!("manifests/{}", tag));
+        let content = serde_json::to_vec(manifest)?;
+        let response = self
+            .send(repository, |client| {
+                client
+                    .put(&url)
+                    .header(CONTENT_TYPE, MANIFEST_MEDIA_TYPE)
+                    .body(content.clone())
+            })
+            .await?;
+        Self::check(
+            response,
+            &format!("Pushing manifest {}:{}", repository, tag),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Push the blobs of `layers` and tag a manifest of them
+    pub async fn push(
+        &self,
+        repository: &str,
+        tag: &str,
+        layers: Vec<(Descriptor, Bytes)>,
+    ) -> Result<()> {
+        self.push_blob(
+            repository,
+            &Descriptor::new(EMPTY_CONFIG_MEDIA_TYPE, EMPTY_CONFIG, None),
+            Bytes::from_static(EMPTY_CONFIG),
+        )
+        .await?;
+        for (descriptor, content) in &layers {
+            self.push_blob(repository, descriptor, content.clone())
+                .await?;
+        }
+        let manifest = Manifest::new(
+            layers
+                .into_iter()
+                .map(|(descriptor, _)| descriptor)
+                .collect(),
+        );
+        self.push_manifest(repository, tag, &manifest).await
+    }
+
+    /// Remove a tagged manifest; registries delete manifests by digest
+    pub async fn delete(&self, repository: &str, tag: &str) -> Result<()> {
+        let Some((_, content)) = self.manifest(repository, tag).await? else {
+            return Ok(());
+        };
+        let url = self.url(repository, &format!("manifests/{}", digest(&content)));
+        let response = self.send(repository, |client| client.delete(&url)).await?;
+        Self::check(response, &format!("Deleting {}:{}", repository, tag)).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{MockResponse, MockServer};
+
+    #[test]
+    fn test_channel_naming_matches_rattler() {
+        let channel = OciChannel::parse("oci://ghcr.io/channel-mirrors/conda-forge/").unwrap();
+        assert_eq!(channel.registry, "ghcr.io");
+        assert_eq!(channel.path, "channel-mirrors/conda-forge");
+        assert_eq!(channel.url(), "oci://ghcr.io/channel-mirrors/conda-forge");
+        assert_eq!(channel.base_url(), "https://ghcr.io");
+        assert_eq!(
+            OciChannel::parse("localhost:5000/conda")
+                .unwrap()
+                .base_url(),
+            "http://localhost:5000"
+        );
+
+        let package = channel
+            .package("linux-64", "libgcc-ng-13.2.0-h77fa898_7.conda")
+            .unwrap();
+        assert_eq!(
+            package.repository,
+            "channel-mirrors/conda-forge/linux-64/libgcc-ng"
+        );
+        assert_eq!(package.tag, "13.2.0-h77fa898_7");
+        assert_eq!(package.media_type, PACKAGE_V2_MEDIA_TYPE);
+
+        let package = channel
+            .package("noarch", "_openmp_mutex-4.5-2_gnu.tar.bz2")
+            .unwrap();
+        assert_eq!(
+            package.repository,
+            "channel-mirrors/conda-forge/noarch/zzz_openmp_mutex"
+        );
+        assert_eq!(package.media_type, PACKAGE_V1_MEDIA_TYPE);
+        assert_eq!(
+            version_build_tag("1.0+local!1-py_0=x"),
+            "1.0__p__local__e__1-py_0__eq__x"
+        );
+        assert_eq!(
+            channel.repodata_repository("noarch"),
+            "channel-mirrors/conda-forge/noarch/repodata.json"
+        );
+
+        assert!(OciChannel::parse("oci://ghcr.io").is_err());
+        assert!(OciChannel::parse("oci://ghcr.io/Org/channel").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_requests_answer_the_token_challenge() {
+        let server = MockServer::start().await.unwrap();
+        let challenge = format!(
+            r#"Bearer realm="{}/token",service="registry",scope="repository:conda/noarch/pkg:pull""#,
+            server.url()
+        );
+        server.mock(
+            "GET",
+            "/v2/conda/noarch/pkg/manifests/1.0-0",
+            MockResponse::new(401, Vec::new()).with_header("WWW-Authenticate", challenge),
+        );
+        server.mock(
+            "GET",
+            "/token",
+            MockResponse::json(200, r#"{"token": "secret"}"#),
+        );
+
+        let client = OciClient::new(
+            Client::new(),
+            server.url().to_string(),
+            Some(("user".to_string(), "password".to_string())),
+        );
+        // The mock keeps refusing, so the request fails once the token was tried
+        assert!(matches!(
+            client.manifest("conda/noarch/pkg", "1.0-0").await,
+            Err(MirrorError::AuthError(_))
+        ));
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        assert!(requests[1].path.starts_with(
+            "/token?service=registry&scope=repository%3Aconda%2Fnoarch%2Fpkg%3Apull%2Cpush"
+        ));
+        assert!(requests[1].headers["authorization"].starts_with("Basic "));
+        assert_eq!(requests[2].headers["authorization"], "Bearer secret");
+    }
+}
@@ -0,0 +1,440 @@
+//! Vulnerability lookups against OSV.dev
+//!
+//! With `vulnerabilities` configured, every package is looked up by name and
+//! version before it is fetched. Known advisories are added to the run report,
+//! and packages with an advisory at or above `block_severity` are skipped, or
+//! held for review when `action` is `quarantine`.
+//! The `scan` command runs the same lookups over a whole channel.
+//!
+//! Conda has no ecosystem of its own in OSV, so packages are looked up in
+//! `ecosystem` (PyPI by default) under their conda name; `packages` maps the
+//! names and ecosystems that differ.
+
+use comfy_table::presets::NOTHING;
+use comfy_table::{Attribute, Cell, ContentArrangement, Table};
+use futures::StreamExt;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::warn;
+
+use crate::error::{MirrorError, Result};
+use crate::policy::ViolationAction;
+use crate::sbom::ChannelPackage;
+
+/// Advisory severity, as rated by the advisory database
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Low,
+    #[serde(alias = "medium")]
+    Moderate,
+    High,
+    Critical,
+}
+
+impl Severity {
+    fn from_label(label: &str) -> Option<Self> {
+        match label.to_lowercase().as_str() {
+            "low" => Some(Self::Low),
+            "moderate" | "medium" => Some(Self::Moderate),
+            "high" => Some(Self::High),
+            "critical" => Some(Self::Critical),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = match self {
+            Self::Low => "low",
+            Self::Moderate => "moderate",
+            Self::High => "high",
+            Self::Critical => "critical",
+        };
+        f.write_str(label)
+    }
+}
+
+/// Name and ecosystem a conda package is known by in OSV
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OsvPackage {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub ecosystem: Option<String>,
+}
+
+/// OSV lookup settings
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VulnerabilityConfig {
+    #[serde(default = "default_api_url")]
+    pub api_url: String,
+    /// OSV ecosystem packages are looked up in unless mapped otherwise
+    #[serde(default = "default_ecosystem")]
+    pub ecosystem: String,
+    /// OSV name or ecosystem of conda packages that differ, e.g. `pytorch` is `torch` on PyPI
+    #[serde(default)]
+    pub packages: HashMap<String, OsvPackage>,
+    /// Skip packages with an advisory of this severity or higher; only annotate when unset
+    #[serde(default)]
+    pub block_severity: Option<Severity>,
+    /// What happens to packages blocked by `block_severity`
+    #[serde(default)]
+    pub action: ViolationAction,
+}
+
+fn default_api_url() -> String {
+    "https://api.osv.dev".to_string()
+}
+
+fn default_ecosystem() -> String {
+    "PyPI".to_string()
+}
+
+impl Default for VulnerabilityConfig {
+    fn default() -> Self {
+        Self {
+            api_url: default_api_url(),
+            ecosystem: default_ecosystem(),
+            packages: HashMap::new(),
+            block_severity: None,
+            action: ViolationAction::default(),
+        }
+    }
+}
+
+/// A published vulnerability affecting a package version
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Advisory {
+    /// OSV identifier, e.g. `GHSA-xxxx-xxxx-xxxx` or `PYSEC-2024-1`
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub severity: Option<Severity>,
+    /// Other identifiers of the same vulnerability, e.g. CVE numbers
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+}
+
+impl Advisory {
+    /// Read an advisory from an OSV vulnerability record
+    fn from_osv(vuln: &Value) -> Option<Self> {
+        let severity = std::iter::once(&vuln["database_specific"]["severity"])
+            .chain(
+                vuln["affected"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .map(|affected| &affected["ecosystem_specific"]["severity"]),
+            )
+            .filter_map(|label| label.as_str().and_then(Severity::from_label))
+            .max();
+
+        Some(Self {
+            id: vuln["id"].as_str()?.to_string(),
+            summary: vuln["summary"].as_str().map(str::to_string),
+            severity,
+            aliases: vuln["aliases"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|alias| alias.as_str().map(str::to_string))
+                .collect(),
+        })
+    }
+}
+
+/// Name and version of a package from its filename, e.g. `numpy-1.26.0-py312_0.conda`
+pub fn name_and_version(filename: &str) -> Option<(&str, &str)> {
+    let stem = filename
+        .strip_suffix(".conda")
+        .or_else(|| filename.strip_suffix(".tar.bz2"))?;
+    let mut parts = stem.rsplitn(3, '-');
+    let _build = parts.next()?;
+    let version = parts.next()?;
+    let name = parts.next().filter(|name| !name.is_empty())?;
+    Some((name, version))
+}
+
+/// Queries OSV, remembering the answers for the lifetime of the client
+pub struct OsvClient {
+    client: Client,
+    config: VulnerabilityConfig,
+    cache: Mutex<HashMap<(String, String), Vec<Advisory>>>,
+}
+
+impl OsvClient {
+    pub fn new(config: &VulnerabilityConfig, timeout_seconds: u64) -> Result<Self> {
+        Ok(Self {
+            client: Client::builder()
+                .timeout(Duration::from_secs(timeout_seconds))
+                .build()?,
+            config: config.clone(),
+            cache: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Ecosystem and name a conda package is looked up under
+    fn osv_package(&self, name: &str) -> (String, String) {
+        let mapped = self.config.packages.get(name);
+        (
+            mapped
+                .and_then(|package| package.ecosystem.clone())
+                .unwrap_or_else(|| self.config.ecosystem.clone()),
+            mapped
+                .and_then(|package| package.name.clone())
+                .unwrap_or_else(|| name.to_string()),
+        )
+    }
+
+    /// Advisories affecting a version of a conda package
+    pub async fn advisories(&self, name: &str, version: &str) -> Result<Vec<Advisory>> {
+        let key = (name.to_string(), version.to_string());
+        if let Some(advisories) = self.cache.lock().unwrap().get(&key) {
+            return Ok(advisories.clone());
+        }
+
+        let (ecosystem, osv_name) = self.osv_package(name);
+        let url = format!("{}/v1/query", self.config.api_url.trim_end_matches('/'));
+        let response = self
+            .client
+            .post(&url)
+            .json(&json!({
+                "package": { "name": osv_name, "ecosystem": ecosystem },
+                "version": version,
+            }))
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(MirrorError::Http {
+                status: status.as_u16(),
+                message: format!("OSV query for {} {} failed", name, version),
+            });
+        }
+        let body: Value = response.json().await?;
+        let mut advisories: Vec<Advisory> = body["vulns"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Advisory::from_osv)
+            .collect();
+        advisories.sort_by(|a, b| b.severity.cmp(&a.severity).then(a.id.cmp(&b.id)));
+
+        self.cache.lock().unwrap().insert(key, advisories.clone());
+        Ok(advisories)
+    }
+
+    /// Advisories for the package a filename names; lookup failures are logged
+    /// and treated as no known advisories
+    pub async fn advisories_for_file(&self, filename: &str) -> Vec<Advisory> {
+        let Some((name, version)) = name_and_version(filename) else {
+            return Vec::new();
+        };
+        match self.advisories(name, version).await {
+            Ok(advisories) => advisories,
+            Err(e) => {
+                warn!("Vulnerability lookup for {} failed: {}", filename, e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// The most severe advisory at or above the blocking threshold
+    pub fn blocking<'a>(&self, advisories: &'a [Advisory]) -> Option<&'a Advisory> {
+        let threshold = self.config.block_severity?;
+        advisories
+            .iter()
+            .filter(|advisory| advisory.severity.is_some_and(|s| s >= threshold))
+            .max_by_key(|advisory| advisory.severity)
+    }
+
+    /// Whether blocked packages are held for review rather than skipped
+    pub fn quarantines(&self) -> bool {
+        self.config.action == ViolationAction::Quarantine
+    }
+}
+
+/// Advisories found for one package of a channel
+#[derive(Debug, Clone, Serialize)]
+pub struct ScanResult {
+    pub filename: String,
+    pub subdir: String,
+    pub name: String,
+    pub version: String,
+    pub advisories: Vec<Advisory>,
+}
+
+/// Look up every package of a channel, `concurrency` queries at a time
+pub async fn scan(
+    osv: &OsvClient,
+    packages: &[ChannelPackage],
+    concurrency: usize,
+) -> Result<Vec<ScanResult>> {
+    futures::stream::iter(packages)
+        .map(|package| async move {
+            Ok(ScanResult {
+                filename: package.filename.clone(),
+                subdir: package.subdir.clone(),
+                name: package.name.clone(),
+                version: package.version.clone(),
+                advisories: osv.advisories(&package.name, &package.version).await?,
+            })
+        })
+        .buffered(concurrency.max(1))
+        .collect::<Vec<Result<ScanResult>>>()
+        .await
+        .into_iter()
+        .collect()
+}
+
+/// Print scan results as yaml, json or a table of the advisories found
+pub fn print_scan(results: &[ScanResult], format: &str) -> Result<()> {
+    match format.to_lowercase().as_str() {
+        "yaml" => println!("{}", serde_yaml::to_string(results)?),
+        "json" => println!("{}", serde_json::to_string_pretty(results)?),
+        "table" => {
+            let affected: Vec<_> = results
+                .iter()
+                .filter(|result| !result.advisories.is_empty())
+                .collect();
+            if affected.is_empty() {
+                println!("No known vulnerabilities in {} packages.", results.len());
+                return Ok(());
+            }
+            let mut table = Table::new();
+            table
+                .load_preset(NOTHING)
+                .set_content_arrangement(ContentArrangement::Dynamic)
+                .set_header(
+                    ["Package", "Version", "Advisory", "Severity", "Summary"]
+                        .iter()
+                        .map(|title| Cell::new(title).add_attribute(Attribute::Bold)),
+                );
+            for result in affected {
+                for advisory in &result.advisories {
+                    table.add_row(vec![
+                        Cell::new(format!("{}/{}", result.subdir, result.name)),
+                        Cell::new(&result.version),
+                        Cell::new(&advisory.id),
+                        Cell::new(
+                            advisory
+                                .severity
+                                .map_or("unknown".to_string(), |s| s.to_string()),
+                        ),
+                        Cell::new(advisory.summary.as_deref().unwrap_or("")),
+                    ]);
+                }
+            }
+            println!("{}", table);
+        }
+        _ => {
+            return Err(MirrorError::InvalidInput(format!(
+                "Unsupported output format: {}. Supported formats: yaml, json, table",
+                format
+            )))
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{MockResponse, MockServer};
+
+    const VULNS: &str = r#"{"vulns": [
+        {"id": "PYSEC-2024-1", "summary": "Path traversal", "aliases": ["CVE-2024-1"]},
+        {"id": "GHSA-aaaa-bbbb-cccc", "summary": "Remote code execution",
+         "database_specific": {"severity": "CRITICAL"}},
+        {"id": "GHSA-dddd-eeee-ffff", "affected": [{"ecosystem_specific": {"severity": "MODERATE"}}]}
+    ]}"#;
+
+    #[test]
+    fn test_name_and_version() {
+        assert_eq!(
+            name_and_version("numpy-1.26.0-py312_0.conda"),
+            Some(("numpy", "1.26.0"))
+        );
+        assert_eq!(
+            name_and_version("python-dateutil-2.9.0-pyhd8ed1ab_0.tar.bz2"),
+            Some(("python-dateutil", "2.9.0"))
+        );
+        assert_eq!(name_and_version("README.md"), None);
+    }
+
+    #[tokio::test]
+    async fn test_advisories() {
+        let server = MockServer::start().await.unwrap();
+        server.mock("POST", "/v1/query", MockResponse::json(200, VULNS));
+
+        let config = VulnerabilityConfig {
+            api_url: server.url().to_string(),
+            packages: HashMap::from([(
+                "pytorch".to_string(),
+                OsvPackage {
+                    name: Some("torch".to_string()),
+                    ecosystem: None,
+                },
+            )]),
+            block_severity: Some(Severity::High),
+            ..VulnerabilityConfig::default()
+        };
+        let osv = OsvClient::new(&config, 10).unwrap();
+        assert_eq!(
+            osv.osv_package("pytorch"),
+            ("PyPI".to_string(), "torch".to_string())
+        );
+
+        let advisories = osv.advisories_for_file("pytorch-2.0.0-0.conda").await;
+        let ids: Vec<_> = advisories.iter().map(|a| a.id.as_str()).collect();
+        assert_eq!(
+            ids,
+            vec!["GHSA-aaaa-bbbb-cccc", "GHSA-dddd-eeee-ffff", "PYSEC-2024-1"]
+        );
+        assert_eq!(advisories[0].severity, Some(Severity::Critical));
+        assert_eq!(advisories[1].severity, Some(Severity::Moderate));
+        assert_eq!(advisories[2].aliases, vec!["CVE-2024-1"]);
+        assert_eq!(osv.blocking(&advisories).unwrap().id, "GHSA-aaaa-bbbb-cccc");
+        assert!(osv.blocking(&advisories[1..]).is_none());
+
+        // Answers are cached per name and version
+        osv.advisories_for_file("pytorch-2.0.0-1.conda").await;
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_scan() {
+        let server = MockServer::start().await.unwrap();
+        server.mock("POST", "/v1/query", MockResponse::json(200, r#"{}"#));
+        let osv = OsvClient::new(
+            &VulnerabilityConfig {
+                api_url: server.url().to_string(),
+                ..VulnerabilityConfig::default()
+            },
+            10,
+        )
+        .unwrap();
+
+        let packages: Vec<ChannelPackage> = serde_json::from_str(
+            r#"[{"name": "a", "version": "1.0", "build": "0", "subdir": "noarch"},
+                {"name": "b", "version": "2.0", "build": "0", "subdir": "linux-64"}]"#,
+        )
+        .unwrap();
+        let results = scan(&osv, &packages, 2).await.unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[1].name, "b");
+        assert!(results.iter().all(|result| result.advisories.is_empty()));
+
+        server.mock("POST", "/v1/query", MockResponse::new(500, "unavailable"));
+        let osv = OsvClient::new(&osv.config, 10).unwrap();
+        assert!(scan(&osv, &packages, 2).await.is_err());
+        assert!(osv.advisories_for_file("a-1.0-0.conda").await.is_empty());
+    }
+}
@@ -0,0 +1,629 @@
+//! Admission policy for packages written to a target
+//!
+//! A [`PolicyConfig`] lists rules every package must pass before it is
+//! uploaded: name patterns it must or must not match, version constraints,
+//! a maximum size and the platforms the target accepts. Rejected packages are
+//! skipped with the reasons in the run report and left out of the repodata.
+//! `policy check` evaluates a source without uploading anything.
+//!
+//! License rules work on license families such as `MIT`, `BSD` or `GPL3`,
+//! derived from each identifier of the package's license expression, or from
+//! its `license_family` when the expression says nothing recognizable. An
+//! expression with `OR` complies when one of its alternatives does.
+
+use comfy_table::presets::NOTHING;
+use comfy_table::{Attribute, Cell, ContentArrangement, Table};
+use futures::StreamExt;
+use rattler_conda_types::{MatchSpec, ParseStrictness, Version};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use crate::conda_package::{CondaPackageHandler, ProcessedPackage};
+use crate::error::{MirrorError, Result};
+use crate::source::SourceProvider;
+
+/// Admission rules as written in the configuration file
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyConfig {
+    /// Regular expressions; when any are given, package names must match one
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// Regular expressions; package names matching any of them are rejected
+    #[serde(default)]
+    pub deny: Vec<String>,
+    /// Match specs such as `numpy >=1.26,<2`; packages of that name must satisfy them
+    #[serde(default)]
+    pub versions: Vec<String>,
+    /// Largest package accepted, in bytes
+    #[serde(default)]
+    pub max_size: Option<u64>,
+    /// When any are given, packages must target one of these platforms (e.g. `noarch`, `linux-64`)
+    #[serde(default)]
+    pub platforms: Vec<String>,
+    /// License families allowed or forbidden at the target
+    #[serde(default)]
+    pub licenses: Option<LicensePolicy>,
+    /// What happens to packages that violate the rules above other than `licenses`
+    #[serde(default)]
+    pub action: ViolationAction,
+}
+
+/// License compliance rules
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LicensePolicy {
+    /// When any are given, packages must be licensed under one of these families
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// Packages under any of these families are refused
+    #[serde(default)]
+    pub deny: Vec<String>,
+    /// What happens to packages that violate the license rules
+    #[serde(default)]
+    pub action: ViolationAction,
+}
+
+/// How a package violating policy, license or vulnerability rules is handled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ViolationAction {
+    /// Skip the package
+    #[default]
+    Reject,
+    /// Skip the package and hold a copy in the quarantine directory for `review`
+    Quarantine,
+}
+
+/// Family assigned to packages that declare no license at all
+pub const NO_LICENSE: &str = "NONE";
+
+/// License family of one SPDX-like identifier, e.g. `GPL3` for `GPL-3.0-or-later`
+pub fn license_family(identifier: &str) -> &'static str {
+    let id = identifier.to_uppercase().replace(['-', '_', ' ', '.'], "");
+    let families: [(&str, &[&str]); 11] = [
+        ("AGPL", &["AGPL"]),
+        ("LGPL", &["LGPL"]),
+        ("GPL3", &["GPL3"]),
+        ("GPL2", &["GPL2"]),
+        ("GPL", &["GPL"]),
+        ("MIT", &["MIT", "X11", "EXPAT"]),
+        ("BSD", &["BSD", "0BSD", "ISC"]),
+        ("APACHE", &["APACHE"]),
+        ("PSF", &["PSF", "PYTHON"]),
+        ("MOZILLA", &["MPL", "MOZILLA"]),
+        (
+            "PUBLIC-DOMAIN",
+            &["PUBLICDOMAIN", "UNLICENSE", "CC0", "WTFPL"],
+        ),
+    ];
+    if id.starts_with("LICENSEREF") || id.contains("PROPRIETARY") {
+        return "PROPRIETARY";
+    }
+    families
+        .iter()
+        .find(|(_, prefixes)| prefixes.iter().any(|prefix| id.starts_with(prefix)))
+        .map_or("OTHER", |(family, _)| family)
+}
+
+/// License families of a package, one list per `OR` alternative
+///
+/// Every family of an alternative applies at once, e.g. `MIT AND BSD-3-Clause`.
+pub fn license_alternatives(license: Option<&str>, family: Option<&str>) -> Vec<Vec<String>> {
+    let declared_family = family
+        .map(|family| family.trim().to_uppercase())
+        .filter(|family| !family.is_empty());
+    let Some(license) = license.map(str::trim).filter(|license| !license.is_empty()) else {
+        return vec![vec![
+            declared_family.unwrap_or_else(|| NO_LICENSE.to_string())
+        ]];
+    };
+
+    let alternatives: Vec<Vec<String>> = license
+        .replace(['(', ')'], " ")
+        .split(" OR ")
+        .map(|alternative| {
+            let mut families: Vec<String> = alternative
+                .split(" AND ")
+                // The exception of `X WITH exception` does not change the family
+                .filter_map(|term| term.split(" WITH ").next())
+                .map(|term| license_family(term.trim()).to_string())
+                .collect();
+            families.dedup();
+            families
+        })
+        .collect();
+
+    match (declared_family, alternatives.as_slice()) {
+        (Some(declared), [only]) if only.iter().all(|family| family == "OTHER") => {
+            vec![vec![declared]]
+        }
+        _ => alternatives,
+    }
+}
+
+/// Compiled admission rules
+#[derive(Debug, Clone)]
+pub struct Policy {
+    allow: Vec<Regex>,
+    /// Patterns as written, for the rejection reason, and compiled
+    deny: Vec<(String, Regex)>,
+    versions: Vec<MatchSpec>,
+    max_size: Option<u64>,
+    platforms: Vec<String>,
+    licenses: Option<LicensePolicy>,
+    action: ViolationAction,
+}
+
+/// Whole-name regex, so `numpy` does not also admit `numpy-stubs`
+fn name_pattern(pattern: &str) -> Result<Regex> {
+    Regex::new(&format!("^(?:{})$", pattern)).map_err(|e| {
+        MirrorError::InvalidInput(format!("Invalid policy pattern '{}': {}", pattern, e))
+    })
+}
+
+impl Policy {
+    pub fn new(config: &PolicyConfig) -> Result<Self> {
+        let versions = config
+            .versions
+            .iter()
+            .map(|spec| {
+                let parsed = MatchSpec::from_str(spec, ParseStrictness::Lenient).map_err(|e| {
+                    MirrorError::InvalidInput(format!(
+                        "Invalid policy version constraint '{}': {}",
+                        spec, e
+                    ))
+                })?;
+                if parsed.name.is_none() || parsed.version.is_none() {
+                    return Err(MirrorError::InvalidInput(format!(
+                        "Policy version constraint '{}' needs a package name and a version",
+                        spec
+                    )));
+                }
+                Ok(parsed)
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            allow: config
+                .allow
+                .iter()
+                .map(|p| name_pattern(p))
+                .collect::<Result<_>>()?,
+            deny: config
+                .deny
+                .iter()
+                .map(|p| Ok((p.clone(), name_pattern(p)?)))
+                .collect::<Result<_>>()?,
+            versions,
+            max_size: config.max_size,
+            platforms: config.platforms.clone(),
+            licenses: config.licenses.as_ref().map(|licenses| LicensePolicy {
+                allow: licenses.allow.iter().map(|f| f.to_uppercase()).collect(),
+                deny: licenses.deny.iter().map(|f| f.to_uppercase()).collect(),
+                action: licenses.action,
+            }),
+            action: config.action,
+        })
+    }
+
+    /// Whether a rule the package violates asks for it to be held for review
+    pub fn quarantines(&self, package: &ProcessedPackage) -> bool {
+        let license_violation = self.license_violation(package).is_some();
+        let license_quarantine = self
+            .licenses
+            .as_ref()
+            .is_some_and(|licenses| licenses.action == ViolationAction::Quarantine);
+        let other_violations = self.violations(package).len() > usize::from(license_violation);
+        (license_violation && license_quarantine)
+            || (other_violations && self.action == ViolationAction::Quarantine)
+    }
+
+    /// Why the package's license is not acceptable, if it is not
+    pub fn license_violation(&self, package: &ProcessedPackage) -> Option<String> {
+        let licenses = self.licenses.as_ref()?;
+        let alternatives = license_alternatives(
+            package.metadata.license.as_deref(),
+            package.metadata.license_family.as_deref(),
+        );
+        let complies = |families: &Vec<String>| {
+            families.iter().all(|family| {
+                !licenses.deny.contains(family)
+                    && (licenses.allow.is_empty() || licenses.allow.contains(family))
+            })
+        };
+        if alternatives.iter().any(complies) {
+            return None;
+        }
+
+        let families = alternatives
+            .iter()
+            .map(|families| families.join(" AND "))
+            .collect::<Vec<_>>()
+            .join(" OR ");
+        Some(format!(
+            "license {} (family {}) is not allowed",
+            package
+                .metadata
+                .license
+                .as_deref()
+                .unwrap_or("not declared"),
+            families
+        ))
+    }
+
+    /// Whether the package is larger than the policy allows
+    pub fn exceeds_max_size(&self, package: &ProcessedPackage) -> bool {
+        self.max_size.is_some_and(|max| package.size > max)
+    }
+
+    /// Reasons the package is rejected; empty when it is admitted
+    pub fn violations(&self, package: &ProcessedPackage) -> Vec<String> {
+        let name = &package.metadata.name;
+        let mut reasons = Vec::new();
+
+        if !self.allow.is_empty() && !self.allow.iter().any(|p| p.is_match(name)) {
+            reasons.push(format!("{} is not on the allowlist", name));
+        }
+        if let Some((pattern, _)) = self.deny.iter().find(|(_, p)| p.is_match(name)) {
+            reasons.push(format!("{} matches denylist pattern '{}'", name, pattern));
+        }
+
+        for spec in &self.versions {
+            let (Some(spec_name), Some(version_spec)) = (&spec.name, &spec.version) else {
+                continue;
+            };
+            if spec_name.as_normalized() != name.to_lowercase() {
+                continue;
+            }
+            let satisfied = Version::from_str(&package.metadata.version)
+                .map(|version| version_spec.matches(&version))
+                .unwrap_or(false);
+            if !satisfied {
+                reasons.push(format!(
+                    "version {} does not satisfy {} {}",
+                    package.metadata.version,
+                    spec_name.as_normalized(),
+                    version_spec
+                ));
+            }
+        }
+
+        if let (true, Some(max_size)) = (self.exceeds_max_size(package), self.max_size) {
+            reasons.push(format!(
+                "size {} bytes exceeds the maximum of {} bytes",
+                package.size, max_size
+            ));
+        }
+
+        let platform = package.platform.as_str();
+        if !self.platforms.is_empty() && !self.platforms.iter().any(|p| p == platform) {
+            reasons.push(format!(
+                "platform {} is not one of {}",
+                platform,
+                self.platforms.join(", ")
+            ));
+        }
+
+        reasons.extend(self.license_violation(package));
+
+        reasons
+    }
+}
+
+/// How the policy judges one package of a source
+#[derive(Debug, Clone, Serialize)]
+pub struct PolicyVerdict {
+    pub filename: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub platform: Option<String>,
+    pub size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    pub admitted: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub reasons: Vec<String>,
+}
+
+/// Fetch every package of a source and evaluate the policy without uploading
+///
+/// Packages that cannot be read are reported as rejected, as a mirror run
+/// would fail them.
+pub async fn check(provider: &dyn SourceProvider, policy: &Policy) -> Result<Vec<PolicyVerdict>> {
+    let mut handler = CondaPackageHandler::new();
+    let mut entries = provider.entries().await?;
+    let mut verdicts = Vec::new();
+
+    while let Some(entry) = entries.next().await {
+        let entry = match entry {
+            Ok(entry) => entry,
+            // Items the source left out never reach the policy
+            Err(MirrorError::Skipped(_)) => continue,
+            Err(e) => return Err(e),
+        };
+        let content = match entry.fetch.await {
+            Ok(content) => content,
+            Err(e) => {
+                verdicts.push(PolicyVerdict {
+                    filename: entry.name,
+                    platform: None,
+                    size: 0,
+                    license: None,
+                    admitted: false,
+                    reasons: vec![format!("unreadable package: {}", e)],
+                });
+                continue;
+            }
+        };
+        let size = content.len() as u64;
+        let verdict = match handler.process_package(content, &entry.name).await {
+            Ok(package) => {
+                let reasons = policy.violations(&package);
+                PolicyVerdict {
+                    filename: entry.name,
+                    platform: Some(package.platform.to_string()),
+                    size,
+                    license: package.metadata.license.clone(),
+                    admitted: reasons.is_empty(),
+                    reasons,
+                }
+            }
+            Err(e) => PolicyVerdict {
+                filename: entry.name,
+                platform: None,
+                size,
+                license: None,
+                admitted: false,
+                reasons: vec![format!("unreadable package: {}", e)],
+            },
+        };
+        verdicts.push(verdict);
+    }
+
+    Ok(verdicts)
+}
+
+/// Print policy verdicts as yaml, json or a table
+pub fn print_verdicts(verdicts: &[PolicyVerdict], format: &str) -> Result<()> {
+    match format.to_lowercase().as_str() {
+        "yaml" => println!("{}", serde_yaml::to_string(verdicts)?),
+        "json" => println!("{}", serde_json::to_string_pretty(verdicts)?),
+        "table" => {
+            let mut table = Table::new();
+            table
+                .load_preset(NOTHING)
+                .set_content_arrangement(ContentArrangement::Dynamic)
+                .set_header(
+                    [
+                        "Package", "Platform", "Size", "License", "Verdict", "Reasons",
+                    ]
+                    .iter()
+                    .map(|title| Cell::new(title).add_attribute(Attribute::Bold)),
+                );
+            for verdict in verdicts {
+                table.add_row(vec![
+                    Cell::new(&verdict.filename),
+                    Cell::new(verdict.platform.as_deref().unwrap_or("")),
+                    Cell::new(verdict.size),
+                    Cell::new(verdict.license.as_deref().unwrap_or("")),
+                    Cell::new(if verdict.admitted { "admit" } else { "reject" }),
+                    Cell::new(verdict.reasons.join("; ")),
+                ]);
+            }
+            println!("{}", table);
+        }
+        _ => {
+            return Err(MirrorError::InvalidInput(format!(
+                "Unsupported output format: {}. Supported formats: yaml, json, table",
+                format
+            )))
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::PackageEntry;
+    use crate::test_support::PackageFixture;
+    use futures::stream;
+
+    async fn package(name: &str, version: &str, subdir: &str) -> ProcessedPackage {
+        let fixture = PackageFixture::new(name, version).subdir(subdir);
+        CondaPackageHandler::new()
+            .process_package(fixture.to_conda(), &fixture.conda_filename())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_violations() {
+        let policy = Policy::new(&PolicyConfig {
+            allow: vec!["numpy".to_string(), "py.*".to_string()],
+            deny: vec!["pytest.*".to_string()],
+            versions: vec!["numpy >=1.26,<2".to_string()],
+            max_size: None,
+            platforms: vec!["linux-64".to_string(), "noarch".to_string()],
+            licenses: None,
+            action: ViolationAction::Reject,
+        })
+        .unwrap();
+
+        assert!(policy
+            .violations(&package("numpy", "1.26.4", "linux-64").await)
+            .is_empty());
+        assert!(policy
+            .violations(&package("pyyaml", "6.0", "noarch").await)
+            .is_empty());
+
+        let reasons = policy.violations(&package("numpy", "2.0.0", "osx-arm64").await);
+        assert_eq!(
+            reasons,
+            vec![
+                "version 2.0.0 does not satisfy numpy >=1.26,<2".to_string(),
+                "platform osx-arm64 is not one of linux-64, noarch".to_string(),
+            ]
+        );
+
+        // Patterns match whole names
+        let reasons = policy.violations(&package("numpy-stubs", "1.0", "noarch").await);
+        assert_eq!(reasons, vec!["numpy-stubs is not on the allowlist"]);
+        let reasons = policy.violations(&package("pytest", "8.0", "noarch").await);
+        assert_eq!(reasons, vec!["pytest matches denylist pattern 'pytest.*'"]);
+
+        let small = Policy::new(&PolicyConfig {
+            max_size: Some(10),
+            ..PolicyConfig::default()
+        })
+        .unwrap();
+        let reasons = small.violations(&package("numpy", "1.26.4", "linux-64").await);
+        assert_eq!(reasons.len(), 1);
+        assert!(reasons[0].contains("exceeds the maximum of 10 bytes"));
+    }
+
+    #[test]
+    fn test_license_alternatives() {
+        let families = |license, family| license_alternatives(license, family);
+        assert_eq!(families(Some("MIT"), None), vec![vec!["MIT"]]);
+        assert_eq!(
+            families(Some("GPL-3.0-or-later WITH GCC-exception-3.1"), None),
+            vec![vec!["GPL3"]]
+        );
+        assert_eq!(
+            families(Some("(MIT OR Apache-2.0) AND BSD-3-Clause"), None),
+            vec![vec!["MIT"], vec!["APACHE", "BSD"]]
+        );
+        assert_eq!(families(Some("LGPL-2.1-only"), None), vec![vec!["LGPL"]]);
+        assert_eq!(
+            families(Some("LicenseRef-Proprietary"), None),
+            vec![vec!["PROPRIETARY"]]
+        );
+        // The recipe's family stands in for expressions that are not recognized
+        assert_eq!(
+            families(Some("Custom license"), Some("bsd")),
+            vec![vec!["BSD"]]
+        );
+        assert_eq!(families(None, Some("PSF")), vec![vec!["PSF"]]);
+        assert_eq!(families(None, None), vec![vec![NO_LICENSE]]);
+    }
+
+    #[tokio::test]
+    async fn test_license_violation() {
+        let licensed = |license: &'static str| async move {
+            let fixture = PackageFixture::new("pkg", "1.0").license(license);
+            CondaPackageHandler::new()
+                .process_package(fixture.to_conda(), &fixture.conda_filename())
+                .await
+                .unwrap()
+        };
+        let policy = Policy::new(&PolicyConfig {
+            licenses: Some(LicensePolicy {
+                allow: vec!["mit".to_string(), "bsd".to_string(), "apache".to_string()],
+                deny: vec!["GPL3".to_string()],
+                action: ViolationAction::Quarantine,
+            }),
+            ..PolicyConfig::default()
+        })
+        .unwrap();
+        assert!(policy.quarantines(&licensed("MPL-2.0").await));
+        assert!(!policy.quarantines(&licensed("MIT").await));
+
+        assert!(policy.license_violation(&licensed("MIT").await).is_none());
+        assert!(policy
+            .license_violation(&licensed("GPL-3.0-only OR MIT").await)
+            .is_none());
+        assert_eq!(
+            policy
+                .license_violation(&licensed("MIT AND GPL-3.0-only").await)
+                .unwrap(),
+            "license MIT AND GPL-3.0-only (family MIT AND GPL3) is not allowed"
+        );
+        assert_eq!(
+            policy.violations(&licensed("MPL-2.0").await),
+            vec!["license MPL-2.0 (family MOZILLA) is not allowed"]
+        );
+        let fixture = PackageFixture::new("pkg", "1.0")
+            .license("Custom license")
+            .license_family("BSD");
+        let custom = CondaPackageHandler::new()
+            .process_package(fixture.to_conda(), &fixture.conda_filename())
+            .await
+            .unwrap();
+        assert!(policy.license_violation(&custom).is_none());
+        assert_eq!(
+            policy.violations(&package("pkg", "1.0", "noarch").await),
+            vec!["license not declared (family NONE) is not allowed"]
+        );
+
+        // Other rules are rejected unless the policy's own action says otherwise
+        let mut config = PolicyConfig {
+            deny: vec!["pkg".to_string()],
+            ..PolicyConfig::default()
+        };
+        assert!(!Policy::new(&config)
+            .unwrap()
+            .quarantines(&licensed("MIT").await));
+        config.action = ViolationAction::Quarantine;
+        assert!(Policy::new(&config)
+            .unwrap()
+            .quarantines(&licensed("MIT").await));
+    }
+
+    #[test]
+    fn test_invalid_policy() {
+        let invalid = |config: PolicyConfig| Policy::new(&config).unwrap_err().to_string();
+        assert!(invalid(PolicyConfig {
+            deny: vec!["(".to_string()],
+            ..PolicyConfig::default()
+        })
+        .contains("Invalid policy pattern"));
+        assert!(invalid(PolicyConfig {
+            versions: vec!["numpy".to_string()],
+            ..PolicyConfig::default()
+        })
+        .contains("needs a package name and a version"));
+    }
+
+    struct Fixtures(Vec<PackageFixture>);
+
+    #[async_trait::async_trait]
+    impl SourceProvider for Fixtures {
+        fn name(&self) -> &str {
+            "fixtures"
+        }
+
+        async fn entries(&self) -> Result<crate::source::PackageStream> {
+            let entries: Vec<Result<PackageEntry>> = self
+                .0
+                .iter()
+                .map(|fixture| {
+                    Ok(PackageEntry::ready(
+                        fixture.conda_filename(),
+                        fixture.to_conda(),
+                    ))
+                })
+                .collect();
+            Ok(Box::pin(stream::iter(entries)))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check() {
+        let provider = Fixtures(vec![
+            PackageFixture::new("numpy", "1.26.4").subdir("linux-64"),
+            PackageFixture::new("pytest", "8.0").subdir("noarch"),
+        ]);
+        let policy = Policy::new(&PolicyConfig {
+            deny: vec!["pytest".to_string()],
+            ..PolicyConfig::default()
+        })
+        .unwrap();
+
+        let verdicts = check(&provider, &policy).await.unwrap();
+        assert_eq!(verdicts.len(), 2);
+        assert!(verdicts[0].admitted);
+        assert_eq!(verdicts[0].platform.as_deref(), Some("linux-64"));
+        assert!(!verdicts[1].admitted);
+        assert_eq!(verdicts[1].reasons.len(), 1);
+    }
+}
@@ -0,0 +1,248 @@
+//! Polite pacing of requests to upstream hosts
+//!
+//! Mirroring from community infrastructure such as anaconda.org should not look
+//! like a crawler gone wrong. Every client identifies itself with a
+//! `User-Agent` naming the tool and, when `user_agent_contact` is set, how to
+//! reach whoever runs the mirror. Package downloads and channel index reads are
+//! paced per host: at most `max_connections_per_host` requests run at once, and
+//! consecutive requests start at least `crawl_delay_ms` apart. A run can also
+//! be held to `max_bytes_per_second` of package downloads. Caps across all
+//! runs of a process are in [`crate::limits`].
+
+use reqwest::Client;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tokio::time::Instant;
+
+use crate::auth;
+use crate::config::Config;
+use crate::error::Result;
+use crate::limits;
+
+/// `User-Agent` sent with every request
+pub fn user_agent(config: &Config) -> String {
+    let agent = format!("meso-forge-mirror/{}", env!("CARGO_PKG_VERSION"));
+    match &config.user_agent_contact {
+        Some(contact) => format!("{} (+{})", agent, contact),
+        None => agent,
+    }
+}
+
+/// HTTP client with the configured timeout and `User-Agent`
+///
+/// It also sets up the pacing of channel index reads made in this process, and
+/// the credentials they send to private channels.
+pub fn client(config: &Config) -> Result<Client> {
+    shared(config);
+    auth::shared(config);
+    Ok(Client::builder()
+        .timeout(Duration::from_secs(config.timeout_seconds))
+        .user_agent(user_agent(config))
+        .build()?)
+}
+
+#[derive(Debug)]
+struct HostSlot {
+    connections: Option<Semaphore>,
+    next_request: tokio::sync::Mutex<Option<Instant>>,
+}
+
+/// Limits concurrent requests per host and spaces them out
+#[derive(Debug)]
+pub struct HostThrottle {
+    delay: Duration,
+    max_connections: usize,
+    hosts: Mutex<HashMap<String, Arc<HostSlot>>>,
+}
+
+impl HostThrottle {
+    /// `max_connections` of 0 leaves concurrency unlimited
+    pub fn new(delay: Duration, max_connections: usize) -> Self {
+        Self {
+            delay,
+            max_connections,
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn slot(&self, url: &str) -> Arc<HostSlot> {
+        let host = url::Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_else(|| url.to_string());
+        let mut hosts = self.hosts.lock().unwrap();
+        Arc::clone(hosts.entry(host).or_insert_with(|| {
+            Arc::new(HostSlot {
+                connections: (self.max_connections > 0)
+                    .then(|| Semaphore::new(self.max_connections)),
+                next_request: tokio::sync::Mutex::new(None),
+            })
+        }))
+    }
+
+    /// Run a request against `url` once its host has a free connection and the crawl delay passed
+    pub async fn guard<F, Fut, T>(&self, url: &str, operation: F) -> T
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        let _request = match limits::current() {
+            Some(limits) => limits.request().await,
+            None => None,
+        };
+        if self.delay.is_zero() && self.max_connections == 0 {
+            return operation().await;
+        }
+
+        let slot = self.slot(url);
+        let _permit = match &slot.connections {
+            Some(connections) => Some(connections.acquire().await.expect("never closed")),
+            None => None,
+        };
+        if !self.delay.is_zero() {
+            let mut next_request = slot.next_request.lock().await;
+            if let Some(at) = *next_request {
+                tokio::time::sleep_until(at).await;
+            }
+            *next_request = Some(Instant::now() + self.delay);
+        }
+        operation().await
+    }
+}
+
+/// Holds the packages fetched by one run to an average transfer rate
+///
+/// The rate is averaged over the run: after a package arrives, the consumer
+/// waits until the bytes fetched so far fit the limit. Packages fetched ahead
+/// of it are thereby held back as well.
+#[derive(Debug)]
+pub struct BandwidthLimit {
+    bytes_per_second: u64,
+    started: Instant,
+    transferred: u64,
+}
+
+impl BandwidthLimit {
+    /// `bytes_per_second` of 0 leaves the rate unlimited
+    pub fn new(bytes_per_second: u64) -> Self {
+        Self {
+            bytes_per_second,
+            started: Instant::now(),
+            transferred: 0,
+        }
+    }
+
+    /// Account for `bytes` fetched, waiting until they are within the limit
+    pub async fn consume(&mut self, bytes: u64) {
+        if self.bytes_per_second == 0 {
+            return;
+        }
+        self.transferred += bytes;
+        let due = Duration::from_secs_f64(self.transferred as f64 / self.bytes_per_second as f64);
+        tokio::time::sleep_until(self.started + due).await;
+    }
+}
+
+static THROTTLE: OnceLock<HostThrottle> = OnceLock::new();
+
+/// Throttle shared by every request made in this process
+///
+/// Like the circuit breaker, it is created from the first configuration that
+/// asks for it, so all sources of a run share the limits of a host.
+pub fn shared(config: &Config) -> &'static HostThrottle {
+    THROTTLE.get_or_init(|| {
+        HostThrottle::new(
+            Duration::from_millis(config.crawl_delay_ms),
+            config.max_connections_per_host,
+        )
+    })
+}
+
+/// Run a request through the shared throttle, or directly if none was set up
+pub async fn throttled<F, Fut, T>(url: &str, operation: F) -> T
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = T>,
+{
+    match (THROTTLE.get(), limits::current()) {
+        (Some(throttle), _) => throttle.guard(url, operation).await,
+        (None, Some(limits)) => {
+            let _request = limits.request().await;
+            operation().await
+        }
+        (None, None) => operation().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_user_agent() {
+        let config = Config {
+            user_agent_contact: Some("https://example.org/mirror".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            user_agent(&config),
+            format!(
+                "meso-forge-mirror/{} (+https://example.org/mirror)",
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+        assert!(!user_agent(&Config::default()).contains('+'));
+    }
+
+    #[tokio::test]
+    async fn test_guard_spaces_requests_to_a_host() {
+        let throttle = HostThrottle::new(Duration::from_millis(50), 0);
+        let started = std::time::Instant::now();
+        for _ in 0..3 {
+            throttle
+                .guard("https://conda.anaconda.org/conda-forge", || async {})
+                .await;
+        }
+        assert!(started.elapsed() >= Duration::from_millis(100));
+
+        // Other hosts are not held back
+        let started = std::time::Instant::now();
+        throttle.guard("https://example.org/pkg", || async {}).await;
+        assert!(started.elapsed() < Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn test_bandwidth_limit() {
+        let started = std::time::Instant::now();
+        let mut limit = BandwidthLimit::new(10_000);
+        limit.consume(500).await;
+        limit.consume(500).await;
+        assert!(started.elapsed() >= Duration::from_millis(100));
+
+        let started = std::time::Instant::now();
+        BandwidthLimit::new(0).consume(u64::MAX).await;
+        assert!(started.elapsed() < Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn test_guard_caps_connections_per_host() {
+        let throttle = HostThrottle::new(Duration::ZERO, 2);
+        let running = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        let request = || async {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            peak.fetch_max(now, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            running.fetch_sub(1, Ordering::SeqCst);
+        };
+        futures::future::join_all(
+            (0..6).map(|_| throttle.guard("https://conda.anaconda.org/a", request)),
+        )
+        .await;
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+    }
+}
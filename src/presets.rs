@@ -0,0 +1,85 @@
+//! Named filter presets for common CI artifact layouts
+//!
+//! Selecting the packages of a CI run takes two regular expressions: one for
+//! the artifact names and one for the package paths inside each artifact.
+//! `filter_preset` (or `--filter-preset`) supplies both for well-known
+//! layouts, such as the `conda_artifacts_*` artifacts of conda-forge
+//! feedstocks, so they need not be worked out from artifact listings. An
+//! explicit `--src-path` takes precedence over the preset.
+
+use crate::error::{MirrorError, Result};
+use crate::suggest;
+
+/// Artifact name and package path patterns of a CI layout
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterPreset {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Artifacts to mirror from; every artifact when unset
+    pub artifacts: Option<&'static str>,
+    /// Paths of the packages inside an artifact, ZIP file or tarball
+    pub paths: &'static str,
+}
+
+/// The built-in presets
+pub const PRESETS: &[FilterPreset] = &[
+    FilterPreset {
+        name: "conda-forge",
+        description: "conda-forge feedstocks: conda_artifacts_* and conda_pkgs_* artifacts holding build_artifacts/<subdir>/ packages",
+        artifacts: Some(r"^conda_(artifacts|pkgs)_"),
+        paths: r"(^|/)(noarch|[a-z0-9]+-[a-z0-9_]+)/[^/]+\.(conda|tar\.bz2)$",
+    },
+    FilterPreset {
+        name: "rattler-build",
+        description: "rattler-build output directories: output/<subdir>/ packages in any artifact",
+        artifacts: None,
+        paths: r"(^|/)output/(noarch|[a-z0-9]+-[a-z0-9_]+)/[^/]+\.(conda|tar\.bz2)$",
+    },
+];
+
+/// The preset called `name`
+pub fn find(name: &str) -> Result<&'static FilterPreset> {
+    if let Some(preset) = PRESETS.iter().find(|preset| preset.name == name) {
+        return Ok(preset);
+    }
+    let mut message = format!("Unknown filter preset '{}'", name);
+    let suggestions = suggest::similar(name, PRESETS.iter().map(|preset| preset.name));
+    if let Some(hint) = suggest::did_you_mean(&suggestions) {
+        message.push_str(&format!("; {}", hint));
+    }
+    message.push_str(". Available presets:");
+    for preset in PRESETS {
+        message.push_str(&format!("\n  {}: {}", preset.name, preset.description));
+    }
+    Err(MirrorError::InvalidInput(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    #[test]
+    fn test_conda_forge_preset() {
+        let preset = find("conda-forge").unwrap();
+        let artifacts = Regex::new(preset.artifacts.unwrap()).unwrap();
+        assert!(artifacts.is_match("conda_artifacts_20240101.1_linux_64_python3.12"));
+        assert!(artifacts.is_match("conda_pkgs_win"));
+        assert!(!artifacts.is_match("conda_envs_linux_64"));
+
+        let paths = Regex::new(preset.paths).unwrap();
+        assert!(paths.is_match("build_artifacts/linux-64/numpy-1.26.0-py312_0.conda"));
+        assert!(paths.is_match("build_artifacts/noarch/tzdata-2024a-0.tar.bz2"));
+        assert!(paths.is_match("osx-arm64/numpy-1.26.0-py312_0.conda"));
+        // The package cache of the build environment is not the build output
+        assert!(!paths.is_match("pkgs/python-3.12.0-0.conda"));
+        assert!(!paths.is_match("build_artifacts/linux-64/repodata.json"));
+    }
+
+    #[test]
+    fn test_unknown_preset_lists_the_presets() {
+        let err = find("conda-froge").unwrap_err().to_string();
+        assert!(err.contains("did you mean conda-forge?"));
+        assert!(err.contains("rattler-build:"));
+    }
+}
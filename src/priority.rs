@@ -0,0 +1,108 @@
+//! Preemption of daemon jobs by jobs of higher priority
+//!
+//! A security-patch mirror should not wait for a bulk sync that happens to run
+//! at the same time. Every running job holds a [`Running`] guard for its
+//! priority, and mirroring loops call [`yield_to_higher`] between packages:
+//! while a job of higher priority runs, lower ones pause after the package in
+//! flight and continue once it is done. Jobs are registered only after taking
+//! their target's turn, so a paused job never holds up a higher one waiting for
+//! the same target.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use tokio::sync::Notify;
+use tracing::info;
+
+use crate::shutdown;
+
+/// Running jobs by priority
+static RUNNING: Mutex<BTreeMap<i32, usize>> = Mutex::new(BTreeMap::new());
+static CHANGED: Notify = Notify::const_new();
+
+/// Marks a job of some priority as running until it is dropped
+#[cfg_attr(not(feature = "schedule"), allow(dead_code))]
+#[derive(Debug)]
+pub struct Running {
+    priority: i32,
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        let mut running = RUNNING.lock().unwrap();
+        if let Some(count) = running.get_mut(&self.priority) {
+            *count -= 1;
+            if *count == 0 {
+                running.remove(&self.priority);
+            }
+        }
+        drop(running);
+        CHANGED.notify_waiters();
+    }
+}
+
+/// Register a running job of `priority`
+#[cfg_attr(not(feature = "schedule"), allow(dead_code))]
+pub fn start(priority: i32) -> Running {
+    *RUNNING.lock().unwrap().entry(priority).or_default() += 1;
+    Running { priority }
+}
+
+/// Whether a job of higher priority than `priority` is running
+pub fn is_preempted(priority: i32) -> bool {
+    RUNNING
+        .lock()
+        .unwrap()
+        .range(priority.saturating_add(1)..)
+        .next()
+        .is_some()
+}
+
+/// Wait while jobs of higher priority than `priority` run, or until a shutdown is requested
+pub async fn yield_to_higher(priority: i32) {
+    if !is_preempted(priority) {
+        return;
+    }
+    info!(
+        "Pausing: a job of higher priority than {} is running",
+        priority
+    );
+    loop {
+        let changed = CHANGED.notified();
+        if !is_preempted(priority) || shutdown::is_requested() {
+            return;
+        }
+        tokio::select! {
+            _ = changed => {}
+            _ = shutdown::requested() => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_lower_priority_waits_for_higher() {
+        // Priorities no other test registers, as the registry is process-wide
+        let low = start(-100);
+        assert!(!is_preempted(-100));
+
+        let high = start(-90);
+        assert!(is_preempted(-100));
+        assert!(!is_preempted(-90));
+
+        let waiting = tokio::spawn(yield_to_higher(-100));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiting.is_finished());
+
+        drop(high);
+        tokio::time::timeout(Duration::from_secs(1), waiting)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!is_preempted(-100));
+        drop(low);
+    }
+}
@@ -0,0 +1,237 @@
+//! Promotion of packages from a staging target to production
+//!
+//! Packages built by a pull request are commonly mirrored to a staging
+//! channel first, where they are installed and tested before anyone relies on
+//! them. The `promote` command copies the packages the staging repodata lists
+//! into a production target. Every selected package is read back from staging
+//! and checked against the sha256 its repodata records and against the
+//! admission policy; only when all of them pass are they mirrored into
+//! production, whose repodata, signatures and manifest are updated as for any
+//! other run. With a state database, each promoted package is recorded along
+//! with the staging target and an optional label, e.g. `stable`.
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::{self, StreamExt};
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use tracing::info;
+
+use crate::config::Config;
+use crate::error::{MirrorError, Result};
+use crate::mirror::mirror_from_provider;
+use crate::policy::{self, Policy, PolicyVerdict};
+use crate::report::MirrorReport;
+use crate::repository::{listed_packages, ListedPackage, Repository, RepositoryBackend};
+use crate::source::{PackageEntry, PackageStream, SourceProvider};
+#[cfg(feature = "state-db")]
+use crate::state::StateDb;
+use crate::suggest;
+
+/// The packages of a staging target, as a source for a mirror run into production
+pub struct StagingSource {
+    name: String,
+    backend: Arc<dyn RepositoryBackend>,
+    /// Only packages whose name matches are promoted
+    package: Option<Regex>,
+}
+
+impl StagingSource {
+    pub fn new(staging: &Repository, package: Option<Regex>) -> Self {
+        Self {
+            name: staging.path.clone(),
+            backend: staging.backend(),
+            package,
+        }
+    }
+}
+
+#[async_trait]
+impl SourceProvider for StagingSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn entries(&self) -> Result<PackageStream> {
+        let selected: Vec<ListedPackage> = listed_packages(self.backend.as_ref())
+            .await?
+            .into_iter()
+            .filter(|staged| {
+                self.package
+                    .as_ref()
+                    .is_none_or(|package| package.is_match(suggest::package_name(&staged.filename)))
+            })
+            .collect();
+        if selected.is_empty() {
+            let mut message = format!("No packages in the repodata of {}", self.name);
+            if let Some(package) = &self.package {
+                message.push_str(&format!(" match '{}'", package));
+            }
+            return Err(MirrorError::NotFound(message));
+        }
+        info!("Promoting {} packages of {}", selected.len(), self.name);
+
+        let entries: Vec<Result<PackageEntry>> = selected
+            .into_iter()
+            .map(|staged| {
+                let origin = format!("{}/{}", staged.platform, staged.filename);
+                let filename = staged.filename.clone();
+                let size = staged.size;
+                let backend = Arc::clone(&self.backend);
+                Ok(PackageEntry::new(filename, size, fetch(backend, staged)).with_origin(origin))
+            })
+            .collect();
+        Ok(stream::iter(entries).boxed())
+    }
+}
+
+/// Read a staged package back and check it against its repodata record
+async fn fetch(backend: Arc<dyn RepositoryBackend>, staged: ListedPackage) -> Result<Bytes> {
+    let path = format!("{}/{}", staged.platform, staged.filename);
+    let content = backend
+        .package(&staged.platform, &staged.filename)
+        .await?
+        .ok_or_else(|| MirrorError::NotFound(format!("{} at the staging target", path)))?;
+    if let Some(expected) = &staged.sha256 {
+        let actual = format!("{:x}", Sha256::digest(&content));
+        if &actual != expected {
+            return Err(MirrorError::Corrupt(format!(
+                "{} has sha256 {}, the staging repodata lists {}",
+                path, actual, expected
+            )));
+        }
+    }
+    Ok(content)
+}
+
+/// Check every staged package before anything is promoted
+///
+/// A package is rejected when it cannot be read back, does not match its
+/// repodata record, or violates the admission policy of `config`.
+pub async fn validate(source: &StagingSource, config: &Config) -> Result<Vec<PolicyVerdict>> {
+    let policy = Policy::new(&config.policy.clone().unwrap_or_default())?;
+    policy::check(source, &policy).await
+}
+
+/// Mirror the staged packages into production and record the promotion
+///
+/// Callers are expected to have [`validate`]d the source first.
+pub async fn promote(
+    source: &StagingSource,
+    production: &mut Repository,
+    config: &Config,
+    label: Option<&str>,
+) -> Result<MirrorReport> {
+    let report = mirror_from_provider(source, production, config).await?;
+
+    #[cfg(feature = "state-db")]
+    if let Some(state_db) = &config.state_db {
+        let recorded = StateDb::open(state_db)?.record_promotion(&report, label)?;
+        info!("Recorded the promotion of {} packages", recorded);
+    }
+    #[cfg(not(feature = "state-db"))]
+    let _ = label;
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::RepositoryType;
+    use crate::test_support::PackageFixture;
+    use tempfile::TempDir;
+
+    /// A local channel holding `fixtures`, indexed by a mirror run
+    async fn staging_channel(dir: &std::path::Path, fixtures: &[PackageFixture]) -> Repository {
+        let mut staging = Repository::new(RepositoryType::Local, dir.to_string_lossy().to_string());
+        for fixture in fixtures {
+            staging
+                .upload_package(&fixture.conda_filename(), fixture.to_conda())
+                .await
+                .unwrap();
+        }
+        staging.finalize_repository().await.unwrap();
+        staging
+    }
+
+    #[cfg(feature = "state-db")]
+    #[tokio::test]
+    async fn test_promote_copies_validated_packages() {
+        let temp_dir = TempDir::new().unwrap();
+        let staging = staging_channel(
+            &temp_dir.path().join("staging"),
+            &[
+                PackageFixture::new("numpy", "1.26.4").subdir("linux-64"),
+                PackageFixture::new("scipy", "1.12.0"),
+            ],
+        )
+        .await;
+        let production_dir = temp_dir.path().join("production");
+        let mut production = Repository::new(
+            RepositoryType::Local,
+            production_dir.to_string_lossy().to_string(),
+        );
+        let config = Config {
+            state_db: Some(
+                temp_dir
+                    .path()
+                    .join("state.db")
+                    .to_string_lossy()
+                    .to_string(),
+            ),
+            resume_state_file: temp_dir
+                .path()
+                .join("resume.json")
+                .to_string_lossy()
+                .to_string(),
+            ..Config::default()
+        };
+
+        let source = StagingSource::new(&staging, Some(Regex::new("^numpy$").unwrap()));
+        let verdicts = validate(&source, &config).await.unwrap();
+        assert_eq!(verdicts.len(), 1);
+        assert!(verdicts[0].admitted);
+
+        let report = promote(&source, &mut production, &config, Some("stable"))
+            .await
+            .unwrap();
+        assert_eq!(report.mirrored_count(), 1);
+        let repodata =
+            std::fs::read_to_string(production_dir.join("linux-64/repodata.json")).unwrap();
+        assert!(repodata.contains("numpy-1.26.4-0.conda"));
+        assert!(!production_dir.join("noarch/scipy-1.12.0-0.conda").exists());
+
+        let promotions = StateDb::open(config.state_db.as_ref().unwrap())
+            .unwrap()
+            .promotions(&Default::default())
+            .unwrap();
+        assert_eq!(promotions.len(), 1);
+        assert_eq!(promotions[0].path(), "linux-64/numpy-1.26.4-0.conda");
+        assert_eq!(promotions[0].label.as_deref(), Some("stable"));
+        assert_eq!(promotions[0].staging, staging.path);
+    }
+
+    #[tokio::test]
+    async fn test_altered_staged_package_fails_validation() {
+        let temp_dir = TempDir::new().unwrap();
+        let staging_dir = temp_dir.path().join("staging");
+        let fixture = PackageFixture::new("numpy", "1.26.4");
+        let staging = staging_channel(&staging_dir, std::slice::from_ref(&fixture)).await;
+        std::fs::write(
+            staging_dir.join("noarch").join(fixture.conda_filename()),
+            PackageFixture::new("numpy", "1.26.4")
+                .depends(["python"])
+                .to_conda(),
+        )
+        .unwrap();
+
+        let verdicts = validate(&StagingSource::new(&staging, None), &Config::default())
+            .await
+            .unwrap();
+        assert_eq!(verdicts.len(), 1);
+        assert!(!verdicts[0].admitted);
+        assert!(verdicts[0].reasons[0].contains("the staging repodata lists"));
+    }
+}
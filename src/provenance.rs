@@ -0,0 +1,126 @@
+//! SLSA provenance for mirrored packages
+//!
+//! With `provenance` enabled, every package a run writes gets an in-toto
+//! statement carrying a SLSA v1 provenance predicate. It names the package by
+//! its digests, records the source the run read, the workflow run, build or URL
+//! the package came from, and the version of this tool. Targets that can hold
+//! extra files store it next to the package as `<filename>.intoto.json`; the
+//! state database keeps a copy either way.
+
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+
+use crate::conda_package::ProcessedPackage;
+
+/// `_type` of an in-toto v1 statement
+pub const STATEMENT_TYPE: &str = "https://in-toto.io/Statement/v1";
+/// `predicateType` of a SLSA v1 provenance predicate
+pub const PREDICATE_TYPE: &str = "https://slsa.dev/provenance/v1";
+/// Identifies mirroring as the "build" that produced the package at the target
+pub const BUILD_TYPE: &str = "https://github.com/babeloff/meso-forge-mirror/mirror/v1";
+/// Identifies this tool as the builder
+pub const BUILDER_ID: &str = "https://github.com/babeloff/meso-forge-mirror";
+
+/// What a provenance statement describes besides the package itself
+#[derive(Debug, Clone)]
+pub struct ProvenanceContext<'a> {
+    /// The source as given to the run, e.g. `owner/repo`
+    pub source: &'a str,
+    /// Where the package came from within the source, e.g. a workflow run URL
+    pub origin: Option<&'a str>,
+    pub target: &'a str,
+    pub started_on: DateTime<Utc>,
+    pub finished_on: DateTime<Utc>,
+}
+
+/// Filename of the attestation stored next to `filename`
+pub fn attestation_filename(filename: &str) -> String {
+    format!("{}.intoto.json", filename)
+}
+
+/// in-toto statement with a SLSA provenance predicate for a mirrored package
+pub fn statement(package: &ProcessedPackage, context: &ProvenanceContext) -> Value {
+    json!({
+        "_type": STATEMENT_TYPE,
+        "subject": [{
+            "name": format!("{}/{}", package.platform, package.filename),
+            "digest": { "sha256": package.sha256, "md5": package.md5 },
+        }],
+        "predicateType": PREDICATE_TYPE,
+        "predicate": {
+            "buildDefinition": {
+                "buildType": BUILD_TYPE,
+                "externalParameters": {
+                    "source": context.source,
+                    "target": context.target,
+                },
+                // Packages are copied unchanged, so the fetched copy has the same digests
+                "resolvedDependencies": [{
+                    "uri": context.origin.unwrap_or(context.source),
+                    "name": package.filename,
+                    "digest": { "sha256": package.sha256, "md5": package.md5 },
+                }],
+            },
+            "runDetails": {
+                "builder": {
+                    "id": BUILDER_ID,
+                    "version": { "meso-forge-mirror": env!("CARGO_PKG_VERSION") },
+                },
+                "metadata": {
+                    "startedOn": context.started_on.to_rfc3339(),
+                    "finishedOn": context.finished_on.to_rfc3339(),
+                },
+            },
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conda_package::CondaPackageHandler;
+    use crate::test_support::PackageFixture;
+
+    #[tokio::test]
+    async fn test_statement() {
+        let fixture = PackageFixture::new("pkg", "1.0").subdir("linux-64");
+        let package = CondaPackageHandler::new()
+            .process_package(fixture.to_conda(), &fixture.conda_filename())
+            .await
+            .unwrap();
+        let started_on = Utc::now();
+        let statement = statement(
+            &package,
+            &ProvenanceContext {
+                source: "owner/repo",
+                origin: Some("https://github.com/owner/repo/actions/runs/7/artifacts/42"),
+                target: "./repo",
+                started_on,
+                finished_on: started_on,
+            },
+        );
+
+        assert_eq!(statement["_type"], STATEMENT_TYPE);
+        assert_eq!(statement["predicateType"], PREDICATE_TYPE);
+        assert_eq!(statement["subject"][0]["name"], "linux-64/pkg-1.0-0.conda");
+        assert_eq!(statement["subject"][0]["digest"]["sha256"], package.sha256);
+
+        let predicate = &statement["predicate"];
+        assert_eq!(
+            predicate["buildDefinition"]["externalParameters"]["source"],
+            "owner/repo"
+        );
+        assert_eq!(
+            predicate["buildDefinition"]["resolvedDependencies"][0]["uri"],
+            "https://github.com/owner/repo/actions/runs/7/artifacts/42"
+        );
+        assert_eq!(
+            predicate["runDetails"]["builder"]["version"]["meso-forge-mirror"],
+            env!("CARGO_PKG_VERSION")
+        );
+        assert_eq!(
+            attestation_filename("pkg-1.0-0.conda"),
+            "pkg-1.0-0.conda.intoto.json"
+        );
+    }
+}
@@ -0,0 +1,94 @@
+//! Holding area for archives that failed to extract
+//!
+//! Archives that are still corrupt after being downloaded a second time, and
+//! packages held back for [`review`](crate::review), are written here together
+//! with a `.reason` file, so they can be inspected later without being
+//! uploaded to the target repository.
+
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+use crate::error::{MirrorError, Result};
+
+/// Write an archive into the quarantine directory and return its new path
+pub fn quarantine_file(dir: &Path, name: &str, content: &[u8], reason: &str) -> Result<PathBuf> {
+    std::fs::create_dir_all(dir).map_err(|e| MirrorError::target_io(dir, e))?;
+
+    let file_name = format!(
+        "{}-{}",
+        chrono::Utc::now().format("%Y%m%dT%H%M%S"),
+        sanitize_file_name(name)
+    );
+    let path = dir.join(&file_name);
+    std::fs::write(&path, content).map_err(|e| MirrorError::target_io(&path, e))?;
+
+    let reason_path = dir.join(format!("{}.reason", file_name));
+    std::fs::write(&reason_path, format!("{}\n{}\n", name, reason))
+        .map_err(|e| MirrorError::target_io(&reason_path, e))?;
+
+    warn!("Quarantined {} at {:?}", name, path);
+    Ok(path)
+}
+
+/// Reduce a source URL or path to something safe to use as a file name
+fn sanitize_file_name(name: &str) -> String {
+    let base = name
+        .split(['?', '#'])
+        .next()
+        .unwrap_or(name)
+        .trim_end_matches('/')
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or(name);
+
+    let sanitized: String = base
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    if sanitized.is_empty() {
+        "archive".to_string()
+    } else {
+        sanitized
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_sanitize_file_name() {
+        assert_eq!(
+            sanitize_file_name("https://example.com/a/packages.zip?token=x"),
+            "packages.zip"
+        );
+        assert_eq!(
+            sanitize_file_name("GitHub artifact 'conda pkgs'"),
+            "GitHub_artifact__conda_pkgs_"
+        );
+        assert_eq!(sanitize_file_name("https://example.com/"), "example.com");
+    }
+
+    #[test]
+    fn test_quarantine_file_writes_content_and_reason() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().join("quarantine");
+
+        let path = quarantine_file(&dir, "/tmp/broken.zip", b"not a zip", "bad header").unwrap();
+
+        assert!(path.starts_with(&dir));
+        assert!(path.to_string_lossy().ends_with("broken.zip"));
+        assert_eq!(std::fs::read(&path).unwrap(), b"not a zip");
+
+        let reason = std::fs::read_to_string(format!("{}.reason", path.display())).unwrap();
+        assert!(reason.contains("bad header"));
+    }
+}
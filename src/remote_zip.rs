@@ -0,0 +1,248 @@
+//! Partial reads of remote ZIP archives
+//!
+//! A ZIP file lists its members in a central directory at its end, so single
+//! packages can be taken from a multi-gigabyte artifact without downloading
+//! the rest of it. When a host announces byte ranges for a `zip-url` source of
+//! at least `range_read_min_bytes`, the central directory and the selected
+//! members are fetched with HTTP range requests instead of the whole archive.
+
+use bytes::Bytes;
+use reqwest::header::{ACCEPT_RANGES, CONTENT_LENGTH, RANGE};
+use reqwest::{Client, StatusCode};
+use std::collections::VecDeque;
+use std::io::{self, Read, Seek, SeekFrom};
+use tokio::runtime::Handle;
+use tracing::debug;
+
+use crate::{auth, politeness};
+
+/// Size of the first range fetched at a new position
+const MIN_FETCH: u64 = 64 * 1024;
+
+/// Upper bound on a single range, reached by doubling while a member is read sequentially
+const MAX_FETCH: u64 = 8 * 1024 * 1024;
+
+/// Fetched ranges kept for reads that go back a little, e.g. to a local header
+const CACHED_RANGES: usize = 4;
+
+/// Length of the remote file at `url`, if its host serves byte ranges of it
+pub async fn range_length(client: &Client, url: &str) -> Option<u64> {
+    let response = politeness::throttled(url, || auth::head(client, url).send())
+        .await
+        .ok()?;
+    if !response.status().is_success() {
+        return None;
+    }
+    let headers = response.headers();
+    let ranges = headers
+        .get(ACCEPT_RANGES)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.eq_ignore_ascii_case("bytes"));
+    let length = headers
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok());
+    debug!(
+        "{}: {} bytes, byte ranges {}",
+        url,
+        length.map_or("unknown".to_string(), |length: u64| length.to_string()),
+        if ranges { "supported" } else { "not announced" }
+    );
+    length.filter(|_| ranges)
+}
+
+/// A remote file read through HTTP range requests
+///
+/// Reads block on the runtime of `handle`, so the reader is used from a
+/// blocking task, e.g. one started with `spawn_blocking`.
+pub struct RangeReader {
+    handle: Handle,
+    client: Client,
+    url: String,
+    len: u64,
+    pos: u64,
+    /// Recently fetched ranges and their offsets, newest last
+    cache: VecDeque<(u64, Bytes)>,
+    /// Size of the next range if it continues the last one
+    next_fetch: u64,
+    fetched: u64,
+}
+
+impl RangeReader {
+    pub fn new(handle: Handle, client: Client, url: impl Into<String>, len: u64) -> Self {
+        Self {
+            handle,
+            client,
+            url: url.into(),
+            len,
+            pos: 0,
+            cache: VecDeque::new(),
+            next_fetch: MIN_FETCH,
+            fetched: 0,
+        }
+    }
+
+    /// Bytes downloaded so far
+    pub fn fetched(&self) -> u64 {
+        self.fetched
+    }
+
+    /// The cached range holding `pos`, fetching one that starts there if none does
+    fn range_at(&mut self, pos: u64) -> io::Result<(u64, Bytes)> {
+        if let Some(range) = self
+            .cache
+            .iter()
+            .find(|(start, content)| (*start..*start + content.len() as u64).contains(&pos))
+        {
+            return Ok(range.clone());
+        }
+
+        let sequential = self
+            .cache
+            .back()
+            .is_some_and(|(start, content)| start + content.len() as u64 == pos);
+        let size = if sequential {
+            self.next_fetch
+        } else {
+            MIN_FETCH
+        };
+        self.next_fetch = (size * 2).min(MAX_FETCH);
+
+        let end = (pos + size).min(self.len) - 1;
+        let content = self.handle.block_on(self.fetch(pos, end))?;
+        self.fetched += content.len() as u64;
+        if self.cache.len() == CACHED_RANGES {
+            self.cache.pop_front();
+        }
+        self.cache.push_back((pos, content.clone()));
+        Ok((pos, content))
+    }
+
+    /// Fetch bytes `start..=end`
+    async fn fetch(&self, start: u64, end: u64) -> io::Result<Bytes> {
+        let response = politeness::throttled(&self.url, || {
+            auth::get(&self.client, &self.url)
+                .header(RANGE, format!("bytes={}-{}", start, end))
+                .send()
+        })
+        .await
+        .map_err(io::Error::other)?;
+        if response.status() != StatusCode::PARTIAL_CONTENT {
+            return Err(io::Error::other(format!(
+                "{} answered HTTP {} to a range request",
+                self.url,
+                response.status()
+            )));
+        }
+        let content = response.bytes().await.map_err(io::Error::other)?;
+        if content.len() as u64 != end - start + 1 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!(
+                    "{} returned {} bytes for range {}-{}",
+                    self.url,
+                    content.len(),
+                    start,
+                    end
+                ),
+            ));
+        }
+        Ok(content)
+    }
+}
+
+impl Read for RangeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+        let (start, content) = self.range_at(self.pos)?;
+        let available = &content[(self.pos - start) as usize..];
+        let read = available.len().min(buf.len());
+        buf[..read].copy_from_slice(&available[..read]);
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for RangeReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+        self.pos = target.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek before the start of the file",
+            )
+        })?;
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serve::{bind, serve, ChannelServer};
+    use std::io::Write;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_range_reader_reads_members_of_a_served_zip() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored);
+        writer.start_file("large.bin", options).unwrap();
+        writer.write_all(&vec![7u8; 4 * 1024 * 1024]).unwrap();
+        writer.start_file("small.txt", options).unwrap();
+        writer.write_all(b"small").unwrap();
+        let archive = writer.finish().unwrap().into_inner();
+        std::fs::write(temp_dir.path().join("artifact.zip"), &archive).unwrap();
+
+        let listener = bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/artifact.zip", listener.local_addr().unwrap());
+        let server = ChannelServer::new(temp_dir.path(), None).unwrap();
+        let task = tokio::spawn(serve(listener, server));
+
+        let client = Client::new();
+        let length = range_length(&client, &url).await.unwrap();
+        assert_eq!(length, archive.len() as u64);
+
+        let handle = Handle::current();
+        let reader = RangeReader::new(handle, client, url, length);
+        let (content, fetched) = tokio::task::spawn_blocking(move || {
+            let mut archive = zip::ZipArchive::new(reader).unwrap();
+            let mut content = String::new();
+            archive
+                .by_name("small.txt")
+                .unwrap()
+                .read_to_string(&mut content)
+                .unwrap();
+            (content, archive.into_inner().fetched())
+        })
+        .await
+        .unwrap();
+        assert_eq!(content, "small");
+        assert!(fetched < archive.len() as u64 / 4);
+
+        task.abort();
+    }
+
+    #[test]
+    fn test_seek() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let mut reader = RangeReader::new(
+            runtime.handle().clone(),
+            Client::new(),
+            "http://localhost/artifact.zip",
+            100,
+        );
+        assert_eq!(reader.seek(SeekFrom::End(-22)).unwrap(), 78);
+        assert_eq!(reader.seek(SeekFrom::Current(2)).unwrap(), 80);
+        assert!(reader.seek(SeekFrom::Current(-81)).is_err());
+        reader.seek(SeekFrom::Start(100)).unwrap();
+        assert_eq!(reader.read(&mut [0; 4]).unwrap(), 0);
+    }
+}
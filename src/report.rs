@@ -0,0 +1,599 @@
+//! Structured outcome of a mirroring run
+//!
+//! [`MirrorReport`] records what happened to every package a source offered, so
+//! callers can act on partial outcomes without parsing logs.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::conda_package::MetadataSource;
+use crate::osv::Advisory;
+use crate::source::ArtifactSource;
+
+/// Platform under which packages of no known subdir are summarized
+pub const UNKNOWN_PLATFORM: &str = "unknown";
+
+/// Machine-readable reason a package or source item was skipped
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum SkipCode {
+    /// An identical copy is already at the target
+    AlreadyPresent,
+    /// Mirrored before an interrupted run of the same source and target
+    Resumed,
+    /// Recorded in the state database as delivered by an earlier run
+    DeliveredEarlier,
+    /// Rejected by the admission policy
+    PolicyRejected,
+    /// Larger than the admission policy allows
+    Oversized,
+    /// Blocked by a known vulnerability
+    Vulnerable,
+    /// Matched by `src_exclude`
+    Excluded,
+    /// A conda package in an archive that `--src-path` does not match
+    Unmatched,
+    /// A CI artifact that expired before it could be downloaded
+    Expired,
+    /// A CI artifact built for a platform outside `--platforms`
+    OtherPlatform,
+    /// A package matching none of the `--spec` match specs
+    SpecMismatch,
+    /// An archive member whose path cannot be used as a package filename
+    UnsafePath,
+    /// A source unchanged upstream since the last run that mirrored it
+    Unchanged,
+    /// Recorded without a code, e.g. by an older version
+    #[default]
+    Other,
+}
+
+impl SkipCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SkipCode::AlreadyPresent => "already-present",
+            SkipCode::Resumed => "resumed",
+            SkipCode::DeliveredEarlier => "delivered-earlier",
+            SkipCode::PolicyRejected => "policy-rejected",
+            SkipCode::Oversized => "oversized",
+            SkipCode::Vulnerable => "vulnerable",
+            SkipCode::Excluded => "excluded",
+            SkipCode::Unmatched => "unmatched",
+            SkipCode::Expired => "expired",
+            SkipCode::OtherPlatform => "other-platform",
+            SkipCode::SpecMismatch => "spec-mismatch",
+            SkipCode::UnsafePath => "unsafe-path",
+            SkipCode::Unchanged => "unchanged",
+            SkipCode::Other => "other",
+        }
+    }
+}
+
+impl std::fmt::Display for SkipCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// What happened to one package
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+pub enum PackageOutcome {
+    /// The package was written to the target
+    Mirrored,
+    /// The package was not written, e.g. because an identical copy is already there
+    Skipped {
+        #[serde(default)]
+        code: SkipCode,
+        reason: String,
+    },
+    /// Fetching, processing or uploading the package failed
+    Failed { error: String },
+}
+
+/// Result for one package offered by the source
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackageReport {
+    pub filename: String,
+    #[serde(flatten)]
+    pub outcome: PackageOutcome,
+    /// Size of the package content, 0 if it was never fetched
+    pub bytes: u64,
+    #[serde(with = "duration_secs")]
+    pub duration: Duration,
+    /// Where the package came from within the source, e.g. an artifact id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<String>,
+    /// The CI artifact the package was extracted from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artifact: Option<ArtifactSource>,
+    /// Platform subdirectory the package was stored under
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub platform: Option<String>,
+    /// Canonical filename the package was stored under, when it was renamed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stored_as: Option<String>,
+    /// sha256 of the package, known once it has been processed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// License declared by the package, known once it has been processed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    /// Where the metadata of the package came from, known once it has been processed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata_source: Option<MetadataSource>,
+    /// Known vulnerabilities of the package version, when lookups are enabled
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub advisories: Vec<Advisory>,
+    /// in-toto provenance statement, when provenance is enabled and the package was written
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<serde_json::Value>,
+    /// How the platform of the package was decided, when `explain_platform` is on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub platform_explanation: Option<String>,
+}
+
+/// Something a source offered that never became a package of the run
+///
+/// Expired CI artifacts and archive members left out by `--src-path` or
+/// `src_exclude` are recorded here, so they are accounted for in the report.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SkippedItem {
+    /// What was skipped, e.g. `artifact` or `ZIP member`
+    pub kind: String,
+    pub name: String,
+    pub code: SkipCode,
+    pub reason: String,
+}
+
+impl SkippedItem {
+    pub fn new(
+        kind: impl Into<String>,
+        name: impl Into<String>,
+        code: SkipCode,
+        reason: impl Into<String>,
+    ) -> Self {
+        Self {
+            kind: kind.into(),
+            name: name.into(),
+            code,
+            reason: reason.into(),
+        }
+    }
+}
+
+/// An archive that stayed corrupt after a re-download and was moved aside
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuarantinedArchive {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// Packages of one platform subdir in a run, by outcome
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PlatformSummary {
+    pub platform: String,
+    pub found: usize,
+    pub mirrored: usize,
+    pub skipped: usize,
+    pub failed: usize,
+}
+
+/// Outcome of mirroring one source into one target
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MirrorReport {
+    pub source: String,
+    pub target: String,
+    pub started_at: DateTime<Utc>,
+    #[serde(with = "duration_secs")]
+    pub duration: Duration,
+    pub packages: Vec<PackageReport>,
+    pub quarantined: Vec<QuarantinedArchive>,
+    /// Items of the source skipped before they became packages of the run
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped_items: Vec<SkippedItem>,
+}
+
+impl MirrorReport {
+    pub fn new(source: impl Into<String>, target: impl Into<String>) -> Self {
+        Self {
+            source: source.into(),
+            target: target.into(),
+            started_at: Utc::now(),
+            duration: Duration::ZERO,
+            packages: Vec::new(),
+            quarantined: Vec::new(),
+            skipped_items: Vec::new(),
+        }
+    }
+
+    pub fn record(
+        &mut self,
+        filename: impl Into<String>,
+        outcome: PackageOutcome,
+        bytes: u64,
+        duration: Duration,
+    ) -> &mut PackageReport {
+        self.packages.push(PackageReport {
+            filename: filename.into(),
+            outcome,
+            bytes,
+            duration,
+            origin: None,
+            artifact: None,
+            platform: None,
+            stored_as: None,
+            sha256: None,
+            license: None,
+            metadata_source: None,
+            advisories: Vec::new(),
+            provenance: None,
+            platform_explanation: None,
+        });
+        self.packages.last_mut().expect("package was just recorded")
+    }
+
+    pub fn mirrored_count(&self) -> usize {
+        self.count(|outcome| matches!(outcome, PackageOutcome::Mirrored))
+    }
+
+    pub fn skipped_count(&self) -> usize {
+        self.count(|outcome| matches!(outcome, PackageOutcome::Skipped { .. }))
+    }
+
+    pub fn failed_count(&self) -> usize {
+        self.count(|outcome| matches!(outcome, PackageOutcome::Failed { .. }))
+    }
+
+    /// Bytes written to the target
+    pub fn bytes_transferred(&self) -> u64 {
+        self.packages
+            .iter()
+            .filter(|package| package.outcome == PackageOutcome::Mirrored)
+            .map(|package| package.bytes)
+            .sum()
+    }
+
+    /// Packages that failed, with their errors
+    pub fn failures(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.packages
+            .iter()
+            .filter_map(|package| match &package.outcome {
+                PackageOutcome::Failed { error } => {
+                    Some((package.filename.as_str(), error.as_str()))
+                }
+                _ => None,
+            })
+    }
+
+    /// Skipped packages and source items per reason code, in code order
+    pub fn skip_summary(&self) -> BTreeMap<SkipCode, usize> {
+        let mut summary = BTreeMap::new();
+        let package_codes = self
+            .packages
+            .iter()
+            .filter_map(|package| match package.outcome {
+                PackageOutcome::Skipped { code, .. } => Some(code),
+                _ => None,
+            });
+        for code in package_codes.chain(self.skipped_items.iter().map(|item| item.code)) {
+            *summary.entry(code).or_insert(0) += 1;
+        }
+        summary
+    }
+
+    /// Processed packages per metadata source, in source order
+    pub fn metadata_summary(&self) -> BTreeMap<MetadataSource, usize> {
+        let mut summary = BTreeMap::new();
+        for source in self
+            .packages
+            .iter()
+            .filter_map(|package| package.metadata_source)
+        {
+            *summary.entry(source).or_insert(0) += 1;
+        }
+        summary
+    }
+
+    /// No package failed, and quarantined archives did not leave the run empty-handed
+    pub fn is_success(&self) -> bool {
+        self.failed_count() == 0
+            && (self.quarantined.is_empty() || self.mirrored_count() + self.skipped_count() > 0)
+    }
+
+    /// Packages per platform subdir, in subdir order
+    ///
+    /// Packages whose platform was never determined, e.g. because they failed
+    /// to download, are counted under `unknown`.
+    pub fn platform_summary(&self) -> Vec<PlatformSummary> {
+        let mut summaries: BTreeMap<&str, PlatformSummary> = BTreeMap::new();
+        for package in &self.packages {
+            let platform = package.platform.as_deref().unwrap_or(UNKNOWN_PLATFORM);
+            let summary = summaries
+                .entry(platform)
+                .or_insert_with(|| PlatformSummary {
+                    platform: platform.to_string(),
+                    ..PlatformSummary::default()
+                });
+            summary.found += 1;
+            match package.outcome {
+                PackageOutcome::Mirrored => summary.mirrored += 1,
+                PackageOutcome::Skipped { .. } => summary.skipped += 1,
+                PackageOutcome::Failed { .. } => summary.failed += 1,
+            }
+        }
+        summaries.into_values().collect()
+    }
+
+    fn count(&self, predicate: impl Fn(&PackageOutcome) -> bool) -> usize {
+        self.packages
+            .iter()
+            .filter(|package| predicate(&package.outcome))
+            .count()
+    }
+
+    /// Print a summary of the run
+    pub fn print_summary(&self) {
+        println!("Mirror Report:");
+        println!("  Source: {}", self.source);
+        println!("  Target: {}", self.target);
+        println!("  Mirrored: {}", self.mirrored_count());
+        println!("  Skipped: {}", self.skipped_count());
+        println!("  Failed: {}", self.failed_count());
+        println!("  Bytes transferred: {}", self.bytes_transferred());
+        println!("  Duration: {:.1}s", self.duration.as_secs_f64());
+
+        let platforms = self.platform_summary();
+        if platforms.len() > 1 {
+            println!("  Platforms:");
+            for summary in &platforms {
+                println!(
+                    "    {}: {} found, {} mirrored, {} skipped, {} failed",
+                    summary.platform,
+                    summary.found,
+                    summary.mirrored,
+                    summary.skipped,
+                    summary.failed
+                );
+            }
+        }
+        let skips = self.skip_summary();
+        if !skips.is_empty() {
+            println!("  Skip reasons:");
+            for (code, count) in &skips {
+                println!("    {}: {}", code, count);
+            }
+        }
+        let metadata = self.metadata_summary();
+        if !metadata.is_empty() {
+            println!("  Metadata sources:");
+            for (source, count) in &metadata {
+                println!("    {}: {}", source, count);
+            }
+        }
+        for (filename, error) in self.failures() {
+            println!("    failed: {}: {}", filename, error);
+        }
+        for archive in &self.quarantined {
+            println!(
+                "    quarantined: {} ({})",
+                archive.path.display(),
+                archive.reason
+            );
+        }
+    }
+}
+
+/// Serialize durations as fractional seconds
+mod duration_secs {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(duration.as_secs_f64())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        let secs = f64::deserialize(deserializer)?;
+        Duration::try_from_secs_f64(secs).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_counts_and_serialization() {
+        let mut report = MirrorReport::new("artifacts.zip", "./repo");
+        report.record(
+            "a-1.0-0.conda",
+            PackageOutcome::Mirrored,
+            100,
+            Duration::from_millis(1500),
+        );
+        report.record(
+            "b-1.0-0.conda",
+            PackageOutcome::Skipped {
+                code: SkipCode::AlreadyPresent,
+                reason: "identical copy already present".to_string(),
+            },
+            50,
+            Duration::ZERO,
+        );
+        report.record(
+            "c-1.0-0.conda",
+            PackageOutcome::Failed {
+                error: "boom".to_string(),
+            },
+            0,
+            Duration::ZERO,
+        );
+
+        assert_eq!(report.mirrored_count(), 1);
+        assert_eq!(report.skipped_count(), 1);
+        assert_eq!(report.failed_count(), 1);
+        assert_eq!(report.bytes_transferred(), 100);
+        assert!(!report.is_success());
+        assert_eq!(
+            report.failures().collect::<Vec<_>>(),
+            vec![("c-1.0-0.conda", "boom")]
+        );
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["packages"][0]["status"], "mirrored");
+        assert_eq!(json["packages"][0]["duration"], 1.5);
+        assert_eq!(
+            json["packages"][1]["reason"],
+            "identical copy already present"
+        );
+
+        assert_eq!(json["packages"][1]["code"], "already-present");
+
+        let restored: MirrorReport = serde_json::from_value(json).unwrap();
+        assert_eq!(restored.packages[2].outcome, report.packages[2].outcome);
+
+        // Reports written before skip codes were recorded still load
+        let restored: PackageReport = serde_json::from_value(serde_json::json!({
+            "filename": "b-1.0-0.conda",
+            "status": "skipped",
+            "reason": "identical copy already present",
+            "bytes": 50,
+            "duration": 0.0
+        }))
+        .unwrap();
+        assert!(matches!(
+            restored.outcome,
+            PackageOutcome::Skipped {
+                code: SkipCode::Other,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_skip_summary() {
+        let mut report = MirrorReport::new("owner/repo", "./repo");
+        for (filename, code) in [
+            ("a-1.0-0.conda", SkipCode::AlreadyPresent),
+            ("b-1.0-0.conda", SkipCode::AlreadyPresent),
+            ("c-1.0-0.conda", SkipCode::Oversized),
+        ] {
+            report.record(
+                filename,
+                PackageOutcome::Skipped {
+                    code,
+                    reason: code.to_string(),
+                },
+                1,
+                Duration::ZERO,
+            );
+        }
+        report.skipped_items.push(SkippedItem::new(
+            "artifact",
+            "conda-packages",
+            SkipCode::Expired,
+            "artifact has expired",
+        ));
+
+        assert_eq!(
+            report.skip_summary().into_iter().collect::<Vec<_>>(),
+            vec![
+                (SkipCode::AlreadyPresent, 2),
+                (SkipCode::Oversized, 1),
+                (SkipCode::Expired, 1),
+            ]
+        );
+        assert_eq!(report.skipped_count(), 3);
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["skipped_items"][0]["code"], "expired");
+    }
+
+    #[test]
+    fn test_metadata_summary() {
+        let mut report = MirrorReport::new("owner/repo", "./repo");
+        for (filename, source) in [
+            ("a-1.0-0.conda", Some(MetadataSource::Rattler)),
+            ("b-1.0-0.conda", Some(MetadataSource::Heuristic)),
+            ("c-1.0-0.conda", Some(MetadataSource::Rattler)),
+            ("d-1.0-0.conda", None),
+        ] {
+            report
+                .record(filename, PackageOutcome::Mirrored, 1, Duration::ZERO)
+                .metadata_source = source;
+        }
+
+        assert_eq!(
+            report.metadata_summary().into_iter().collect::<Vec<_>>(),
+            vec![(MetadataSource::Rattler, 2), (MetadataSource::Heuristic, 1)]
+        );
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["packages"][1]["metadata_source"], "heuristic");
+        assert!(json["packages"][3].get("metadata_source").is_none());
+    }
+
+    #[test]
+    fn test_platform_summary() {
+        let mut report = MirrorReport::new("artifacts.zip", "./repo");
+        report
+            .record("a-1.0-0.conda", PackageOutcome::Mirrored, 1, Duration::ZERO)
+            .platform = Some("linux-64".to_string());
+        report
+            .record(
+                "b-1.0-0.conda",
+                PackageOutcome::Skipped {
+                    code: SkipCode::AlreadyPresent,
+                    reason: "identical copy already present".to_string(),
+                },
+                1,
+                Duration::ZERO,
+            )
+            .platform = Some("linux-64".to_string());
+        report.record(
+            "c-1.0-0.conda",
+            PackageOutcome::Failed {
+                error: "boom".to_string(),
+            },
+            0,
+            Duration::ZERO,
+        );
+
+        assert_eq!(
+            report.platform_summary(),
+            vec![
+                PlatformSummary {
+                    platform: "linux-64".to_string(),
+                    found: 2,
+                    mirrored: 1,
+                    skipped: 1,
+                    failed: 0,
+                },
+                PlatformSummary {
+                    platform: UNKNOWN_PLATFORM.to_string(),
+                    found: 1,
+                    mirrored: 0,
+                    skipped: 0,
+                    failed: 1,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_quarantine_only_run_is_not_successful() {
+        let mut report = MirrorReport::new("owner/repo", "./repo");
+        report.quarantined.push(QuarantinedArchive {
+            path: PathBuf::from("quarantine/artifact.zip"),
+            reason: "bad zip".to_string(),
+        });
+        assert!(!report.is_success());
+
+        report.record("a-1.0-0.conda", PackageOutcome::Mirrored, 1, Duration::ZERO);
+        assert!(report.is_success());
+    }
+}
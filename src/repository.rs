@@ -1,110 +1,382 @@
-use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+#[cfg(feature = "s3")]
+use aws_sdk_s3::error::ProvideErrorMetadata;
 use bytes::Bytes;
-use rattler_cache::package_cache::PackageCache;
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use rattler_cache::package_cache::{CacheKey, PackageCache};
+use rattler_conda_types::package::ArchiveIdentifier;
 use rattler_conda_types::Platform;
-use std::path::Path;
-use tracing::{info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use tracing::{debug, info, warn};
 
+use crate::channeldata::{self, CHANNELDATA_FILENAME};
 use crate::conda_package::{CondaPackageHandler, ProcessedPackage};
+use crate::enrich::MetadataUpstream;
+use crate::error::{MirrorError, Result};
+use crate::extraction;
+use crate::manifest::{MirrorManifest, MANIFEST_FILENAME};
+#[cfg(feature = "s3")]
+use crate::multipart::{self, MultipartState, PendingUpload};
+use crate::naming::{self, FilenamePolicy};
+use crate::oci::{self, Descriptor, OciChannel, OciClient, PackageReference};
+use crate::policy::Policy;
+use crate::provenance::attestation_filename;
+use crate::report::MirrorReport;
+use crate::retention::RetentionRule;
+use crate::scratch;
+#[cfg(feature = "sharded-repodata")]
+use crate::shards;
+use crate::signing::{
+    public_key_file, signature_filename, KeyIndex, KeyRotation, Signer, SigningConfig, KEYS_FILE,
+};
+use crate::snapshot::{self, Rollback, Snapshot, SnapshotIndex, SNAPSHOT_INDEX};
+use crate::specs::SpecFilter;
 
-#[derive(Debug, Clone)]
+/// File written and removed again by [`RepositoryBackend::preflight`]
+const PREFLIGHT_MARKER: &str = ".meso-forge-mirror-preflight";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum RepositoryType {
     PrefixDev,
     S3,
     Local,
     Cache,
+    Oci,
 }
 
 impl RepositoryType {
+    /// The name `--tgt-type` takes for this type
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RepositoryType::PrefixDev => "prefix-dev",
+            RepositoryType::S3 => "s3",
+            RepositoryType::Local => "local",
+            RepositoryType::Cache => "cache",
+            RepositoryType::Oci => "oci",
+        }
+    }
+
     pub fn from_string(s: &str) -> Result<Self> {
         match s.to_lowercase().as_str() {
             "prefix-dev" | "prefix" => Ok(RepositoryType::PrefixDev),
             "s3" | "minio" => Ok(RepositoryType::S3),
             "local" | "file" => Ok(RepositoryType::Local),
             "cache" => Ok(RepositoryType::Cache),
-            _ => Err(anyhow!("Unknown repository type: {}", s)),
+            "oci" => Ok(RepositoryType::Oci),
+            _ => Err(MirrorError::InvalidInput(format!(
+                "Unknown repository type: {}",
+                s
+            ))),
         }
     }
 }
 
-pub struct Repository {
-    pub repo_type: RepositoryType,
-    pub path: String,
-    conda_handler: CondaPackageHandler,
-    #[allow(dead_code)]
-    package_cache: Option<PackageCache>,
+/// What to do when a filename is processed twice in one run with different platforms
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DuplicatePlatformPolicy {
+    /// Refuse the second copy
+    #[default]
+    Error,
+    /// Keep the platform of the first copy and warn
+    KeepFirst,
+    /// Use the platform read from real metadata over a guessed one; refuse if both or neither are guessed
+    PreferMetadata,
 }
 
-impl Clone for Repository {
-    fn clone(&self) -> Self {
-        let package_cache = if matches!(self.repo_type, RepositoryType::Cache) {
-            Some(PackageCache::new(&self.path))
-        } else {
-            None
-        };
+impl DuplicatePlatformPolicy {
+    pub fn from_string(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "error" | "fail" => Ok(DuplicatePlatformPolicy::Error),
+            "keep-first" | "first" => Ok(DuplicatePlatformPolicy::KeepFirst),
+            "prefer-metadata" | "metadata" => Ok(DuplicatePlatformPolicy::PreferMetadata),
+            _ => Err(MirrorError::InvalidInput(format!(
+                "Unknown duplicate platform policy: {}. Must be one of: error, keep-first, prefer-metadata",
+                s
+            ))),
+        }
+    }
+}
 
-        Self {
-            repo_type: self.repo_type.clone(),
-            path: self.path.clone(),
-            conda_handler: CondaPackageHandler::new(),
-            package_cache,
+/// What `gc` does with package files no `repodata.json` lists
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OrphanAction {
+    /// Add them to the repodata of their subdir
+    #[default]
+    Index,
+    /// Remove them from the target
+    Delete,
+}
+
+impl OrphanAction {
+    pub fn from_string(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "index" => Ok(OrphanAction::Index),
+            "delete" => Ok(OrphanAction::Delete),
+            _ => Err(MirrorError::InvalidInput(format!(
+                "Unknown orphan action: {}. Must be one of: index, delete",
+                s
+            ))),
         }
     }
 }
 
-impl Repository {
-    pub fn new(repo_type: RepositoryType, path: String) -> Self {
-        let package_cache = if matches!(repo_type, RepositoryType::Cache) {
-            Some(PackageCache::new(&path))
-        } else {
-            None
+/// Publishes the packages of one subdir under another
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlatformMapping {
+    /// Subdir packages are detected as, e.g. `osx-64`
+    pub from: Platform,
+    /// Subdir they are published under, e.g. `osx-arm64`
+    pub to: Platform,
+    /// Also keep publishing them under `from`, making `to` an alias
+    #[serde(default)]
+    pub keep_original: bool,
+}
+
+impl PlatformMapping {
+    /// Parse `FROM=TO`, e.g. `osx-64=osx-arm64`
+    pub fn from_arg(arg: &str, keep_original: bool) -> Result<Self> {
+        let invalid = |reason: String| {
+            MirrorError::InvalidInput(format!("Invalid platform mapping '{}': {}", arg, reason))
         };
+        let (from, to) = arg
+            .split_once('=')
+            .ok_or_else(|| invalid("expected FROM=TO".to_string()))?;
+        Ok(Self {
+            from: Platform::from_str(from.trim()).map_err(|e| invalid(e.to_string()))?,
+            to: Platform::from_str(to.trim()).map_err(|e| invalid(e.to_string()))?,
+            keep_original,
+        })
+    }
+}
 
-        Self {
-            repo_type,
-            path,
-            conda_handler: CondaPackageHandler::new(),
-            package_cache,
+/// Change of a stored file's content, for [`RepositoryBackend::update_repodata`]
+pub type ContentEdit<'a> = dyn Fn(Option<&[u8]>) -> Result<Option<Vec<u8>>> + Send + Sync + 'a;
+
+/// Storage behind a [`Repository`]
+///
+/// [`Repository`] processes and validates packages and applies the conflict
+/// policies; a backend only stores what it is given. The built-in targets are
+/// implementations of this trait, and [`Repository::from_backend`] accepts any
+/// other one, e.g. an internal artifact store.
+#[async_trait]
+pub trait RepositoryBackend: Send + Sync {
+    /// Where a package is or would be stored, used in logs and error messages
+    fn location(&self, platform: &Platform, filename: &str) -> String;
+
+    /// Check that the target accepts writes before any package is downloaded
+    async fn preflight(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// sha256 of the copy already stored under `filename`, or `None` if there is none
+    async fn exists(&self, platform: &Platform, filename: &str) -> Result<Option<String>>;
+
+    /// Store a processed package
+    async fn upload(&self, package: &ProcessedPackage) -> Result<()>;
+
+    /// Paths of the stored packages relative to the target, e.g. `linux-64/pkg-1.0-0.conda`
+    #[allow(dead_code)]
+    async fn list(&self) -> Result<Vec<String>>;
+
+    /// Store a provenance attestation next to a stored package
+    ///
+    /// Returns `false` if the target has nowhere to keep files besides packages.
+    async fn store_attestation(
+        &self,
+        _platform: &Platform,
+        _filename: &str,
+        _statement: &[u8],
+    ) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Store a detached signature next to a stored package or `repodata.json`
+    ///
+    /// `filename` is the name of the signature itself, e.g. `repodata.json.asc`.
+    /// Returns `false` if the target has nowhere to keep files besides packages.
+    async fn store_signature(
+        &self,
+        _platform: &Platform,
+        _filename: &str,
+        _signature: &[u8],
+    ) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Content of a stored package, or `None` if the target cannot read it back
+    async fn package(&self, _platform: &Platform, _filename: &str) -> Result<Option<Bytes>> {
+        Ok(None)
+    }
+
+    /// Content of the `repodata.json` written for a platform, if the target has one
+    async fn repodata(&self, _platform: &Platform) -> Result<Option<Vec<u8>>> {
+        Ok(None)
+    }
+
+    /// Content of the `repodata.json` of a platform with its version, e.g. an S3 ETag
+    ///
+    /// Backends shared by several writers return a version, so that what is
+    /// derived from the content can be checked against [`Self::repodata_version`]
+    /// afterwards.
+    async fn versioned_repodata(
+        &self,
+        platform: &Platform,
+    ) -> Result<Option<(Vec<u8>, Option<String>)>> {
+        Ok(self
+            .repodata(platform)
+            .await?
+            .map(|content| (content, None)))
+    }
+
+    /// Current version of the `repodata.json` of a platform, see [`Self::versioned_repodata`]
+    async fn repodata_version(&self, _platform: &Platform) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// Replace the `repodata.json` of a platform, e.g. after packages were removed
+    ///
+    /// Returns `false` if the target maintains its repodata itself.
+    async fn store_repodata(&self, _platform: &Platform, _content: &[u8]) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Rewrite the `repodata.json` of a platform with what `edit` makes of it
+    ///
+    /// `edit` gets the current content, if any, and returns the new content,
+    /// or `None` to leave it as it is. Backends shared by several writers call
+    /// it again with the newer content when another writer got in between.
+    /// Returns whether a new `repodata.json` was written.
+    async fn update_repodata(&self, platform: &Platform, edit: &ContentEdit<'_>) -> Result<bool> {
+        let existing = self.repodata(platform).await?;
+        match edit(existing.as_deref())? {
+            Some(content) => self.store_repodata(platform, &content).await,
+            None => Ok(false),
         }
     }
 
-    pub async fn upload_package(&mut self, package_name: &str, content: Bytes) -> Result<()> {
-        // Process the conda package to extract metadata and validate
-        let processed_package = self
-            .conda_handler
-            .process_package(content, package_name)
-            .await?;
+    /// Content of a file at the channel root, e.g. `mirror-manifest.json`
+    async fn channel_file(&self, _name: &str) -> Result<Option<Vec<u8>>> {
+        Ok(None)
+    }
 
-        // Validate the package
-        self.conda_handler.validate_package(&processed_package)?;
+    /// Replace a file at the channel root so readers see either the old or the new content
+    ///
+    /// Returns `false` if the target has nowhere to keep files besides packages.
+    async fn store_channel_file(&self, _name: &str, _content: &[u8]) -> Result<bool> {
+        Ok(false)
+    }
 
-        match &self.repo_type {
-            RepositoryType::Local => self.upload_local_structured(&processed_package).await,
-            RepositoryType::S3 => self.upload_s3_structured(&processed_package).await,
-            RepositoryType::PrefixDev => {
-                self.upload_prefix_dev_structured(&processed_package).await
-            }
-            RepositoryType::Cache => self.upload_cache(&processed_package).await,
+    /// Rewrite a file at the channel root with what `edit` makes of it
+    ///
+    /// Like [`RepositoryBackend::update_repodata`]. Returns whether the file was written.
+    async fn update_channel_file(&self, name: &str, edit: &ContentEdit<'_>) -> Result<bool> {
+        let existing = self.channel_file(name).await?;
+        match edit(existing.as_deref())? {
+            Some(content) => self.store_channel_file(name, &content).await,
+            None => Ok(false),
         }
     }
 
-    async fn upload_local_structured(&mut self, package: &ProcessedPackage) -> Result<()> {
+    /// Remove a file below the channel root, e.g. an expired repodata snapshot
+    async fn delete_channel_file(&self, _name: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// When each stored package was written, keyed like [`RepositoryBackend::list`]
+    ///
+    /// Packages the target cannot date are left out.
+    async fn stored_at(&self) -> Result<HashMap<String, DateTime<Utc>>> {
+        Ok(HashMap::new())
+    }
+
+    /// Abort uploads the target holds that were started but never completed
+    ///
+    /// Returns a description of each; with `dry_run` nothing is aborted. Targets
+    /// without resumable uploads have none.
+    async fn abort_incomplete_uploads(&self, _dry_run: bool) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    /// Write repository metadata for the given packages of each platform
+    async fn finalize(&self, packages: &HashMap<Platform, Vec<ProcessedPackage>>) -> Result<()>;
+
+    /// Packages `upload` accepted but could not deliver, taken once reported
+    ///
+    /// Targets that upload in batches accept a package before sending it, so
+    /// it fails only when its batch is sent, at the latest in `finalize`.
+    /// Other targets fail `upload` itself and have none.
+    fn take_failed_uploads(&self) -> Vec<FailedUpload> {
+        Vec::new()
+    }
+
+    /// Remove a stored package
+    async fn delete(&self, platform: &Platform, filename: &str) -> Result<()>;
+}
+
+/// A conda channel on the local filesystem
+pub struct LocalBackend {
+    path: String,
+}
+
+impl LocalBackend {
+    pub fn new(path: impl Into<String>) -> Self {
+        Self { path: path.into() }
+    }
+
+    fn platform_dir(&self, platform: &Platform) -> PathBuf {
+        extended_length_path(&normalize_local_path(&self.path).join(platform.to_string()))
+    }
+}
+
+#[async_trait]
+impl RepositoryBackend for LocalBackend {
+    fn location(&self, platform: &Platform, filename: &str) -> String {
+        normalize_local_path(&self.path)
+            .join(platform.to_string())
+            .join(filename)
+            .to_string_lossy()
+            .to_string()
+    }
+
+    async fn preflight(&self) -> Result<()> {
+        local_preflight(&self.path)
+    }
+
+    async fn exists(&self, platform: &Platform, filename: &str) -> Result<Option<String>> {
+        read_existing_sha256(&local_package_path(&self.platform_dir(platform), filename)?)
+    }
+
+    async fn upload(&self, package: &ProcessedPackage) -> Result<()> {
         info!(
             "Uploading {} to local repository at {} (platform: {})",
             package.filename, self.path, package.platform
         );
 
-        let base_path = Path::new(&self.path);
-        let platform_dir = base_path.join(package.platform.to_string());
-        std::fs::create_dir_all(&platform_dir)?;
+        let base_path = normalize_local_path(&self.path);
+        let platform_dir = self.platform_dir(&package.platform);
+        std::fs::create_dir_all(&platform_dir)
+            .map_err(|e| MirrorError::target_io(&platform_dir, e))?;
 
-        let file_path = platform_dir.join(&package.filename);
-        std::fs::write(file_path, &package.content)?;
+        let file_path = local_package_path(&platform_dir, &package.filename)?;
+        // Renamed over a replaced package, so hard links to the old file, as in channel
+        // snapshots, keep the old content
+        let partial = platform_dir.join(format!(".{}.partial", package.filename));
+        std::fs::write(&partial, &package.content)
+            .map_err(|e| MirrorError::target_io(&partial, e))?;
+        std::fs::rename(&partial, &file_path).map_err(|e| MirrorError::target_io(&file_path, e))?;
 
         // Update repodata.json for this platform
         let packages_for_platform = vec![package.clone()];
-        self.conda_handler
-            .create_repodata(&package.platform, &packages_for_platform, base_path)
+        CondaPackageHandler::new()
+            .create_repodata(&package.platform, &packages_for_platform, &base_path)
             .await?;
 
         info!(
@@ -114,296 +386,4810 @@ impl Repository {
         Ok(())
     }
 
-    async fn upload_s3_structured(&mut self, package: &ProcessedPackage) -> Result<()> {
-        info!(
-            "Uploading {} to S3 repository at {} (platform: {})",
-            package.filename, self.path, package.platform
-        );
+    async fn list(&self) -> Result<Vec<String>> {
+        let base_path = normalize_local_path(&self.path);
+        let mut stored = Vec::new();
 
-        // Parse bucket and key from path
-        let parts: Vec<&str> = self
-            .path
-            .trim_start_matches("s3://")
-            .splitn(2, '/')
-            .collect();
-        let bucket = parts.first().ok_or_else(|| anyhow!("Invalid S3 path"))?;
-        let prefix = parts.get(1).unwrap_or(&"");
+        for subdir in read_dir_names(&base_path)? {
+            let subdir_path = base_path.join(&subdir);
+            if !subdir_path.is_dir() {
+                continue;
+            }
+            for filename in read_dir_names(&subdir_path)? {
+                if CondaPackageHandler::is_conda_package(&filename) {
+                    stored.push(format!("{}/{}", subdir, filename));
+                }
+            }
+        }
 
-        // Create structured path with platform subdirectory
-        let structured_key = if prefix.is_empty() {
-            format!("{}/{}", package.platform, package.filename)
-        } else {
-            format!("{}/{}/{}", prefix, package.platform, package.filename)
-        };
+        stored.sort();
+        Ok(stored)
+    }
 
-        let config = aws_config::defaults(aws_config::BehaviorVersion::latest())
-            .load()
-            .await;
-        let client = aws_sdk_s3::Client::new(&config);
+    async fn stored_at(&self) -> Result<HashMap<String, DateTime<Utc>>> {
+        local_modification_times(&normalize_local_path(&self.path), self.list().await?)
+    }
 
-        // Upload the package
-        client
-            .put_object()
-            .bucket(*bucket)
-            .key(&structured_key)
-            .body(package.content.clone().into())
-            .content_type("application/x-conda-package")
-            .send()
-            .await?;
+    async fn store_attestation(
+        &self,
+        platform: &Platform,
+        filename: &str,
+        statement: &[u8],
+    ) -> Result<bool> {
+        let path = local_package_path(
+            &self.platform_dir(platform),
+            &attestation_filename(filename),
+        )?;
+        std::fs::write(&path, statement).map_err(|e| MirrorError::target_io(&path, e))?;
+        Ok(true)
+    }
 
-        // Generate and upload repodata.json for this platform
-        let packages_for_platform = vec![package.clone()];
-        let repodata_content = self
-            .generate_repodata_content(&packages_for_platform, &package.platform)
-            .await?;
+    async fn store_signature(
+        &self,
+        platform: &Platform,
+        filename: &str,
+        signature: &[u8],
+    ) -> Result<bool> {
+        let path = local_package_path(&self.platform_dir(platform), filename)?;
+        std::fs::write(&path, signature).map_err(|e| MirrorError::target_io(&path, e))?;
+        Ok(true)
+    }
 
-        let repodata_key = if prefix.is_empty() {
-            format!("{}/repodata.json", package.platform)
-        } else {
-            format!("{}/{}/repodata.json", prefix, package.platform)
-        };
+    async fn package(&self, platform: &Platform, filename: &str) -> Result<Option<Bytes>> {
+        let path = local_package_path(&self.platform_dir(platform), filename)?;
+        match std::fs::read(&path) {
+            Ok(content) => Ok(Some(Bytes::from(content))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(MirrorError::target_io(&path, e)),
+        }
+    }
 
-        client
-            .put_object()
-            .bucket(*bucket)
-            .key(&repodata_key)
-            .body(repodata_content.into_bytes().into())
-            .content_type("application/json")
-            .send()
-            .await?;
+    async fn repodata(&self, platform: &Platform) -> Result<Option<Vec<u8>>> {
+        let path = self.platform_dir(platform).join("repodata.json");
+        match std::fs::read(&path) {
+            Ok(content) => Ok(Some(content)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(MirrorError::target_io(&path, e)),
+        }
+    }
 
-        info!(
-            "Successfully uploaded {} to S3 under {}/",
-            package.filename, package.platform
-        );
-        Ok(())
+    async fn store_repodata(&self, platform: &Platform, content: &[u8]) -> Result<bool> {
+        let path = self.platform_dir(platform).join("repodata.json");
+        // Write next to the file and rename over it, which replaces it atomically
+        let partial = self.platform_dir(platform).join(".repodata.json.partial");
+        std::fs::write(&partial, content).map_err(|e| MirrorError::target_io(&partial, e))?;
+        std::fs::rename(&partial, &path).map_err(|e| MirrorError::target_io(&path, e))?;
+        Ok(true)
     }
 
-    async fn upload_prefix_dev_structured(&mut self, package: &ProcessedPackage) -> Result<()> {
-        info!(
-            "Uploading {} to prefix.dev at {} (platform: {})",
-            package.filename, self.path, package.platform
-        );
+    async fn channel_file(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        let path = extended_length_path(&normalize_local_path(&self.path).join(name));
+        match std::fs::read(&path) {
+            Ok(content) => Ok(Some(content)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(MirrorError::target_io(&path, e)),
+        }
+    }
 
-        // For prefix.dev, we need to use their API with structured paths
-        let client = reqwest::Client::new();
-        let structured_url = format!(
-            "{}/{}/{}",
-            self.path.trim_end_matches('/'),
-            package.platform,
-            package.filename
-        );
+    async fn store_channel_file(&self, name: &str, content: &[u8]) -> Result<bool> {
+        let path = extended_length_path(&normalize_local_path(&self.path).join(name));
+        let (Some(dir), Some(file_name)) = (path.parent(), path.file_name()) else {
+            return Err(MirrorError::InvalidInput(format!(
+                "Invalid channel file name: {}",
+                name
+            )));
+        };
+        std::fs::create_dir_all(dir).map_err(|e| MirrorError::target_io(dir, e))?;
+        // Write next to the file and rename over it, which replaces it atomically
+        let partial = dir.join(format!(".{}.partial", file_name.to_string_lossy()));
+        std::fs::write(&partial, content).map_err(|e| MirrorError::target_io(&partial, e))?;
+        std::fs::rename(&partial, &path).map_err(|e| MirrorError::target_io(&path, e))?;
+        Ok(true)
+    }
 
-        let response = client
-            .put(&structured_url)
-            .header("Content-Type", "application/x-conda-package")
-            .body(package.content.clone())
-            .send()
-            .await?;
+    async fn delete_channel_file(&self, name: &str) -> Result<()> {
+        let path = extended_length_path(&normalize_local_path(&self.path).join(name));
+        match std::fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(MirrorError::target_io(&path, e)),
+        }
+        // Drop the directory of a snapshot once its last file is gone
+        if let Some(dir) = path.parent() {
+            let _ = std::fs::remove_dir(dir);
+        }
+        Ok(())
+    }
 
-        if response.status().is_success() {
-            info!(
-                "Successfully uploaded {} to prefix.dev under {}/",
-                package.filename, package.platform
-            );
+    async fn finalize(&self, packages: &HashMap<Platform, Vec<ProcessedPackage>>) -> Result<()> {
+        let base_path = normalize_local_path(&self.path);
+        let handler = CondaPackageHandler::new();
+        for (platform, packages) in packages {
+            handler
+                .create_repodata(platform, packages, &base_path)
+                .await?;
+        }
+        Ok(())
+    }
 
-            // Note: prefix.dev typically handles repodata generation automatically
-            warn!("Note: Repodata generation for prefix.dev should be handled by their service");
-            Ok(())
-        } else {
-            Err(anyhow!(
-                "Failed to upload to prefix.dev: {} - {}",
-                response.status(),
-                response.text().await.unwrap_or_default()
-            ))
+    async fn delete(&self, platform: &Platform, filename: &str) -> Result<()> {
+        let path = self.platform_dir(platform).join(filename);
+        match std::fs::remove_file(&path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(MirrorError::target_io(&path, e)),
         }
     }
+}
 
-    /// Generate repodata.json content for a set of packages
-    async fn generate_repodata_content(
-        &self,
-        packages: &[ProcessedPackage],
-        platform: &Platform,
-    ) -> Result<String> {
-        use std::collections::HashMap;
-
-        #[derive(serde::Serialize)]
-        struct RepoData {
-            info: RepoDataInfo,
-            packages: HashMap<String, PackageRecord>,
-        }
-
-        #[derive(serde::Serialize)]
-        struct RepoDataInfo {
-            subdir: String,
-        }
-
-        #[derive(serde::Serialize)]
-        struct PackageRecord {
-            build: String,
-            build_number: u64,
-            depends: Vec<String>,
-            license: String,
-            md5: String,
-            sha256: String,
-            size: u64,
-            subdir: String,
-            name: String,
-            version: String,
-            timestamp: Option<chrono::DateTime<chrono::Utc>>,
-        }
-
-        let mut repodata = RepoData {
-            info: RepoDataInfo {
-                subdir: platform.to_string(),
-            },
-            packages: HashMap::new(),
-        };
-
-        for package in packages {
-            let package_record = PackageRecord {
-                build: package.metadata.build.clone(),
-                build_number: package.metadata.build_number,
-                depends: package.metadata.depends.clone(),
-                license: package.metadata.license.clone().unwrap_or_default(),
-                md5: package.md5.clone(),
-                sha256: package.sha256.clone(),
-                size: package.size,
-                subdir: platform.to_string(),
-                name: package.metadata.name.clone(),
-                version: package.metadata.version.clone(),
-                timestamp: package.metadata.timestamp,
-            };
+/// A flat directory of packages laid out for the rattler package cache
+///
+/// Archives are stored at the top of the directory. With extraction enabled
+/// every package is also unpacked through [`PackageCache`] into the
+/// `<name>-<version>-<build>` directory and `.lock` file rattler and pixi look
+/// for, so they use it without downloading the package again.
+pub struct CacheBackend {
+    path: String,
+    extract: bool,
+}
 
-            repodata
-                .packages
-                .insert(package.filename.clone(), package_record);
+impl CacheBackend {
+    pub fn new(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            extract: false,
         }
-
-        Ok(serde_json::to_string_pretty(&repodata)?)
     }
 
-    /// Get statistics about processed packages
-    pub fn get_package_stats(&self) -> crate::conda_package::PackageStats {
-        self.conda_handler.get_stats()
+    /// Unpack uploaded packages into the package cache layout as well
+    pub fn with_extract(mut self, extract: bool) -> Self {
+        self.extract = extract;
+        self
     }
 
-    /// Finalize repository by updating all repodata files
-    pub async fn finalize_repository(&mut self) -> Result<()> {
-        info!("Finalizing repository structure");
+    fn cache_dir(&self) -> PathBuf {
+        extended_length_path(&normalize_local_path(&self.path))
+    }
 
-        let organized_packages = self.conda_handler.organize_packages();
+    /// Name of the directory `PackageCache` unpacks a package archive into
+    fn extracted_name(filename: &str) -> Option<String> {
+        ArchiveIdentifier::try_from_filename(filename)
+            .map(|identifier| CacheKey::from(identifier).to_string())
+    }
 
-        match &self.repo_type {
-            RepositoryType::Local => {
-                let base_path = Path::new(&self.path);
-                for (platform, packages) in organized_packages {
-                    if !packages.is_empty() {
-                        self.conda_handler
-                            .create_repodata(&platform, &packages, base_path)
-                            .await?;
+    /// Unpack a stored archive, replacing an extraction of a different build
+    async fn extract(&self, package: &ProcessedPackage, archive: &Path) -> Result<()> {
+        let identifier =
+            ArchiveIdentifier::try_from_filename(&package.filename).ok_or_else(|| {
+                MirrorError::InvalidInput(format!(
+                    "{} is not a conda package filename",
+                    package.filename
+                ))
+            })?;
+        let key = CacheKey::from(identifier).with_sha256(Sha256::digest(&package.content));
+        let archive = archive.to_path_buf();
+        let cache_dir = self.cache_dir();
+        let cache = PackageCache::new(&cache_dir);
+        let lock = cache
+            .get_or_fetch(
+                key,
+                move |destination| {
+                    let archive = archive.clone();
+                    async move {
+                        rattler_package_streaming::tokio::fs::extract(&archive, &destination)
+                            .await
+                            .map(|_| ())
                     }
-                }
-            }
-            RepositoryType::Cache => {
-                // Cache doesn't need repository finalization - packages are stored individually
-                info!("Cache repositories don't require repodata generation - packages are cached individually");
-            }
-            RepositoryType::S3 => {
-                // For S3, repodata is updated per package upload
-                info!("S3 repositories update repodata per upload");
-            }
-            RepositoryType::PrefixDev => {
-                // prefix.dev handles repodata automatically
-                info!("prefix.dev handles repodata generation automatically");
-            }
-        }
+                },
+                None,
+            )
+            .await
+            .map_err(|e| MirrorError::target_io(&cache_dir, std::io::Error::other(e)))?;
+        debug!("Extracted {} to {:?}", package.filename, lock.path());
+        Ok(())
+    }
+}
 
-        let stats = self.get_package_stats();
-        stats.print_summary();
+#[async_trait]
+impl RepositoryBackend for CacheBackend {
+    // The cache is flat, so where a package is stored does not depend on its platform
+    fn location(&self, _platform: &Platform, filename: &str) -> String {
+        normalize_local_path(&self.path)
+            .join(filename)
+            .to_string_lossy()
+            .to_string()
+    }
 
-        Ok(())
+    async fn preflight(&self) -> Result<()> {
+        local_preflight(&self.path)
     }
 
-    async fn upload_cache(&mut self, package: &ProcessedPackage) -> Result<()> {
+    async fn exists(&self, _platform: &Platform, filename: &str) -> Result<Option<String>> {
+        read_existing_sha256(&local_package_path(&self.cache_dir(), filename)?)
+    }
+
+    async fn upload(&self, package: &ProcessedPackage) -> Result<()> {
         info!(
             "Caching package {} in cache directory at {}",
             package.filename, self.path
         );
 
-        // For cache, we don't create repository structures
-        // Instead, we would use PackageCache to store the individual package
-        // However, PackageCache expects to fetch packages, not store already processed ones
-        // So for now, we'll store the package file directly in the cache structure
+        // PackageCache expects to fetch packages rather than store already processed
+        // ones, so the package file is written directly into the cache directory.
+        // Other processes may read the cache meanwhile, so the archive only
+        // appears under its name once it is complete.
+        let cache_dir = self.cache_dir();
+        std::fs::create_dir_all(&cache_dir).map_err(|e| MirrorError::target_io(&cache_dir, e))?;
 
-        let cache_dir = Path::new(&self.path);
-        std::fs::create_dir_all(cache_dir)?;
-
-        // Store package file directly in cache
-        let package_path = cache_dir.join(&package.filename);
-        std::fs::write(&package_path, &package.content)?;
+        let package_path = local_package_path(&cache_dir, &package.filename)?;
+        let partial = cache_dir.join(format!(".{}.partial", package.filename));
+        std::fs::write(&partial, &package.content)
+            .map_err(|e| MirrorError::target_io(&partial, e))?;
+        std::fs::rename(&partial, &package_path)
+            .map_err(|e| MirrorError::target_io(&package_path, e))?;
+        if self.extract {
+            self.extract(package, &package_path).await?;
+        }
 
         info!(
             "Package {} cached successfully at {:?}",
             package.filename, package_path
         );
-
         Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    async fn list(&self) -> Result<Vec<String>> {
+        let mut stored: Vec<String> = read_dir_names(&normalize_local_path(&self.path))?
+            .into_iter()
+            .filter(|filename| CondaPackageHandler::is_conda_package(filename))
+            .collect();
+        stored.sort();
+        Ok(stored)
+    }
 
-    #[test]
-    fn test_repository_type_from_string() {
-        assert!(matches!(
-            RepositoryType::from_string("prefix-dev").unwrap(),
-            RepositoryType::PrefixDev
-        ));
-        assert!(matches!(
-            RepositoryType::from_string("prefix").unwrap(),
-            RepositoryType::PrefixDev
-        ));
-        assert!(matches!(
-            RepositoryType::from_string("s3").unwrap(),
-            RepositoryType::S3
-        ));
-        assert!(matches!(
-            RepositoryType::from_string("minio").unwrap(),
-            RepositoryType::S3
-        ));
-        assert!(matches!(
-            RepositoryType::from_string("local").unwrap(),
-            RepositoryType::Local
-        ));
-        assert!(matches!(
-            RepositoryType::from_string("file").unwrap(),
-            RepositoryType::Local
-        ));
-        assert!(matches!(
-            RepositoryType::from_string("cache").unwrap(),
-            RepositoryType::Cache
-        ));
-        assert!(RepositoryType::from_string("invalid").is_err());
+    async fn stored_at(&self) -> Result<HashMap<String, DateTime<Utc>>> {
+        local_modification_times(&normalize_local_path(&self.path), self.list().await?)
     }
 
-    #[test]
-    fn test_repository_new() {
-        let repo = Repository::new(RepositoryType::Local, "/tmp/test".to_string());
-        assert!(matches!(repo.repo_type, RepositoryType::Local));
-        assert_eq!(repo.path, "/tmp/test");
+    async fn store_attestation(
+        &self,
+        _platform: &Platform,
+        filename: &str,
+        statement: &[u8],
+    ) -> Result<bool> {
+        let path = local_package_path(&self.cache_dir(), &attestation_filename(filename))?;
+        std::fs::write(&path, statement).map_err(|e| MirrorError::target_io(&path, e))?;
+        Ok(true)
     }
 
-    #[test]
-    fn test_cache_repository_has_package_cache() {
-        let cache_repo = Repository::new(RepositoryType::Cache, "/tmp/cache".to_string());
-        assert!(matches!(cache_repo.repo_type, RepositoryType::Cache));
+    async fn store_signature(
+        &self,
+        _platform: &Platform,
+        filename: &str,
+        signature: &[u8],
+    ) -> Result<bool> {
+        let path = local_package_path(&self.cache_dir(), filename)?;
+        std::fs::write(&path, signature).map_err(|e| MirrorError::target_io(&path, e))?;
+        Ok(true)
+    }
+
+    async fn finalize(&self, _packages: &HashMap<Platform, Vec<ProcessedPackage>>) -> Result<()> {
+        // Cache doesn't need repository finalization - packages are stored individually
+        info!("Cache repositories don't require repodata generation - packages are cached individually");
+        Ok(())
+    }
+
+    async fn delete(&self, _platform: &Platform, filename: &str) -> Result<()> {
+        let path = self.cache_dir().join(filename);
+        match std::fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(MirrorError::target_io(&path, e)),
+        }
+
+        // The extraction goes with the archive; its lock file is left for
+        // rattler, which may hold it in another process
+        if let Some(name) = Self::extracted_name(filename) {
+            let extracted = self.cache_dir().join(name);
+            match std::fs::remove_dir_all(&extracted) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(MirrorError::target_io(&extracted, e)),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A conda channel in an S3 (or MinIO) bucket, addressed as `s3://bucket/prefix`
+#[cfg(feature = "s3")]
+pub struct S3Backend {
+    path: String,
+    multipart: Option<MultipartSettings>,
+}
+
+/// When and how an S3 target splits packages into resumable parts
+#[cfg(feature = "s3")]
+struct MultipartSettings {
+    threshold: u64,
+    part_size: u64,
+    state_file: String,
+    /// Serializes updates of the state file by the uploads of this process
+    state_lock: std::sync::Mutex<()>,
+}
+
+#[cfg(feature = "s3")]
+impl MultipartSettings {
+    /// Change the recorded uploads and write them back
+    fn update<T>(&self, change: impl FnOnce(&mut MultipartState) -> T) -> Result<T> {
+        let _guard = self.state_lock.lock().unwrap();
+        let mut state = MultipartState::load(&self.state_file)?;
+        let changed = change(&mut state);
+        state.save(&self.state_file)?;
+        Ok(changed)
+    }
+}
+
+#[cfg(feature = "s3")]
+impl S3Backend {
+    pub fn new(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            multipart: None,
+        }
+    }
+
+    /// Upload packages of at least `threshold` bytes in parts, recorded in `state_file`
+    ///
+    /// A `threshold` of 0 uploads every package in one request.
+    pub fn with_multipart(mut self, threshold: u64, part_size: u64, state_file: String) -> Self {
+        self.multipart = (threshold > 0).then(|| MultipartSettings {
+            threshold,
+            part_size,
+            state_file,
+            state_lock: std::sync::Mutex::new(()),
+        });
+        self
+    }
+
+    fn key(&self, platform: &Platform, filename: &str) -> Result<(&str, String)> {
+        let (bucket, prefix) = s3_bucket_and_prefix(&self.path)?;
+        let key = if prefix.is_empty() {
+            format!("{}/{}", platform, filename)
+        } else {
+            format!("{}/{}/{}", prefix, platform, filename)
+        };
+        Ok((bucket, key))
+    }
+
+    fn root_key(&self, name: &str) -> Result<(&str, String)> {
+        let (bucket, prefix) = s3_bucket_and_prefix(&self.path)?;
+        let key = if prefix.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}/{}", prefix, name)
+        };
+        Ok((bucket, key))
+    }
+
+    /// Content of an object, or `None` if there is no object under `key`
+    async fn object(bucket: &str, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(Self::versioned_object(bucket, key)
+            .await?
+            .map(|(content, _)| content))
+    }
+
+    /// Content and ETag of an object, or `None` if there is no object under `key`
+    async fn versioned_object(
+        bucket: &str,
+        key: &str,
+    ) -> Result<Option<(Vec<u8>, Option<String>)>> {
+        let object = match Self::client()
+            .await
+            .get_object()
+            .bucket(bucket)
+            .key(key)
+            .send()
+            .await
+        {
+            Ok(object) => object,
+            Err(e)
+                if e.as_service_error()
+                    .is_some_and(|service_error| service_error.is_no_such_key()) =>
+            {
+                return Ok(None)
+            }
+            Err(e) => return Err(s3_error(key, e)),
+        };
+        let etag = object.e_tag.clone();
+        let content = object.body.collect().await.map_err(|e| {
+            MirrorError::TargetUpload(format!("Failed to read S3 object '{}': {}", key, e))
+        })?;
+        Ok(Some((content.into_bytes().to_vec(), etag)))
+    }
+
+    /// Replace the object under `key` with what `edit` makes of its content
+    ///
+    /// The object is only replaced if no other writer replaced it since it was
+    /// read: the PUT carries `If-Match` with the ETag that was read, or
+    /// `If-None-Match: *` when there was no object yet. When another writer got
+    /// in between, the object is read and edited again after a short, jittered
+    /// wait. Stores that do not support conditional writes get plain PUTs.
+    async fn update_object(&self, key: &str, edit: &ContentEdit<'_>) -> Result<bool> {
+        let (bucket, _) = s3_bucket_and_prefix(&self.path)?;
+        let client = Self::client().await;
+        let mut conditional = true;
+        for attempt in 1..=MAX_CONDITIONAL_WRITES {
+            let (existing, etag) = match Self::versioned_object(bucket, key).await? {
+                Some((content, etag)) => (Some(content), etag),
+                None => (None, None),
+            };
+            let Some(content) = edit(existing.as_deref())? else {
+                return Ok(false);
+            };
+            let mut request = client
+                .put_object()
+                .bucket(bucket)
+                .key(key)
+                .body(content.into())
+                .content_type(if key.ends_with(".json") {
+                    "application/json"
+                } else {
+                    "application/octet-stream"
+                });
+            if conditional {
+                request = match (&existing, etag) {
+                    (None, _) => request.if_none_match("*"),
+                    (Some(_), Some(etag)) => request.if_match(etag),
+                    (Some(_), None) => request,
+                };
+            }
+            let err = match request.send().await {
+                Ok(_) => return Ok(true),
+                Err(e) => e,
+            };
+            match err.code() {
+                Some(code) if conditional && is_write_conflict(code) => {
+                    let delay = conflict_delay(attempt);
+                    debug!(
+                        "{} was changed by another writer; merging again in {:?} (attempt {}/{})",
+                        key, delay, attempt, MAX_CONDITIONAL_WRITES
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                Some("NotImplemented") if conditional => {
+                    warn!(
+                        "{} does not support conditional writes; concurrent writers may overwrite each other's changes to {}",
+                        self.path, key
+                    );
+                    conditional = false;
+                }
+                _ => return Err(s3_error(key, err)),
+            }
+        }
+        Err(MirrorError::TargetUpload(format!(
+            "S3 object '{}' kept changing under other writers; gave up after {} attempts",
+            key, MAX_CONDITIONAL_WRITES
+        )))
+    }
+
+    async fn client() -> aws_sdk_s3::Client {
+        let config = aws_config::defaults(aws_config::BehaviorVersion::latest())
+            .load()
+            .await;
+        aws_sdk_s3::Client::new(&config)
+    }
+
+    /// Stored packages relative to the prefix, with when they were last written
+    async fn list_packages(&self) -> Result<Vec<(String, Option<DateTime<Utc>>)>> {
+        let (bucket, prefix) = s3_bucket_and_prefix(&self.path)?;
+        let list_prefix = if prefix.is_empty() {
+            String::new()
+        } else {
+            format!("{}/", prefix)
+        };
+
+        let client = Self::client().await;
+        let mut stored = Vec::new();
+        let mut continuation_token = None;
+        loop {
+            let response = client
+                .list_objects_v2()
+                .bucket(bucket)
+                .prefix(&list_prefix)
+                .set_continuation_token(continuation_token)
+                .send()
+                .await
+                .map_err(|e| s3_error(&list_prefix, e))?;
+
+            stored.extend(response.contents().iter().filter_map(|object| {
+                let key = object.key()?.strip_prefix(&list_prefix)?;
+                CondaPackageHandler::is_conda_package(key).then(|| {
+                    let modified = object.last_modified().and_then(|time| {
+                        DateTime::from_timestamp(time.secs(), time.subsec_nanos())
+                    });
+                    (key.to_string(), modified)
+                })
+            }));
+
+            match response.next_continuation_token() {
+                Some(token) => continuation_token = Some(token.to_string()),
+                None => break,
+            }
+        }
+
+        Ok(stored)
+    }
+
+    /// Upload a package in parts, continuing an interrupted upload of it if S3 still has one
+    async fn upload_parts(
+        &self,
+        client: &aws_sdk_s3::Client,
+        multipart: &MultipartSettings,
+        bucket: &str,
+        key: &str,
+        package: &ProcessedPackage,
+    ) -> Result<()> {
+        use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart};
+
+        let object = format!("{}/{}", bucket, key);
+        let content = &package.content;
+        let part_size = multipart::part_size(content.len() as u64, multipart.part_size);
+
+        let (pending, stale) = multipart.update(|state| {
+            match state.resumable(&object, &package.sha256, part_size) {
+                Some(pending) => (Some(pending.clone()), None),
+                None => (None, state.uploads.remove(&object)),
+            }
+        })?;
+        if let Some(stale) = stale {
+            // The key now gets other content, so the parts of the old upload are of no use
+            if let Err(e) = client
+                .abort_multipart_upload()
+                .bucket(bucket)
+                .key(key)
+                .upload_id(&stale.upload_id)
+                .send()
+                .await
+            {
+                warn!(
+                    "Failed to abort the earlier upload to {}: {}",
+                    object,
+                    s3_error(key, e)
+                );
+            }
+        }
+
+        let resumed = match pending {
+            Some(pending) => {
+                match Self::uploaded_parts(client, bucket, key, &pending.upload_id).await {
+                    Ok(uploaded) => {
+                        let parts: BTreeMap<i32, String> = pending
+                            .parts
+                            .into_iter()
+                            .filter(|(number, etag)| uploaded.get(number) == Some(etag))
+                            .collect();
+                        info!(
+                            "Resuming the upload of {} with {} parts already at S3",
+                            object,
+                            parts.len()
+                        );
+                        Some((pending.upload_id, parts))
+                    }
+                    Err(e) => {
+                        warn!(
+                            "Cannot resume the upload of {}, starting over: {}",
+                            object, e
+                        );
+                        multipart.update(|state| state.uploads.remove(&object))?;
+                        None
+                    }
+                }
+            }
+            None => None,
+        };
+        let (upload_id, mut parts) = match resumed {
+            Some(resumed) => resumed,
+            None => {
+                let created = client
+                    .create_multipart_upload()
+                    .bucket(bucket)
+                    .key(key)
+                    .content_type("application/x-conda-package")
+                    .metadata("sha256", &package.sha256)
+                    .send()
+                    .await
+                    .map_err(|e| s3_error(key, e))?;
+                let upload_id = created.upload_id().map(str::to_string).ok_or_else(|| {
+                    MirrorError::TargetUpload(format!("S3 started no upload for '{}'", key))
+                })?;
+                let pending = PendingUpload {
+                    upload_id: upload_id.clone(),
+                    sha256: package.sha256.clone(),
+                    part_size,
+                    parts: BTreeMap::new(),
+                    started_at: Utc::now(),
+                };
+                multipart.update(|state| state.uploads.insert(object.clone(), pending))?;
+                (upload_id, BTreeMap::new())
+            }
+        };
+
+        for (number, range) in multipart::parts(content.len(), part_size) {
+            if parts.contains_key(&number) {
+                continue;
+            }
+            let uploaded = client
+                .upload_part()
+                .bucket(bucket)
+                .key(key)
+                .upload_id(&upload_id)
+                .part_number(number)
+                .body(content.slice(range).into())
+                .send()
+                .await
+                .map_err(|e| s3_error(key, e))?;
+            let etag = uploaded.e_tag().unwrap_or_default().to_string();
+            multipart.update(|state| {
+                if let Some(pending) = state.uploads.get_mut(&object) {
+                    pending.parts.insert(number, etag.clone());
+                }
+            })?;
+            parts.insert(number, etag);
+        }
+
+        let completed = CompletedMultipartUpload::builder()
+            .set_parts(Some(
+                parts
+                    .into_iter()
+                    .map(|(number, etag)| {
+                        CompletedPart::builder()
+                            .part_number(number)
+                            .e_tag(etag)
+                            .build()
+                    })
+                    .collect(),
+            ))
+            .build();
+        client
+            .complete_multipart_upload()
+            .bucket(bucket)
+            .key(key)
+            .upload_id(&upload_id)
+            .multipart_upload(completed)
+            .send()
+            .await
+            .map_err(|e| s3_error(key, e))?;
+        multipart.update(|state| state.uploads.remove(&object))?;
+        Ok(())
+    }
+
+    /// ETags of the parts S3 holds for an upload, by part number
+    async fn uploaded_parts(
+        client: &aws_sdk_s3::Client,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+    ) -> Result<BTreeMap<i32, String>> {
+        let mut uploaded = BTreeMap::new();
+        let mut marker = None;
+        loop {
+            let response = client
+                .list_parts()
+                .bucket(bucket)
+                .key(key)
+                .upload_id(upload_id)
+                .set_part_number_marker(marker)
+                .send()
+                .await
+                .map_err(|e| s3_error(key, e))?;
+            uploaded.extend(
+                response
+                    .parts()
+                    .iter()
+                    .filter_map(|part| Some((part.part_number()?, part.e_tag()?.to_string()))),
+            );
+            match response.next_part_number_marker() {
+                Some(next) if response.is_truncated() == Some(true) => {
+                    marker = Some(next.to_string())
+                }
+                _ => break,
+            }
+        }
+        Ok(uploaded)
+    }
+
+    /// Add the records of `packages` to the `repodata.json` of a platform
+    ///
+    /// A put replaces the object whole, so readers never see a partial index,
+    /// and records other writers added in the meantime are merged, not lost.
+    async fn upload_repodata(
+        &self,
+        platform: &Platform,
+        packages: &[ProcessedPackage],
+    ) -> Result<()> {
+        self.update_repodata(platform, &|existing| {
+            let repodata = CondaPackageHandler::merged_repodata(existing, platform, packages)?;
+            Ok(Some(serde_json::to_vec_pretty(&repodata)?))
+        })
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "s3")]
+#[async_trait]
+impl RepositoryBackend for S3Backend {
+    fn location(&self, platform: &Platform, filename: &str) -> String {
+        match self.key(platform, filename) {
+            Ok((bucket, key)) => format!("s3://{}/{}", bucket, key),
+            Err(_) => format!("{}/{}/{}", self.path, platform, filename),
+        }
+    }
+
+    async fn preflight(&self) -> Result<()> {
+        let (bucket, prefix) = s3_bucket_and_prefix(&self.path)?;
+        let key = if prefix.is_empty() {
+            PREFLIGHT_MARKER.to_string()
+        } else {
+            format!("{}/{}", prefix, PREFLIGHT_MARKER)
+        };
+
+        let client = Self::client().await;
+        client
+            .put_object()
+            .bucket(bucket)
+            .key(&key)
+            .body(Bytes::from_static(b"preflight").into())
+            .send()
+            .await
+            .map_err(|e| s3_error(&key, e))?;
+        client
+            .delete_object()
+            .bucket(bucket)
+            .key(&key)
+            .send()
+            .await
+            .map_err(|e| s3_error(&key, e))?;
+        Ok(())
+    }
+
+    async fn exists(&self, platform: &Platform, filename: &str) -> Result<Option<String>> {
+        let (bucket, key) = self.key(platform, filename)?;
+        s3_existing_sha256(&Self::client().await, bucket, &key).await
+    }
+
+    async fn upload(&self, package: &ProcessedPackage) -> Result<()> {
+        info!(
+            "Uploading {} to S3 repository at {} (platform: {})",
+            package.filename, self.path, package.platform
+        );
+
+        let (bucket, structured_key) = self.key(&package.platform, &package.filename)?;
+        let client = Self::client().await;
+
+        match &self.multipart {
+            Some(multipart) if package.content.len() as u64 >= multipart.threshold => {
+                self.upload_parts(&client, multipart, bucket, &structured_key, package)
+                    .await?
+            }
+            _ => {
+                // Record the sha256 so later runs can detect conflicts without downloading
+                client
+                    .put_object()
+                    .bucket(bucket)
+                    .key(&structured_key)
+                    .body(package.content.clone().into())
+                    .content_type("application/x-conda-package")
+                    .metadata("sha256", &package.sha256)
+                    .send()
+                    .await
+                    .map_err(|e| s3_error(&structured_key, e))?;
+            }
+        }
+
+        // Generate and upload repodata.json for this platform
+        self.upload_repodata(&package.platform, std::slice::from_ref(package))
+            .await?;
+
+        info!(
+            "Successfully uploaded {} to S3 under {}/",
+            package.filename, package.platform
+        );
+        Ok(())
+    }
+
+    async fn list(&self) -> Result<Vec<String>> {
+        let mut stored: Vec<String> = self
+            .list_packages()
+            .await?
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        stored.sort();
+        Ok(stored)
+    }
+
+    async fn stored_at(&self) -> Result<HashMap<String, DateTime<Utc>>> {
+        Ok(self
+            .list_packages()
+            .await?
+            .into_iter()
+            .filter_map(|(key, modified)| Some((key, modified?)))
+            .collect())
+    }
+
+    async fn store_attestation(
+        &self,
+        platform: &Platform,
+        filename: &str,
+        statement: &[u8],
+    ) -> Result<bool> {
+        let (bucket, key) = self.key(platform, &attestation_filename(filename))?;
+        Self::client()
+            .await
+            .put_object()
+            .bucket(bucket)
+            .key(&key)
+            .body(Bytes::copy_from_slice(statement).into())
+            .content_type("application/json")
+            .send()
+            .await
+            .map_err(|e| s3_error(&key, e))?;
+        Ok(true)
+    }
+
+    async fn store_signature(
+        &self,
+        platform: &Platform,
+        filename: &str,
+        signature: &[u8],
+    ) -> Result<bool> {
+        let (bucket, key) = self.key(platform, filename)?;
+        Self::client()
+            .await
+            .put_object()
+            .bucket(bucket)
+            .key(&key)
+            .body(Bytes::copy_from_slice(signature).into())
+            .content_type("application/pgp-signature")
+            .send()
+            .await
+            .map_err(|e| s3_error(&key, e))?;
+        Ok(true)
+    }
+
+    async fn package(&self, platform: &Platform, filename: &str) -> Result<Option<Bytes>> {
+        let (bucket, key) = self.key(platform, filename)?;
+        Ok(Self::object(bucket, &key).await?.map(Bytes::from))
+    }
+
+    async fn repodata(&self, platform: &Platform) -> Result<Option<Vec<u8>>> {
+        let (bucket, key) = self.key(platform, "repodata.json")?;
+        Self::object(bucket, &key).await
+    }
+
+    async fn store_repodata(&self, platform: &Platform, content: &[u8]) -> Result<bool> {
+        let (bucket, key) = self.key(platform, "repodata.json")?;
+        Self::client()
+            .await
+            .put_object()
+            .bucket(bucket)
+            .key(&key)
+            .body(Bytes::copy_from_slice(content).into())
+            .content_type("application/json")
+            .send()
+            .await
+            .map_err(|e| s3_error(&key, e))?;
+        Ok(true)
+    }
+
+    async fn update_repodata(&self, platform: &Platform, edit: &ContentEdit<'_>) -> Result<bool> {
+        let (_, key) = self.key(platform, "repodata.json")?;
+        self.update_object(&key, edit).await
+    }
+
+    async fn versioned_repodata(
+        &self,
+        platform: &Platform,
+    ) -> Result<Option<(Vec<u8>, Option<String>)>> {
+        let (bucket, key) = self.key(platform, "repodata.json")?;
+        Self::versioned_object(bucket, &key).await
+    }
+
+    async fn repodata_version(&self, platform: &Platform) -> Result<Option<String>> {
+        let (bucket, key) = self.key(platform, "repodata.json")?;
+        match Self::client()
+            .await
+            .head_object()
+            .bucket(bucket)
+            .key(&key)
+            .send()
+            .await
+        {
+            Ok(head) => Ok(head.e_tag),
+            Err(e)
+                if e.as_service_error()
+                    .is_some_and(|service_error| service_error.is_not_found()) =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(s3_error(&key, e)),
+        }
+    }
+
+    async fn channel_file(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        let (bucket, key) = self.root_key(name)?;
+        Self::object(bucket, &key).await
+    }
+
+    async fn update_channel_file(&self, name: &str, edit: &ContentEdit<'_>) -> Result<bool> {
+        let (_, key) = self.root_key(name)?;
+        self.update_object(&key, edit).await
+    }
+
+    // A single PUT replaces the object atomically
+    async fn store_channel_file(&self, name: &str, content: &[u8]) -> Result<bool> {
+        let (bucket, key) = self.root_key(name)?;
+        Self::client()
+            .await
+            .put_object()
+            .bucket(bucket)
+            .key(&key)
+            .body(Bytes::copy_from_slice(content).into())
+            .content_type(if name.ends_with(".json") {
+                "application/json"
+            } else {
+                "application/octet-stream"
+            })
+            .send()
+            .await
+            .map_err(|e| s3_error(&key, e))?;
+        Ok(true)
+    }
+
+    async fn delete_channel_file(&self, name: &str) -> Result<()> {
+        let (bucket, key) = self.root_key(name)?;
+        Self::client()
+            .await
+            .delete_object()
+            .bucket(bucket)
+            .key(&key)
+            .send()
+            .await
+            .map_err(|e| s3_error(&key, e))?;
+        Ok(())
+    }
+
+    /// Abort the multipart uploads under the target's prefix, including those of other tools
+    async fn abort_incomplete_uploads(&self, dry_run: bool) -> Result<Vec<String>> {
+        let (bucket, prefix) = s3_bucket_and_prefix(&self.path)?;
+        let list_prefix = if prefix.is_empty() {
+            String::new()
+        } else {
+            format!("{}/", prefix)
+        };
+
+        let client = Self::client().await;
+        let mut incomplete = Vec::new();
+        let (mut key_marker, mut upload_id_marker) = (None, None);
+        loop {
+            let response = client
+                .list_multipart_uploads()
+                .bucket(bucket)
+                .prefix(&list_prefix)
+                .set_key_marker(key_marker)
+                .set_upload_id_marker(upload_id_marker)
+                .send()
+                .await
+                .map_err(|e| s3_error(&list_prefix, e))?;
+            for upload in response.uploads() {
+                let (Some(key), Some(upload_id)) = (upload.key(), upload.upload_id()) else {
+                    continue;
+                };
+                incomplete.push((
+                    key.to_string(),
+                    upload_id.to_string(),
+                    upload.initiated().map(|initiated| initiated.to_string()),
+                ));
+            }
+            if response.is_truncated() != Some(true) {
+                break;
+            }
+            key_marker = response.next_key_marker().map(str::to_string);
+            upload_id_marker = response.next_upload_id_marker().map(str::to_string);
+        }
+
+        let mut aborted = Vec::new();
+        for (key, upload_id, initiated) in incomplete {
+            if !dry_run {
+                client
+                    .abort_multipart_upload()
+                    .bucket(bucket)
+                    .key(&key)
+                    .upload_id(&upload_id)
+                    .send()
+                    .await
+                    .map_err(|e| s3_error(&key, e))?;
+                if let Some(multipart) = &self.multipart {
+                    let object = format!("{}/{}", bucket, key);
+                    multipart.update(|state| state.uploads.remove(&object))?;
+                }
+            }
+            aborted.push(match initiated {
+                Some(initiated) => format!("{} (started {})", key, initiated),
+                None => key,
+            });
+        }
+        Ok(aborted)
+    }
+
+    async fn finalize(&self, packages: &HashMap<Platform, Vec<ProcessedPackage>>) -> Result<()> {
+        for (platform, packages) in packages {
+            self.upload_repodata(platform, packages).await?;
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, platform: &Platform, filename: &str) -> Result<()> {
+        let (bucket, key) = self.key(platform, filename)?;
+        Self::client()
+            .await
+            .delete_object()
+            .bucket(bucket)
+            .key(&key)
+            .send()
+            .await
+            .map_err(|e| s3_error(&key, e))?;
+        Ok(())
+    }
+}
+
+/// How uploads to a prefix.dev channel are grouped and retried
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UploadBatching {
+    /// Packages queued before a batch is uploaded; 1 uploads every package at once
+    pub max_packages: usize,
+    /// Queued bytes that also start a batch; 0 leaves the size unlimited
+    pub max_bytes: u64,
+    /// Uploads of a batch that run at once
+    pub parallelism: usize,
+    /// Attempts per package before a 429 or 5xx answer fails it
+    pub attempts: u32,
+}
+
+impl Default for UploadBatching {
+    fn default() -> Self {
+        Self {
+            max_packages: 1,
+            max_bytes: 0,
+            parallelism: 1,
+            attempts: 3,
+        }
+    }
+}
+
+/// A prefix.dev channel, addressed by its URL
+///
+/// Packages are queued and uploaded in batches as set by [`UploadBatching`];
+/// whatever is still queued is uploaded when the repository is finalized,
+/// after which the channel's repodata is checked for every uploaded package.
+pub struct PrefixDevBackend {
+    url: String,
+    client: reqwest::Client,
+    batching: UploadBatching,
+    queue: tokio::sync::Mutex<Vec<ProcessedPackage>>,
+    uploaded: std::sync::Mutex<Vec<(Platform, String)>>,
+    failed: std::sync::Mutex<Vec<FailedUpload>>,
+}
+
+impl PrefixDevBackend {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self::with_client(url, reqwest::Client::new())
+    }
+
+    /// Use a caller-supplied client for all requests to the channel
+    pub fn with_client(url: impl Into<String>, client: reqwest::Client) -> Self {
+        Self {
+            url: url.into(),
+            client,
+            batching: UploadBatching::default(),
+            queue: tokio::sync::Mutex::new(Vec::new()),
+            uploaded: std::sync::Mutex::new(Vec::new()),
+            failed: std::sync::Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Group and retry uploads as set by `batching`
+    pub fn with_batching(mut self, batching: UploadBatching) -> Self {
+        self.batching = batching;
+        self
+    }
+
+    /// Upload one package, waiting out 429 and 5xx answers as long as attempts remain
+    async fn upload_one(&self, package: &ProcessedPackage) -> Result<()> {
+        let structured_url = self.location(&package.platform, &package.filename);
+        let attempts = self.batching.attempts.max(1);
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let response = self
+                .client
+                .put(&structured_url)
+                .header("Content-Type", "application/x-conda-package")
+                .body(package.content.clone())
+                .send()
+                .await;
+            let (error, retry_after) = match response {
+                Ok(response) if response.status().is_success() => {
+                    info!(
+                        "Successfully uploaded {} to prefix.dev under {}/",
+                        package.filename, package.platform
+                    );
+                    return Ok(());
+                }
+                Ok(response) => {
+                    let status = response.status();
+                    let retry_after = retry_after(response.headers());
+                    let error_text = response.text().await.unwrap_or_default();
+                    let error = match MirrorError::from_status(
+                        status,
+                        "Failed to upload to prefix.dev",
+                        &error_text,
+                    ) {
+                        MirrorError::RateLimited { message, .. } => MirrorError::RateLimited {
+                            message,
+                            retry_after,
+                        },
+                        error => error,
+                    };
+                    (error, retry_after)
+                }
+                Err(e) => (MirrorError::from(e), None),
+            };
+            if attempt >= attempts || !error.is_retryable() {
+                return Err(error);
+            }
+
+            let delay = retry_after
+                .unwrap_or_else(|| std::time::Duration::from_secs(2_u64.pow(attempt - 1)));
+            warn!(
+                "Upload of {} failed (attempt {}/{}): {}, retrying in {}s",
+                package.filename,
+                attempt,
+                attempts,
+                error,
+                delay.as_secs()
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Upload the queued packages, `parallelism` at a time
+    ///
+    /// Packages that fail are kept for [`RepositoryBackend::take_failed_uploads`].
+    async fn flush(&self, queue: &mut Vec<ProcessedPackage>) {
+        if queue.is_empty() {
+            return;
+        }
+        let batch = std::mem::take(queue);
+        let batch_size = batch.len();
+        if batch_size > 1 {
+            info!(
+                "Uploading a batch of {} packages to prefix.dev at {}",
+                batch_size, self.url
+            );
+        }
+
+        let results: Vec<(ProcessedPackage, Result<()>)> = futures::stream::iter(batch)
+            .map(|package| async move {
+                let result = self.upload_one(&package).await;
+                (package, result)
+            })
+            .buffer_unordered(self.batching.parallelism.max(1))
+            .collect()
+            .await;
+
+        for (package, result) in results {
+            match result {
+                Ok(()) => self
+                    .uploaded
+                    .lock()
+                    .unwrap()
+                    .push((package.platform, package.filename)),
+                Err(error) => {
+                    warn!(
+                        "Failed to upload {} to prefix.dev: {}",
+                        package.filename, error
+                    );
+                    self.failed.lock().unwrap().push(FailedUpload {
+                        platform: package.platform,
+                        error: MirrorError::TargetUpload(format!(
+                            "{}: {}",
+                            package.filename, error
+                        )),
+                        filename: package.filename,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Warn about uploaded packages that the channel's repodata does not list yet
+    async fn validate(&self) -> Result<()> {
+        let uploaded = std::mem::take(&mut *self.uploaded.lock().unwrap());
+        if uploaded.is_empty() {
+            return Ok(());
+        }
+        let listed: std::collections::HashSet<String> = self.list().await?.into_iter().collect();
+        let missing: Vec<String> = uploaded
+            .iter()
+            .map(|(platform, filename)| format!("{}/{}", platform, filename))
+            .filter(|path| !listed.contains(path))
+            .collect();
+        if missing.is_empty() {
+            info!(
+                "prefix.dev channel {} lists all {} uploaded packages",
+                self.url,
+                uploaded.len()
+            );
+        } else {
+            warn!(
+                "prefix.dev channel {} does not list {} of {} uploaded packages yet: {}",
+                self.url,
+                missing.len(),
+                uploaded.len(),
+                missing.join(", ")
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Delay asked for by a `Retry-After` header given in seconds
+fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<std::time::Duration> {
+    headers
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .map(std::time::Duration::from_secs)
+}
+
+#[async_trait]
+impl RepositoryBackend for PrefixDevBackend {
+    fn location(&self, platform: &Platform, filename: &str) -> String {
+        format!(
+            "{}/{}/{}",
+            self.url.trim_end_matches('/'),
+            platform,
+            filename
+        )
+    }
+
+    async fn preflight(&self) -> Result<()> {
+        let response = self.client.get(&self.url).send().await?;
+        let status = response.status();
+        if matches!(
+            status,
+            reqwest::StatusCode::UNAUTHORIZED
+                | reqwest::StatusCode::FORBIDDEN
+                | reqwest::StatusCode::NOT_FOUND
+        ) {
+            return Err(MirrorError::from_status(
+                status,
+                &format!("prefix.dev channel {} is not accessible", self.url),
+                "",
+            ));
+        }
+        Ok(())
+    }
+
+    async fn exists(&self, platform: &Platform, filename: &str) -> Result<Option<String>> {
+        prefix_dev_existing_sha256(&self.client, &self.location(platform, filename)).await
+    }
+
+    async fn upload(&self, package: &ProcessedPackage) -> Result<()> {
+        info!(
+            "Uploading {} to prefix.dev at {} (platform: {})",
+            package.filename, self.url, package.platform
+        );
+
+        let mut queue = self.queue.lock().await;
+        queue.push(package.clone());
+        let queued_bytes: u64 = queue.iter().map(|package| package.size).sum();
+        if queue.len() >= self.batching.max_packages
+            || (self.batching.max_bytes > 0 && queued_bytes >= self.batching.max_bytes)
+        {
+            self.flush(&mut queue).await;
+        }
+
+        // Only this package's own failure is returned; the rest of its batch
+        // is reported through take_failed_uploads
+        let mut failed = self.failed.lock().unwrap();
+        match failed
+            .iter()
+            .position(|f| f.platform == package.platform && f.filename == package.filename)
+        {
+            Some(index) => Err(failed.remove(index).error),
+            None => Ok(()),
+        }
+    }
+
+    async fn list(&self) -> Result<Vec<String>> {
+        let mut stored = Vec::new();
+
+        // The channel's repodata is the only listing prefix.dev serves
+        for platform in Platform::all() {
+            let url = self.location(&platform, "repodata.json");
+            let response = self.client.get(&url).send().await?;
+            let status = response.status();
+            if status == reqwest::StatusCode::NOT_FOUND {
+                continue;
+            }
+            if !status.is_success() {
+                let body = response.text().await.unwrap_or_default();
+                return Err(MirrorError::from_status(status, &url, &body));
+            }
+
+            let repodata: serde_json::Value = response.json().await?;
+            for section in ["packages", "packages.conda"] {
+                if let Some(packages) = repodata.get(section).and_then(|p| p.as_object()) {
+                    stored.extend(
+                        packages
+                            .keys()
+                            .map(|filename| format!("{}/{}", platform, filename)),
+                    );
+                }
+            }
+        }
+
+        stored.sort();
+        Ok(stored)
+    }
+
+    async fn finalize(&self, _packages: &HashMap<Platform, Vec<ProcessedPackage>>) -> Result<()> {
+        self.flush(&mut *self.queue.lock().await).await;
+        // prefix.dev generates the repodata itself, so it is only checked
+        self.validate().await
+    }
+
+    fn take_failed_uploads(&self) -> Vec<FailedUpload> {
+        std::mem::take(&mut *self.failed.lock().unwrap())
+    }
+
+    async fn delete(&self, platform: &Platform, filename: &str) -> Result<()> {
+        Err(MirrorError::TargetUpload(format!(
+            "Deleting {} is not supported for prefix.dev channels",
+            self.location(platform, filename)
+        )))
+    }
+}
+
+/// A conda channel in an OCI registry, addressed as `oci://registry/path`
+///
+/// Packages are pushed as they are uploaded, in the layout rattler reads
+/// `oci://` channels from; the `repodata.json` of each subdir is merged with
+/// the one in the registry and pushed when the repository is finalized.
+pub struct OciBackend {
+    target: String,
+    registry: Option<(OciChannel, OciClient)>,
+}
+
+impl OciBackend {
+    pub fn new(target: impl Into<String>) -> Self {
+        Self::with_client(target, reqwest::Client::new(), None)
+    }
+
+    /// Use a caller-supplied client, and credentials instead of those of the environment
+    pub fn with_client(
+        target: impl Into<String>,
+        client: reqwest::Client,
+        credentials: Option<(String, String)>,
+    ) -> Self {
+        let target = target.into();
+        let registry = OciChannel::parse(&target).ok().map(|channel| {
+            let credentials = credentials.or_else(|| oci::env_credentials(&channel.registry));
+            let client = OciClient::new(client, channel.base_url(), credentials);
+            (channel, client)
+        });
+        Self { target, registry }
+    }
+
+    fn registry(&self) -> Result<(&OciChannel, &OciClient)> {
+        match &self.registry {
+            Some((channel, client)) => Ok((channel, client)),
+            None => Err(OciChannel::parse(&self.target)
+                .err()
+                .unwrap_or_else(|| MirrorError::InvalidInput(self.target.clone()))),
+        }
+    }
+
+    /// The manifest of a stored package and where it is kept
+    async fn package_manifest(
+        &self,
+        platform: &Platform,
+        filename: &str,
+    ) -> Result<Option<(oci::Manifest, PackageReference)>> {
+        let (channel, client) = self.registry()?;
+        let reference = channel.package(&platform.to_string(), filename)?;
+        Ok(client
+            .manifest(&reference.repository, &reference.tag)
+            .await?
+            .map(|(manifest, _)| (manifest, reference)))
+    }
+}
+
+#[async_trait]
+impl RepositoryBackend for OciBackend {
+    fn location(&self, platform: &Platform, filename: &str) -> String {
+        match self.registry() {
+            Ok((channel, _)) => match channel.package(&platform.to_string(), filename) {
+                Ok(reference) => format!(
+                    "oci://{}/{}:{}",
+                    channel.registry, reference.repository, reference.tag
+                ),
+                Err(_) => format!("{}/{}/{}", channel.url(), platform, filename),
+            },
+            Err(_) => format!("{}/{}/{}", self.target, platform, filename),
+        }
+    }
+
+    async fn preflight(&self) -> Result<()> {
+        let (channel, client) = self.registry()?;
+        client
+            .manifest(
+                &channel.repodata_repository(&Platform::NoArch.to_string()),
+                oci::REPODATA_TAG,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn exists(&self, platform: &Platform, filename: &str) -> Result<Option<String>> {
+        Ok(self
+            .package_manifest(platform, filename)
+            .await?
+            .and_then(|(manifest, reference)| {
+                Some(manifest.layer(reference.media_type)?.sha256()?.to_string())
+            }))
+    }
+
+    async fn upload(&self, package: &ProcessedPackage) -> Result<()> {
+        let (channel, client) = self.registry()?;
+        let reference = channel.package(&package.platform.to_string(), &package.filename)?;
+        info!(
+            "Pushing {} to {}/{}:{}",
+            package.filename, channel.registry, reference.repository, reference.tag
+        );
+
+        let record = serde_json::to_vec_pretty(&CondaPackageHandler::repodata_record(
+            package,
+            &package.platform,
+        ))?;
+        let layers = vec![
+            (
+                Descriptor::new(
+                    reference.media_type,
+                    &package.content,
+                    Some(&package.filename),
+                ),
+                package.content.clone(),
+            ),
+            (
+                Descriptor::new(oci::INDEX_MEDIA_TYPE, &record, Some("index.json")),
+                Bytes::from(record),
+            ),
+        ];
+        client
+            .push(&reference.repository, &reference.tag, layers)
+            .await
+    }
+
+    async fn list(&self) -> Result<Vec<String>> {
+        let mut stored = Vec::new();
+
+        // A registry cannot list the repositories below a path, so the repodata is the listing
+        for platform in Platform::all() {
+            let Some(content) = self.repodata(&platform).await? else {
+                continue;
+            };
+            let repodata: serde_json::Value = serde_json::from_slice(&content)?;
+            for section in ["packages", "packages.conda"] {
+                if let Some(packages) = repodata.get(section).and_then(|p| p.as_object()) {
+                    stored.extend(
+                        packages
+                            .keys()
+                            .map(|filename| format!("{}/{}", platform, filename)),
+                    );
+                }
+            }
+        }
+
+        stored.sort();
+        Ok(stored)
+    }
+
+    async fn package(&self, platform: &Platform, filename: &str) -> Result<Option<Bytes>> {
+        let Some((manifest, reference)) = self.package_manifest(platform, filename).await? else {
+            return Ok(None);
+        };
+        let Some(layer) = manifest.layer(reference.media_type) else {
+            return Ok(None);
+        };
+        let (_, client) = self.registry()?;
+        Ok(Some(
+            client.blob(&reference.repository, &layer.digest).await?,
+        ))
+    }
+
+    async fn repodata(&self, platform: &Platform) -> Result<Option<Vec<u8>>> {
+        let (channel, client) = self.registry()?;
+        let repository = channel.repodata_repository(&platform.to_string());
+        let Some((manifest, _)) = client.manifest(&repository, oci::REPODATA_TAG).await? else {
+            return Ok(None);
+        };
+        let Some(layer) = manifest.layer(oci::REPODATA_MEDIA_TYPE) else {
+            return Ok(None);
+        };
+        Ok(Some(
+            client.blob(&repository, &layer.digest).await?.to_vec(),
+        ))
+    }
+
+    async fn store_repodata(&self, platform: &Platform, content: &[u8]) -> Result<bool> {
+        let (channel, client) = self.registry()?;
+        let repository = channel.repodata_repository(&platform.to_string());
+        let layer = Descriptor::new(oci::REPODATA_MEDIA_TYPE, content, Some("repodata.json"));
+        client
+            .push(
+                &repository,
+                oci::REPODATA_TAG,
+                vec![(layer, Bytes::copy_from_slice(content))],
+            )
+            .await?;
+        Ok(true)
+    }
+
+    async fn finalize(&self, packages: &HashMap<Platform, Vec<ProcessedPackage>>) -> Result<()> {
+        for (platform, packages) in packages {
+            let existing = self.repodata(platform).await?;
+            let repodata =
+                CondaPackageHandler::merged_repodata(existing.as_deref(), platform, packages)?;
+            self.store_repodata(platform, &serde_json::to_vec_pretty(&repodata)?)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, platform: &Platform, filename: &str) -> Result<()> {
+        let (channel, client) = self.registry()?;
+        let reference = channel.package(&platform.to_string(), filename)?;
+        client.delete(&reference.repository, &reference.tag).await
+    }
+}
+
+/// Stands in for a target whose cargo feature was not compiled in
+#[cfg(not(feature = "s3"))]
+struct DisabledBackend {
+    feature: &'static str,
+}
+
+#[cfg(not(feature = "s3"))]
+impl DisabledBackend {
+    fn error(&self) -> MirrorError {
+        MirrorError::InvalidInput(format!(
+            "This target requires the '{}' feature, which this build was compiled without",
+            self.feature
+        ))
+    }
+}
+
+#[cfg(not(feature = "s3"))]
+#[async_trait]
+impl RepositoryBackend for DisabledBackend {
+    fn location(&self, platform: &Platform, filename: &str) -> String {
+        format!("{}/{}", platform, filename)
+    }
+
+    async fn preflight(&self) -> Result<()> {
+        Err(self.error())
+    }
+
+    async fn exists(&self, _platform: &Platform, _filename: &str) -> Result<Option<String>> {
+        Err(self.error())
+    }
+
+    async fn upload(&self, _package: &ProcessedPackage) -> Result<()> {
+        Err(self.error())
+    }
+
+    async fn list(&self) -> Result<Vec<String>> {
+        Err(self.error())
+    }
+
+    async fn finalize(&self, _packages: &HashMap<Platform, Vec<ProcessedPackage>>) -> Result<()> {
+        Err(self.error())
+    }
+
+    async fn delete(&self, _platform: &Platform, _filename: &str) -> Result<()> {
+        Err(self.error())
+    }
+}
+
+/// A package listed in the repodata of a target
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListedPackage {
+    pub platform: Platform,
+    pub filename: String,
+    /// sha256 recorded in the repodata
+    pub sha256: Option<String>,
+    pub size: Option<u64>,
+}
+
+/// Packages the repodata of every subdir of a target lists, sorted by path
+pub async fn listed_packages(backend: &dyn RepositoryBackend) -> Result<Vec<ListedPackage>> {
+    let mut packages = Vec::new();
+    for platform in Platform::all() {
+        let Some(content) = backend.repodata(&platform).await? else {
+            continue;
+        };
+        let repodata: serde_json::Value = serde_json::from_slice(&content)?;
+        for section in ["packages", "packages.conda"] {
+            let Some(records) = repodata.get(section).and_then(|p| p.as_object()) else {
+                continue;
+            };
+            packages.extend(records.iter().map(|(filename, record)| ListedPackage {
+                platform,
+                filename: filename.clone(),
+                sha256: record["sha256"].as_str().map(str::to_string),
+                size: record["size"].as_u64(),
+            }));
+        }
+    }
+    packages.sort_by(|a, b| {
+        (a.platform.as_str(), &a.filename).cmp(&(b.platform.as_str(), &b.filename))
+    });
+    Ok(packages)
+}
+
+/// A package a target accepted for a later batch that then failed to upload
+#[derive(Debug)]
+pub struct FailedUpload {
+    pub platform: Platform,
+    /// Name the package was stored under
+    pub filename: String,
+    pub error: MirrorError,
+}
+
+/// What [`Repository::upload_package`] did with a package
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UploadStatus {
+    /// The package was written to the target
+    Uploaded,
+    /// An identical copy was already stored, so nothing was written
+    AlreadyPresent,
+}
+
+pub struct Repository {
+    pub repo_type: RepositoryType,
+    pub path: String,
+    backend: Arc<dyn RepositoryBackend>,
+    conda_handler: CondaPackageHandler,
+    #[allow(dead_code)]
+    package_cache: Option<PackageCache>,
+    force_replace: bool,
+    /// Upload packages even when an identical copy is already stored
+    force: bool,
+    duplicate_platform_policy: DuplicatePlatformPolicy,
+    strict_platform: bool,
+    /// Record how each package's platform was decided
+    explain_platform: bool,
+    /// How the platform of this run's packages was decided, by original filename
+    platform_explanations: HashMap<String, String>,
+    filename_policy: FilenamePolicy,
+    /// Extract packages and check them against `info/paths.json` before uploading
+    verify_extraction: bool,
+    /// Where temporary files are made; the system temporary directory when unset
+    scratch_dir: Option<PathBuf>,
+    /// Bytes kept free on the scratch and local target file systems
+    min_free_space: u64,
+    /// Canonical filenames of this run's packages that were renamed, by original filename
+    renamed: HashMap<String, String>,
+    policy: Option<Policy>,
+    specs: Option<SpecFilter>,
+    metadata_upstream: Option<MetadataUpstream>,
+    signer: Option<Signer>,
+    channel_name: Option<String>,
+    platform_mappings: Vec<PlatformMapping>,
+    /// Copies of this run's packages published under alias subdirs
+    aliases: Vec<ProcessedPackage>,
+    /// Repodata snapshots kept at the target; 0 takes none
+    repodata_snapshots: usize,
+    /// Also publish each subdir's repodata as CEP-16 shards
+    sharded_repodata: bool,
+}
+
+impl Clone for Repository {
+    fn clone(&self) -> Self {
+        let package_cache = if matches!(self.repo_type, RepositoryType::Cache) {
+            Some(PackageCache::new(&self.path))
+        } else {
+            None
+        };
+
+        Self {
+            repo_type: self.repo_type.clone(),
+            path: self.path.clone(),
+            backend: Arc::clone(&self.backend),
+            conda_handler: CondaPackageHandler::new(),
+            package_cache,
+            force_replace: self.force_replace,
+            force: self.force,
+            duplicate_platform_policy: self.duplicate_platform_policy,
+            strict_platform: self.strict_platform,
+            explain_platform: self.explain_platform,
+            platform_explanations: HashMap::new(),
+            filename_policy: self.filename_policy,
+            verify_extraction: self.verify_extraction,
+            scratch_dir: self.scratch_dir.clone(),
+            min_free_space: self.min_free_space,
+            renamed: HashMap::new(),
+            policy: self.policy.clone(),
+            specs: self.specs.clone(),
+            metadata_upstream: self.metadata_upstream.clone(),
+            signer: self.signer.clone(),
+            channel_name: self.channel_name.clone(),
+            platform_mappings: self.platform_mappings.clone(),
+            aliases: Vec::new(),
+            repodata_snapshots: self.repodata_snapshots,
+            sharded_repodata: self.sharded_repodata,
+        }
+    }
+}
+
+impl Repository {
+    pub fn new(repo_type: RepositoryType, path: String) -> Self {
+        let (backend, package_cache): (Arc<dyn RepositoryBackend>, _) = match repo_type {
+            RepositoryType::Local => (Arc::new(LocalBackend::new(&path)), None),
+            #[cfg(feature = "s3")]
+            RepositoryType::S3 => (Arc::new(S3Backend::new(&path)), None),
+            #[cfg(not(feature = "s3"))]
+            RepositoryType::S3 => (Arc::new(DisabledBackend { feature: "s3" }), None),
+            RepositoryType::PrefixDev => (Arc::new(PrefixDevBackend::new(&path)), None),
+            RepositoryType::Oci => (Arc::new(OciBackend::new(&path)), None),
+            RepositoryType::Cache => (
+                Arc::new(CacheBackend::new(&path)),
+                Some(PackageCache::new(&path)),
+            ),
+        };
+
+        Self {
+            repo_type,
+            path,
+            backend,
+            conda_handler: CondaPackageHandler::new(),
+            package_cache,
+            force_replace: false,
+            force: false,
+            duplicate_platform_policy: DuplicatePlatformPolicy::default(),
+            strict_platform: false,
+            explain_platform: false,
+            platform_explanations: HashMap::new(),
+            filename_policy: FilenamePolicy::default(),
+            verify_extraction: false,
+            scratch_dir: None,
+            min_free_space: 0,
+            renamed: HashMap::new(),
+            policy: None,
+            specs: None,
+            metadata_upstream: None,
+            signer: None,
+            channel_name: None,
+            platform_mappings: Vec::new(),
+            aliases: Vec::new(),
+            repodata_snapshots: 0,
+            sharded_repodata: false,
+        }
+    }
+
+    /// Store packages through a custom backend, e.g. an internal artifact store
+    ///
+    /// `repo_type` names the layout the backend provides and `path` describes
+    /// the target in logs and resume state.
+    #[allow(dead_code)]
+    pub fn from_backend(
+        repo_type: RepositoryType,
+        path: impl Into<String>,
+        backend: Arc<dyn RepositoryBackend>,
+    ) -> Self {
+        Self {
+            backend,
+            ..Self::new(repo_type, path.into())
+        }
+    }
+
+    /// Allow replacing packages whose sha256 differs from the copy already at the target
+    pub fn with_force_replace(mut self, force_replace: bool) -> Self {
+        self.force_replace = force_replace;
+        self
+    }
+
+    /// Upload packages again even when an identical copy is already at the target
+    pub fn with_force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// Choose how to handle a filename seen twice with different detected platforms
+    pub fn with_duplicate_platform_policy(mut self, policy: DuplicatePlatformPolicy) -> Self {
+        self.duplicate_platform_policy = policy;
+        self
+    }
+
+    /// Refuse packages whose platform was not read from their own metadata
+    pub fn with_strict_platform(mut self, strict_platform: bool) -> Self {
+        self.strict_platform = strict_platform;
+        self
+    }
+
+    /// Print the rules that decided each package's platform as it is processed
+    pub fn with_explain_platform(mut self, explain: bool) -> Self {
+        self.explain_platform = explain;
+        self
+    }
+
+    /// Choose whether packages with a nonconforming filename are renamed or refused
+    pub fn with_filename_policy(mut self, policy: FilenamePolicy) -> Self {
+        self.filename_policy = policy;
+        self
+    }
+
+    /// Unpack packages into the `PackageCache` layout when the target is a cache
+    pub fn with_cache_extract(mut self, extract: bool) -> Self {
+        if extract && matches!(self.repo_type, RepositoryType::Cache) {
+            self.backend = Arc::new(CacheBackend::new(&self.path).with_extract(true));
+        }
+        self
+    }
+
+    /// Upload packages of at least `threshold` bytes to an S3 target in resumable parts
+    ///
+    /// Uploads in progress are recorded in `state_file`; a `threshold` of 0
+    /// uploads every package in one request. Other targets are not affected.
+    #[cfg_attr(not(feature = "s3"), allow(unused_variables, unused_mut))]
+    pub fn with_s3_multipart(mut self, threshold: u64, part_size: u64, state_file: &str) -> Self {
+        #[cfg(feature = "s3")]
+        if matches!(self.repo_type, RepositoryType::S3) {
+            self.backend = Arc::new(S3Backend::new(&self.path).with_multipart(
+                threshold,
+                part_size,
+                state_file.to_string(),
+            ));
+        }
+        self
+    }
+
+    /// Fully extract packages and check their files before they are uploaded
+    pub fn with_verify_extraction(mut self, verify: bool) -> Self {
+        self.verify_extraction = verify;
+        self
+    }
+
+    /// Make temporary files under `scratch_dir` and keep `min_free_space` bytes free
+    pub fn with_scratch_space(mut self, scratch_dir: Option<PathBuf>, min_free_space: u64) -> Self {
+        self.scratch_dir = scratch_dir;
+        self.min_free_space = min_free_space;
+        self
+    }
+
+    /// Check that a package of `size` bytes fits on the disks it will be written to
+    ///
+    /// Extraction checks need room for the unpacked files in the scratch
+    /// directory; local and cache targets need room for the package itself.
+    pub fn ensure_space(&self, size: u64) -> Result<()> {
+        if self.verify_extraction {
+            scratch::ensure_space(
+                &scratch::scratch_path(self.scratch_dir.as_deref()),
+                size.saturating_mul(scratch::EXTRACTION_FACTOR)
+                    .saturating_add(self.min_free_space),
+                "extracting packages",
+            )?;
+        }
+        if matches!(
+            self.repo_type,
+            RepositoryType::Local | RepositoryType::Cache
+        ) {
+            scratch::ensure_space(
+                &normalize_local_path(&self.path),
+                size.saturating_add(self.min_free_space),
+                "the target repository",
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Reject packages that fail an admission policy before they are uploaded
+    pub fn with_policy(mut self, policy: Option<Policy>) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Mirror only packages matching one of these match specs
+    pub fn with_specs(mut self, specs: Option<SpecFilter>) -> Self {
+        self.specs = specs;
+        self
+    }
+
+    /// Fill in a license or dependencies missing from package metadata from an upstream channel
+    pub fn with_metadata_upstream(mut self, upstream: Option<MetadataUpstream>) -> Self {
+        self.metadata_upstream = upstream;
+        self
+    }
+
+    /// Publish a detached signature next to every uploaded package and `repodata.json`
+    pub fn with_signer(mut self, signer: Option<Signer>) -> Self {
+        self.signer = signer;
+        self
+    }
+
+    /// Write a channel name into the repodata of the target and its package records
+    pub fn with_channel_name(mut self, channel_name: Option<String>) -> Self {
+        self.channel_name = channel_name;
+        self
+    }
+
+    /// Publish the packages of some subdirs under others, instead or as well
+    pub fn with_platform_mappings(mut self, mappings: Vec<PlatformMapping>) -> Self {
+        self.platform_mappings = mappings;
+        self
+    }
+
+    /// Keep up to `keep` snapshots of the target's repodata; 0 takes none
+    pub fn with_repodata_snapshots(mut self, keep: usize) -> Self {
+        self.repodata_snapshots = keep;
+        self
+    }
+
+    /// Publish sharded repodata next to each `repodata.json` the target writes
+    pub fn with_sharded_repodata(mut self, sharded_repodata: bool) -> Self {
+        self.sharded_repodata = sharded_repodata;
+        self
+    }
+
+    /// Check that the target accepts writes before any package is downloaded
+    ///
+    /// Local and cache targets get a marker file written and removed, S3 targets a
+    /// marker object, and prefix.dev channels a request that must not be refused.
+    /// Credential and permission problems therefore surface before the first download.
+    pub async fn preflight(&self) -> Result<()> {
+        info!("Checking that target {} is writable", self.path);
+        self.backend.preflight().await?;
+        #[cfg(not(feature = "sharded-repodata"))]
+        if self.sharded_repodata {
+            return Err(sharded_repodata_unavailable());
+        }
+        info!("Target {} passed preflight checks", self.path);
+        Ok(())
+    }
+
+    /// Leave a refused package out of the repodata written at finalization
+    fn forget_package(&mut self, filename: &str, previous: Option<ProcessedPackage>) {
+        self.conda_handler.remove_package(filename);
+        if let Some(previous) = previous {
+            self.conda_handler.record_package(previous);
+        }
+    }
+
+    pub async fn upload_package(
+        &mut self,
+        package_name: &str,
+        content: Bytes,
+    ) -> Result<UploadStatus> {
+        self.renamed.remove(package_name);
+        let mut previous = self.conda_handler.get_package(package_name).cloned();
+
+        // Process the conda package to extract metadata and validate
+        let mut processed_package = self
+            .conda_handler
+            .process_package(content, package_name)
+            .await?;
+        if self.explain_platform {
+            let explanation = self.platform_explanation(package_name, &processed_package);
+            self.platform_explanations
+                .insert(package_name.to_string(), explanation);
+        }
+
+        // Metadata that fell back to parsing the filename has no `subdir` and
+        // nothing to check the filename against
+        let checked = if CondaPackageHandler::platform_from_metadata(&processed_package.metadata) {
+            naming::nonconforming(&processed_package.metadata, package_name)
+        } else {
+            Ok(None)
+        };
+        let canonical = match checked {
+            Ok(canonical) => canonical,
+            Err(e) => {
+                self.forget_package(package_name, previous);
+                return Err(e);
+            }
+        };
+        if let Some(canonical) = canonical {
+            self.forget_package(package_name, previous);
+            if self.filename_policy == FilenamePolicy::Reject {
+                return Err(MirrorError::InvalidFilename {
+                    filename: package_name.to_string(),
+                    reason: format!("expected {} (see --filename-policy)", canonical),
+                });
+            }
+            warn!("Storing {} as {}", package_name, canonical);
+            previous = self.conda_handler.get_package(&canonical).cloned();
+            processed_package.metadata.name = processed_package.metadata.name.to_lowercase();
+            processed_package.filename = canonical.clone();
+            self.conda_handler.record_package(processed_package.clone());
+        }
+        let filename = processed_package.filename.clone();
+
+        if let Some(upstream) = &mut self.metadata_upstream {
+            if !upstream.enrich(&mut processed_package).await.is_empty() {
+                self.conda_handler.record_package(processed_package.clone());
+            }
+        }
+
+        if self.strict_platform
+            && !CondaPackageHandler::platform_from_metadata(&processed_package.metadata)
+        {
+            self.forget_package(&filename, previous);
+            return Err(MirrorError::GuessedPlatform {
+                filename: package_name.to_string(),
+                guessed: processed_package.platform.to_string(),
+            });
+        }
+
+        if let Some(specs) = &self.specs {
+            if !specs.matches(&processed_package) {
+                let mismatch = MirrorError::SpecMismatch {
+                    filename: package_name.to_string(),
+                    specs: specs.describe(),
+                };
+                self.forget_package(&filename, previous);
+                return Err(mismatch);
+            }
+        }
+
+        if let Some(policy) = &self.policy {
+            let reasons = policy.violations(&processed_package);
+            if !reasons.is_empty() {
+                let rejection = MirrorError::PolicyRejected {
+                    filename: package_name.to_string(),
+                    platform: processed_package.platform.to_string(),
+                    reasons: reasons.join("; "),
+                    quarantine: policy.quarantines(&processed_package),
+                    oversized: reasons.len() == 1 && policy.exceeds_max_size(&processed_package),
+                };
+                self.forget_package(&filename, previous);
+                return Err(rejection);
+            }
+        }
+
+        if self.verify_extraction {
+            let content = processed_package.content.clone();
+            let name = filename.clone();
+            let scratch_dir = self.scratch_dir.clone();
+            let verified = tokio::task::spawn_blocking(move || {
+                extraction::verify(&content, &name, scratch_dir.as_deref())
+            })
+            .await
+            .map_err(|e| MirrorError::Other(e.into()))?;
+            match verified {
+                Ok(verification) => {
+                    debug!(
+                        "{} extracted with {} intact paths",
+                        filename, verification.paths
+                    );
+                    for warning in &verification.relocation_warnings {
+                        warn!("{} may not be relocatable: {}", package_name, warning);
+                    }
+                }
+                Err(reason) => {
+                    self.forget_package(&filename, previous);
+                    return Err(MirrorError::ExtractionFailed {
+                        filename: package_name.to_string(),
+                        reason,
+                    });
+                }
+            }
+        }
+
+        let (platform, aliases) = self.mapped_platforms(processed_package.platform);
+        if platform != processed_package.platform {
+            info!(
+                "Publishing {} under {} instead of {}",
+                package_name, platform, processed_package.platform
+            );
+            processed_package.platform = platform;
+            self.conda_handler.record_package(processed_package.clone());
+        }
+
+        // The same filename was already processed in this run under another platform
+        if let Some(previous) = previous.filter(|p| p.platform != processed_package.platform) {
+            let platform = self.resolve_duplicate_platform(&previous, &processed_package);
+            let platform = match platform {
+                Ok(platform) => platform,
+                Err(e) => {
+                    // Keep the first copy as the one the repository knows about
+                    self.conda_handler.record_package(previous);
+                    return Err(e);
+                }
+            };
+
+            processed_package.platform = platform;
+            self.conda_handler.record_package(processed_package.clone());
+
+            if platform != previous.platform {
+                self.remove_superseded(&previous, platform).await?;
+            }
+        }
+
+        // Validate the package
+        self.conda_handler.validate_package(&processed_package)?;
+        if filename != package_name {
+            self.renamed.insert(package_name.to_string(), filename);
+        }
+
+        let status = self.publish(&processed_package).await?;
+        for alias in aliases {
+            let copy = ProcessedPackage {
+                platform: alias,
+                ..processed_package.clone()
+            };
+            self.publish(&copy).await?;
+            self.aliases
+                .retain(|p| (p.platform, &p.filename) != (alias, &copy.filename));
+            self.aliases.push(copy);
+        }
+        Ok(status)
+    }
+
+    /// Subdir a package would be published under, without storing or recording it
+    ///
+    /// Platform mappings apply; how a filename seen twice in a run is
+    /// resolved depends on the packages uploaded before it and does not.
+    pub async fn published_platform(&self, package_name: &str, content: Bytes) -> Result<Platform> {
+        let processed = CondaPackageHandler::new()
+            .process_package(content, package_name)
+            .await?;
+        Ok(self.mapped_platforms(processed.platform).0)
+    }
+
+    /// How the platform of a processed package was decided, and where it is published
+    fn platform_explanation(&self, package_name: &str, package: &ProcessedPackage) -> String {
+        let explanation = CondaPackageHandler::explain_platform(&package.metadata);
+        let mut lines = vec![format!("{}: {}", package_name, explanation.platform)];
+        lines.extend(
+            explanation
+                .to_string()
+                .lines()
+                .map(|line| format!("  {}", line)),
+        );
+        let (primary, aliases) = self.mapped_platforms(explanation.platform);
+        if primary != explanation.platform {
+            lines.push(format!("  platform_mappings: published under {}", primary));
+        }
+        for alias in aliases {
+            lines.push(format!(
+                "  platform_mappings: also published under {}",
+                alias
+            ));
+        }
+        lines.join("\n")
+    }
+
+    /// Subdir a package detected as `platform` is published under, and its aliases
+    fn mapped_platforms(&self, platform: Platform) -> (Platform, Vec<Platform>) {
+        let mut primary = platform;
+        let mut aliases = Vec::new();
+        for mapping in self.platform_mappings.iter().filter(|m| m.from == platform) {
+            if mapping.keep_original {
+                aliases.push(mapping.to);
+            } else {
+                primary = mapping.to;
+            }
+        }
+        aliases.retain(|alias| *alias != primary);
+        aliases.dedup();
+        (primary, aliases)
+    }
+
+    /// Store a validated package under its platform unless an identical copy is there
+    async fn publish(&self, package: &ProcessedPackage) -> Result<UploadStatus> {
+        let location = self.backend.location(&package.platform, &package.filename);
+        let existing_sha256 = self
+            .backend
+            .exists(&package.platform, &package.filename)
+            .await?;
+        if self.check_existing(package, &location, existing_sha256)? {
+            return Ok(UploadStatus::AlreadyPresent);
+        }
+
+        // Sign before uploading so a package is never published without its signature
+        let signature = match &self.signer {
+            Some(signer) => Some(
+                signer
+                    .sign(package.platform.as_str(), &package.content)
+                    .await?,
+            ),
+            None => None,
+        };
+        self.backend.upload(package).await?;
+        if let Some(signature) = signature {
+            self.store_signature(&package.platform, &package.filename, &signature)
+                .await?;
+        }
+        Ok(UploadStatus::Uploaded)
+    }
+
+    /// Pick the platform for a filename detected with two different platforms in one run
+    fn resolve_duplicate_platform(
+        &self,
+        previous: &ProcessedPackage,
+        current: &ProcessedPackage,
+    ) -> Result<Platform> {
+        let conflict = || MirrorError::PlatformConflict {
+            filename: current.filename.clone(),
+            first: previous.platform.to_string(),
+            second: current.platform.to_string(),
+        };
+
+        match self.duplicate_platform_policy {
+            DuplicatePlatformPolicy::Error => Err(conflict()),
+            DuplicatePlatformPolicy::KeepFirst => {
+                warn!(
+                    "{} was first processed as {} and is now detected as {}; keeping {}",
+                    current.filename, previous.platform, current.platform, previous.platform
+                );
+                Ok(previous.platform)
+            }
+            DuplicatePlatformPolicy::PreferMetadata => {
+                match (
+                    CondaPackageHandler::platform_from_metadata(&previous.metadata),
+                    CondaPackageHandler::platform_from_metadata(&current.metadata),
+                ) {
+                    (true, false) => {
+                        warn!(
+                            "{}: keeping metadata platform {} over guessed {}",
+                            current.filename, previous.platform, current.platform
+                        );
+                        Ok(previous.platform)
+                    }
+                    (false, true) => {
+                        warn!(
+                            "{}: replacing guessed platform {} with metadata platform {}",
+                            current.filename, previous.platform, current.platform
+                        );
+                        Ok(current.platform)
+                    }
+                    _ => Err(conflict()),
+                }
+            }
+        }
+    }
+
+    /// Remove a copy stored under a platform that was later corrected
+    async fn remove_superseded(
+        &self,
+        previous: &ProcessedPackage,
+        platform: Platform,
+    ) -> Result<()> {
+        let stale = self
+            .backend
+            .location(&previous.platform, &previous.filename);
+        if stale == self.backend.location(&platform, &previous.filename) {
+            return Ok(());
+        }
+
+        if let Err(e) = self
+            .backend
+            .delete(&previous.platform, &previous.filename)
+            .await
+        {
+            warn!(
+                "A copy of {} remains at {} and should be removed manually: {}",
+                previous.filename, stale, e
+            );
+            return Ok(());
+        }
+        info!("Removed superseded copy {}", stale);
+        self.unlist(&previous.platform, &[previous.filename.as_str()])
+            .await
+    }
+
+    /// Compare a package with the copy already stored at the target, if any
+    ///
+    /// Returns `Ok(true)` when an identical copy is already present so the write can be
+    /// skipped. A copy with a different sha256 is refused unless `force_replace` is set.
+    fn check_existing(
+        &self,
+        package: &ProcessedPackage,
+        location: &str,
+        existing_sha256: Option<String>,
+    ) -> Result<bool> {
+        let Some(existing_sha256) = existing_sha256 else {
+            return Ok(false);
+        };
+
+        if existing_sha256.eq_ignore_ascii_case(&package.sha256) {
+            if self.force {
+                info!(
+                    "{} already present at {} with identical sha256, uploading it again",
+                    package.filename, location
+                );
+                return Ok(false);
+            }
+            info!(
+                "{} already present at {} with identical sha256, skipping upload",
+                package.filename, location
+            );
+            return Ok(true);
+        }
+
+        if self.force_replace {
+            warn!(
+                "Replacing {} at {}: existing sha256 {} differs from new sha256 {}",
+                package.filename, location, existing_sha256, package.sha256
+            );
+            return Ok(false);
+        }
+
+        Err(MirrorError::ChecksumConflict {
+            filename: package.filename.clone(),
+            location: location.to_string(),
+            existing_sha256,
+            new_sha256: package.sha256.clone(),
+        })
+    }
+
+    /// Store a provenance attestation next to a package, if the target can hold one
+    pub async fn store_attestation(
+        &self,
+        platform: &Platform,
+        filename: &str,
+        statement: &[u8],
+    ) -> Result<bool> {
+        self.backend
+            .store_attestation(platform, filename, statement)
+            .await
+    }
+
+    /// Set the `channel` of the `repodata.json` of a platform and its package records
+    ///
+    /// Does nothing without a channel name or for targets that maintain their
+    /// repodata themselves.
+    async fn rename_channel(&self, platform: &Platform) -> Result<()> {
+        let Some(channel_name) = &self.channel_name else {
+            return Ok(());
+        };
+        let channel = serde_json::Value::from(channel_name.as_str());
+        self.backend
+            .update_repodata(platform, &|content| {
+                let Some(content) = content else {
+                    return Ok(None);
+                };
+                let mut repodata: serde_json::Value = serde_json::from_slice(content)?;
+                if let Some(info) = repodata.get_mut("info").and_then(|i| i.as_object_mut()) {
+                    info.insert("channel".to_string(), channel.clone());
+                }
+                for section in ["packages", "packages.conda"] {
+                    if let Some(packages) =
+                        repodata.get_mut(section).and_then(|p| p.as_object_mut())
+                    {
+                        for record in packages.values_mut().filter_map(|r| r.as_object_mut()) {
+                            record.insert("channel".to_string(), channel.clone());
+                        }
+                    }
+                }
+                Ok(Some(serde_json::to_vec_pretty(&repodata)?))
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// Sign the `repodata.json` of a platform, if a signer is configured
+    ///
+    /// On targets shared by several writers, the repodata must still be the
+    /// version that was signed once the signature is stored. When another
+    /// writer replaced it in between, its new content is signed again.
+    async fn sign_repodata(&self, platform: &Platform) -> Result<()> {
+        let Some(signer) = &self.signer else {
+            return Ok(());
+        };
+        for attempt in 1..=MAX_CONDITIONAL_WRITES {
+            let Some((repodata, version)) = self.backend.versioned_repodata(platform).await? else {
+                return Ok(());
+            };
+            let signature = signer.sign(platform.as_str(), &repodata).await?;
+            self.store_signature(platform, "repodata.json", &signature)
+                .await?;
+            if version.is_none() || self.backend.repodata_version(platform).await? == version {
+                return Ok(());
+            }
+            let delay = conflict_delay(attempt);
+            debug!(
+                "{}/repodata.json was changed by another writer while it was signed; signing again in {:?} (attempt {}/{})",
+                platform, delay, attempt, MAX_CONDITIONAL_WRITES
+            );
+            tokio::time::sleep(delay).await;
+        }
+        Err(MirrorError::Signing(format!(
+            "{}/repodata.json kept changing under other writers; gave up after {} attempts",
+            platform, MAX_CONDITIONAL_WRITES
+        )))
+    }
+
+    /// Write the shards of a platform's `repodata.json`, if sharded repodata is enabled
+    ///
+    /// New shards are written before the index that refers to them, and shards
+    /// only the previous index referred to are removed afterwards, so clients
+    /// never find the index pointing at a missing shard.
+    #[cfg(feature = "sharded-repodata")]
+    async fn shard_repodata(&self, platform: &Platform) -> Result<()> {
+        if !self.sharded_repodata {
+            return Ok(());
+        }
+        let Some(repodata) = self.backend.repodata(platform).await? else {
+            return Ok(());
+        };
+        let subdir = platform.as_str();
+        let sharded = shards::shard(subdir, &repodata)?;
+        let index_path = shards::index_path(subdir);
+        let previous_index_path = shards::previous_index_path(subdir);
+        let previous_index = self.backend.channel_file(&index_path).await?;
+        let listed = |index: Option<&[u8]>, path: &str| match index {
+            Some(index) => shards::listed_shards(index).unwrap_or_else(|e| {
+                warn!("Replacing unreadable {}: {}", path, e);
+                Default::default()
+            }),
+            None => Default::default(),
+        };
+        let previous = listed(previous_index.as_deref(), &index_path);
+        // Shards of the index the previous run replaced, which no client should still hold
+        let retired = listed(
+            self.backend
+                .channel_file(&previous_index_path)
+                .await?
+                .as_deref(),
+            &previous_index_path,
+        );
+
+        let mut written = 0;
+        for (hash, content) in &sharded.shards {
+            if previous.contains(hash) {
+                continue;
+            }
+            if !self
+                .backend
+                .store_channel_file(&shards::shard_path(subdir, hash), content)
+                .await?
+            {
+                warn!(
+                    "Target {} has nowhere to keep sharded repodata; only repodata.json was written",
+                    self.path
+                );
+                return Ok(());
+            }
+            written += 1;
+        }
+        // The replaced index keeps its shards until the next run, for clients
+        // that fetched it just before it was replaced
+        if let Some(previous_index) = &previous_index {
+            self.backend
+                .store_channel_file(&previous_index_path, previous_index)
+                .await?;
+        }
+        self.backend
+            .store_channel_file(&index_path, &sharded.index)
+            .await?;
+        for stale in retired
+            .iter()
+            .filter(|hash| !previous.contains(*hash) && !sharded.shards.contains_key(*hash))
+        {
+            self.backend
+                .delete_channel_file(&shards::shard_path(subdir, stale))
+                .await?;
+        }
+        info!(
+            "Wrote sharded repodata for {}: {} shards, {} new",
+            subdir,
+            sharded.shards.len(),
+            written
+        );
+        Ok(())
+    }
+
+    #[cfg(not(feature = "sharded-repodata"))]
+    async fn shard_repodata(&self, _platform: &Platform) -> Result<()> {
+        if self.sharded_repodata {
+            return Err(sharded_repodata_unavailable());
+        }
+        Ok(())
+    }
+
+    /// Move subdirs, or the default key, to `new_key` and publish the change
+    ///
+    /// The `repodata.json` of every subdir whose key changes is re-signed with
+    /// `new_key`; package signatures are left as they are. The public keys are
+    /// exported next to `keys.json`, which records the rotation and is signed
+    /// by the previous keys together with the new one. Returns the updated
+    /// signing configuration for later runs.
+    pub async fn rotate_signing_key(
+        &self,
+        new_key: &str,
+        subdirs: &[String],
+    ) -> Result<(SigningConfig, KeyRotation)> {
+        let signer = self.signer.as_ref().ok_or_else(|| {
+            MirrorError::InvalidInput(
+                "Rotating keys needs a 'signing' section in the configuration".to_string(),
+            )
+        })?;
+        for subdir in subdirs {
+            Platform::from_str(subdir).map_err(|e| {
+                MirrorError::InvalidInput(format!("Unknown subdir {}: {}", subdir, e))
+            })?;
+        }
+        let previous = signer.config();
+        let rotated = previous.rotated(new_key, subdirs);
+        let new_signer = Signer::new(&rotated);
+        let mut from: Vec<String> = Vec::new();
+        let moved = if subdirs.is_empty() {
+            vec![previous.key.as_str()]
+        } else {
+            subdirs
+                .iter()
+                .map(|subdir| previous.key_for(subdir))
+                .collect()
+        };
+        for old_key in moved {
+            if old_key != new_key && !from.iter().any(|key| key == old_key) {
+                from.push(old_key.to_string());
+            }
+        }
+        if from.is_empty() {
+            return Err(MirrorError::InvalidInput(format!(
+                "{} already signs the subdirs to rotate",
+                new_key
+            )));
+        }
+
+        let mut resigned = Vec::new();
+        for (subdir, repodata) in self.current_repodata().await? {
+            let old_key = previous.key_for(&subdir);
+            if old_key == rotated.key_for(&subdir) {
+                continue;
+            }
+            let platform = Platform::from_str(&subdir).map_err(|e| {
+                MirrorError::InvalidInput(format!("Unknown subdir {}: {}", subdir, e))
+            })?;
+            let signature = new_signer.sign(&subdir, &repodata).await?;
+            self.store_signature(&platform, "repodata.json", &signature)
+                .await?;
+            info!(
+                "Re-signed {}/repodata.json with {} instead of {}",
+                subdir, new_key, old_key
+            );
+            resigned.push(subdir);
+        }
+
+        let mut index = match self.backend.channel_file(KEYS_FILE).await? {
+            Some(content) => KeyIndex::from_slice(&content)?,
+            None => KeyIndex::default(),
+        };
+        index.key = rotated.key.clone();
+        index.subdir_keys = rotated.subdir_keys.clone();
+        for key in rotated
+            .keys()
+            .into_iter()
+            .chain(from.iter().map(String::as_str))
+        {
+            if index.public_keys.contains_key(key) {
+                continue;
+            }
+            let file = public_key_file(key);
+            let public_key = new_signer.export_public_key(key).await?;
+            if !self.backend.store_channel_file(&file, &public_key).await? {
+                return Err(MirrorError::InvalidInput(format!(
+                    "Target {} has nowhere to publish signing keys",
+                    self.path
+                )));
+            }
+            index.public_keys.insert(key.to_string(), file);
+        }
+        let rotation = KeyRotation {
+            rotated_at: Utc::now(),
+            from,
+            to: new_key.to_string(),
+            subdirs: resigned,
+        };
+        index.rotations.push(rotation.clone());
+
+        // Clients trusting a previous key accept its successor through this signature
+        let content = index.to_vec()?;
+        let mut signers: Vec<&str> = rotation.from.iter().map(String::as_str).collect();
+        signers.push(new_key);
+        let signature = new_signer.sign_with(&signers, &content).await?;
+        if !self.backend.store_channel_file(KEYS_FILE, &content).await? {
+            return Err(MirrorError::InvalidInput(format!(
+                "Target {} has nowhere to publish signing keys",
+                self.path
+            )));
+        }
+        self.backend
+            .store_channel_file(&signature_filename(KEYS_FILE), &signature)
+            .await?;
+        Ok((rotated, rotation))
+    }
+
+    /// Remove the packages a retention rule expires, with their signatures and attestations
+    ///
+    /// Returns the `subdir/filename` paths of the expired packages; with
+    /// `dry_run` nothing is removed. Expired packages are dropped from the
+    /// `repodata.json` of their platform before their files are deleted, so
+    /// clients never see records of missing files; a file that cannot be
+    /// deleted is reported and left for `gc`.
+    pub async fn prune(&self, rule: &RetentionRule, dry_run: bool) -> Result<Vec<String>> {
+        let stored_at = if rule.uses_age() {
+            self.backend.stored_at().await?
+        } else {
+            HashMap::new()
+        };
+        let expired = rule.expired(
+            &self.stored_packages().await?,
+            &stored_at,
+            chrono::Utc::now(),
+        );
+        if dry_run || expired.is_empty() {
+            return Ok(expired);
+        }
+        let removed = group_by_platform(&expired)?;
+        self.snapshot_repodata("prune").await?;
+
+        for (platform, filenames) in &removed {
+            self.unlist(platform, filenames).await?;
+        }
+        self.edit_manifest(|manifest| manifest.remove(&expired))
+            .await?;
+
+        for (platform, filenames) in &removed {
+            for filename in filenames {
+                self.delete_package(platform, filename, "expired").await;
+            }
+        }
+        Ok(expired)
+    }
+
+    /// Delete a package file with its signature and attestation, reporting failures
+    ///
+    /// Returns whether the package itself was deleted. `kind` describes the
+    /// package in log messages, e.g. `expired`.
+    async fn delete_package(&self, platform: &Platform, filename: &str, kind: &str) -> bool {
+        if let Err(e) = self.backend.delete(platform, filename).await {
+            warn!(
+                "Failed to remove {} package {}/{}: {}",
+                kind, platform, filename, e
+            );
+            return false;
+        }
+        for sidecar in [signature_filename(filename), attestation_filename(filename)] {
+            if let Err(e) = self.backend.delete(platform, &sidecar).await {
+                warn!(
+                    "Failed to remove {} of {} package {}/{}: {}",
+                    sidecar, kind, platform, filename, e
+                );
+            }
+        }
+        info!("Removed {} package {}/{}", kind, platform, filename);
+        true
+    }
+
+    /// Drop packages from the `repodata.json` of a platform and sign it again
+    async fn unlist(&self, platform: &Platform, filenames: &[&str]) -> Result<()> {
+        let unlisted = self
+            .backend
+            .update_repodata(platform, &|content| {
+                let Some(content) = content else {
+                    return Ok(None);
+                };
+                let mut repodata: serde_json::Value = serde_json::from_slice(content)?;
+                for section in ["packages", "packages.conda"] {
+                    if let Some(packages) =
+                        repodata.get_mut(section).and_then(|p| p.as_object_mut())
+                    {
+                        for filename in filenames {
+                            packages.remove(*filename);
+                        }
+                    }
+                }
+                Ok(Some(serde_json::to_vec_pretty(&repodata)?))
+            })
+            .await?;
+        if unlisted {
+            self.sign_repodata(platform).await?;
+            self.shard_repodata(platform).await?;
+        }
+        Ok(())
+    }
+
+    /// Abort the uploads left incomplete at the target, e.g. multipart uploads to S3
+    ///
+    /// Returns a description of each; with `dry_run` nothing is aborted.
+    pub async fn abort_incomplete_uploads(&self, dry_run: bool) -> Result<Vec<String>> {
+        self.backend.abort_incomplete_uploads(dry_run).await
+    }
+
+    /// Index or delete package files at the target that no `repodata.json` lists
+    ///
+    /// Such orphans are left by interrupted runs or copied in by hand. With
+    /// [`OrphanAction::Index`] they are added to the repodata of their subdir,
+    /// with [`OrphanAction::Delete`] removed along with their signatures and
+    /// attestations. Returns the `subdir/filename` paths of the orphans handled;
+    /// with `dry_run`, of every orphan found, and nothing is changed. Orphans
+    /// that cannot be read back as packages are reported and left alone.
+    pub async fn collect_orphans(
+        &self,
+        action: OrphanAction,
+        dry_run: bool,
+    ) -> Result<Vec<String>> {
+        let current = self.current_repodata().await?;
+        let mut listed = HashSet::new();
+        for (subdir, content) in &current {
+            let repodata: serde_json::Value = serde_json::from_slice(content)?;
+            for section in ["packages", "packages.conda"] {
+                if let Some(packages) = repodata.get(section).and_then(|p| p.as_object()) {
+                    listed.extend(
+                        packages
+                            .keys()
+                            .map(|filename| format!("{}/{}", subdir, filename)),
+                    );
+                }
+            }
+        }
+        let orphans: Vec<String> = self
+            .backend
+            .list()
+            .await?
+            .into_iter()
+            .filter(|path| !listed.contains(path))
+            .collect();
+        if dry_run || orphans.is_empty() {
+            return Ok(orphans);
+        }
+        self.snapshot_repodata("gc").await?;
+
+        if action == OrphanAction::Delete {
+            // As in prune, records go before files, and one failed delete
+            // does not stop the others
+            let orphaned = group_by_platform(&orphans)?;
+            self.edit_manifest(|manifest| manifest.remove(&orphans))
+                .await?;
+            let mut handled = Vec::new();
+            for (platform, filenames) in &orphaned {
+                for filename in filenames {
+                    if self.delete_package(platform, filename, "orphaned").await {
+                        handled.push(format!("{}/{}", platform, filename));
+                    }
+                }
+            }
+            return Ok(handled);
+        }
+
+        let mut handled = Vec::new();
+        let mut records: BTreeMap<&str, Vec<(&str, serde_json::Value)>> = BTreeMap::new();
+        for path in &orphans {
+            let (subdir, filename) = path.rsplit_once('/').unwrap_or(("noarch", path));
+            let platform = Platform::from_str(subdir).map_err(|e| {
+                MirrorError::InvalidInput(format!("Unknown subdir in {}: {}", path, e))
+            })?;
+            match self.orphan_record(&platform, filename).await {
+                Ok(record) => records.entry(subdir).or_default().push((filename, record)),
+                Err(e) => {
+                    warn!("Cannot index orphaned package {}: {}", path, e);
+                    continue;
+                }
+            }
+            handled.push(path.clone());
+        }
+
+        for (subdir, records) in records {
+            let platform = Platform::from_str(subdir).map_err(|e| {
+                MirrorError::InvalidInput(format!("Unknown subdir {}: {}", subdir, e))
+            })?;
+            let count = records.len();
+            let indexed = self
+                .backend
+                .update_repodata(&platform, &|content| {
+                    let content = match content {
+                        Some(content) => content.to_vec(),
+                        None => snapshot::empty_repodata(subdir)?,
+                    };
+                    let mut repodata: serde_json::Value = serde_json::from_slice(&content)?;
+                    for (filename, record) in &records {
+                        let (section, _) = CondaPackageHandler::repodata_sections(filename);
+                        if !repodata[section].is_object() {
+                            repodata[section] = serde_json::json!({});
+                        }
+                        repodata[section][*filename] = record.clone();
+                    }
+                    Ok(Some(serde_json::to_vec_pretty(&repodata)?))
+                })
+                .await?;
+            if !indexed {
+                return Err(MirrorError::InvalidInput(format!(
+                    "Target {} maintains its repodata itself",
+                    self.path
+                )));
+            }
+            self.rename_channel(&platform).await?;
+            self.sign_repodata(&platform).await?;
+            self.shard_repodata(&platform).await?;
+            info!("Indexed {} orphaned packages in {}", count, subdir);
+        }
+        Ok(handled)
+    }
+
+    /// The repodata record of a stored package, read back from the target
+    async fn orphan_record(
+        &self,
+        platform: &Platform,
+        filename: &str,
+    ) -> Result<serde_json::Value> {
+        let content = self
+            .backend
+            .package(platform, filename)
+            .await?
+            .ok_or_else(|| {
+                MirrorError::InvalidInput(format!("target {} cannot read packages back", self.path))
+            })?;
+        let package = CondaPackageHandler::new()
+            .process_package(content, filename)
+            .await?;
+        // Metadata guessed from the filename is not worth publishing
+        if !CondaPackageHandler::platform_from_metadata(&package.metadata) {
+            return Err(MirrorError::InvalidInput(format!(
+                "the metadata of {} cannot be read",
+                filename
+            )));
+        }
+        Ok(CondaPackageHandler::repodata_record(&package, platform))
+    }
+
+    /// The `repodata.json` of every subdir of the target that has one
+    async fn current_repodata(&self) -> Result<BTreeMap<String, Vec<u8>>> {
+        let mut current = BTreeMap::new();
+        for platform in Platform::all() {
+            if let Some(content) = self.backend.repodata(&platform).await? {
+                current.insert(platform.to_string(), content);
+            }
+        }
+        Ok(current)
+    }
+
+    async fn snapshot_index(&self) -> Result<SnapshotIndex> {
+        match self.backend.channel_file(SNAPSHOT_INDEX).await? {
+            Some(content) => SnapshotIndex::from_slice(&content),
+            None => Ok(SnapshotIndex::default()),
+        }
+    }
+
+    /// Snapshots of the target's repodata, oldest first
+    pub async fn repodata_snapshots(&self) -> Result<Vec<Snapshot>> {
+        Ok(self.snapshot_index().await?.snapshots)
+    }
+
+    /// Snapshot the repodata of every subdir before `reason` rewrites it
+    ///
+    /// Nothing is taken when snapshots are disabled, the target has no
+    /// repodata of its own, or the repodata is unchanged since the latest
+    /// snapshot. Snapshots beyond the configured number are removed, oldest first.
+    pub async fn snapshot_repodata(&self, reason: &str) -> Result<Option<Snapshot>> {
+        if self.repodata_snapshots == 0 {
+            return Ok(None);
+        }
+        let current = self.current_repodata().await?;
+        if current.is_empty() {
+            return Ok(None);
+        }
+        let mut index = self.snapshot_index().await?;
+        if let Some(latest) = index.latest() {
+            if self.snapshot_matches(latest, &current).await? {
+                debug!(
+                    "Repodata of {} unchanged since snapshot {}",
+                    self.path, latest.id
+                );
+                return Ok(None);
+            }
+        }
+
+        let snapshot = index.add(Utc::now(), reason, current.keys().cloned().collect());
+        for (subdir, content) in &current {
+            if !self
+                .backend
+                .store_channel_file(&snapshot.file(subdir), content)
+                .await?
+            {
+                return Ok(None);
+            }
+        }
+        let expired = index.expire(self.repodata_snapshots);
+        self.backend
+            .store_channel_file(SNAPSHOT_INDEX, &index.to_vec()?)
+            .await?;
+        for expired in expired {
+            for subdir in &expired.subdirs {
+                if let Err(e) = self
+                    .backend
+                    .delete_channel_file(&expired.file(subdir))
+                    .await
+                {
+                    warn!(
+                        "Failed to remove expired repodata snapshot {}: {}",
+                        expired.id, e
+                    );
+                }
+            }
+        }
+        info!(
+            "Took snapshot {} of the repodata of {} before {}",
+            snapshot.id, self.path, reason
+        );
+        Ok(Some(snapshot))
+    }
+
+    async fn snapshot_matches(
+        &self,
+        snapshot: &Snapshot,
+        current: &BTreeMap<String, Vec<u8>>,
+    ) -> Result<bool> {
+        if !snapshot.subdirs.iter().eq(current.keys()) {
+            return Ok(false);
+        }
+        for (subdir, content) in current {
+            if self
+                .backend
+                .channel_file(&snapshot.file(subdir))
+                .await?
+                .as_ref()
+                != Some(content)
+            {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Put the repodata of a snapshot back in place, the latest one unless `id` is given
+    ///
+    /// The current repodata is snapshotted first, so a rollback can be undone
+    /// in turn. Subdirs the snapshot lacks get an empty repodata, and packages
+    /// whose files are gone from the target, e.g. after a prune, are left out.
+    pub async fn rollback(&self, id: Option<&str>) -> Result<Rollback> {
+        let snapshot = self.snapshot_index().await?.find(id)?.clone();
+        let mut restored = BTreeMap::new();
+        for subdir in &snapshot.subdirs {
+            let name = snapshot.file(subdir);
+            let content = self.backend.channel_file(&name).await?.ok_or_else(|| {
+                MirrorError::InvalidInput(format!(
+                    "Repodata snapshot {} lacks {}",
+                    snapshot.id, name
+                ))
+            })?;
+            restored.insert(subdir.clone(), content);
+        }
+        let current = self.current_repodata().await?;
+        self.snapshot_repodata("rollback").await?;
+
+        let stored: HashSet<String> = self.backend.list().await?.into_iter().collect();
+        let mut missing = Vec::new();
+        for (subdir, content) in &restored {
+            let mut repodata: serde_json::Value = serde_json::from_slice(content)?;
+            for section in ["packages", "packages.conda"] {
+                if let Some(packages) = repodata.get_mut(section).and_then(|p| p.as_object_mut()) {
+                    packages.retain(|filename, _| {
+                        let path = format!("{}/{}", subdir, filename);
+                        let kept = stored.contains(&path);
+                        if !kept {
+                            missing.push(path);
+                        }
+                        kept
+                    });
+                }
+            }
+            self.restore_repodata(subdir, &serde_json::to_vec_pretty(&repodata)?)
+                .await?;
+        }
+        for subdir in current
+            .keys()
+            .filter(|subdir| !restored.contains_key(*subdir))
+        {
+            self.restore_repodata(subdir, &snapshot::empty_repodata(subdir)?)
+                .await?;
+        }
+        info!(
+            "Rolled the repodata of {} back to snapshot {}",
+            self.path, snapshot.id
+        );
+        Ok(Rollback { snapshot, missing })
+    }
+
+    async fn restore_repodata(&self, subdir: &str, content: &[u8]) -> Result<()> {
+        let platform = Platform::from_str(subdir)
+            .map_err(|e| MirrorError::InvalidInput(format!("Unknown subdir {}: {}", subdir, e)))?;
+        if !self.backend.store_repodata(&platform, content).await? {
+            return Err(MirrorError::InvalidInput(format!(
+                "Target {} maintains its repodata itself and cannot be rolled back",
+                self.path
+            )));
+        }
+        self.sign_repodata(&platform).await?;
+        self.shard_repodata(&platform).await
+    }
+
+    /// Record the packages a run mirrored in the channel's `mirror-manifest.json`
+    ///
+    /// Returns `false` if the target has nowhere to keep the manifest.
+    pub async fn update_manifest(&self, report: &MirrorReport) -> Result<bool> {
+        self.edit_manifest(|manifest| {
+            manifest.record(report, Utc::now());
+        })
+        .await
+    }
+
+    async fn edit_manifest(
+        &self,
+        edit: impl Fn(&mut MirrorManifest) + Send + Sync,
+    ) -> Result<bool> {
+        let edited = std::sync::Mutex::new(MirrorManifest::default());
+        let stored = self
+            .backend
+            .update_channel_file(MANIFEST_FILENAME, &|content| {
+                let mut manifest = match content {
+                    Some(content) => MirrorManifest::from_slice(content)?,
+                    None => MirrorManifest::default(),
+                };
+                edit(&mut manifest);
+                let content = manifest.to_vec()?;
+                *edited.lock().unwrap() = manifest;
+                Ok(Some(content))
+            })
+            .await?;
+        if stored {
+            let manifest = edited.into_inner().unwrap();
+            if let Err(e) = self.write_channeldata(&manifest).await {
+                warn!(
+                    "Failed to update the channeldata.json of {}: {}",
+                    self.path, e
+                );
+            }
+        }
+        Ok(stored)
+    }
+
+    /// Rewrite `channeldata.json` from the current repodata and `manifest`
+    async fn write_channeldata(&self, manifest: &MirrorManifest) -> Result<()> {
+        let existing = self.backend.channel_file(CHANNELDATA_FILENAME).await?;
+        let channeldata = channeldata::build(
+            existing.as_deref(),
+            &self.current_repodata().await?,
+            manifest,
+            Utc::now(),
+        )?;
+        self.backend
+            .store_channel_file(
+                CHANNELDATA_FILENAME,
+                &serde_json::to_vec_pretty(&channeldata)?,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Store the signature of `filename`, warning if the target cannot hold it
+    async fn store_signature(
+        &self,
+        platform: &Platform,
+        filename: &str,
+        signature: &[u8],
+    ) -> Result<()> {
+        let stored = self
+            .backend
+            .store_signature(platform, &signature_filename(filename), signature)
+            .await?;
+        if !stored {
+            warn!(
+                "Target {} cannot hold signatures; {} is published unsigned",
+                self.path, filename
+            );
+        }
+        Ok(())
+    }
+
+    /// The package processed under `filename` in this run, if any
+    ///
+    /// A renamed package is found under its original filename as well.
+    pub fn processed_package(&self, filename: &str) -> Option<&ProcessedPackage> {
+        let filename = self.renamed.get(filename).map_or(filename, String::as_str);
+        self.conda_handler.get_package(filename)
+    }
+
+    /// How the platform of an uploaded package was decided, with `explain_platform` on
+    pub fn take_platform_explanation(&mut self, filename: &str) -> Option<String> {
+        self.platform_explanations.remove(filename)
+    }
+
+    /// The backend packages are stored through, e.g. to read the target as a source
+    pub fn backend(&self) -> Arc<dyn RepositoryBackend> {
+        Arc::clone(&self.backend)
+    }
+
+    /// Packages the target accepted for a batch that then failed to upload
+    ///
+    /// Complete once [`Repository::finalize_repository`] has sent the last batch.
+    pub fn take_failed_uploads(&self) -> Vec<FailedUpload> {
+        self.backend.take_failed_uploads()
+    }
+
+    /// Paths of the packages stored at the target, e.g. `linux-64/pkg-1.0-0.conda`
+    pub async fn stored_packages(&self) -> Result<Vec<String>> {
+        self.backend.list().await
+    }
+
+    /// Get statistics about processed packages
+    pub fn get_package_stats(&self) -> crate::conda_package::PackageStats {
+        self.conda_handler.get_stats()
+    }
+
+    /// Finalize repository by updating all repodata files
+    pub async fn finalize_repository(&mut self) -> Result<()> {
+        info!("Finalizing repository structure");
+
+        let mut organized_packages = self.conda_handler.organize_packages();
+        for alias in &self.aliases {
+            organized_packages
+                .entry(alias.platform)
+                .or_default()
+                .push(alias.clone());
+        }
+        self.backend.finalize(&organized_packages).await?;
+        for platform in organized_packages.keys() {
+            self.rename_channel(platform).await?;
+            self.sign_repodata(platform).await?;
+            self.shard_repodata(platform).await?;
+        }
+
+        let stats = self.get_package_stats();
+        stats.print_summary();
+
+        Ok(())
+    }
+}
+
+/// Split `subdir/filename` paths by platform, in the order the platforms first appear
+fn group_by_platform(paths: &[String]) -> Result<Vec<(Platform, Vec<&str>)>> {
+    let mut groups: Vec<(Platform, Vec<&str>)> = Vec::new();
+    for path in paths {
+        let (subdir, filename) = path.rsplit_once('/').unwrap_or(("noarch", path));
+        let platform = Platform::from_str(subdir)
+            .map_err(|e| MirrorError::InvalidInput(format!("Unknown subdir in {}: {}", path, e)))?;
+        match groups.iter_mut().find(|(p, _)| *p == platform) {
+            Some((_, filenames)) => filenames.push(filename),
+            None => groups.push((platform, vec![filename])),
+        }
+    }
+    Ok(groups)
+}
+
+/// Check that a local directory can be created and written to
+fn local_preflight(path: &str) -> Result<()> {
+    let base_path = normalize_local_path(path);
+    std::fs::create_dir_all(&base_path).map_err(|e| MirrorError::target_io(&base_path, e))?;
+
+    let marker = base_path.join(PREFLIGHT_MARKER);
+    std::fs::write(&marker, b"preflight").map_err(|e| MirrorError::target_io(&marker, e))?;
+    std::fs::remove_file(&marker).map_err(|e| MirrorError::target_io(&marker, e))?;
+    Ok(())
+}
+
+/// Names of the entries of a local directory; a missing directory has none
+#[allow(dead_code)]
+fn read_dir_names(dir: &Path) -> Result<Vec<String>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(MirrorError::target_io(dir, e)),
+    };
+
+    entries
+        .map(|entry| {
+            entry
+                .map(|entry| entry.file_name().to_string_lossy().to_string())
+                .map_err(|e| MirrorError::target_io(dir, e))
+        })
+        .collect()
+}
+
+/// Device names Windows reserves in every directory, with or without an extension
+const WINDOWS_RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Paths at least this long need the `\\?\` prefix to be usable on Windows
+const WINDOWS_MAX_PATH: usize = 248;
+
+/// Use the platform's separator throughout a user-supplied local target path
+fn normalize_local_path(path: &str) -> PathBuf {
+    if cfg!(windows) {
+        PathBuf::from(path.replace('/', "\\"))
+    } else {
+        PathBuf::from(path)
+    }
+}
+
+/// Add the extended-length prefix to long absolute paths on Windows
+fn extended_length_path(path: &Path) -> PathBuf {
+    if !cfg!(windows) {
+        return path.to_path_buf();
+    }
+
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let display = absolute.to_string_lossy();
+    if display.len() < WINDOWS_MAX_PATH || display.starts_with(r"\\?\") {
+        return absolute;
+    }
+
+    match display.strip_prefix(r"\\") {
+        // UNC paths use the \\?\UNC\server\share form
+        Some(unc) => PathBuf::from(format!(r"\\?\UNC\{}", unc)),
+        None => PathBuf::from(format!(r"\\?\{}", display)),
+    }
+}
+
+/// Reject package filenames that cannot be written portably to a local target
+fn validate_target_filename(filename: &str) -> Result<()> {
+    let invalid = |reason: &str| {
+        Err(MirrorError::InvalidInput(format!(
+            "Package filename '{}' {}",
+            filename, reason
+        )))
+    };
+
+    if filename.is_empty() || filename == "." || filename == ".." {
+        return invalid("is not a valid file name");
+    }
+    if filename.contains(['/', '\\']) {
+        return invalid("contains a path separator");
+    }
+    if let Some(c) = filename
+        .chars()
+        .find(|c| matches!(c, '<' | '>' | ':' | '"' | '|' | '?' | '*') || c.is_control())
+    {
+        return invalid(&format!("contains the character {:?}", c));
+    }
+    if filename.ends_with(['.', ' ']) {
+        return invalid("ends with a dot or space");
+    }
+
+    let stem = filename.split('.').next().unwrap_or(filename);
+    if WINDOWS_RESERVED_NAMES
+        .iter()
+        .any(|reserved| stem.trim_end().eq_ignore_ascii_case(reserved))
+    {
+        return invalid("uses a name reserved on Windows");
+    }
+
+    Ok(())
+}
+
+/// Find an existing file in `dir` whose name differs from `filename` only by case
+///
+/// Such files collide on case-insensitive file systems (Windows, default macOS),
+/// where writing one would silently replace the other.
+fn find_case_insensitive_duplicate(dir: &Path, filename: &str) -> Result<Option<PathBuf>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(MirrorError::target_io(dir, e)),
+    };
+
+    for entry in entries {
+        let entry = entry.map_err(|e| MirrorError::target_io(dir, e))?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name != filename && name.eq_ignore_ascii_case(filename) {
+            return Ok(Some(entry.path()));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Resolve where a package is written inside a local directory, rejecting unsafe names
+fn local_package_path(dir: &Path, filename: &str) -> Result<PathBuf> {
+    validate_target_filename(filename)?;
+
+    if let Some(existing) = find_case_insensitive_duplicate(dir, filename)? {
+        return Err(MirrorError::InvalidInput(format!(
+            "Package filename '{}' differs only in case from existing {:?}",
+            filename, existing
+        )));
+    }
+
+    Ok(dir.join(filename))
+}
+
+/// Split an `s3://bucket/prefix` target into its bucket and key prefix
+pub(crate) fn s3_bucket_and_prefix(path: &str) -> Result<(&str, &str)> {
+    let mut parts = path.trim_start_matches("s3://").splitn(2, '/');
+    let bucket = parts
+        .next()
+        .filter(|bucket| !bucket.is_empty())
+        .ok_or_else(|| MirrorError::InvalidInput("Invalid S3 path".to_string()))?;
+    let prefix = parts.next().unwrap_or("").trim_end_matches('/');
+    Ok((bucket, prefix))
+}
+
+/// Modification times of stored packages, given by their paths relative to `base`
+fn local_modification_times(
+    base: &Path,
+    paths: Vec<String>,
+) -> Result<HashMap<String, DateTime<Utc>>> {
+    let mut stored_at = HashMap::new();
+    for path in paths {
+        let full_path = extended_length_path(&base.join(&path));
+        let modified = std::fs::metadata(&full_path)
+            .and_then(|metadata| metadata.modified())
+            .map_err(|e| MirrorError::target_io(&full_path, e))?;
+        stored_at.insert(path, modified.into());
+    }
+    Ok(stored_at)
+}
+
+/// Hash the file already stored at `path`, if there is one
+fn read_existing_sha256(path: &Path) -> Result<Option<String>> {
+    match std::fs::read(path) {
+        Ok(content) => Ok(Some(format!("{:x}", Sha256::digest(&content)))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(MirrorError::target_io(path, e)),
+    }
+}
+
+/// Look up the sha256 of an existing S3 object, preferring the metadata written on upload
+#[cfg(feature = "s3")]
+async fn s3_existing_sha256(
+    client: &aws_sdk_s3::Client,
+    bucket: &str,
+    key: &str,
+) -> Result<Option<String>> {
+    match client.head_object().bucket(bucket).key(key).send().await {
+        Ok(head) => {
+            if let Some(sha256) = head.metadata().and_then(|metadata| metadata.get("sha256")) {
+                return Ok(Some(sha256.clone()));
+            }
+
+            // Objects uploaded by other tools carry no checksum metadata
+            let object = client
+                .get_object()
+                .bucket(bucket)
+                .key(key)
+                .send()
+                .await
+                .map_err(|e| s3_error(key, e))?;
+            let content = object.body.collect().await.map_err(|e| {
+                MirrorError::TargetUpload(format!(
+                    "Failed to read existing S3 object '{}': {}",
+                    key, e
+                ))
+            })?;
+            Ok(Some(format!("{:x}", Sha256::digest(content.into_bytes()))))
+        }
+        Err(e)
+            if e.as_service_error()
+                .is_some_and(|service_error| service_error.is_not_found()) =>
+        {
+            Ok(None)
+        }
+        Err(e) => Err(s3_error(key, e)),
+    }
+}
+
+/// Hash the package currently served at a prefix.dev URL, if there is one
+async fn prefix_dev_existing_sha256(client: &reqwest::Client, url: &str) -> Result<Option<String>> {
+    let response = client.get(url).send().await?;
+    let status = response.status();
+
+    if status.is_success() {
+        let content = response.bytes().await?;
+        Ok(Some(format!("{:x}", Sha256::digest(&content))))
+    } else if status == reqwest::StatusCode::NOT_FOUND {
+        Ok(None)
+    } else {
+        warn!(
+            "Could not check for an existing package at {} (HTTP {}), uploading without conflict check",
+            url, status
+        );
+        Ok(None)
+    }
+}
+
+/// Error for targets asked for sharded repodata by a build that cannot write it
+#[cfg(not(feature = "sharded-repodata"))]
+fn sharded_repodata_unavailable() -> MirrorError {
+    MirrorError::InvalidInput(
+        "Sharded repodata requires the 'sharded-repodata' feature, which this build was compiled without"
+            .to_string(),
+    )
+}
+
+/// Conditional writes of one object an update attempts before giving up
+const MAX_CONDITIONAL_WRITES: u32 = 8;
+
+/// Whether an S3 error code means another writer changed the object first
+#[cfg(feature = "s3")]
+fn is_write_conflict(code: &str) -> bool {
+    matches!(code, "PreconditionFailed" | "ConditionalRequestConflict")
+}
+
+/// Wait before merging again after the `attempt`th conflicting write
+///
+/// Doubles from 100ms with up to 100ms of jitter, so writers that collided
+/// once are unlikely to collide again.
+fn conflict_delay(attempt: u32) -> std::time::Duration {
+    let jitter = Utc::now().timestamp_subsec_nanos() % 100_000_000;
+    std::time::Duration::from_millis(100 << attempt.min(6).saturating_sub(1))
+        + std::time::Duration::from_nanos(u64::from(jitter))
+}
+
+/// Classify an S3 SDK failure, separating credential problems from other upload errors
+#[cfg(feature = "s3")]
+fn s3_error<E, R>(key: &str, err: aws_sdk_s3::error::SdkError<E, R>) -> MirrorError
+where
+    E: aws_sdk_s3::error::ProvideErrorMetadata + std::error::Error + Send + Sync + 'static,
+    R: std::fmt::Debug,
+{
+    let code = err.code().map(str::to_string);
+    let message = format!(
+        "S3 request for '{}' failed: {}",
+        key,
+        aws_sdk_s3::error::DisplayErrorContext(&err)
+    );
+
+    match code.as_deref() {
+        Some("AccessDenied")
+        | Some("InvalidAccessKeyId")
+        | Some("SignatureDoesNotMatch")
+        | Some("ExpiredToken") => MirrorError::AuthError(message),
+        Some("NoSuchBucket") => MirrorError::NotFound(message),
+        Some("SlowDown") => MirrorError::RateLimited {
+            message,
+            retry_after: None,
+        },
+        _ => MirrorError::TargetUpload(message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repository_type_from_string() {
+        assert!(matches!(
+            RepositoryType::from_string("prefix-dev").unwrap(),
+            RepositoryType::PrefixDev
+        ));
+        assert!(matches!(
+            RepositoryType::from_string("prefix").unwrap(),
+            RepositoryType::PrefixDev
+        ));
+        assert!(matches!(
+            RepositoryType::from_string("s3").unwrap(),
+            RepositoryType::S3
+        ));
+        assert!(matches!(
+            RepositoryType::from_string("minio").unwrap(),
+            RepositoryType::S3
+        ));
+        assert!(matches!(
+            RepositoryType::from_string("local").unwrap(),
+            RepositoryType::Local
+        ));
+        assert!(matches!(
+            RepositoryType::from_string("file").unwrap(),
+            RepositoryType::Local
+        ));
+        assert!(matches!(
+            RepositoryType::from_string("cache").unwrap(),
+            RepositoryType::Cache
+        ));
+        assert!(RepositoryType::from_string("invalid").is_err());
+    }
+
+    /// Build a legacy .tar.bz2 package whose info/index.json declares `subdir`
+    fn legacy_package(subdir: &str) -> Bytes {
+        crate::test_support::PackageFixture::new("dup", "1.0")
+            .subdir(subdir)
+            .to_tar_bz2()
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_platform_error_policy() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut repo = Repository::new(
+            RepositoryType::Local,
+            temp_dir.path().to_string_lossy().to_string(),
+        );
+        let filename = "dup-1.0-0.tar.bz2";
+
+        // Unparseable content falls back to a guessed noarch platform
+        repo.upload_package(filename, Bytes::from_static(b"guessed"))
+            .await
+            .unwrap();
+
+        let result = repo
+            .upload_package(filename, legacy_package("linux-64"))
+            .await;
+        assert!(matches!(result, Err(MirrorError::PlatformConflict { .. })));
+        assert!(temp_dir.path().join("noarch").join(filename).exists());
+        assert!(!temp_dir.path().join("linux-64").join(filename).exists());
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_platform_prefer_metadata_policy() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut repo = Repository::new(
+            RepositoryType::Local,
+            temp_dir.path().to_string_lossy().to_string(),
+        )
+        .with_duplicate_platform_policy(DuplicatePlatformPolicy::PreferMetadata);
+        let filename = "dup-1.0-0.tar.bz2";
+
+        repo.upload_package(filename, Bytes::from_static(b"guessed"))
+            .await
+            .unwrap();
+        repo.upload_package(filename, legacy_package("linux-64"))
+            .await
+            .unwrap();
+
+        assert!(!temp_dir.path().join("noarch").join(filename).exists());
+        assert!(temp_dir.path().join("linux-64").join(filename).exists());
+
+        let noarch_repodata: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(temp_dir.path().join("noarch").join("repodata.json")).unwrap(),
+        )
+        .unwrap();
+        assert!(noarch_repodata["packages"].get(filename).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_platform_keep_first_policy() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut repo = Repository::new(
+            RepositoryType::Local,
+            temp_dir.path().to_string_lossy().to_string(),
+        )
+        .with_duplicate_platform_policy(DuplicatePlatformPolicy::KeepFirst)
+        .with_force_replace(true);
+        let filename = "dup-1.0-0.tar.bz2";
+
+        repo.upload_package(filename, Bytes::from_static(b"guessed"))
+            .await
+            .unwrap();
+        repo.upload_package(filename, legacy_package("linux-64"))
+            .await
+            .unwrap();
+
+        assert!(temp_dir.path().join("noarch").join(filename).exists());
+        assert!(!temp_dir.path().join("linux-64").join(filename).exists());
+    }
+
+    #[tokio::test]
+    async fn test_runs_add_to_existing_repodata() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let noarch = temp_dir.path().join("noarch");
+        std::fs::create_dir_all(&noarch).unwrap();
+        std::fs::write(
+            noarch.join("repodata.json"),
+            serde_json::json!({
+                "info": { "subdir": "noarch", "base_url": "https://mirror.example.org" },
+                "packages": { "kept-1.0-0.tar.bz2": { "name": "kept" } },
+                "packages.conda": { "second-1.0-0.conda": { "name": "stale" } },
+                "repodata_version": 1
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        for name in ["first", "second"] {
+            let mut repo = Repository::new(
+                RepositoryType::Local,
+                temp_dir.path().to_string_lossy().to_string(),
+            );
+            let fixture = crate::test_support::PackageFixture::new(name, "1.0");
+            repo.upload_package(&fixture.conda_filename(), fixture.to_conda())
+                .await
+                .unwrap();
+            repo.finalize_repository().await.unwrap();
+        }
+
+        let repodata: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(noarch.join("repodata.json")).unwrap()).unwrap();
+        let packages = repodata["packages.conda"].as_object().unwrap();
+        let mut listed: Vec<&str> = packages.keys().map(String::as_str).collect();
+        listed.sort();
+        assert_eq!(listed, ["first-1.0-0.conda", "second-1.0-0.conda"]);
+        assert_eq!(packages["second-1.0-0.conda"]["name"], "second");
+        assert_eq!(
+            repodata["packages"],
+            serde_json::json!({ "kept-1.0-0.tar.bz2": { "name": "kept" } })
+        );
+        assert_eq!(repodata["info"]["base_url"], "https://mirror.example.org");
+        assert_eq!(repodata["repodata_version"], 1);
+        let leftovers: Vec<_> = std::fs::read_dir(&noarch)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .filter(|name| name.to_string_lossy().ends_with(".partial"))
+            .collect();
+        assert!(leftovers.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_platform_mappings_move_and_alias_packages() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut repo = Repository::new(
+            RepositoryType::Local,
+            temp_dir.path().to_string_lossy().to_string(),
+        )
+        .with_platform_mappings(vec![
+            PlatformMapping::from_arg("osx-64=osx-arm64", false).unwrap(),
+            PlatformMapping::from_arg("linux-aarch64=linux-ppc64le", true).unwrap(),
+        ])
+        .with_explain_platform(true);
+        for (name, subdir) in [("mac", "osx-64"), ("arm", "linux-aarch64")] {
+            let fixture = crate::test_support::PackageFixture::new(name, "1.0").subdir(subdir);
+            repo.upload_package(&fixture.conda_filename(), fixture.to_conda())
+                .await
+                .unwrap();
+        }
+        repo.finalize_repository().await.unwrap();
+
+        let repodata = |subdir: &str| -> serde_json::Value {
+            serde_json::from_str(
+                &std::fs::read_to_string(temp_dir.path().join(subdir).join("repodata.json"))
+                    .unwrap(),
+            )
+            .unwrap()
+        };
+        assert!(temp_dir.path().join("osx-arm64/mac-1.0-0.conda").exists());
+        assert!(!temp_dir.path().join("osx-64").exists());
+        assert_eq!(
+            repodata("osx-arm64")["packages.conda"]["mac-1.0-0.conda"]["subdir"],
+            "osx-arm64"
+        );
+        assert_eq!(
+            repo.processed_package("mac-1.0-0.conda").unwrap().platform,
+            Platform::OsxArm64
+        );
+        let explanation = repo.take_platform_explanation("mac-1.0-0.conda").unwrap();
+        assert!(explanation.starts_with("mac-1.0-0.conda: osx-64"));
+        assert!(explanation.contains("platform_mappings: published under osx-arm64"));
+        assert!(repo.take_platform_explanation("mac-1.0-0.conda").is_none());
+        for subdir in ["linux-aarch64", "linux-ppc64le"] {
+            assert!(temp_dir
+                .path()
+                .join(subdir)
+                .join("arm-1.0-0.conda")
+                .exists());
+            assert_eq!(
+                repodata(subdir)["packages.conda"]["arm-1.0-0.conda"]["subdir"],
+                subdir
+            );
+        }
+
+        assert!(PlatformMapping::from_arg("osx-64", false).is_err());
+        assert!(PlatformMapping::from_arg("osx-64=linux-arm64", false).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_channel_name_written_into_repodata() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut repo = Repository::new(
+            RepositoryType::Local,
+            temp_dir.path().to_string_lossy().to_string(),
+        )
+        .with_channel_name(Some("internal-forge".to_string()));
+        let fixture = crate::test_support::PackageFixture::new("pkg", "1.0");
+        repo.upload_package(&fixture.conda_filename(), fixture.to_conda())
+            .await
+            .unwrap();
+        repo.finalize_repository().await.unwrap();
+
+        let repodata: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(temp_dir.path().join("noarch/repodata.json")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(repodata["info"]["channel"], "internal-forge");
+        assert_eq!(repodata["info"]["subdir"], "noarch");
+        assert_eq!(
+            repodata["packages.conda"]["pkg-1.0-0.conda"]["channel"],
+            "internal-forge"
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_signatures_published_next_to_packages_and_repodata() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let channel = temp_dir.path().join("channel");
+        let signing = crate::signing::tests::fake_gpg(temp_dir.path());
+        let mut repo =
+            Repository::new(RepositoryType::Local, channel.to_string_lossy().to_string())
+                .with_signer(Some(Signer::new(&signing)));
+        let filename = "dup-1.0-0.tar.bz2";
+
+        repo.upload_package(filename, legacy_package("linux-64"))
+            .await
+            .unwrap();
+        repo.finalize_repository().await.unwrap();
+
+        let platform_dir = channel.join("linux-64");
+        for signed in [filename, "repodata.json"] {
+            let size = std::fs::metadata(platform_dir.join(signed)).unwrap().len();
+            let signature =
+                std::fs::read_to_string(platform_dir.join(signature_filename(signed))).unwrap();
+            assert!(signature.starts_with("-----BEGIN PGP SIGNATURE-----"));
+            assert_eq!(signature.lines().nth(1).unwrap().trim(), size.to_string());
+        }
+
+        // Nothing is published when signing fails
+        let mut repo =
+            Repository::new(RepositoryType::Local, channel.to_string_lossy().to_string())
+                .with_signer(Some(Signer::new(&crate::signing::SigningConfig {
+                    program: "false".to_string(),
+                    ..signing
+                })));
+        let other = crate::test_support::PackageFixture::new("other", "1.0").subdir("linux-64");
+        let result = repo
+            .upload_package("other-1.0-0.tar.bz2", other.to_tar_bz2())
+            .await;
+        assert!(matches!(result, Err(MirrorError::Signing(_))));
+        assert!(!platform_dir.join("other-1.0-0.tar.bz2").exists());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_rotate_signing_key() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let channel = temp_dir.path().join("channel");
+        let mut signing = crate::signing::tests::fake_gpg(temp_dir.path());
+        signing
+            .subdir_keys
+            .insert("linux-64".to_string(), "linux@example.com".to_string());
+        let repository = |signing: &SigningConfig| {
+            Repository::new(RepositoryType::Local, channel.to_string_lossy().to_string())
+                .with_signer(Some(Signer::new(signing)))
+        };
+        let mut repo = repository(&signing);
+        for subdir in ["linux-64", "noarch"] {
+            let fixture = crate::test_support::PackageFixture::new(subdir, "1.0").subdir(subdir);
+            repo.upload_package(&fixture.tar_bz2_filename(), fixture.to_tar_bz2())
+                .await
+                .unwrap();
+        }
+        repo.finalize_repository().await.unwrap();
+        let signer_of = |path: &str| {
+            let signature = std::fs::read_to_string(channel.join(path)).unwrap();
+            signature.lines().nth(2).unwrap().trim().to_string()
+        };
+        assert_eq!(signer_of("linux-64/repodata.json.asc"), "linux@example.com");
+        assert_eq!(signer_of("noarch/repodata.json.asc"), "mirror@example.com");
+
+        // The default key moves, linux-64 keeps its own
+        let (rotated, rotation) = repository(&signing)
+            .rotate_signing_key("new@example.com", &[])
+            .await
+            .unwrap();
+        assert_eq!(rotation.from, ["mirror@example.com"]);
+        assert_eq!(rotation.subdirs, ["noarch"]);
+        assert_eq!(rotated.key, "new@example.com");
+        assert_eq!(signer_of("noarch/repodata.json.asc"), "new@example.com");
+        assert_eq!(signer_of("linux-64/repodata.json.asc"), "linux@example.com");
+        assert_eq!(
+            signer_of("keys.json.asc"),
+            "mirror@example.com new@example.com"
+        );
+
+        signing = rotated;
+        let (rotated, rotation) = repository(&signing)
+            .rotate_signing_key("new@example.com", &["linux-64".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(rotation.from, ["linux@example.com"]);
+        assert!(rotated.subdir_keys.is_empty());
+        assert_eq!(signer_of("linux-64/repodata.json.asc"), "new@example.com");
+
+        let index = KeyIndex::from_slice(&std::fs::read(channel.join(KEYS_FILE)).unwrap()).unwrap();
+        assert_eq!(index.key, "new@example.com");
+        assert!(index.subdir_keys.is_empty());
+        assert_eq!(index.rotations.len(), 2);
+        for (key, file) in &index.public_keys {
+            let public_key = std::fs::read_to_string(channel.join(file)).unwrap();
+            assert!(public_key.trim_end().ends_with(key.as_str()));
+        }
+        assert_eq!(index.public_keys.len(), 3);
+
+        signing = rotated;
+        assert!(matches!(
+            repository(&signing)
+                .rotate_signing_key("new@example.com", &[])
+                .await,
+            Err(MirrorError::InvalidInput(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_prune_removes_expired_packages_and_their_repodata() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut repo = Repository::new(
+            RepositoryType::Local,
+            temp_dir.path().to_string_lossy().to_string(),
+        );
+        for version in ["1.0", "1.10", "1.9"] {
+            let fixture = crate::test_support::PackageFixture::new("dup", version);
+            repo.upload_package(&fixture.conda_filename(), fixture.to_conda())
+                .await
+                .unwrap();
+        }
+        repo.finalize_repository().await.unwrap();
+
+        let rule = RetentionRule::new(&crate::retention::RetentionConfig {
+            keep: Some(2),
+            ..Default::default()
+        })
+        .unwrap();
+        let expired = vec!["noarch/dup-1.0-0.conda".to_string()];
+        assert_eq!(repo.prune(&rule, true).await.unwrap(), expired);
+        assert!(temp_dir.path().join("noarch/dup-1.0-0.conda").exists());
+
+        assert_eq!(repo.prune(&rule, false).await.unwrap(), expired);
+        assert!(!temp_dir.path().join("noarch/dup-1.0-0.conda").exists());
+        assert!(temp_dir.path().join("noarch/dup-1.9-0.conda").exists());
+        let repodata: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(temp_dir.path().join("noarch/repodata.json")).unwrap(),
+        )
+        .unwrap();
+        let packages = repodata["packages.conda"].as_object().unwrap();
+        assert!(!packages.contains_key("dup-1.0-0.conda"));
+        assert!(packages.contains_key("dup-1.10-0.conda"));
+        assert!(repo.prune(&rule, false).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_prune_unlists_packages_it_cannot_delete() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut repo = Repository::new(
+            RepositoryType::Local,
+            temp_dir.path().to_string_lossy().to_string(),
+        );
+        for version in ["1.0", "2.0"] {
+            let fixture = crate::test_support::PackageFixture::new("dup", version);
+            repo.upload_package(&fixture.conda_filename(), fixture.to_conda())
+                .await
+                .unwrap();
+        }
+        repo.finalize_repository().await.unwrap();
+        // A non-empty directory in place of the package cannot be removed as a file
+        let stuck = temp_dir.path().join("noarch/dup-1.0-0.conda");
+        std::fs::remove_file(&stuck).unwrap();
+        std::fs::create_dir(&stuck).unwrap();
+        std::fs::write(stuck.join("file"), b"content").unwrap();
+
+        let rule = RetentionRule::new(&crate::retention::RetentionConfig {
+            keep: Some(1),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            repo.prune(&rule, false).await.unwrap(),
+            vec!["noarch/dup-1.0-0.conda".to_string()]
+        );
+        assert!(stuck.exists());
+        let repodata: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(temp_dir.path().join("noarch/repodata.json")).unwrap(),
+        )
+        .unwrap();
+        let packages = repodata["packages.conda"].as_object().unwrap();
+        assert!(!packages.contains_key("dup-1.0-0.conda"));
+        assert!(packages.contains_key("dup-2.0-0.conda"));
+    }
+
+    #[tokio::test]
+    async fn test_repodata_snapshots_and_rollback() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut repo = Repository::new(
+            RepositoryType::Local,
+            temp_dir.path().to_string_lossy().to_string(),
+        )
+        .with_repodata_snapshots(2);
+        assert!(repo.snapshot_repodata("mirror").await.unwrap().is_none());
+
+        let old = crate::test_support::PackageFixture::new("old", "1.0");
+        repo.upload_package(&old.conda_filename(), old.to_conda())
+            .await
+            .unwrap();
+        repo.finalize_repository().await.unwrap();
+        let before = repo.snapshot_repodata("mirror").await.unwrap().unwrap();
+        assert_eq!(before.subdirs, vec!["noarch".to_string()]);
+        // Unchanged repodata is not snapshotted again
+        assert!(repo.snapshot_repodata("mirror").await.unwrap().is_none());
+
+        for fixture in [
+            crate::test_support::PackageFixture::new("bad", "1.0"),
+            crate::test_support::PackageFixture::new("native", "1.0").subdir("linux-64"),
+        ] {
+            repo.upload_package(&fixture.conda_filename(), fixture.to_conda())
+                .await
+                .unwrap();
+        }
+        repo.finalize_repository().await.unwrap();
+        std::fs::remove_file(temp_dir.path().join("noarch/old-1.0-0.conda")).unwrap();
+
+        let rollback = repo.rollback(None).await.unwrap();
+        assert_eq!(rollback.snapshot, before);
+        assert_eq!(rollback.missing, vec!["noarch/old-1.0-0.conda".to_string()]);
+        let repodata = |subdir: &str| -> serde_json::Value {
+            serde_json::from_str(
+                &std::fs::read_to_string(temp_dir.path().join(subdir).join("repodata.json"))
+                    .unwrap(),
+            )
+            .unwrap()
+        };
+        assert!(repodata("noarch")["packages.conda"]
+            .as_object()
+            .unwrap()
+            .is_empty());
+        assert!(repodata("linux-64")["packages.conda"]
+            .as_object()
+            .unwrap()
+            .is_empty());
+
+        // The rollback can be undone, and only the newest snapshots are kept
+        let snapshots = repo.repodata_snapshots().await.unwrap();
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!(snapshots[1].reason, "rollback");
+        repo.rollback(None).await.unwrap();
+        assert!(repodata("linux-64")["packages.conda"]
+            .as_object()
+            .unwrap()
+            .contains_key("native-1.0-0.conda"));
+        let snapshots = repo.repodata_snapshots().await.unwrap();
+        assert_eq!(snapshots.len(), 2);
+        assert!(!snapshots.contains(&before));
+        assert!(!temp_dir
+            .path()
+            .join(snapshot::SNAPSHOT_DIR)
+            .join(&before.id)
+            .exists());
+    }
+
+    #[cfg(feature = "s3")]
+    #[test]
+    fn test_conflicting_writes_back_off() {
+        assert!(is_write_conflict("PreconditionFailed"));
+        assert!(is_write_conflict("ConditionalRequestConflict"));
+        assert!(!is_write_conflict("AccessDenied"));
+
+        let first = conflict_delay(1);
+        assert!(first >= std::time::Duration::from_millis(100));
+        assert!(first < std::time::Duration::from_millis(200));
+        assert!(conflict_delay(3) >= std::time::Duration::from_millis(400));
+        assert!(conflict_delay(100) < std::time::Duration::from_millis(6500));
+    }
+
+    #[cfg(feature = "sharded-repodata")]
+    #[tokio::test]
+    async fn test_sharded_repodata() {
+        use std::collections::BTreeSet;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut repo = Repository::new(
+            RepositoryType::Local,
+            temp_dir.path().to_string_lossy().to_string(),
+        )
+        .with_sharded_repodata(true);
+        let shard_files = || -> BTreeSet<String> {
+            std::fs::read_dir(temp_dir.path().join("noarch/shards"))
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+                .collect()
+        };
+
+        let first = crate::test_support::PackageFixture::new("first", "1.0");
+        repo.upload_package(&first.conda_filename(), first.to_conda())
+            .await
+            .unwrap();
+        repo.finalize_repository().await.unwrap();
+        let index = std::fs::read(temp_dir.path().join(shards::index_path("noarch"))).unwrap();
+        let listed = shards::listed_shards(&index).unwrap();
+        assert_eq!(listed.len(), 1);
+        let before = shard_files();
+
+        // A new version rewrites the shard of its name; the old shard stays for
+        // clients holding the replaced index
+        let upload = |version: &'static str| {
+            let fixture = crate::test_support::PackageFixture::new("first", version);
+            (fixture.conda_filename(), fixture.to_conda())
+        };
+        let (filename, content) = upload("2.0");
+        repo.upload_package(&filename, content).await.unwrap();
+        repo.finalize_repository().await.unwrap();
+        let index = std::fs::read(temp_dir.path().join(shards::index_path("noarch"))).unwrap();
+        let second = shards::listed_shards(&index).unwrap();
+        let shard_names = |hashes: &BTreeSet<String>| -> BTreeSet<String> {
+            hashes
+                .iter()
+                .map(|hash| format!("{}.msgpack.zst", hash))
+                .collect()
+        };
+        assert!(before.is_disjoint(&shard_names(&second)));
+        assert_eq!(
+            shard_files(),
+            before.union(&shard_names(&second)).cloned().collect()
+        );
+        assert!(temp_dir
+            .path()
+            .join(shards::previous_index_path("noarch"))
+            .is_file());
+
+        // The next run removes the shards no index lists any more
+        let (filename, content) = upload("3.0");
+        repo.upload_package(&filename, content).await.unwrap();
+        repo.finalize_repository().await.unwrap();
+        let index = std::fs::read(temp_dir.path().join(shards::index_path("noarch"))).unwrap();
+        let third = shards::listed_shards(&index).unwrap();
+        assert_eq!(
+            shard_files(),
+            shard_names(&second)
+                .union(&shard_names(&third))
+                .cloned()
+                .collect()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_collect_orphans() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut repo = Repository::new(
+            RepositoryType::Local,
+            temp_dir.path().to_string_lossy().to_string(),
+        );
+        let listed = crate::test_support::PackageFixture::new("listed", "1.0");
+        repo.upload_package(&listed.conda_filename(), listed.to_conda())
+            .await
+            .unwrap();
+        repo.finalize_repository().await.unwrap();
+
+        // Files copied in by hand, one of them not a package at all
+        let copied = crate::test_support::PackageFixture::new("copied", "1.0");
+        std::fs::write(
+            temp_dir.path().join("noarch").join(copied.conda_filename()),
+            copied.to_conda(),
+        )
+        .unwrap();
+        let native = crate::test_support::PackageFixture::new("native", "1.0").subdir("linux-64");
+        std::fs::create_dir_all(temp_dir.path().join("linux-64")).unwrap();
+        std::fs::write(
+            temp_dir
+                .path()
+                .join("linux-64")
+                .join(native.conda_filename()),
+            native.to_conda(),
+        )
+        .unwrap();
+        std::fs::write(temp_dir.path().join("noarch/junk-1.0-0.conda"), b"junk").unwrap();
+
+        let orphans = vec![
+            "linux-64/native-1.0-0.conda".to_string(),
+            "noarch/copied-1.0-0.conda".to_string(),
+            "noarch/junk-1.0-0.conda".to_string(),
+        ];
+        assert_eq!(
+            repo.collect_orphans(OrphanAction::Index, true)
+                .await
+                .unwrap(),
+            orphans
+        );
+        assert_eq!(
+            repo.collect_orphans(OrphanAction::Index, false)
+                .await
+                .unwrap(),
+            orphans[..2]
+        );
+        let repodata = |subdir: &str| -> serde_json::Value {
+            serde_json::from_str(
+                &std::fs::read_to_string(temp_dir.path().join(subdir).join("repodata.json"))
+                    .unwrap(),
+            )
+            .unwrap()
+        };
+        let noarch = repodata("noarch");
+        assert!(noarch["packages.conda"]["listed-1.0-0.conda"].is_object());
+        assert_eq!(
+            noarch["packages.conda"]["copied-1.0-0.conda"]["name"],
+            "copied"
+        );
+        assert_eq!(
+            repodata("linux-64")["packages.conda"]["native-1.0-0.conda"]["subdir"],
+            "linux-64"
+        );
+
+        assert_eq!(
+            repo.collect_orphans(OrphanAction::Delete, false)
+                .await
+                .unwrap(),
+            orphans[2..]
+        );
+        assert!(!temp_dir.path().join("noarch/junk-1.0-0.conda").exists());
+        assert!(repo
+            .collect_orphans(OrphanAction::Delete, true)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_manifest_tracks_mirrored_and_pruned_packages() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut repo = Repository::new(
+            RepositoryType::Local,
+            temp_dir.path().to_string_lossy().to_string(),
+        );
+        let mut report = MirrorReport::new("owner/repo", repo.path.clone());
+        for version in ["1.0", "2.0"] {
+            let fixture = crate::test_support::PackageFixture::new("dup", version);
+            let filename = fixture.conda_filename();
+            repo.upload_package(&filename, fixture.to_conda())
+                .await
+                .unwrap();
+            let processed = repo.processed_package(&filename).unwrap();
+            let (platform, sha256) = (processed.platform.to_string(), processed.sha256.clone());
+            let package = report.record(
+                filename,
+                crate::report::PackageOutcome::Mirrored,
+                0,
+                std::time::Duration::ZERO,
+            );
+            package.platform = Some(platform);
+            package.sha256 = Some(sha256);
+            package.origin = Some(format!("https://example.com/dup-{}", version));
+        }
+        repo.finalize_repository().await.unwrap();
+        assert!(repo.update_manifest(&report).await.unwrap());
+
+        let read_manifest = || {
+            MirrorManifest::from_slice(
+                &std::fs::read(temp_dir.path().join(MANIFEST_FILENAME)).unwrap(),
+            )
+            .unwrap()
+        };
+        let manifest = read_manifest();
+        assert_eq!(
+            manifest.packages.keys().collect::<Vec<_>>(),
+            ["noarch/dup-1.0-0.conda", "noarch/dup-2.0-0.conda"]
+        );
+        assert_eq!(
+            manifest.packages["noarch/dup-2.0-0.conda"].url.as_deref(),
+            Some("https://example.com/dup-2.0")
+        );
+        let read_channeldata = || -> serde_json::Value {
+            serde_json::from_slice(
+                &std::fs::read(temp_dir.path().join(CHANNELDATA_FILENAME)).unwrap(),
+            )
+            .unwrap()
+        };
+        let dup = &read_channeldata()["packages"]["dup"];
+        assert_eq!(dup["version"], "2.0");
+        assert_eq!(dup[channeldata::EXTENSION_KEY]["mirrored"], 2);
+        assert_eq!(
+            dup[channeldata::EXTENSION_KEY]["files"]["noarch/dup-1.0-0.conda"]["source"],
+            "owner/repo"
+        );
+
+        let rule = RetentionRule::new(&crate::retention::RetentionConfig {
+            keep: Some(1),
+            ..Default::default()
+        })
+        .unwrap();
+        repo.prune(&rule, false).await.unwrap();
+        assert_eq!(
+            read_manifest().packages.keys().collect::<Vec<_>>(),
+            ["noarch/dup-2.0-0.conda"]
+        );
+        assert_eq!(
+            read_channeldata()["packages"]["dup"][channeldata::EXTENSION_KEY]["mirrored"],
+            1
+        );
+        assert!(!temp_dir
+            .path()
+            .join(".mirror-manifest.json.partial")
+            .exists());
+
+        let cache = Repository::new(
+            RepositoryType::Cache,
+            temp_dir.path().join("cache").to_string_lossy().to_string(),
+        );
+        assert!(!cache.update_manifest(&report).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_strict_platform_refuses_guessed_platform() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut repo = Repository::new(
+            RepositoryType::Local,
+            temp_dir.path().to_string_lossy().to_string(),
+        )
+        .with_strict_platform(true);
+
+        let result = repo
+            .upload_package("guess-1.0-0.tar.bz2", Bytes::from_static(b"guessed"))
+            .await;
+        assert!(matches!(result, Err(MirrorError::GuessedPlatform { .. })));
+        assert_eq!(repo.get_package_stats().total_packages, 0);
+
+        repo.upload_package("dup-1.0-0.tar.bz2", legacy_package("linux-64"))
+            .await
+            .unwrap();
+        assert!(temp_dir
+            .path()
+            .join("linux-64")
+            .join("dup-1.0-0.tar.bz2")
+            .exists());
+    }
+
+    #[test]
+    fn test_duplicate_platform_policy_from_string() {
+        assert_eq!(
+            DuplicatePlatformPolicy::from_string("keep-first").unwrap(),
+            DuplicatePlatformPolicy::KeepFirst
+        );
+        assert_eq!(
+            DuplicatePlatformPolicy::from_string("prefer-metadata").unwrap(),
+            DuplicatePlatformPolicy::PreferMetadata
+        );
+        assert!(DuplicatePlatformPolicy::from_string("last").is_err());
+    }
+
+    #[test]
+    fn test_validate_target_filename() {
+        assert!(validate_target_filename("numpy-1.21.0-py39_0.conda").is_ok());
+        assert!(validate_target_filename("console-1.0-0.tar.bz2").is_ok());
+
+        for name in [
+            "",
+            "..",
+            "con.tar.bz2",
+            "NUL.conda",
+            "Lpt1.conda",
+            "a/b.conda",
+            "a\\b.conda",
+            "pkg:1.conda",
+            "pkg.conda.",
+            "pkg\u{7}.conda",
+        ] {
+            assert!(
+                validate_target_filename(name).is_err(),
+                "{:?} should be rejected",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_case_insensitive_duplicate_detection() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("NumPy-1.0-0.conda"), b"x").unwrap();
+
+        assert!(local_package_path(temp_dir.path(), "NumPy-1.0-0.conda").is_ok());
+        assert!(local_package_path(temp_dir.path(), "numpy-1.0-0.conda").is_err());
+        assert!(local_package_path(temp_dir.path(), "scipy-1.0-0.conda").is_ok());
+        assert!(local_package_path(&temp_dir.path().join("missing"), "a-1.0-0.conda").is_ok());
+    }
+
+    #[test]
+    fn test_extended_length_path() {
+        let short = Path::new("repo").join("noarch");
+        let long = std::env::temp_dir().join("a".repeat(300)).join("noarch");
+
+        if cfg!(windows) {
+            assert!(!extended_length_path(&short)
+                .to_string_lossy()
+                .starts_with(r"\\?\"));
+            assert!(extended_length_path(&long)
+                .to_string_lossy()
+                .starts_with(r"\\?\"));
+            assert_eq!(
+                normalize_local_path("C:/repo/conda"),
+                PathBuf::from(r"C:\repo\conda")
+            );
+        } else {
+            assert_eq!(extended_length_path(&short), short);
+            assert_eq!(extended_length_path(&long), long);
+            assert_eq!(
+                normalize_local_path("/srv/repo/conda"),
+                PathBuf::from("/srv/repo/conda")
+            );
+        }
+    }
+
+    #[test]
+    fn test_s3_bucket_and_prefix() {
+        assert_eq!(
+            s3_bucket_and_prefix("s3://bucket/conda/packages/").unwrap(),
+            ("bucket", "conda/packages")
+        );
+        assert_eq!(s3_bucket_and_prefix("s3://bucket").unwrap(), ("bucket", ""));
+        assert!(s3_bucket_and_prefix("s3://").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_preflight_local_target() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let target = temp_dir.path().join("repo");
+        let repo = Repository::new(RepositoryType::Local, target.to_string_lossy().to_string());
+
+        repo.preflight().await.unwrap();
+        assert!(target.exists());
+        assert!(!target.join(PREFLIGHT_MARKER).exists());
+    }
+
+    #[tokio::test]
+    async fn test_preflight_unwritable_local_target() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let blocker = temp_dir.path().join("file");
+        std::fs::write(&blocker, b"not a directory").unwrap();
+
+        let repo = Repository::new(
+            RepositoryType::Local,
+            blocker.join("repo").to_string_lossy().to_string(),
+        );
+        assert!(matches!(
+            repo.preflight().await,
+            Err(MirrorError::TargetIo { .. })
+        ));
+    }
+
+    /// Backend keeping packages in memory, standing in for a downstream store
+    #[derive(Default)]
+    struct MemoryBackend {
+        packages: std::sync::Mutex<HashMap<String, String>>,
+    }
+
+    #[async_trait]
+    impl RepositoryBackend for MemoryBackend {
+        fn location(&self, platform: &Platform, filename: &str) -> String {
+            format!("{}/{}", platform, filename)
+        }
+
+        async fn exists(&self, platform: &Platform, filename: &str) -> Result<Option<String>> {
+            let packages = self.packages.lock().unwrap();
+            Ok(packages.get(&self.location(platform, filename)).cloned())
+        }
+
+        async fn upload(&self, package: &ProcessedPackage) -> Result<()> {
+            let key = self.location(&package.platform, &package.filename);
+            self.packages
+                .lock()
+                .unwrap()
+                .insert(key, package.sha256.clone());
+            Ok(())
+        }
+
+        async fn list(&self) -> Result<Vec<String>> {
+            Ok(self.packages.lock().unwrap().keys().cloned().collect())
+        }
+
+        async fn finalize(
+            &self,
+            _packages: &HashMap<Platform, Vec<ProcessedPackage>>,
+        ) -> Result<()> {
+            Ok(())
+        }
+
+        async fn delete(&self, platform: &Platform, filename: &str) -> Result<()> {
+            let key = self.location(platform, filename);
+            self.packages.lock().unwrap().remove(&key);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_backend() {
+        let backend = Arc::new(MemoryBackend::default());
+        let mut repo = Repository::from_backend(RepositoryType::Local, "memory", backend.clone());
+
+        repo.upload_package("dup-1.0-0.tar.bz2", legacy_package("linux-64"))
+            .await
+            .unwrap();
+        assert_eq!(
+            backend.list().await.unwrap(),
+            vec!["linux-64/dup-1.0-0.tar.bz2".to_string()]
+        );
+
+        // Conflict checks apply to custom backends as well
+        let mut repo = Repository::from_backend(RepositoryType::Local, "memory", backend.clone());
+        let result = repo
+            .upload_package("dup-1.0-0.tar.bz2", legacy_package("osx-64"))
+            .await;
+        assert!(result.is_ok());
+        let result = repo
+            .upload_package("dup-1.0-0.tar.bz2", Bytes::from_static(b"guessed"))
+            .await;
+        assert!(matches!(result, Err(MirrorError::PlatformConflict { .. })));
+    }
+
+    /// Backend whose `repodata.json` is replaced by another writer once it is first read
+    #[derive(Default)]
+    struct RacedRepodataBackend {
+        reads: std::sync::Mutex<usize>,
+        signatures: std::sync::Mutex<Vec<Vec<u8>>>,
+    }
+
+    impl RacedRepodataBackend {
+        fn current(&self) -> (Vec<u8>, String) {
+            let reads = *self.reads.lock().unwrap();
+            let content = if reads == 0 {
+                "{}"
+            } else {
+                r#"{"packages":{}}"#
+            };
+            (
+                content.as_bytes().to_vec(),
+                format!("\"{}\"", content.len()),
+            )
+        }
+    }
+
+    #[async_trait]
+    impl RepositoryBackend for RacedRepodataBackend {
+        fn location(&self, platform: &Platform, filename: &str) -> String {
+            format!("{}/{}", platform, filename)
+        }
+
+        async fn exists(&self, _platform: &Platform, _filename: &str) -> Result<Option<String>> {
+            Ok(None)
+        }
+
+        async fn upload(&self, _package: &ProcessedPackage) -> Result<()> {
+            Ok(())
+        }
+
+        async fn list(&self) -> Result<Vec<String>> {
+            Ok(Vec::new())
+        }
+
+        async fn finalize(
+            &self,
+            _packages: &HashMap<Platform, Vec<ProcessedPackage>>,
+        ) -> Result<()> {
+            Ok(())
+        }
+
+        async fn delete(&self, _platform: &Platform, _filename: &str) -> Result<()> {
+            Ok(())
+        }
+
+        async fn store_signature(
+            &self,
+            _platform: &Platform,
+            _filename: &str,
+            signature: &[u8],
+        ) -> Result<bool> {
+            self.signatures.lock().unwrap().push(signature.to_vec());
+            Ok(true)
+        }
+
+        async fn versioned_repodata(
+            &self,
+            _platform: &Platform,
+        ) -> Result<Option<(Vec<u8>, Option<String>)>> {
+            let (content, version) = self.current();
+            *self.reads.lock().unwrap() += 1;
+            Ok(Some((content, Some(version))))
+        }
+
+        async fn repodata_version(&self, _platform: &Platform) -> Result<Option<String>> {
+            let (_, version) = self.current();
+            *self.reads.lock().unwrap() += 1;
+            Ok(Some(version))
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_sign_repodata_replaced_by_another_writer() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let backend = Arc::new(RacedRepodataBackend::default());
+        let repo = Repository::from_backend(RepositoryType::S3, "s3://bucket", backend.clone())
+            .with_signer(Some(Signer::new(&crate::signing::tests::fake_gpg(
+                temp_dir.path(),
+            ))));
+
+        repo.sign_repodata(&Platform::Linux64).await.unwrap();
+
+        // The first signature covers content that was replaced before it was
+        // stored, so the repodata of the other writer is signed again
+        let signatures = backend.signatures.lock().unwrap();
+        let signed_sizes: Vec<&str> = signatures
+            .iter()
+            .map(|signature| {
+                std::str::from_utf8(signature)
+                    .unwrap()
+                    .lines()
+                    .nth(1)
+                    .unwrap()
+                    .trim()
+            })
+            .collect();
+        assert_eq!(signed_sizes, vec!["2", "15"]);
+    }
+
+    #[tokio::test]
+    async fn test_local_backend_list_and_delete() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().to_string_lossy().to_string();
+        let mut repo = Repository::new(RepositoryType::Local, path.clone());
+        repo.upload_package("dup-1.0-0.tar.bz2", legacy_package("linux-64"))
+            .await
+            .unwrap();
+
+        let backend = LocalBackend::new(path);
+        assert_eq!(
+            backend.list().await.unwrap(),
+            vec!["linux-64/dup-1.0-0.tar.bz2".to_string()]
+        );
+
+        backend
+            .delete(&Platform::Linux64, "dup-1.0-0.tar.bz2")
+            .await
+            .unwrap();
+        assert!(backend.list().await.unwrap().is_empty());
+        backend
+            .delete(&Platform::Linux64, "dup-1.0-0.tar.bz2")
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn test_repository_new() {
+        let repo = Repository::new(RepositoryType::Local, "/tmp/test".to_string());
+        assert!(matches!(repo.repo_type, RepositoryType::Local));
+        assert_eq!(repo.path, "/tmp/test");
+    }
+
+    #[test]
+    fn test_cache_repository_has_package_cache() {
+        let cache_repo = Repository::new(RepositoryType::Cache, "/tmp/cache".to_string());
+        assert!(matches!(cache_repo.repo_type, RepositoryType::Cache));
         assert!(cache_repo.package_cache.is_some());
 
         let local_repo = Repository::new(RepositoryType::Local, "/tmp/local".to_string());
         assert!(matches!(local_repo.repo_type, RepositoryType::Local));
         assert!(local_repo.package_cache.is_none());
     }
+
+    async fn processed(name: &str) -> ProcessedPackage {
+        let fixture = crate::test_support::PackageFixture::new(name, "1.0");
+        CondaPackageHandler::new()
+            .process_package(fixture.to_conda(), &fixture.conda_filename())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_prefix_dev_uploads_in_batches() {
+        use crate::test_util::{MockResponse, MockServer};
+
+        let server = MockServer::start().await.unwrap();
+        server.mock(
+            "PUT",
+            "/channel/noarch/a-1.0-0.conda",
+            MockResponse::new(403, "forbidden"),
+        );
+        for name in ["b", "c"] {
+            server.mock(
+                "PUT",
+                &format!("/channel/noarch/{}-1.0-0.conda", name),
+                MockResponse::new(200, ""),
+            );
+        }
+        server.mock(
+            "GET",
+            "/channel/noarch/repodata.json",
+            MockResponse::json(200, r#"{"packages.conda": {"b-1.0-0.conda": {}}}"#),
+        );
+        let backend = PrefixDevBackend::new(format!("{}/channel", server.url())).with_batching(
+            UploadBatching {
+                max_packages: 2,
+                max_bytes: 0,
+                parallelism: 2,
+                attempts: 1,
+            },
+        );
+        let puts = || {
+            server
+                .requests()
+                .iter()
+                .filter(|request| request.method == "PUT")
+                .count()
+        };
+
+        backend.upload(&processed("a").await).await.unwrap();
+        assert_eq!(puts(), 0);
+        // The failure of the first package is not charged to the one completing the batch
+        backend.upload(&processed("b").await).await.unwrap();
+        assert_eq!(puts(), 2);
+        backend.upload(&processed("c").await).await.unwrap();
+        assert_eq!(puts(), 2);
+
+        // The rest is uploaded when finalizing, then the repodata is checked
+        backend.finalize(&HashMap::new()).await.unwrap();
+        assert_eq!(puts(), 3);
+        assert!(server
+            .requests()
+            .iter()
+            .any(|request| request.path == "/channel/noarch/repodata.json"));
+        let failed = backend.take_failed_uploads();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].filename, "a-1.0-0.conda");
+        assert!(backend.take_failed_uploads().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_oci_pushes_package_and_repodata() {
+        use crate::test_util::{MockResponse, MockServer};
+
+        let server = MockServer::start().await.unwrap();
+        for repository in ["conda/noarch/a", "conda/noarch/repodata.json"] {
+            server.mock(
+                "POST",
+                &format!("/v2/{}/blobs/uploads/", repository),
+                MockResponse::new(202, "").with_header("Location", "/upload/1"),
+            );
+        }
+        server.mock("PUT", "/upload/1", MockResponse::new(201, ""));
+        server.mock(
+            "PUT",
+            "/v2/conda/noarch/a/manifests/1.0-0",
+            MockResponse::new(201, ""),
+        );
+        server.mock(
+            "PUT",
+            "/v2/conda/noarch/repodata.json/manifests/latest",
+            MockResponse::new(201, ""),
+        );
+        let target = format!("oci://{}/conda", server.url().trim_start_matches("http://"));
+        let backend = OciBackend::with_client(target, reqwest::Client::new(), None);
+
+        let package = processed("a").await;
+        backend.upload(&package).await.unwrap();
+        backend
+            .finalize(&HashMap::from([(Platform::NoArch, vec![package])]))
+            .await
+            .unwrap();
+
+        let requests = server.requests();
+        let manifests: Vec<_> = requests
+            .iter()
+            .filter(|request| request.method == "PUT" && request.path.contains("/manifests/"))
+            .map(|request| request.path.as_str())
+            .collect();
+        assert_eq!(
+            manifests,
+            [
+                "/v2/conda/noarch/a/manifests/1.0-0",
+                "/v2/conda/noarch/repodata.json/manifests/latest"
+            ]
+        );
+        // The config blob, the package and its record, then the config blob and repodata again,
+        // as the mock registry does not hold blobs it was sent
+        let blobs = requests
+            .iter()
+            .filter(|request| request.path.starts_with("/upload/1?digest=sha256%3A"))
+            .count();
+        assert_eq!(blobs, 5);
+    }
+
+    #[tokio::test]
+    async fn test_prefix_dev_upload_retries_after_rate_limit() {
+        use crate::test_util::{MockResponse, MockServer};
+
+        let server = std::sync::Arc::new(MockServer::start().await.unwrap());
+        server.mock(
+            "PUT",
+            "/channel/noarch/a-1.0-0.conda",
+            MockResponse::new(429, "slow down").with_header("Retry-After", "1"),
+        );
+        server.mock(
+            "PUT",
+            "/channel/noarch/b-1.0-0.conda",
+            MockResponse::new(403, "forbidden"),
+        );
+        let backend = PrefixDevBackend::new(format!("{}/channel", server.url()));
+
+        // The channel recovers once the first attempt was refused
+        let recovering = std::sync::Arc::clone(&server);
+        let recovery = tokio::spawn(async move {
+            while recovering.requests().is_empty() {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+            recovering.mock(
+                "PUT",
+                "/channel/noarch/a-1.0-0.conda",
+                MockResponse::new(200, ""),
+            );
+        });
+        let started = std::time::Instant::now();
+        backend.upload(&processed("a").await).await.unwrap();
+        recovery.await.unwrap();
+        assert!(started.elapsed() >= std::time::Duration::from_secs(1));
+        assert_eq!(server.requests().len(), 2);
+
+        // Refusals that cannot succeed on retry fail at once
+        let error = backend.upload(&processed("b").await).await.unwrap_err();
+        assert!(error.to_string().contains("b-1.0-0.conda"));
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_cache_extract_is_found_by_package_cache() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let cache_dir = temp_dir.path().join("pkgs");
+        let mut repo = Repository::new(
+            RepositoryType::Cache,
+            cache_dir.to_string_lossy().to_string(),
+        )
+        .with_cache_extract(true);
+        let fixture = crate::test_support::PackageFixture::new("pkg", "1.0").subdir("linux-64");
+        let filename = fixture.conda_filename();
+        repo.upload_package(&filename, fixture.to_conda())
+            .await
+            .unwrap();
+        assert!(cache_dir.join(&filename).exists());
+        assert!(cache_dir.join("pkg-1.0-0").join("info/index.json").exists());
+
+        // rattler takes the extraction as is instead of fetching the package
+        let key = CacheKey::from(ArchiveIdentifier::try_from_filename(&filename).unwrap())
+            .with_sha256(Sha256::digest(fixture.to_conda()));
+        let lock = PackageCache::new(&cache_dir)
+            .get_or_fetch(
+                key,
+                |_| async { Err(std::io::Error::other("package was fetched")) },
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(lock.path(), cache_dir.join("pkg-1.0-0"));
+        drop(lock);
+
+        let backend = CacheBackend::new(cache_dir.to_string_lossy()).with_extract(true);
+        backend.delete(&Platform::Linux64, &filename).await.unwrap();
+        assert!(!cache_dir.join(&filename).exists());
+        assert!(!cache_dir.join("pkg-1.0-0").exists());
+
+        // Without extraction only the archive is written
+        let mut repo = Repository::new(
+            RepositoryType::Cache,
+            cache_dir.to_string_lossy().to_string(),
+        );
+        repo.upload_package(&filename, fixture.to_conda())
+            .await
+            .unwrap();
+        assert!(cache_dir.join(&filename).exists());
+        assert!(!cache_dir.join("pkg-1.0-0").exists());
+    }
 }
@@ -0,0 +1,95 @@
+//! Resume state for interrupted mirroring runs
+//!
+//! When a run is interrupted, the packages already mirrored and those still
+//! pending are written to a JSON file. A later run for the same source and
+//! target skips the completed packages and removes the file once it finishes.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::error::{MirrorError, Result};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResumeState {
+    pub source: String,
+    pub target: String,
+    pub completed: Vec<String>,
+    pub pending: Vec<String>,
+    pub interrupted_at: DateTime<Utc>,
+}
+
+impl ResumeState {
+    /// Load the state left by an interrupted run of the same source and target
+    pub fn load_for(path: &str, source: &str, target: &str) -> Result<Option<Self>> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let state: ResumeState = serde_json::from_str(&content).map_err(|e| {
+            MirrorError::InvalidInput(format!("Invalid resume state file '{}': {}", path, e))
+        })?;
+
+        Ok(Some(state).filter(|state| state.source == source && state.target == target))
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        if let Some(parent) = Path::new(path).parent() {
+            if !parent.as_os_str().is_empty() {
+                std::fs::create_dir_all(parent)?;
+            }
+        }
+        std::fs::write(path, content)?;
+        Ok(())
+    }
+
+    /// Remove the state file once the run it describes has completed
+    pub fn clear(path: &str) -> Result<()> {
+        match std::fs::remove_file(path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_resume_state_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("state.json");
+        let path = path.to_str().unwrap();
+
+        let state = ResumeState {
+            source: "artifacts.zip".to_string(),
+            target: "./repo".to_string(),
+            completed: vec!["a-1.0-0.conda".to_string()],
+            pending: vec!["b-1.0-0.conda".to_string()],
+            interrupted_at: Utc::now(),
+        };
+        state.save(path).unwrap();
+
+        let loaded = ResumeState::load_for(path, "artifacts.zip", "./repo")
+            .unwrap()
+            .unwrap();
+        assert_eq!(loaded.completed, state.completed);
+        assert_eq!(loaded.pending, state.pending);
+
+        // State from a different source or target is ignored
+        assert!(ResumeState::load_for(path, "other.zip", "./repo")
+            .unwrap()
+            .is_none());
+
+        ResumeState::clear(path).unwrap();
+        assert!(ResumeState::load_for(path, "artifacts.zip", "./repo")
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,495 @@
+//! Retention of the newest packages at a target
+//!
+//! A retention rule keeps the newest `keep` versions of each package name, or
+//! the newest `keep` builds of each package version, in every subdir, and/or
+//! removes packages stored longer ago than `older_than`, and/or removes
+//! packages no given lockfile references, which keeps a package cache down to
+//! what environments use. Packages matching a protected spec are never
+//! removed. The `prune` command applies a rule on
+//! demand, and a `retention` section applies it after every run that mirrored
+//! something. Packages are identified by their filenames, so packages written
+//! by earlier runs or other tools count too.
+
+use chrono::{DateTime, Duration, Utc};
+use rattler_conda_types::{
+    MatchSpec, Matches, PackageName, PackageRecord, ParseStrictness, Platform, Version,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::str::FromStr;
+
+use crate::error::{MirrorError, Result};
+
+/// What a retention rule counts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RetentionScope {
+    /// The newest versions of each package name, with all their builds
+    #[default]
+    Versions,
+    /// The newest builds of each package version
+    Builds,
+}
+
+impl RetentionScope {
+    pub fn from_string(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "versions" | "version" => Ok(RetentionScope::Versions),
+            "builds" | "build" => Ok(RetentionScope::Builds),
+            _ => Err(MirrorError::InvalidInput(format!(
+                "Unknown retention scope: {}. Must be one of: versions, builds",
+                s
+            ))),
+        }
+    }
+}
+
+/// Which packages to keep
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetentionConfig {
+    /// Number of versions or builds to keep
+    #[serde(default)]
+    pub keep: Option<usize>,
+    #[serde(default)]
+    pub scope: RetentionScope,
+    /// Age after which packages are removed, e.g. `180d`, `12w` or `36h`
+    #[serde(default)]
+    pub older_than: Option<String>,
+    /// Match specs of packages that are never removed, e.g. `python >=3.10`
+    #[serde(default)]
+    pub protect: Vec<String>,
+    /// Lockfiles (`pixi.lock`) whose packages are never removed
+    #[serde(default)]
+    pub lockfiles: Vec<String>,
+}
+
+/// Parse an age such as `180d`: a number followed by `s`, `m`, `h`, `d` or `w`
+pub fn parse_age(age: &str) -> Result<Duration> {
+    let invalid = || {
+        MirrorError::InvalidInput(format!(
+            "Invalid age '{}': expected a number followed by s, m, h, d or w, e.g. 180d",
+            age
+        ))
+    };
+    let unit_at = age.len().checked_sub(1).ok_or_else(invalid)?;
+    let (amount, unit) = age.split_at(unit_at);
+    let amount: i64 = amount.parse().map_err(|_| invalid())?;
+    match unit {
+        "s" => Duration::try_seconds(amount),
+        "m" => Duration::try_minutes(amount),
+        "h" => Duration::try_hours(amount),
+        "d" => Duration::try_days(amount),
+        "w" => Duration::try_weeks(amount),
+        _ => None,
+    }
+    .filter(|age| *age > Duration::zero())
+    .ok_or_else(invalid)
+}
+
+/// A validated [`RetentionConfig`]
+#[derive(Debug, Clone)]
+pub struct RetentionRule {
+    keep: Option<usize>,
+    scope: RetentionScope,
+    older_than: Option<Duration>,
+    protect: Vec<MatchSpec>,
+    /// Filenames of the packages the lockfiles reference, if any lockfiles are given
+    referenced: Option<HashSet<String>>,
+}
+
+impl RetentionRule {
+    pub fn new(config: &RetentionConfig) -> Result<Self> {
+        if config.keep == Some(0) {
+            return Err(MirrorError::InvalidInput(
+                "Retention must keep at least one version or build".to_string(),
+            ));
+        }
+        if config.keep.is_none() && config.older_than.is_none() && config.lockfiles.is_empty() {
+            return Err(MirrorError::InvalidInput(
+                "Retention needs keep, older_than or lockfiles".to_string(),
+            ));
+        }
+        let protect = config
+            .protect
+            .iter()
+            .map(|spec| {
+                MatchSpec::from_str(spec, ParseStrictness::Lenient).map_err(|e| {
+                    MirrorError::InvalidInput(format!("Invalid protected spec '{}': {}", spec, e))
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            keep: config.keep,
+            scope: config.scope,
+            older_than: config.older_than.as_deref().map(parse_age).transpose()?,
+            protect,
+            referenced: referenced_packages(&config.lockfiles)?,
+        })
+    }
+
+    /// Whether the rule needs to know when packages were stored
+    pub fn uses_age(&self) -> bool {
+        self.older_than.is_some()
+    }
+
+    /// Paths of the packages the rule removes, sorted
+    ///
+    /// `stored_at` dates the paths; with `older_than`, undated packages are
+    /// kept. A package is removed only when every criterion of the rule removes
+    /// it: beyond the newest `keep`, older than `older_than`, and referenced by
+    /// none of the lockfiles. Paths that are not conda package filenames are
+    /// never removed.
+    pub fn expired(
+        &self,
+        paths: &[String],
+        stored_at: &HashMap<String, DateTime<Utc>>,
+        now: DateTime<Utc>,
+    ) -> Vec<String> {
+        let candidates = match self.keep {
+            Some(keep) => beyond_newest(paths, keep, self.scope),
+            None => paths.to_vec(),
+        };
+        let mut expired: Vec<String> = candidates
+            .into_iter()
+            .filter(|path| self.removes(path, stored_at.get(path), now))
+            .collect();
+        expired.sort();
+        expired
+    }
+
+    fn removes(&self, path: &str, stored_at: Option<&DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+        let Some(package) = StoredPackage::parse(path) else {
+            return false;
+        };
+        if self
+            .protect
+            .iter()
+            .any(|spec| spec.matches(&package.record()))
+        {
+            return false;
+        }
+        if let Some(referenced) = &self.referenced {
+            let filename = path.rsplit_once('/').map_or(path, |(_, filename)| filename);
+            if referenced.contains(filename) {
+                return false;
+            }
+        }
+        match (self.older_than, stored_at) {
+            (None, _) => true,
+            (Some(older_than), Some(stored_at)) => now - *stored_at > older_than,
+            (Some(_), None) => false,
+        }
+    }
+}
+
+/// Filenames of the packages any of `lockfiles` references, `None` without lockfiles
+#[cfg(feature = "solve")]
+fn referenced_packages(lockfiles: &[String]) -> Result<Option<HashSet<String>>> {
+    if lockfiles.is_empty() {
+        return Ok(None);
+    }
+    let mut referenced = HashSet::new();
+    for lockfile in lockfiles {
+        referenced.extend(crate::lockfile::referenced_packages(std::path::Path::new(
+            lockfile,
+        ))?);
+    }
+    Ok(Some(referenced))
+}
+
+#[cfg(not(feature = "solve"))]
+fn referenced_packages(lockfiles: &[String]) -> Result<Option<HashSet<String>>> {
+    if lockfiles.is_empty() {
+        return Ok(None);
+    }
+    Err(MirrorError::InvalidInput(
+        "Protecting the packages of lockfiles requires the 'solve' feature, which this build was compiled without"
+            .to_string(),
+    ))
+}
+
+/// A stored package, identified by its `subdir/filename` path
+#[derive(Debug, Clone)]
+struct StoredPackage<'a> {
+    path: &'a str,
+    subdir: &'a str,
+    name: &'a str,
+    version: Version,
+    build: &'a str,
+}
+
+impl<'a> StoredPackage<'a> {
+    /// Parse `name-version-build.conda` (or `.tar.bz2`), optionally under a subdir
+    fn parse(path: &'a str) -> Option<Self> {
+        let (subdir, filename) = path.rsplit_once('/').unwrap_or(("", path));
+        let stem = filename
+            .strip_suffix(".conda")
+            .or_else(|| filename.strip_suffix(".tar.bz2"))?;
+        let mut parts = stem.rsplitn(3, '-');
+        let build = parts.next()?;
+        let version = Version::from_str(parts.next()?).ok()?;
+        let name = parts.next().filter(|name| !name.is_empty())?;
+        Some(Self {
+            path,
+            subdir,
+            name,
+            version,
+            build,
+        })
+    }
+
+    /// Build number by the conda convention of a trailing `_<number>` or a numeric build
+    fn build_number(&self) -> u64 {
+        let digits = self.build.rsplit('_').next().unwrap_or(self.build);
+        digits.parse().unwrap_or(0)
+    }
+
+    /// A record with what the filename tells, for matching against specs
+    fn record(&self) -> PackageRecord {
+        let name = PackageName::new_unchecked(self.name);
+        let mut record = PackageRecord::new(name, self.version.clone(), self.build.to_string());
+        record.build_number = self.build_number();
+        record.subdir = if self.subdir.is_empty() {
+            Platform::NoArch.to_string()
+        } else {
+            self.subdir.to_string()
+        };
+        record
+    }
+}
+
+/// Paths of the packages beyond the newest `keep` versions or builds
+fn beyond_newest(paths: &[String], keep: usize, scope: RetentionScope) -> Vec<String> {
+    let packages: Vec<StoredPackage> = paths
+        .iter()
+        .filter_map(|path| StoredPackage::parse(path))
+        .collect();
+
+    let mut expired = Vec::new();
+    match scope {
+        RetentionScope::Versions => {
+            let mut groups: BTreeMap<(&str, &str), Vec<&StoredPackage>> = BTreeMap::new();
+            for package in &packages {
+                groups
+                    .entry((package.subdir, package.name))
+                    .or_default()
+                    .push(package);
+            }
+            for group in groups.values() {
+                let versions: BTreeSet<&Version> = group.iter().map(|p| &p.version).collect();
+                let kept: Vec<&Version> = versions.into_iter().rev().take(keep).collect();
+                expired.extend(
+                    group
+                        .iter()
+                        .filter(|p| !kept.contains(&&p.version))
+                        .map(|p| p.path.to_string()),
+                );
+            }
+        }
+        RetentionScope::Builds => {
+            let mut groups: BTreeMap<(&str, &str, &Version), Vec<&StoredPackage>> = BTreeMap::new();
+            for package in &packages {
+                groups
+                    .entry((package.subdir, package.name, &package.version))
+                    .or_default()
+                    .push(package);
+            }
+            for group in groups.values() {
+                // Both formats of one build are kept or removed together
+                let builds: BTreeSet<(u64, &str)> =
+                    group.iter().map(|p| (p.build_number(), p.build)).collect();
+                let kept: Vec<&str> = builds
+                    .into_iter()
+                    .rev()
+                    .take(keep)
+                    .map(|(_, build)| build)
+                    .collect();
+                expired.extend(
+                    group
+                        .iter()
+                        .filter(|p| !kept.contains(&p.build))
+                        .map(|p| p.path.to_string()),
+                );
+            }
+        }
+    }
+    expired
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths() -> Vec<String> {
+        [
+            "linux-64/numpy-1.26.4-py312h1_0.conda",
+            "linux-64/numpy-1.26.4-py312h1_1.conda",
+            "linux-64/numpy-1.26.4-py312h1_1.tar.bz2",
+            "linux-64/numpy-2.0.0-py312h2_0.conda",
+            "linux-64/numpy-1.9.3-py27_0.tar.bz2",
+            "noarch/numpy-1.9.3-py27_0.tar.bz2",
+            "linux-64/ca-certificates-2024.2.2-hbcca054_0.conda",
+            "linux-64/repodata.json",
+        ]
+        .iter()
+        .map(|path| path.to_string())
+        .collect()
+    }
+
+    fn rule(keep: Option<usize>, scope: RetentionScope) -> RetentionRule {
+        RetentionRule::new(&RetentionConfig {
+            keep,
+            scope,
+            ..RetentionConfig::default()
+        })
+        .unwrap()
+    }
+
+    fn expired(rule: &RetentionRule) -> Vec<String> {
+        rule.expired(&paths(), &HashMap::new(), Utc::now())
+    }
+
+    #[test]
+    fn test_keep_newest_versions() {
+        // 1.9.3 sorts below 1.26.4 as a version, though not as a string
+        assert_eq!(
+            expired(&rule(Some(2), RetentionScope::Versions)),
+            vec!["linux-64/numpy-1.9.3-py27_0.tar.bz2"]
+        );
+        assert_eq!(
+            expired(&rule(Some(1), RetentionScope::Versions)),
+            vec![
+                "linux-64/numpy-1.26.4-py312h1_0.conda",
+                "linux-64/numpy-1.26.4-py312h1_1.conda",
+                "linux-64/numpy-1.26.4-py312h1_1.tar.bz2",
+                "linux-64/numpy-1.9.3-py27_0.tar.bz2",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_keep_newest_builds() {
+        assert_eq!(
+            expired(&rule(Some(1), RetentionScope::Builds)),
+            vec!["linux-64/numpy-1.26.4-py312h1_0.conda"]
+        );
+        assert_eq!(
+            RetentionScope::from_string("builds").unwrap(),
+            RetentionScope::Builds
+        );
+        assert!(RetentionScope::from_string("everything").is_err());
+    }
+
+    #[test]
+    fn test_older_than_with_protected_specs() {
+        let now = Utc::now();
+        let stored_at: HashMap<String, DateTime<Utc>> = paths()
+            .into_iter()
+            .map(|path| {
+                let age = if path.contains("2.0.0") { 10 } else { 400 };
+                (path, now - Duration::days(age))
+            })
+            .filter(|(path, _)| !path.starts_with("noarch/"))
+            .collect();
+        let rule = RetentionRule::new(&RetentionConfig {
+            older_than: Some("180d".to_string()),
+            protect: vec![
+                "numpy >=1.26,<1.27".to_string(),
+                "numpy[build=py27_0]".to_string(),
+            ],
+            ..RetentionConfig::default()
+        })
+        .unwrap();
+        // Recent, protected and undated packages stay
+        assert_eq!(
+            rule.expired(&paths(), &stored_at, now),
+            vec!["linux-64/ca-certificates-2024.2.2-hbcca054_0.conda"]
+        );
+
+        // Only packages beyond the newest version and old enough go
+        let rule = RetentionRule::new(&RetentionConfig {
+            keep: Some(1),
+            older_than: Some("180d".to_string()),
+            protect: vec!["numpy 1.9.*".to_string()],
+            ..RetentionConfig::default()
+        })
+        .unwrap();
+        assert_eq!(
+            rule.expired(&paths(), &stored_at, now),
+            vec![
+                "linux-64/numpy-1.26.4-py312h1_0.conda",
+                "linux-64/numpy-1.26.4-py312h1_1.conda",
+                "linux-64/numpy-1.26.4-py312h1_1.tar.bz2",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unreferenced_cached_packages() {
+        let cached: Vec<String> = [
+            "numpy-1.26.4-py312h1_0.conda",
+            "numpy-2.0.0-py312h2_0.conda",
+            "ca-certificates-2024.2.2-hbcca054_0.conda",
+        ]
+        .iter()
+        .map(|path| path.to_string())
+        .collect();
+        let mut rule = RetentionRule {
+            keep: None,
+            scope: RetentionScope::default(),
+            older_than: None,
+            protect: Vec::new(),
+            referenced: Some(HashSet::from(["numpy-2.0.0-py312h2_0.conda".to_string()])),
+        };
+        let now = Utc::now();
+        assert_eq!(
+            rule.expired(&cached, &HashMap::new(), now),
+            vec![
+                "ca-certificates-2024.2.2-hbcca054_0.conda",
+                "numpy-1.26.4-py312h1_0.conda",
+            ]
+        );
+
+        // Unreferenced packages that are still recent stay
+        rule.older_than = Some(Duration::days(30));
+        let stored_at: HashMap<String, DateTime<Utc>> = cached
+            .iter()
+            .map(|path| {
+                let age = if path.starts_with("ca-") { 10 } else { 400 };
+                (path.clone(), now - Duration::days(age))
+            })
+            .collect();
+        assert_eq!(
+            rule.expired(&cached, &stored_at, now),
+            vec!["numpy-1.26.4-py312h1_0.conda"]
+        );
+
+        assert!(RetentionRule::new(&RetentionConfig {
+            lockfiles: vec!["/nonexistent/pixi.lock".to_string()],
+            ..RetentionConfig::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_age_and_validation() {
+        assert_eq!(parse_age("180d").unwrap(), Duration::days(180));
+        assert_eq!(parse_age("12w").unwrap(), Duration::weeks(12));
+        assert_eq!(parse_age("36h").unwrap(), Duration::hours(36));
+        for invalid in ["", "d", "180", "-1d", "0d", "3y"] {
+            assert!(parse_age(invalid).is_err(), "{}", invalid);
+        }
+        assert!(RetentionRule::new(&RetentionConfig::default()).is_err());
+        assert!(RetentionRule::new(&RetentionConfig {
+            keep: Some(0),
+            ..RetentionConfig::default()
+        })
+        .is_err());
+        assert!(RetentionRule::new(&RetentionConfig {
+            keep: Some(1),
+            protect: vec!["numpy >=>1".to_string()],
+            ..RetentionConfig::default()
+        })
+        .is_err());
+    }
+}
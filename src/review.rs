@@ -0,0 +1,414 @@
+//! Review of packages held back by policy, license or vulnerability rules
+//!
+//! Rules whose `action` is `quarantine` do not just skip a package: a copy is
+//! written to `quarantine_dir` and listed as pending in its `review.json`,
+//! together with why it was held, the source it came from and the target it
+//! was meant for. `review approve` mirrors pending packages into that target,
+//! past the rules that held them back, and re-indexes it; `review reject`
+//! deletes them. Either way the decision stays in `review.json`, so a package
+//! never has to be mirrored again from its source after a manual review.
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use comfy_table::presets::NOTHING;
+use comfy_table::{Attribute, Cell, ContentArrangement, Table};
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::error::{MirrorError, Result};
+use crate::mirror::{mirror_from_provider, target_repository};
+use crate::quarantine::quarantine_file;
+use crate::report::{MirrorReport, PackageOutcome};
+use crate::repository::RepositoryType;
+use crate::source::{PackageEntry, PackageStream, SourceProvider};
+
+/// Name of the review manifest inside the quarantine directory
+pub const REVIEW_FILENAME: &str = "review.json";
+
+/// Where a held package stands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReviewStatus {
+    Pending,
+    Approved,
+    Rejected,
+}
+
+impl std::fmt::Display for ReviewStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ReviewStatus::Pending => "pending",
+            ReviewStatus::Approved => "approved",
+            ReviewStatus::Rejected => "rejected",
+        })
+    }
+}
+
+/// One package held for review
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReviewEntry {
+    /// Package filename, e.g. `numpy-1.26.0-py312_0.conda`
+    pub filename: String,
+    /// Why the package was held
+    pub reason: String,
+    /// Source of the run that held it
+    pub source: String,
+    /// Target type and path the package was meant for
+    pub target_type: String,
+    pub target: String,
+    pub sha256: String,
+    pub quarantined_at: DateTime<Utc>,
+    pub status: ReviewStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reviewed_at: Option<DateTime<Utc>>,
+}
+
+/// Every package held in a quarantine directory, by the name of its copy there
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ReviewManifest {
+    pub packages: BTreeMap<String, ReviewEntry>,
+}
+
+impl ReviewManifest {
+    /// Load the manifest of `dir`, empty if there is none yet
+    pub fn load(dir: &Path) -> Result<Self> {
+        let path = dir.join(REVIEW_FILENAME);
+        let content = match std::fs::read(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(MirrorError::target_io(&path, e)),
+        };
+        serde_json::from_slice(&content).map_err(|e| {
+            MirrorError::Corrupt(format!("Invalid review manifest {}: {}", path.display(), e))
+        })
+    }
+
+    /// Write the manifest next to the packages it lists, replacing the old one whole
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        std::fs::create_dir_all(dir).map_err(|e| MirrorError::target_io(dir, e))?;
+        let path = dir.join(REVIEW_FILENAME);
+        let staged = dir.join(format!("{}.tmp", REVIEW_FILENAME));
+        std::fs::write(&staged, serde_json::to_vec_pretty(self)?)
+            .map_err(|e| MirrorError::target_io(&staged, e))?;
+        std::fs::rename(&staged, &path).map_err(|e| MirrorError::target_io(&path, e))
+    }
+
+    /// Pending packages selected by the name of their copy or their filename
+    ///
+    /// Every pending package is selected when `selectors` is empty.
+    pub fn select_pending(&self, selectors: &[String]) -> Result<Vec<String>> {
+        let pending = self
+            .packages
+            .iter()
+            .filter(|(_, entry)| entry.status == ReviewStatus::Pending);
+        if selectors.is_empty() {
+            return Ok(pending.map(|(id, _)| id.clone()).collect());
+        }
+        let pending: Vec<_> = pending.collect();
+        let mut selected = Vec::new();
+        for selector in selectors {
+            let matching: Vec<_> = pending
+                .iter()
+                .filter(|(id, entry)| *id == selector || &entry.filename == selector)
+                .map(|(id, _)| (*id).clone())
+                .collect();
+            if matching.is_empty() {
+                return Err(MirrorError::NotFound(format!(
+                    "No package pending review matches '{}'",
+                    selector
+                )));
+            }
+            selected.extend(matching);
+        }
+        selected.dedup();
+        Ok(selected)
+    }
+}
+
+/// Hold a package for review: keep a copy in `dir` and list it as pending
+pub fn hold(
+    dir: &Path,
+    filename: &str,
+    content: &[u8],
+    reason: &str,
+    source: &str,
+    target_type: &RepositoryType,
+    target: &str,
+) -> Result<PathBuf> {
+    let path = quarantine_file(dir, filename, content, reason)?;
+    let id = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut manifest = ReviewManifest::load(dir)?;
+    manifest.packages.insert(
+        id,
+        ReviewEntry {
+            filename: filename.to_string(),
+            reason: reason.to_string(),
+            source: source.to_string(),
+            target_type: target_type.as_str().to_string(),
+            target: target.to_string(),
+            sha256: format!("{:x}", Sha256::digest(content)),
+            quarantined_at: Utc::now(),
+            status: ReviewStatus::Pending,
+            reviewed_at: None,
+        },
+    );
+    manifest.save(dir)?;
+    Ok(path)
+}
+
+/// Held packages, as a source for a mirror run into their target
+struct ReviewSource {
+    name: String,
+    dir: PathBuf,
+    entries: Vec<(String, ReviewEntry)>,
+}
+
+#[async_trait]
+impl SourceProvider for ReviewSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn entries(&self) -> Result<PackageStream> {
+        let entries: Vec<Result<PackageEntry>> = self
+            .entries
+            .iter()
+            .map(|(id, entry)| {
+                let path = self.dir.join(id);
+                let expected = entry.sha256.clone();
+                Ok(PackageEntry::new(entry.filename.clone(), None, async move {
+                    read_held(&path, &expected)
+                })
+                .with_origin(entry.source.clone()))
+            })
+            .collect();
+        Ok(stream::iter(entries).boxed())
+    }
+}
+
+/// Read a held package back, checking it was not changed while it waited
+fn read_held(path: &Path, expected: &str) -> Result<Bytes> {
+    let content = std::fs::read(path).map_err(|e| MirrorError::target_io(path, e))?;
+    let actual = format!("{:x}", Sha256::digest(&content));
+    if actual != expected {
+        return Err(MirrorError::Corrupt(format!(
+            "{} has sha256 {}, but was held with {}",
+            path.display(),
+            actual,
+            expected
+        )));
+    }
+    Ok(Bytes::from(content))
+}
+
+/// Mirror the selected pending packages into the targets they were held from
+///
+/// The policy and vulnerability rules of `config` are not applied again.
+/// Packages that made it into their target are marked approved and their
+/// copies removed; those that failed stay pending.
+pub async fn approve(
+    dir: &Path,
+    selectors: &[String],
+    config: &Config,
+) -> Result<Vec<MirrorReport>> {
+    let mut manifest = ReviewManifest::load(dir)?;
+    let selected = manifest.select_pending(selectors)?;
+    let mut by_target: BTreeMap<(String, String), Vec<(String, ReviewEntry)>> = BTreeMap::new();
+    for id in selected {
+        let entry = manifest.packages[&id].clone();
+        by_target
+            .entry((entry.target_type.clone(), entry.target.clone()))
+            .or_default()
+            .push((id, entry));
+    }
+
+    let config = Config {
+        policy: None,
+        vulnerabilities: None,
+        ..config.clone()
+    };
+    let mut reports = Vec::new();
+    for ((target_type, target), entries) in by_target {
+        info!("Approving {} packages for {}", entries.len(), target);
+        let mut repository =
+            target_repository(RepositoryType::from_string(&target_type)?, &target, &config)?;
+        let source = ReviewSource {
+            name: format!("review of {}", dir.display()),
+            dir: dir.to_path_buf(),
+            entries,
+        };
+        let report = mirror_from_provider(&source, &mut repository, &config).await?;
+
+        for (id, entry) in &source.entries {
+            let delivered = report.packages.iter().any(|package| {
+                package.filename == entry.filename
+                    && !matches!(package.outcome, PackageOutcome::Failed { .. })
+            });
+            if delivered {
+                decide(&mut manifest, dir, id, ReviewStatus::Approved);
+            }
+        }
+        manifest.save(dir)?;
+        reports.push(report);
+    }
+    Ok(reports)
+}
+
+/// Delete the selected pending packages, recording that they were rejected
+pub fn reject(dir: &Path, selectors: &[String]) -> Result<Vec<String>> {
+    let mut manifest = ReviewManifest::load(dir)?;
+    let selected = manifest.select_pending(selectors)?;
+    for id in &selected {
+        decide(&mut manifest, dir, id, ReviewStatus::Rejected);
+    }
+    manifest.save(dir)?;
+    Ok(selected)
+}
+
+/// Record a decision and remove the held copy and its `.reason` file
+fn decide(manifest: &mut ReviewManifest, dir: &Path, id: &str, status: ReviewStatus) {
+    if let Some(entry) = manifest.packages.get_mut(id) {
+        entry.status = status;
+        entry.reviewed_at = Some(Utc::now());
+    }
+    for path in [dir.join(id), dir.join(format!("{}.reason", id))] {
+        if let Err(e) = std::fs::remove_file(&path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                warn!("Failed to remove {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+/// Print the packages of a review manifest
+pub fn print_entries(manifest: &ReviewManifest, all: bool, format: &str) -> Result<()> {
+    let entries: BTreeMap<&String, &ReviewEntry> = manifest
+        .packages
+        .iter()
+        .filter(|(_, entry)| all || entry.status == ReviewStatus::Pending)
+        .collect();
+    match format.to_lowercase().as_str() {
+        "yaml" => println!("{}", serde_yaml::to_string(&entries)?),
+        "json" => println!("{}", serde_json::to_string_pretty(&entries)?),
+        "table" => {
+            let mut table = Table::new();
+            table
+                .load_preset(NOTHING)
+                .set_content_arrangement(ContentArrangement::Dynamic)
+                .set_header(
+                    ["ID", "Package", "Target", "Held", "Status", "Reason"]
+                        .iter()
+                        .map(|title| Cell::new(title).add_attribute(Attribute::Bold)),
+                );
+            for (id, entry) in entries {
+                table.add_row(vec![
+                    Cell::new(id),
+                    Cell::new(&entry.filename),
+                    Cell::new(&entry.target),
+                    Cell::new(entry.quarantined_at.format("%Y-%m-%d %H:%M")),
+                    Cell::new(entry.status),
+                    Cell::new(&entry.reason),
+                ]);
+            }
+            println!("{}", table);
+        }
+        _ => {
+            return Err(MirrorError::InvalidInput(format!(
+                "Unsupported output format: {}. Supported formats: yaml, json, table",
+                format
+            )))
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::Repository;
+    use crate::test_support::PackageFixture;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_approve_and_reject_held_packages() {
+        let temp_dir = TempDir::new().unwrap();
+        let quarantine = temp_dir.path().join("quarantine");
+        let channel = temp_dir.path().join("channel");
+        let target = channel.to_string_lossy().to_string();
+
+        let fixtures = [
+            PackageFixture::new("held", "1.0").subdir("linux-64"),
+            PackageFixture::new("other", "1.0").subdir("linux-64"),
+        ];
+        for fixture in &fixtures {
+            hold(
+                &quarantine,
+                &fixture.conda_filename(),
+                &fixture.to_conda(),
+                "license GPL-3.0-only (family GPL3) is not allowed",
+                "owner/repo",
+                &RepositoryType::Local,
+                &target,
+            )
+            .unwrap();
+        }
+        let manifest = ReviewManifest::load(&quarantine).unwrap();
+        assert_eq!(manifest.select_pending(&[]).unwrap().len(), 2);
+        assert!(manifest.select_pending(&["missing".to_string()]).is_err());
+
+        // The policy that held the package back does not apply to approvals
+        let config = Config {
+            policy: Some(crate::policy::PolicyConfig {
+                deny: vec!["held".to_string()],
+                ..Default::default()
+            }),
+            resume_state_file: temp_dir
+                .path()
+                .join("resume.json")
+                .to_string_lossy()
+                .to_string(),
+            quarantine_dir: quarantine.to_string_lossy().to_string(),
+            ..Config::default()
+        };
+        let reports = approve(&quarantine, &[fixtures[0].conda_filename()], &config)
+            .await
+            .unwrap();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].mirrored_count(), 1);
+
+        let repository = Repository::new(RepositoryType::Local, target.clone());
+        let listed = crate::repository::listed_packages(repository.backend().as_ref())
+            .await
+            .unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].filename, fixtures[0].conda_filename());
+
+        let rejected = reject(&quarantine, &[]).unwrap();
+        assert_eq!(rejected.len(), 1);
+
+        let manifest = ReviewManifest::load(&quarantine).unwrap();
+        let statuses: Vec<_> = manifest
+            .packages
+            .values()
+            .map(|entry| (entry.filename.clone(), entry.status))
+            .collect();
+        assert!(statuses.contains(&(fixtures[0].conda_filename(), ReviewStatus::Approved)));
+        assert!(statuses.contains(&(fixtures[1].conda_filename(), ReviewStatus::Rejected)));
+        assert!(manifest.select_pending(&[]).unwrap().is_empty());
+        // Only the manifest is left once every package is decided
+        let left: Vec<_> = std::fs::read_dir(&quarantine)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(left, [REVIEW_FILENAME]);
+    }
+}
@@ -0,0 +1,576 @@
+//! Software bill of materials for a conda channel
+//!
+//! The `sbom` command reads the `repodata.json` of every subdir of a channel,
+//! local or remote, and describes its packages as a CycloneDX 1.5 or SPDX 2.3
+//! JSON document: names, versions, licenses, sha256 and md5 digests, package
+//! URLs, and the dependency relationships between packages of the channel.
+//! Dependencies on packages outside the channel (virtual packages, other
+//! channels) are left out of the relationships.
+
+use chrono::{DateTime, Utc};
+use rattler_conda_types::{MatchSpec, ParseStrictness, Platform, Version};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::str::FromStr;
+use tracing::{info, warn};
+
+use crate::auth;
+use crate::error::{MirrorError, Result};
+use crate::politeness;
+
+/// Document format of the bill of materials
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SbomFormat {
+    CycloneDx,
+    Spdx,
+}
+
+impl SbomFormat {
+    pub fn from_string(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "cyclonedx" => Ok(SbomFormat::CycloneDx),
+            "spdx" => Ok(SbomFormat::Spdx),
+            _ => Err(MirrorError::InvalidInput(format!(
+                "Unsupported SBOM format: {}. Supported formats: cyclonedx, spdx",
+                s
+            ))),
+        }
+    }
+}
+
+/// A package listed in the repodata of a channel
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChannelPackage {
+    #[serde(skip)]
+    pub filename: String,
+    pub name: String,
+    pub version: String,
+    pub build: String,
+    #[serde(default)]
+    pub build_number: u64,
+    #[serde(default)]
+    pub subdir: String,
+    #[serde(default)]
+    pub license: Option<String>,
+    #[serde(default)]
+    pub sha256: Option<String>,
+    #[serde(default)]
+    pub md5: Option<String>,
+    /// Size of the package file in bytes
+    #[serde(default)]
+    pub size: Option<u64>,
+    #[serde(default)]
+    pub depends: Vec<String>,
+}
+
+impl ChannelPackage {
+    /// Package URL, e.g. `pkg:conda/numpy@1.26.0?build=py312_0&subdir=linux-64&type=conda`
+    pub fn purl(&self) -> String {
+        let package_type = if self.filename.ends_with(".tar.bz2") {
+            "tar.bz2"
+        } else {
+            "conda"
+        };
+        let qualifiers: String = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("build", &self.build)
+            .append_pair("subdir", &self.subdir)
+            .append_pair("type", package_type)
+            .finish();
+        format!(
+            "pkg:conda/{}@{}?{}",
+            self.name,
+            self.version.replace('+', "%2B").replace('!', "%21"),
+            qualifiers
+        )
+    }
+
+    /// License as recorded in the package, if it is not empty
+    fn license(&self) -> Option<&str> {
+        self.license
+            .as_deref()
+            .map(str::trim)
+            .filter(|license| !license.is_empty())
+    }
+}
+
+#[derive(Deserialize)]
+struct RepodataFile {
+    #[serde(default)]
+    packages: BTreeMap<String, ChannelPackage>,
+    #[serde(default, rename = "packages.conda")]
+    conda_packages: BTreeMap<String, ChannelPackage>,
+}
+
+/// Read the packages of a channel
+///
+/// `channel` is a local repository path or the URL of a channel. Without
+/// `subdirs`, every subdir of a local channel with a `repodata.json` is read,
+/// and every known platform subdir of a remote channel that has one.
+pub async fn load_channel(
+    client: &Client,
+    channel: &str,
+    subdirs: &[String],
+) -> Result<Vec<ChannelPackage>> {
+    let remote = channel.starts_with("http://") || channel.starts_with("https://");
+    let subdirs = match (subdirs.is_empty(), remote) {
+        (false, _) => subdirs.to_vec(),
+        (true, false) => local_subdirs(Path::new(channel))?,
+        (true, true) => Platform::all().map(|p| p.to_string()).collect(),
+    };
+
+    let mut packages = Vec::new();
+    for subdir in &subdirs {
+        let Some(content) = read_repodata(client, channel, subdir).await? else {
+            continue;
+        };
+
+        let repodata: RepodataFile = serde_json::from_str(&content).map_err(|e| {
+            MirrorError::InvalidResponse(format!("Invalid repodata.json for {}: {}", subdir, e))
+        })?;
+        for (filename, mut package) in repodata.packages.into_iter().chain(repodata.conda_packages)
+        {
+            package.filename = filename;
+            if package.subdir.is_empty() {
+                package.subdir = subdir.clone();
+            }
+            packages.push(package);
+        }
+    }
+
+    if packages.is_empty() {
+        return Err(MirrorError::NotFound(format!(
+            "No packages found in channel {}",
+            channel
+        )));
+    }
+    info!(
+        "Read {} packages from {} subdirs of {}",
+        packages.len(),
+        subdirs.len(),
+        channel
+    );
+
+    packages.sort_by(|a, b| (&a.subdir, &a.filename).cmp(&(&b.subdir, &b.filename)));
+    Ok(packages)
+}
+
+/// Read the `repodata.json` of one subdir of a local or remote channel
+///
+/// Returns `None` when the subdir has no `repodata.json`.
+pub(crate) async fn read_repodata(
+    client: &Client,
+    channel: &str,
+    subdir: &str,
+) -> Result<Option<String>> {
+    if channel.starts_with("http://") || channel.starts_with("https://") {
+        let url = format!("{}/{}/repodata.json", channel.trim_end_matches('/'), subdir);
+        let response = politeness::throttled(&url, || auth::get(client, &url).send()).await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(MirrorError::from_status(
+                response.status(),
+                &format!("Failed to fetch {}", url),
+                "",
+            ));
+        }
+        Ok(Some(response.text().await?))
+    } else {
+        let path = Path::new(channel).join(subdir).join("repodata.json");
+        match std::fs::read_to_string(&path) {
+            Ok(content) => Ok(Some(content)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                warn!("No repodata.json in {}", path.display());
+                Ok(None)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+pub(crate) fn local_subdirs(channel: &Path) -> Result<Vec<String>> {
+    let mut subdirs = Vec::new();
+    for entry in std::fs::read_dir(channel)? {
+        let entry = entry?;
+        if entry.path().join("repodata.json").is_file() {
+            subdirs.push(entry.file_name().to_string_lossy().to_string());
+        }
+    }
+    subdirs.sort();
+    Ok(subdirs)
+}
+
+/// Indices of the packages each package depends on, within the channel
+///
+/// A dependency is resolved to the packages of the same subdir or `noarch`
+/// (any subdir for `noarch` packages) whose name and version match its spec.
+fn dependency_graph(packages: &[ChannelPackage]) -> Vec<Vec<usize>> {
+    let mut by_name: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    for (index, package) in packages.iter().enumerate() {
+        by_name.entry(&package.name).or_default().push(index);
+    }
+
+    packages
+        .iter()
+        .map(|package| {
+            let mut dependencies = Vec::new();
+            for depend in &package.depends {
+                let Ok(spec) = MatchSpec::from_str(depend, ParseStrictness::Lenient) else {
+                    continue;
+                };
+                let Some(name) = &spec.name else {
+                    continue;
+                };
+                let candidates = by_name.get(name.as_normalized()).into_iter().flatten();
+                for &candidate in candidates {
+                    let other = &packages[candidate];
+                    let same_subdir = package.subdir == "noarch"
+                        || other.subdir == "noarch"
+                        || other.subdir == package.subdir;
+                    let version_matches = match (&spec.version, Version::from_str(&other.version)) {
+                        (Some(version_spec), Ok(version)) => version_spec.matches(&version),
+                        (Some(_), Err(_)) => false,
+                        (None, _) => true,
+                    };
+                    if same_subdir && version_matches && !dependencies.contains(&candidate) {
+                        dependencies.push(candidate);
+                    }
+                }
+            }
+            dependencies
+        })
+        .collect()
+}
+
+/// Bill of materials describing `packages` of `channel` in the given format
+pub fn generate(
+    format: SbomFormat,
+    channel: &str,
+    packages: &[ChannelPackage],
+    created: DateTime<Utc>,
+) -> Value {
+    match format {
+        SbomFormat::CycloneDx => cyclonedx(packages, created),
+        SbomFormat::Spdx => spdx(channel, packages, created),
+    }
+}
+
+fn cyclonedx(packages: &[ChannelPackage], created: DateTime<Utc>) -> Value {
+    let components: Vec<Value> = packages
+        .iter()
+        .map(|package| {
+            let mut component = json!({
+                "type": "library",
+                "bom-ref": package.purl(),
+                "name": package.name,
+                "version": package.version,
+                "purl": package.purl(),
+                "properties": [
+                    { "name": "conda:build", "value": package.build },
+                    { "name": "conda:build_number", "value": package.build_number.to_string() },
+                    { "name": "conda:subdir", "value": package.subdir },
+                    { "name": "conda:filename", "value": package.filename },
+                ],
+            });
+            let mut hashes = Vec::new();
+            if let Some(sha256) = &package.sha256 {
+                hashes.push(json!({ "alg": "SHA-256", "content": sha256 }));
+            }
+            if let Some(md5) = &package.md5 {
+                hashes.push(json!({ "alg": "MD5", "content": md5 }));
+            }
+            if !hashes.is_empty() {
+                component["hashes"] = Value::from(hashes);
+            }
+            if let Some(license) = package.license() {
+                component["licenses"] = json!([{ "license": { "name": license } }]);
+            }
+            component
+        })
+        .collect();
+
+    let dependencies: Vec<Value> = dependency_graph(packages)
+        .into_iter()
+        .zip(packages)
+        .map(|(dependencies, package)| {
+            let depends_on: Vec<String> = dependencies
+                .into_iter()
+                .map(|index| packages[index].purl())
+                .collect();
+            json!({ "ref": package.purl(), "dependsOn": depends_on })
+        })
+        .collect();
+
+    json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.5",
+        "version": 1,
+        "metadata": {
+            "timestamp": created.to_rfc3339(),
+            "tools": {
+                "components": [{
+                    "type": "application",
+                    "name": "meso-forge-mirror",
+                    "version": env!("CARGO_PKG_VERSION"),
+                }],
+            },
+        },
+        "components": components,
+        "dependencies": dependencies,
+    })
+}
+
+fn spdx(channel: &str, packages: &[ChannelPackage], created: DateTime<Utc>) -> Value {
+    let spdx_id = |index: usize| format!("SPDXRef-Package-{}", index);
+    let remote = channel.starts_with("http://") || channel.starts_with("https://");
+
+    let spdx_packages: Vec<Value> = packages
+        .iter()
+        .enumerate()
+        .map(|(index, package)| {
+            let download_location = if remote {
+                format!(
+                    "{}/{}/{}",
+                    channel.trim_end_matches('/'),
+                    package.subdir,
+                    package.filename
+                )
+            } else {
+                "NOASSERTION".to_string()
+            };
+            let mut checksums = Vec::new();
+            if let Some(sha256) = &package.sha256 {
+                checksums.push(json!({ "algorithm": "SHA256", "checksumValue": sha256 }));
+            }
+            if let Some(md5) = &package.md5 {
+                checksums.push(json!({ "algorithm": "MD5", "checksumValue": md5 }));
+            }
+
+            let mut spdx_package = json!({
+                "SPDXID": spdx_id(index),
+                "name": package.name,
+                "versionInfo": package.version,
+                "packageFileName": package.filename,
+                "downloadLocation": download_location,
+                "filesAnalyzed": false,
+                "licenseConcluded": "NOASSERTION",
+                "licenseDeclared": "NOASSERTION",
+                "copyrightText": "NOASSERTION",
+                "checksums": checksums,
+                "externalRefs": [{
+                    "referenceCategory": "PACKAGE-MANAGER",
+                    "referenceType": "purl",
+                    "referenceLocator": package.purl(),
+                }],
+            });
+            // Conda licenses are free text; only those that read as SPDX expressions are declared
+            match package.license() {
+                Some(license) if is_spdx_expression(license) => {
+                    spdx_package["licenseDeclared"] = Value::from(license);
+                }
+                Some(license) => {
+                    spdx_package["licenseComments"] =
+                        Value::from(format!("License declared by the package: {}", license));
+                }
+                None => {}
+            }
+            spdx_package
+        })
+        .collect();
+
+    let mut relationships: Vec<Value> = (0..packages.len())
+        .map(|index| {
+            json!({
+                "spdxElementId": "SPDXRef-DOCUMENT",
+                "relationshipType": "DESCRIBES",
+                "relatedSpdxElement": spdx_id(index),
+            })
+        })
+        .collect();
+    for (index, dependencies) in dependency_graph(packages).into_iter().enumerate() {
+        relationships.extend(dependencies.into_iter().map(|dependency| {
+            json!({
+                "spdxElementId": spdx_id(index),
+                "relationshipType": "DEPENDS_ON",
+                "relatedSpdxElement": spdx_id(dependency),
+            })
+        }));
+    }
+
+    let namespace_digest = Sha256::digest(format!("{}@{}", channel, created.to_rfc3339()));
+    json!({
+        "spdxVersion": "SPDX-2.3",
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": format!("conda channel {}", channel),
+        "documentNamespace": format!(
+            "https://github.com/babeloff/meso-forge-mirror/spdx/{:x}",
+            namespace_digest
+        ),
+        "creationInfo": {
+            "created": created.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+            "creators": [format!("Tool: meso-forge-mirror-{}", env!("CARGO_PKG_VERSION"))],
+        },
+        "packages": spdx_packages,
+        "relationships": relationships,
+    })
+}
+
+/// Whether a license reads as an SPDX license expression, e.g. `MIT OR Apache-2.0`
+///
+/// License identifiers must alternate with `AND`, `OR` or `WITH`, so free text
+/// such as `BSD 3-Clause` is not mistaken for an expression.
+fn is_spdx_expression(license: &str) -> bool {
+    let mut expect_identifier = true;
+    for token in license
+        .split_whitespace()
+        .flat_map(|token| token.split(['(', ')']))
+        .filter(|token| !token.is_empty())
+    {
+        let is_operator = matches!(token, "AND" | "OR" | "WITH");
+        let is_identifier = !is_operator
+            && token
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '+'));
+        if (expect_identifier && !is_identifier) || (!expect_identifier && !is_operator) {
+            return false;
+        }
+        expect_identifier = !expect_identifier;
+    }
+    !expect_identifier
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conda_package::CondaPackageHandler;
+    use crate::test_support::PackageFixture;
+
+    async fn channel() -> tempfile::TempDir {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let fixtures = [
+            PackageFixture::new("app", "2.0")
+                .subdir("linux-64")
+                .depends(["lib >=1.5", "__glibc >=2.17"])
+                .license("MIT"),
+            PackageFixture::new("lib", "1.0").subdir("linux-64"),
+            PackageFixture::new("lib", "1.6")
+                .subdir("linux-64")
+                .license("BSD 3-Clause"),
+        ];
+        let mut handler = CondaPackageHandler::new();
+        let mut packages = Vec::new();
+        for fixture in &fixtures {
+            packages.push(
+                handler
+                    .process_package(fixture.to_conda(), &fixture.conda_filename())
+                    .await
+                    .unwrap(),
+            );
+        }
+        handler
+            .create_repodata(&Platform::Linux64, &packages, temp_dir.path())
+            .await
+            .unwrap();
+        temp_dir
+    }
+
+    #[tokio::test]
+    async fn test_cyclonedx() {
+        let channel = channel().await;
+        let path = channel.path().to_string_lossy().to_string();
+        let packages = load_channel(&Client::new(), &path, &[]).await.unwrap();
+        assert_eq!(packages.len(), 3);
+
+        let bom = generate(SbomFormat::CycloneDx, &path, &packages, Utc::now());
+        assert_eq!(bom["bomFormat"], "CycloneDX");
+        let app = &bom["components"][0];
+        assert_eq!(
+            app["purl"],
+            "pkg:conda/app@2.0?build=0&subdir=linux-64&type=conda"
+        );
+        assert_eq!(app["licenses"][0]["license"]["name"], "MIT");
+        assert_eq!(
+            app["hashes"][0]["content"],
+            packages[0].sha256.clone().unwrap()
+        );
+
+        // Only the version of lib matching ">=1.5" is a dependency; __glibc is outside the channel
+        let app_dependencies = &bom["dependencies"][0];
+        assert_eq!(app_dependencies["ref"], app["purl"]);
+        assert_eq!(
+            app_dependencies["dependsOn"],
+            json!(["pkg:conda/lib@1.6?build=0&subdir=linux-64&type=conda"])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_spdx() {
+        let channel = channel().await;
+        let path = channel.path().to_string_lossy().to_string();
+        let packages = load_channel(&Client::new(), &path, &["linux-64".to_string()])
+            .await
+            .unwrap();
+
+        let document = generate(SbomFormat::Spdx, &path, &packages, Utc::now());
+        assert_eq!(document["spdxVersion"], "SPDX-2.3");
+        assert_eq!(document["packages"][0]["licenseDeclared"], "MIT");
+        assert_eq!(document["packages"][2]["licenseDeclared"], "NOASSERTION");
+        assert!(document["packages"][2]["licenseComments"]
+            .as_str()
+            .unwrap()
+            .contains("BSD 3-Clause"));
+
+        let depends_on: Vec<_> = document["relationships"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|r| r["relationshipType"] == "DEPENDS_ON")
+            .collect();
+        assert_eq!(depends_on.len(), 1);
+        assert_eq!(depends_on[0]["spdxElementId"], "SPDXRef-Package-0");
+        assert_eq!(depends_on[0]["relatedSpdxElement"], "SPDXRef-Package-2");
+    }
+
+    #[test]
+    fn test_is_spdx_expression() {
+        assert!(is_spdx_expression("MIT"));
+        assert!(is_spdx_expression("(MIT OR Apache-2.0) AND BSD-3-Clause"));
+        assert!(is_spdx_expression(
+            "GPL-2.0-or-later WITH Classpath-exception-2.0"
+        ));
+        assert!(!is_spdx_expression("BSD 3-Clause"));
+        assert!(!is_spdx_expression("Proprietary, see LICENSE.txt"));
+    }
+
+    #[tokio::test]
+    async fn test_load_remote_channel_skips_missing_subdirs() {
+        use crate::test_util::{MockResponse, MockServer};
+
+        let server = MockServer::start().await.unwrap();
+        server.mock(
+            "GET",
+            "/channel/noarch/repodata.json",
+            MockResponse::json(
+                200,
+                r#"{"packages.conda": {"tool-1.0-0.conda": {"name": "tool", "version": "1.0", "build": "0", "subdir": "noarch"}}}"#,
+            ),
+        );
+
+        let channel = format!("{}/channel", server.url());
+        let packages = load_channel(&Client::new(), &channel, &[]).await.unwrap();
+        assert_eq!(packages.len(), 1);
+        assert_eq!(packages[0].filename, "tool-1.0-0.conda");
+
+        let document = generate(SbomFormat::Spdx, &channel, &packages, Utc::now());
+        assert_eq!(
+            document["packages"][0]["downloadLocation"],
+            format!("{}/noarch/tool-1.0-0.conda", channel)
+        );
+    }
+}
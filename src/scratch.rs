@@ -0,0 +1,98 @@
+//! Scratch space and free disk space
+//!
+//! Downloads are kept in memory, but `verify_extraction` unpacks every package
+//! to a temporary directory, and local and cache targets write it to disk.
+//! Temporary directories are made under `scratch_dir`, or the system temporary
+//! directory when it is unset. Before a package is fetched, each file system
+//! it will be written to must have room for it plus `min_free_space`, so a
+//! full disk stops the run with a clear message instead of an ENOSPC halfway
+//! through an extraction.
+
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+use tracing::debug;
+
+use crate::error::{MirrorError, Result};
+
+/// Room assumed for the extracted files of a package, as a multiple of its size
+pub const EXTRACTION_FACTOR: u64 = 4;
+
+/// The directory temporary files are made in
+pub fn scratch_path(scratch_dir: Option<&Path>) -> PathBuf {
+    scratch_dir.map_or_else(std::env::temp_dir, Path::to_path_buf)
+}
+
+/// A temporary directory under the scratch directory, removed when dropped
+pub fn tempdir(scratch_dir: Option<&Path>) -> std::io::Result<TempDir> {
+    let dir = scratch_path(scratch_dir);
+    std::fs::create_dir_all(&dir)?;
+    tempfile::Builder::new()
+        .prefix("meso-forge-mirror-")
+        .tempdir_in(dir)
+}
+
+/// Free bytes on the file system of `path`, or of its nearest existing ancestor
+pub fn available_space(path: &Path) -> std::io::Result<u64> {
+    let mut existing = path;
+    while !existing.exists() {
+        existing = match existing.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+    }
+    fs4::available_space(existing)
+}
+
+/// Fail unless the file system of `path` has `needed` bytes free
+///
+/// When the free space cannot be told, as on some network file systems, the
+/// check passes and the write itself reports a full disk.
+pub fn ensure_space(path: &Path, needed: u64, purpose: &str) -> Result<()> {
+    let available = match available_space(path) {
+        Ok(available) => available,
+        Err(e) => {
+            debug!("Cannot tell the free space at {}: {}", path.display(), e);
+            return Ok(());
+        }
+    };
+    if available < needed {
+        return Err(MirrorError::InsufficientSpace {
+            path: path.to_path_buf(),
+            purpose: purpose.to_string(),
+            needed,
+            available,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tempdir_is_made_in_the_scratch_dir() {
+        let scratch = TempDir::new().unwrap();
+        let nested = scratch.path().join("mirror/tmp");
+        let dir = tempdir(Some(&nested)).unwrap();
+        assert!(dir.path().starts_with(&nested));
+        assert!(dir.path().is_dir());
+    }
+
+    #[test]
+    fn test_ensure_space() {
+        let scratch = TempDir::new().unwrap();
+        let missing = scratch.path().join("not/yet/created");
+        let available = available_space(&missing).unwrap();
+        assert!(available > 0);
+        assert!(ensure_space(&missing, 1, "extracting packages").is_ok());
+
+        let err = ensure_space(&missing, u64::MAX, "extracting packages").unwrap_err();
+        assert!(matches!(
+            err,
+            MirrorError::InsufficientSpace { needed: u64::MAX, ref purpose, .. }
+                if purpose == "extracting packages"
+        ));
+        assert!(err.to_string().contains("Not enough disk space"));
+    }
+}
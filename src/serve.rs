@@ -0,0 +1,492 @@
+//! HTTP server exposing a local channel to conda clients
+//!
+//! The `serve` command answers plain HTTP for the files of a local target, so
+//! pixi, conda or mamba can install from the mirror directly, e.g. with
+//! `pixi add --channel http://mirror.example.org:8080 numpy`:
+//!
+//! - Subdirs, packages, `repodata.json` and its compressed variants are served
+//!   as they are on disk; directories answer with a plain listing
+//! - A platform subdir the channel lacks answers `repodata.json` with empty
+//!   repodata, so clients asking for every subdir of their platform succeed
+//! - `Range` requests for a single range are answered with partial content,
+//!   letting clients resume large packages
+//! - Hidden files, e.g. the `.partial` files of uploads in progress, are never
+//!   served
+//!
+//! With credentials configured, every request must carry them as HTTP basic
+//! authentication.
+
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use rattler_conda_types::Platform;
+use sha2::{Digest, Sha256};
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, info};
+
+use crate::error::{MirrorError, Result};
+use crate::health::read_request_head;
+use crate::shutdown;
+
+/// Realm announced to clients that did not send credentials
+const REALM: &str = "meso-forge-mirror";
+
+/// A local channel and the credentials clients must present
+#[derive(Debug, Clone)]
+pub struct ChannelServer {
+    root: PathBuf,
+    /// sha256 of the expected `Authorization` header value, when authentication is required
+    ///
+    /// Comparing digests keeps the time a comparison takes unrelated to how
+    /// much of the credentials a client guessed right.
+    authorization: Option<[u8; 32]>,
+}
+
+/// Part of a file a `Range` header asks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ByteRange {
+    Full,
+    Partial { start: u64, end: u64 },
+    Unsatisfiable,
+}
+
+enum Body {
+    Text(String),
+    File {
+        file: tokio::fs::File,
+        start: u64,
+        len: u64,
+    },
+}
+
+struct Response {
+    code: u16,
+    headers: Vec<(&'static str, String)>,
+    body: Body,
+}
+
+impl Response {
+    fn text(code: u16, text: &str) -> Self {
+        Self {
+            code,
+            headers: vec![("Content-Type", "text/plain; charset=utf-8".to_string())],
+            body: Body::Text(format!("{}\n", text)),
+        }
+    }
+
+    fn with_header(mut self, name: &'static str, value: String) -> Self {
+        self.headers.push((name, value));
+        self
+    }
+
+    fn len(&self) -> u64 {
+        match &self.body {
+            Body::Text(text) => text.len() as u64,
+            Body::File { len, .. } => *len,
+        }
+    }
+}
+
+impl ChannelServer {
+    /// Serve the channel in `root`, requiring `credentials` (user name and password) when given
+    pub fn new(root: impl Into<PathBuf>, credentials: Option<(&str, &str)>) -> Result<Self> {
+        let root = root.into();
+        if !root.is_dir() {
+            return Err(MirrorError::NotFound(format!(
+                "Channel directory {}",
+                root.display()
+            )));
+        }
+        let authorization = credentials.map(|(username, password)| {
+            let expected = format!(
+                "Basic {}",
+                base64::engine::general_purpose::STANDARD
+                    .encode(format!("{}:{}", username, password))
+            );
+            Sha256::digest(expected).into()
+        });
+        Ok(Self {
+            root,
+            authorization,
+        })
+    }
+
+    /// The file a request path refers to; `None` for hidden files and paths leaving the channel
+    fn resolve(&self, path: &str) -> Option<PathBuf> {
+        let mut resolved = self.root.clone();
+        for segment in path.split('/').filter(|segment| !segment.is_empty()) {
+            if segment.starts_with('.') || segment.contains('\\') {
+                return None;
+            }
+            resolved.push(segment);
+        }
+        Some(resolved)
+    }
+
+    async fn respond(&self, method: &str, target: &str, head: &str) -> Response {
+        if method != "GET" && method != "HEAD" {
+            return Response::text(405, "method not allowed");
+        }
+        if let Some(expected) = &self.authorization {
+            let presented = header(head, "authorization").map(Sha256::digest);
+            if presented.is_none_or(|presented| presented.as_slice() != expected) {
+                return Response::text(401, "authentication required")
+                    .with_header("WWW-Authenticate", format!("Basic realm=\"{}\"", REALM));
+            }
+        }
+
+        let path = target.split(['?', '#']).next().unwrap_or_default();
+        let Some(file) = self.resolve(path) else {
+            return Response::text(404, "not found");
+        };
+        if file.is_dir() {
+            return match listing(&file) {
+                Ok(listing) => Response::text(200, &listing),
+                Err(_) => Response::text(404, "not found"),
+            };
+        }
+        if !file.is_file() {
+            return match empty_repodata(path) {
+                Some(repodata) => Response {
+                    code: 200,
+                    headers: vec![("Content-Type", "application/json".to_string())],
+                    body: Body::Text(repodata),
+                },
+                None => Response::text(404, "not found"),
+            };
+        }
+
+        match self.file_response(&file, header(head, "range")).await {
+            Ok(response) => response,
+            Err(e) => {
+                debug!("Failed to open {}: {}", file.display(), e);
+                Response::text(404, "not found")
+            }
+        }
+    }
+
+    async fn file_response(&self, path: &Path, range: Option<&str>) -> std::io::Result<Response> {
+        let file = tokio::fs::File::open(path).await?;
+        let metadata = file.metadata().await?;
+        let size = metadata.len();
+        let mut headers = vec![
+            ("Content-Type", content_type(path).to_string()),
+            ("Accept-Ranges", "bytes".to_string()),
+        ];
+        if let Ok(modified) = metadata.modified() {
+            headers.push((
+                "Last-Modified",
+                DateTime::<Utc>::from(modified)
+                    .format("%a, %d %b %Y %H:%M:%S GMT")
+                    .to_string(),
+            ));
+        }
+
+        let (code, start, len) = match byte_range(range, size) {
+            ByteRange::Full => (200, 0, size),
+            ByteRange::Partial { start, end } => {
+                headers.push(("Content-Range", format!("bytes {}-{}/{}", start, end, size)));
+                (206, start, end - start + 1)
+            }
+            ByteRange::Unsatisfiable => {
+                return Ok(Response::text(416, "range not satisfiable")
+                    .with_header("Content-Range", format!("bytes */{}", size)));
+            }
+        };
+        Ok(Response {
+            code,
+            headers,
+            body: Body::File { file, start, len },
+        })
+    }
+}
+
+/// Value of a request header, matched case-insensitively
+fn header<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+    head.lines().skip(1).find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        key.trim()
+            .eq_ignore_ascii_case(name)
+            .then_some(value.trim())
+    })
+}
+
+/// Interpret a `Range` header for a file of `size` bytes
+///
+/// Only single ranges are honored; anything else is answered with the whole
+/// file, as HTTP allows.
+fn byte_range(range: Option<&str>, size: u64) -> ByteRange {
+    let Some(spec) = range.and_then(|range| range.trim().strip_prefix("bytes=")) else {
+        return ByteRange::Full;
+    };
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return ByteRange::Full;
+    };
+    if spec.contains(',') {
+        return ByteRange::Full;
+    }
+    match (start.trim(), end.trim()) {
+        ("", suffix) => match suffix.parse::<u64>() {
+            Ok(0) => ByteRange::Unsatisfiable,
+            Ok(_) if size == 0 => ByteRange::Unsatisfiable,
+            Ok(suffix) => ByteRange::Partial {
+                start: size.saturating_sub(suffix),
+                end: size - 1,
+            },
+            Err(_) => ByteRange::Full,
+        },
+        (start, end) => {
+            let Ok(start) = start.parse::<u64>() else {
+                return ByteRange::Full;
+            };
+            let end = match end {
+                "" => u64::MAX,
+                end => match end.parse::<u64>() {
+                    Ok(end) if end >= start => end,
+                    _ => return ByteRange::Full,
+                },
+            };
+            if start >= size {
+                ByteRange::Unsatisfiable
+            } else {
+                ByteRange::Partial {
+                    start,
+                    end: end.min(size - 1),
+                }
+            }
+        }
+    }
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("json") => "application/json",
+        Some("html") => "text/html; charset=utf-8",
+        Some("txt") => "text/plain; charset=utf-8",
+        _ => "application/octet-stream",
+    }
+}
+
+/// The entries of a directory, one per line, subdirectories with a trailing `/`
+fn listing(dir: &Path) -> std::io::Result<String> {
+    let mut entries = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            entries.push(format!("{}/", name));
+        } else {
+            entries.push(name);
+        }
+    }
+    entries.sort();
+    Ok(entries.join("\n"))
+}
+
+/// Empty repodata for `<platform>/repodata.json` of a subdir the channel lacks
+fn empty_repodata(path: &str) -> Option<String> {
+    let (subdir, filename) = path.trim_matches('/').split_once('/')?;
+    if filename != "repodata.json" {
+        return None;
+    }
+    let platform = Platform::from_str(subdir).ok()?;
+    Some(
+        serde_json::json!({
+            "info": { "subdir": platform.as_str() },
+            "packages": {},
+            "packages.conda": {},
+            "repodata_version": 1,
+        })
+        .to_string(),
+    )
+}
+
+/// Bind the address to serve on, e.g. `0.0.0.0:8080`
+pub async fn bind(address: &str) -> Result<TcpListener> {
+    Ok(TcpListener::bind(address).await?)
+}
+
+/// Serve the channel on `listener` until a shutdown is requested
+pub async fn serve(listener: TcpListener, server: ChannelServer) {
+    if let Ok(address) = listener.local_addr() {
+        info!(
+            "Serving {} as a conda channel on http://{}",
+            server.root.display(),
+            address
+        );
+    }
+    let server = Arc::new(server);
+    loop {
+        let stream = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    debug!("Failed to accept connection: {}", e);
+                    continue;
+                }
+            },
+            _ = shutdown::requested() => return,
+        };
+        let server = Arc::clone(&server);
+        tokio::spawn(async move {
+            if let Err(e) = handle(stream, &server).await {
+                debug!("Connection failed: {}", e);
+            }
+        });
+    }
+}
+
+async fn handle(mut stream: TcpStream, server: &ChannelServer) -> std::io::Result<()> {
+    let Some(head) = read_request_head(&mut stream).await? else {
+        return Ok(());
+    };
+    let mut request_line = head.lines().next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default();
+    let target = request_line.next().unwrap_or_default();
+    let response = server.respond(method, target, &head).await;
+    debug!("{} {} {}", method, target, response.code);
+
+    let reason = match response.code {
+        200 => "OK",
+        206 => "Partial Content",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Range Not Satisfiable",
+    };
+    let mut head = format!("HTTP/1.1 {} {}\r\n", response.code, reason);
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str(&format!(
+        "Content-Length: {}\r\nConnection: close\r\n\r\n",
+        response.len()
+    ));
+    stream.write_all(head.as_bytes()).await?;
+
+    if method != "HEAD" {
+        match response.body {
+            Body::Text(text) => stream.write_all(text.as_bytes()).await?,
+            Body::File {
+                mut file,
+                start,
+                len,
+            } => {
+                file.seek(SeekFrom::Start(start)).await?;
+                tokio::io::copy(&mut file.take(len), &mut stream).await?;
+            }
+        }
+    }
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_byte_range() {
+        assert_eq!(byte_range(None, 100), ByteRange::Full);
+        assert_eq!(
+            byte_range(Some("bytes=10-19"), 100),
+            ByteRange::Partial { start: 10, end: 19 }
+        );
+        assert_eq!(
+            byte_range(Some("bytes=90-"), 100),
+            ByteRange::Partial { start: 90, end: 99 }
+        );
+        assert_eq!(
+            byte_range(Some("bytes=90-200"), 100),
+            ByteRange::Partial { start: 90, end: 99 }
+        );
+        assert_eq!(
+            byte_range(Some("bytes=-10"), 100),
+            ByteRange::Partial { start: 90, end: 99 }
+        );
+        assert_eq!(
+            byte_range(Some("bytes=100-"), 100),
+            ByteRange::Unsatisfiable
+        );
+        assert_eq!(byte_range(Some("bytes=-0"), 100), ByteRange::Unsatisfiable);
+        assert_eq!(byte_range(Some("bytes=0-1,5-6"), 100), ByteRange::Full);
+        assert_eq!(byte_range(Some("bytes=20-10"), 100), ByteRange::Full);
+        assert_eq!(byte_range(Some("items=0-1"), 100), ByteRange::Full);
+    }
+
+    #[tokio::test]
+    async fn test_serve_channel() {
+        let temp_dir = TempDir::new().unwrap();
+        let channel = temp_dir.path().join("channel");
+        let noarch = channel.join("noarch");
+        std::fs::create_dir_all(&noarch).unwrap();
+        std::fs::write(noarch.join("repodata.json"), r#"{"packages": {}}"#).unwrap();
+        std::fs::write(noarch.join("pkg-1.0-0.conda"), b"0123456789").unwrap();
+        std::fs::write(noarch.join(".pkg-2.0-0.conda.partial"), b"partial").unwrap();
+        std::fs::write(temp_dir.path().join("secret"), b"secret").unwrap();
+
+        let listener = bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = ChannelServer::new(&channel, Some(("mirror", "secret"))).unwrap();
+        assert_eq!(server.resolve("/noarch/../secret"), None);
+        assert_eq!(
+            server.resolve("/noarch/repodata.json"),
+            Some(noarch.join("repodata.json"))
+        );
+        let task = tokio::spawn(serve(listener, server));
+        let url = |path: &str| format!("http://{}{}", address, path);
+        let client = reqwest::Client::new();
+
+        let response = client
+            .get(url("/noarch/repodata.json"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 401);
+        assert!(response.headers().contains_key("www-authenticate"));
+
+        let get = |path: &str| client.get(url(path)).basic_auth("mirror", Some("secret"));
+        let response = get("/noarch/repodata.json").send().await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["content-type"], "application/json");
+        assert_eq!(response.text().await.unwrap(), r#"{"packages": {}}"#);
+
+        let response = get("/noarch/pkg-1.0-0.conda")
+            .header("Range", "bytes=4-")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 206);
+        assert_eq!(response.headers()["content-range"], "bytes 4-9/10");
+        assert_eq!(response.text().await.unwrap(), "456789");
+
+        // Subdirs the channel lacks have empty repodata
+        let repodata: serde_json::Value = get("/linux-64/repodata.json")
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(repodata["info"]["subdir"], "linux-64");
+
+        let listing = get("/noarch/").send().await.unwrap().text().await.unwrap();
+        assert_eq!(listing, "pkg-1.0-0.conda\nrepodata.json\n");
+
+        for path in [
+            "/noarch/.pkg-2.0-0.conda.partial",
+            "/noarch/missing-1.0-0.conda",
+        ] {
+            assert_eq!(get(path).send().await.unwrap().status(), 404, "{}", path);
+        }
+
+        task.abort();
+    }
+}
@@ -0,0 +1,187 @@
+//! Sharded repodata (CEP-16)
+//!
+//! The `repodata.json` of a large channel runs to hundreds of megabytes, all of
+//! which a client downloads and parses before solving. With sharded repodata,
+//! each subdir also gets a small `repodata_shards.msgpack.zst` index mapping
+//! every package name to the sha256 of a shard under `shards/`, which holds
+//! the records of that name alone; rattler-based clients such as pixi fetch
+//! only the shards of the packages they resolve. Shards are named by the hash
+//! of their content, so a run only writes the shards whose packages changed.
+//!
+//! A client may have fetched the index a run replaces moments before, so the
+//! replaced index is kept as `repodata_shards.previous.msgpack.zst` and the
+//! shards it lists stay until the next run replaces it in turn.
+
+use chrono::Utc;
+use rattler_conda_types::{RepoData, Shard, ShardedRepodata, ShardedSubdirInfo};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::error::{MirrorError, Result};
+use crate::suggest;
+
+/// Index of the shards of a subdir, next to its `repodata.json`
+pub const SHARDS_INDEX: &str = "repodata_shards.msgpack.zst";
+
+/// The index a run replaced, whose shards are kept until the next run
+pub const PREVIOUS_SHARDS_INDEX: &str = "repodata_shards.previous.msgpack.zst";
+
+/// zstd level of the index and the shards
+const COMPRESSION_LEVEL: i32 = 19;
+
+/// The sharded form of the repodata of one subdir
+#[derive(Debug, Clone)]
+pub struct ShardedSubdir {
+    /// The compressed index
+    pub index: Vec<u8>,
+    /// The compressed shards, by the hex sha256 that names them
+    pub shards: BTreeMap<String, Vec<u8>>,
+}
+
+/// Path of a shard relative to the channel root
+pub fn shard_path(subdir: &str, hash: &str) -> String {
+    format!("{}/shards/{}.msgpack.zst", subdir, hash)
+}
+
+/// Path of the shard index of a subdir relative to the channel root
+pub fn index_path(subdir: &str) -> String {
+    format!("{}/{}", subdir, SHARDS_INDEX)
+}
+
+/// Path of the index a run replaced, relative to the channel root
+pub fn previous_index_path(subdir: &str) -> String {
+    format!("{}/{}", subdir, PREVIOUS_SHARDS_INDEX)
+}
+
+/// Split the `repodata.json` of `subdir` into an index and one shard per package name
+pub fn shard(subdir: &str, repodata: &[u8]) -> Result<ShardedSubdir> {
+    let repodata: RepoData = serde_json::from_slice(repodata).map_err(|e| {
+        MirrorError::InvalidResponse(format!("Invalid repodata.json for {}: {}", subdir, e))
+    })?;
+
+    let mut by_name: BTreeMap<String, Shard> = BTreeMap::new();
+    for (filename, record) in repodata.packages {
+        let name = record.name.as_normalized().to_string();
+        by_name
+            .entry(name)
+            .or_default()
+            .packages
+            .insert(filename, record);
+    }
+    for (filename, record) in repodata.conda_packages {
+        let name = record.name.as_normalized().to_string();
+        by_name
+            .entry(name)
+            .or_default()
+            .conda_packages
+            .insert(filename, record);
+    }
+    for filename in repodata.removed {
+        let name = suggest::package_name(&filename).to_lowercase();
+        by_name.entry(name).or_default().removed.insert(filename);
+    }
+
+    let mut index = ShardedRepodata {
+        info: ShardedSubdirInfo {
+            subdir: subdir.to_string(),
+            base_url: "./".to_string(),
+            shards_base_url: "./shards/".to_string(),
+            created_at: Some(Utc::now()),
+        },
+        shards: Default::default(),
+    };
+    let mut shards = BTreeMap::new();
+    for (name, shard) in by_name {
+        let content = compress(&encode(&shard)?)?;
+        let digest = Sha256::digest(&content);
+        index.shards.insert(name, digest);
+        shards.insert(format!("{:x}", digest), content);
+    }
+
+    Ok(ShardedSubdir {
+        index: compress(&encode(&index)?)?,
+        shards,
+    })
+}
+
+/// Hex sha256 of the shards a compressed index refers to
+pub fn listed_shards(index: &[u8]) -> Result<BTreeSet<String>> {
+    let index: ShardedRepodata = rmp_serde::from_slice(&zstd::stream::decode_all(index)?)
+        .map_err(|e| MirrorError::InvalidResponse(format!("Invalid shard index: {}", e)))?;
+    Ok(index
+        .shards
+        .values()
+        .map(|digest| format!("{:x}", digest))
+        .collect())
+}
+
+/// MessagePack with field names, as CEP-16 readers expect
+fn encode<T: serde::Serialize>(value: &T) -> Result<Vec<u8>> {
+    rmp_serde::to_vec_named(value).map_err(|e| MirrorError::Other(e.into()))
+}
+
+fn compress(content: &[u8]) -> Result<Vec<u8>> {
+    Ok(zstd::stream::encode_all(content, COMPRESSION_LEVEL)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shard_repodata() {
+        let repodata = serde_json::json!({
+            "info": { "subdir": "linux-64" },
+            "packages": {
+                "numpy-1.26.4-py312_0.tar.bz2": {
+                    "name": "numpy", "version": "1.26.4", "build": "py312_0",
+                    "build_number": 0, "subdir": "linux-64", "depends": [],
+                    "sha256": "a".repeat(64),
+                }
+            },
+            "packages.conda": {
+                "numpy-1.26.4-py312_0.conda": {
+                    "name": "numpy", "version": "1.26.4", "build": "py312_0",
+                    "build_number": 0, "subdir": "linux-64", "depends": ["python >=3.12"],
+                    "sha256": "b".repeat(64),
+                },
+                "scipy-1.13.0-py312_0.conda": {
+                    "name": "scipy", "version": "1.13.0", "build": "py312_0",
+                    "build_number": 0, "subdir": "linux-64", "depends": [],
+                }
+            },
+            "repodata_version": 1
+        });
+        let sharded = shard("linux-64", &serde_json::to_vec(&repodata).unwrap()).unwrap();
+        assert_eq!(sharded.shards.len(), 2);
+
+        let index: ShardedRepodata =
+            rmp_serde::from_slice(&zstd::stream::decode_all(&sharded.index[..]).unwrap()).unwrap();
+        assert_eq!(index.info.subdir, "linux-64");
+        assert_eq!(index.info.shards_base_url, "./shards/");
+        let numpy = format!("{:x}", index.shards["numpy"]);
+        assert_eq!(
+            listed_shards(&sharded.index).unwrap(),
+            sharded.shards.keys().cloned().collect()
+        );
+
+        // Shards are named by the sha256 of their compressed content
+        let content = &sharded.shards[&numpy];
+        assert_eq!(format!("{:x}", Sha256::digest(content)), numpy);
+        let shard: Shard =
+            rmp_serde::from_slice(&zstd::stream::decode_all(&content[..]).unwrap()).unwrap();
+        assert_eq!(shard.packages.len(), 1);
+        let record = &shard.conda_packages["numpy-1.26.4-py312_0.conda"];
+        assert_eq!(record.depends, vec!["python >=3.12".to_string()]);
+        assert_eq!(
+            record.sha256.map(|digest| format!("{:x}", digest)),
+            Some("b".repeat(64))
+        );
+
+        assert_eq!(
+            shard_path("linux-64", &numpy),
+            format!("linux-64/shards/{}.msgpack.zst", numpy)
+        );
+        assert_eq!(index_path("noarch"), "noarch/repodata_shards.msgpack.zst");
+    }
+}
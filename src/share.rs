@@ -0,0 +1,356 @@
+//! Channels built from the packages of a local rattler cache
+//!
+//! pixi and other rattler tools keep the packages they install in the package
+//! cache, `~/.cache/rattler/cache/pkgs` by default. The `share` command turns
+//! what is there into a standalone channel: package archives found in the
+//! cache are copied or hard-linked into their subdir, unpacked packages are
+//! packed again as `.conda` archives, and `repodata.json` is written for every
+//! subdir. Whatever a developer has locally can then be handed to teammates as
+//! a directory or served over HTTP.
+
+use bytes::Bytes;
+use rattler_conda_types::compression_level::CompressionLevel;
+use rattler_conda_types::Platform;
+use rattler_package_streaming::write::write_conda_package;
+use regex::Regex;
+use std::collections::HashSet;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use tracing::{info, warn};
+
+use crate::conda_package::CondaPackageHandler;
+use crate::error::{MirrorError, Result};
+use crate::report::{MirrorReport, PackageOutcome};
+use crate::suggest;
+
+/// Where a cached package is read from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CachedSource {
+    /// A package archive, e.g. written by a `cache` target
+    Archive(PathBuf),
+    /// A package unpacked by rattler into `<name>-<version>-<build>`
+    Unpacked(PathBuf),
+}
+
+/// A package found in a package cache
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedPackage {
+    /// Filename the package gets in the channel
+    pub filename: String,
+    pub source: CachedSource,
+}
+
+/// Packages in a package cache directory, sorted by filename
+///
+/// A package cached both as an archive and unpacked is listed once, from its
+/// archive, so the channel serves the original bytes. Directories without an
+/// `info/index.json`, such as partially unpacked packages, are ignored.
+pub fn cached_packages(cache_dir: &Path) -> Result<Vec<CachedPackage>> {
+    let entries = std::fs::read_dir(cache_dir).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => {
+            MirrorError::NotFound(format!("Package cache directory {}", cache_dir.display()))
+        }
+        _ => MirrorError::Io(e),
+    })?;
+    let mut archives = Vec::new();
+    let mut unpacked = Vec::new();
+    for entry in entries {
+        let path = entry
+            .map_err(|e| MirrorError::target_io(cache_dir, e))?
+            .path();
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        if path.is_dir() {
+            match unpacked_filename(&path) {
+                Some(filename) => unpacked.push(CachedPackage {
+                    filename,
+                    source: CachedSource::Unpacked(path),
+                }),
+                None => warn!("Skipping {}: no readable info/index.json", path.display()),
+            }
+        } else if CondaPackageHandler::is_conda_package(name) && !name.starts_with('.') {
+            archives.push(CachedPackage {
+                filename: name.to_string(),
+                source: CachedSource::Archive(path.clone()),
+            });
+        }
+    }
+
+    let archived: HashSet<String> = archives.iter().map(|p| stem(&p.filename)).collect();
+    let mut packages = archives;
+    packages.extend(
+        unpacked
+            .into_iter()
+            .filter(|package| !archived.contains(&stem(&package.filename))),
+    );
+    packages.sort_by(|a, b| a.filename.cmp(&b.filename));
+    Ok(packages)
+}
+
+/// `name-version-build` of a package filename
+fn stem(filename: &str) -> String {
+    filename
+        .strip_suffix(".conda")
+        .or_else(|| filename.strip_suffix(".tar.bz2"))
+        .unwrap_or(filename)
+        .to_string()
+}
+
+/// `.conda` filename of an unpacked package, from its `info/index.json`
+fn unpacked_filename(dir: &Path) -> Option<String> {
+    let index: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(dir.join("info/index.json")).ok()?).ok()?;
+    Some(format!(
+        "{}-{}-{}.conda",
+        index["name"].as_str()?,
+        index["version"].as_str()?,
+        index["build"].as_str()?
+    ))
+}
+
+/// Every file below `dir`; symlinks are kept as they are
+fn package_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            package_files(&entry.path(), files)?;
+        } else {
+            files.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+/// Pack an unpacked package into a `.conda` archive again
+async fn repack(dir: &Path, filename: &str) -> Result<Bytes> {
+    let dir = dir.to_path_buf();
+    let out_name = stem(filename);
+    tokio::task::spawn_blocking(move || -> Result<Bytes> {
+        let mut files = Vec::new();
+        package_files(&dir, &mut files)?;
+        files.sort();
+        let mut archive = Cursor::new(Vec::new());
+        write_conda_package(
+            &mut archive,
+            &dir,
+            &files,
+            CompressionLevel::Default,
+            None,
+            &out_name,
+            None,
+            None,
+        )?;
+        Ok(Bytes::from(archive.into_inner()))
+    })
+    .await
+    .map_err(|e| MirrorError::Other(e.into()))?
+}
+
+/// Write a channel with the packages of `cache_dir` to `target`
+///
+/// `package` restricts the channel to packages whose name matches it. With
+/// `hardlink`, archives are linked instead of copied where the filesystem
+/// allows it. The repodata of `target` is replaced by one listing exactly
+/// the shared packages.
+pub async fn share_cache(
+    cache_dir: &Path,
+    target: &Path,
+    package: Option<&Regex>,
+    hardlink: bool,
+) -> Result<MirrorReport> {
+    let started = Instant::now();
+    let mut report = MirrorReport::new(
+        cache_dir.to_string_lossy(),
+        target.to_string_lossy().to_string(),
+    );
+    let mut handler = CondaPackageHandler::new();
+
+    for cached in cached_packages(cache_dir)? {
+        let name = suggest::package_name(&cached.filename);
+        if package.is_some_and(|package| !package.is_match(name)) {
+            continue;
+        }
+
+        let package_started = Instant::now();
+        match share_package(&mut handler, &cached, target, hardlink).await {
+            Ok(processed) => {
+                let package = report.record(
+                    &cached.filename,
+                    PackageOutcome::Mirrored,
+                    processed.size,
+                    package_started.elapsed(),
+                );
+                package.platform = Some(processed.platform.to_string());
+                package.sha256 = Some(processed.sha256);
+                package.license = processed.metadata.license;
+            }
+            Err(e) => {
+                warn!("Skipping cached package {}: {}", cached.filename, e);
+                handler.remove_package(&cached.filename);
+                report.record(
+                    &cached.filename,
+                    PackageOutcome::Failed {
+                        error: e.to_string(),
+                    },
+                    0,
+                    package_started.elapsed(),
+                );
+            }
+        }
+    }
+
+    if report.mirrored_count() > 0 {
+        let mut organized = handler.organize_packages();
+        // Clients read noarch of every channel, even one without noarch packages
+        organized.entry(Platform::NoArch).or_default();
+        for (platform, packages) in &organized {
+            handler.create_repodata(platform, packages, target).await?;
+        }
+    }
+    report.duration = started.elapsed();
+
+    if report.packages.is_empty() {
+        let mut message = format!("No cached packages found in {}", cache_dir.display());
+        if let Some(package) = package {
+            message.push_str(&format!(" matching '{}'", package));
+        }
+        return Err(MirrorError::NotFound(message));
+    }
+    Ok(report)
+}
+
+/// Place one cached package into its subdir of the channel
+async fn share_package(
+    handler: &mut CondaPackageHandler,
+    cached: &CachedPackage,
+    target: &Path,
+    hardlink: bool,
+) -> Result<crate::conda_package::ProcessedPackage> {
+    let content = match &cached.source {
+        CachedSource::Archive(path) => {
+            Bytes::from(std::fs::read(path).map_err(|e| MirrorError::target_io(path, e))?)
+        }
+        CachedSource::Unpacked(dir) => repack(dir, &cached.filename).await?,
+    };
+    let processed = handler.process_package(content, &cached.filename).await?;
+
+    let platform_dir = target.join(processed.platform.to_string());
+    std::fs::create_dir_all(&platform_dir).map_err(|e| MirrorError::target_io(&platform_dir, e))?;
+    let destination = platform_dir.join(&cached.filename);
+    match std::fs::remove_file(&destination) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(MirrorError::target_io(&destination, e)),
+    }
+
+    let linked = match (&cached.source, hardlink) {
+        (CachedSource::Archive(path), true) => match std::fs::hard_link(path, &destination) {
+            Ok(()) => true,
+            Err(e) => {
+                info!("Copying {} instead of linking it: {}", cached.filename, e);
+                false
+            }
+        },
+        _ => false,
+    };
+    if !linked {
+        std::fs::write(&destination, &processed.content)
+            .map_err(|e| MirrorError::target_io(&destination, e))?;
+    }
+    Ok(processed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::PackageFixture;
+
+    #[tokio::test]
+    async fn test_share_cache_builds_channel() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let cache_dir = temp_dir.path().join("pkgs");
+        std::fs::create_dir_all(&cache_dir).unwrap();
+
+        // An archive, e.g. left by a cache target
+        let archived = PackageFixture::new("numpy", "1.26.4").subdir("linux-64");
+        std::fs::write(
+            cache_dir.join(archived.conda_filename()),
+            archived.to_conda(),
+        )
+        .unwrap();
+        // A package rattler unpacked, with the lock file it keeps next to it
+        let unpacked = PackageFixture::new("pytest", "8.0");
+        rattler_package_streaming::read::extract_conda_via_streaming(
+            &unpacked.to_conda()[..],
+            &cache_dir.join("pytest-8.0-0"),
+        )
+        .unwrap();
+        std::fs::write(cache_dir.join("pytest-8.0-0.lock"), b"").unwrap();
+        // A package being unpacked
+        std::fs::create_dir_all(cache_dir.join("partial-1.0-0")).unwrap();
+
+        let packages = cached_packages(&cache_dir).unwrap();
+        assert_eq!(
+            packages
+                .iter()
+                .map(|p| p.filename.as_str())
+                .collect::<Vec<_>>(),
+            vec!["numpy-1.26.4-0.conda", "pytest-8.0-0.conda"]
+        );
+
+        let channel = temp_dir.path().join("channel");
+        let report = share_cache(&cache_dir, &channel, None, true).await.unwrap();
+        assert_eq!(report.mirrored_count(), 2);
+        let linux = std::fs::read_to_string(channel.join("linux-64/repodata.json")).unwrap();
+        assert!(linux.contains("numpy-1.26.4-0.conda"));
+        let noarch = std::fs::read_to_string(channel.join("noarch/repodata.json")).unwrap();
+        assert!(noarch.contains("pytest-8.0-0.conda"));
+
+        // The repacked package is a valid archive of the same package
+        let mut handler = CondaPackageHandler::new();
+        let repacked = std::fs::read(channel.join("noarch/pytest-8.0-0.conda")).unwrap();
+        let processed = handler
+            .process_package(Bytes::from(repacked), "pytest-8.0-0.conda")
+            .await
+            .unwrap();
+        assert_eq!(processed.metadata.name, "pytest");
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let cached = std::fs::metadata(cache_dir.join("numpy-1.26.4-0.conda")).unwrap();
+            let shared = std::fs::metadata(channel.join("linux-64/numpy-1.26.4-0.conda")).unwrap();
+            assert_eq!(cached.ino(), shared.ino());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_share_cache_filters_by_package_name() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let cache_dir = temp_dir.path().join("pkgs");
+        std::fs::create_dir_all(&cache_dir).unwrap();
+        for fixture in [
+            PackageFixture::new("numpy", "1.26.4"),
+            PackageFixture::new("scipy", "1.12.0"),
+        ] {
+            std::fs::write(cache_dir.join(fixture.conda_filename()), fixture.to_conda()).unwrap();
+        }
+        let channel = temp_dir.path().join("channel");
+
+        let filter = Regex::new("^num").unwrap();
+        let report = share_cache(&cache_dir, &channel, Some(&filter), false)
+            .await
+            .unwrap();
+        assert_eq!(report.mirrored_count(), 1);
+        assert!(channel.join("noarch/numpy-1.26.4-0.conda").exists());
+        assert!(!channel.join("noarch/scipy-1.12.0-0.conda").exists());
+
+        let nothing = Regex::new("^pandas$").unwrap();
+        assert!(matches!(
+            share_cache(&cache_dir, &channel, Some(&nothing), false).await,
+            Err(MirrorError::NotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,226 @@
+//! Top-level shortcuts for common mirror runs
+//!
+//! `meso-forge-mirror pr <github-pr-url> --tgt ...` and the other shortcuts
+//! below are rewritten into the `mirror` invocation they stand for before the
+//! command line is parsed, so every `mirror` option can follow them. When
+//! `--tgt-type` is not given, it is read from the form of `--tgt`.
+
+use std::ffi::OsString;
+
+/// A shortcut command and the `mirror` source type it stands for
+pub struct Shortcut {
+    pub name: &'static str,
+    pub src_type: &'static str,
+}
+
+pub const SHORTCUTS: &[Shortcut] = &[
+    Shortcut {
+        name: "pr",
+        src_type: "github",
+    },
+    Shortcut {
+        name: "build",
+        src_type: "azure",
+    },
+    Shortcut {
+        name: "pipeline",
+        src_type: "gitlab",
+    },
+    Shortcut {
+        name: "release",
+        src_type: "github-release",
+    },
+    Shortcut {
+        name: "channel",
+        src_type: "channel",
+    },
+];
+
+/// Help text listing the shortcuts, shown after the commands
+pub const HELP: &str = "Shortcuts, each standing for `mirror --src-type <type> --src <source>`:
+  pr <github-pr-url>                   --src-type github
+  build <org/project#build-id>         --src-type azure
+  pipeline <group/project#pipeline-id> --src-type gitlab
+  release <owner/repo@tag>             --src-type github-release
+  channel <channel-url>                --src-type channel
+
+Example: meso-forge-mirror pr https://github.com/owner/repo/pull/12 --tgt s3://bucket/conda";
+
+/// Rewrite a shortcut command line into the `mirror` command line it stands for
+///
+/// Other command lines are returned unchanged.
+pub fn expand(args: Vec<OsString>) -> Vec<OsString> {
+    let Some(shortcut) = args
+        .get(1)
+        .and_then(|command| command.to_str())
+        .and_then(|command| SHORTCUTS.iter().find(|s| s.name == command))
+    else {
+        return args;
+    };
+
+    let mut rest = args[2..].iter().cloned().peekable();
+    let mut expanded: Vec<OsString> = vec![
+        args[0].clone(),
+        "mirror".into(),
+        "--src-type".into(),
+        shortcut.src_type.into(),
+    ];
+    // Without a source, `mirror` reports --src as missing
+    if let Some(source) = rest.next_if(|arg| !arg.to_string_lossy().starts_with('-')) {
+        expanded.push("--src".into());
+        expanded.push(source);
+    }
+    let rest: Vec<OsString> = rest.collect();
+
+    let has_tgt_type = rest.iter().any(|arg| {
+        let arg = arg.to_string_lossy();
+        arg == "--tgt-type" || arg.starts_with("--tgt-type=")
+    });
+    if !has_tgt_type {
+        if let Some(tgt_type) = target_value(&rest).as_deref().and_then(target_type) {
+            expanded.push("--tgt-type".into());
+            expanded.push(tgt_type.into());
+        }
+    }
+    expanded.extend(rest);
+    expanded
+}
+
+/// The value of `--tgt` or `-t` on a command line
+fn target_value(args: &[OsString]) -> Option<String> {
+    let mut args = args.iter().map(|arg| arg.to_string_lossy());
+    while let Some(arg) = args.next() {
+        if arg == "--tgt" || arg == "-t" {
+            return args.next().map(|value| value.to_string());
+        }
+        if let Some(value) = arg.strip_prefix("--tgt=") {
+            return Some(value.to_string());
+        }
+    }
+    None
+}
+
+/// The target type a `--tgt` value is written for
+fn target_type(tgt: &str) -> Option<&'static str> {
+    if tgt.starts_with("s3://") {
+        Some("s3")
+    } else if tgt.starts_with("oci://") {
+        Some("oci")
+    } else if tgt.starts_with("https://prefix.dev/") {
+        Some("prefix-dev")
+    } else if tgt.contains("://") {
+        // Another URL; --tgt-type has to say what it is
+        None
+    } else {
+        Some("local")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand_args(args: &[&str]) -> Vec<String> {
+        expand(args.iter().map(OsString::from).collect())
+            .into_iter()
+            .map(|arg| arg.to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_expand_shortcuts() {
+        assert_eq!(
+            expand_args(&[
+                "meso-forge-mirror",
+                "pr",
+                "https://github.com/owner/repo/pull/12",
+                "--tgt",
+                "s3://bucket/conda",
+                "--all-matches",
+            ]),
+            [
+                "meso-forge-mirror",
+                "mirror",
+                "--src-type",
+                "github",
+                "--src",
+                "https://github.com/owner/repo/pull/12",
+                "--tgt-type",
+                "s3",
+                "--tgt",
+                "s3://bucket/conda",
+                "--all-matches",
+            ]
+        );
+        assert_eq!(
+            expand_args(&[
+                "meso-forge-mirror",
+                "build",
+                "org/project#42",
+                "-t",
+                "./channel"
+            ]),
+            [
+                "meso-forge-mirror",
+                "mirror",
+                "--src-type",
+                "azure",
+                "--src",
+                "org/project#42",
+                "--tgt-type",
+                "local",
+                "-t",
+                "./channel",
+            ]
+        );
+
+        // An explicit target type is kept, and no target means the cache
+        assert_eq!(
+            expand_args(&[
+                "meso-forge-mirror",
+                "release",
+                "owner/repo@v1",
+                "--tgt-type=prefix-dev",
+                "--tgt=https://example.org/channel",
+            ])[4..],
+            [
+                "--src",
+                "owner/repo@v1",
+                "--tgt-type=prefix-dev",
+                "--tgt=https://example.org/channel",
+            ]
+        );
+        assert_eq!(
+            expand_args(&["meso-forge-mirror", "channel", "https://example.org/c"]).len(),
+            6
+        );
+
+        // A missing source is left for `mirror` to report
+        assert_eq!(
+            expand_args(&["meso-forge-mirror", "pr", "--help"]),
+            [
+                "meso-forge-mirror",
+                "mirror",
+                "--src-type",
+                "github",
+                "--help"
+            ]
+        );
+        assert_eq!(
+            expand_args(&["meso-forge-mirror", "info", "--github", "owner/repo"]),
+            ["meso-forge-mirror", "info", "--github", "owner/repo"]
+        );
+    }
+
+    #[test]
+    fn test_target_type() {
+        assert_eq!(target_type("s3://bucket/conda"), Some("s3"));
+        assert_eq!(target_type("oci://ghcr.io/org/conda"), Some("oci"));
+        assert_eq!(
+            target_type("https://prefix.dev/channels/meso-forge"),
+            Some("prefix-dev")
+        );
+        assert_eq!(target_type("https://example.org/channel"), None);
+        assert_eq!(target_type("/srv/conda"), Some("local"));
+    }
+}
@@ -0,0 +1,52 @@
+//! Cooperative shutdown on Ctrl-C
+//!
+//! The first interrupt only sets a flag: mirroring loops check it between
+//! packages, finish the upload in flight, update repodata and record what is
+//! left to do. A second interrupt exits immediately.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::Notify;
+use tracing::warn;
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+static NOTIFY: Notify = Notify::const_new();
+
+/// Exit status used when the process is stopped by SIGINT
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// Listen for Ctrl-C in the background
+pub fn install_handler() {
+    tokio::spawn(async {
+        loop {
+            if tokio::signal::ctrl_c().await.is_err() {
+                return;
+            }
+
+            if REQUESTED.swap(true, Ordering::SeqCst) {
+                warn!("Second interrupt received, exiting immediately");
+                std::process::exit(INTERRUPTED_EXIT_CODE);
+            }
+
+            warn!(
+                "Interrupt received, finishing in-flight uploads before stopping (press Ctrl-C again to abort)"
+            );
+            NOTIFY.notify_waiters();
+        }
+    });
+}
+
+/// Whether a shutdown has been requested
+pub fn is_requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Wait until a shutdown is requested
+pub async fn requested() {
+    loop {
+        let notified = NOTIFY.notified();
+        if is_requested() {
+            return;
+        }
+        notified.await;
+    }
+}
@@ -0,0 +1,359 @@
+//! Detached GPG signatures for packages and repodata
+//!
+//! With a `signing` section, every package a run uploads and every
+//! `repodata.json` it writes gets an ASCII-armored detached signature stored
+//! next to it as `<filename>.asc`, so clients can verify the mirror's content
+//! with plain `gpg --verify`, independently of conda-content-trust. Signing
+//! runs the configured `gpg` program; keys stay in its keyring.
+//!
+//! `subdir_keys` delegates subdirs to keys of their own, e.g. so the team
+//! building `win-64` packages holds that key. The `keys rotate` command moves
+//! the channel, or some of its subdirs, to a new key: it re-signs their
+//! `repodata.json`, exports the public keys to `keys/<key>.asc` and records
+//! the change in `keys.json`. That file is signed by the previous keys and the
+//! new one together, so clients that trust a previous key can pick up its
+//! successor.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use crate::error::{MirrorError, Result};
+
+/// Name of the key list published at the channel root
+pub const KEYS_FILE: &str = "keys.json";
+
+/// Directory at the channel root holding the exported public keys
+pub const PUBLIC_KEY_DIR: &str = "keys";
+
+/// Which keys sign, and how `gpg` is run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SigningConfig {
+    /// Key id, fingerprint or user id passed to `gpg --local-user`
+    pub key: String,
+    /// Keys signing the packages and repodata of particular subdirs instead of `key`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub subdir_keys: BTreeMap<String, String>,
+    /// `gpg` executable
+    #[serde(default = "default_program")]
+    pub program: String,
+    /// Keyring directory passed as `--homedir` (default: gpg's own)
+    #[serde(default)]
+    pub homedir: Option<String>,
+    /// File holding the key's passphrase, for keys that have one
+    #[serde(default)]
+    pub passphrase_file: Option<String>,
+}
+
+fn default_program() -> String {
+    "gpg".to_string()
+}
+
+impl SigningConfig {
+    /// Key signing the packages and repodata of `subdir`
+    pub fn key_for(&self, subdir: &str) -> &str {
+        self.subdir_keys.get(subdir).unwrap_or(&self.key)
+    }
+
+    /// This configuration with `subdirs` moved to `new_key`, or the default key when none are given
+    ///
+    /// Subdirs delegated to a key of their own keep it when the default key changes.
+    pub fn rotated(&self, new_key: &str, subdirs: &[String]) -> Self {
+        let mut rotated = self.clone();
+        if subdirs.is_empty() {
+            rotated.key = new_key.to_string();
+        } else {
+            for subdir in subdirs {
+                rotated
+                    .subdir_keys
+                    .insert(subdir.clone(), new_key.to_string());
+            }
+        }
+        rotated.subdir_keys.retain(|_, key| *key != rotated.key);
+        rotated
+    }
+
+    /// Every key of the configuration, the default key first
+    pub fn keys(&self) -> Vec<&str> {
+        let mut keys = vec![self.key.as_str()];
+        for key in self.subdir_keys.values() {
+            if !keys.contains(&key.as_str()) {
+                keys.push(key);
+            }
+        }
+        keys
+    }
+}
+
+/// Where the exported public key of `key` is published, relative to the channel root
+pub fn public_key_file(key: &str) -> String {
+    let name: String = key
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "@._-".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{}/{}.asc", PUBLIC_KEY_DIR, name)
+}
+
+/// One change of signing keys, as recorded in `keys.json`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyRotation {
+    pub rotated_at: DateTime<Utc>,
+    /// Keys that signed the moved subdirs before
+    pub from: Vec<String>,
+    pub to: String,
+    /// Subdirs whose `repodata.json` was re-signed with the new key
+    pub subdirs: Vec<String>,
+}
+
+/// The signing keys of a channel, published as `keys.json`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyIndex {
+    /// Key signing the subdirs that are not in `subdir_keys`
+    pub key: String,
+    #[serde(default)]
+    pub subdir_keys: BTreeMap<String, String>,
+    /// Exported public key of every key listed, relative to the channel root
+    #[serde(default)]
+    pub public_keys: BTreeMap<String, String>,
+    /// Key changes, oldest first
+    #[serde(default)]
+    pub rotations: Vec<KeyRotation>,
+}
+
+impl KeyIndex {
+    pub fn from_slice(content: &[u8]) -> Result<Self> {
+        Ok(serde_json::from_slice(content)?)
+    }
+
+    pub fn to_vec(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec_pretty(self)?)
+    }
+}
+
+/// Filename of the signature stored next to `filename`
+pub fn signature_filename(filename: &str) -> String {
+    format!("{}.asc", filename)
+}
+
+/// Produces detached signatures with the configured keys
+#[derive(Debug, Clone)]
+pub struct Signer {
+    config: SigningConfig,
+}
+
+impl Signer {
+    pub fn new(config: &SigningConfig) -> Self {
+        Self {
+            config: config.clone(),
+        }
+    }
+
+    pub fn config(&self) -> &SigningConfig {
+        &self.config
+    }
+
+    /// ASCII-armored detached signature of `content` by the key of `subdir`
+    pub async fn sign(&self, subdir: &str, content: &[u8]) -> Result<Vec<u8>> {
+        self.sign_with(&[self.config.key_for(subdir)], content)
+            .await
+    }
+
+    /// ASCII-armored detached signature of `content` by each of `keys`
+    pub async fn sign_with(&self, keys: &[&str], content: &[u8]) -> Result<Vec<u8>> {
+        let mut command = self.command();
+        command.args(["--armor", "--detach-sign"]);
+        for key in keys {
+            command.args(["--local-user", key]);
+        }
+        command.args(["--output", "-"]);
+
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| {
+                MirrorError::Signing(format!("Cannot run {}: {}", self.config.program, e))
+            })?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let write = async move {
+            let written = stdin.write_all(content).await;
+            drop(stdin);
+            written
+        };
+        let (written, output) = tokio::join!(write, child.wait_with_output());
+        let output = self.check(output?)?;
+        written?;
+        Ok(output)
+    }
+
+    /// ASCII-armored public key of `key`, to publish for clients
+    pub async fn export_public_key(&self, key: &str) -> Result<Vec<u8>> {
+        let mut command = self.command();
+        command.args(["--armor", "--export", key]);
+        let output = command.stdin(Stdio::null()).output().await.map_err(|e| {
+            MirrorError::Signing(format!("Cannot run {}: {}", self.config.program, e))
+        })?;
+        let public_key = self.check(output)?;
+        if public_key.is_empty() {
+            return Err(MirrorError::Signing(format!(
+                "No public key for {} in the keyring",
+                key
+            )));
+        }
+        Ok(public_key)
+    }
+
+    /// `gpg` with the options every invocation shares
+    fn command(&self) -> Command {
+        let mut command = Command::new(&self.config.program);
+        command.args(["--batch", "--yes"]);
+        if let Some(homedir) = &self.config.homedir {
+            command.args(["--homedir", homedir]);
+        }
+        if let Some(passphrase_file) = &self.config.passphrase_file {
+            command.args([
+                "--pinentry-mode",
+                "loopback",
+                "--passphrase-file",
+                passphrase_file,
+            ]);
+        }
+        command
+    }
+
+    /// The standard output of a finished `gpg`, or why it failed
+    fn check(&self, output: std::process::Output) -> Result<Vec<u8>> {
+        if !output.status.success() {
+            return Err(MirrorError::Signing(format!(
+                "{} exited with {}: {}",
+                self.config.program,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(output.stdout)
+    }
+}
+
+#[cfg(all(test, unix))]
+pub(crate) mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+
+    /// A stand-in for `gpg` that "signs" by reporting the size of its input and the keys used
+    pub(crate) fn fake_gpg(dir: &Path) -> SigningConfig {
+        let program = dir.join("fake-gpg");
+        std::fs::write(
+            &program,
+            r#"#!/bin/sh
+block=SIGNATURE
+keys=
+while [ $# -gt 0 ]; do
+  case "$1" in
+    --local-user) keys="$keys $2"; shift ;;
+    --export) block="PUBLIC KEY BLOCK"; keys="$keys $2"; shift ;;
+  esac
+  shift
+done
+printf -- '-----BEGIN PGP %s-----\n%s\n%s\n' "$block" "$(wc -c)" "$keys"
+"#,
+        )
+        .unwrap();
+        std::fs::set_permissions(&program, std::fs::Permissions::from_mode(0o755)).unwrap();
+        SigningConfig {
+            key: "mirror@example.com".to_string(),
+            subdir_keys: BTreeMap::new(),
+            program: program.to_string_lossy().to_string(),
+            homedir: None,
+            passphrase_file: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sign() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = fake_gpg(temp_dir.path());
+        config
+            .subdir_keys
+            .insert("win-64".to_string(), "windows@example.com".to_string());
+        let signer = Signer::new(&config);
+        let signature =
+            String::from_utf8(signer.sign("linux-64", b"package").await.unwrap()).unwrap();
+        assert!(signature.starts_with("-----BEGIN PGP SIGNATURE-----"));
+        assert_eq!(signature.lines().nth(1).unwrap().trim(), "7");
+        assert_eq!(
+            signature.lines().nth(2).unwrap().trim(),
+            "mirror@example.com"
+        );
+        let signature = signer.sign("win-64", b"package").await.unwrap();
+        assert!(String::from_utf8(signature)
+            .unwrap()
+            .ends_with(" windows@example.com\n"));
+        let cosigned = signer
+            .sign_with(&["old@example.com", "new@example.com"], b"keys")
+            .await
+            .unwrap();
+        assert!(String::from_utf8(cosigned)
+            .unwrap()
+            .ends_with(" old@example.com new@example.com\n"));
+        let public_key = signer
+            .export_public_key("mirror@example.com")
+            .await
+            .unwrap();
+        assert!(String::from_utf8(public_key)
+            .unwrap()
+            .starts_with("-----BEGIN PGP PUBLIC KEY BLOCK-----"));
+
+        let failing = Signer::new(&SigningConfig {
+            program: "false".to_string(),
+            ..fake_gpg(temp_dir.path())
+        });
+        assert!(matches!(
+            failing.sign("noarch", b"package").await,
+            Err(MirrorError::Signing(_))
+        ));
+    }
+
+    #[test]
+    fn test_rotated() {
+        let config = SigningConfig {
+            key: "old@example.com".to_string(),
+            subdir_keys: BTreeMap::from([(
+                "win-64".to_string(),
+                "windows@example.com".to_string(),
+            )]),
+            program: default_program(),
+            homedir: None,
+            passphrase_file: None,
+        };
+        assert_eq!(config.keys(), ["old@example.com", "windows@example.com"]);
+
+        let rotated = config.rotated("new@example.com", &[]);
+        assert_eq!(rotated.key_for("linux-64"), "new@example.com");
+        assert_eq!(rotated.key_for("win-64"), "windows@example.com");
+
+        let rotated = config.rotated("new@example.com", &["osx-arm64".to_string()]);
+        assert_eq!(rotated.key_for("linux-64"), "old@example.com");
+        assert_eq!(rotated.key_for("osx-arm64"), "new@example.com");
+
+        // A subdir moved back to the default key is no longer delegated
+        let rotated = config.rotated("old@example.com", &["win-64".to_string()]);
+        assert!(rotated.subdir_keys.is_empty());
+
+        assert_eq!(
+            public_key_file("Mirror Bot <bot@example.com>"),
+            "keys/Mirror_Bot__bot@example.com_.asc"
+        );
+    }
+}
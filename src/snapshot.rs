@@ -0,0 +1,153 @@
+//! Snapshots of a channel's repodata, and rolling back to them
+//!
+//! Before a run, prune, gc or rollback rewrites the `repodata.json` of a local or
+//! S3 channel, the current repodata of every subdir is copied to
+//! `repodata-snapshots/<id>/<subdir>.json` and listed in
+//! `repodata-snapshots/index.json`. A snapshot identical to the latest one is
+//! not taken again, and only the newest `repodata_snapshots` are kept. The
+//! `rollback` command puts the repodata of a snapshot back in place, so a bad
+//! sync or prune can be undone for consumers without touching package files.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{MirrorError, Result};
+
+/// Directory at the channel root holding the snapshots
+pub const SNAPSHOT_DIR: &str = "repodata-snapshots";
+
+/// Name of the snapshot list, relative to the channel root
+pub const SNAPSHOT_INDEX: &str = "repodata-snapshots/index.json";
+
+/// One snapshot of the repodata of a channel
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Timestamp naming the snapshot, e.g. `20240501T120000.000Z`
+    pub id: String,
+    pub taken_at: DateTime<Utc>,
+    /// What was about to rewrite the repodata: `mirror`, `prune`, `gc` or `rollback`
+    pub reason: String,
+    /// Subdirs that had a `repodata.json`
+    pub subdirs: Vec<String>,
+}
+
+impl Snapshot {
+    /// Where the repodata of `subdir` is kept, relative to the channel root
+    pub fn file(&self, subdir: &str) -> String {
+        format!("{}/{}/{}.json", SNAPSHOT_DIR, self.id, subdir)
+    }
+}
+
+/// Snapshots of a channel, oldest first
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotIndex {
+    #[serde(default)]
+    pub snapshots: Vec<Snapshot>,
+}
+
+impl SnapshotIndex {
+    pub fn from_slice(content: &[u8]) -> Result<Self> {
+        Ok(serde_json::from_slice(content)?)
+    }
+
+    pub fn to_vec(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec_pretty(self)?)
+    }
+
+    pub fn latest(&self) -> Option<&Snapshot> {
+        self.snapshots.last()
+    }
+
+    /// The snapshot with `id`, or the latest one when no id is given
+    pub fn find(&self, id: Option<&str>) -> Result<&Snapshot> {
+        match id {
+            Some(id) => self
+                .snapshots
+                .iter()
+                .find(|snapshot| snapshot.id == id)
+                .ok_or_else(|| MirrorError::InvalidInput(format!("No repodata snapshot {}", id))),
+            None => self
+                .latest()
+                .ok_or_else(|| MirrorError::InvalidInput("No repodata snapshots".to_string())),
+        }
+    }
+
+    /// Add a snapshot taken at `taken_at`, named after it
+    pub fn add(&mut self, taken_at: DateTime<Utc>, reason: &str, subdirs: Vec<String>) -> Snapshot {
+        let stamp = taken_at.format("%Y%m%dT%H%M%S%.3fZ").to_string();
+        let mut id = stamp.clone();
+        let mut suffix = 1;
+        while self.snapshots.iter().any(|snapshot| snapshot.id == id) {
+            suffix += 1;
+            id = format!("{}-{}", stamp, suffix);
+        }
+        let snapshot = Snapshot {
+            id,
+            taken_at,
+            reason: reason.to_string(),
+            subdirs,
+        };
+        self.snapshots.push(snapshot.clone());
+        snapshot
+    }
+
+    /// Drop all but the newest `keep` snapshots, returning the dropped ones
+    pub fn expire(&mut self, keep: usize) -> Vec<Snapshot> {
+        let expired = self.snapshots.len().saturating_sub(keep);
+        self.snapshots.drain(..expired).collect()
+    }
+}
+
+/// Outcome of rolling a channel back to a snapshot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rollback {
+    pub snapshot: Snapshot,
+    /// `subdir/filename` of packages the snapshot lists whose files are gone
+    pub missing: Vec<String>,
+}
+
+/// `repodata.json` of a subdir holding no packages, written when rolling back
+/// to a snapshot taken before the subdir existed
+pub fn empty_repodata(subdir: &str) -> Result<Vec<u8>> {
+    Ok(serde_json::to_vec_pretty(&serde_json::json!({
+        "info": { "subdir": subdir },
+        "packages": {},
+        "packages.conda": {},
+        "removed": [],
+        "repodata_version": 1
+    }))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_add_find_and_expire() {
+        let taken_at = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        let mut index = SnapshotIndex::default();
+        assert!(index.find(None).is_err());
+
+        let first = index.add(taken_at, "mirror", vec!["noarch".to_string()]);
+        assert_eq!(first.id, "20240501T120000.000Z");
+        assert_eq!(
+            first.file("noarch"),
+            "repodata-snapshots/20240501T120000.000Z/noarch.json"
+        );
+        let second = index.add(taken_at, "prune", vec!["noarch".to_string()]);
+        assert_eq!(second.id, "20240501T120000.000Z-2");
+        let third = index.add(taken_at, "rollback", Vec::new());
+
+        assert_eq!(index.find(None).unwrap(), &third);
+        assert_eq!(index.find(Some(&second.id)).unwrap(), &second);
+        assert!(index.find(Some("20230101T000000.000Z")).is_err());
+
+        let loaded = SnapshotIndex::from_slice(&index.to_vec().unwrap()).unwrap();
+        assert_eq!(loaded, index);
+
+        assert_eq!(index.expire(2), vec![first]);
+        assert_eq!(index.snapshots, vec![second, third]);
+        assert!(index.expire(2).is_empty());
+    }
+}
@@ -0,0 +1,143 @@
+//! Sources of packages to mirror
+//!
+//! A [`SourceProvider`] lists the candidate packages of a source as a stream of
+//! [`PackageEntry`] values, each carrying a future that fetches its content.
+//! The mirroring engine only consumes this stream, so the built-in sources
+//! (single packages, ZIP files, tarballs, conda channels, and GitHub, Azure
+//! DevOps and GitLab CI artifacts) and sources defined by downstream crates are
+//! handled alike.
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use futures::FutureExt;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use tokio::sync::OwnedSemaphorePermit;
+
+use crate::error::Result;
+use crate::report::SkipCode;
+
+/// Stream of candidate packages produced by a [`SourceProvider`]
+pub type PackageStream = BoxStream<'static, Result<PackageEntry>>;
+
+/// A CI artifact packages were extracted from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArtifactSource {
+    /// Artifact name
+    pub name: String,
+    /// GitHub Actions workflow run that produced the artifact
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<u64>,
+    /// Azure DevOps build that produced the artifact
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build_id: Option<u64>,
+    /// GitLab CI pipeline that produced the artifact
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pipeline_id: Option<u64>,
+    /// GitLab CI job whose artifacts the packages were taken from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job_id: Option<u64>,
+}
+
+/// A package offered by a source
+pub struct PackageEntry {
+    /// Package filename, e.g. `numpy-1.26.0-py312_0.conda`
+    pub name: String,
+    /// Size in bytes, if the source knows it before fetching
+    pub size: Option<u64>,
+    /// Where the package came from within the source, e.g. an artifact or build id
+    pub origin: Option<String>,
+    /// The CI artifact the package was extracted from
+    pub artifact: Option<ArtifactSource>,
+    /// Fetches the package content; not polled for packages that are skipped
+    pub fetch: BoxFuture<'static, Result<Bytes>>,
+    /// Why the package is skipped without being fetched, e.g. because the target has it
+    pub skip: Option<(SkipCode, String)>,
+    /// Room taken under `global_max_buffered_bytes` while the package is fetched ahead
+    pub(crate) reservation: Option<OwnedSemaphorePermit>,
+}
+
+impl PackageEntry {
+    pub fn new(
+        name: impl Into<String>,
+        size: Option<u64>,
+        fetch: impl Future<Output = Result<Bytes>> + Send + 'static,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            size,
+            origin: None,
+            artifact: None,
+            fetch: fetch.boxed(),
+            skip: None,
+            reservation: None,
+        }
+    }
+
+    /// Record where the package came from, kept in the run report and state database
+    pub fn with_origin(mut self, origin: impl Into<String>) -> Self {
+        self.origin = Some(origin.into());
+        self
+    }
+
+    /// Record the CI artifact the package was extracted from
+    pub fn with_artifact(mut self, artifact: ArtifactSource) -> Self {
+        self.artifact = Some(artifact);
+        self
+    }
+
+    /// Skip the package without fetching it, recording `code` and `reason` in the run report
+    pub fn skipped(mut self, code: SkipCode, reason: impl Into<String>) -> Self {
+        self.skip = Some((code, reason.into()));
+        self
+    }
+
+    /// An entry whose content has already been read, e.g. from an extracted archive
+    pub fn ready(name: impl Into<String>, content: Bytes) -> Self {
+        let size = Some(content.len() as u64);
+        Self::new(name, size, async move { Ok(content) })
+    }
+}
+
+impl std::fmt::Debug for PackageEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PackageEntry")
+            .field("name", &self.name)
+            .field("size", &self.size)
+            .field("origin", &self.origin)
+            .field("artifact", &self.artifact)
+            .field("skip", &self.skip)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A source of conda packages
+///
+/// Errors for the source as a whole (missing credentials, nothing found) are
+/// returned from [`entries`](Self::entries); errors for one part of it, such as
+/// an archive that stayed corrupt, are yielded in the stream so the remaining
+/// entries can still be mirrored. Parts left out on purpose, such as expired
+/// artifacts, are yielded as [`MirrorError::Skipped`](crate::error::MirrorError::Skipped)
+/// for the run report to record.
+#[async_trait]
+pub trait SourceProvider: Send + Sync {
+    /// Identifies the source in logs and in the resume state of interrupted runs
+    fn name(&self) -> &str;
+
+    /// Candidate packages of the source
+    async fn entries(&self) -> Result<PackageStream>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_ready_entry() {
+        let entry = PackageEntry::ready("pkg-1.0-0.conda", Bytes::from_static(b"content"));
+        assert_eq!(entry.size, Some(7));
+        assert_eq!(entry.fetch.await.unwrap(), Bytes::from_static(b"content"));
+    }
+}
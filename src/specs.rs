@@ -0,0 +1,181 @@
+//! Package selection with match specs
+//!
+//! `--spec "numpy >=1.21,<2"`, repeatable, or `specs` in the configuration,
+//! mirrors only the packages matching at least one of the given match specs,
+//! whatever the source type. A package whose filename already rules it out is
+//! skipped before it is fetched; every other package is checked again once its
+//! `info/index.json` has been read, since a CI upload step may have renamed it.
+
+use rattler_conda_types::{
+    MatchSpec, Matches, PackageName, PackageRecord, ParseStrictness, Version,
+};
+use std::str::FromStr;
+
+use crate::conda_package::ProcessedPackage;
+use crate::error::{MirrorError, Result};
+
+/// Match specs a package must satisfy one of to be mirrored
+#[derive(Debug, Clone)]
+pub struct SpecFilter {
+    /// Specs as written, for skip reasons, and parsed
+    specs: Vec<(String, MatchSpec)>,
+}
+
+impl SpecFilter {
+    /// Parse `specs` leniently, as conda does; `None` when there are none
+    pub fn new(specs: &[String]) -> Result<Option<Self>> {
+        if specs.is_empty() {
+            return Ok(None);
+        }
+        let specs = specs
+            .iter()
+            .map(|spec| {
+                let parsed = MatchSpec::from_str(spec, ParseStrictness::Lenient).map_err(|e| {
+                    MirrorError::InvalidInput(format!("Invalid match spec '{}': {}", spec, e))
+                })?;
+                Ok((spec.clone(), parsed))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Some(Self { specs }))
+    }
+
+    /// The specs as given, for messages
+    pub fn describe(&self) -> String {
+        self.specs
+            .iter()
+            .map(|(spec, _)| format!("'{}'", spec))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Whether `filename` alone shows the package matches no spec
+    ///
+    /// Only the name, version and build a canonical filename carries are
+    /// compared; anything the filename cannot tell, or a filename that is not
+    /// `<name>-<version>-<build>.<ext>`, is left for [`matches`](Self::matches).
+    pub fn rules_out(&self, filename: &str) -> bool {
+        let Some((name, version, build)) = filename_parts(filename) else {
+            return false;
+        };
+        !self.specs.iter().any(|(_, spec)| {
+            spec.name
+                .as_ref()
+                .is_none_or(|spec_name| spec_name.as_normalized() == name)
+                && spec
+                    .version
+                    .as_ref()
+                    .is_none_or(|version_spec| version_spec.matches(&version))
+                && spec
+                    .build
+                    .as_ref()
+                    .is_none_or(|build_spec| build_spec.matches(build))
+        })
+    }
+
+    /// Whether the package, with its metadata read, matches one of the specs
+    pub fn matches(&self, package: &ProcessedPackage) -> bool {
+        let Some(record) = package_record(package) else {
+            return false;
+        };
+        let subdir = package.platform.as_str();
+        self.specs.iter().any(|(_, spec)| {
+            spec.matches(&record) && spec.subdir.as_deref().is_none_or(|s| s == subdir)
+        })
+    }
+}
+
+/// Lower-cased name, version and build of a `name-version-build.conda` (or `.tar.bz2`) filename
+fn filename_parts(filename: &str) -> Option<(String, Version, &str)> {
+    let basename = filename.rsplit('/').next().unwrap_or(filename);
+    let stem = basename
+        .strip_suffix(".conda")
+        .or_else(|| basename.strip_suffix(".tar.bz2"))?;
+    let mut parts = stem.rsplitn(3, '-');
+    let build = parts.next()?;
+    // A renamed package such as `numpy-linux-64.conda` has no version to go by
+    let version = parts
+        .next()
+        .filter(|version| version.starts_with(|c: char| c.is_ascii_digit()))?;
+    let version = Version::from_str(version).ok()?;
+    let name = parts.next().filter(|name| !name.is_empty())?;
+    Some((name.to_lowercase(), version, build))
+}
+
+/// A record with what the package's metadata tells, for matching against specs
+fn package_record(package: &ProcessedPackage) -> Option<PackageRecord> {
+    let metadata = &package.metadata;
+    let name = PackageName::from_str(&metadata.name.to_lowercase()).ok()?;
+    let version = Version::from_str(&metadata.version).ok()?;
+    let mut record = PackageRecord::new(name, version, metadata.build.clone());
+    record.build_number = metadata.build_number;
+    record.subdir = package.platform.to_string();
+    record.license = metadata.license.clone();
+    Some(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conda_package::SimpleIndexJson;
+    use rattler_conda_types::Platform;
+
+    fn package(name: &str, version: &str, build: &str, platform: Platform) -> ProcessedPackage {
+        ProcessedPackage {
+            content: bytes::Bytes::new(),
+            metadata: SimpleIndexJson {
+                name: name.to_string(),
+                version: version.to_string(),
+                build: build.to_string(),
+                build_number: 0,
+                license: Some("BSD-3-Clause".to_string()),
+                ..Default::default()
+            },
+            filename: format!("{}-{}-{}.conda", name, version, build),
+            platform,
+            size: 0,
+            md5: String::new(),
+            sha256: String::new(),
+            metadata_source: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_rules_out_by_filename() {
+        let filter =
+            SpecFilter::new(&["numpy >=1.21,<2".to_string(), "scipy * py312*".to_string()])
+                .unwrap()
+                .unwrap();
+        assert!(!filter.rules_out("numpy-1.26.4-py312h8753938_0.conda"));
+        assert!(filter.rules_out("numpy-2.0.0-py312h8753938_0.conda"));
+        assert!(filter.rules_out("pandas-2.2.0-py312_0.tar.bz2"));
+        assert!(!filter.rules_out("scipy-1.13.0-py312_0.conda"));
+        assert!(filter.rules_out("scipy-1.13.0-py311_0.conda"));
+        // Nothing can be told from a renamed package until its metadata is read
+        assert!(!filter.rules_out("numpy-linux-64.conda"));
+        assert!(!filter.rules_out("artifact.zip"));
+
+        assert!(SpecFilter::new(&[]).unwrap().is_none());
+        assert!(SpecFilter::new(&["numpy >=>1".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_matches_metadata() {
+        let filter = SpecFilter::new(&[
+            "numpy >=1.21,<2".to_string(),
+            "tzdata[subdir=noarch]".to_string(),
+            "scipy[license=MIT]".to_string(),
+        ])
+        .unwrap()
+        .unwrap();
+        assert!(filter.matches(&package("numpy", "1.26.4", "py312_0", Platform::Linux64)));
+        assert!(filter.matches(&package("NumPy", "1.26.4", "py312_0", Platform::Linux64)));
+        assert!(!filter.matches(&package("numpy", "2.0.0", "py312_0", Platform::Linux64)));
+        assert!(filter.matches(&package("tzdata", "2024a", "0", Platform::NoArch)));
+        assert!(!filter.matches(&package("tzdata", "2024a", "0", Platform::Linux64)));
+        assert!(!filter.matches(&package("scipy", "1.13.0", "py312_0", Platform::Linux64)));
+        assert_eq!(
+            filter.describe(),
+            "'numpy >=1.21,<2', 'tzdata[subdir=noarch]', 'scipy[license=MIT]'"
+        );
+    }
+}
@@ -0,0 +1,910 @@
+//! Persistent record of mirrored packages
+//!
+//! [`StateDb`] keeps an SQLite database of every package a run wrote to a
+//! target or found already there, with its origin, platform, sha256 and the
+//! time it was recorded. It lets `--since-last-run` skip packages an earlier run
+//! already delivered without downloading them again, answers audit queries
+//! through the `history` command, and reports drift between what was mirrored
+//! and what a target actually holds. Requires the `state-db` feature.
+
+use chrono::{DateTime, Utc};
+use comfy_table::presets::NOTHING;
+use comfy_table::{Attribute, Cell, ContentArrangement, Table};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::path::Path;
+
+use crate::error::{MirrorError, Result};
+use crate::report::{MirrorReport, PackageOutcome};
+use crate::suggest;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    source TEXT NOT NULL,
+    target TEXT NOT NULL,
+    started_at TEXT NOT NULL,
+    finished_at TEXT NOT NULL,
+    mirrored INTEGER NOT NULL,
+    skipped INTEGER NOT NULL,
+    failed INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS packages (
+    id INTEGER PRIMARY KEY,
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    source TEXT NOT NULL,
+    origin TEXT,
+    target TEXT NOT NULL,
+    filename TEXT NOT NULL,
+    platform TEXT,
+    sha256 TEXT,
+    size INTEGER NOT NULL,
+    outcome TEXT NOT NULL,
+    recorded_at TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS packages_by_target ON packages (target, filename);
+CREATE TABLE IF NOT EXISTS run_stats (
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    platform TEXT NOT NULL,
+    packages INTEGER NOT NULL,
+    size INTEGER NOT NULL
+);
+";
+
+/// Changes applied in order to databases created by earlier versions,
+/// tracked through `PRAGMA user_version`
+const MIGRATIONS: &[&str] = &[
+    "ALTER TABLE packages ADD COLUMN provenance TEXT;",
+    "CREATE TABLE IF NOT EXISTS promotions (
+        id INTEGER PRIMARY KEY,
+        staging TEXT NOT NULL,
+        production TEXT NOT NULL,
+        label TEXT,
+        filename TEXT NOT NULL,
+        platform TEXT,
+        sha256 TEXT,
+        size INTEGER NOT NULL,
+        promoted_at TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS promotions_by_production ON promotions (production, filename);",
+];
+
+/// One package recorded by a run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MirroredPackage {
+    pub run_id: i64,
+    pub source: String,
+    /// Where the package came from within the source, e.g. an artifact id
+    pub origin: Option<String>,
+    pub target: String,
+    pub filename: String,
+    pub platform: Option<String>,
+    pub sha256: Option<String>,
+    pub size: u64,
+    /// `mirrored` if the run wrote the package, `present` if an identical copy was already there
+    pub outcome: String,
+    pub recorded_at: DateTime<Utc>,
+    /// in-toto provenance statement, if the run produced one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<serde_json::Value>,
+}
+
+impl MirroredPackage {
+    /// Path of the package relative to the target, e.g. `linux-64/pkg-1.0-0.conda`
+    pub fn path(&self) -> String {
+        match &self.platform {
+            Some(platform) => format!("{}/{}", platform, self.filename),
+            None => self.filename.clone(),
+        }
+    }
+}
+
+/// One package promoted from a staging target to production
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Promotion {
+    pub staging: String,
+    pub production: String,
+    /// Label the promotion was made under, e.g. `stable`
+    pub label: Option<String>,
+    pub filename: String,
+    pub platform: Option<String>,
+    pub sha256: Option<String>,
+    pub size: u64,
+    pub promoted_at: DateTime<Utc>,
+}
+
+impl Promotion {
+    /// Path of the package relative to the targets, e.g. `linux-64/pkg-1.0-0.conda`
+    pub fn path(&self) -> String {
+        match &self.platform {
+            Some(platform) => format!("{}/{}", platform, self.filename),
+            None => self.filename.clone(),
+        }
+    }
+}
+
+/// Filters for [`StateDb::history`] and [`StateDb::promotions`]
+#[derive(Debug, Clone, Default)]
+pub struct HistoryQuery {
+    pub source: Option<String>,
+    pub target: Option<String>,
+    /// Substring of the package filename
+    pub package: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// Packages of one platform a target held after a run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlatformStats {
+    pub platform: String,
+    pub packages: u64,
+    /// Total size in bytes of the packages the database has records of
+    pub size: u64,
+}
+
+/// What a target held after a run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunStats {
+    pub run_id: i64,
+    pub finished_at: DateTime<Utc>,
+    pub platforms: Vec<PlatformStats>,
+}
+
+impl RunStats {
+    pub fn packages(&self) -> u64 {
+        self.platforms
+            .iter()
+            .map(|platform| platform.packages)
+            .sum()
+    }
+
+    pub fn size(&self) -> u64 {
+        self.platforms.iter().map(|platform| platform.size).sum()
+    }
+}
+
+/// Differences between the recorded packages of a target and what it holds
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Drift {
+    /// Recorded as mirrored but no longer stored at the target
+    pub missing: Vec<MirroredPackage>,
+    /// Stored at the target without any record of being mirrored there
+    pub untracked: Vec<String>,
+}
+
+impl Drift {
+    pub fn is_empty(&self) -> bool {
+        self.missing.is_empty() && self.untracked.is_empty()
+    }
+}
+
+/// SQLite database recording the packages delivered by mirroring runs
+pub struct StateDb {
+    conn: Connection,
+}
+
+impl StateDb {
+    /// Open the database at `path`, creating it and its tables if needed
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let conn = Connection::open(path).map_err(|e| {
+            MirrorError::StateDb(format!("Failed to open {}: {}", path.display(), e))
+        })?;
+        conn.execute_batch(SCHEMA)?;
+        let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            conn.execute_batch(migration)?;
+            conn.pragma_update(None, "user_version", index + 1)?;
+        }
+        Ok(Self { conn })
+    }
+
+    /// Record a finished run and the packages it mirrored or found already present
+    ///
+    /// Returns the id of the run.
+    pub fn record_report(&mut self, report: &MirrorReport) -> Result<i64> {
+        let finished_at =
+            report.started_at + chrono::Duration::from_std(report.duration).unwrap_or_default();
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT INTO runs (source, target, started_at, finished_at, mirrored, skipped, failed)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                report.source,
+                report.target,
+                report.started_at,
+                finished_at,
+                report.mirrored_count() as i64,
+                report.skipped_count() as i64,
+                report.failed_count() as i64,
+            ],
+        )?;
+        let run_id = tx.last_insert_rowid();
+
+        // Packages skipped without being processed have no sha256 to record
+        for package in report.packages.iter().filter(|p| p.sha256.is_some()) {
+            let outcome = match package.outcome {
+                PackageOutcome::Mirrored => "mirrored",
+                PackageOutcome::Skipped { .. } => "present",
+                PackageOutcome::Failed { .. } => continue,
+            };
+            tx.execute(
+                "INSERT INTO packages
+                 (run_id, source, origin, target, filename, platform, sha256, size, outcome, recorded_at, provenance)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                params![
+                    run_id,
+                    report.source,
+                    package.origin,
+                    report.target,
+                    package.filename,
+                    package.platform,
+                    package.sha256,
+                    package.bytes as i64,
+                    outcome,
+                    finished_at,
+                    package.provenance.as_ref().map(|statement| statement.to_string()),
+                ],
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(run_id)
+    }
+
+    /// Whether an earlier run delivered `filename` to `target`
+    pub fn is_mirrored(&self, target: &str, filename: &str) -> Result<bool> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT 1 FROM packages WHERE target = ?1 AND filename = ?2 LIMIT 1",
+                params![target, filename],
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some())
+    }
+
+    /// When the last run from `source` into `target` finished
+    pub fn last_run(&self, source: &str, target: &str) -> Result<Option<DateTime<Utc>>> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT finished_at FROM runs WHERE source = ?1 AND target = ?2
+                 ORDER BY id DESC LIMIT 1",
+                params![source, target],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    /// Recorded packages matching `query`, most recent first
+    pub fn history(&self, query: &HistoryQuery) -> Result<Vec<MirroredPackage>> {
+        let limit = query.limit.map_or(-1, |limit| limit as i64);
+        let mut statement = self.conn.prepare(
+            "SELECT run_id, source, origin, target, filename, platform, sha256, size, outcome, recorded_at, provenance
+             FROM packages
+             WHERE (?1 IS NULL OR source = ?1)
+               AND (?2 IS NULL OR target = ?2)
+               AND (?3 IS NULL OR instr(filename, ?3) > 0)
+             ORDER BY id DESC
+             LIMIT ?4",
+        )?;
+        let rows = statement.query_map(
+            params![query.source, query.target, query.package, limit],
+            |row| {
+                Ok(MirroredPackage {
+                    run_id: row.get(0)?,
+                    source: row.get(1)?,
+                    origin: row.get(2)?,
+                    target: row.get(3)?,
+                    filename: row.get(4)?,
+                    platform: row.get(5)?,
+                    sha256: row.get(6)?,
+                    size: row.get::<_, i64>(7)? as u64,
+                    outcome: row.get(8)?,
+                    recorded_at: row.get(9)?,
+                    provenance: row
+                        .get::<_, Option<String>>(10)?
+                        .and_then(|statement| serde_json::from_str(&statement).ok()),
+                })
+            },
+        )?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Record the packages a promotion run placed in production or found already there
+    ///
+    /// The report's source is the staging target and its target production.
+    /// Returns the number of packages recorded.
+    pub fn record_promotion(
+        &mut self,
+        report: &MirrorReport,
+        label: Option<&str>,
+    ) -> Result<usize> {
+        let promoted_at =
+            report.started_at + chrono::Duration::from_std(report.duration).unwrap_or_default();
+        let tx = self.conn.transaction()?;
+        let mut recorded = 0;
+        for package in report.packages.iter().filter(|p| p.sha256.is_some()) {
+            if matches!(package.outcome, PackageOutcome::Failed { .. }) {
+                continue;
+            }
+            tx.execute(
+                "INSERT INTO promotions
+                 (staging, production, label, filename, platform, sha256, size, promoted_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    report.source,
+                    report.target,
+                    label,
+                    package.filename,
+                    package.platform,
+                    package.sha256,
+                    package.bytes as i64,
+                    promoted_at,
+                ],
+            )?;
+            recorded += 1;
+        }
+        tx.commit()?;
+        Ok(recorded)
+    }
+
+    /// Recorded promotions matching `query`, most recent first
+    ///
+    /// The source and target of the query select the staging and production targets.
+    pub fn promotions(&self, query: &HistoryQuery) -> Result<Vec<Promotion>> {
+        let limit = query.limit.map_or(-1, |limit| limit as i64);
+        let mut statement = self.conn.prepare(
+            "SELECT staging, production, label, filename, platform, sha256, size, promoted_at
+             FROM promotions
+             WHERE (?1 IS NULL OR staging = ?1)
+               AND (?2 IS NULL OR production = ?2)
+               AND (?3 IS NULL OR instr(filename, ?3) > 0)
+             ORDER BY id DESC
+             LIMIT ?4",
+        )?;
+        let rows = statement.query_map(
+            params![query.source, query.target, query.package, limit],
+            |row| {
+                Ok(Promotion {
+                    staging: row.get(0)?,
+                    production: row.get(1)?,
+                    label: row.get(2)?,
+                    filename: row.get(3)?,
+                    platform: row.get(4)?,
+                    sha256: row.get(5)?,
+                    size: row.get::<_, i64>(6)? as u64,
+                    promoted_at: row.get(7)?,
+                })
+            },
+        )?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Record what `target` holds after run `run_id`, per platform
+    ///
+    /// `stored` holds paths as returned by
+    /// [`Repository::stored_packages`](crate::repository::Repository::stored_packages).
+    /// Sizes are taken from the latest record of each package, so packages the
+    /// database has no record of are counted without a size.
+    pub fn record_stats(&mut self, run_id: i64, target: &str, stored: &[String]) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        let mut platforms: BTreeMap<String, (u64, u64)> = BTreeMap::new();
+        for path in stored {
+            let filename = file_name(path);
+            let recorded: Option<(Option<String>, i64)> = tx
+                .query_row(
+                    "SELECT platform, size FROM packages WHERE target = ?1 AND filename = ?2
+                     ORDER BY id DESC LIMIT 1",
+                    params![target, filename],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .optional()?;
+            let platform = match (path.rsplit_once('/'), &recorded) {
+                (Some((platform, _)), _) => platform.to_string(),
+                (None, Some((Some(platform), _))) => platform.clone(),
+                (None, _) => "unknown".to_string(),
+            };
+            let stats = platforms.entry(platform).or_default();
+            stats.0 += 1;
+            stats.1 += recorded.map_or(0, |(_, size)| size as u64);
+        }
+        for (platform, (packages, size)) in platforms {
+            tx.execute(
+                "INSERT INTO run_stats (run_id, platform, packages, size) VALUES (?1, ?2, ?3, ?4)",
+                params![run_id, platform, packages as i64, size as i64],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// What `target` held after each recorded run, oldest first
+    pub fn stats_history(&self, target: &str) -> Result<Vec<RunStats>> {
+        let mut statement = self.conn.prepare(
+            "SELECT runs.id, runs.finished_at, run_stats.platform, run_stats.packages, run_stats.size
+             FROM run_stats JOIN runs ON runs.id = run_stats.run_id
+             WHERE runs.target = ?1
+             ORDER BY runs.id, run_stats.platform",
+        )?;
+        let rows = statement.query_map(params![target], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, DateTime<Utc>>(1)?,
+                PlatformStats {
+                    platform: row.get(2)?,
+                    packages: row.get::<_, i64>(3)? as u64,
+                    size: row.get::<_, i64>(4)? as u64,
+                },
+            ))
+        })?;
+
+        let mut history: Vec<RunStats> = Vec::new();
+        for row in rows {
+            let (run_id, finished_at, platform) = row?;
+            match history.last_mut() {
+                Some(stats) if stats.run_id == run_id => stats.platforms.push(platform),
+                _ => history.push(RunStats {
+                    run_id,
+                    finished_at,
+                    platforms: vec![platform],
+                }),
+            }
+        }
+        Ok(history)
+    }
+
+    /// Names of the packages recorded for `target`, or for any target, sorted
+    pub fn package_names(&self, target: Option<&str>) -> Result<Vec<String>> {
+        let mut statement = self
+            .conn
+            .prepare("SELECT DISTINCT filename FROM packages WHERE (?1 IS NULL OR target = ?1)")?;
+        let filenames = statement
+            .query_map(params![target], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        let mut names: Vec<String> = filenames
+            .iter()
+            .map(|filename| suggest::package_name(filename).to_string())
+            .collect();
+        names.sort();
+        names.dedup();
+        Ok(names)
+    }
+
+    /// Compare the packages recorded for `target` with the paths it stores
+    ///
+    /// `stored` holds paths as returned by
+    /// [`Repository::stored_packages`](crate::repository::Repository::stored_packages);
+    /// packages are matched by filename.
+    pub fn drift(&self, target: &str, stored: &[String]) -> Result<Drift> {
+        let recorded = self.history(&HistoryQuery {
+            target: Some(target.to_string()),
+            ..HistoryQuery::default()
+        })?;
+        let stored_names: HashSet<&str> = stored.iter().map(|path| file_name(path)).collect();
+        let recorded_names: HashSet<&str> = recorded.iter().map(|p| p.filename.as_str()).collect();
+
+        // history() lists the latest record of each filename first
+        let mut seen = HashSet::new();
+        let mut missing: Vec<_> = recorded
+            .iter()
+            .filter(|package| seen.insert(package.filename.clone()))
+            .filter(|package| !stored_names.contains(package.filename.as_str()))
+            .cloned()
+            .collect();
+        missing.sort_by_key(MirroredPackage::path);
+
+        let untracked = stored
+            .iter()
+            .filter(|path| !recorded_names.contains(file_name(path)))
+            .cloned()
+            .collect();
+
+        Ok(Drift { missing, untracked })
+    }
+}
+
+/// Print recorded packages as yaml, json or a table
+pub fn print_history(packages: &[MirroredPackage], format: &str) -> Result<()> {
+    match format.to_lowercase().as_str() {
+        "yaml" => println!("{}", serde_yaml::to_string(packages)?),
+        "json" => println!("{}", serde_json::to_string_pretty(packages)?),
+        "table" => {
+            if packages.is_empty() {
+                println!("No mirrored packages recorded.");
+                return Ok(());
+            }
+            let mut table = new_table(&[
+                "Recorded", "Package", "Outcome", "Source", "Target", "sha256",
+            ]);
+            for package in packages {
+                table.add_row(vec![
+                    Cell::new(package.recorded_at.format("%Y-%m-%d %H:%M UTC")),
+                    Cell::new(package.path()),
+                    Cell::new(&package.outcome),
+                    Cell::new(package.origin.as_deref().unwrap_or(&package.source)),
+                    Cell::new(&package.target),
+                    Cell::new(
+                        package
+                            .sha256
+                            .as_deref()
+                            .map_or("", |sha| &sha[..sha.len().min(12)]),
+                    ),
+                ]);
+            }
+            println!("{}", table);
+        }
+        _ => return Err(unsupported_format(format)),
+    }
+    Ok(())
+}
+
+/// Print recorded promotions as yaml, json or a table
+pub fn print_promotions(promotions: &[Promotion], format: &str) -> Result<()> {
+    match format.to_lowercase().as_str() {
+        "yaml" => println!("{}", serde_yaml::to_string(promotions)?),
+        "json" => println!("{}", serde_json::to_string_pretty(promotions)?),
+        "table" => {
+            if promotions.is_empty() {
+                println!("No promotions recorded.");
+                return Ok(());
+            }
+            let mut table = new_table(&[
+                "Promoted",
+                "Package",
+                "Label",
+                "Staging",
+                "Production",
+                "sha256",
+            ]);
+            for promotion in promotions {
+                table.add_row(vec![
+                    Cell::new(promotion.promoted_at.format("%Y-%m-%d %H:%M UTC")),
+                    Cell::new(promotion.path()),
+                    Cell::new(promotion.label.as_deref().unwrap_or("")),
+                    Cell::new(&promotion.staging),
+                    Cell::new(&promotion.production),
+                    Cell::new(
+                        promotion
+                            .sha256
+                            .as_deref()
+                            .map_or("", |sha| &sha[..sha.len().min(12)]),
+                    ),
+                ]);
+            }
+            println!("{}", table);
+        }
+        _ => return Err(unsupported_format(format)),
+    }
+    Ok(())
+}
+
+/// Print run statistics as yaml, json, csv or a table with a column per platform
+pub fn print_stats(stats: &[RunStats], format: &str) -> Result<()> {
+    match format.to_lowercase().as_str() {
+        "yaml" => println!("{}", serde_yaml::to_string(stats)?),
+        "json" => println!("{}", serde_json::to_string_pretty(stats)?),
+        "csv" => {
+            println!("run_id,finished_at,platform,packages,size");
+            for run in stats {
+                for platform in &run.platforms {
+                    println!(
+                        "{},{},{},{},{}",
+                        run.run_id,
+                        run.finished_at.to_rfc3339(),
+                        platform.platform,
+                        platform.packages,
+                        platform.size
+                    );
+                }
+            }
+        }
+        "table" => {
+            if stats.is_empty() {
+                println!("No run statistics recorded.");
+                return Ok(());
+            }
+            let platforms: BTreeSet<&str> = stats
+                .iter()
+                .flat_map(|run| run.platforms.iter().map(|p| p.platform.as_str()))
+                .collect();
+            let mut header = vec!["Finished", "Run", "Packages", "Size"];
+            header.extend(&platforms);
+            let mut table = new_table(&header);
+            for run in stats {
+                let mut row = vec![
+                    Cell::new(run.finished_at.format("%Y-%m-%d %H:%M UTC")),
+                    Cell::new(run.run_id),
+                    Cell::new(run.packages()),
+                    Cell::new(run.size()),
+                ];
+                row.extend(platforms.iter().map(|platform| {
+                    Cell::new(
+                        run.platforms
+                            .iter()
+                            .find(|p| p.platform == *platform)
+                            .map_or(0, |p| p.packages),
+                    )
+                }));
+                table.add_row(row);
+            }
+            println!("{}", table);
+        }
+        _ => return Err(unsupported_format(format)),
+    }
+    Ok(())
+}
+
+/// Print a drift report as yaml, json or a table
+pub fn print_drift(drift: &Drift, format: &str) -> Result<()> {
+    match format.to_lowercase().as_str() {
+        "yaml" => println!("{}", serde_yaml::to_string(drift)?),
+        "json" => println!("{}", serde_json::to_string_pretty(drift)?),
+        "table" => {
+            if drift.is_empty() {
+                println!("No drift: the target holds exactly the recorded packages.");
+                return Ok(());
+            }
+            let mut table = new_table(&["Package", "Drift", "Last recorded"]);
+            for package in &drift.missing {
+                table.add_row(vec![
+                    Cell::new(package.path()),
+                    Cell::new("missing from target"),
+                    Cell::new(package.recorded_at.format("%Y-%m-%d %H:%M UTC")),
+                ]);
+            }
+            for path in &drift.untracked {
+                table.add_row(vec![
+                    Cell::new(path),
+                    Cell::new("not recorded"),
+                    Cell::new(""),
+                ]);
+            }
+            println!("{}", table);
+        }
+        _ => return Err(unsupported_format(format)),
+    }
+    Ok(())
+}
+
+fn new_table(header: &[&str]) -> Table {
+    let mut table = Table::new();
+    table
+        .load_preset(NOTHING)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(
+            header
+                .iter()
+                .map(|title| Cell::new(title).add_attribute(Attribute::Bold)),
+        );
+    table
+}
+
+fn unsupported_format(format: &str) -> MirrorError {
+    MirrorError::InvalidInput(format!(
+        "Unsupported output format: {}. Supported formats: yaml, json, table",
+        format
+    ))
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::SkipCode;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    fn report(source: &str, packages: &[(&str, PackageOutcome, Option<&str>)]) -> MirrorReport {
+        let mut report = MirrorReport::new(source, "./repo");
+        for (filename, outcome, sha256) in packages {
+            let package = report.record(*filename, outcome.clone(), 10, Duration::ZERO);
+            package.platform = Some("noarch".to_string());
+            package.sha256 = sha256.map(str::to_string);
+        }
+        report
+    }
+
+    #[test]
+    fn test_record_and_query_history() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("state.db");
+        let mut db = StateDb::open(&path).unwrap();
+
+        let mut first = report(
+            "owner/repo",
+            &[
+                ("a-1.0-0.conda", PackageOutcome::Mirrored, Some("aa")),
+                (
+                    "b-1.0-0.conda",
+                    PackageOutcome::Failed {
+                        error: "boom".to_string(),
+                    },
+                    None,
+                ),
+            ],
+        );
+        first.packages[0].provenance = Some(serde_json::json!({ "_type": "statement" }));
+        let run_id = db.record_report(&first).unwrap();
+
+        // Reopening keeps the records
+        let mut db = StateDb::open(&path).unwrap();
+        let mut second = report(
+            "owner/other",
+            &[(
+                "c-1.0-0.conda",
+                PackageOutcome::Skipped {
+                    code: SkipCode::AlreadyPresent,
+                    reason: "identical copy already present at the target".to_string(),
+                },
+                Some("cc"),
+            )],
+        );
+        second.packages[0].origin = Some("owner/other#42".to_string());
+        db.record_report(&second).unwrap();
+
+        assert!(db.is_mirrored("./repo", "a-1.0-0.conda").unwrap());
+        assert!(!db.is_mirrored("./repo", "b-1.0-0.conda").unwrap());
+        assert!(!db.is_mirrored("./elsewhere", "a-1.0-0.conda").unwrap());
+        assert!(db.last_run("owner/repo", "./repo").unwrap().is_some());
+        assert!(db.last_run("owner/repo", "./elsewhere").unwrap().is_none());
+
+        let all = db.history(&HistoryQuery::default()).unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].filename, "c-1.0-0.conda");
+        assert_eq!(all[0].outcome, "present");
+        assert_eq!(all[0].origin.as_deref(), Some("owner/other#42"));
+        assert_eq!(all[1].run_id, run_id);
+        assert_eq!(all[1].sha256.as_deref(), Some("aa"));
+        assert_eq!(all[1].path(), "noarch/a-1.0-0.conda");
+        assert_eq!(all[1].provenance.as_ref().unwrap()["_type"], "statement");
+        assert!(all[0].provenance.is_none());
+
+        let filtered = db
+            .history(&HistoryQuery {
+                source: Some("owner/repo".to_string()),
+                package: Some("a-1".to_string()),
+                ..HistoryQuery::default()
+            })
+            .unwrap();
+        assert_eq!(filtered.len(), 1);
+        let limited = db
+            .history(&HistoryQuery {
+                limit: Some(1),
+                ..HistoryQuery::default()
+            })
+            .unwrap();
+        assert_eq!(limited.len(), 1);
+
+        assert_eq!(db.package_names(None).unwrap(), vec!["a", "c"]);
+        assert!(db.package_names(Some("./elsewhere")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_drift() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut db = StateDb::open(temp_dir.path().join("state.db")).unwrap();
+        db.record_report(&report(
+            "owner/repo",
+            &[
+                ("a-1.0-0.conda", PackageOutcome::Mirrored, Some("aa")),
+                ("b-1.0-0.conda", PackageOutcome::Mirrored, Some("bb")),
+            ],
+        ))
+        .unwrap();
+
+        let drift = db
+            .drift(
+                "./repo",
+                &[
+                    "noarch/a-1.0-0.conda".to_string(),
+                    "linux-64/x-1.0-0.conda".to_string(),
+                ],
+            )
+            .unwrap();
+        assert_eq!(drift.missing.len(), 1);
+        assert_eq!(drift.missing[0].filename, "b-1.0-0.conda");
+        assert_eq!(drift.untracked, vec!["linux-64/x-1.0-0.conda"]);
+        assert!(!drift.is_empty());
+    }
+
+    #[test]
+    fn test_run_stats_history() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut db = StateDb::open(temp_dir.path().join("state.db")).unwrap();
+        let first = db
+            .record_report(&report(
+                "owner/repo",
+                &[("a-1.0-0.conda", PackageOutcome::Mirrored, Some("aa"))],
+            ))
+            .unwrap();
+        db.record_stats(first, "./repo", &["noarch/a-1.0-0.conda".to_string()])
+            .unwrap();
+        let second = db
+            .record_report(&report(
+                "owner/repo",
+                &[("b-1.0-0.conda", PackageOutcome::Mirrored, Some("bb"))],
+            ))
+            .unwrap();
+        db.record_stats(
+            second,
+            "./repo",
+            &[
+                "noarch/a-1.0-0.conda".to_string(),
+                "noarch/b-1.0-0.conda".to_string(),
+                "linux-64/x-1.0-0.conda".to_string(),
+            ],
+        )
+        .unwrap();
+
+        let history = db.stats_history("./repo").unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].run_id, first);
+        assert_eq!((history[0].packages(), history[0].size()), (1, 10));
+        assert_eq!(
+            history[1].platforms,
+            vec![
+                PlatformStats {
+                    platform: "linux-64".to_string(),
+                    packages: 1,
+                    size: 0,
+                },
+                PlatformStats {
+                    platform: "noarch".to_string(),
+                    packages: 2,
+                    size: 20,
+                },
+            ]
+        );
+        assert!(db.stats_history("./elsewhere").unwrap().is_empty());
+        assert!(print_stats(&history, "csv").is_ok());
+        assert!(print_stats(&history, "xml").is_err());
+    }
+
+    #[test]
+    fn test_record_and_query_promotions() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut db = StateDb::open(temp_dir.path().join("state.db")).unwrap();
+
+        let promotion = report(
+            "./staging",
+            &[
+                ("a-1.0-0.conda", PackageOutcome::Mirrored, Some("aa")),
+                (
+                    "b-1.0-0.conda",
+                    PackageOutcome::Failed {
+                        error: "boom".to_string(),
+                    },
+                    Some("bb"),
+                ),
+            ],
+        );
+        assert_eq!(db.record_promotion(&promotion, Some("stable")).unwrap(), 1);
+        // Promotions are not mirror runs
+        assert!(db.history(&HistoryQuery::default()).unwrap().is_empty());
+
+        let promotions = db
+            .promotions(&HistoryQuery {
+                target: Some("./repo".to_string()),
+                ..HistoryQuery::default()
+            })
+            .unwrap();
+        assert_eq!(promotions.len(), 1);
+        assert_eq!(promotions[0].staging, "./staging");
+        assert_eq!(promotions[0].label.as_deref(), Some("stable"));
+        assert_eq!(promotions[0].path(), "noarch/a-1.0-0.conda");
+        assert!(db
+            .promotions(&HistoryQuery {
+                source: Some("./elsewhere".to_string()),
+                ..HistoryQuery::default()
+            })
+            .unwrap()
+            .is_empty());
+    }
+}
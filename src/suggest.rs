@@ -0,0 +1,117 @@
+//! "Did you mean" suggestions for names that match nothing
+//!
+//! A misspelt package or artifact name, e.g. `rb-asciidocgtor-revealjs`, simply
+//! finds nothing. Commands that filter by name therefore look for known names
+//! within a small edit distance of the one given and offer them alongside the
+//! empty result.
+
+/// Most suggestions offered at once
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Levenshtein distance between two strings, counted in characters
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Package name of a filename such as `linux-64/numpy-1.26.0-py312_0.conda`
+///
+/// Anything that is not a conda package filename is returned unchanged.
+pub fn package_name(filename: &str) -> &str {
+    let basename = filename.rsplit('/').next().unwrap_or(filename);
+    basename
+        .strip_suffix(".conda")
+        .or_else(|| basename.strip_suffix(".tar.bz2"))
+        .and_then(|stem| stem.rsplitn(3, '-').nth(2))
+        .unwrap_or(filename)
+}
+
+/// Candidates close enough to `query` to be what was meant, closest first
+///
+/// Case is ignored when measuring, so `NumPy` suggests `numpy`.
+pub fn similar<'a>(query: &str, candidates: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let query_lower = query.to_lowercase();
+    let max_distance = (query.chars().count() / 4).clamp(1, 3);
+    let mut scored: Vec<(usize, &str)> = candidates
+        .into_iter()
+        .filter(|candidate| *candidate != query)
+        .map(|candidate| {
+            let distance = edit_distance(&query_lower, &candidate.to_lowercase());
+            (distance, candidate)
+        })
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect();
+    scored.sort();
+    scored.dedup();
+    scored
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, candidate)| candidate.to_string())
+        .collect()
+}
+
+/// `did you mean ...?` naming the suggestions, `None` when there are none
+pub fn did_you_mean(suggestions: &[String]) -> Option<String> {
+    match suggestions {
+        [] => None,
+        [only] => Some(format!("did you mean {}?", only)),
+        [init @ .., last] => Some(format!("did you mean {} or {}?", init.join(", "), last)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(
+            edit_distance("rb-asciidocgtor-revealjs", "rb-asciidoctor-revealjs"),
+            1
+        );
+    }
+
+    #[test]
+    fn test_package_name() {
+        assert_eq!(
+            package_name("noarch/rb-asciidoctor-revealjs-5.2.0-h1d6dcf3_0.conda"),
+            "rb-asciidoctor-revealjs"
+        );
+        assert_eq!(package_name("numpy-1.26.0-py312_0.tar.bz2"), "numpy");
+        assert_eq!(package_name("conda-packages"), "conda-packages");
+    }
+
+    #[test]
+    fn test_similar_and_did_you_mean() {
+        let names = [
+            "rb-asciidoctor-revealjs",
+            "rb-asciidoctor",
+            "rb-asciidoctor-revealjs",
+            "python",
+        ];
+        let suggestions = similar("rb-asciidocgtor-revealjs", names);
+        assert_eq!(suggestions, vec!["rb-asciidoctor-revealjs"]);
+        assert_eq!(
+            did_you_mean(&suggestions).as_deref(),
+            Some("did you mean rb-asciidoctor-revealjs?")
+        );
+        assert_eq!(similar("Python", names), vec!["python"]);
+        assert!(similar("numpy", names).is_empty());
+        assert_eq!(did_you_mean(&[]), None);
+        assert_eq!(
+            did_you_mean(&["a".to_string(), "b".to_string(), "c".to_string()]).as_deref(),
+            Some("did you mean a, b or c?")
+        );
+    }
+}
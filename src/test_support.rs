@@ -0,0 +1,281 @@
+//! Minimal but valid conda packages for tests
+//!
+//! [`PackageFixture`] builds real `.conda` archives (ZIP with zstd-compressed
+//! `info-*.tar.zst` and `pkg-*.tar.zst` members) and legacy `.tar.bz2` archives,
+//! both carrying `info/index.json` and `info/paths.json`:
+//!
+//! ```rust
+//! use meso_forge_mirror::test_support::PackageFixture;
+//!
+//! let fixture = PackageFixture::new("numpy", "1.26.0").subdir("linux-64");
+//! let content = fixture.to_conda();
+//! assert_eq!(fixture.conda_filename(), "numpy-1.26.0-0.conda");
+//! # assert!(!content.is_empty());
+//! ```
+//!
+//! Only available with the `test-util` feature.
+
+use bytes::Bytes;
+use sha2::{Digest, Sha256};
+use std::io::Write;
+
+/// Description of a package to generate
+#[derive(Debug, Clone)]
+pub struct PackageFixture {
+    pub name: String,
+    pub version: String,
+    pub build: String,
+    pub build_number: u64,
+    pub subdir: String,
+    pub depends: Vec<String>,
+    pub license: Option<String>,
+    pub license_family: Option<String>,
+}
+
+impl PackageFixture {
+    /// A `noarch` package with build string `0` and no dependencies
+    pub fn new(name: impl Into<String>, version: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            version: version.into(),
+            build: "0".to_string(),
+            build_number: 0,
+            subdir: "noarch".to_string(),
+            depends: Vec::new(),
+            license: None,
+            license_family: None,
+        }
+    }
+
+    /// A `noarch` package matching a filename such as `numpy-1.26.0-py312_0.conda`
+    pub fn from_filename(filename: &str) -> Option<Self> {
+        let stem = filename
+            .strip_suffix(".conda")
+            .or_else(|| filename.strip_suffix(".tar.bz2"))?;
+        let mut parts = stem.rsplitn(3, '-');
+        let build = parts.next()?;
+        let version = parts.next()?;
+        let name = parts.next().filter(|name| !name.is_empty())?;
+        Some(Self::new(name, version).build(build))
+    }
+
+    pub fn build(mut self, build: impl Into<String>) -> Self {
+        self.build = build.into();
+        self
+    }
+
+    pub fn build_number(mut self, build_number: u64) -> Self {
+        self.build_number = build_number;
+        self
+    }
+
+    pub fn subdir(mut self, subdir: impl Into<String>) -> Self {
+        self.subdir = subdir.into();
+        self
+    }
+
+    pub fn depends<I, S>(mut self, depends: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.depends = depends.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn license(mut self, license: impl Into<String>) -> Self {
+        self.license = Some(license.into());
+        self
+    }
+
+    pub fn license_family(mut self, license_family: impl Into<String>) -> Self {
+        self.license_family = Some(license_family.into());
+        self
+    }
+
+    /// `name-version-build`, the filename without extension
+    pub fn stem(&self) -> String {
+        format!("{}-{}-{}", self.name, self.version, self.build)
+    }
+
+    pub fn conda_filename(&self) -> String {
+        format!("{}.conda", self.stem())
+    }
+
+    pub fn tar_bz2_filename(&self) -> String {
+        format!("{}.tar.bz2", self.stem())
+    }
+
+    /// Contents of `info/index.json`
+    pub fn index_json(&self) -> serde_json::Value {
+        let mut index = serde_json::json!({
+            "name": self.name,
+            "version": self.version,
+            "build": self.build,
+            "build_number": self.build_number,
+            "depends": self.depends,
+            "subdir": self.subdir,
+            "timestamp": 1_700_000_000_000u64,
+        });
+        if let Some(license) = &self.license {
+            index["license"] = serde_json::Value::from(license.as_str());
+        }
+        if let Some(license_family) = &self.license_family {
+            index["license_family"] = serde_json::Value::from(license_family.as_str());
+        }
+        if self.subdir == "noarch" {
+            index["noarch"] = serde_json::Value::from("generic");
+        }
+        index
+    }
+
+    /// A `.conda` package
+    pub fn to_conda(&self) -> Bytes {
+        let stem = self.stem();
+        let info = zstd::stream::encode_all(&self.tarball(&self.info_files())[..], 0)
+            .expect("zstd compression of an in-memory buffer");
+        let pkg = zstd::stream::encode_all(&self.tarball(&self.payload_files())[..], 0)
+            .expect("zstd compression of an in-memory buffer");
+
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored);
+        let members = [
+            (
+                "metadata.json".to_string(),
+                br#"{"conda_pkg_format_version": 2}"#.to_vec(),
+            ),
+            (format!("pkg-{}.tar.zst", stem), pkg),
+            (format!("info-{}.tar.zst", stem), info),
+        ];
+        for (name, content) in members {
+            writer.start_file(name, options).expect("zip member");
+            writer.write_all(&content).expect("zip member content");
+        }
+        Bytes::from(writer.finish().expect("zip archive").into_inner())
+    }
+
+    /// A legacy `.tar.bz2` package
+    pub fn to_tar_bz2(&self) -> Bytes {
+        let mut files = self.info_files();
+        files.extend(self.payload_files());
+
+        let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+        encoder
+            .write_all(&self.tarball(&files))
+            .expect("bzip2 compression of an in-memory buffer");
+        Bytes::from(encoder.finish().expect("bzip2 stream"))
+    }
+
+    fn payload_files(&self) -> Vec<(String, Vec<u8>)> {
+        vec![(
+            format!("share/{}/README", self.name),
+            format!("{} {}\n", self.name, self.version).into_bytes(),
+        )]
+    }
+
+    fn info_files(&self) -> Vec<(String, Vec<u8>)> {
+        let paths: Vec<_> = self
+            .payload_files()
+            .iter()
+            .map(|(path, content)| {
+                serde_json::json!({
+                    "_path": path,
+                    "path_type": "hardlink",
+                    "sha256": format!("{:x}", Sha256::digest(content)),
+                    "size_in_bytes": content.len(),
+                })
+            })
+            .collect();
+        let paths_json = serde_json::json!({ "paths": paths, "paths_version": 1 });
+
+        vec![
+            (
+                "info/index.json".to_string(),
+                self.index_json().to_string().into_bytes(),
+            ),
+            (
+                "info/paths.json".to_string(),
+                paths_json.to_string().into_bytes(),
+            ),
+        ]
+    }
+
+    fn tarball(&self, files: &[(String, Vec<u8>)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_mtime(1_700_000_000);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, path, content.as_slice())
+                .expect("tar entry");
+        }
+        builder.into_inner().expect("tar archive")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conda_package::CondaPackageHandler;
+    use rattler_conda_types::Platform;
+
+    #[tokio::test]
+    async fn test_fixtures_are_read_from_their_metadata() {
+        let fixture = PackageFixture::new("fixture", "2.0")
+            .build("h123_1")
+            .build_number(1)
+            .subdir("linux-64")
+            .depends(["python >=3.10"])
+            .license("MIT");
+
+        let mut handler = CondaPackageHandler::new();
+        for (filename, content) in [
+            (fixture.conda_filename(), fixture.to_conda()),
+            (fixture.tar_bz2_filename(), fixture.to_tar_bz2()),
+        ] {
+            let processed = handler.process_package(content, &filename).await.unwrap();
+            assert_eq!(processed.platform, Platform::Linux64);
+            assert_eq!(processed.metadata.build_number, 1);
+            assert_eq!(processed.metadata.depends, vec!["python >=3.10"]);
+            assert_eq!(processed.metadata.license.as_deref(), Some("MIT"));
+        }
+    }
+
+    #[test]
+    fn test_from_filename() {
+        let fixture =
+            PackageFixture::from_filename("rb-asciidoctor-revealjs-5.2.0-h1d6dcf3_0.conda")
+                .unwrap();
+        assert_eq!(fixture.name, "rb-asciidoctor-revealjs");
+        assert_eq!(fixture.version, "5.2.0");
+        assert_eq!(fixture.build, "h1d6dcf3_0");
+        assert_eq!(
+            fixture.tar_bz2_filename(),
+            "rb-asciidoctor-revealjs-5.2.0-h1d6dcf3_0.tar.bz2"
+        );
+        assert!(PackageFixture::from_filename("pkg-1.0.zip").is_none());
+        assert!(PackageFixture::from_filename("1.0-0.conda").is_none());
+    }
+
+    #[test]
+    fn test_fixtures_are_extractable_by_rattler() {
+        use rattler_package_streaming::read::{extract_conda_via_streaming, extract_tar_bz2};
+
+        let fixture = PackageFixture::new("fixture", "1.0");
+
+        let conda_dir = tempfile::TempDir::new().unwrap();
+        extract_conda_via_streaming(&fixture.to_conda()[..], conda_dir.path()).unwrap();
+        let tar_bz2_dir = tempfile::TempDir::new().unwrap();
+        extract_tar_bz2(&fixture.to_tar_bz2()[..], tar_bz2_dir.path()).unwrap();
+
+        for dir in [conda_dir.path(), tar_bz2_dir.path()] {
+            assert!(dir.join("info/index.json").exists());
+            assert!(dir.join("info/paths.json").exists());
+            assert!(dir.join("share/fixture/README").exists());
+        }
+    }
+}
@@ -0,0 +1,266 @@
+//! Local HTTP server replaying recorded responses
+//!
+//! [`MockServer`] lets the GitHub, Azure DevOps and channel logic be tested
+//! without credentials or network access. Point a client at [`MockServer::url`]
+//! (see [`GitHubClient::with_api_base`](crate::github::GitHubClient::with_api_base))
+//! and register the responses it should receive:
+//!
+//! ```rust,no_run
+//! use meso_forge_mirror::test_util::{MockResponse, MockServer};
+//!
+//! # async fn run() -> std::io::Result<()> {
+//! let server = MockServer::start().await?;
+//! server.mock(
+//!     "GET",
+//!     "/repos/owner/repo/actions/artifacts",
+//!     MockResponse::json(200, r#"{"total_count": 0, "artifacts": []}"#),
+//! );
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Only available with the `test-util` feature.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Upper bound on the size of a request head the server accepts
+const MAX_HEAD_SIZE: usize = 64 * 1024;
+
+/// A response replayed by [`MockServer`]
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl MockResponse {
+    pub fn new(status: u16, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: body.into(),
+        }
+    }
+
+    /// A response with a JSON body
+    pub fn json(status: u16, body: impl Into<Vec<u8>>) -> Self {
+        Self::new(status, body).with_header("Content-Type", "application/json")
+    }
+
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+}
+
+/// A request received by [`MockServer`]
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: String,
+    /// Path including the query string
+    pub path: String,
+    /// Header names are lowercase
+    pub headers: HashMap<String, String>,
+}
+
+#[derive(Default)]
+struct State {
+    mocks: Vec<(String, String, MockResponse)>,
+    requests: Vec<RecordedRequest>,
+}
+
+impl State {
+    /// The most recently registered mock matching the request
+    ///
+    /// A mock path without a query string matches any query.
+    fn response_for(&self, method: &str, path: &str) -> MockResponse {
+        let without_query = path.split('?').next().unwrap_or(path);
+        self.mocks
+            .iter()
+            .rev()
+            .find(|(mock_method, mock_path, _)| {
+                mock_method.eq_ignore_ascii_case(method)
+                    && (mock_path == path
+                        || (!mock_path.contains('?') && mock_path == without_query))
+            })
+            .map(|(_, _, response)| response.clone())
+            .unwrap_or_else(|| MockResponse::new(404, format!("No mock for {} {}", method, path)))
+    }
+}
+
+/// HTTP server on a local port answering with registered [`MockResponse`]s
+///
+/// Requests without a matching mock get a 404. The server stops when dropped.
+pub struct MockServer {
+    url: String,
+    state: Arc<Mutex<State>>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl MockServer {
+    pub async fn start() -> std::io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}", listener.local_addr()?);
+        let state = Arc::new(Mutex::new(State::default()));
+
+        let accept_state = Arc::clone(&state);
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let state = Arc::clone(&accept_state);
+                tokio::spawn(async move {
+                    let _ = serve(stream, state).await;
+                });
+            }
+        });
+
+        Ok(Self { url, state, task })
+    }
+
+    /// Base URL of the server, e.g. `http://127.0.0.1:41234`
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Answer `method` requests for `path` with `response`
+    ///
+    /// Later registrations take precedence over earlier ones for the same request.
+    pub fn mock(&self, method: &str, path: &str, response: MockResponse) {
+        self.state
+            .lock()
+            .unwrap()
+            .mocks
+            .push((method.to_string(), path.to_string(), response));
+    }
+
+    /// Requests received so far, in order
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Answer a single request and close the connection
+async fn serve(mut stream: TcpStream, state: Arc<Mutex<State>>) -> std::io::Result<()> {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 4096];
+    let head_end = loop {
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            return Ok(());
+        }
+        head.extend_from_slice(&buf[..read]);
+        if let Some(pos) = head.windows(4).position(|window| window == b"\r\n\r\n") {
+            break pos;
+        }
+        if head.len() > MAX_HEAD_SIZE {
+            return Ok(());
+        }
+    };
+
+    let text = String::from_utf8_lossy(&head[..head_end]).to_string();
+    let mut lines = text.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_string();
+    let path = request_line.next().unwrap_or_default().to_string();
+    let headers: HashMap<String, String> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_string()))
+        .collect();
+
+    // Drain the request body so the client is not cut off while still sending
+    let content_length = headers
+        .get("content-length")
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(0);
+    let mut received = head.len() - (head_end + 4);
+    while received < content_length {
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+        received += read;
+    }
+
+    let response = {
+        let mut state = state.lock().unwrap();
+        let response = state.response_for(&method, &path);
+        state.requests.push(RecordedRequest {
+            method,
+            path,
+            headers,
+        });
+        response
+    };
+
+    let mut out = format!(
+        "HTTP/1.1 {} {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        reqwest::StatusCode::from_u16(response.status)
+            .ok()
+            .and_then(|status| status.canonical_reason())
+            .unwrap_or(""),
+        response.body.len()
+    );
+    for (name, value) in &response.headers {
+        out.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    out.push_str("\r\n");
+
+    stream.write_all(out.as_bytes()).await?;
+    stream.write_all(&response.body).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_mock_server_replays_and_records() {
+        let server = MockServer::start().await.unwrap();
+        server.mock("GET", "/items", MockResponse::json(200, r#"{"ok":true}"#));
+        server.mock("GET", "/items?page=2", MockResponse::new(500, "boom"));
+
+        let client = reqwest::Client::new();
+        let response = client
+            .get(format!("{}/items?page=1", server.url()))
+            .header("Authorization", "Bearer token")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.text().await.unwrap(), r#"{"ok":true}"#);
+
+        let response = client
+            .get(format!("{}/items?page=2", server.url()))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 500);
+
+        let response = client
+            .get(format!("{}/missing", server.url()))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 404);
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0].method, "GET");
+        assert_eq!(requests[0].path, "/items?page=1");
+        assert_eq!(
+            requests[0].headers.get("authorization").map(String::as_str),
+            Some("Bearer token")
+        );
+    }
+}
@@ -0,0 +1,340 @@
+//! Change detection for upstream archives and packages
+//!
+//! A cron job mirroring the same `url`, `zip-url` or `tgz-url` source every few
+//! minutes mostly finds nothing new. With `upstream_state_file` set, a run
+//! first asks the source for its `ETag` and `Last-Modified` with a `HEAD`
+//! request, or reads the modification time of a local file, and compares them
+//! with those of the last run that mirrored the source into the same target
+//! without failures. When they match, the run ends before anything is
+//! downloaded. Sources that report neither are always mirrored.
+//!
+//! A `channel` source is checked through the `repodata.json` of each of its
+//! subdirs, and the run ends early only when none of them changed.
+
+use chrono::{DateTime, Utc};
+use rattler_conda_types::Platform;
+use reqwest::header::{HeaderMap, ETAG, LAST_MODIFIED};
+use reqwest::{Client, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tracing::{debug, warn};
+
+use crate::auth;
+use crate::error::{MirrorError, Result};
+use crate::politeness;
+use crate::sbom::local_subdirs;
+
+/// What identifies the version of an upstream source
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Validators {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    /// `Last-Modified` of a URL, or the modification time of a local file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    /// Validators of an HTTP response, if it carries any
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let header = |name| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let validators = Self {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        };
+        (validators.etag.is_some() || validators.last_modified.is_some()).then_some(validators)
+    }
+
+    /// Short description for logs and skip reasons
+    pub fn describe(&self) -> String {
+        match (&self.etag, &self.last_modified) {
+            (Some(etag), _) => format!("ETag {}", etag),
+            (None, Some(last_modified)) => format!("last modified {}", last_modified),
+            (None, None) => "no validators".to_string(),
+        }
+    }
+}
+
+/// Validators recorded for one source and target
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UpstreamEntry {
+    pub source: String,
+    pub target: String,
+    #[serde(flatten)]
+    pub validators: Validators,
+    pub mirrored_at: DateTime<Utc>,
+}
+
+/// Validators of the sources mirrored so far
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UpstreamState {
+    #[serde(default)]
+    pub entries: Vec<UpstreamEntry>,
+}
+
+impl UpstreamState {
+    /// Read the state file; a missing file holds no entries
+    pub fn load(path: &str) -> Result<Self> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e.into()),
+        };
+        serde_json::from_str(&content).map_err(|e| {
+            MirrorError::InvalidInput(format!("Invalid upstream state file '{}': {}", path, e))
+        })
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        if let Some(parent) = Path::new(path).parent() {
+            if !parent.as_os_str().is_empty() {
+                std::fs::create_dir_all(parent)?;
+            }
+        }
+        std::fs::write(path, content)?;
+        Ok(())
+    }
+
+    /// Whether `source` was last mirrored into `target` with the same validators
+    pub fn is_unchanged(&self, source: &str, target: &str, validators: &Validators) -> bool {
+        self.entries.iter().any(|entry| {
+            entry.source == source && entry.target == target && &entry.validators == validators
+        })
+    }
+
+    /// Record the validators of a run that mirrored `source` into `target`
+    pub fn record(
+        &mut self,
+        source: &str,
+        target: &str,
+        validators: Validators,
+        mirrored_at: DateTime<Utc>,
+    ) {
+        self.entries
+            .retain(|entry| entry.source != source || entry.target != target);
+        self.entries.push(UpstreamEntry {
+            source: source.to_string(),
+            target: target.to_string(),
+            validators,
+            mirrored_at,
+        });
+    }
+}
+
+/// Current validators of a source, or `None` if it has none to offer
+///
+/// A `HEAD` request that fails is only logged, so that the run goes ahead.
+pub async fn probe(client: &Client, source: &str, is_local_file: bool) -> Option<Validators> {
+    if is_local_file {
+        let modified = std::fs::metadata(source).and_then(|m| m.modified()).ok()?;
+        return Some(Validators {
+            etag: None,
+            last_modified: Some(DateTime::<Utc>::from(modified).to_rfc3339()),
+        });
+    }
+    if !source.starts_with("http://") && !source.starts_with("https://") {
+        return None;
+    }
+
+    let response = head(client, source).await?;
+    if !response.status().is_success() {
+        debug!(
+            "HEAD {} answered HTTP {}, mirroring without change detection",
+            source,
+            response.status()
+        );
+        return None;
+    }
+    Validators::from_headers(response.headers())
+}
+
+/// Current validators of the `repodata.json` of each subdir of a channel, keyed by its URL or path
+///
+/// Subdirs without a `repodata.json` are left out, every subdir when `subdirs`
+/// is empty. Returns `None` when a `repodata.json` offers no validators, as the
+/// channel cannot be checked for changes then.
+pub async fn probe_channel(
+    client: &Client,
+    channel: &str,
+    subdirs: &[String],
+) -> Option<Vec<(String, Validators)>> {
+    let channel = channel.trim_end_matches('/');
+    let remote = channel.starts_with("http://") || channel.starts_with("https://");
+    let subdirs = match (subdirs.is_empty(), remote) {
+        (false, _) => subdirs.to_vec(),
+        (true, false) => local_subdirs(Path::new(channel)).ok()?,
+        (true, true) => Platform::all().map(|p| p.to_string()).collect(),
+    };
+
+    let mut repodata = Vec::new();
+    for subdir in &subdirs {
+        let source = format!("{}/{}/repodata.json", channel, subdir);
+        let validators = if remote {
+            let response = head(client, &source).await?;
+            if response.status() == StatusCode::NOT_FOUND {
+                continue;
+            }
+            if !response.status().is_success() {
+                debug!(
+                    "HEAD {} answered HTTP {}, mirroring {} without change detection",
+                    source,
+                    response.status(),
+                    channel
+                );
+                return None;
+            }
+            Validators::from_headers(response.headers())?
+        } else {
+            if !Path::new(&source).is_file() {
+                continue;
+            }
+            probe(client, &source, true).await?
+        };
+        repodata.push((source, validators));
+    }
+    Some(repodata)
+}
+
+/// Send a `HEAD` request, logging a failure to send it
+async fn head(client: &Client, source: &str) -> Option<Response> {
+    politeness::throttled(source, || auth::head(client, source).send())
+        .await
+        .map_err(|e| {
+            warn!(
+                "Could not check {} for changes, mirroring it anyway: {}",
+                source, e
+            )
+        })
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{MockResponse, MockServer};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_state_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("upstream.json");
+        let path = path.to_str().unwrap();
+        let validators = Validators {
+            etag: Some("\"v1\"".to_string()),
+            last_modified: None,
+        };
+
+        let mut state = UpstreamState::load(path).unwrap();
+        assert!(!state.is_unchanged("https://example.org/a.zip", "./repo", &validators));
+        state.record(
+            "https://example.org/a.zip",
+            "./repo",
+            validators.clone(),
+            Utc::now(),
+        );
+        state.save(path).unwrap();
+
+        let mut state = UpstreamState::load(path).unwrap();
+        assert!(state.is_unchanged("https://example.org/a.zip", "./repo", &validators));
+        assert!(!state.is_unchanged("https://example.org/a.zip", "./other", &validators));
+        let changed = Validators {
+            etag: Some("\"v2\"".to_string()),
+            last_modified: None,
+        };
+        assert!(!state.is_unchanged("https://example.org/a.zip", "./repo", &changed));
+
+        state.record(
+            "https://example.org/a.zip",
+            "./repo",
+            changed.clone(),
+            Utc::now(),
+        );
+        assert_eq!(state.entries.len(), 1);
+        assert!(state.is_unchanged("https://example.org/a.zip", "./repo", &changed));
+    }
+
+    #[tokio::test]
+    async fn test_probe() {
+        let server = MockServer::start().await.unwrap();
+        server.mock(
+            "HEAD",
+            "/a.zip",
+            MockResponse::new(200, "")
+                .with_header("ETag", "\"v1\"")
+                .with_header("Last-Modified", "Wed, 01 May 2024 12:00:00 GMT"),
+        );
+        server.mock("HEAD", "/plain.zip", MockResponse::new(200, ""));
+        let client = Client::new();
+
+        let validators = probe(&client, &format!("{}/a.zip", server.url()), false)
+            .await
+            .unwrap();
+        assert_eq!(validators.etag.as_deref(), Some("\"v1\""));
+        assert_eq!(validators.describe(), "ETag \"v1\"");
+        assert!(
+            probe(&client, &format!("{}/plain.zip", server.url()), false)
+                .await
+                .is_none()
+        );
+        assert!(
+            probe(&client, &format!("{}/missing.zip", server.url()), false)
+                .await
+                .is_none()
+        );
+
+        let temp_dir = TempDir::new().unwrap();
+        let file = temp_dir.path().join("a.zip");
+        std::fs::write(&file, b"zip").unwrap();
+        let local = probe(&client, file.to_str().unwrap(), true).await.unwrap();
+        assert!(local.last_modified.is_some());
+        assert!(probe(&client, "missing.zip", true).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_probe_channel() {
+        let server = MockServer::start().await.unwrap();
+        server.mock(
+            "HEAD",
+            "/channel/linux-64/repodata.json",
+            MockResponse::new(200, "").with_header("ETag", "\"linux\""),
+        );
+        server.mock(
+            "HEAD",
+            "/plain/noarch/repodata.json",
+            MockResponse::new(200, ""),
+        );
+        let client = Client::new();
+        let subdirs = ["linux-64".to_string(), "noarch".to_string()];
+
+        // The channel has no noarch subdir
+        let channel = format!("{}/channel/", server.url());
+        let repodata = probe_channel(&client, &channel, &subdirs).await.unwrap();
+        assert_eq!(repodata.len(), 1);
+        assert_eq!(
+            repodata[0].0,
+            format!("{}/channel/linux-64/repodata.json", server.url())
+        );
+        assert_eq!(repodata[0].1.etag.as_deref(), Some("\"linux\""));
+
+        // A repodata.json without validators leaves the channel unchecked
+        let plain = format!("{}/plain", server.url());
+        assert!(probe_channel(&client, &plain, &subdirs).await.is_none());
+
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(temp_dir.path().join("noarch")).unwrap();
+        std::fs::write(temp_dir.path().join("noarch/repodata.json"), b"{}").unwrap();
+        std::fs::create_dir_all(temp_dir.path().join("linux-64")).unwrap();
+        let local = probe_channel(&client, temp_dir.path().to_str().unwrap(), &[])
+            .await
+            .unwrap();
+        assert_eq!(local.len(), 1);
+        assert!(local[0].0.ends_with("noarch/repodata.json"));
+        assert!(local[0].1.last_modified.is_some());
+    }
+}
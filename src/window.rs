@@ -0,0 +1,180 @@
+//! Time windows restricting when daemon jobs may transfer
+//!
+//! Sites whose network policy reserves business hours for other traffic can
+//! give a job `windows` such as `01:00-05:00`, evaluated in the job's time
+//! zone; a window whose end is before its start runs past midnight. A
+//! schedule firing outside every window waits for the next one to open. A run
+//! still going when its window closes stops between packages as if
+//! interrupted, saving its resume state, and the daemon resumes it as soon as
+//! the next window opens.
+
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+use crate::error::{MirrorError, Result};
+
+/// A daily span of local time, e.g. `01:00-05:00`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl TimeWindow {
+    /// Parse `HH:MM-HH:MM`
+    pub fn parse(window: &str) -> Result<Self> {
+        let invalid = || {
+            MirrorError::InvalidInput(format!(
+                "Invalid time window '{}': expected HH:MM-HH:MM, e.g. 01:00-05:00",
+                window
+            ))
+        };
+        let (start, end) = window.split_once('-').ok_or_else(invalid)?;
+        let time = |value: &str| NaiveTime::parse_from_str(value.trim(), "%H:%M").ok();
+        let (Some(start), Some(end)) = (time(start), time(end)) else {
+            return Err(invalid());
+        };
+        if start == end {
+            return Err(MirrorError::InvalidInput(format!(
+                "Invalid time window '{}': it starts and ends at the same time",
+                window
+            )));
+        }
+        Ok(Self { start, end })
+    }
+
+    fn wraps_midnight(&self) -> bool {
+        self.end < self.start
+    }
+
+    /// Whether the local time of day `time` is inside the window
+    fn contains(&self, time: NaiveTime) -> bool {
+        if self.wraps_midnight() {
+            time >= self.start || time < self.end
+        } else {
+            time >= self.start && time < self.end
+        }
+    }
+}
+
+/// The windows of a job in its time zone; no windows means always open
+#[derive(Debug, Clone)]
+pub struct Windows {
+    windows: Vec<TimeWindow>,
+    timezone: Tz,
+}
+
+impl Windows {
+    pub fn parse(windows: &[String], timezone: Tz) -> Result<Self> {
+        Ok(Self {
+            windows: windows
+                .iter()
+                .map(|window| TimeWindow::parse(window))
+                .collect::<Result<_>>()?,
+            timezone,
+        })
+    }
+
+    pub fn is_restricted(&self) -> bool {
+        !self.windows.is_empty()
+    }
+
+    /// Whether transfers are allowed at `now`
+    pub fn is_open(&self, now: DateTime<Utc>) -> bool {
+        let time = now.with_timezone(&self.timezone).time();
+        !self.is_restricted() || self.windows.iter().any(|window| window.contains(time))
+    }
+
+    /// When the window open at `now` closes; `None` when unrestricted or closed
+    pub fn closes_at(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let local = now.with_timezone(&self.timezone);
+        let (date, time) = (local.date_naive(), local.time());
+        self.windows
+            .iter()
+            .filter(|window| window.contains(time))
+            .map(|window| {
+                // A window past midnight opened yesterday if it is early in the day
+                let end_date = if window.wraps_midnight() && time >= window.start {
+                    date.succ_opt().unwrap_or(date)
+                } else {
+                    date
+                };
+                self.at(end_date, window.end)
+            })
+            .max()
+    }
+
+    /// The first time at or after `after` at which a window is open
+    pub fn next_open(&self, after: DateTime<Utc>) -> DateTime<Utc> {
+        if self.is_open(after) {
+            return after;
+        }
+        let date = after.with_timezone(&self.timezone).date_naive();
+        (0..=2)
+            .filter_map(|days| date.checked_add_signed(Duration::days(days)))
+            .flat_map(|date| self.windows.iter().map(move |window| (date, window.start)))
+            .map(|(date, start)| self.at(date, start))
+            .filter(|start| *start > after)
+            .min()
+            .unwrap_or(after)
+    }
+
+    /// A local date and time as UTC; times skipped by a daylight saving change move forward
+    fn at(&self, date: NaiveDate, time: NaiveTime) -> DateTime<Utc> {
+        let local = date.and_time(time);
+        (0..=2)
+            .find_map(|hours| {
+                self.timezone
+                    .from_local_datetime(&(local + Duration::hours(hours)))
+                    .earliest()
+            })
+            .map_or_else(
+                || Utc.from_utc_datetime(&local),
+                |time| time.with_timezone(&Utc),
+            )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 7, day, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let window = TimeWindow::parse("01:00-05:30").unwrap();
+        assert_eq!(window.start, NaiveTime::from_hms_opt(1, 0, 0).unwrap());
+        assert_eq!(window.end, NaiveTime::from_hms_opt(5, 30, 0).unwrap());
+        assert!(TimeWindow::parse("1am-5am").is_err());
+        assert!(TimeWindow::parse("25:00-05:00").is_err());
+        assert!(TimeWindow::parse("02:00-02:00").is_err());
+    }
+
+    #[test]
+    fn test_windows() {
+        let night = Windows::parse(&["22:00-02:00".to_string()], Tz::UTC).unwrap();
+        assert!(night.is_open(utc(1, 23, 0)));
+        assert!(night.is_open(utc(1, 1, 59)));
+        assert!(!night.is_open(utc(1, 2, 0)));
+        assert_eq!(night.closes_at(utc(1, 23, 0)), Some(utc(2, 2, 0)));
+        assert_eq!(night.closes_at(utc(2, 1, 0)), Some(utc(2, 2, 0)));
+        assert_eq!(night.closes_at(utc(2, 12, 0)), None);
+        assert_eq!(night.next_open(utc(2, 12, 0)), utc(2, 22, 0));
+        assert_eq!(night.next_open(utc(2, 23, 0)), utc(2, 23, 0));
+
+        // Evaluated in the job's time zone: 01:00-05:00 in Berlin is 23:00-03:00 UTC in summer
+        let berlin = Windows::parse(&["01:00-05:00".to_string()], Tz::Europe__Berlin).unwrap();
+        assert!(berlin.is_open(utc(1, 23, 30)));
+        assert!(!berlin.is_open(utc(2, 3, 0)));
+        assert_eq!(berlin.closes_at(utc(1, 23, 30)), Some(utc(2, 3, 0)));
+        assert_eq!(berlin.next_open(utc(2, 12, 0)), utc(2, 23, 0));
+
+        let always = Windows::parse(&[], Tz::UTC).unwrap();
+        assert!(!always.is_restricted());
+        assert!(always.is_open(utc(1, 12, 0)));
+        assert_eq!(always.closes_at(utc(1, 12, 0)), None);
+    }
+}
@@ -160,7 +160,7 @@ async fn test_filter_artifacts() {
     let filtered = azure_client.filter_artifacts_by_name(&artifacts, Some("conda-packages.*"));
     assert_eq!(filtered.len(), 2);
     assert!(filtered.iter().any(|a| a.name == "conda-packages-linux"));
-    assert!(filtered.iter().any(|a| a.name == "conda-packages-windows"));
+    assert!(filtered.iter().any(|a| a.name == "conda-packages-osx"));
 
     // Test type filtering
     let container_artifacts = azure_client.filter_artifacts_by_type(&artifacts, Some("Container"));
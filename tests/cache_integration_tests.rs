@@ -3,6 +3,8 @@ use std::fs;
 use tempfile::TempDir;
 
 use meso_forge_mirror::repository::{Repository, RepositoryType};
+use meso_forge_mirror::test_support::PackageFixture;
+use meso_forge_mirror::MirrorError;
 use rattler_cache::default_cache_dir;
 use rattler_cache::package_cache::PackageCache;
 
@@ -19,7 +21,7 @@ async fn test_cache_stores_packages_correctly() {
 
     // Create test package content
     let package_name = "rb-asciidoctor-revealjs-5.2.0-h1d6dcf3_0.conda";
-    let package_content = create_minimal_conda_package();
+    let package_content = create_minimal_conda_package(package_name);
 
     // Upload package to cache
     let result = cache_repo
@@ -55,7 +57,7 @@ async fn test_cache_integrates_with_rattler_package_cache() {
     );
 
     let package_name = "test-package-1.0.0-py311_0.conda";
-    let package_content = create_minimal_conda_package();
+    let package_content = create_minimal_conda_package(package_name);
 
     let result = cache_repo
         .upload_package(package_name, Bytes::from(package_content))
@@ -146,7 +148,7 @@ async fn test_cache_vs_repository_structure_differences() {
     );
 
     let package_name = "example-package-1.0.0-h123_0.conda";
-    let package_content = create_minimal_conda_package();
+    let package_content = create_minimal_conda_package(package_name);
 
     // Upload to both
     let cache_result = cache_repo
@@ -193,7 +195,7 @@ async fn test_multiple_packages_in_cache() {
 
     // Cache multiple packages
     for package_name in &packages {
-        let content = create_minimal_conda_package();
+        let content = create_minimal_conda_package(package_name);
         let result = cache_repo
             .upload_package(package_name, Bytes::from(content))
             .await;
@@ -277,7 +279,9 @@ async fn test_cache_repository_finalization() {
     let result = cache_repo
         .upload_package(
             "test-package-1.0.0-h123_0.conda",
-            Bytes::from(create_minimal_conda_package()),
+            Bytes::from(create_minimal_conda_package(
+                "test-package-1.0.0-h123_0.conda",
+            )),
         )
         .await;
     assert!(result.is_ok(), "Package upload should succeed");
@@ -296,30 +300,11 @@ async fn test_cache_repository_finalization() {
 
 // Helper functions
 
-fn create_minimal_conda_package() -> Vec<u8> {
-    // Create a minimal conda package structure for testing
-    // In reality, conda packages are tar.bz2 or conda (zip) archives with specific structure
-    let mut content = Vec::new();
-
-    // Add conda package signature (simplified for testing)
-    content.extend_from_slice(b"PK\x03\x04"); // ZIP signature for .conda format
-    content.extend_from_slice(b"test_conda_package_content");
-
-    // Add some realistic-looking metadata
-    let metadata = r#"
-    {
-        "name": "test-package",
-        "version": "1.0.0",
-        "build": "h123_0",
-        "channel": "conda-forge"
-    }
-    "#;
-    content.extend_from_slice(metadata.as_bytes());
-
-    // Pad to make it look more like a real package
-    content.extend_from_slice(&[0u8; 256]);
-
-    content
+fn create_minimal_conda_package(filename: &str) -> Vec<u8> {
+    PackageFixture::from_filename(filename)
+        .expect("conda package filename")
+        .to_conda()
+        .to_vec()
 }
 
 fn package_matches_search(package_filename: &str, search_term: &str) -> bool {
@@ -334,7 +319,7 @@ fn package_matches_search(package_filename: &str, search_term: &str) -> bool {
     // Extract the package name part (before version)
     // conda packages follow: name-version-build pattern
     if let Some(version_start) = find_version_start(package_name) {
-        let name_part = &package_name[..version_start];
+        let name_part = package_name[..version_start].trim_end_matches('-');
         name_part == search_term
     } else {
         // Fallback: simple prefix match
@@ -401,3 +386,41 @@ mod package_name_parsing_tests {
         }
     }
 }
+
+#[tokio::test]
+async fn test_local_repository_refuses_checksum_conflict() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let repo_path = temp_dir.path().to_string_lossy().to_string();
+    let package_name = "conflict-pkg-1.0.0-h1234567_0.tar.bz2";
+
+    let mut repo = Repository::new(RepositoryType::Local, repo_path.clone());
+    repo.upload_package(package_name, Bytes::from_static(b"first build"))
+        .await
+        .expect("Initial upload should succeed");
+
+    // Re-uploading the identical package is a no-op
+    repo.upload_package(package_name, Bytes::from_static(b"first build"))
+        .await
+        .expect("Identical re-upload should succeed");
+
+    // A different build under the same filename is refused
+    let result = repo
+        .upload_package(package_name, Bytes::from_static(b"second build"))
+        .await;
+    assert!(
+        matches!(result, Err(MirrorError::ChecksumConflict { .. })),
+        "Expected checksum conflict, got {:?}",
+        result
+    );
+
+    let stored = temp_dir.path().join("noarch").join(package_name);
+    assert_eq!(fs::read(&stored).unwrap(), b"first build");
+
+    // --force-replace overwrites the existing package
+    let mut forced = Repository::new(RepositoryType::Local, repo_path).with_force_replace(true);
+    forced
+        .upload_package(package_name, Bytes::from_static(b"second build"))
+        .await
+        .expect("Forced replacement should succeed");
+    assert_eq!(fs::read(&stored).unwrap(), b"second build");
+}
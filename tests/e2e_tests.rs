@@ -0,0 +1,453 @@
+//! End-to-end mirror runs against local stand-ins for the services involved
+//!
+//! GitHub and Azure DevOps are replayed by a [`MockServer`], channels are
+//! served in-process by the `serve` command's [`ChannelServer`], and mirrored
+//! channels are solved and installed with `check-install`. The S3 flow needs
+//! a MinIO (or other S3) endpoint and only runs when `E2E_S3_BUCKET` is set,
+//! together with the usual `AWS_ENDPOINT_URL`, `AWS_REGION`,
+//! `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY`.
+//!
+//! Run with `cargo test --features e2e --test e2e_tests`.
+#![cfg(feature = "e2e")]
+
+use std::io::Write;
+use std::path::Path;
+
+use meso_forge_mirror::config::Config;
+use meso_forge_mirror::install_check::check_install;
+use meso_forge_mirror::serve::{self, ChannelServer};
+use meso_forge_mirror::test_support::PackageFixture;
+use meso_forge_mirror::test_util::{MockResponse, MockServer};
+use meso_forge_mirror::{mirror_packages, CondaPackageHandler, RepositoryType};
+use rattler_conda_types::Platform;
+use tempfile::TempDir;
+
+/// An application and the library it depends on, both for linux-64
+fn fixtures() -> Vec<PackageFixture> {
+    vec![
+        PackageFixture::new("app", "2.0")
+            .subdir("linux-64")
+            .depends(["lib >=1.5"]),
+        PackageFixture::new("lib", "1.6").subdir("linux-64"),
+    ]
+}
+
+/// A configuration keeping every file a run writes in `dir`
+fn config(dir: &Path, server: &MockServer) -> Config {
+    let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+    Config {
+        github_token: None,
+        github_api_url: server.url().to_string(),
+        azure_devops_token: None,
+        azure_devops_url: server.url().to_string(),
+        resume_state_file: path("resume.json"),
+        quarantine_dir: path("quarantine"),
+        // Every package of an artifact, not only the first
+        all_matches: true,
+        ..Default::default()
+    }
+}
+
+/// A CI artifact holding the packages under `build_artifacts/<subdir>/`
+fn artifact_zip(fixtures: &[PackageFixture]) -> Vec<u8> {
+    let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    for fixture in fixtures {
+        let subdir = fixture.index_json()["subdir"]
+            .as_str()
+            .unwrap_or("noarch")
+            .to_string();
+        writer
+            .start_file(
+                format!("build_artifacts/{}/{}", subdir, fixture.conda_filename()),
+                zip::write::SimpleFileOptions::default(),
+            )
+            .unwrap();
+        writer.write_all(&fixture.to_conda()).unwrap();
+    }
+    writer.finish().unwrap().into_inner()
+}
+
+/// Serve the channel in `root` on a free local port, returning its URL
+async fn serve_channel(root: &Path) -> String {
+    let listener = serve::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let server = ChannelServer::new(root, None).unwrap();
+    tokio::spawn(serve::serve(listener, server));
+    url
+}
+
+/// Solve and install `app` from the channel at `url`, returning the installed filenames
+async fn install_app(url: &str, config: &Config) -> Vec<String> {
+    let check = check_install(
+        &reqwest::Client::new(),
+        url,
+        &["app".to_string()],
+        Platform::Linux64,
+        None,
+        config,
+    )
+    .await
+    .unwrap();
+    check.packages
+}
+
+fn expected_packages() -> Vec<String> {
+    let mut filenames: Vec<String> = fixtures().iter().map(|f| f.conda_filename()).collect();
+    filenames.sort();
+    filenames
+}
+
+/// Filenames a `repodata.json` lists, each checked to be in the section of its extension
+#[cfg(feature = "s3")]
+fn listed_packages(repodata: &serde_json::Value) -> Vec<String> {
+    let mut listed = Vec::new();
+    for section in ["packages", "packages.conda"] {
+        let Some(records) = repodata[section].as_object() else {
+            continue;
+        };
+        for filename in records.keys() {
+            assert_eq!(
+                CondaPackageHandler::repodata_sections(filename).0,
+                section,
+                "{} is listed in the wrong section",
+                filename
+            );
+            listed.push(filename.clone());
+        }
+    }
+    listed.sort();
+    listed
+}
+
+#[tokio::test]
+async fn test_github_artifact_to_served_channel() {
+    let temp_dir = TempDir::new().unwrap();
+    let server = MockServer::start().await.unwrap();
+    server.mock(
+        "GET",
+        "/repos/owner/repo/actions/artifacts",
+        MockResponse::json(
+            200,
+            serde_json::json!({
+                "total_count": 1,
+                "artifacts": [{
+                    "id": 7,
+                    "name": "conda-packages",
+                    // GitHub's listed size need not be the size of the ZIP
+                    "size_in_bytes": 1,
+                    "url": format!("{}/repos/owner/repo/actions/artifacts/7", server.url()),
+                    "archive_download_url":
+                        format!("{}/repos/owner/repo/actions/artifacts/7/zip", server.url()),
+                    "expired": false,
+                    "created_at": "2026-01-01T00:00:00Z",
+                    "updated_at": "2026-01-01T00:00:00Z",
+                    "expires_at": "2099-01-01T00:00:00Z",
+                    "workflow_run": null
+                }]
+            })
+            .to_string(),
+        ),
+    );
+    server.mock(
+        "GET",
+        "/repos/owner/repo/actions/artifacts/7/zip",
+        MockResponse::new(200, artifact_zip(&fixtures())),
+    );
+    let config = config(temp_dir.path(), &server);
+    let target = temp_dir.path().join("channel");
+
+    let report = mirror_packages(
+        "owner/repo",
+        None,
+        "github",
+        false,
+        RepositoryType::Local,
+        &target.to_string_lossy(),
+        &config,
+    )
+    .await
+    .unwrap();
+    assert_eq!(report.failures().count(), 0);
+    assert_eq!(report.mirrored_count(), 2);
+    assert!(target.join("linux-64/repodata.json").exists());
+
+    let url = serve_channel(&target).await;
+    let mut installed = install_app(&url, &config).await;
+    installed.sort();
+    assert_eq!(installed, expected_packages());
+}
+
+#[tokio::test]
+async fn test_azure_artifact_to_served_channel() {
+    let temp_dir = TempDir::new().unwrap();
+    let server = MockServer::start().await.unwrap();
+    let artifact = "conda_artifacts_20260101.1_linux_64_";
+    server.mock(
+        "GET",
+        "/org/project/_apis/build/builds/42/artifacts?api-version=6.0",
+        MockResponse::json(
+            200,
+            serde_json::json!({
+                "count": 1,
+                "value": [{
+                    "id": 1,
+                    "name": artifact,
+                    "source": "1",
+                    "resource": {
+                        "type": "Container",
+                        "data": "#/1/conda",
+                        "url": format!("{}/org/project/_apis/build/builds/42/artifacts", server.url()),
+                        "downloadUrl": format!(
+                            "{}/org/project/_apis/build/builds/42/artifacts?artifactName={}&$format=zip",
+                            server.url(),
+                            artifact
+                        )
+                    }
+                }]
+            })
+            .to_string(),
+        ),
+    );
+    server.mock(
+        "GET",
+        &format!(
+            "/org/project/_apis/build/builds/42/artifacts?artifactName={}&$format=zip&api-version=6.0",
+            artifact
+        ),
+        MockResponse::new(200, artifact_zip(&fixtures())),
+    );
+    let config = config(temp_dir.path(), &server);
+    let target = temp_dir.path().join("channel");
+
+    let report = mirror_packages(
+        "org/project#42",
+        None,
+        "azure",
+        false,
+        RepositoryType::Local,
+        &target.to_string_lossy(),
+        &config,
+    )
+    .await
+    .unwrap();
+    assert_eq!(report.failures().count(), 0);
+    assert_eq!(report.mirrored_count(), 2);
+
+    let url = serve_channel(&target).await;
+    let mut installed = install_app(&url, &config).await;
+    installed.sort();
+    assert_eq!(installed, expected_packages());
+}
+
+#[tokio::test]
+async fn test_served_channel_to_channel() {
+    let temp_dir = TempDir::new().unwrap();
+    let server = MockServer::start().await.unwrap();
+    let config = Config {
+        src_subdirs: vec!["linux-64".to_string(), "noarch".to_string()],
+        sharded_repodata: true,
+        ..config(temp_dir.path(), &server)
+    };
+
+    // The upstream channel is written by hand, as another tool would
+    let upstream = temp_dir.path().join("upstream");
+    for fixture in fixtures() {
+        std::fs::create_dir_all(upstream.join("linux-64")).unwrap();
+        std::fs::write(
+            upstream.join("linux-64").join(fixture.conda_filename()),
+            fixture.to_conda(),
+        )
+        .unwrap();
+    }
+    let mut repository = meso_forge_mirror::Repository::new(
+        RepositoryType::Local,
+        upstream.to_string_lossy().to_string(),
+    );
+    for fixture in fixtures() {
+        repository
+            .upload_package(&fixture.conda_filename(), fixture.to_conda())
+            .await
+            .unwrap();
+    }
+    repository.finalize_repository().await.unwrap();
+    let upstream_url = serve_channel(&upstream).await;
+
+    let mirror = temp_dir.path().join("mirror");
+    let mirror_path = mirror.to_string_lossy().to_string();
+    let report = mirror_packages(
+        &upstream_url,
+        None,
+        "channel",
+        false,
+        RepositoryType::Local,
+        &mirror_path,
+        &config,
+    )
+    .await
+    .unwrap();
+    assert_eq!(report.failures().count(), 0);
+    assert_eq!(report.mirrored_count(), 2);
+    assert!(mirror.join("linux-64/repodata_shards.msgpack.zst").exists());
+
+    // A second run finds nothing new upstream
+    let report = mirror_packages(
+        &upstream_url,
+        None,
+        "channel",
+        false,
+        RepositoryType::Local,
+        &mirror_path,
+        &config,
+    )
+    .await
+    .unwrap();
+    assert_eq!(report.mirrored_count(), 0);
+
+    let url = serve_channel(&mirror).await;
+    let mut installed = install_app(&url, &config).await;
+    installed.sort();
+    assert_eq!(installed, expected_packages());
+}
+
+#[cfg(feature = "s3")]
+#[tokio::test]
+async fn test_github_artifact_to_s3() {
+    let Ok(bucket) = std::env::var("E2E_S3_BUCKET") else {
+        eprintln!("E2E_S3_BUCKET is not set; skipping the S3 flow");
+        return;
+    };
+    let sdk_config = aws_config::defaults(aws_config::BehaviorVersion::latest())
+        .load()
+        .await;
+    let s3 = aws_sdk_s3::Client::new(&sdk_config);
+    // The bucket may already exist from an earlier run
+    let _ = s3.create_bucket().bucket(&bucket).send().await;
+
+    let temp_dir = TempDir::new().unwrap();
+    let server = MockServer::start().await.unwrap();
+    server.mock(
+        "GET",
+        "/repos/owner/repo/actions/artifacts/7",
+        MockResponse::json(
+            200,
+            serde_json::json!({
+                "id": 7,
+                "name": "conda-packages",
+                "size_in_bytes": 0,
+                "url": format!("{}/repos/owner/repo/actions/artifacts/7", server.url()),
+                "archive_download_url":
+                    format!("{}/repos/owner/repo/actions/artifacts/7/zip", server.url()),
+                "expired": false,
+                "created_at": "2026-01-01T00:00:00Z",
+                "updated_at": "2026-01-01T00:00:00Z",
+                "expires_at": "2099-01-01T00:00:00Z",
+                "workflow_run": null
+            })
+            .to_string(),
+        ),
+    );
+    server.mock(
+        "GET",
+        "/repos/owner/repo/actions/artifacts/7/zip",
+        MockResponse::new(200, artifact_zip(&fixtures())),
+    );
+    let config = Config {
+        sharded_repodata: true,
+        ..config(temp_dir.path(), &server)
+    };
+    let prefix = format!("e2e-{}", chrono::Utc::now().timestamp_millis());
+
+    let report = mirror_packages(
+        "owner/repo#7",
+        None,
+        "github",
+        false,
+        RepositoryType::S3,
+        &format!("s3://{}/{}", bucket, prefix),
+        &config,
+    )
+    .await
+    .unwrap();
+    assert_eq!(report.failures().count(), 0);
+    assert_eq!(report.mirrored_count(), 2);
+
+    let repodata = s3
+        .get_object()
+        .bucket(&bucket)
+        .key(format!("{}/linux-64/repodata.json", prefix))
+        .send()
+        .await
+        .unwrap()
+        .body
+        .collect()
+        .await
+        .unwrap()
+        .into_bytes();
+    let repodata: serde_json::Value = serde_json::from_slice(&repodata).unwrap();
+    let listed = listed_packages(&repodata);
+    assert_eq!(listed, expected_packages());
+    s3.head_object()
+        .bucket(&bucket)
+        .key(format!("{}/linux-64/repodata_shards.msgpack.zst", prefix))
+        .send()
+        .await
+        .unwrap();
+}
+
+#[cfg(feature = "s3")]
+#[tokio::test]
+async fn test_concurrent_writers_to_s3() {
+    let Ok(bucket) = std::env::var("E2E_S3_BUCKET") else {
+        eprintln!("E2E_S3_BUCKET is not set; skipping the S3 flow");
+        return;
+    };
+    let sdk_config = aws_config::defaults(aws_config::BehaviorVersion::latest())
+        .load()
+        .await;
+    let s3 = aws_sdk_s3::Client::new(&sdk_config);
+    let _ = s3.create_bucket().bucket(&bucket).send().await;
+
+    let target = format!(
+        "s3://{}/e2e-concurrent-{}",
+        bucket,
+        chrono::Utc::now().timestamp_millis()
+    );
+    // Each writer is a separate repository, as on separate machines
+    let writers: Vec<_> = (0..4)
+        .map(|i| {
+            let target = target.clone();
+            tokio::spawn(async move {
+                let fixture = PackageFixture::new(format!("pkg{}", i), "1.0").subdir("linux-64");
+                let mut repository = meso_forge_mirror::Repository::new(RepositoryType::S3, target);
+                repository
+                    .upload_package(&fixture.conda_filename(), fixture.to_conda())
+                    .await
+                    .unwrap();
+                repository.finalize_repository().await.unwrap();
+                fixture.conda_filename()
+            })
+        })
+        .collect();
+    let mut expected = Vec::new();
+    for writer in writers {
+        expected.push(writer.await.unwrap());
+    }
+    expected.sort();
+
+    let prefix = target.trim_start_matches(&format!("s3://{}/", bucket));
+    let repodata = s3
+        .get_object()
+        .bucket(&bucket)
+        .key(format!("{}/linux-64/repodata.json", prefix))
+        .send()
+        .await
+        .unwrap()
+        .body
+        .collect()
+        .await
+        .unwrap()
+        .into_bytes();
+    let repodata: serde_json::Value = serde_json::from_slice(&repodata).unwrap();
+    let listed = listed_packages(&repodata);
+    assert_eq!(listed, expected);
+}
@@ -8,6 +8,7 @@ use anyhow::Result;
 use bytes::Bytes;
 use meso_forge_mirror::conda_package::CondaPackageHandler;
 use meso_forge_mirror::repository::{Repository, RepositoryType};
+use meso_forge_mirror::test_support::PackageFixture;
 use rattler_conda_types::Platform;
 use std::collections::HashMap;
 use std::fs::{create_dir_all, read_dir};
@@ -49,15 +50,13 @@ impl TestPackageInfo {
     }
 }
 
-/// Create mock conda package content for testing
+/// Create a valid conda package whose metadata matches `info`
 fn create_mock_conda_package(info: &TestPackageInfo) -> Vec<u8> {
-    // Create a simple mock package that will trigger fallback logic
-    // In real usage, this would be actual .conda archive content
-    format!(
-        "mock_conda_package:{}:{}:{}",
-        info.name, info.version, info.subdir
-    )
-    .into_bytes()
+    PackageFixture::new(&info.name, &info.version)
+        .build(&info.build)
+        .subdir(&info.subdir)
+        .to_conda()
+        .to_vec()
 }
 
 #[tokio::test]
@@ -187,13 +186,8 @@ async fn test_platform_detection_fallback_logic() -> Result<()> {
     ];
 
     for (filename, expected_platform) in test_cases {
-        let mock_content = create_mock_conda_package(&TestPackageInfo {
-            name: "test".to_string(),
-            version: "1.0.0".to_string(),
-            build: "h123_0".to_string(),
-            subdir: "unknown".to_string(),
-            expected_platform: Platform::NoArch,
-        });
+        // Content without readable metadata, so the platform comes from the filename
+        let mock_content = b"not a conda archive".to_vec();
 
         let processed = handler
             .process_package(Bytes::from(mock_content), filename)